@@ -0,0 +1,98 @@
+//! Compares the middleware reading the database to the IPC and HTTP endpoints of the same node.
+//!
+//! Needs a running reth node, see `bench_utils` for the paths, then `cargo bench --bench backends`.
+mod bench_utils;
+
+use bench_utils::{
+    spawn_bench_http_provider, spawn_bench_ipc_provider, spawn_bench_reth_middleware,
+};
+use criterion::{criterion_group, criterion_main, Criterion};
+use ethers::{
+    providers::{Http, Ipc, Middleware, Provider},
+    types::{
+        transaction::eip2718::TypedTransaction, Address, BlockNumber, Bytes, Filter,
+        TransactionRequest, H256,
+    },
+};
+use ethers_reth::RethMiddleware;
+
+/// WETH, deployed on mainnet since block 4719568.
+const WETH: &str = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2";
+/// `totalSupply()`
+const TOTAL_SUPPLY: &str = "0x18160ddd";
+/// Blocks behind the tip the inputs are taken from, so they are on disk and final.
+const TIP_DISTANCE: u64 = 64;
+/// Blocks covered by the `get_logs` filter.
+const LOGS_RANGE: u64 = 100;
+
+struct Backends {
+    reth: RethMiddleware<Provider<Ipc>>,
+    ipc: Provider<Ipc>,
+    http: Provider<Http>,
+}
+
+/// Inputs shared by every backend.
+struct Inputs {
+    block: u64,
+    tx: H256,
+    call: TypedTransaction,
+    filter: Filter,
+}
+
+impl Inputs {
+    /// Takes the inputs from the node: the newest block with transactions, `TIP_DISTANCE` behind
+    /// the tip or more.
+    async fn fetch(provider: &Provider<Ipc>) -> Inputs {
+        let mut block = provider.get_block_number().await.unwrap().as_u64() - TIP_DISTANCE;
+        let tx = loop {
+            let txs = provider.get_block(block).await.unwrap().unwrap().transactions;
+            if let Some(tx) = txs.first() {
+                break *tx
+            }
+            block -= 1;
+        };
+        let weth: Address = WETH.parse().unwrap();
+        let call = TransactionRequest::new().to(weth).data(TOTAL_SUPPLY.parse::<Bytes>().unwrap());
+        let filter = Filter::new()
+            .address(weth)
+            .from_block(BlockNumber::Number((block - LOGS_RANGE).into()))
+            .to_block(BlockNumber::Number(block.into()));
+        Inputs { block, tx, call: call.into(), filter }
+    }
+}
+
+/// Benchmarks a call against every backend, as a group named after the method.
+macro_rules! bench_backends {
+    ($c:expr, $rt:expr, $backends:expr, $name:literal, |$m:ident| $call:expr) => {{
+        let mut group = $c.benchmark_group($name);
+        let $m = &$backends.reth;
+        group.bench_function("reth", |b| b.to_async($rt).iter(|| async { $call.await.unwrap() }));
+        let $m = &$backends.ipc;
+        group.bench_function("ipc", |b| b.to_async($rt).iter(|| async { $call.await.unwrap() }));
+        let $m = &$backends.http;
+        group.bench_function("http", |b| b.to_async($rt).iter(|| async { $call.await.unwrap() }));
+        group.finish();
+    }};
+}
+
+fn backends(c: &mut Criterion) {
+    let rt = bench_utils::bench_runtime();
+    let (backends, inputs) = rt.block_on(async {
+        let backends = Backends {
+            reth: spawn_bench_reth_middleware().await.unwrap(),
+            ipc: spawn_bench_ipc_provider().await.unwrap(),
+            http: spawn_bench_http_provider().unwrap(),
+        };
+        let inputs = Inputs::fetch(&backends.ipc).await;
+        (backends, inputs)
+    });
+
+    bench_backends!(c, &rt, backends, "get_block", |m| m.get_block(inputs.block));
+    bench_backends!(c, &rt, backends, "get_logs", |m| m.get_logs(&inputs.filter));
+    bench_backends!(c, &rt, backends, "eth_call", |m| m
+        .call(&inputs.call, Some(inputs.block.into())));
+    bench_backends!(c, &rt, backends, "trace_transaction", |m| m.trace_transaction(inputs.tx));
+}
+
+criterion_group!(benches, backends);
+criterion_main!(benches);
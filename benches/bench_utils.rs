@@ -1,12 +1,29 @@
-use ethers::providers::{Ipc, Provider};
+use ethers::providers::{Http, Ipc, Provider};
 use ethers_reth::{RethMiddleware, RethMiddlewareError};
 use eyre::Result;
-use std::path::Path;
-use tokio::runtime::Runtime;
+use std::{env, path::Path};
+use tokio::runtime::{Handle, Runtime};
 
 const TEST_IPC_PATH: &str = "/tmp/reth.ipc";
+const TEST_HTTP_URL: &str = "http://localhost:8545";
 const TEST_DB_PATH: &str = "/NVMe/data/reth/db";
 
+/// Returns the IPC path of the benchmarked node, `BENCH_IPC_PATH` if set.
+pub fn bench_ipc_path() -> String {
+    env::var("BENCH_IPC_PATH").unwrap_or_else(|_| TEST_IPC_PATH.to_string())
+}
+
+/// Returns the HTTP url of the benchmarked node, `BENCH_HTTP_URL` if set.
+pub fn bench_http_url() -> String {
+    env::var("BENCH_HTTP_URL").unwrap_or_else(|_| TEST_HTTP_URL.to_string())
+}
+
+/// Returns the database path of the benchmarked node, `BENCH_DB_PATH` if set.
+pub fn bench_db_path() -> String {
+    env::var("BENCH_DB_PATH").unwrap_or_else(|_| TEST_DB_PATH.to_string())
+}
+
+#[allow(dead_code)]
 pub async fn spawn_ipc_provider(
     ipc_path: &str,
 ) -> Result<Provider<Ipc>, ethers::providers::ProviderError> {
@@ -14,23 +31,28 @@ pub async fn spawn_ipc_provider(
 }
 
 pub async fn spawn_bench_ipc_provider() -> Result<Provider<Ipc>, ethers::providers::ProviderError> {
-    Provider::connect_ipc(TEST_IPC_PATH).await
+    Provider::connect_ipc(bench_ipc_path()).await
+}
+
+pub fn spawn_bench_http_provider() -> Result<Provider<Http>> {
+    Ok(Provider::<Http>::try_from(bench_http_url())?)
 }
 
+/// Opens the middleware on the runtime of the caller, which must outlive it.
 pub async fn spawn_reth_middleware(
     provider: Provider<Ipc>,
     db_path: &Path,
 ) -> Result<RethMiddleware<Provider<Ipc>>, RethMiddlewareError<Provider<Ipc>>> {
-    let rt = Runtime::new().unwrap();
-    let handle = rt.handle().clone();
-    Ok(RethMiddleware::new(provider, db_path, handle).unwrap())
+    RethMiddleware::new(provider, db_path, Handle::current())
 }
 
 pub async fn spawn_bench_reth_middleware(
 ) -> Result<RethMiddleware<Provider<Ipc>>, RethMiddlewareError<Provider<Ipc>>> {
-    let rt = Runtime::new().unwrap();
-    let handle = rt.handle().clone();
-    let provider = Provider::connect_ipc(TEST_IPC_PATH).await.unwrap();
-    let middleware = spawn_reth_middleware(provider, Path::new(TEST_DB_PATH)).await?;
-    Ok(middleware)
+    let provider = spawn_bench_ipc_provider().await.unwrap();
+    spawn_reth_middleware(provider, Path::new(&bench_db_path())).await
+}
+
+/// Returns the runtime the benchmarks run on.
+pub fn bench_runtime() -> Runtime {
+    Runtime::new().expect("failed to start the benchmark runtime")
 }
@@ -0,0 +1,123 @@
+use alloy_primitives::{
+    Address as AlloyAddress, Bytes as AlloyBytes, B256 as AlloyB256, U256 as AlloyU256,
+    U64 as AlloyU64,
+};
+use reth_primitives::{Bytes, H160, H256, U256, U64};
+
+// -----------------------------------------------
+/// conversion traits
+pub trait ToAlloy<T> {
+    /// Reth -> Alloy
+    fn into_alloy(self) -> T;
+}
+
+pub trait FromAlloy<T> {
+    /// Alloy -> Reth
+    fn from_alloy(value: T) -> Self;
+}
+
+// -----------------------------------------------
+/// generic Vec<> conversion
+impl<T, F> ToAlloy<Vec<F>> for Vec<T>
+where
+    T: ToAlloy<F>,
+{
+    fn into_alloy(self) -> Vec<F> {
+        self.into_iter().map(|x| x.into_alloy()).collect()
+    }
+}
+
+impl<T, F> FromAlloy<Vec<F>> for Vec<T>
+where
+    T: FromAlloy<F>,
+{
+    fn from_alloy(value: Vec<F>) -> Self {
+        value.into_iter().map(T::from_alloy).collect()
+    }
+}
+
+// -----------------------------------------------
+/// generic Option<> conversion
+impl<T, F> ToAlloy<Option<F>> for Option<T>
+where
+    T: ToAlloy<F>,
+{
+    fn into_alloy(self) -> Option<F> {
+        self.map(|x| x.into_alloy())
+    }
+}
+
+impl<T, F> FromAlloy<Option<F>> for Option<T>
+where
+    T: FromAlloy<F>,
+{
+    fn from_alloy(value: Option<F>) -> Self {
+        value.map(T::from_alloy)
+    }
+}
+
+// -----------------------------------------------
+/// fixed hash conversions
+impl ToAlloy<AlloyAddress> for H160 {
+    fn into_alloy(self) -> AlloyAddress {
+        AlloyAddress::from(self.0)
+    }
+}
+
+impl FromAlloy<AlloyAddress> for H160 {
+    fn from_alloy(value: AlloyAddress) -> Self {
+        H160(value.0 .0)
+    }
+}
+
+impl ToAlloy<AlloyB256> for H256 {
+    fn into_alloy(self) -> AlloyB256 {
+        AlloyB256::from(self.0)
+    }
+}
+
+impl FromAlloy<AlloyB256> for H256 {
+    fn from_alloy(value: AlloyB256) -> Self {
+        H256(value.0)
+    }
+}
+
+// -----------------------------------------------
+/// Uint conversions, through limbs as both sides are ruint but may not share its version
+impl ToAlloy<AlloyU256> for U256 {
+    fn into_alloy(self) -> AlloyU256 {
+        AlloyU256::from_limbs(self.into_limbs())
+    }
+}
+
+impl FromAlloy<AlloyU256> for U256 {
+    fn from_alloy(value: AlloyU256) -> Self {
+        U256::from_limbs(value.into_limbs())
+    }
+}
+
+impl ToAlloy<AlloyU64> for U64 {
+    fn into_alloy(self) -> AlloyU64 {
+        AlloyU64::from_limbs(self.into_limbs())
+    }
+}
+
+impl FromAlloy<AlloyU64> for U64 {
+    fn from_alloy(value: AlloyU64) -> Self {
+        U64::from_limbs(value.into_limbs())
+    }
+}
+
+// -----------------------------------------------
+/// Bytes conversion
+impl ToAlloy<AlloyBytes> for Bytes {
+    fn into_alloy(self) -> AlloyBytes {
+        AlloyBytes::from(self.to_vec())
+    }
+}
+
+impl FromAlloy<AlloyBytes> for Bytes {
+    fn from_alloy(value: AlloyBytes) -> Self {
+        Bytes::from(value.to_vec())
+    }
+}
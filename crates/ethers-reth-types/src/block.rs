@@ -1,9 +1,10 @@
 use super::{ToEthers, ToReth};
 
 use ethers::types::{
-    BlockId as EthersBlockId, BlockNumber as EthersBlockNumber, H256 as EthersH256,
+    Block as EthersBlock, BlockId as EthersBlockId, BlockNumber as EthersBlockNumber,
+    H256 as EthersH256, H64 as EthersH64,
 };
-use reth_primitives::{BlockId, BlockNumberOrTag, H256};
+use reth_primitives::{BlockId, BlockNumberOrTag, SealedHeader, H256};
 
 /// BlockId (ethers) -> (reth)
 impl ToReth<BlockId> for EthersBlockId {
@@ -12,9 +13,7 @@ impl ToReth<BlockId> for EthersBlockId {
             EthersBlockId::Hash(hash) => {
                 BlockId::Hash(<EthersH256 as ToReth<H256>>::into_reth(hash).into())
             }
-            EthersBlockId::Number(number) => {
-                BlockId::Number(BlockNumberOrTag::Number(number.as_number().unwrap().as_u64()))
-            }
+            EthersBlockId::Number(number) => BlockId::Number(number.into_reth()),
         }
     }
 }
@@ -77,3 +76,33 @@ impl ToEthers<EthersBlockNumber> for BlockId {
         }
     }
 }
+
+// -----------------------------------------------
+
+/// SealedHeader (reth) -> EthersBlock<EthersH256> (ethers), a block with its header fields only:
+/// transactions, uncles, total difficulty and size are left empty.
+impl ToEthers<EthersBlock<EthersH256>> for SealedHeader {
+    fn into_ethers(self) -> EthersBlock<EthersH256> {
+        EthersBlock {
+            hash: Some(self.hash.into_ethers()),
+            parent_hash: self.parent_hash.into_ethers(),
+            uncles_hash: self.ommers_hash.into_ethers(),
+            author: Some(self.beneficiary.into_ethers()),
+            state_root: self.state_root.into_ethers(),
+            transactions_root: self.transactions_root.into_ethers(),
+            receipts_root: self.receipts_root.into_ethers(),
+            number: Some(self.number.into()),
+            gas_used: self.gas_used.into(),
+            gas_limit: self.gas_limit.into(),
+            extra_data: self.extra_data.clone().into_ethers(),
+            logs_bloom: Some(self.logs_bloom.into_ethers()),
+            timestamp: self.timestamp.into(),
+            difficulty: self.difficulty.into_ethers(),
+            mix_hash: Some(self.mix_hash.into_ethers()),
+            nonce: Some(EthersH64::from_low_u64_be(self.nonce)),
+            base_fee_per_gas: self.base_fee_per_gas.map(Into::into),
+            withdrawals_root: self.withdrawals_root.into_ethers(),
+            ..Default::default()
+        }
+    }
+}
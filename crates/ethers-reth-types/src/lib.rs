@@ -2,10 +2,13 @@ use std::{
     collections::{BTreeMap, HashSet},
     hash::Hash,
 };
+use thiserror::Error;
 
 // Numerous acts of type terrorism having been commited during the making of this program. Please
 // forgive us.
 pub mod access_list;
+#[cfg(feature = "alloy")]
+pub mod alloy;
 pub mod block;
 pub mod primitives;
 pub mod rpc;
@@ -23,6 +26,25 @@ pub trait ToEthers<T> {
     fn into_ethers(self) -> T;
 }
 
+/// fallible conversion traits, for types with fields the other side cannot leave empty
+pub trait TryToReth<T> {
+    /// Ethers -> Reth
+    fn try_into_reth(self) -> Result<T, ConversionError>;
+}
+
+pub trait TryToEthers<T> {
+    /// Reth -> Ethers
+    fn try_into_ethers(self) -> Result<T, ConversionError>;
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ConversionError {
+    /// A field that is optional on the source type is required on the target, e.g. the
+    /// transaction index of a pending receipt.
+    #[error("{ty} is missing `{field}`")]
+    MissingField { ty: &'static str, field: &'static str },
+}
+
 // -----------------------------------------------
 /// generic as_ref conversion
 impl<T, F> ToReth<F> for &T
@@ -63,6 +85,25 @@ where
     }
 }
 
+/// generic fallible Vec<> conversion
+impl<T, F> TryToReth<Vec<T>> for Vec<F>
+where
+    F: TryToReth<T>,
+{
+    fn try_into_reth(self) -> Result<Vec<T>, ConversionError> {
+        self.into_iter().map(|x| x.try_into_reth()).collect()
+    }
+}
+
+impl<F, T> TryToEthers<Vec<F>> for Vec<T>
+where
+    T: TryToEthers<F>,
+{
+    fn try_into_ethers(self) -> Result<Vec<F>, ConversionError> {
+        self.into_iter().map(|x| x.try_into_ethers()).collect()
+    }
+}
+
 // -----------------------------------------------
 /// generic Option<> conversion
 impl<T, F> ToReth<Option<T>> for Option<F>
@@ -83,6 +124,25 @@ where
     }
 }
 
+/// generic fallible Option<> conversion
+impl<T, F> TryToReth<Option<T>> for Option<F>
+where
+    F: TryToReth<T>,
+{
+    fn try_into_reth(self) -> Result<Option<T>, ConversionError> {
+        self.map(|x| x.try_into_reth()).transpose()
+    }
+}
+
+impl<F, T> TryToEthers<Option<F>> for Option<T>
+where
+    T: TryToEthers<F>,
+{
+    fn try_into_ethers(self) -> Result<Option<F>, ConversionError> {
+        self.map(|x| x.try_into_ethers()).transpose()
+    }
+}
+
 // -----------------------------------------------
 
 /// generic HashSet<> -> Vec<> conversion
@@ -1,4 +1,4 @@
-use crate::type_conversions::{ToEthers, ToReth};
+use crate::{ToEthers, ToReth};
 use std::{fmt::Debug, mem};
 
 use ethers::types::{
@@ -96,11 +96,7 @@ macro_rules! array_impls {
                 T: Default + Debug,
             {
                 fn into_reth(self) -> [T; $N] {
-                    let mut result: [T; $N] = (0..$N).map(|i| self[i].clone().into_reth()).collect::<Vec<T>>().try_into().unwrap();
-                    for (i, item) in self.iter().enumerate() {
-                        result[i] = item.clone().into_reth();
-                    }
-                    result
+                    self.map(|item| item.into_reth())
                 }
             }
 
@@ -110,11 +106,7 @@ macro_rules! array_impls {
                 F: Default + Debug,
             {
                 fn into_ethers(self) -> [F; $N] {
-                    let mut result: [F; $N] = (0..$N).map(|i| self[i].clone().into_ethers()).collect::<Vec<F>>().try_into().unwrap();
-                    for (i, item) in self.iter().enumerate() {
-                        result[i] = item.clone().into_ethers();
-                    }
-                    result
+                    self.map(|item| item.into_ethers())
                 }
             }
         )+
@@ -124,8 +116,10 @@ macro_rules! array_impls {
 array_impls!(4, 32);
 
 impl_ToEthers_Uint!(EthersU256, (U256, U128));
+// the conversion of an ethers U256 to a reth U64 keeps the low 64 bits
 impl_ToEthers!(EthersU256, (U64, H256));
 
+// the conversion of a reth U256 to an ethers U64 keeps the low 64 bits
 impl_ToEthers_Uint!(EthersU64, (U256, U8));
 impl_ToEthers!(EthersU64, (U64));
 
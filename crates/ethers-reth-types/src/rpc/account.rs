@@ -1,4 +1,4 @@
-use crate::type_conversions::{ToEthers, ToReth};
+use crate::{ToEthers, ToReth};
 
 use ethers::types::{
     EIP1186ProofResponse as EthersEIP1186ProofResponse, StorageProof as EthersStorageProof,
@@ -1,9 +1,55 @@
-use crate::type_conversions::{ToEthers, ToReth};
+use crate::{ToEthers, ToReth};
 
 use ethers::types::{
     Block as EthersBlock, OtherFields, Transaction as EthersTransaction, H256 as EthersH256,
+    U64 as EthersU64,
 };
 use reth_rpc_types::{Block, BlockTransactions, Header, Rich};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// Std
+use std::collections::BTreeMap;
+
+/// Header fields added by Cancun, which neither ethers nor reth blocks have fields for yet. They
+/// travel in the block's [OtherFields] on the ethers side and in the [Rich] extra info on the
+/// reth side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancunFields {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob_gas_used: Option<EthersU64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub excess_blob_gas: Option<EthersU64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_beacon_block_root: Option<EthersH256>,
+}
+
+impl CancunFields {
+    /// Reads the Cancun fields of a block, all `None` for blocks before Cancun.
+    pub fn of<TX>(block: &EthersBlock<TX>) -> Self {
+        Self {
+            blob_gas_used: block.other.get_deserialized("blobGasUsed").and_then(Result::ok),
+            excess_blob_gas: block.other.get_deserialized("excessBlobGas").and_then(Result::ok),
+            parent_beacon_block_root: block
+                .other
+                .get_deserialized("parentBeaconBlockRoot")
+                .and_then(Result::ok),
+        }
+    }
+}
+
+/// Extra block fields (ethers) -> (reth), keeping the Cancun fields and any other unknown ones.
+fn into_extra_info(other: OtherFields) -> BTreeMap<String, Value> {
+    other.into_iter().collect()
+}
+
+/// Extra block fields (reth) -> (ethers).
+fn into_other_fields(extra_info: BTreeMap<String, Value>) -> OtherFields {
+    let mut other = OtherFields::default();
+    other.extend(extra_info);
+    other
+}
 
 /// EthersBlock<EthersH256> (ethers) -> Rich<Block> (reth)
 impl ToReth<Rich<Block>> for EthersBlock<EthersH256> {
@@ -14,18 +60,18 @@ impl ToReth<Rich<Block>> for EthersBlock<EthersH256> {
                 hash: self.hash.into_reth(),
                 parent_hash: self.parent_hash.into_reth(),
                 uncles_hash: self.uncles_hash.into_reth(),
-                miner: self.author.into_reth().unwrap(),
+                miner: self.author.into_reth().unwrap_or_default(),
                 state_root: self.state_root.into_reth(),
                 transactions_root: self.transactions_root.into_reth(),
                 receipts_root: self.receipts_root.into_reth(),
-                logs_bloom: self.logs_bloom.into_reth().unwrap(),
+                logs_bloom: self.logs_bloom.into_reth().unwrap_or_default(),
                 difficulty: self.difficulty.into_reth(),
                 number: self.number.into_reth(),
                 gas_limit: self.gas_limit.into_reth(),
                 gas_used: self.gas_used.into_reth(),
                 timestamp: self.timestamp.into_reth(),
                 extra_data: self.extra_data.into_reth(),
-                mix_hash: self.mix_hash.into_reth().unwrap(),
+                mix_hash: self.mix_hash.into_reth().unwrap_or_default(),
                 nonce: self.nonce.into_reth(),
                 base_fee_per_gas: self.base_fee_per_gas.into_reth(),
                 withdrawals_root: self.withdrawals_root.into_reth(),
@@ -36,7 +82,7 @@ impl ToReth<Rich<Block>> for EthersBlock<EthersH256> {
             size: self.size.into_reth(),
             withdrawals: self.withdrawals.into_reth(),
         };
-        Rich::from(block)
+        Rich { inner: block, extra_info: into_extra_info(self.other) }
     }
 }
 
@@ -49,18 +95,18 @@ impl ToReth<Rich<Block>> for EthersBlock<EthersTransaction> {
                 hash: self.hash.into_reth(),
                 parent_hash: self.parent_hash.into_reth(),
                 uncles_hash: self.uncles_hash.into_reth(),
-                miner: self.author.into_reth().unwrap(),
+                miner: self.author.into_reth().unwrap_or_default(),
                 state_root: self.state_root.into_reth(),
                 transactions_root: self.transactions_root.into_reth(),
                 receipts_root: self.receipts_root.into_reth(),
-                logs_bloom: self.logs_bloom.into_reth().unwrap(),
+                logs_bloom: self.logs_bloom.into_reth().unwrap_or_default(),
                 difficulty: self.difficulty.into_reth(),
                 number: self.number.into_reth(),
                 gas_limit: self.gas_limit.into_reth(),
                 gas_used: self.gas_used.into_reth(),
                 timestamp: self.timestamp.into_reth(),
                 extra_data: self.extra_data.into_reth(),
-                mix_hash: self.mix_hash.into_reth().unwrap(),
+                mix_hash: self.mix_hash.into_reth().unwrap_or_default(),
                 nonce: self.nonce.into_reth(),
                 base_fee_per_gas: self.base_fee_per_gas.into_reth(),
                 withdrawals_root: self.withdrawals_root.into_reth(),
@@ -71,7 +117,7 @@ impl ToReth<Rich<Block>> for EthersBlock<EthersTransaction> {
             size: self.size.into_reth(),
             withdrawals: self.withdrawals.into_reth(),
         };
-        Rich::from(block)
+        Rich { inner: block, extra_info: into_extra_info(self.other) }
     }
 }
 
@@ -109,7 +155,7 @@ impl ToEthers<EthersBlock<EthersH256>> for Rich<Block> {
             base_fee_per_gas: self.header.base_fee_per_gas.into_ethers(),
             withdrawals_root: self.header.withdrawals_root.into_ethers(),
             withdrawals: self.inner.withdrawals.into_ethers(),
-            other: OtherFields::default(),
+            other: into_other_fields(self.extra_info),
         }
     }
 }
@@ -146,7 +192,7 @@ impl ToEthers<EthersBlock<EthersTransaction>> for Rich<Block> {
             base_fee_per_gas: self.header.base_fee_per_gas.into_ethers(),
             withdrawals_root: self.header.withdrawals_root.into_ethers(),
             withdrawals: self.inner.withdrawals.into_ethers(),
-            other: OtherFields::default(),
+            other: into_other_fields(self.extra_info),
         }
     }
 }
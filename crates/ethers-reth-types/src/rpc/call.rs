@@ -1,7 +1,7 @@
-use crate::type_conversions::ToReth;
+use crate::ToReth;
 
 use ethers::types::transaction::eip2718::TypedTransaction as EthersTypedTransaction;
-use reth_revm::primitives::ruint::Uint;
+use reth_primitives::U8;
 use reth_rpc_types::CallRequest;
 
 /// Typed Tx (ethers) -> Call Request (reth)
@@ -11,14 +11,10 @@ impl ToReth<CallRequest> for EthersTypedTransaction {
             from: self.from().into_reth(),
             to: self.to_addr().into_reth(),
             gas_price: self.gas_price().into_reth(),
-            max_fee_per_gas: self
-                .as_eip1559_ref()
-                .map(|tx| tx.max_fee_per_gas.into_reth())
-                .unwrap(),
+            max_fee_per_gas: self.as_eip1559_ref().and_then(|tx| tx.max_fee_per_gas.into_reth()),
             max_priority_fee_per_gas: self
                 .as_eip1559_ref()
-                .map(|tx| tx.max_priority_fee_per_gas.into_reth())
-                .unwrap(),
+                .and_then(|tx| tx.max_priority_fee_per_gas.into_reth()),
             gas: self.gas().into_reth(),
             value: self.value().into_reth(),
             data: self.data().into_reth(),
@@ -26,9 +22,14 @@ impl ToReth<CallRequest> for EthersTypedTransaction {
             chain_id: self.chain_id().into_reth(),
             access_list: self.access_list().into_reth(),
             transaction_type: match self {
-                EthersTypedTransaction::Legacy(_) => Some(Uint::from(0)),
-                EthersTypedTransaction::Eip2930(_) => Some(Uint::from(1)),
-                EthersTypedTransaction::Eip1559(_) => Some(Uint::from(2)),
+                EthersTypedTransaction::Legacy(_) => Some(U8::from(0)),
+                EthersTypedTransaction::Eip2930(_) => Some(U8::from(1)),
+                EthersTypedTransaction::Eip1559(_) => Some(U8::from(2)),
+                #[cfg(feature = "optimism")]
+                EthersTypedTransaction::OptimismDeposited(_) => {
+                    // the deposit transaction type of the op-stack
+                    Some(U8::from(0x7E))
+                }
             },
         }
     }
@@ -1,7 +1,7 @@
-use crate::type_conversions::{ToEthers, ToReth};
+use crate::{ToEthers, ToReth};
 
 use ethers::types::{
-    Filter as EthersFilter, FilterBlockOption as EthersFilterBlockOption,
+    Filter as EthersFilter, FilterBlockOption as EthersFilterBlockOption, Topic as EthersTopic,
     ValueOrArray as EthersValueOrArray,
 };
 use reth_rpc_types::{Filter, FilterBlockOption, ValueOrArray};
@@ -72,11 +72,24 @@ impl ToReth<Filter> for EthersFilter {
         Filter {
             block_option: self.block_option.into_reth(),
             address: self.address.into_reth(),
-            topics: self.topics.into_reth(),
+            topics: self.topics.map(normalize_topic).into_reth(),
         }
     }
 }
 
+/// Rewrites the wildcards geth accepts within a topic position, an empty array or an array
+/// containing `null`, as a single `null` that keeps the position.
+fn normalize_topic(topic: Option<EthersTopic>) -> Option<EthersTopic> {
+    match topic {
+        Some(EthersValueOrArray::Array(topics))
+            if topics.is_empty() || topics.iter().any(Option::is_none) =>
+        {
+            Some(EthersValueOrArray::Value(None))
+        }
+        topic => topic,
+    }
+}
+
 /// Filter (reth) -> (ethers)
 impl ToEthers<EthersFilter> for Filter {
     fn into_ethers(self) -> EthersFilter {
@@ -1,4 +1,4 @@
-use crate::type_conversions::{ToEthers, ToReth};
+use crate::{ToEthers, ToReth};
 
 use ethers::types::Log as EthersLog;
 use reth_rpc_types::Log;
@@ -15,7 +15,7 @@ impl ToReth<Log> for EthersLog {
             transaction_hash: self.transaction_hash.into_reth(),
             transaction_index: self.transaction_index.into_reth(),
             log_index: self.log_index.into_reth(),
-            removed: self.removed.is_some(),
+            removed: self.removed.unwrap_or_default(),
         }
     }
 }
@@ -1,4 +1,4 @@
-use crate::type_conversions::{ToEthers, ToReth};
+use crate::{ConversionError, ToEthers, ToReth, TryToEthers};
 
 use ethers::types::{
     AccountDiff as EthersAccountDiff, Action as EthersAction, ActionType as EthersActionType,
@@ -11,7 +11,6 @@ use ethers::types::{
     VMExecutedOperation as EthersVMExecutedOperation, VMOperation as EthersVMOperation,
     VMTrace as EthersVMTrace,
 };
-use reth_revm::primitives::bitvec::macros::internal::funty::Fundamental;
 use reth_rpc_types::trace::parity::{
     AccountDiff, Action, CallAction, CallOutput, CallType, ChangedType, CreateAction, CreateOutput,
     Delta, LocalizedTransactionTrace, MemoryDelta, RewardAction, RewardType, SelfdestructAction,
@@ -201,7 +200,7 @@ impl ToReth<LocalizedTransactionTrace> for EthersTrace {
     fn into_reth(self) -> LocalizedTransactionTrace {
         LocalizedTransactionTrace {
             trace: self.clone().into_reth(),
-            transaction_position: self.transaction_position.map(|x| x.as_u64()),
+            transaction_position: self.transaction_position.map(|x| x as u64),
             transaction_hash: self.transaction_hash.into_reth(),
             block_number: Some(self.block_number),
             block_hash: Some(self.block_hash.into_reth()),
@@ -209,19 +208,22 @@ impl ToReth<LocalizedTransactionTrace> for EthersTrace {
     }
 }
 
-/// LocalizedTransactionTrace (reth) -> EthersTrace (ethers)
-impl ToEthers<EthersTrace> for LocalizedTransactionTrace {
-    fn into_ethers(self) -> EthersTrace {
+/// LocalizedTransactionTrace (reth) -> EthersTrace (ethers), failing on traces of pending
+/// transactions which have no block
+impl TryToEthers<EthersTrace> for LocalizedTransactionTrace {
+    fn try_into_ethers(self) -> Result<EthersTrace, ConversionError> {
+        let missing =
+            |field| ConversionError::MissingField { ty: "LocalizedTransactionTrace", field };
         let action = self.trace.action.into_ethers();
-        EthersTrace {
+        Ok(EthersTrace {
             action: action.clone(),
             result: self.trace.result.clone().into_ethers(),
             trace_address: self.trace.trace_address,
             subtraces: self.trace.subtraces,
             transaction_position: self.transaction_position.map(|x| x as usize),
             transaction_hash: self.transaction_hash.into_ethers(),
-            block_number: self.block_number.unwrap(),
-            block_hash: self.block_hash.into_ethers().unwrap(),
+            block_number: self.block_number.ok_or_else(|| missing("block_number"))?,
+            block_hash: self.block_hash.ok_or_else(|| missing("block_hash"))?.into_ethers(),
             action_type: match action {
                 EthersAction::Call(_) => EthersActionType::Call,
                 EthersAction::Create(_) => EthersActionType::Create,
@@ -229,7 +231,7 @@ impl ToEthers<EthersTrace> for LocalizedTransactionTrace {
                 EthersAction::Reward(_) => EthersActionType::Reward,
             },
             error: self.trace.error,
-        }
+        })
     }
 }
 
@@ -351,7 +353,7 @@ impl ToReth<TraceResultsWithTransactionHash> for EthersBlockTrace {
     fn into_reth(self) -> TraceResultsWithTransactionHash {
         TraceResultsWithTransactionHash {
             full_trace: self.clone().into_reth(),
-            transaction_hash: self.transaction_hash.into_reth().unwrap(),
+            transaction_hash: self.transaction_hash.into_reth().unwrap_or_default(),
         }
     }
 }
@@ -456,7 +458,7 @@ impl ToReth<VmExecutedOperation> for EthersVMExecutedOperation {
     fn into_reth(self) -> VmExecutedOperation {
         VmExecutedOperation {
             used: self.used,
-            push: Some(self.push[0]).into_reth(), // Check this
+            push: self.push.first().copied().into_reth(),
             mem: self.mem.into_reth(),
             store: self.store.into_reth(),
         }
@@ -468,7 +470,7 @@ impl ToEthers<EthersVMExecutedOperation> for VmExecutedOperation {
     fn into_ethers(self) -> EthersVMExecutedOperation {
         EthersVMExecutedOperation {
             used: self.used,
-            push: vec![self.push.into_ethers().unwrap()], // Check this
+            push: self.push.into_ethers().into_iter().collect(),
             mem: self.mem.into_ethers(),
             store: self.store.into_ethers(),
         }
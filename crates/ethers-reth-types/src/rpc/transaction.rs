@@ -1,10 +1,10 @@
-use crate::type_conversions::{ToEthers, ToReth};
+use crate::{ConversionError, ToEthers, ToReth, TryToEthers};
 
 use ethers::types::{
     OtherFields, Transaction as EthersTransaction, TransactionReceipt as EthersTransactionReceipt,
+    U256 as EthersU256,
 };
-use reth_primitives::AccessList;
-use reth_revm::primitives::ruint::Uint;
+use reth_primitives::{AccessList, U256};
 use reth_rpc_types::{Signature, Transaction, TransactionReceipt};
 
 /// Transaction (ethers) -> (reth)
@@ -39,8 +39,9 @@ impl ToReth<Transaction> for EthersTransaction {
 /// Transaction (reth) -> (ethers)
 impl ToEthers<EthersTransaction> for Transaction {
     fn into_ethers(self) -> EthersTransaction {
-        let (v, r, s) =
-            self.signature.map_or((Uint::MIN, Uint::MIN, Uint::MIN), |sig| (sig.v, sig.r, sig.s));
+        let (v, r, s) = self
+            .signature
+            .map_or((U256::ZERO, U256::ZERO, U256::ZERO), |sig| (sig.v, sig.r, sig.s));
         EthersTransaction {
             hash: self.hash.into_ethers(),
             nonce: self.nonce.into_ethers(),
@@ -85,18 +86,30 @@ impl ToReth<TransactionReceipt> for EthersTransactionReceipt {
             status_code: self.status.into_reth(),
             state_root: self.root.into_reth(),
             logs_bloom: self.logs_bloom.into_reth(),
-            transaction_type: self.transaction_type.into_reth().unwrap(),
-            effective_gas_price: self.effective_gas_price.into_reth().unwrap(),
+            // receipts from before Berlin and London carry neither field
+            transaction_type: self.transaction_type.into_reth().unwrap_or_default(),
+            effective_gas_price: self
+                .effective_gas_price
+                .map(|price| price.min(u128::MAX.into()))
+                .into_reth()
+                .unwrap_or_default(),
         }
     }
 }
 
-/// TransactionReceipt (reth) -> (ethers)
-impl ToEthers<EthersTransactionReceipt> for TransactionReceipt {
-    fn into_ethers(self) -> EthersTransactionReceipt {
-        EthersTransactionReceipt {
-            transaction_hash: self.transaction_hash.into_ethers().unwrap(),
-            transaction_index: self.transaction_index.into_ethers().unwrap(),
+/// TransactionReceipt (reth) -> (ethers), failing on pending receipts which have no hash or index
+impl TryToEthers<EthersTransactionReceipt> for TransactionReceipt {
+    fn try_into_ethers(self) -> Result<EthersTransactionReceipt, ConversionError> {
+        let missing = |field| ConversionError::MissingField { ty: "TransactionReceipt", field };
+        Ok(EthersTransactionReceipt {
+            transaction_hash: self
+                .transaction_hash
+                .ok_or_else(|| missing("transaction_hash"))?
+                .into_ethers(),
+            transaction_index: self
+                .transaction_index
+                .ok_or_else(|| missing("transaction_index"))?
+                .into_ethers(),
             block_hash: self.block_hash.into_ethers(),
             block_number: self.block_number.into_ethers(),
             from: self.from.into_ethers(),
@@ -111,6 +124,20 @@ impl ToEthers<EthersTransactionReceipt> for TransactionReceipt {
             transaction_type: Some(self.transaction_type.into_ethers()),
             effective_gas_price: Some(self.effective_gas_price.into_ethers()),
             other: OtherFields::default(),
+        })
+    }
+}
+
+/// Returns the price per unit of gas paid by `tx` in a block with the given base fee: the gas
+/// price for legacy transactions, and the base fee plus the priority fee capped at the max fee for
+/// EIP-1559 ones. Without a base fee, i.e. before London, the max fee is paid.
+pub fn effective_gas_price(tx: &EthersTransaction, base_fee: Option<EthersU256>) -> EthersU256 {
+    match (tx.max_fee_per_gas, base_fee) {
+        (Some(max_fee), Some(base_fee)) => {
+            let tip = tx.max_priority_fee_per_gas.unwrap_or_default();
+            max_fee.min(base_fee.saturating_add(tip))
         }
+        (Some(max_fee), None) => max_fee,
+        (None, _) => tx.gas_price.unwrap_or_default(),
     }
 }
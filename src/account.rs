@@ -0,0 +1,96 @@
+use crate::{
+    instrument::{db_read, instrument, record_block},
+    type_conversions::{ToEthers, ToReth},
+    RethClient, RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{
+        Address as EthersAddress, BlockId as EthersBlockId, NameOrAddress, H256 as EthersH256,
+        U256 as EthersU256, U64 as EthersU64,
+    },
+};
+use jsonrpsee::{core::Error as RpcError, RpcModule};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_primitives::{Account, Address, BlockId, BlockNumberOrTag, H256, KECCAK_EMPTY};
+use reth_provider::{AccountReader, StateProvider, StateProviderFactory};
+
+/// The state of an account at a block, as `eth_getAccount` returns it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountState {
+    pub balance: EthersU256,
+    pub nonce: EthersU64,
+    /// Hash of the account's code, the hash of empty code for accounts without one.
+    pub code_hash: EthersH256,
+    /// Root of the account's storage trie, the empty root for accounts without storage.
+    pub storage_root: EthersH256,
+}
+
+impl AccountState {
+    /// Returns the state of an account from its database entry, empty when there is none.
+    pub fn new(account: Option<Account>, storage_root: H256) -> Self {
+        let account = account.unwrap_or_default();
+        Self {
+            balance: account.balance.into(),
+            nonce: account.nonce.into(),
+            code_hash: account.bytecode_hash.unwrap_or(KECCAK_EMPTY).into_ethers(),
+            storage_root: storage_root.into_ethers(),
+        }
+    }
+}
+
+/// Reads the state of an account at a block. The storage root comes from the trie, which only
+/// holds the latest state, so older blocks fail like `eth_getProof` does.
+fn read_account(
+    provider: &RethClient,
+    address: Address,
+    block_id: BlockId,
+) -> Result<AccountState, reth_interfaces::Error> {
+    let state = provider.state_by_block_id(block_id)?;
+    let account = state.basic_account(address)?;
+    let (_, storage_root, _) = state.proof(address, &[])?;
+    Ok(AccountState::new(account, storage_root))
+}
+
+/// Returns the `eth_getAccount` method, answered with a single state read.
+pub(crate) fn account_module(provider: RethClient) -> Result<RpcModule<()>, RpcError> {
+    let mut module = RpcModule::new(());
+    module.register_method("eth_getAccount", move |params, _| {
+        let (address, block): (EthersAddress, Option<EthersBlockId>) = params.parse()?;
+        let block_id = block.into_reth().unwrap_or(BlockId::Number(BlockNumberOrTag::Latest));
+        read_account(&provider, address.into_reth(), block_id)
+            .map_err(|err| RpcError::Custom(err.to_string()))
+    })?;
+    Ok(module)
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the balance, nonce, code hash and storage root of an account in one read, like
+    /// `eth_getAccount`, instead of three calls and a proof.
+    pub async fn get_account<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        from: T,
+        block: Option<EthersBlockId>,
+    ) -> Result<AccountState, RethMiddlewareError<M>> {
+        instrument("get_account", async {
+            let address = self.get_address(from).await?;
+            let block_id = block.into_reth();
+            record_block(&block_id);
+            self.ensure_history(block_id)?;
+            self.ensure_fresh(block_id)?;
+            self.ensure_forkchoice(block_id)?;
+
+            let block_id = block_id.unwrap_or(BlockId::Number(BlockNumberOrTag::Latest));
+            Ok(db_read("get_account", || {
+                read_account(&self.reth_provider, address.into_reth(), block_id)
+            })?)
+        })
+        .await
+    }
+}
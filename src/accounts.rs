@@ -0,0 +1,73 @@
+use crate::RethMiddleware;
+use ethers::{
+    providers::Middleware,
+    signers::{LocalWallet, Signer, WalletError},
+    types::Address as EthersAddress,
+};
+
+// Std
+use std::{fs, path::Path};
+
+/// The accounts reported by `get_accounts`, like `eth_accounts`, and the sender filled into
+/// transactions without one. When empty, both come from the inner provider.
+///
+/// The middleware does not sign: the accounts are those of the signers stacked on top of it, e.g.
+/// a `SignerMiddleware`, so tools relying on `eth_accounts` see who can send.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Accounts {
+    pub addresses: Vec<EthersAddress>,
+    /// Sender of the transactions without one, the first address if unset.
+    pub default_sender: Option<EthersAddress>,
+}
+
+impl Accounts {
+    pub fn new(addresses: Vec<EthersAddress>) -> Self {
+        Self { addresses, default_sender: None }
+    }
+
+    /// Returns the accounts of a set of signers, in order.
+    pub fn from_signers<'a, S: Signer + 'a>(signers: impl IntoIterator<Item = &'a S>) -> Self {
+        Self::new(signers.into_iter().map(Signer::address).collect())
+    }
+
+    /// Sets the sender of the transactions without one.
+    pub fn with_default_sender(mut self, sender: EthersAddress) -> Self {
+        self.default_sender = Some(sender);
+        self
+    }
+
+    /// Returns the sender of the transactions without one, if any account is set.
+    pub fn sender(&self) -> Option<EthersAddress> {
+        self.default_sender.or_else(|| self.addresses.first().copied())
+    }
+}
+
+/// Decrypts every keystore file of a directory, e.g. the `keystore` directory of a geth datadir,
+/// with the same password. Wallets are returned in the order of their file names.
+pub fn load_keystore(dir: &Path, password: &str) -> Result<Vec<LocalWallet>, WalletError> {
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    paths.into_iter().map(|path| LocalWallet::decrypt_keystore(path, password)).collect()
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Sets the accounts reported by `get_accounts` and the default sender.
+    pub fn with_accounts(mut self, accounts: Accounts) -> Self {
+        self.accounts = accounts;
+        self
+    }
+
+    /// Returns the accounts reported by `get_accounts` and the default sender.
+    pub fn accounts(&self) -> &Accounts {
+        &self.accounts
+    }
+}
@@ -0,0 +1,79 @@
+//! Alloy-typed access to the database, behind the `alloy` feature.
+//!
+//! alloy's `Provider` trait is not released yet, so these mirror its state methods as inherent
+//! methods taking and returning [alloy_primitives] types. Code moving from ethers-rs to alloy can
+//! switch to them without going through the ethers conversions.
+
+use crate::{
+    instrument::instrument,
+    type_conversions::alloy::{FromAlloy, ToAlloy},
+    RethMiddleware, RethMiddlewareError,
+};
+use alloy_primitives::{Address, Bytes, B256, U256};
+use ethers::providers::Middleware;
+
+// Reth
+use reth_primitives::{serde_helper::JsonStorageKey, BlockId, H160, H256};
+use reth_rpc_api::EthApiServer;
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    pub async fn alloy_get_block_number(&self) -> Result<u64, RethMiddlewareError<M>> {
+        instrument("alloy_get_block_number", async {
+            Ok(self.reth_api.block_number()?.to::<u64>())
+        })
+        .await
+    }
+
+    pub async fn alloy_get_balance(
+        &self,
+        address: Address,
+        block: Option<BlockId>,
+    ) -> Result<U256, RethMiddlewareError<M>> {
+        instrument("alloy_get_balance", async {
+            Ok(self.reth_api.balance(H160::from_alloy(address), block).await?.into_alloy())
+        })
+        .await
+    }
+
+    pub async fn alloy_get_transaction_count(
+        &self,
+        address: Address,
+        block: Option<BlockId>,
+    ) -> Result<U256, RethMiddlewareError<M>> {
+        instrument("alloy_get_transaction_count", async {
+            Ok(self
+                .reth_api
+                .transaction_count(H160::from_alloy(address), block)
+                .await?
+                .into_alloy())
+        })
+        .await
+    }
+
+    pub async fn alloy_get_code(
+        &self,
+        address: Address,
+        block: Option<BlockId>,
+    ) -> Result<Bytes, RethMiddlewareError<M>> {
+        instrument("alloy_get_code", async {
+            Ok(self.reth_api.get_code(H160::from_alloy(address), block).await?.into_alloy())
+        })
+        .await
+    }
+
+    pub async fn alloy_get_storage_at(
+        &self,
+        address: Address,
+        slot: B256,
+        block: Option<BlockId>,
+    ) -> Result<B256, RethMiddlewareError<M>> {
+        instrument("alloy_get_storage_at", async {
+            let slot = JsonStorageKey(H256::from_alloy(slot));
+            Ok(self.reth_api.storage_at(H160::from_alloy(address), slot, block).await?.into_alloy())
+        })
+        .await
+    }
+}
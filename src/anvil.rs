@@ -0,0 +1,35 @@
+//! Anvil integration behind the `anvil` feature.
+//!
+//! Anvil only forks from an RPC url, so the database is served on a loopback port the fork is
+//! pointed at. Every fork request is then answered locally, which is what makes forking mainnet
+//! thousands of times in a test suite cheap.
+
+use crate::{instrument::db_read, RethMiddleware};
+use anvil::NodeConfig;
+use ethers::providers::Middleware;
+use jsonrpsee::server::ServerHandle;
+
+// Reth
+use reth_provider::BlockNumReader;
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Configures Anvil to fork from the database at `block`, defaulting to the latest block.
+    ///
+    /// The returned handle keeps the fork source alive, and must outlive the Anvil instance.
+    pub async fn anvil_fork_config(
+        &self,
+        config: NodeConfig,
+        block: Option<u64>,
+    ) -> eyre::Result<(NodeConfig, ServerHandle)> {
+        let block = match block {
+            Some(block) => block,
+            None => db_read("best_block_number", || self.reth_provider.best_block_number())?,
+        };
+
+        let (url, handle) = self.serve_fork_source().await?;
+        Ok((config.with_eth_rpc_url(Some(url)).with_fork_block_number(Some(block)), handle))
+    }
+}
@@ -0,0 +1,121 @@
+use crate::{
+    instrument::{db_read, instrument, record_block},
+    type_conversions::ToReth,
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Address as EthersAddress, BlockId as EthersBlockId, U256 as EthersU256},
+};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_primitives::{Address, BlockId};
+use reth_provider::{AccountReader, BlockIdReader, ChangeSetReader, StateProviderFactory};
+use reth_rpc::eth::error::EthApiError;
+
+// Std
+use std::{collections::BTreeMap, ops::RangeInclusive};
+
+/// Most points a single balance history may have.
+pub const MAX_BALANCE_HISTORY_POINTS: u64 = 10_000;
+
+/// Balance of an account before and after a block.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceChange {
+    pub before: EthersU256,
+    pub after: EthersU256,
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the accounts whose balance a block changed, like `reth_getBalanceChangesInBlock`,
+    /// read from the block's account changeset instead of tracing it.
+    pub async fn get_balance_changes_in_block<T: Into<EthersBlockId> + Send + Sync>(
+        &self,
+        block: T,
+    ) -> Result<BTreeMap<EthersAddress, BalanceChange>, RethMiddlewareError<M>> {
+        instrument("get_balance_changes_in_block", async {
+            let block_id: BlockId = block.into().into_reth();
+            record_block(&block_id);
+            self.ensure_history(Some(block_id))?;
+
+            let number = db_read("block_number_for_id", || {
+                self.reth_provider.block_number_for_id(block_id)
+            })?
+            .ok_or(RethMiddlewareError::BlockNotFound)?;
+            let changeset = db_read("account_block_changeset", || {
+                self.reth_provider.account_block_changeset(number)
+            })?;
+            let state = db_read("history_by_block_number", || {
+                self.reth_provider.history_by_block_number(number)
+            })?;
+
+            let mut changes = BTreeMap::new();
+            for change in changeset {
+                let before = change.info.map(|account| account.balance).unwrap_or_default();
+                let after = state
+                    .basic_account(change.address)?
+                    .map(|account| account.balance)
+                    .unwrap_or_default();
+                if before != after {
+                    changes.insert(
+                        change.address.into(),
+                        BalanceChange { before: before.into(), after: after.into() },
+                    );
+                }
+            }
+            Ok(changes)
+        })
+        .await
+    }
+
+    /// Returns the balance of `address` at the end of every `step`-th block of `range`, and of
+    /// its last block, as `(block, balance)` points in order.
+    ///
+    /// Each point is one lookup in the account history index and the changeset it points to, so
+    /// the cost grows with the number of points rather than the length of the range.
+    pub async fn balance_history(
+        &self,
+        address: EthersAddress,
+        range: RangeInclusive<u64>,
+        step: u64,
+    ) -> Result<Vec<(u64, EthersU256)>, RethMiddlewareError<M>> {
+        instrument("balance_history", async {
+            record_block(&range);
+            let (first, last) = range.into_inner();
+            if step == 0 || first > last {
+                return Err(EthApiError::InvalidParams("empty balance history".to_string()).into())
+            }
+            if (last - first) / step >= MAX_BALANCE_HISTORY_POINTS {
+                return Err(EthApiError::InvalidParams(format!(
+                    "at most {MAX_BALANCE_HISTORY_POINTS} points can be queried"
+                ))
+                .into())
+            }
+            self.ensure_history(Some(first.into()))?;
+
+            let address = Address::from(address.0);
+            let mut blocks: Vec<u64> = (first..=last).step_by(step as usize).collect();
+            if blocks.last() != Some(&last) {
+                blocks.push(last);
+            }
+
+            let mut points = Vec::with_capacity(blocks.len());
+            for number in blocks {
+                let state = db_read("history_by_block_number", || {
+                    self.reth_provider.history_by_block_number(number)
+                })?;
+                let balance = state
+                    .basic_account(address)?
+                    .map(|account| account.balance)
+                    .unwrap_or_default();
+                points.push((number, balance.into()));
+            }
+            Ok(points)
+        })
+        .await
+    }
+}
@@ -0,0 +1,51 @@
+use crate::{instrument::instrument, RethMiddleware, RethMiddlewareError};
+use ethers::{
+    providers::Middleware,
+    types::{Address as EthersAddress, BlockId as EthersBlockId, H160, H256 as EthersH256},
+};
+use hex_literal::hex;
+
+/// Address of the EIP-4788 beacon roots contract.
+pub const BEACON_ROOTS_ADDRESS: EthersAddress =
+    H160(hex!("000F3df6D732807Ef1319fB7B8bB8522d0Beac02"));
+
+/// Length of the ring buffers of the beacon roots contract. Timestamps this many seconds apart
+/// share an entry, so with 12 second slots a root is kept for 8191 slots, about 27 hours.
+pub const HISTORY_BUFFER_LENGTH: u64 = 8191;
+
+/// Returns the storage slots of the beacon roots contract holding the timestamp a root was
+/// recorded at and the root itself, for a given timestamp.
+pub fn beacon_root_slots(timestamp: u64) -> (EthersH256, EthersH256) {
+    let index = timestamp % HISTORY_BUFFER_LENGTH;
+    let slot = |index: u64| EthersH256::from_low_u64_be(index);
+    (slot(index), slot(index + HISTORY_BUFFER_LENGTH))
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the parent beacon block root recorded by the block with the given timestamp, as
+    /// the beacon roots contract holds it at `block`, by reading its ring buffers directly.
+    ///
+    /// Returns `None` where the contract's `get` would revert: the timestamp is not a block's,
+    /// the root was overwritten since, or `block` is before Cancun.
+    pub async fn get_beacon_root(
+        &self,
+        timestamp: u64,
+        block: Option<EthersBlockId>,
+    ) -> Result<Option<EthersH256>, RethMiddlewareError<M>> {
+        instrument("get_beacon_root", async {
+            if timestamp == 0 {
+                return Ok(None)
+            }
+            let (timestamp_slot, root_slot) = beacon_root_slots(timestamp);
+            let recorded = self.get_storage_at(BEACON_ROOTS_ADDRESS, timestamp_slot, block).await?;
+            if recorded != EthersH256::from_low_u64_be(timestamp) {
+                return Ok(None)
+            }
+            Ok(Some(self.get_storage_at(BEACON_ROOTS_ADDRESS, root_slot, block).await?))
+        })
+        .await
+    }
+}
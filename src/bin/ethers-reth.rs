@@ -0,0 +1,153 @@
+//! Queries a reth datadir from the command line, printing the results as JSON.
+
+use clap::{Parser, Subcommand};
+use ethers::{
+    providers::{Middleware, MockProvider, Provider},
+    types::{Address, BlockId, BlockNumber, Filter, H256},
+};
+use ethers_reth::RethMiddleware;
+use serde::Serialize;
+
+// Std
+use std::{io, path::PathBuf, str::FromStr};
+
+#[derive(Debug, Parser)]
+#[command(name = "ethers-reth", version, about = "Offline queries against a reth datadir")]
+struct Cli {
+    /// Datadir of the reth node, e.g. `~/.local/share/reth/mainnet`.
+    #[arg(long, env = "ETHERS_RETH_DATADIR")]
+    datadir: PathBuf,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Prints a block.
+    Block {
+        /// Number, hash or tag of the block.
+        #[arg(default_value = "latest")]
+        block: Block,
+        /// Includes the full transactions instead of their hashes.
+        #[arg(long)]
+        txs: bool,
+    },
+    /// Prints a transaction.
+    Tx { hash: H256 },
+    /// Prints the receipts of a block.
+    Receipts {
+        #[arg(default_value = "latest")]
+        block: Block,
+    },
+    /// Prints the logs matching a filter.
+    Logs {
+        #[arg(long)]
+        from_block: Option<Block>,
+        #[arg(long)]
+        to_block: Option<Block>,
+        /// Emitting contracts, any of them.
+        #[arg(long)]
+        address: Vec<Address>,
+        /// Event signature hashes, any of them.
+        #[arg(long)]
+        topic: Vec<H256>,
+    },
+    /// Prints the parity style traces of a transaction.
+    Trace { hash: H256 },
+    /// Prints the account and storage proof of an address.
+    Proof {
+        address: Address,
+        /// Storage slots to prove.
+        #[arg(long)]
+        slot: Vec<H256>,
+        #[arg(long)]
+        block: Option<Block>,
+    },
+}
+
+/// A block number, a block hash, or a tag like `latest`.
+#[derive(Debug, Clone, Copy)]
+struct Block(BlockId);
+
+impl FromStr for Block {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() == 66 {
+            let hash = s.parse::<H256>().map_err(|err| err.to_string())?;
+            return Ok(Block(hash.into()))
+        }
+        let number = match s {
+            "latest" => BlockNumber::Latest,
+            "finalized" => BlockNumber::Finalized,
+            "safe" => BlockNumber::Safe,
+            "earliest" => BlockNumber::Earliest,
+            "pending" => BlockNumber::Pending,
+            _ => BlockNumber::Number(
+                s.parse::<u64>().map_err(|_| format!("invalid block `{s}`"))?.into(),
+            ),
+        };
+        Ok(Block(number.into()))
+    }
+}
+
+/// Returns the number of a block tag, for the filters only taking numbers.
+fn block_number(block: Block) -> eyre::Result<BlockNumber> {
+    match block.0 {
+        BlockId::Number(number) => Ok(number),
+        BlockId::Hash(_) => eyre::bail!("filters take block numbers or tags, not hashes"),
+    }
+}
+
+fn print(value: &impl Serialize) -> eyre::Result<()> {
+    serde_json::to_writer_pretty(io::stdout().lock(), value)?;
+    println!();
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    let cli = Cli::parse();
+    // every query is answered from the database, the mock only fills the inner provider
+    let middleware =
+        RethMiddleware::from_db_path(Provider::new(MockProvider::new()), &cli.datadir)?;
+
+    match cli.command {
+        Command::Block { block, txs: false } => print(&middleware.get_block(block.0).await?),
+        Command::Block { block, txs: true } => {
+            print(&middleware.get_block_with_txs(block.0).await?)
+        }
+        Command::Tx { hash } => print(&middleware.get_transaction(hash).await?),
+        Command::Receipts { block } => {
+            let block = middleware
+                .get_block(block.0)
+                .await?
+                .ok_or_else(|| eyre::eyre!("block not found"))?;
+            let mut receipts = Vec::with_capacity(block.transactions.len());
+            for hash in block.transactions {
+                receipts.push(middleware.get_transaction_receipt(hash).await?);
+            }
+            print(&receipts)
+        }
+        Command::Logs { from_block, to_block, address, topic } => {
+            let mut filter = Filter::new();
+            if !address.is_empty() {
+                filter = filter.address(address);
+            }
+            if !topic.is_empty() {
+                filter = filter.topic0(topic);
+            }
+            if let Some(block) = from_block {
+                filter = filter.from_block(block_number(block)?);
+            }
+            if let Some(block) = to_block {
+                filter = filter.to_block(block_number(block)?);
+            }
+            print(&middleware.get_logs(&filter).await?)
+        }
+        Command::Trace { hash } => print(&middleware.trace_transaction(hash).await?),
+        Command::Proof { address, slot, block } => {
+            print(&middleware.get_proof(address, slot, block.map(|block| block.0)).await?)
+        }
+    }
+}
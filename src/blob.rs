@@ -0,0 +1,202 @@
+use crate::{
+    instrument::{db_read, instrument},
+    type_conversions::{rpc::block::CancunFields, ToEthers},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{
+        Block as EthersBlock, BlockNumber as EthersBlockNumber, H256 as EthersH256,
+        U256 as EthersU256,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_rpc::eth::error::EthApiError;
+
+// Std
+use std::ops::RangeInclusive;
+
+/// Blob gas a block is priced to target, three blobs.
+pub const TARGET_BLOB_GAS_PER_BLOCK: u64 = 393_216;
+
+/// Most blob gas a block may use, six blobs.
+pub const MAX_BLOB_GAS_PER_BLOCK: u64 = 786_432;
+
+/// Lowest price of a unit of blob gas, in wei.
+pub const MIN_BLOB_GASPRICE: u64 = 1;
+
+/// How fast the blob base fee reacts to excess blob gas.
+pub const BLOB_GASPRICE_UPDATE_FRACTION: u64 = 3_338_477;
+
+/// Most blocks a blob fee history may span, as for `eth_feeHistory`.
+pub const MAX_BLOB_FEE_HISTORY_BLOCKS: u64 = 1024;
+
+/// Blob base fees of a range of blocks, in the shape of the blob fields of `eth_feeHistory`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlobFeeHistory {
+    pub oldest_block: u64,
+    /// Blob base fee of every block of the range and of the block after it, `None` for blocks
+    /// before Cancun.
+    pub base_fee_per_blob_gas: Vec<Option<EthersU256>>,
+    /// Blob gas used by every block of the range over the most it may use.
+    pub blob_gas_used_ratio: Vec<f64>,
+}
+
+/// Returns the blob base fee of a block with the given excess blob gas, as specified by EIP-4844.
+pub fn blob_base_fee(excess_blob_gas: u64) -> EthersU256 {
+    fake_exponential(
+        MIN_BLOB_GASPRICE.into(),
+        excess_blob_gas.into(),
+        BLOB_GASPRICE_UPDATE_FRACTION.into(),
+    )
+}
+
+/// Returns the excess blob gas of the block following one with the given excess and usage.
+pub fn next_excess_blob_gas(excess_blob_gas: u64, blob_gas_used: u64) -> u64 {
+    excess_blob_gas.saturating_add(blob_gas_used).saturating_sub(TARGET_BLOB_GAS_PER_BLOCK)
+}
+
+/// Approximates `factor * e ** (numerator / denominator)` with integers, saturating at
+/// [EthersU256::MAX] for fees no block could pay.
+fn fake_exponential(
+    factor: EthersU256,
+    numerator: EthersU256,
+    denominator: EthersU256,
+) -> EthersU256 {
+    let Some(mut accum) = factor.checked_mul(denominator) else { return EthersU256::MAX };
+    let mut output = EthersU256::zero();
+    let mut i = EthersU256::one();
+    while !accum.is_zero() {
+        let Some(sum) = output.checked_add(accum) else { return EthersU256::MAX };
+        output = sum;
+        let (Some(product), Some(divisor)) =
+            (accum.checked_mul(numerator), denominator.checked_mul(i))
+        else {
+            return EthersU256::MAX
+        };
+        accum = product / divisor;
+        i += EthersU256::one();
+    }
+    output / denominator
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the blob base fees of the blocks in `range` and of the block after it, computed
+    /// from the excess blob gas in their headers.
+    pub async fn blob_fee_history(
+        &self,
+        range: RangeInclusive<u64>,
+    ) -> Result<BlobFeeHistory, RethMiddlewareError<M>> {
+        instrument("blob_fee_history", async {
+            let (oldest_block, last_block) = range.clone().into_inner();
+            if oldest_block > last_block {
+                return Err(EthApiError::InvalidParams(format!(
+                    "oldest block {oldest_block} is after the last block {last_block}"
+                ))
+                .into())
+            }
+            if last_block - oldest_block >= MAX_BLOB_FEE_HISTORY_BLOCKS {
+                return Err(EthApiError::InvalidParams(format!(
+                    "at most {MAX_BLOB_FEE_HISTORY_BLOCKS} blocks can be queried"
+                ))
+                .into())
+            }
+
+            let headers =
+                db_read("sealed_headers_range", || self.reth_provider.sealed_headers_range(range))?;
+            if headers.len() as u64 != last_block - oldest_block + 1 {
+                return Err(RethMiddlewareError::BlockNotFound)
+            }
+
+            let mut history = BlobFeeHistory { oldest_block, ..Default::default() };
+            let mut next = None;
+            for header in headers {
+                let header: EthersBlock<EthersH256> = header.into_ethers();
+                let fields = CancunFields::of(&header);
+                let blob_gas_used = fields.blob_gas_used.map_or(0, |used| used.as_u64());
+                let excess_blob_gas = fields.excess_blob_gas.map(|excess| excess.as_u64());
+
+                history.base_fee_per_blob_gas.push(excess_blob_gas.map(blob_base_fee));
+                history
+                    .blob_gas_used_ratio
+                    .push(blob_gas_used as f64 / MAX_BLOB_GAS_PER_BLOCK as f64);
+                next = excess_blob_gas
+                    .map(|excess| blob_base_fee(next_excess_blob_gas(excess, blob_gas_used)));
+            }
+            history.base_fee_per_blob_gas.push(next);
+            Ok(history)
+        })
+        .await
+    }
+
+    /// Returns the blob base fee of the next block, `None` before Cancun.
+    pub async fn estimate_blob_fee(&self) -> Result<Option<EthersU256>, RethMiddlewareError<M>> {
+        instrument("estimate_blob_fee", async {
+            let block = self
+                .get_block(EthersBlockNumber::Latest)
+                .await?
+                .ok_or(RethMiddlewareError::BlockNotFound)?;
+            let fields = CancunFields::of(&block);
+            let blob_gas_used = fields.blob_gas_used.map_or(0, |used| used.as_u64());
+            Ok(fields
+                .excess_blob_gas
+                .map(|excess| blob_base_fee(next_excess_blob_gas(excess.as_u64(), blob_gas_used))))
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fake_exponential_matches_eip4844_vectors() {
+        let vectors = [
+            (1, 0, 1, 1),
+            (38493, 0, 1000, 38493),
+            (0, 1234, 2345, 0),
+            (1, 2, 1, 6),
+            (1, 4, 2, 6),
+            (1, 3, 1, 16),
+            (1, 6, 2, 18),
+            (1, 2, 2, 2),
+            (1, 8, 2, 50),
+        ];
+        for (factor, numerator, denominator, expected) in vectors {
+            assert_eq!(
+                fake_exponential(factor.into(), numerator.into(), denominator.into()),
+                expected.into(),
+                "fake_exponential({factor}, {numerator}, {denominator})"
+            );
+        }
+    }
+
+    #[test]
+    fn fake_exponential_saturates() {
+        assert_eq!(fake_exponential(EthersU256::MAX, 1.into(), 2.into()), EthersU256::MAX);
+        assert_eq!(fake_exponential(1.into(), EthersU256::MAX, 1.into()), EthersU256::MAX);
+    }
+
+    #[test]
+    fn blob_base_fee_starts_at_minimum() {
+        assert_eq!(blob_base_fee(0), MIN_BLOB_GASPRICE.into());
+        assert_eq!(blob_base_fee(TARGET_BLOB_GAS_PER_BLOCK), MIN_BLOB_GASPRICE.into());
+        assert!(blob_base_fee(10 * BLOB_GASPRICE_UPDATE_FRACTION) > 1.into());
+    }
+
+    #[test]
+    fn next_excess_blob_gas_tracks_target() {
+        assert_eq!(next_excess_blob_gas(0, 0), 0);
+        assert_eq!(next_excess_blob_gas(0, TARGET_BLOB_GAS_PER_BLOCK), 0);
+        assert_eq!(next_excess_blob_gas(0, MAX_BLOB_GAS_PER_BLOCK), TARGET_BLOB_GAS_PER_BLOCK);
+        assert_eq!(next_excess_blob_gas(100, 0), 0);
+        assert_eq!(next_excess_blob_gas(u64::MAX, u64::MAX), u64::MAX - TARGET_BLOB_GAS_PER_BLOCK);
+    }
+}
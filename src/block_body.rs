@@ -0,0 +1,157 @@
+use crate::{
+    instrument::{db_read, instrument, record_block},
+    type_conversions::{ToEthers, ToReth},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::{Middleware, MiddlewareError},
+    types::{
+        BlockId as EthersBlockId, BlockNumber as EthersBlockNumber,
+        Transaction as EthersTransaction, H256 as EthersH256, U256 as EthersU256,
+    },
+};
+
+// Reth
+use reth_primitives::{BlockId, SealedHeader, StoredBlockBodyIndices, U256};
+use reth_provider::{BlockIdReader, BlockReader, HeaderProvider, TransactionsProvider};
+use reth_rlp::Encodable;
+use reth_rpc::eth::error::EthApiError;
+use reth_rpc_types::{Block, Rich, Transaction};
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the number of transactions in the block with the given number, like
+    /// `eth_getBlockTransactionCountByNumber`, from the block's body indices alone.
+    pub async fn get_block_transaction_count_by_number(
+        &self,
+        block: EthersBlockNumber,
+    ) -> Result<EthersU256, RethMiddlewareError<M>> {
+        instrument(
+            "get_block_transaction_count_by_number",
+            self.fallback(
+                "get_block_transaction_count_by_number",
+                self.block_transaction_count(EthersBlockId::Number(block)),
+                || async {
+                    self.inner
+                        .provider()
+                        .request("eth_getBlockTransactionCountByNumber", [block])
+                        .await
+                        .map_err(M::Error::from_provider_err)
+                },
+            ),
+        )
+        .await
+    }
+
+    /// Returns the number of transactions in the block with the given hash, like
+    /// `eth_getBlockTransactionCountByHash`, from the block's body indices alone.
+    pub async fn get_block_transaction_count_by_hash(
+        &self,
+        hash: EthersH256,
+    ) -> Result<EthersU256, RethMiddlewareError<M>> {
+        instrument(
+            "get_block_transaction_count_by_hash",
+            self.fallback(
+                "get_block_transaction_count_by_hash",
+                self.block_transaction_count(EthersBlockId::Hash(hash)),
+                || async {
+                    self.inner
+                        .provider()
+                        .request("eth_getBlockTransactionCountByHash", [hash])
+                        .await
+                        .map_err(M::Error::from_provider_err)
+                },
+            ),
+        )
+        .await
+    }
+
+    /// Fills in the total difficulty and size of a block if reth left them out, as several
+    /// libraries fail to parse blocks without them.
+    pub(crate) fn with_block_info(
+        &self,
+        block: Option<Rich<Block>>,
+    ) -> Result<Option<Rich<Block>>, RethMiddlewareError<M>> {
+        let Some(mut block) = block else { return Ok(None) };
+        let Some(hash) = block.header.hash else { return Ok(Some(block)) };
+
+        if block.total_difficulty.is_none() {
+            block.inner.total_difficulty =
+                db_read("header_td", || self.reth_provider.header_td(&hash))?;
+        }
+        if block.size.is_none() {
+            block.inner.size = db_read("block_by_hash", || self.reth_provider.block_by_hash(hash))?
+                .map(|stored| U256::from(stored.length()));
+        }
+        Ok(Some(block))
+    }
+
+    /// Reads the transaction at `index` in a block without loading the rest of its body.
+    pub(crate) async fn transaction_by_block_and_index(
+        &self,
+        block: EthersBlockId,
+        index: u64,
+    ) -> Result<Option<EthersTransaction>, RethMiddlewareError<M>> {
+        let Some((header, indices)) = self.block_body_indices(block)? else { return Ok(None) };
+        if index >= indices.tx_count {
+            return Ok(None)
+        }
+
+        let tx_id = indices.first_tx_num + index;
+        let Some(tx) =
+            db_read("transaction_by_id", || self.reth_provider.transaction_by_id(tx_id))?
+        else {
+            return Ok(None)
+        };
+        let tx = tx.into_ecrecovered().ok_or(EthApiError::InvalidTransactionSignature)?;
+
+        Ok(Some(
+            Transaction::from_recovered_with_block_context(
+                tx,
+                header.hash,
+                header.number,
+                header.base_fee_per_gas,
+                U256::from(index),
+            )
+            .into_ethers(),
+        ))
+    }
+
+    async fn block_transaction_count(
+        &self,
+        block: EthersBlockId,
+    ) -> Result<EthersU256, RethMiddlewareError<M>> {
+        let (_, indices) =
+            self.block_body_indices(block)?.ok_or(RethMiddlewareError::BlockNotFound)?;
+        Ok(indices.tx_count.into())
+    }
+
+    /// Resolves a block to its header and the range of its transactions in the transactions table.
+    fn block_body_indices(
+        &self,
+        block: EthersBlockId,
+    ) -> Result<Option<(SealedHeader, StoredBlockBodyIndices)>, RethMiddlewareError<M>> {
+        let block_id: BlockId = block.into_reth();
+        record_block(&block_id);
+        self.ensure_forkchoice(Some(block_id))?;
+
+        let Some(number) =
+            db_read("block_number_for_id", || self.reth_provider.block_number_for_id(block_id))?
+        else {
+            return Ok(None)
+        };
+        let Some(header) = db_read("sealed_header", || self.reth_provider.sealed_header(number))?
+        else {
+            return Ok(None)
+        };
+        // a hash of a block off the canonical chain may still resolve to a canonical number
+        if matches!(block, EthersBlockId::Hash(hash) if hash != header.hash.into()) {
+            return Ok(None)
+        }
+        let indices =
+            db_read("block_body_indices", || self.reth_provider.block_body_indices(number))?;
+        Ok(indices.map(|indices| (header, indices)))
+    }
+}
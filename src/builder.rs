@@ -0,0 +1,410 @@
+#[cfg(feature = "debug")]
+use crate::execution::tracers::NativeTracers;
+use crate::{
+    accounts::Accounts,
+    datadir::{check_db_version, DatadirError},
+    execution::{precompiles::PrecompileOverrides, spec::SpecOverride},
+    fallback::FallbackConfig,
+    fees::{FeeEstimator, GasPriceMode},
+    init::ApiConfig,
+    limits::{CallLimits, ConcurrencyLimit, LogLimits, MethodTimeouts, TraceLimits},
+    pending::PendingMode,
+    pruning::PruneLimits,
+    server::{
+        auth::ServerAuth,
+        rate_limit::{RateLimiter, RateLimits},
+    },
+    staleness::StaleTipPolicy,
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::providers::Middleware;
+use serde::Deserialize;
+
+// Reth
+use reth_primitives::ChainSpec;
+use reth_provider::BlockHashReader;
+use reth_rpc::eth::{cache::EthStateCacheConfig, gas_oracle::GasPriceOracleConfig};
+
+// Std
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::runtime::Handle;
+
+/// The RPC namespaces served from the database.
+///
+/// A disabled namespace fails with [RethMiddlewareError::UnsupportedMethod], so its methods go to
+/// the inner provider under a fallback policy, and it is left out of [RethMiddleware::rpc_module].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Namespaces {
+    pub trace: bool,
+    pub debug: bool,
+}
+
+impl Default for Namespaces {
+    fn default() -> Self {
+        Self { trace: cfg!(feature = "trace"), debug: cfg!(feature = "debug") }
+    }
+}
+
+/// Configures and opens a [RethMiddleware].
+///
+/// Every table lives in the MDBX environment at `db_path`, the database version this crate reads
+/// has no separate static files to point at.
+///
+/// ```rust,ignore
+/// let middleware = RethMiddleware::builder(provider, db_path)
+///     .chain(chain_spec)
+///     .max_tracing_requests(32)
+///     .debug(false)
+///     .build()?;
+/// ```
+#[derive(Debug)]
+#[must_use]
+pub struct RethMiddlewareBuilder<M> {
+    inner: M,
+    db_path: PathBuf,
+    handle: Option<Handle>,
+    chain: Option<Arc<ChainSpec>>,
+    api_config: ApiConfig,
+    prune_limits: Option<PruneLimits>,
+    fallback: FallbackConfig,
+    stale_tip: StaleTipPolicy,
+    pending: PendingMode,
+    call_limits: CallLimits,
+    log_limits: LogLimits,
+    trace_limits: TraceLimits,
+    method_timeouts: MethodTimeouts,
+    max_heavy_requests: Option<usize>,
+    proof_chunk_size: Option<usize>,
+    max_proof_keys: Option<usize>,
+    spec_override: SpecOverride,
+    precompile_overrides: PrecompileOverrides,
+    native_multicall: bool,
+    #[cfg(feature = "debug")]
+    native_tracers: NativeTracers,
+    fee_estimator: FeeEstimator,
+    gas_price_mode: GasPriceMode,
+    manage_nonces: bool,
+    accounts: Accounts,
+    namespaces: Namespaces,
+    rate_limits: RateLimits,
+    server_auth: ServerAuth,
+}
+
+impl<M> RethMiddlewareBuilder<M>
+where
+    M: Middleware,
+{
+    pub fn new<P: AsRef<Path>>(inner: M, db_path: P) -> Self {
+        Self {
+            inner,
+            db_path: db_path.as_ref().to_path_buf(),
+            handle: None,
+            chain: None,
+            api_config: ApiConfig::default(),
+            prune_limits: None,
+            fallback: FallbackConfig::default(),
+            stale_tip: StaleTipPolicy::default(),
+            pending: PendingMode::default(),
+            call_limits: CallLimits::default(),
+            log_limits: LogLimits::default(),
+            trace_limits: TraceLimits::default(),
+            method_timeouts: MethodTimeouts::default(),
+            max_heavy_requests: None,
+            proof_chunk_size: None,
+            max_proof_keys: None,
+            spec_override: SpecOverride::default(),
+            precompile_overrides: PrecompileOverrides::default(),
+            native_multicall: false,
+            #[cfg(feature = "debug")]
+            native_tracers: NativeTracers::default(),
+            fee_estimator: FeeEstimator::default(),
+            gas_price_mode: GasPriceMode::default(),
+            manage_nonces: false,
+            accounts: Accounts::default(),
+            namespaces: Namespaces::default(),
+            rate_limits: RateLimits::default(),
+            server_auth: ServerAuth::default(),
+        }
+    }
+
+    /// Runtime the background tasks are spawned on, the current one by default.
+    pub fn handle(mut self, handle: Handle) -> Self {
+        self.handle = Some(handle);
+        self
+    }
+
+    /// Chain the database belongs to, checked against its genesis block.
+    ///
+    /// Known chains are detected from the genesis block when unset, unknown ones default to
+    /// mainnet.
+    pub fn chain(mut self, chain: Arc<ChainSpec>) -> Self {
+        self.chain = Some(chain);
+        self
+    }
+
+    /// Sizes of the block, receipt and environment caches shared by the handlers.
+    pub fn state_cache(mut self, config: EthStateCacheConfig) -> Self {
+        self.api_config.state_cache = config;
+        self
+    }
+
+    /// Block range and percentile used to suggest gas prices.
+    pub fn gas_oracle(mut self, config: GasPriceOracleConfig) -> Self {
+        self.api_config.gas_oracle = config;
+        self
+    }
+
+    /// Number of `trace` and `debug` requests executed concurrently.
+    pub fn max_tracing_requests(mut self, max: u32) -> Self {
+        self.api_config.max_tracing_requests = max;
+        self
+    }
+
+    /// Maximum number of logs returned by a single `get_logs`.
+    pub fn max_logs_per_response(mut self, max: usize) -> Self {
+        self.api_config.max_logs_per_response = max;
+        self
+    }
+
+    /// Overrides the pruned segments otherwise detected when the database is opened.
+    pub fn prune_limits(mut self, limits: PruneLimits) -> Self {
+        self.prune_limits = Some(limits);
+        self
+    }
+
+    /// When to delegate requests to the inner provider.
+    pub fn fallback(mut self, config: FallbackConfig) -> Self {
+        self.fallback = config;
+        self
+    }
+
+    /// Limits past which requests for `latest` state are considered stale.
+    pub fn stale_tip(mut self, policy: StaleTipPolicy) -> Self {
+        self.stale_tip = policy;
+        self
+    }
+
+    /// How requests for the `pending` block are served.
+    pub fn pending(mut self, mode: PendingMode) -> Self {
+        self.pending = mode;
+        self
+    }
+
+    /// Gas cap, timeout and result size limit of calls.
+    pub fn call_limits(mut self, limits: CallLimits) -> Self {
+        self.call_limits = limits;
+        self
+    }
+
+    /// Block range and result limits of `get_logs`.
+    pub fn log_limits(mut self, limits: LogLimits) -> Self {
+        self.log_limits = limits;
+        self
+    }
+
+    /// Depth, capture and size limits of traces.
+    pub fn trace_limits(mut self, limits: TraceLimits) -> Self {
+        self.trace_limits = limits;
+        self
+    }
+
+    /// Time budgets of the methods served from the database.
+    pub fn method_timeouts(mut self, timeouts: MethodTimeouts) -> Self {
+        self.method_timeouts = timeouts;
+        self
+    }
+
+    /// Most traces, log scans and proofs served from the database at once, unbounded by default.
+    pub fn max_heavy_requests(mut self, max: usize) -> Self {
+        self.max_heavy_requests = Some(max);
+        self
+    }
+
+    /// Most storage keys proven per trie walk, `get_proof` requests with more being proven in
+    /// chunks. Unchunked by default.
+    pub fn proof_chunk_size(mut self, keys: usize) -> Self {
+        self.proof_chunk_size = Some(keys.max(1));
+        self
+    }
+
+    /// Most storage keys a single `get_proof` request may ask for, unbounded by default.
+    pub fn max_proof_keys(mut self, keys: usize) -> Self {
+        self.max_proof_keys = Some(keys);
+        self
+    }
+
+    /// Chain spec or hardfork the local executor and simulator run under instead of the chain's.
+    pub fn spec_override(mut self, spec: SpecOverride) -> Self {
+        self.spec_override = spec;
+        self
+    }
+
+    /// Precompiles added or replaced in the local simulator.
+    pub fn precompile_overrides(mut self, precompiles: PrecompileOverrides) -> Self {
+        self.precompile_overrides = precompiles;
+        self
+    }
+
+    /// Serve calls to Multicall3's `aggregate3` by running their calls directly.
+    pub fn native_multicall(mut self, enabled: bool) -> Self {
+        self.native_multicall = enabled;
+        self
+    }
+
+    /// Native tracers `debug_trace*` requests can select by name.
+    #[cfg(feature = "debug")]
+    pub fn native_tracers(mut self, tracers: NativeTracers) -> Self {
+        self.native_tracers = tracers;
+        self
+    }
+
+    /// How EIP-1559 fees are estimated from recent blocks.
+    pub fn fee_estimator(mut self, estimator: FeeEstimator) -> Self {
+        self.fee_estimator = estimator;
+        self
+    }
+
+    /// Where gas prices and priority fees are suggested from.
+    pub fn gas_price_mode(mut self, mode: GasPriceMode) -> Self {
+        self.gas_price_mode = mode;
+        self
+    }
+
+    /// Fills missing nonces from the chain and the local pool, disabled by default.
+    pub fn nonce_manager(mut self, enabled: bool) -> Self {
+        self.manage_nonces = enabled;
+        self
+    }
+
+    /// Accounts reported by `get_accounts` and the default sender, the inner provider's when
+    /// unset.
+    pub fn accounts(mut self, accounts: Accounts) -> Self {
+        self.accounts = accounts;
+        self
+    }
+
+    /// Global, per connection and per method rate limits of the served endpoints.
+    pub fn rate_limits(mut self, limits: RateLimits) -> Self {
+        self.rate_limits = limits;
+        self
+    }
+
+    /// Authentication required by the HTTP and WebSocket server.
+    pub fn server_auth(mut self, auth: ServerAuth) -> Self {
+        self.server_auth = auth;
+        self
+    }
+
+    /// Serves the `trace` namespace from the database, enabled by default with the `trace` feature.
+    pub fn trace(mut self, enabled: bool) -> Self {
+        self.namespaces.trace = enabled;
+        self
+    }
+
+    /// Serves the `debug` namespace from the database, enabled by default with the `debug` feature.
+    pub fn debug(mut self, enabled: bool) -> Self {
+        self.namespaces.debug = enabled;
+        self
+    }
+
+    /// Opens the database and builds the middleware.
+    ///
+    /// Fails with [RethMiddlewareError::ChainMismatch] if the chain set does not match the
+    /// database, and with [DatadirError::VersionMismatch] if its version file names a schema the
+    /// pinned reth revision cannot read. Panics if no handle was set and it is called outside of a
+    /// tokio runtime.
+    pub fn build(self) -> Result<RethMiddleware<M>, RethMiddlewareError<M>> {
+        // databases without a version file, e.g. from reth's test utilities, are opened as is
+        match check_db_version(&self.db_path) {
+            Ok(()) | Err(DatadirError::MissingVersionFile(_)) => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        let handle = self.handle.unwrap_or_else(Handle::current);
+        let mut components = match &self.chain {
+            Some(chain) => {
+                RethMiddleware::<M>::try_new_with_chain(&self.db_path, chain.clone(), handle)?
+            }
+            None => RethMiddleware::<M>::try_new(&self.db_path, handle)?,
+        };
+
+        if let Some(chain) = &self.chain {
+            let expected = chain.genesis_hash();
+            match components.provider.block_hash(0)? {
+                Some(found) if found != expected => {
+                    return Err(RethMiddlewareError::ChainMismatch { expected, found })
+                }
+                _ => {}
+            }
+        }
+        if let Some(prune_limits) = self.prune_limits {
+            components.prune_limits = prune_limits;
+        }
+
+        let mut middleware =
+            RethMiddleware::from_node_with_config(self.inner, components, &self.api_config);
+        middleware.fallback = self.fallback;
+        middleware.stale_tip = self.stale_tip;
+        middleware.pending = self.pending;
+        middleware.call_limits = self.call_limits;
+        middleware.log_limits = self.log_limits;
+        middleware.trace_limits = self.trace_limits;
+        middleware.method_timeouts = self.method_timeouts;
+        middleware.heavy_requests = ConcurrencyLimit::new(self.max_heavy_requests);
+        middleware.proof_chunk_size = self.proof_chunk_size;
+        middleware.max_proof_keys = self.max_proof_keys;
+        middleware.spec_override = self.spec_override;
+        middleware.precompile_overrides = self.precompile_overrides;
+        middleware.native_multicall = self.native_multicall;
+        #[cfg(feature = "debug")]
+        middleware.native_tracers = self.native_tracers;
+        middleware.fee_estimator = self.fee_estimator;
+        middleware.gas_price_mode = self.gas_price_mode;
+        middleware.nonces.enabled = self.manage_nonces;
+        middleware.accounts = self.accounts;
+        middleware.namespaces = self.namespaces;
+        middleware.rate_limiter = RateLimiter::new(self.rate_limits);
+        middleware.server_auth = self.server_auth;
+        Ok(middleware)
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Starts configuring a middleware over the database at `db_path`.
+    pub fn builder<P: AsRef<Path>>(inner: M, db_path: P) -> RethMiddlewareBuilder<M> {
+        RethMiddlewareBuilder::new(inner, db_path)
+    }
+
+    /// Returns the namespaces served from the database.
+    pub fn namespaces(&self) -> Namespaces {
+        self.namespaces
+    }
+
+    /// Fails if the method belongs to a namespace that is disabled.
+    #[cfg(any(feature = "trace", feature = "debug"))]
+    pub(crate) fn ensure_namespace(
+        &self,
+        method: &'static str,
+    ) -> Result<(), RethMiddlewareError<M>> {
+        let enabled = if method.starts_with("trace_") {
+            self.namespaces.trace
+        } else if method.starts_with("debug_") {
+            self.namespaces.debug
+        } else {
+            true
+        };
+
+        if enabled {
+            Ok(())
+        } else {
+            Err(RethMiddlewareError::UnsupportedMethod(method))
+        }
+    }
+}
@@ -0,0 +1,539 @@
+//! An opt-in cache of the blocks, receipts and logs served from the database, kept already
+//! converted to ethers types and invalidated when their blocks are reorged out.
+use crate::{instrument::db_read, type_conversions::ToEthers, RethMiddleware, RethMiddlewareError};
+use ethers::{
+    providers::Middleware,
+    types::{
+        Block, BlockId, BlockNumber, Filter, FilterBlockOption, Log, TransactionReceipt, H256,
+    },
+    utils::keccak256,
+};
+use schnellru::{ByLength, LruMap};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_provider::{BlockNumReader, CanonStateNotification};
+
+// Std
+use std::{
+    collections::{BTreeMap, HashSet},
+    ops::RangeInclusive,
+    sync::{Arc, Mutex, MutexGuard},
+};
+use tokio::sync::broadcast::error::RecvError;
+
+/// How many entries each cache holds before evicting the least recently used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheConfig {
+    /// Blocks without their transactions, by hash and number.
+    pub max_blocks: u32,
+    /// Transaction receipts, by transaction hash.
+    pub max_receipts: u32,
+    /// Results of log queries over settled block ranges, by filter.
+    pub max_log_queries: u32,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self { max_blocks: 1024, max_receipts: 8192, max_log_queries: 256 }
+    }
+}
+
+/// Lookups of a cache so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Entries dropped because their block was reorged out.
+    pub invalidated: u64,
+}
+
+impl CacheStats {
+    /// Returns the share of lookups that hit, 0 before any lookup.
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+}
+
+/// Lookups of the caches of [RethMiddleware::with_cache] so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseCacheStats {
+    pub blocks: CacheStats,
+    pub receipts: CacheStats,
+    pub logs: CacheStats,
+}
+
+struct Caches {
+    blocks: LruMap<H256, Arc<Block<H256>>, ByLength>,
+    max_blocks: u32,
+    /// The hashes of the cached blocks by number, to serve lookups by number.
+    block_hashes: BTreeMap<u64, H256>,
+    receipts: LruMap<H256, Arc<TransactionReceipt>, ByLength>,
+    /// Log query results by the hash of their filter.
+    logs: LruMap<H256, (RangeInclusive<u64>, Arc<Vec<Log>>), ByLength>,
+    stats: ResponseCacheStats,
+    /// Bumped by every invalidation, so entries read from the database before it are not
+    /// inserted after it.
+    generation: u64,
+}
+
+impl Caches {
+    /// Drops the entries of blocks from `fork` on, and of the `reverted` blocks.
+    fn invalidate(&mut self, fork: u64, reverted: &HashSet<H256>) {
+        self.generation += 1;
+        let mut hashes: HashSet<H256> = self.block_hashes.split_off(&fork).into_values().collect();
+        hashes.extend(reverted);
+        for hash in &hashes {
+            if self.blocks.remove(hash).is_some() {
+                self.stats.blocks.invalidated += 1;
+            }
+        }
+        if !reverted.is_empty() {
+            self.block_hashes.retain(|_, hash| !reverted.contains(hash));
+        }
+
+        let receipts: Vec<H256> = self
+            .receipts
+            .iter()
+            .filter(|(_, receipt)| {
+                receipt.block_number.map_or(true, |number| number.as_u64() >= fork) ||
+                    receipt.block_hash.map_or(false, |hash| reverted.contains(&hash))
+            })
+            .map(|(hash, _)| *hash)
+            .collect();
+        for hash in receipts {
+            self.receipts.remove(&hash);
+            self.stats.receipts.invalidated += 1;
+        }
+
+        let queries: Vec<H256> = self
+            .logs
+            .iter()
+            .filter(|(_, (range, _))| *range.end() >= fork)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in queries {
+            self.logs.remove(&key);
+            self.stats.logs.invalidated += 1;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.generation += 1;
+        self.blocks.clear();
+        self.block_hashes.clear();
+        self.receipts.clear();
+        self.logs.clear();
+    }
+}
+
+/// The caches of a middleware, shared by its clones.
+#[derive(Clone)]
+pub(crate) struct ResponseCache(Arc<Mutex<Caches>>);
+
+impl std::fmt::Debug for ResponseCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResponseCache").finish_non_exhaustive()
+    }
+}
+
+fn record_lookup(cache: &'static str, stats: &mut CacheStats, hit: bool) {
+    #[cfg(feature = "metrics")]
+    crate::metrics::record_cache_lookup(cache, hit);
+    #[cfg(not(feature = "metrics"))]
+    let _ = cache;
+
+    if hit {
+        stats.hits += 1;
+    } else {
+        stats.misses += 1;
+    }
+}
+
+impl ResponseCache {
+    fn new(config: CacheConfig) -> Self {
+        Self(Arc::new(Mutex::new(Caches {
+            blocks: LruMap::new(ByLength::new(config.max_blocks)),
+            max_blocks: config.max_blocks,
+            block_hashes: BTreeMap::new(),
+            receipts: LruMap::new(ByLength::new(config.max_receipts)),
+            logs: LruMap::new(ByLength::new(config.max_log_queries)),
+            stats: ResponseCacheStats::default(),
+            generation: 0,
+        })))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Caches> {
+        self.0.lock().unwrap()
+    }
+
+    /// Returns the generation entries read from the database from now on are inserted under.
+    fn generation(&self) -> u64 {
+        self.lock().generation
+    }
+
+    /// Applies a notification, or clears the caches if notifications were missed, as one of them
+    /// may have been a reorg. Returns false once the notifications closed.
+    fn on_notification(&self, notification: Result<CanonStateNotification, RecvError>) -> bool {
+        match notification {
+            Ok(notification) => self.process(&notification),
+            Err(RecvError::Lagged(_)) => self.lock().clear(),
+            Err(RecvError::Closed) => return false,
+        }
+        true
+    }
+
+    /// Drops the entries the notification reverts: its reverted blocks, and the blocks its
+    /// committed segment overwrites.
+    fn process(&self, notification: &CanonStateNotification) {
+        let reverted: HashSet<H256> = notification
+            .reverted()
+            .map(|chain| chain.blocks().values().map(|block| block.hash().into_ethers()).collect())
+            .unwrap_or_default();
+        let fork = notification
+            .committed()
+            .and_then(|chain| chain.blocks().keys().next().copied())
+            .unwrap_or(u64::MAX);
+        let mut caches = self.lock();
+        caches.invalidate(fork, &reverted);
+        #[cfg(feature = "metrics")]
+        {
+            crate::metrics::record_cache_entries("blocks", caches.blocks.len());
+            crate::metrics::record_cache_entries("receipts", caches.receipts.len());
+            crate::metrics::record_cache_entries("logs", caches.logs.len());
+        }
+    }
+
+    /// Drops the entries of the blocks a reorg replaced, when the follower finds the head at
+    /// `replaced` replaced without a notification reporting the fork: from the lowest cached
+    /// block, receipt or log whose hash `canonical` no longer returns at its height, and at least
+    /// from the replaced head on.
+    pub(crate) fn on_replaced_head(&self, replaced: u64, canonical: impl Fn(u64) -> Option<H256>) {
+        let cached: BTreeMap<u64, HashSet<H256>> = {
+            let caches = self.lock();
+            let blocks = caches.block_hashes.iter().map(|(number, hash)| (*number, *hash));
+            let receipts = caches.receipts.iter().filter_map(|(_, receipt)| {
+                Some((receipt.block_number?.as_u64(), receipt.block_hash?))
+            });
+            let logs = caches.logs.iter().flat_map(|(_, (_, logs))| {
+                logs.iter().filter_map(|log| Some((log.block_number?.as_u64(), log.block_hash?)))
+            });
+            let mut cached = BTreeMap::<u64, HashSet<H256>>::new();
+            for (number, hash) in blocks.chain(receipts).chain(logs) {
+                cached.entry(number).or_default().insert(hash);
+            }
+            cached
+        };
+        // read the database without holding the caches
+        let stale = cached
+            .into_iter()
+            .find(|(number, hashes)| {
+                let canonical = canonical(*number);
+                hashes.iter().any(|hash| Some(*hash) != canonical)
+            })
+            .map(|(number, _)| number);
+        let fork = stale.map_or(replaced, |stale| stale.min(replaced));
+        self.lock().invalidate(fork, &HashSet::new());
+    }
+
+    fn block(&self, id: BlockId) -> Option<Block<H256>> {
+        let hash = match id {
+            BlockId::Hash(hash) => Some(hash),
+            BlockId::Number(BlockNumber::Number(number)) => {
+                self.lock().block_hashes.get(&number.as_u64()).copied()
+            }
+            // tags move with the chain
+            BlockId::Number(_) => return None,
+        };
+        let mut caches = self.lock();
+        let block = hash.and_then(|hash| caches.blocks.get(&hash).map(|block| (**block).clone()));
+        record_lookup("blocks", &mut caches.stats.blocks, block.is_some());
+        block
+    }
+
+    /// Inserts a block read from the database under `generation`, unless the caches were
+    /// invalidated since.
+    fn insert_block(&self, block: &Block<H256>, generation: u64) {
+        let (Some(hash), Some(number)) = (block.hash, block.number) else { return };
+        let mut caches = self.lock();
+        if caches.generation != generation {
+            return
+        }
+        // evicts the least recently used block itself, to forget its number too
+        if caches.blocks.peek(&hash).is_none() && caches.blocks.len() >= caches.max_blocks as usize
+        {
+            if let Some((evicted, block)) = caches.blocks.pop_oldest() {
+                let number = block.number.map(|number| number.as_u64());
+                if let Some(number) = number {
+                    if caches.block_hashes.get(&number) == Some(&evicted) {
+                        caches.block_hashes.remove(&number);
+                    }
+                }
+            }
+        }
+        caches.blocks.insert(hash, Arc::new(block.clone()));
+        caches.block_hashes.insert(number.as_u64(), hash);
+    }
+
+    fn receipt(&self, transaction_hash: H256) -> Option<TransactionReceipt> {
+        let mut caches = self.lock();
+        let receipt = caches.receipts.get(&transaction_hash).map(|receipt| (**receipt).clone());
+        record_lookup("receipts", &mut caches.stats.receipts, receipt.is_some());
+        receipt
+    }
+
+    /// Inserts a receipt read from the database under `generation`, unless the caches were
+    /// invalidated since.
+    fn insert_receipt(&self, receipt: &TransactionReceipt, generation: u64) {
+        if receipt.block_number.is_none() {
+            return
+        }
+        let mut caches = self.lock();
+        if caches.generation == generation {
+            caches.receipts.insert(receipt.transaction_hash, Arc::new(receipt.clone()));
+        }
+    }
+
+    fn logs(&self, key: H256) -> Option<Vec<Log>> {
+        let mut caches = self.lock();
+        let logs = caches.logs.get(&key).map(|(_, logs)| (**logs).clone());
+        record_lookup("logs", &mut caches.stats.logs, logs.is_some());
+        logs
+    }
+}
+
+/// Returns the block range of a filter if both its bounds are block numbers.
+fn numbered_range(filter: &Filter) -> Option<RangeInclusive<u64>> {
+    match filter.block_option {
+        FilterBlockOption::Range {
+            from_block: Some(BlockNumber::Number(from)),
+            to_block: Some(BlockNumber::Number(to)),
+        } => Some(from.as_u64()..=to.as_u64()),
+        _ => None,
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Caches the blocks, receipts and log query results served from the database, sparing the
+    /// database reads and the conversions of repeated requests, e.g. of an indexer revisiting
+    /// recent blocks. Entries of reorged blocks are dropped as the canonical state notifications
+    /// report them.
+    ///
+    /// Only log queries over numbered ranges the chain already reached are cached, as others
+    /// change with new blocks.
+    pub fn with_cache(mut self, config: CacheConfig) -> Self {
+        let cache = ResponseCache::new(config);
+        let mut notifications = self.subscribe_to_canonical_state();
+        let listener = cache.clone();
+        let task =
+            tokio::spawn(
+                async move { while listener.on_notification(notifications.recv().await) {} },
+            );
+        self.tasks.register(&task);
+        self.response_cache = Some(cache);
+        self
+    }
+
+    /// Returns the lookups of the caches so far, all zero without [Self::with_cache].
+    pub fn cache_stats(&self) -> ResponseCacheStats {
+        self.response_cache.as_ref().map(|cache| cache.lock().stats).unwrap_or_default()
+    }
+
+    /// Returns the generation of the caches, to be read before the database so a reorg in
+    /// between keeps the read out of the caches.
+    pub(crate) fn cache_generation(&self) -> u64 {
+        self.response_cache.as_ref().map_or(0, ResponseCache::generation)
+    }
+
+    pub(crate) fn cached_block(&self, id: BlockId) -> Option<Block<H256>> {
+        self.response_cache.as_ref()?.block(id)
+    }
+
+    pub(crate) fn cache_block(&self, block: &Option<Block<H256>>, generation: u64) {
+        if let (Some(cache), Some(block)) = (&self.response_cache, block) {
+            cache.insert_block(block, generation);
+        }
+    }
+
+    pub(crate) fn cached_receipt(&self, transaction_hash: H256) -> Option<TransactionReceipt> {
+        self.response_cache.as_ref()?.receipt(transaction_hash)
+    }
+
+    pub(crate) fn cache_receipt(&self, receipt: &Option<TransactionReceipt>, generation: u64) {
+        if let (Some(cache), Some(receipt)) = (&self.response_cache, receipt) {
+            cache.insert_receipt(receipt, generation);
+        }
+    }
+
+    /// Serves `get_logs` from the cache if the same query over a settled range was served before.
+    pub(crate) async fn cached_logs(
+        &self,
+        filter: &Filter,
+    ) -> Result<Vec<Log>, RethMiddlewareError<M>> {
+        let Some(cache) = &self.response_cache else { return self.local_logs(filter).await };
+        let Some(range) = numbered_range(filter) else { return self.local_logs(filter).await };
+        let key = H256(keccak256(format!("{filter:?}")));
+        let generation = cache.generation();
+        if let Some(logs) = cache.logs(key) {
+            return Ok(logs)
+        }
+
+        let logs = self.local_logs(filter).await?;
+        let best = db_read("best_block_number", || self.reth_provider.best_block_number())?;
+        let mut caches = cache.lock();
+        if *range.end() <= best && caches.generation == generation {
+            caches.logs.insert(key, (range, Arc::new(logs.clone())));
+        }
+        Ok(logs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::U64;
+
+    fn block(number: u64, hash: u8) -> Block<H256> {
+        Block {
+            hash: Some(H256::repeat_byte(hash)),
+            number: Some(U64::from(number)),
+            ..Default::default()
+        }
+    }
+
+    fn receipt(number: u64, hash: u8) -> TransactionReceipt {
+        TransactionReceipt {
+            transaction_hash: H256::from_low_u64_be(number),
+            block_hash: Some(H256::repeat_byte(hash)),
+            block_number: Some(U64::from(number)),
+            ..Default::default()
+        }
+    }
+
+    fn number(number: u64) -> BlockId {
+        BlockId::Number(BlockNumber::Number(number.into()))
+    }
+
+    #[test]
+    fn blocks_are_found_by_hash_and_number() {
+        let cache = ResponseCache::new(CacheConfig::default());
+        cache.insert_block(&block(1, 0xa1), 0);
+
+        assert_eq!(cache.block(number(1)).unwrap().hash, Some(H256::repeat_byte(0xa1)));
+        assert!(cache.block(BlockId::Hash(H256::repeat_byte(0xa1))).is_some());
+        assert!(cache.block(number(2)).is_none());
+        // tags are never served from the cache
+        assert!(cache.block(BlockId::Number(BlockNumber::Latest)).is_none());
+        assert_eq!(cache.lock().stats.blocks, CacheStats { hits: 2, misses: 1, invalidated: 0 });
+    }
+
+    #[test]
+    fn evicted_blocks_leave_the_number_index() {
+        let cache = ResponseCache::new(CacheConfig { max_blocks: 2, ..Default::default() });
+        for number in 1..=3 {
+            cache.insert_block(&block(number, number as u8), 0);
+        }
+
+        let caches = cache.lock();
+        assert_eq!(caches.blocks.len(), 2);
+        assert_eq!(caches.block_hashes.keys().copied().collect::<Vec<_>>(), vec![2, 3]);
+    }
+
+    #[test]
+    fn invalidation_drops_the_reorged_entries() {
+        let cache = ResponseCache::new(CacheConfig::default());
+        for number in 0..=3 {
+            cache.insert_block(&block(number, number as u8), 0);
+            cache.insert_receipt(&receipt(number, number as u8), 0);
+        }
+        let settled = H256::repeat_byte(0x11);
+        let reorged = H256::repeat_byte(0x22);
+        cache.lock().logs.insert(settled, (1..=1, Arc::default()));
+        cache.lock().logs.insert(reorged, (1..=2, Arc::default()));
+
+        // block 1 was replaced by a sibling, blocks 2 and 3 by a new fork
+        let reverted = HashSet::from([H256::repeat_byte(1)]);
+        cache.lock().invalidate(2, &reverted);
+
+        let mut caches = cache.lock();
+        assert_eq!(caches.blocks.len(), 1);
+        assert_eq!(caches.block_hashes.keys().copied().collect::<Vec<_>>(), vec![0]);
+        assert_eq!(caches.receipts.len(), 1);
+        assert!(caches.logs.get(&settled).is_some());
+        assert!(caches.logs.get(&reorged).is_none());
+        assert_eq!(caches.stats.blocks.invalidated, 3);
+        assert_eq!(caches.stats.receipts.invalidated, 3);
+        assert_eq!(caches.stats.logs.invalidated, 1);
+    }
+
+    #[test]
+    fn reads_from_before_an_invalidation_are_not_cached() {
+        let cache = ResponseCache::new(CacheConfig::default());
+        let generation = cache.generation();
+        cache.lock().invalidate(1, &HashSet::new());
+
+        cache.insert_block(&block(1, 1), generation);
+        cache.insert_receipt(&receipt(1, 1), generation);
+        assert!(cache.block(number(1)).is_none());
+        assert!(cache.receipt(H256::from_low_u64_be(1)).is_none());
+
+        cache.insert_block(&block(1, 1), cache.generation());
+        assert!(cache.block(number(1)).is_some());
+    }
+
+    #[test]
+    fn a_replaced_head_drops_the_entries_from_the_fork() {
+        let cache = ResponseCache::new(CacheConfig::default());
+        for number in 0..=4 {
+            cache.insert_block(&block(number, number as u8), 0);
+        }
+        cache.insert_receipt(&receipt(1, 1), 0);
+        let settled = H256::repeat_byte(0x11);
+        let reorged = H256::repeat_byte(0x22);
+        cache.lock().logs.insert(settled, (0..=1, Arc::default()));
+        cache.lock().logs.insert(reorged, (0..=2, Arc::default()));
+
+        // the head 4 was replaced, along with block 2 below it
+        let canonical = |number: u64| {
+            Some(H256::repeat_byte(if number >= 2 { 0xf0 | number as u8 } else { number as u8 }))
+        };
+        cache.on_replaced_head(4, canonical);
+
+        let mut caches = cache.lock();
+        assert_eq!(caches.block_hashes.keys().copied().collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(caches.receipts.len(), 1);
+        assert!(caches.logs.get(&settled).is_some());
+        assert!(caches.logs.get(&reorged).is_none());
+        drop(caches);
+
+        // with nothing stale cached, the entries of the replaced head itself are dropped
+        cache.on_replaced_head(1, |number| Some(H256::repeat_byte(number as u8)));
+        assert_eq!(cache.lock().block_hashes.keys().copied().collect::<Vec<_>>(), vec![0]);
+    }
+
+    #[test]
+    fn missed_notifications_clear_the_caches() {
+        let cache = ResponseCache::new(CacheConfig::default());
+        cache.insert_block(&block(1, 1), 0);
+        cache.insert_receipt(&receipt(1, 1), 0);
+
+        assert!(cache.on_notification(Err(RecvError::Lagged(3))));
+        let caches = cache.lock();
+        assert_eq!(caches.blocks.len(), 0);
+        assert!(caches.block_hashes.is_empty());
+        assert_eq!(caches.receipts.len(), 0);
+        assert_eq!(caches.generation, 1);
+        drop(caches);
+
+        assert!(!cache.on_notification(Err(RecvError::Closed)));
+    }
+}
@@ -0,0 +1,461 @@
+#[cfg(feature = "trace")]
+use crate::{RethMiddleware, RethMiddlewareError};
+use ethers::{
+    abi::{self, Abi, Function, ParamType, Selector, Token},
+    types::{Action, Address, Bytes, CallFrame as GethCallFrame, CallType, Res, Trace, U256},
+};
+#[cfg(feature = "trace")]
+use ethers::{providers::Middleware, types::TxHash};
+use serde::{Serialize, Serializer};
+
+// Std
+use std::collections::HashMap;
+
+/// Selector of the `Error(string)` payload of reverts with a reason.
+const ERROR_SELECTOR: Selector = [0x08, 0xc3, 0x79, 0xa0];
+
+/// The ABIs the frames of a call tree are decoded with.
+#[derive(Debug, Clone, Default)]
+pub struct AbiRegistry {
+    contracts: HashMap<Address, Abi>,
+    functions: HashMap<Selector, Function>,
+}
+
+impl AbiRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the ABI of the contract at `address`. For proxies, register the implementation:
+    /// delegate calls are decoded with the ABI of the code they run.
+    pub fn with_contract(mut self, address: Address, abi: Abi) -> Self {
+        self.contracts.insert(address, abi);
+        self
+    }
+
+    /// Registers functions matched by their selector on any contract, e.g. the ERC-20 ones. The
+    /// first function registered for a selector wins.
+    pub fn with_functions(mut self, abi: &Abi) -> Self {
+        for function in abi.functions() {
+            self.functions.entry(function.short_signature()).or_insert_with(|| function.clone());
+        }
+        self
+    }
+
+    /// Returns the function `selector` calls on the contract at `address`, falling back to the
+    /// functions registered on any contract.
+    pub fn function(&self, address: Option<Address>, selector: Selector) -> Option<&Function> {
+        address
+            .and_then(|address| self.contracts.get(&address))
+            .and_then(|abi| abi.functions().find(|function| function.short_signature() == selector))
+            .or_else(|| self.functions.get(&selector))
+    }
+}
+
+/// What a frame of the call tree does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FrameKind {
+    Call,
+    CallCode,
+    DelegateCall,
+    StaticCall,
+    Create,
+    /// Only reported by geth's `callTracer`, parity traces report every creation as
+    /// [Self::Create].
+    Create2,
+    SelfDestruct,
+}
+
+impl FrameKind {
+    fn from_geth(ty: &str) -> Self {
+        match ty.to_ascii_uppercase().as_str() {
+            "CALLCODE" => FrameKind::CallCode,
+            "DELEGATECALL" => FrameKind::DelegateCall,
+            "STATICCALL" => FrameKind::StaticCall,
+            "CREATE" => FrameKind::Create,
+            "CREATE2" => FrameKind::Create2,
+            "SELFDESTRUCT" => FrameKind::SelfDestruct,
+            _ => FrameKind::Call,
+        }
+    }
+
+    fn is_create(self) -> bool {
+        matches!(self, FrameKind::Create | FrameKind::Create2)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FrameStatus {
+    Success,
+    /// Stopped by `REVERT`, the frame's changes are undone but its remaining gas is returned.
+    Reverted,
+    /// Stopped by an exceptional halt, e.g. out of gas or an invalid opcode.
+    Failed,
+}
+
+/// A frame's input and output, decoded with the ABI of the function it calls.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DecodedCall {
+    /// Signature of the function, e.g. `transfer(address,uint256)`.
+    pub signature: String,
+    pub inputs: Vec<Token>,
+    /// The return values, if the frame succeeded.
+    pub outputs: Option<Vec<Token>>,
+}
+
+/// A frame of the call tree of a transaction, with the frames it opened as children.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallFrame {
+    /// Number of frames above this one, 0 for the top-level call.
+    pub depth: usize,
+    /// Position of the frame in the tree, as in parity traces.
+    pub trace_address: Vec<usize>,
+    pub kind: FrameKind,
+    pub from: Address,
+    /// The callee, the created contract, or the beneficiary of a self-destruct. Unknown for
+    /// creations that failed.
+    pub to: Option<Address>,
+    pub value: U256,
+    /// First four bytes of the input of a call.
+    #[serde(serialize_with = "serialize_selector")]
+    pub selector: Option<Selector>,
+    pub input: Bytes,
+    pub output: Bytes,
+    pub gas: U256,
+    pub gas_used: U256,
+    pub status: FrameStatus,
+    pub error: Option<String>,
+    /// Reason of a revert with an `Error(string)` payload.
+    pub revert_reason: Option<String>,
+    pub decoded: Option<DecodedCall>,
+    pub children: Vec<CallFrame>,
+}
+
+pub(crate) fn serialize_selector<S: Serializer>(
+    selector: &Option<Selector>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    selector.map(|selector| Bytes::from(selector.to_vec())).serialize(serializer)
+}
+
+/// A frame before it is decoded and placed in the tree.
+struct RawFrame {
+    trace_address: Vec<usize>,
+    kind: FrameKind,
+    from: Address,
+    to: Option<Address>,
+    value: U256,
+    input: Bytes,
+    output: Bytes,
+    gas: U256,
+    gas_used: U256,
+    error: Option<String>,
+}
+
+impl CallFrame {
+    fn new(frame: RawFrame, registry: Option<&AbiRegistry>) -> Self {
+        let status = match frame.error.as_deref() {
+            None => FrameStatus::Success,
+            Some("Reverted" | "execution reverted") => FrameStatus::Reverted,
+            Some(_) => FrameStatus::Failed,
+        };
+        let selector = (!frame.kind.is_create() && frame.input.len() >= 4)
+            .then(|| frame.input[..4].try_into().expect("4 bytes"));
+        let revert_reason =
+            (status == FrameStatus::Reverted).then(|| revert_reason(&frame.output)).flatten();
+        let decoded = selector
+            .zip(registry)
+            .and_then(|(selector, registry)| registry.function(frame.to, selector))
+            .and_then(|function| {
+                let inputs = function.decode_input(&frame.input[4..]).ok()?;
+                let outputs = (status == FrameStatus::Success)
+                    .then(|| function.decode_output(&frame.output).ok())
+                    .flatten();
+                Some(DecodedCall { signature: function.signature(), inputs, outputs })
+            });
+
+        Self {
+            depth: frame.trace_address.len(),
+            trace_address: frame.trace_address,
+            kind: frame.kind,
+            from: frame.from,
+            to: frame.to,
+            value: frame.value,
+            selector,
+            input: frame.input,
+            output: frame.output,
+            gas: frame.gas,
+            gas_used: frame.gas_used,
+            status,
+            error: frame.error,
+            revert_reason,
+            decoded,
+            children: vec![],
+        }
+    }
+
+    /// Returns the frames of the tree, this one first, in execution order.
+    pub fn frames(&self) -> Vec<&CallFrame> {
+        let mut frames = vec![self];
+        for child in &self.children {
+            frames.extend(child.frames());
+        }
+        frames
+    }
+}
+
+/// Decodes the reason of a revert with an `Error(string)` payload.
+fn revert_reason(output: &[u8]) -> Option<String> {
+    let payload = output.strip_prefix(&ERROR_SELECTOR)?;
+    abi::decode(&[ParamType::String], payload).ok()?.pop()?.into_string()
+}
+
+/// Returns the frame of a parity trace, none for block rewards.
+fn parity_frame(trace: &Trace) -> Option<RawFrame> {
+    let (kind, from, mut to, value, gas, input) = match &trace.action {
+        Action::Call(call) => {
+            let kind = match call.call_type {
+                CallType::CallCode => FrameKind::CallCode,
+                CallType::DelegateCall => FrameKind::DelegateCall,
+                CallType::StaticCall => FrameKind::StaticCall,
+                CallType::None | CallType::Call => FrameKind::Call,
+            };
+            (kind, call.from, Some(call.to), call.value, call.gas, call.input.clone())
+        }
+        Action::Create(create) => {
+            (FrameKind::Create, create.from, None, create.value, create.gas, create.init.clone())
+        }
+        Action::Suicide(suicide) => (
+            FrameKind::SelfDestruct,
+            suicide.address,
+            Some(suicide.refund_address),
+            suicide.balance,
+            U256::zero(),
+            Bytes::default(),
+        ),
+        Action::Reward(_) => return None,
+    };
+    let (gas_used, output) = match &trace.result {
+        Some(Res::Call(result)) => (result.gas_used, result.output.clone()),
+        Some(Res::Create(result)) => {
+            to = Some(result.address);
+            (result.gas_used, result.code.clone())
+        }
+        Some(Res::None) | None => (U256::zero(), Bytes::default()),
+    };
+    Some(RawFrame {
+        trace_address: trace.trace_address.clone(),
+        kind,
+        from,
+        to,
+        value,
+        input,
+        output,
+        gas,
+        gas_used,
+        error: trace.error.clone(),
+    })
+}
+
+/// Builds the call tree of a transaction from its parity traces, in the order `trace_transaction`
+/// returns them. Returns none without a top-level frame.
+pub fn call_tree_from_parity(
+    traces: &[Trace],
+    registry: Option<&AbiRegistry>,
+) -> Option<CallFrame> {
+    let mut frames = traces.iter().filter_map(parity_frame);
+    let mut root = CallFrame::new(frames.next()?, registry);
+    if root.depth != 0 {
+        return None
+    }
+    for frame in frames {
+        let frame = CallFrame::new(frame, registry);
+        // traces come depth first, so the parent is the last child at every level
+        let mut parent = &mut root;
+        for _ in 1..frame.depth {
+            parent = parent.children.last_mut()?;
+        }
+        parent.children.push(frame);
+    }
+    Some(root)
+}
+
+/// Builds the call tree of a transaction from the output of geth's `callTracer`.
+pub fn call_tree_from_geth(frame: &GethCallFrame, registry: Option<&AbiRegistry>) -> CallFrame {
+    geth_frame(frame, vec![], registry)
+}
+
+fn geth_frame(
+    frame: &GethCallFrame,
+    trace_address: Vec<usize>,
+    registry: Option<&AbiRegistry>,
+) -> CallFrame {
+    let raw = RawFrame {
+        trace_address: trace_address.clone(),
+        kind: FrameKind::from_geth(&frame.typ),
+        from: frame.from,
+        to: frame.to.as_ref().and_then(|to| to.as_address().copied()),
+        value: frame.value.unwrap_or_default(),
+        input: frame.input.clone(),
+        output: frame.output.clone().unwrap_or_default(),
+        gas: frame.gas,
+        gas_used: frame.gas_used,
+        error: frame.error.clone(),
+    };
+    let mut call_frame = CallFrame::new(raw, registry);
+    call_frame.children = frame
+        .calls
+        .iter()
+        .flatten()
+        .enumerate()
+        .map(|(index, child)| {
+            let mut address = trace_address.clone();
+            address.push(index);
+            geth_frame(child, address, registry)
+        })
+        .collect();
+    call_frame
+}
+
+#[cfg(feature = "trace")]
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the call tree of a transaction, built from its parity traces and decoded with the
+    /// registry's ABIs. Parity traces drop the output of reverted frames, so their revert reasons
+    /// are only known from [call_tree_from_geth].
+    pub async fn get_call_tree(
+        &self,
+        tx_hash: TxHash,
+        registry: Option<&AbiRegistry>,
+    ) -> Result<CallFrame, RethMiddlewareError<M>> {
+        let traces = self.trace_transaction(tx_hash).await?;
+        call_tree_from_parity(&traces, registry).ok_or(RethMiddlewareError::MissingTrace)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        abi::parse_abi,
+        types::{ActionType, Call, CallResult, Reward, RewardType, H256},
+    };
+
+    fn registry() -> AbiRegistry {
+        let erc20 = parse_abi(&["function transfer(address,uint256) returns (bool)"]).unwrap();
+        AbiRegistry::new().with_functions(&erc20)
+    }
+
+    fn transfer_input(to: Address, amount: u64) -> Bytes {
+        let mut input = ethers::utils::id("transfer(address,uint256)").to_vec();
+        input.extend(abi::encode(&[Token::Address(to), Token::Uint(amount.into())]));
+        input.into()
+    }
+
+    fn call_trace(trace_address: Vec<usize>, input: Bytes, output: Bytes) -> Trace {
+        Trace {
+            action: Action::Call(Call {
+                from: Address::from_low_u64_be(1),
+                to: Address::from_low_u64_be(trace_address.len() as u64 + 2),
+                input,
+                call_type: CallType::Call,
+                ..Default::default()
+            }),
+            result: Some(Res::Call(CallResult { gas_used: 21_000.into(), output })),
+            subtraces: 0,
+            trace_address,
+            transaction_position: Some(0),
+            transaction_hash: Some(H256::zero()),
+            block_number: 1,
+            block_hash: H256::zero(),
+            action_type: ActionType::Call,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn parity_traces_are_nested_by_trace_address() {
+        let traces = vec![
+            call_trace(vec![], Bytes::default(), Bytes::default()),
+            call_trace(vec![0], Bytes::default(), Bytes::default()),
+            call_trace(vec![0, 0], Bytes::default(), Bytes::default()),
+            call_trace(vec![1], Bytes::default(), Bytes::default()),
+        ];
+        let root = call_tree_from_parity(&traces, None).unwrap();
+
+        assert_eq!(root.children.len(), 2);
+        assert_eq!(root.children[0].children[0].trace_address, vec![0, 0]);
+        assert_eq!(root.children[0].children[0].depth, 2);
+        let order = root.frames().into_iter().map(|frame| frame.trace_address.clone());
+        assert_eq!(order.collect::<Vec<_>>(), vec![vec![], vec![0], vec![0, 0], vec![1]]);
+    }
+
+    #[test]
+    fn parity_traces_need_a_top_level_frame() {
+        let mut reward = call_trace(vec![], Bytes::default(), Bytes::default());
+        reward.action = Action::Reward(Reward {
+            author: Address::from_low_u64_be(1),
+            value: U256::one(),
+            reward_type: RewardType::Block,
+        });
+        reward.action_type = ActionType::Reward;
+        let child = call_trace(vec![0], Bytes::default(), Bytes::default());
+
+        assert!(call_tree_from_parity(&[reward.clone()], None).is_none());
+        assert!(call_tree_from_parity(&[reward, child], None).is_none());
+    }
+
+    #[test]
+    fn frames_are_decoded_with_the_registry() {
+        let input = transfer_input(Address::from_low_u64_be(9), 100);
+        let output = abi::encode(&[Token::Bool(true)]).into();
+        let root =
+            call_tree_from_parity(&[call_trace(vec![], input, output)], Some(&registry())).unwrap();
+
+        assert_eq!(root.status, FrameStatus::Success);
+        assert_eq!(root.selector, Some(ethers::utils::id("transfer(address,uint256)")));
+        let decoded = root.decoded.unwrap();
+        assert_eq!(decoded.signature, "transfer(address,uint256)");
+        assert_eq!(
+            decoded.inputs,
+            vec![Token::Address(Address::from_low_u64_be(9)), Token::Uint(100.into())]
+        );
+        assert_eq!(decoded.outputs, Some(vec![Token::Bool(true)]));
+    }
+
+    #[test]
+    fn geth_frames_keep_revert_reasons() {
+        let mut output = ERROR_SELECTOR.to_vec();
+        output.extend(abi::encode(&[Token::String("insufficient balance".to_string())]));
+        let frame = GethCallFrame {
+            typ: "CALL".to_string(),
+            from: Address::from_low_u64_be(1),
+            to: Some(Address::from_low_u64_be(2).into()),
+            input: transfer_input(Address::from_low_u64_be(9), 100),
+            output: Some(output.into()),
+            error: Some("execution reverted".to_string()),
+            calls: Some(vec![GethCallFrame {
+                typ: "CREATE2".to_string(),
+                from: Address::from_low_u64_be(2),
+                input: vec![0x60, 0x80, 0x60, 0x40].into(),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        let root = call_tree_from_geth(&frame, Some(&registry()));
+
+        assert_eq!(root.status, FrameStatus::Reverted);
+        assert_eq!(root.revert_reason.as_deref(), Some("insufficient balance"));
+        assert_eq!(root.decoded.unwrap().outputs, None);
+        let create = &root.children[0];
+        assert_eq!(create.kind, FrameKind::Create2);
+        assert_eq!(create.trace_address, vec![0]);
+        // the init code of a creation has no selector
+        assert_eq!(create.selector, None);
+    }
+}
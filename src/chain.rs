@@ -0,0 +1,47 @@
+//! Detection of the chain a database belongs to, from the genesis hash it stores.
+//!
+//! Opening a database with the wrong chain spec does not fail by itself, it silently applies the
+//! wrong hardfork schedule to execution. Known chains are therefore picked from the genesis
+//! block, and an explicitly passed spec is checked against it.
+
+use crate::init::view;
+
+// Reth
+use reth_db::{
+    mdbx::{Env, WriteMap},
+    tables,
+    transaction::DbTx,
+    DatabaseError,
+};
+use reth_primitives::{ChainSpec, GOERLI, H256, MAINNET, SEPOLIA};
+
+// Std
+use std::sync::Arc;
+
+/// Returns the hash of the genesis block stored in the database, if any.
+pub fn genesis_hash(db: &Env<WriteMap>) -> Result<Option<H256>, DatabaseError> {
+    view(db, |tx| tx.get::<tables::CanonicalHeaders>(0))?
+}
+
+/// Returns the spec of the known chain starting with the given genesis block.
+///
+/// Covers mainnet, goerli and sepolia, plus the OP-stack chains of [crate::optimism] with the
+/// `optimism` feature. Holesky is not known to the pinned reth revision.
+pub fn chain_spec_by_genesis(genesis_hash: H256) -> Option<Arc<ChainSpec>> {
+    let known = [MAINNET.clone(), GOERLI.clone(), SEPOLIA.clone()];
+    if let Some(chain) = known.into_iter().find(|chain| chain.genesis_hash() == genesis_hash) {
+        return Some(chain)
+    }
+
+    #[cfg(feature = "optimism")]
+    if let Some(chain_id) = crate::optimism::chain_id_by_genesis(genesis_hash) {
+        return Some(crate::optimism::chain_spec(chain_id))
+    }
+
+    None
+}
+
+/// Returns the spec of the chain the database belongs to, if it is a known one.
+pub fn detect_chain(db: &Env<WriteMap>) -> Result<Option<Arc<ChainSpec>>, DatabaseError> {
+    Ok(genesis_hash(db)?.and_then(chain_spec_by_genesis))
+}
@@ -0,0 +1,374 @@
+//! Middleware configuration read from a TOML file and environment variables, behind the `config`
+//! feature, so deployments can be tuned without recompiling the embedding application.
+//!
+//! ```toml
+//! datadir = "/data/reth/db"
+//! fallback_url = "http://localhost:8545"
+//! fallback_policy = "on_unavailable"
+//! jwt_secret = "/data/reth/jwt.hex"
+//! max_tracing_requests = 32
+//! max_heavy_requests = 16
+//! proof_chunk_size = 100
+//! max_proof_keys = 1000
+//! accounts = ["0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266"]
+//!
+//! [state_cache]
+//! max_blocks = 1000
+//!
+//! [namespaces]
+//! debug = false
+//!
+//! [call_limits]
+//! gas_cap = 50000000
+//! timeout = 5
+//!
+//! [method_timeouts]
+//! default = 0.2
+//! methods = { trace_block = 30 }
+//!
+//! [log_limits]
+//! max_block_range = 10000
+//! chunk = true
+//!
+//! [trace_limits]
+//! max_depth = 64
+//! disable_memory = true
+//! max_filter_blocks = 5000
+//!
+//! [fee_estimator]
+//! blocks = 20
+//! reward_percentile = 10.0
+//!
+//! [rate_limits]
+//! global = { per_second = 500.0, burst = 1000 }
+//! per_connection = { per_second = 50.0, burst = 100 }
+//! method_weights = { trace_block = 50, debug_traceTransaction = 20 }
+//! method_quotas = { debug_traceTransaction = { per_second = 5.0, burst = 10 } }
+//! ```
+//!
+//! Every key can be overridden by an `ETHERS_RETH_` variable, e.g. `ETHERS_RETH_DATADIR` or
+//! `ETHERS_RETH_STATE_CACHE_MAX_BLOCKS`. `ETHERS_RETH_NAMESPACES` lists the served namespaces,
+//! e.g. `trace,debug`, and `ETHERS_RETH_ACCOUNTS` the accounts. Method weights and quotas of the
+//! rate limits can only be set in the file.
+
+use crate::{
+    accounts::{load_keystore, Accounts},
+    builder::{Namespaces, RethMiddlewareBuilder},
+    fallback::{FallbackConfig, FallbackPolicy},
+    fees::FeeEstimator,
+    limits::{CallLimits, LogLimits, MethodTimeouts, TraceLimits},
+    server::{
+        auth::ServerAuth,
+        rate_limit::{Quota, RateLimits},
+    },
+    RethMiddleware,
+};
+use ethers::{
+    providers::{Http, Middleware, Provider},
+    signers::Signer,
+    types::Address,
+};
+use serde::Deserialize;
+use thiserror::Error;
+
+// Reth
+use reth_rpc::{eth::cache::EthStateCacheConfig, JwtSecret};
+
+// Std
+use std::{
+    env,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+/// Prefix of the environment variables overriding the file.
+const ENV_PREFIX: &str = "ETHERS_RETH_";
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RethMiddlewareConfig {
+    /// Path of the node's MDBX database.
+    pub datadir: Option<PathBuf>,
+    /// URL of the provider requests fall back to.
+    pub fallback_url: Option<String>,
+    pub fallback_policy: FallbackPolicy,
+    pub state_cache: StateCacheConfig,
+    pub max_tracing_requests: Option<u32>,
+    /// Most traces, log scans and proofs served at once.
+    pub max_heavy_requests: Option<usize>,
+    /// Most storage keys proven per trie walk.
+    pub proof_chunk_size: Option<usize>,
+    /// Most storage keys a single proof request may ask for.
+    pub max_proof_keys: Option<usize>,
+    pub max_logs_per_response: Option<usize>,
+    pub namespaces: Namespaces,
+    pub call_limits: CallLimits,
+    pub log_limits: LogLimits,
+    pub trace_limits: TraceLimits,
+    pub method_timeouts: MethodTimeouts,
+    pub fee_estimator: FeeEstimator,
+    pub rate_limits: RateLimits,
+    /// File holding the hex encoded secret the served endpoints authenticate JWTs with.
+    pub jwt_secret: Option<PathBuf>,
+    /// Static token the served endpoints require, when no JWT secret is set.
+    pub auth_token: Option<String>,
+    /// Accounts reported by `get_accounts`, before those of the keystore.
+    pub accounts: Vec<Address>,
+    /// Sender of the transactions without one, the first account if unset.
+    pub default_sender: Option<Address>,
+    /// Directory of keystore files whose accounts are reported, decrypted with the password.
+    pub keystore: Option<PathBuf>,
+    pub keystore_password: Option<String>,
+}
+
+/// Sizes of the handlers' caches, reth's defaults when unset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StateCacheConfig {
+    pub max_blocks: Option<u32>,
+    pub max_receipts: Option<u32>,
+    pub max_envs: Option<u32>,
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Toml(#[from] toml::de::Error),
+
+    /// An environment variable holds a value of the wrong type.
+    #[error("Invalid value for {var}: {value}")]
+    InvalidEnv { var: String, value: String },
+
+    #[error("No datadir configured")]
+    MissingDatadir,
+
+    #[error("No fallback url configured")]
+    MissingFallbackUrl,
+
+    #[error("Invalid fallback url: {0}")]
+    InvalidUrl(String),
+
+    #[error("Invalid JWT secret: {0}")]
+    InvalidJwtSecret(String),
+
+    #[error("Invalid keystore: {0}")]
+    InvalidKeystore(String),
+}
+
+impl RethMiddlewareConfig {
+    /// Reads the configuration from a TOML file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Reads the configuration from the environment only.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::default().with_env()
+    }
+
+    /// Reads the file if given, then applies the environment on top of it.
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        match path {
+            Some(path) => Self::from_file(path)?.with_env(),
+            None => Self::from_env(),
+        }
+    }
+
+    /// Overrides the values set in the environment.
+    pub fn with_env(mut self) -> Result<Self, ConfigError> {
+        if let Some(datadir) = env_var("DATADIR") {
+            self.datadir = Some(datadir.into());
+        }
+        if let Some(url) = env_var("FALLBACK_URL") {
+            self.fallback_url = Some(url);
+        }
+        if let Some(path) = env_var("JWT_SECRET") {
+            self.jwt_secret = Some(path.into());
+        }
+        if let Some(token) = env_var("AUTH_TOKEN") {
+            self.auth_token = Some(token);
+        }
+        if let Some(path) = env_var("KEYSTORE") {
+            self.keystore = Some(path.into());
+        }
+        if let Some(password) = env_var("KEYSTORE_PASSWORD") {
+            self.keystore_password = Some(password);
+        }
+        if let Some(accounts) = env_var("ACCOUNTS") {
+            self.accounts = accounts
+                .split(',')
+                .map(|account| account.trim().parse())
+                .collect::<Result<_, _>>()
+                .map_err(|_| invalid_env("ACCOUNTS", accounts.clone()))?;
+        }
+        if let Some(policy) = env_var("FALLBACK_POLICY") {
+            self.fallback_policy = toml::Value::String(policy.clone())
+                .try_into()
+                .map_err(|_| invalid_env("FALLBACK_POLICY", policy))?;
+        }
+        if let Some(namespaces) = env_var("NAMESPACES") {
+            let enabled: Vec<&str> = namespaces.split(',').map(str::trim).collect();
+            self.namespaces =
+                Namespaces { trace: enabled.contains(&"trace"), debug: enabled.contains(&"debug") };
+        }
+
+        parse_env("DEFAULT_SENDER", &mut self.default_sender)?;
+        parse_env("STATE_CACHE_MAX_BLOCKS", &mut self.state_cache.max_blocks)?;
+        parse_env("STATE_CACHE_MAX_RECEIPTS", &mut self.state_cache.max_receipts)?;
+        parse_env("STATE_CACHE_MAX_ENVS", &mut self.state_cache.max_envs)?;
+        parse_env("MAX_TRACING_REQUESTS", &mut self.max_tracing_requests)?;
+        parse_env("MAX_HEAVY_REQUESTS", &mut self.max_heavy_requests)?;
+        parse_env("PROOF_CHUNK_SIZE", &mut self.proof_chunk_size)?;
+        parse_env("MAX_PROOF_KEYS", &mut self.max_proof_keys)?;
+        parse_env("MAX_LOGS_PER_RESPONSE", &mut self.max_logs_per_response)?;
+        parse_env("CALL_LIMITS_GAS_CAP", &mut self.call_limits.gas_cap)?;
+        parse_env("CALL_LIMITS_MAX_RESULT_SIZE", &mut self.call_limits.max_result_size)?;
+        let mut timeout = None;
+        parse_env::<f64>("CALL_LIMITS_TIMEOUT", &mut timeout)?;
+        if let Some(secs) = timeout {
+            self.call_limits.timeout = Some(Duration::from_secs_f64(secs));
+        }
+        parse_env("LOG_LIMITS_MAX_BLOCK_RANGE", &mut self.log_limits.max_block_range)?;
+        parse_env("LOG_LIMITS_MAX_RESULTS", &mut self.log_limits.max_results)?;
+        parse_env_value("LOG_LIMITS_CHUNK", &mut self.log_limits.chunk)?;
+        let mut timeout = None;
+        parse_env::<f64>("METHOD_TIMEOUTS_DEFAULT", &mut timeout)?;
+        if let Some(secs) = timeout {
+            self.method_timeouts.default = Some(Duration::from_secs_f64(secs));
+        }
+        parse_env("TRACE_LIMITS_MAX_DEPTH", &mut self.trace_limits.max_depth)?;
+        parse_env("TRACE_LIMITS_MAX_OUTPUT_SIZE", &mut self.trace_limits.max_output_size)?;
+        parse_env_value("TRACE_LIMITS_DISABLE_MEMORY", &mut self.trace_limits.disable_memory)?;
+        parse_env_value("TRACE_LIMITS_DISABLE_STACK", &mut self.trace_limits.disable_stack)?;
+        parse_env("TRACE_LIMITS_MAX_FILTER_BLOCKS", &mut self.trace_limits.max_filter_blocks)?;
+        parse_env_value("FEE_ESTIMATOR_BLOCKS", &mut self.fee_estimator.blocks)?;
+        parse_env_value(
+            "FEE_ESTIMATOR_REWARD_PERCENTILE",
+            &mut self.fee_estimator.reward_percentile,
+        )?;
+        parse_env_value(
+            "FEE_ESTIMATOR_BASE_FEE_MULTIPLIER",
+            &mut self.fee_estimator.base_fee_multiplier,
+        )?;
+        parse_env_value(
+            "FEE_ESTIMATOR_MIN_PRIORITY_FEE",
+            &mut self.fee_estimator.min_priority_fee,
+        )?;
+        parse_quota_env("RATE_LIMITS_GLOBAL", &mut self.rate_limits.global)?;
+        parse_quota_env("RATE_LIMITS_PER_CONNECTION", &mut self.rate_limits.per_connection)?;
+        parse_env_value("RATE_LIMITS_DEFAULT_WEIGHT", &mut self.rate_limits.default_weight)?;
+
+        Ok(self)
+    }
+
+    /// Returns a builder configured with everything but the chain and runtime handle.
+    pub fn builder<M: Middleware>(
+        &self,
+        inner: M,
+    ) -> Result<RethMiddlewareBuilder<M>, ConfigError> {
+        let datadir = self.datadir.as_ref().ok_or(ConfigError::MissingDatadir)?;
+
+        let defaults = EthStateCacheConfig::default();
+        let state_cache = EthStateCacheConfig {
+            max_blocks: self.state_cache.max_blocks.unwrap_or(defaults.max_blocks),
+            max_receipts: self.state_cache.max_receipts.unwrap_or(defaults.max_receipts),
+            max_envs: self.state_cache.max_envs.unwrap_or(defaults.max_envs),
+        };
+
+        let mut builder = RethMiddleware::builder(inner, datadir)
+            .state_cache(state_cache)
+            .fallback(FallbackConfig::new(self.fallback_policy))
+            .call_limits(self.call_limits)
+            .log_limits(self.log_limits)
+            .trace_limits(self.trace_limits)
+            .method_timeouts(self.method_timeouts.clone())
+            .fee_estimator(self.fee_estimator)
+            .rate_limits(self.rate_limits.clone())
+            .trace(self.namespaces.trace)
+            .debug(self.namespaces.debug);
+        if let Some(path) = &self.jwt_secret {
+            let secret = JwtSecret::from_file(path)
+                .map_err(|err| ConfigError::InvalidJwtSecret(err.to_string()))?;
+            builder = builder.server_auth(ServerAuth::Jwt(secret));
+        } else if let Some(token) = &self.auth_token {
+            builder = builder.server_auth(ServerAuth::Token(token.clone()));
+        }
+        let mut accounts = self.accounts.clone();
+        if let Some(dir) = &self.keystore {
+            let password = self.keystore_password.as_deref().unwrap_or_default();
+            let wallets = load_keystore(dir, password)
+                .map_err(|err| ConfigError::InvalidKeystore(err.to_string()))?;
+            accounts.extend(wallets.iter().map(Signer::address));
+        }
+        if !accounts.is_empty() || self.default_sender.is_some() {
+            builder = builder
+                .accounts(Accounts { addresses: accounts, default_sender: self.default_sender });
+        }
+        if let Some(max) = self.max_heavy_requests {
+            builder = builder.max_heavy_requests(max);
+        }
+        if let Some(keys) = self.proof_chunk_size {
+            builder = builder.proof_chunk_size(keys);
+        }
+        if let Some(keys) = self.max_proof_keys {
+            builder = builder.max_proof_keys(keys);
+        }
+        if let Some(max) = self.max_tracing_requests {
+            builder = builder.max_tracing_requests(max);
+        }
+        if let Some(max) = self.max_logs_per_response {
+            builder = builder.max_logs_per_response(max);
+        }
+
+        Ok(builder)
+    }
+
+    /// Returns a builder wrapping an HTTP provider connected to the fallback url.
+    pub fn http_builder(&self) -> Result<RethMiddlewareBuilder<Provider<Http>>, ConfigError> {
+        let url = self.fallback_url.as_ref().ok_or(ConfigError::MissingFallbackUrl)?;
+        let provider = Provider::<Http>::try_from(url.as_str())
+            .map_err(|err| ConfigError::InvalidUrl(err.to_string()))?;
+        self.builder(provider)
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    env::var(format!("{ENV_PREFIX}{name}")).ok()
+}
+
+fn invalid_env(name: &str, value: String) -> ConfigError {
+    ConfigError::InvalidEnv { var: format!("{ENV_PREFIX}{name}"), value }
+}
+
+fn parse_env<T: FromStr>(name: &str, target: &mut Option<T>) -> Result<(), ConfigError> {
+    if let Some(value) = env_var(name) {
+        *target = Some(value.parse().map_err(|_| invalid_env(name, value))?);
+    }
+    Ok(())
+}
+
+/// Overrides a setting that has a default with the variable `name`, if set.
+fn parse_env_value<T: FromStr>(name: &str, target: &mut T) -> Result<(), ConfigError> {
+    if let Some(value) = env_var(name) {
+        *target = value.parse().map_err(|_| invalid_env(name, value))?;
+    }
+    Ok(())
+}
+
+/// Reads a quota from `<name>_PER_SECOND` and `<name>_BURST`, the burst defaulting to one second
+/// of requests.
+fn parse_quota_env(name: &str, target: &mut Option<Quota>) -> Result<(), ConfigError> {
+    let mut per_second = target.map(|quota| quota.per_second);
+    let mut burst = target.map(|quota| quota.burst);
+    parse_env(&format!("{name}_PER_SECOND"), &mut per_second)?;
+    parse_env(&format!("{name}_BURST"), &mut burst)?;
+    if let Some(per_second) = per_second {
+        let burst = burst.unwrap_or(per_second.ceil() as u32);
+        *target = Some(Quota { per_second, burst });
+    }
+    Ok(())
+}
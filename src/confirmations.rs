@@ -0,0 +1,355 @@
+use crate::{instrument::db_read, type_conversions::ToEthers, RethMiddleware, RethMiddlewareError};
+use ethers::{
+    providers::{Middleware, MiddlewareError},
+    types::{
+        Address as EthersAddress, Bytes as EthersBytes,
+        TransactionReceipt as EthersTransactionReceipt, H256 as EthersH256, U256 as EthersU256,
+    },
+};
+use futures::future::BoxFuture;
+
+// Reth
+use reth_provider::{BlockHashReader, BlockNumReader, CanonStateNotification};
+
+// Std
+use std::future::IntoFuture;
+use tokio::sync::broadcast::error::RecvError;
+
+/// How waiting for the confirmations of a transaction ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Confirmation {
+    /// The transaction has the requested confirmations on the canonical chain.
+    Confirmed(Box<EthersTransactionReceipt>),
+    /// Another transaction of the sender with the same nonce has the requested confirmations.
+    Replaced(EthersH256),
+}
+
+/// The canonical block a transaction, or the transaction replacing it, was included in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Inclusion {
+    number: u64,
+    block_hash: EthersH256,
+    tx_hash: EthersH256,
+}
+
+/// A sent transaction whose receipt is resolved from the canonical state notifications as blocks
+/// are committed, instead of polling for it like ethers' `PendingTransaction`.
+///
+/// It stands beside `PendingTransaction` rather than replacing it in
+/// [Middleware::send_raw_transaction], whose signature returns ethers' type: that type holds the
+/// `Provider` at the bottom of the stack, not the middleware, and its polling interval is private,
+/// so it can neither be woken by the notifications nor read the receipt from the database.
+///
+/// Awaiting it returns the receipt once the transaction has the requested confirmations, one by
+/// default, or `None` if another transaction with the same nonce replaced it.
+#[must_use = "the transaction is only waited for when awaited"]
+#[derive(Debug)]
+pub struct NotifiedPendingTransaction<'a, M> {
+    middleware: &'a RethMiddleware<M>,
+    tx_hash: EthersH256,
+    confirmations: u64,
+}
+
+impl<'a, M> NotifiedPendingTransaction<'a, M> {
+    /// Returns the hash of the transaction.
+    pub fn tx_hash(&self) -> EthersH256 {
+        self.tx_hash
+    }
+
+    /// Sets the number of confirmations to wait for.
+    pub fn confirmations(mut self, confirmations: u64) -> Self {
+        self.confirmations = confirmations;
+        self
+    }
+}
+
+impl<'a, M> IntoFuture for NotifiedPendingTransaction<'a, M>
+where
+    M: Middleware + 'a,
+{
+    type Output = Result<Option<EthersTransactionReceipt>, RethMiddlewareError<M>>;
+    type IntoFuture = BoxFuture<'a, Self::Output>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move {
+            match self.middleware.wait_for_confirmations(self.tx_hash, self.confirmations).await? {
+                Confirmation::Confirmed(receipt) => Ok(Some(*receipt)),
+                Confirmation::Replaced(_) => Ok(None),
+            }
+        })
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Sends a signed transaction through the inner provider and returns a pending transaction
+    /// resolved from the canonical state notifications, so an embedded node sees the receipt as
+    /// soon as the block is committed.
+    ///
+    /// This is [Middleware::send_raw_transaction] with a [NotifiedPendingTransaction] in place of
+    /// the polling `PendingTransaction` the trait method has to return.
+    pub async fn send_raw_transaction_notified(
+        &self,
+        tx: EthersBytes,
+    ) -> Result<NotifiedPendingTransaction<'_, M>, RethMiddlewareError<M>> {
+        let pending =
+            self.inner.send_raw_transaction(tx).await.map_err(MiddlewareError::from_err)?;
+        Ok(self.notified_pending_transaction(*pending))
+    }
+
+    /// Returns a pending transaction resolved from the canonical state notifications, for a
+    /// transaction sent by other means.
+    pub fn notified_pending_transaction(
+        &self,
+        tx_hash: EthersH256,
+    ) -> NotifiedPendingTransaction<'_, M> {
+        NotifiedPendingTransaction { middleware: self, tx_hash, confirmations: 1 }
+    }
+
+    /// Waits until a transaction, or another one from its sender with the same nonce, is
+    /// `confirmations` blocks deep in the canonical chain, following the canonical state
+    /// notifications.
+    ///
+    /// Unlike polling for the receipt, an inclusion that is reorged out is forgotten and waited
+    /// for again, so the returned receipt is the one of the canonical chain. Replacements can
+    /// only be detected for transactions known to the database or the local pool, and not
+    /// within the blocks missed while the notifications lagged, after which the inclusion is
+    /// read again from the database.
+    pub async fn wait_for_confirmations(
+        &self,
+        tx_hash: EthersH256,
+        confirmations: u64,
+    ) -> Result<Confirmation, RethMiddlewareError<M>> {
+        // subscribe before reading the database so no block is missed in between
+        let mut notifications = self.subscribe_to_canonical_state();
+
+        let sender_nonce = self.get_transaction(tx_hash).await?.map(|tx| (tx.from, tx.nonce));
+        let mut inclusion = self.read_inclusion(tx_hash, None).await?;
+        let mut tip = db_read("best_block_number", || self.reth_provider.best_block_number())?;
+
+        loop {
+            if let Some(included) = inclusion {
+                if tip + 1 >= included.number + confirmations.max(1) {
+                    if included.tx_hash != tx_hash {
+                        return Ok(Confirmation::Replaced(included.tx_hash))
+                    }
+                    // the receipt must come from the block still canonical
+                    if let Some(receipt) = self.get_transaction_receipt(tx_hash).await? {
+                        if receipt.block_hash == Some(included.block_hash) {
+                            return Ok(Confirmation::Confirmed(Box::new(receipt)))
+                        }
+                    }
+                }
+            }
+
+            let notification = match notifications.recv().await {
+                Ok(notification) => notification,
+                Err(RecvError::Lagged(_)) => {
+                    // the missed blocks may have included or reorged out the transaction
+                    inclusion = self.read_inclusion(tx_hash, inclusion).await?;
+                    tip = db_read("best_block_number", || self.reth_provider.best_block_number())?;
+                    continue
+                }
+                Err(RecvError::Closed) => return Err(RethMiddlewareError::NotificationsClosed),
+            };
+            tip = notification.tip().number;
+            inclusion = track_inclusion(&notification, inclusion, tx_hash, sender_nonce);
+        }
+    }
+
+    /// Reads the inclusion of a transaction from the database. A replacement found from the
+    /// notifications, `known`, is kept while its block is canonical, as the database cannot find
+    /// replacements by nonce.
+    async fn read_inclusion(
+        &self,
+        tx_hash: EthersH256,
+        known: Option<Inclusion>,
+    ) -> Result<Option<Inclusion>, RethMiddlewareError<M>> {
+        let receipt = self.get_transaction_receipt(tx_hash).await?;
+        if let Some(inclusion) = receipt.and_then(|receipt| {
+            Some(Inclusion {
+                number: receipt.block_number?.as_u64(),
+                block_hash: receipt.block_hash?,
+                tx_hash,
+            })
+        }) {
+            return Ok(Some(inclusion))
+        }
+
+        let Some(replaced) = known.filter(|known| known.tx_hash != tx_hash) else {
+            return Ok(None)
+        };
+        let canonical = db_read("block_hash", || self.reth_provider.block_hash(replaced.number))?;
+        Ok(canonical.filter(|hash| hash.into_ethers() == replaced.block_hash).map(|_| replaced))
+    }
+}
+
+/// Updates the inclusion of a transaction with a canonical state notification: forgets it if its
+/// block was reverted or overwritten, records it if the transaction or a replacement was
+/// committed.
+fn track_inclusion(
+    notification: &CanonStateNotification,
+    mut inclusion: Option<Inclusion>,
+    tx_hash: EthersH256,
+    sender_nonce: Option<(EthersAddress, EthersU256)>,
+) -> Option<Inclusion> {
+    let committed = notification.committed();
+    let fork = committed.as_ref().map(|chain| chain.first().number);
+    if let Some(included) = inclusion {
+        let reverted = notification.reverted().map_or(false, |chain| {
+            chain.blocks().values().any(|block| block.hash().into_ethers() == included.block_hash)
+        });
+        if reverted || fork.map_or(false, |fork| included.number >= fork) {
+            inclusion = None;
+        }
+    }
+
+    for block in committed.iter().flat_map(|chain| chain.blocks().values()) {
+        for (tx, sender) in block.body.iter().zip(block.senders.iter()) {
+            let hash: EthersH256 = tx.hash().into_ethers();
+            let replaces = sender_nonce.map_or(false, |(from, nonce)| {
+                EthersAddress::from(sender.0) == from && EthersU256::from(tx.nonce()) == nonce
+            });
+            if hash == tx_hash || replaces {
+                inclusion = Some(Inclusion {
+                    number: block.header.number,
+                    block_hash: block.hash().into_ethers(),
+                    tx_hash: hash,
+                });
+            }
+        }
+    }
+    inclusion
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reth_primitives::{
+        Address, Header, SealedBlock, SealedBlockWithSenders, Signature, Transaction,
+        TransactionSigned, TxLegacy,
+    };
+    use reth_provider::{Chain, PostState};
+    use std::sync::Arc;
+
+    const SENDER: Address = Address::repeat_byte(1);
+
+    fn tx(nonce: u64, gas_price: u128) -> TransactionSigned {
+        let tx = TxLegacy { nonce, gas_price, ..Default::default() };
+        TransactionSigned::from_transaction_and_signature(
+            Transaction::Legacy(tx),
+            Signature::default(),
+        )
+    }
+
+    fn block(number: u64, fork: u8, txs: &[TransactionSigned]) -> SealedBlockWithSenders {
+        let header = Header { number, extra_data: vec![fork].into(), ..Default::default() };
+        SealedBlockWithSenders {
+            block: SealedBlock {
+                header: header.seal_slow(),
+                body: txs.to_vec(),
+                ..Default::default()
+            },
+            senders: vec![SENDER; txs.len()],
+        }
+    }
+
+    fn chain(blocks: Vec<SealedBlockWithSenders>) -> Arc<Chain> {
+        Arc::new(Chain::new(
+            blocks.into_iter().map(|block| (block, PostState::default())).collect(),
+        ))
+    }
+
+    fn inclusion(block: &SealedBlockWithSenders, tx: &TransactionSigned) -> Inclusion {
+        Inclusion {
+            number: block.number,
+            block_hash: block.hash().into_ethers(),
+            tx_hash: tx.hash().into_ethers(),
+        }
+    }
+
+    fn sender_nonce(nonce: u64) -> Option<(EthersAddress, EthersU256)> {
+        Some((EthersAddress::from(SENDER.0), EthersU256::from(nonce)))
+    }
+
+    #[test]
+    fn records_committed_transaction() {
+        let sent = tx(0, 1);
+        let included = block(2, 0, &[tx(5, 1), sent.clone()]);
+        let notification =
+            CanonStateNotification::Commit { new: chain(vec![block(1, 0, &[]), included.clone()]) };
+
+        let tracked = track_inclusion(&notification, None, sent.hash().into_ethers(), None);
+        assert_eq!(tracked, Some(inclusion(&included, &sent)));
+    }
+
+    #[test]
+    fn ignores_unrelated_blocks() {
+        let sent = tx(0, 1);
+        let known = inclusion(&block(1, 0, &[sent.clone()]), &sent);
+        let notification = CanonStateNotification::Commit { new: chain(vec![block(2, 0, &[])]) };
+
+        let tracked =
+            track_inclusion(&notification, Some(known), sent.hash().into_ethers(), sender_nonce(0));
+        assert_eq!(tracked, Some(known));
+    }
+
+    #[test]
+    fn records_replacement_with_same_nonce() {
+        let (sent, replacement) = (tx(3, 1), tx(3, 2));
+        let included = block(1, 0, &[replacement.clone()]);
+        let notification = CanonStateNotification::Commit { new: chain(vec![included.clone()]) };
+
+        let tracked =
+            track_inclusion(&notification, None, sent.hash().into_ethers(), sender_nonce(3));
+        assert_eq!(tracked, Some(inclusion(&included, &replacement)));
+        let tracked = track_inclusion(&notification, None, sent.hash().into_ethers(), None);
+        assert_eq!(tracked, None);
+    }
+
+    #[test]
+    fn forgets_reorged_out_inclusion() {
+        let sent = tx(0, 1);
+        let old = block(1, 0, &[sent.clone()]);
+        let notification = CanonStateNotification::Reorg {
+            old: chain(vec![old.clone()]),
+            new: chain(vec![block(1, 1, &[])]),
+        };
+
+        let known = Some(inclusion(&old, &sent));
+        assert_eq!(track_inclusion(&notification, known, sent.hash().into_ethers(), None), None);
+    }
+
+    #[test]
+    fn moves_inclusion_to_the_new_fork() {
+        let sent = tx(0, 1);
+        let (old, new) = (block(1, 0, &[sent.clone()]), block(2, 1, &[sent.clone()]));
+        let notification = CanonStateNotification::Reorg {
+            old: chain(vec![old.clone()]),
+            new: chain(vec![block(1, 1, &[]), new.clone()]),
+        };
+
+        let tracked = track_inclusion(
+            &notification,
+            Some(inclusion(&old, &sent)),
+            sent.hash().into_ethers(),
+            None,
+        );
+        assert_eq!(tracked, Some(inclusion(&new, &sent)));
+    }
+
+    #[test]
+    fn forgets_inclusion_overwritten_by_a_commit() {
+        let sent = tx(0, 1);
+        let known = inclusion(&block(2, 0, &[sent.clone()]), &sent);
+        // a commit starting at or below the inclusion replaced its block
+        let notification = CanonStateNotification::Commit { new: chain(vec![block(2, 1, &[])]) };
+
+        assert_eq!(
+            track_inclusion(&notification, Some(known), sent.hash().into_ethers(), None),
+            None
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{
+    instrument::db_read, type_conversions::ToReth, RethClient, RethMiddleware, RethMiddlewareError,
+};
+use ethers::{providers::Middleware, types::BlockId as EthersBlockId};
+
+// Reth
+use reth_primitives::{BlockId, H256};
+use reth_provider::{BlockIdReader, StateProviderBox, StateProviderFactory};
+use reth_revm::{
+    database::State,
+    primitives::{AccountInfo, Bytecode, B160, B256, U256},
+    Database, DatabaseRef,
+};
+
+/// A revm database reading the state of the node at a fixed block.
+///
+/// Every read goes through the same database transaction, so the view stays consistent while the
+/// node keeps committing blocks. Use it to run your own EVM loops, wrapped in a `CacheDB` to
+/// stage writes.
+pub struct RethDatabaseRef<'a> {
+    state: State<StateProviderBox<'a>>,
+    block_hash: H256,
+}
+
+impl<'a> RethDatabaseRef<'a> {
+    /// Opens the state at the end of the given block, i.e. with all of its transactions applied.
+    pub fn at_block(provider: &'a RethClient, block_hash: H256) -> reth_interfaces::Result<Self> {
+        let state = provider.history_by_block_hash(block_hash)?;
+        Ok(Self { state: State::new(state), block_hash })
+    }
+
+    /// Returns the hash of the block whose state is read.
+    pub fn block_hash(&self) -> H256 {
+        self.block_hash
+    }
+}
+
+impl std::fmt::Debug for RethDatabaseRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RethDatabaseRef").field("block_hash", &self.block_hash).finish()
+    }
+}
+
+impl DatabaseRef for RethDatabaseRef<'_> {
+    type Error = reth_interfaces::Error;
+
+    fn basic(&self, address: B160) -> Result<Option<AccountInfo>, Self::Error> {
+        DatabaseRef::basic(&self.state, address)
+    }
+
+    fn code_by_hash(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        DatabaseRef::code_by_hash(&self.state, code_hash)
+    }
+
+    fn storage(&self, address: B160, index: U256) -> Result<U256, Self::Error> {
+        DatabaseRef::storage(&self.state, address, index)
+    }
+
+    fn block_hash(&self, number: U256) -> Result<B256, Self::Error> {
+        DatabaseRef::block_hash(&self.state, number)
+    }
+}
+
+impl Database for RethDatabaseRef<'_> {
+    type Error = reth_interfaces::Error;
+
+    fn basic(&mut self, address: B160) -> Result<Option<AccountInfo>, Self::Error> {
+        DatabaseRef::basic(self, address)
+    }
+
+    fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        DatabaseRef::code_by_hash(self, code_hash)
+    }
+
+    fn storage(&mut self, address: B160, index: U256) -> Result<U256, Self::Error> {
+        DatabaseRef::storage(self, address, index)
+    }
+
+    fn block_hash(&mut self, number: U256) -> Result<B256, Self::Error> {
+        DatabaseRef::block_hash(self, number)
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns a revm database over the state at the end of `block`.
+    pub fn database_at<T: Into<EthersBlockId>>(
+        &self,
+        block: T,
+    ) -> Result<RethDatabaseRef<'_>, RethMiddlewareError<M>> {
+        let block_id: BlockId = block.into().into_reth();
+        let block_hash =
+            db_read("block_hash_for_id", || self.reth_provider.block_hash_for_id(block_id))?
+                .ok_or(RethMiddlewareError::BlockNotFound)?;
+        Ok(RethDatabaseRef::at_block(&self.reth_provider, block_hash)?)
+    }
+}
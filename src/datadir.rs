@@ -0,0 +1,177 @@
+use crate::{
+    chain::detect_chain,
+    init::{open_db, view},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::providers::Middleware;
+use thiserror::Error;
+
+// Reth
+use reth_db::{
+    mdbx::{Env, WriteMap},
+    tables::Tables,
+    version::{check_db_version_file, DatabaseVersionError, DB_VERSION},
+    DatabaseError,
+};
+use reth_primitives::MAINNET;
+
+// Std
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::runtime::Handle;
+
+/// Name of the MDBX data file.
+const MDBX_DATA_FILE: &str = "mdbx.dat";
+
+/// MDBX error code returned when another process holds the environment exclusively.
+const MDBX_BUSY: i32 = -30778;
+
+/// Database versions written by reth releases, oldest first. Only [DB_VERSION], the version of
+/// the pinned reth revision, can be read.
+pub const DB_VERSIONS: &[(u64, &str)] =
+    &[(1, "reth 0.1.0-alpha releases"), (2, "reth 0.2.0-beta and newer releases")];
+
+/// Returns the reth releases writing a database version.
+pub fn reth_releases(version: u64) -> &'static str {
+    DB_VERSIONS
+        .iter()
+        .find(|(known, _)| *known == version)
+        .map_or("an unknown reth release", |(_, releases)| releases)
+}
+
+/// A datadir that cannot be opened, detected before the database is used.
+#[derive(Error, Debug)]
+pub enum DatadirError {
+    #[error("Datadir {0} does not exist")]
+    NotFound(PathBuf),
+
+    /// Neither the path nor its `db` subdirectory contains an MDBX database.
+    #[error("No database found in {0}, expected a reth datadir or its db directory")]
+    MissingDatabase(PathBuf),
+
+    #[error("Database in {0} has no version file, it was not created by reth")]
+    MissingVersionFile(PathBuf),
+
+    /// The database was written by a reth release with a different schema.
+    #[error(
+        "Database version {found}, written by {}, is not supported: expected version {expected}, \
+         written by {}",
+        reth_releases(*found),
+        reth_releases(*expected)
+    )]
+    VersionMismatch { expected: u64, found: u64 },
+
+    /// Tables of the pinned reth revision are missing, so the schema has drifted even though the
+    /// version matched.
+    #[error(
+        "Database in {path} is missing the tables {tables:?}: it was written by a reth revision \
+         with a different schema than the pinned one"
+    )]
+    MissingTables { path: PathBuf, tables: Vec<&'static str> },
+
+    /// Another process opened the database exclusively, e.g. a node started with `--db.exclusive`.
+    #[error("Database in {0} is locked exclusively by another process")]
+    Locked(PathBuf),
+
+    #[error("Invalid datadir {path}: {reason}")]
+    Invalid { path: PathBuf, reason: String },
+}
+
+/// Returns the directory holding the MDBX database, accepting either a reth datadir or its `db`
+/// directory, after checking the database version.
+///
+/// The database version this crate reads keeps every table in MDBX, so there are no static files
+/// to look for.
+pub fn validate_datadir(path: &Path) -> Result<PathBuf, DatadirError> {
+    if !path.exists() {
+        return Err(DatadirError::NotFound(path.to_path_buf()))
+    }
+
+    let db_path = [path.join("db"), path.to_path_buf()]
+        .into_iter()
+        .find(|dir| dir.join(MDBX_DATA_FILE).is_file())
+        .ok_or_else(|| DatadirError::MissingDatabase(path.to_path_buf()))?;
+    check_db_version(&db_path)?;
+
+    Ok(db_path)
+}
+
+/// Fails if the version file of the database in `db_path` is missing or names a version other
+/// than [DB_VERSION].
+pub fn check_db_version(db_path: &Path) -> Result<(), DatadirError> {
+    check_db_version_file(db_path).map_err(|err| match err {
+        DatabaseVersionError::MissingFile => {
+            DatadirError::MissingVersionFile(db_path.to_path_buf())
+        }
+        DatabaseVersionError::VersionMismatch { version } => {
+            DatadirError::VersionMismatch { expected: DB_VERSION, found: version }
+        }
+        err => DatadirError::Invalid { path: db_path.to_path_buf(), reason: err.to_string() },
+    })
+}
+
+/// Fails if a table of the pinned reth revision is missing from the database, naming all of
+/// them, instead of the MDBX error code of the first one the handlers open.
+pub fn check_tables(db: &Env<WriteMap>, db_path: &Path) -> Result<(), DatadirError> {
+    let missing: Vec<&'static str> = view(db, |tx| {
+        Tables::ALL
+            .iter()
+            .map(|table| table.name())
+            .filter(|name| tx.inner.open_db(Some(*name)).is_err())
+            .collect()
+    })
+    .map_err(|err| DatadirError::Invalid {
+        path: db_path.to_path_buf(),
+        reason: err.to_string(),
+    })?;
+
+    if missing.is_empty() {
+        return Ok(())
+    }
+    Err(DatadirError::MissingTables { path: db_path.to_path_buf(), tables: missing })
+}
+
+/// Opens the database in a validated directory, telling an exclusive lock apart from other
+/// failures.
+fn open_validated(db_path: &Path) -> Result<Env<WriteMap>, DatadirError> {
+    let invalid = |err: DatabaseError| DatadirError::Invalid {
+        path: db_path.to_path_buf(),
+        reason: err.to_string(),
+    };
+
+    match open_db(db_path) {
+        Ok(db) => {
+            // a read transaction fails the same way if the lock is taken after opening
+            view(&db, |_| ()).map_err(invalid)?;
+            check_tables(&db, db_path)?;
+            Ok(db)
+        }
+        Err(DatabaseError::FailedToOpen(MDBX_BUSY)) => {
+            Err(DatadirError::Locked(db_path.to_path_buf()))
+        }
+        Err(err) => Err(invalid(err)),
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Opens the database of a reth datadir, or of its `db` directory, on the current runtime.
+    ///
+    /// The layout, database version and lock are checked up front, failing with a
+    /// [DatadirError] instead of an MDBX error code on the first request. The chain is detected
+    /// from the genesis block.
+    pub fn from_db_path<P: AsRef<Path>>(inner: M, path: P) -> Result<Self, RethMiddlewareError<M>> {
+        let db_path = validate_datadir(path.as_ref())?;
+        let db = Arc::new(open_validated(&db_path)?);
+
+        let chain = detect_chain(&db)
+            .map_err(reth_interfaces::Error::from)?
+            .unwrap_or_else(|| MAINNET.clone());
+        let components = Self::init_components(db, chain, Handle::current())?;
+        Ok(Self::from_node(inner, components))
+    }
+}
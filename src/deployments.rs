@@ -0,0 +1,202 @@
+use crate::{
+    instrument::{db_read, instrument, record_block},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Action, Address as EthersAddress, Res, TxHash as EthersTxHash, H256 as EthersH256},
+    utils::keccak256,
+};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_primitives::{Account, Address, H256, KECCAK_EMPTY};
+use reth_provider::{AccountReader, ChangeSetReader, StateProviderFactory};
+use reth_rpc::eth::error::EthApiError;
+
+// Std
+use std::{
+    collections::{BTreeMap, HashSet},
+    ops::RangeInclusive,
+};
+
+/// Most blocks a single scan for contract deployments may span.
+pub const MAX_DEPLOYMENT_SCAN_BLOCKS: u64 = 10_000;
+
+/// A contract created by a transaction, directly or by a factory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContractCreation {
+    pub block_number: u64,
+    pub transaction_hash: Option<EthersTxHash>,
+    pub transaction_position: Option<usize>,
+    pub address: EthersAddress,
+    /// The sender of the deploying transaction, or the factory contract.
+    pub creator: EthersAddress,
+    /// Hash of the init code, which the address of a `CREATE2` contract derives from.
+    pub init_code_hash: EthersH256,
+}
+
+/// Most blocks a single scan for code changes may span.
+pub const MAX_CODE_CHANGE_SCAN_BLOCKS: u64 = 100_000;
+
+/// What happened to the code of a contract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CodeChangeKind {
+    /// The code is gone, e.g. the contract self-destructed.
+    Removed,
+    /// The code was replaced, e.g. by a contract self-destructed and deployed again at the same
+    /// address.
+    Replaced,
+}
+
+/// A change to the code of a contract by a block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeChange {
+    pub block_number: u64,
+    pub address: EthersAddress,
+    pub kind: CodeChangeKind,
+    pub code_hash_before: EthersH256,
+    /// `None` if the code was removed.
+    pub code_hash_after: Option<EthersH256>,
+}
+
+/// Returns the hash of the code of an account, `None` if it has none.
+fn code_hash(account: Option<Account>) -> Option<H256> {
+    account.and_then(|account| account.bytecode_hash).filter(|hash| *hash != KECCAK_EMPTY)
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the hash of the code of every account a block changed, before and after it, read
+    /// from the block's account changeset.
+    pub(crate) fn block_code_changes(
+        &self,
+        number: u64,
+    ) -> Result<BTreeMap<Address, (Option<H256>, Option<H256>)>, RethMiddlewareError<M>> {
+        let changeset = db_read("account_block_changeset", || {
+            self.reth_provider.account_block_changeset(number)
+        })?;
+        let state = db_read("history_by_block_number", || {
+            self.reth_provider.history_by_block_number(number)
+        })?;
+
+        let mut changes = BTreeMap::new();
+        for change in changeset {
+            let after = code_hash(state.basic_account(change.address)?);
+            changes.insert(change.address, (code_hash(change.info), after));
+        }
+        Ok(changes)
+    }
+
+    /// Returns the contracts deployed by the canonical blocks in `range`, in execution order,
+    /// with their creator and the hash of their init code.
+    ///
+    /// Blocks are first screened with their account changesets, for accounts that gained code,
+    /// and only blocks with deployments are traced. Contracts that did not outlive their
+    /// transaction, or were deployed without code, are left out.
+    pub async fn get_contract_creations(
+        &self,
+        range: RangeInclusive<u64>,
+    ) -> Result<Vec<ContractCreation>, RethMiddlewareError<M>> {
+        instrument("get_contract_creations", async {
+            record_block(&range);
+            let (first, last) = range.clone().into_inner();
+            if first > last || last - first >= MAX_DEPLOYMENT_SCAN_BLOCKS {
+                return Err(EthApiError::InvalidParams(format!(
+                    "at most {MAX_DEPLOYMENT_SCAN_BLOCKS} blocks can be scanned"
+                ))
+                .into())
+            }
+            self.ensure_history(Some(first.into()))?;
+
+            let mut creations = vec![];
+            for number in range {
+                let deployed: HashSet<EthersAddress> = self
+                    .block_code_changes(number)?
+                    .into_iter()
+                    .filter(|(_, (before, after))| before.is_none() && after.is_some())
+                    .map(|(address, _)| EthersAddress::from(address.0))
+                    .collect();
+                if deployed.is_empty() {
+                    continue
+                }
+
+                for trace in self.trace_block(number.into()).await? {
+                    let (Action::Create(create), Some(Res::Create(result))) =
+                        (&trace.action, &trace.result)
+                    else {
+                        continue
+                    };
+                    if !deployed.contains(&result.address) {
+                        continue
+                    }
+                    creations.push(ContractCreation {
+                        block_number: number,
+                        transaction_hash: trace.transaction_hash,
+                        transaction_position: trace.transaction_position,
+                        address: result.address,
+                        creator: create.from,
+                        init_code_hash: keccak256(&create.init).into(),
+                    });
+                }
+            }
+            Ok(creations)
+        })
+        .await
+    }
+
+    /// Returns the contracts whose code the canonical blocks in `range` removed or replaced, in
+    /// block order, read from the account changesets without executing anything. Only
+    /// `addresses` are watched, unless empty.
+    ///
+    /// Dependency monitors can alarm on a contract they rely on disappearing. Contracts created
+    /// and removed within a block are left out, as they never had code at a block's end.
+    pub async fn get_code_changes(
+        &self,
+        range: RangeInclusive<u64>,
+        addresses: &[EthersAddress],
+    ) -> Result<Vec<CodeChange>, RethMiddlewareError<M>> {
+        instrument("get_code_changes", async {
+            record_block(&range);
+            let (first, last) = range.clone().into_inner();
+            if first > last || last - first >= MAX_CODE_CHANGE_SCAN_BLOCKS {
+                return Err(EthApiError::InvalidParams(format!(
+                    "at most {MAX_CODE_CHANGE_SCAN_BLOCKS} blocks can be scanned"
+                ))
+                .into())
+            }
+            self.ensure_history(Some(first.into()))?;
+
+            let watched: HashSet<Address> =
+                addresses.iter().map(|address| Address::from(address.0)).collect();
+            let mut changes = vec![];
+            for number in range {
+                for (address, (before, after)) in self.block_code_changes(number)? {
+                    let Some(before) = before else { continue };
+                    if after == Some(before) || (!watched.is_empty() && !watched.contains(&address))
+                    {
+                        continue
+                    }
+                    changes.push(CodeChange {
+                        block_number: number,
+                        address: EthersAddress::from(address.0),
+                        kind: if after.is_some() {
+                            CodeChangeKind::Replaced
+                        } else {
+                            CodeChangeKind::Removed
+                        },
+                        code_hash_before: EthersH256(before.0),
+                        code_hash_after: after.map(|hash| EthersH256(hash.0)),
+                    });
+                }
+            }
+            Ok(changes)
+        })
+        .await
+    }
+}
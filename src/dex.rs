@@ -0,0 +1,272 @@
+//! Uniswap V2 and V3 pool state read straight from storage, through the pools' storage layouts.
+//! Forks keeping the original layouts, e.g. SushiSwap, are read the same way.
+use crate::{storage_layout::StorageLayout, RethMiddleware, RethMiddlewareError};
+use ethers::{
+    abi::Token,
+    providers::Middleware,
+    types::{Address, BlockId, NameOrAddress, I256, U256},
+};
+use serde::{Deserialize, Serialize};
+
+/// The part of the storage layout of `UniswapV2Pair` holding the pair's state.
+const UNISWAP_V2_PAIR_LAYOUT: &str = r#"{
+    "storage": [
+        { "label": "token0", "slot": "6", "offset": 0, "type": "t_address" },
+        { "label": "token1", "slot": "7", "offset": 0, "type": "t_address" },
+        { "label": "reserve0", "slot": "8", "offset": 0, "type": "t_uint112" },
+        { "label": "reserve1", "slot": "8", "offset": 14, "type": "t_uint112" },
+        { "label": "blockTimestampLast", "slot": "8", "offset": 28, "type": "t_uint32" },
+        { "label": "kLast", "slot": "11", "offset": 0, "type": "t_uint256" }
+    ],
+    "types": {
+        "t_address": { "encoding": "inplace", "label": "address", "numberOfBytes": "20" },
+        "t_uint112": { "encoding": "inplace", "label": "uint112", "numberOfBytes": "14" },
+        "t_uint32": { "encoding": "inplace", "label": "uint32", "numberOfBytes": "4" },
+        "t_uint256": { "encoding": "inplace", "label": "uint256", "numberOfBytes": "32" }
+    }
+}"#;
+
+/// The part of the storage layout of `UniswapV3Pool` holding the pool's state. The tokens, fee
+/// and tick spacing are immutables, in the code rather than in storage.
+const UNISWAP_V3_POOL_LAYOUT: &str = r#"{
+    "storage": [
+        { "label": "slot0", "slot": "0", "offset": 0, "type": "t_struct(Slot0)" },
+        { "label": "liquidity", "slot": "4", "offset": 0, "type": "t_uint128" },
+        { "label": "ticks", "slot": "5", "offset": 0, "type": "t_mapping(t_int24,t_struct(Info))" },
+        { "label": "tickBitmap", "slot": "6", "offset": 0, "type": "t_mapping(t_int16,t_uint256)" }
+    ],
+    "types": {
+        "t_bool": { "encoding": "inplace", "label": "bool", "numberOfBytes": "1" },
+        "t_int16": { "encoding": "inplace", "label": "int16", "numberOfBytes": "2" },
+        "t_int24": { "encoding": "inplace", "label": "int24", "numberOfBytes": "3" },
+        "t_int56": { "encoding": "inplace", "label": "int56", "numberOfBytes": "7" },
+        "t_int128": { "encoding": "inplace", "label": "int128", "numberOfBytes": "16" },
+        "t_uint8": { "encoding": "inplace", "label": "uint8", "numberOfBytes": "1" },
+        "t_uint16": { "encoding": "inplace", "label": "uint16", "numberOfBytes": "2" },
+        "t_uint32": { "encoding": "inplace", "label": "uint32", "numberOfBytes": "4" },
+        "t_uint128": { "encoding": "inplace", "label": "uint128", "numberOfBytes": "16" },
+        "t_uint160": { "encoding": "inplace", "label": "uint160", "numberOfBytes": "20" },
+        "t_uint256": { "encoding": "inplace", "label": "uint256", "numberOfBytes": "32" },
+        "t_mapping(t_int16,t_uint256)": {
+            "encoding": "mapping", "label": "mapping(int16 => uint256)", "numberOfBytes": "32",
+            "key": "t_int16", "value": "t_uint256"
+        },
+        "t_mapping(t_int24,t_struct(Info))": {
+            "encoding": "mapping", "label": "mapping(int24 => struct Tick.Info)",
+            "numberOfBytes": "32", "key": "t_int24", "value": "t_struct(Info)"
+        },
+        "t_struct(Slot0)": {
+            "encoding": "inplace", "label": "struct UniswapV3Pool.Slot0", "numberOfBytes": "32",
+            "members": [
+                { "label": "sqrtPriceX96", "slot": "0", "offset": 0, "type": "t_uint160" },
+                { "label": "tick", "slot": "0", "offset": 20, "type": "t_int24" },
+                { "label": "observationIndex", "slot": "0", "offset": 23, "type": "t_uint16" },
+                {
+                    "label": "observationCardinality", "slot": "0", "offset": 25,
+                    "type": "t_uint16"
+                },
+                {
+                    "label": "observationCardinalityNext", "slot": "0", "offset": 27,
+                    "type": "t_uint16"
+                },
+                { "label": "feeProtocol", "slot": "0", "offset": 29, "type": "t_uint8" },
+                { "label": "unlocked", "slot": "0", "offset": 30, "type": "t_bool" }
+            ]
+        },
+        "t_struct(Info)": {
+            "encoding": "inplace", "label": "struct Tick.Info", "numberOfBytes": "128",
+            "members": [
+                { "label": "liquidityGross", "slot": "0", "offset": 0, "type": "t_uint128" },
+                { "label": "liquidityNet", "slot": "0", "offset": 16, "type": "t_int128" },
+                { "label": "feeGrowthOutside0X128", "slot": "1", "offset": 0, "type": "t_uint256" },
+                { "label": "feeGrowthOutside1X128", "slot": "2", "offset": 0, "type": "t_uint256" },
+                { "label": "tickCumulativeOutside", "slot": "3", "offset": 0, "type": "t_int56" },
+                {
+                    "label": "secondsPerLiquidityOutsideX128", "slot": "3", "offset": 7,
+                    "type": "t_uint160"
+                },
+                { "label": "secondsOutside", "slot": "3", "offset": 27, "type": "t_uint32" },
+                { "label": "initialized", "slot": "3", "offset": 31, "type": "t_bool" }
+            ]
+        }
+    }
+}"#;
+
+/// Returns the storage layout of the state of Uniswap V2 pairs.
+pub fn uniswap_v2_pair_layout() -> StorageLayout {
+    serde_json::from_str(UNISWAP_V2_PAIR_LAYOUT).expect("valid layout")
+}
+
+/// Returns the storage layout of the state of Uniswap V3 pools.
+pub fn uniswap_v3_pool_layout() -> StorageLayout {
+    serde_json::from_str(UNISWAP_V3_POOL_LAYOUT).expect("valid layout")
+}
+
+/// The state of a Uniswap V2 pair, as `getReserves` and the token getters return it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UniswapV2Reserves {
+    pub token0: Address,
+    pub token1: Address,
+    pub reserve0: U256,
+    pub reserve1: U256,
+    pub block_timestamp_last: u32,
+    /// `reserve0 * reserve1` after the last liquidity event, zero unless the protocol fee is on.
+    pub k_last: U256,
+}
+
+/// The `slot0` of a Uniswap V3 pool.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Slot0 {
+    pub sqrt_price_x96: U256,
+    pub tick: i32,
+    pub observation_index: u16,
+    pub observation_cardinality: u16,
+    pub observation_cardinality_next: u16,
+    pub fee_protocol: u8,
+    pub unlocked: bool,
+}
+
+/// A tick of a Uniswap V3 pool, empty if it is not initialized.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TickInfo {
+    pub liquidity_gross: u128,
+    pub liquidity_net: i128,
+    pub fee_growth_outside0_x128: U256,
+    pub fee_growth_outside1_x128: U256,
+    pub tick_cumulative_outside: i64,
+    pub seconds_per_liquidity_outside_x128: U256,
+    pub seconds_outside: u32,
+    pub initialized: bool,
+}
+
+/// Unpacks the values of the tokens the layouts above decode to.
+struct Fields(std::vec::IntoIter<Token>);
+
+impl Fields {
+    fn new(tokens: Vec<Token>) -> Self {
+        Self(tokens.into_iter())
+    }
+
+    /// Returns the fields of the next token, a struct.
+    fn tuple(&mut self) -> Self {
+        match self.0.next() {
+            Some(Token::Tuple(fields)) => Self::new(fields),
+            _ => Self::new(vec![]),
+        }
+    }
+
+    fn address(&mut self) -> Address {
+        self.0.next().and_then(Token::into_address).unwrap_or_default()
+    }
+
+    fn uint(&mut self) -> U256 {
+        self.0.next().and_then(Token::into_uint).unwrap_or_default()
+    }
+
+    fn int(&mut self) -> I256 {
+        self.0.next().and_then(Token::into_int).map(I256::from_raw).unwrap_or_default()
+    }
+
+    fn bool(&mut self) -> bool {
+        self.0.next().and_then(Token::into_bool).unwrap_or_default()
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Reads the tokens and reserves of a Uniswap V2 pair at a block.
+    pub async fn get_uniswap_v2_reserves<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        pair: T,
+        block: Option<BlockId>,
+    ) -> Result<UniswapV2Reserves, RethMiddlewareError<M>> {
+        let paths = ["token0", "token1", "reserve0", "reserve1", "blockTimestampLast", "kLast"];
+        let values =
+            self.read_storage_variables(pair, &uniswap_v2_pair_layout(), &paths, block).await?;
+
+        let mut fields = Fields::new(values);
+        Ok(UniswapV2Reserves {
+            token0: fields.address(),
+            token1: fields.address(),
+            reserve0: fields.uint(),
+            reserve1: fields.uint(),
+            block_timestamp_last: fields.uint().as_u32(),
+            k_last: fields.uint(),
+        })
+    }
+
+    /// Reads the price, tick and oracle state of a Uniswap V3 pool at a block.
+    pub async fn get_uniswap_v3_slot0<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        pool: T,
+        block: Option<BlockId>,
+    ) -> Result<Slot0, RethMiddlewareError<M>> {
+        let value =
+            self.read_storage_variable(pool, &uniswap_v3_pool_layout(), "slot0", block).await?;
+
+        let mut fields = Fields::new(vec![value]).tuple();
+        Ok(Slot0 {
+            sqrt_price_x96: fields.uint(),
+            tick: fields.int().as_i32(),
+            observation_index: fields.uint().as_u32() as u16,
+            observation_cardinality: fields.uint().as_u32() as u16,
+            observation_cardinality_next: fields.uint().as_u32() as u16,
+            fee_protocol: fields.uint().as_u32() as u8,
+            unlocked: fields.bool(),
+        })
+    }
+
+    /// Reads the liquidity in range of a Uniswap V3 pool at a block.
+    pub async fn get_uniswap_v3_liquidity<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        pool: T,
+        block: Option<BlockId>,
+    ) -> Result<u128, RethMiddlewareError<M>> {
+        let value =
+            self.read_storage_variable(pool, &uniswap_v3_pool_layout(), "liquidity", block).await?;
+        Ok(Fields::new(vec![value]).uint().as_u128())
+    }
+
+    /// Reads a word of the tick bitmap of a Uniswap V3 pool at a block. Bit `i` of word `w` is
+    /// set if tick `(w * 256 + i) * tick_spacing` is initialized.
+    pub async fn get_uniswap_v3_tick_bitmap<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        pool: T,
+        word: i16,
+        block: Option<BlockId>,
+    ) -> Result<U256, RethMiddlewareError<M>> {
+        let path = format!("tickBitmap[{word}]");
+        let value =
+            self.read_storage_variable(pool, &uniswap_v3_pool_layout(), &path, block).await?;
+        Ok(Fields::new(vec![value]).uint())
+    }
+
+    /// Reads a tick of a Uniswap V3 pool at a block.
+    pub async fn get_uniswap_v3_tick<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        pool: T,
+        tick: i32,
+        block: Option<BlockId>,
+    ) -> Result<TickInfo, RethMiddlewareError<M>> {
+        let path = format!("ticks[{tick}]");
+        let value =
+            self.read_storage_variable(pool, &uniswap_v3_pool_layout(), &path, block).await?;
+
+        let mut fields = Fields::new(vec![value]).tuple();
+        Ok(TickInfo {
+            liquidity_gross: fields.uint().as_u128(),
+            liquidity_net: fields.int().as_i128(),
+            fee_growth_outside0_x128: fields.uint(),
+            fee_growth_outside1_x128: fields.uint(),
+            tick_cumulative_outside: fields.int().as_i64(),
+            seconds_per_liquidity_outside_x128: fields.uint(),
+            seconds_outside: fields.uint().as_u32(),
+            initialized: fields.bool(),
+        })
+    }
+}
@@ -0,0 +1,154 @@
+use crate::RethMiddleware;
+use ethers::providers::Middleware;
+use serde::{Deserialize, Serialize};
+
+// Std
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// Which read requests are mirrored to the inner provider to compare its results against the
+/// database's.
+#[derive(Debug, Clone, Default)]
+pub struct DifferentialConfig {
+    /// Fraction of the requests mirrored, from 0 to 1.
+    pub sample_rate: f64,
+    /// The [Middleware] methods mirrored, every method with a fallback if empty.
+    pub methods: HashSet<&'static str>,
+}
+
+impl DifferentialConfig {
+    /// Mirrors `sample_rate` of the requests of every method.
+    pub fn new(sample_rate: f64) -> Self {
+        Self { sample_rate, methods: HashSet::new() }
+    }
+
+    /// Mirrors the requests of `method`, e.g. `"get_logs"`, no longer every method.
+    pub fn with_method(mut self, method: &'static str) -> Self {
+        self.methods.insert(method);
+        self
+    }
+}
+
+/// How the mirrored requests compared so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DifferentialStats {
+    /// Requests whose results matched.
+    pub matched: u64,
+    /// Requests whose results differed.
+    pub mismatched: u64,
+    /// Requests the inner provider failed while the database served them.
+    pub remote_errors: u64,
+}
+
+/// The differential mode of a middleware, shared by its clones.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Differential {
+    config: Option<Arc<DifferentialConfig>>,
+    requests: Arc<AtomicU64>,
+    matched: Arc<AtomicU64>,
+    mismatched: Arc<AtomicU64>,
+    remote_errors: Arc<AtomicU64>,
+}
+
+impl Differential {
+    fn new(config: DifferentialConfig) -> Self {
+        Self { config: Some(Arc::new(config)), ..Default::default() }
+    }
+
+    /// Returns true if this request of `method` is mirrored. Requests are picked evenly, one
+    /// every `1 / sample_rate`.
+    pub(crate) fn sample(&self, method: &str) -> bool {
+        let Some(config) = &self.config else { return false };
+        if config.sample_rate <= 0.0 ||
+            (!config.methods.is_empty() && !config.methods.contains(method))
+        {
+            return false
+        }
+        let rate = config.sample_rate.min(1.0);
+        let n = self.requests.fetch_add(1, Ordering::Relaxed) as f64;
+        ((n + 1.0) * rate).floor() > (n * rate).floor()
+    }
+
+    /// Compares the results of a mirrored request, reporting a mismatch as a warning and a
+    /// metric. Requests the database failed are not compared.
+    pub(crate) fn compare<T: Serialize, L, R: std::fmt::Display>(
+        &self,
+        method: &'static str,
+        local: &Result<T, L>,
+        remote: &Result<T, R>,
+    ) {
+        let Ok(local) = local else { return };
+        let outcome = match remote {
+            Err(err) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(
+                    target: "ethers_reth::differential",
+                    method,
+                    %err,
+                    "inner provider failed a mirrored request"
+                );
+                #[cfg(not(feature = "tracing"))]
+                let _ = err;
+                self.remote_errors.fetch_add(1, Ordering::Relaxed);
+                "remote_error"
+            }
+            Ok(remote) => {
+                let local = serde_json::to_value(local).ok();
+                let remote = serde_json::to_value(remote).ok();
+                if local == remote {
+                    self.matched.fetch_add(1, Ordering::Relaxed);
+                    "match"
+                } else {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(
+                        target: "ethers_reth::differential",
+                        method,
+                        local = ?local,
+                        remote = ?remote,
+                        "local result differs from the inner provider"
+                    );
+                    self.mismatched.fetch_add(1, Ordering::Relaxed);
+                    "mismatch"
+                }
+            }
+        };
+
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_differential(method, outcome);
+        #[cfg(not(feature = "metrics"))]
+        let _ = (method, outcome);
+    }
+
+    fn stats(&self) -> DifferentialStats {
+        DifferentialStats {
+            matched: self.matched.load(Ordering::Relaxed),
+            mismatched: self.mismatched.load(Ordering::Relaxed),
+            remote_errors: self.remote_errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Mirrors a fraction of the read requests served from the database to the inner provider,
+    /// comparing the results. Mismatches are logged as warnings and counted, while the caller
+    /// still gets the database's result, so the local path can be vetted on live traffic before
+    /// relying on it. Mirrored requests wait for both.
+    pub fn with_differential(mut self, config: DifferentialConfig) -> Self {
+        self.differential = Differential::new(config);
+        self
+    }
+
+    /// Returns how the mirrored requests compared so far.
+    pub fn differential_stats(&self) -> DifferentialStats {
+        self.differential.stats()
+    }
+}
@@ -0,0 +1,168 @@
+//! ERC-20 metadata, balances and allowances read in batches through the native multicall path.
+use crate::{execution::multicall::Call3, RethMiddleware, RethMiddlewareError};
+use ethers::{
+    abi::{self, ParamType, Token},
+    providers::Middleware,
+    types::{Address, BlockId, Bytes, U256},
+};
+use hex_literal::hex;
+use serde::{Deserialize, Serialize};
+
+const NAME_SELECTOR: [u8; 4] = hex!("06fdde03");
+const SYMBOL_SELECTOR: [u8; 4] = hex!("95d89b41");
+const DECIMALS_SELECTOR: [u8; 4] = hex!("313ce567");
+const BALANCE_OF_SELECTOR: [u8; 4] = hex!("70a08231");
+const ALLOWANCE_SELECTOR: [u8; 4] = hex!("dd62ed3e");
+
+/// The metadata of an ERC-20 token, each field `None` if the token does not implement it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenMetadata {
+    pub token: Address,
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: Option<u8>,
+}
+
+/// The balance of a holder of a token, `None` if `balanceOf` failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenBalance {
+    pub token: Address,
+    pub holder: Address,
+    pub balance: Option<U256>,
+}
+
+/// What a spender may transfer of an owner's tokens, `None` if `allowance` failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenAllowance {
+    pub token: Address,
+    pub owner: Address,
+    pub spender: Address,
+    pub allowance: Option<U256>,
+}
+
+fn call(target: Address, selector: [u8; 4], args: &[Token]) -> Call3 {
+    Call3 {
+        target,
+        allow_failure: true,
+        call_data: Bytes::from([&selector[..], &abi::encode(args)].concat()),
+    }
+}
+
+/// Decodes a returned `uint256`, `None` if the call failed or returned something else.
+fn decode_uint(return_data: Option<&[u8]>) -> Option<U256> {
+    match abi::decode(&[ParamType::Uint(256)], return_data?).ok()?.pop()? {
+        Token::Uint(value) => Some(value),
+        _ => None,
+    }
+}
+
+/// Decodes a returned `string`, or the `bytes32` early tokens like MKR return, trailing zeros
+/// trimmed.
+fn decode_string(return_data: Option<&[u8]>) -> Option<String> {
+    let return_data = return_data?;
+    if let Ok(Some(Token::String(value))) =
+        abi::decode(&[ParamType::String], return_data).map(|mut tokens| tokens.pop())
+    {
+        return Some(value)
+    }
+    if return_data.len() != 32 {
+        return None
+    }
+    let end = return_data.iter().rposition(|byte| *byte != 0).map_or(0, |end| end + 1);
+    String::from_utf8(return_data[..end].to_vec()).ok()
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the name, symbol and decimals of ERC-20 tokens at `block`, the latest if `None`,
+    /// all read in one batch on the same state.
+    pub async fn get_token_metadata(
+        &self,
+        tokens: &[Address],
+        block: Option<BlockId>,
+    ) -> Result<Vec<TokenMetadata>, RethMiddlewareError<M>> {
+        let calls: Vec<_> = tokens
+            .iter()
+            .flat_map(|token| {
+                [NAME_SELECTOR, SYMBOL_SELECTOR, DECIMALS_SELECTOR]
+                    .map(|selector| call(*token, selector, &[]))
+            })
+            .collect();
+        let results = self.aggregate3(&calls, block).await?;
+        let return_data: Vec<_> = results
+            .iter()
+            .map(|result| result.success.then_some(result.return_data.as_ref()))
+            .collect();
+
+        Ok(tokens
+            .iter()
+            .zip(return_data.chunks(3))
+            .map(|(token, return_data)| TokenMetadata {
+                token: *token,
+                name: decode_string(return_data[0]),
+                symbol: decode_string(return_data[1]),
+                decimals: decode_uint(return_data[2])
+                    .filter(|decimals| *decimals <= U256::from(u8::MAX))
+                    .map(|decimals| decimals.as_u32() as u8),
+            })
+            .collect())
+    }
+
+    /// Returns the balances of `(token, holder)` pairs at `block`, the latest if `None`, all
+    /// read in one batch on the same state.
+    pub async fn get_token_balances(
+        &self,
+        pairs: &[(Address, Address)],
+        block: Option<BlockId>,
+    ) -> Result<Vec<TokenBalance>, RethMiddlewareError<M>> {
+        let calls: Vec<_> = pairs
+            .iter()
+            .map(|(token, holder)| call(*token, BALANCE_OF_SELECTOR, &[Token::Address(*holder)]))
+            .collect();
+        let results = self.aggregate3(&calls, block).await?;
+        Ok(pairs
+            .iter()
+            .zip(results)
+            .map(|((token, holder), result)| TokenBalance {
+                token: *token,
+                holder: *holder,
+                balance: decode_uint(result.success.then_some(result.return_data.as_ref())),
+            })
+            .collect())
+    }
+
+    /// Returns the allowances of `(token, owner, spender)` triples at `block`, the latest if
+    /// `None`, all read in one batch on the same state.
+    pub async fn get_token_allowances(
+        &self,
+        triples: &[(Address, Address, Address)],
+        block: Option<BlockId>,
+    ) -> Result<Vec<TokenAllowance>, RethMiddlewareError<M>> {
+        let calls: Vec<_> = triples
+            .iter()
+            .map(|(token, owner, spender)| {
+                call(
+                    *token,
+                    ALLOWANCE_SELECTOR,
+                    &[Token::Address(*owner), Token::Address(*spender)],
+                )
+            })
+            .collect();
+        let results = self.aggregate3(&calls, block).await?;
+        Ok(triples
+            .iter()
+            .zip(results)
+            .map(|((token, owner, spender), result)| TokenAllowance {
+                token: *token,
+                owner: *owner,
+                spender: *spender,
+                allowance: decode_uint(result.success.then_some(result.return_data.as_ref())),
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,534 @@
+//! ERC-4337 user operations: decoding the `handleOps` bundles of EntryPoint v0.6 and v0.7, and
+//! simulating an operation against local state.
+use crate::{
+    execution::{call_many::StateContext, call_tx_env},
+    instrument::{db_read, instrument, record_block},
+    type_conversions::ToEthers,
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    abi::{self, ParamType, Token},
+    providers::Middleware,
+    types::{
+        Address as EthersAddress, BlockId as EthersBlockId, BlockNumber as EthersBlockNumber,
+        Bytes as EthersBytes, Log as EthersLog, TransactionRequest, H160, H256 as EthersH256,
+        U256 as EthersU256,
+    },
+    utils::keccak256,
+};
+use hex_literal::hex;
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_provider::TransactionsProvider;
+use reth_revm::{
+    primitives::{AccountInfo, ExecutionResult, Log, B160, B256, U256},
+    EVM,
+};
+use reth_rpc::eth::error::EthApiError;
+
+// Std
+use std::ops::RangeInclusive;
+
+/// Most blocks a single user operation scan may span.
+pub const MAX_USER_OPERATION_SCAN_BLOCKS: u64 = 10_000;
+
+/// Address of the v0.6 EntryPoint, the same on every chain it is deployed on.
+pub const ENTRY_POINT_V06: EthersAddress = H160(hex!("5FF137D4b0FDCD49DcA30c7CF57E578a026d2789"));
+
+/// Address of the v0.7 EntryPoint, the same on every chain it is deployed on.
+pub const ENTRY_POINT_V07: EthersAddress = H160(hex!("0000000071727De22E5E9d8BAf0edAc6f37da032"));
+
+/// Account the bundles of [RethMiddleware::simulate_user_operation] are sent from.
+const SIMULATION_BUNDLER: EthersAddress = H160(hex!("0000000000000000000000000000000000004337"));
+
+/// Version of the EntryPoint a bundle is sent to, which sets how operations are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EntryPointVersion {
+    /// `UserOperation`s with every gas field in a word.
+    V06,
+    /// `PackedUserOperation`s, with the gas limits and fees packed in pairs.
+    V07,
+}
+
+/// A user operation, in the v0.6 layout. v0.7 operations are unpacked into it, their
+/// `paymasterAndData` keeping the paymaster's gas limits.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperation {
+    pub sender: EthersAddress,
+    pub nonce: EthersU256,
+    /// The factory and its calldata, for operations deploying their account.
+    pub init_code: EthersBytes,
+    pub call_data: EthersBytes,
+    pub call_gas_limit: EthersU256,
+    pub verification_gas_limit: EthersU256,
+    pub pre_verification_gas: EthersU256,
+    pub max_fee_per_gas: EthersU256,
+    pub max_priority_fee_per_gas: EthersU256,
+    pub paymaster_and_data: EthersBytes,
+    pub signature: EthersBytes,
+}
+
+/// A user operation found in a mined `handleOps` bundle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundledUserOperation {
+    pub block_number: u64,
+    pub transaction_hash: EthersH256,
+    pub entry_point: EthersAddress,
+    pub version: EntryPointVersion,
+    /// Account the bundler collects the fees to.
+    pub beneficiary: EthersAddress,
+    /// Position of the operation in the bundle.
+    pub index: usize,
+    pub operation: UserOperation,
+}
+
+/// What executing a user operation in a bundle of its own did.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperationSimulation {
+    /// Why the validation phase rejected the operation, as the EntryPoint's `FailedOp` reports
+    /// it. The execution phase does not run then.
+    pub validation_error: Option<String>,
+    pub user_op_hash: Option<EthersH256>,
+    /// Whether the call of the account succeeded.
+    pub success: Option<bool>,
+    /// Revert data of a failed call of the account.
+    pub revert_reason: Option<EthersBytes>,
+    /// What the operation paid, in wei.
+    pub actual_gas_cost: Option<EthersU256>,
+    pub actual_gas_used: Option<EthersU256>,
+    /// Gas used by the whole bundle transaction.
+    pub bundle_gas_used: u64,
+    pub logs: Vec<EthersLog>,
+}
+
+fn bytes32(high: EthersU256, low: EthersU256) -> Vec<u8> {
+    let (mut high_bytes, mut low_bytes) = ([0u8; 32], [0u8; 32]);
+    high.to_big_endian(&mut high_bytes);
+    low.to_big_endian(&mut low_bytes);
+    [&high_bytes[16..], &low_bytes[16..]].concat()
+}
+
+fn unpack_bytes32(word: &[u8]) -> (EthersU256, EthersU256) {
+    (EthersU256::from_big_endian(&word[..16]), EthersU256::from_big_endian(&word[16..]))
+}
+
+impl EntryPointVersion {
+    fn operation_type(self) -> ParamType {
+        let gas_fields = match self {
+            EntryPointVersion::V06 => vec![ParamType::Uint(256); 5],
+            EntryPointVersion::V07 => {
+                vec![ParamType::FixedBytes(32), ParamType::Uint(256), ParamType::FixedBytes(32)]
+            }
+        };
+        let mut fields = vec![ParamType::Address, ParamType::Uint(256)];
+        fields.extend([ParamType::Bytes, ParamType::Bytes]);
+        fields.extend(gas_fields);
+        fields.extend([ParamType::Bytes, ParamType::Bytes]);
+        ParamType::Tuple(fields)
+    }
+
+    fn handle_ops_params(self) -> [ParamType; 2] {
+        [ParamType::Array(Box::new(self.operation_type())), ParamType::Address]
+    }
+
+    fn handle_ops_selector(self) -> [u8; 4] {
+        abi::short_signature("handleOps", &self.handle_ops_params())
+    }
+
+    fn encode(self, op: &UserOperation) -> Token {
+        let gas_fields = match self {
+            EntryPointVersion::V06 => vec![
+                Token::Uint(op.call_gas_limit),
+                Token::Uint(op.verification_gas_limit),
+                Token::Uint(op.pre_verification_gas),
+                Token::Uint(op.max_fee_per_gas),
+                Token::Uint(op.max_priority_fee_per_gas),
+            ],
+            EntryPointVersion::V07 => vec![
+                Token::FixedBytes(bytes32(op.verification_gas_limit, op.call_gas_limit)),
+                Token::Uint(op.pre_verification_gas),
+                Token::FixedBytes(bytes32(op.max_priority_fee_per_gas, op.max_fee_per_gas)),
+            ],
+        };
+        let mut fields = vec![
+            Token::Address(op.sender),
+            Token::Uint(op.nonce),
+            Token::Bytes(op.init_code.to_vec()),
+            Token::Bytes(op.call_data.to_vec()),
+        ];
+        fields.extend(gas_fields);
+        fields.extend([
+            Token::Bytes(op.paymaster_and_data.to_vec()),
+            Token::Bytes(op.signature.to_vec()),
+        ]);
+        Token::Tuple(fields)
+    }
+
+    fn decode(self, token: Token) -> Option<UserOperation> {
+        let fields = token.into_tuple()?;
+        let bytes = |index: usize| fields[index].clone().into_bytes().map(EthersBytes::from);
+        let uint = |index: usize| fields[index].clone().into_uint();
+        let mut op = UserOperation {
+            sender: fields.first()?.clone().into_address()?,
+            nonce: uint(1)?,
+            init_code: bytes(2)?,
+            call_data: bytes(3)?,
+            ..Default::default()
+        };
+        match self {
+            EntryPointVersion::V06 if fields.len() == 11 => {
+                op.call_gas_limit = uint(4)?;
+                op.verification_gas_limit = uint(5)?;
+                op.pre_verification_gas = uint(6)?;
+                op.max_fee_per_gas = uint(7)?;
+                op.max_priority_fee_per_gas = uint(8)?;
+                op.paymaster_and_data = bytes(9)?;
+                op.signature = bytes(10)?;
+            }
+            EntryPointVersion::V07 if fields.len() == 9 => {
+                let gas_limits = fields[4].clone().into_fixed_bytes()?;
+                (op.verification_gas_limit, op.call_gas_limit) = unpack_bytes32(&gas_limits);
+                op.pre_verification_gas = uint(5)?;
+                let gas_fees = fields[6].clone().into_fixed_bytes()?;
+                (op.max_priority_fee_per_gas, op.max_fee_per_gas) = unpack_bytes32(&gas_fees);
+                op.paymaster_and_data = bytes(7)?;
+                op.signature = bytes(8)?;
+            }
+            _ => return None,
+        }
+        Some(op)
+    }
+}
+
+/// Decodes the operations and the beneficiary of `handleOps` calldata, of either EntryPoint
+/// version, none if the calldata is not a `handleOps` call.
+pub fn decode_handle_ops(
+    data: &[u8],
+) -> Option<(EntryPointVersion, Vec<UserOperation>, EthersAddress)> {
+    if data.len() < 4 {
+        return None
+    }
+    let (selector, args) = data.split_at(4);
+    let version = [EntryPointVersion::V06, EntryPointVersion::V07]
+        .into_iter()
+        .find(|version| version.handle_ops_selector() == selector)?;
+    let mut tokens = abi::decode(&version.handle_ops_params(), args).ok()?.into_iter();
+    let ops = tokens.next()?.into_array()?;
+    let beneficiary = tokens.next()?.into_address()?;
+    let ops = ops.into_iter().map(|op| version.decode(op)).collect::<Option<_>>()?;
+    Some((version, ops, beneficiary))
+}
+
+/// Encodes a `handleOps` call bundling `ops` for an EntryPoint of `version`.
+pub fn encode_handle_ops(
+    version: EntryPointVersion,
+    ops: &[UserOperation],
+    beneficiary: EthersAddress,
+) -> EthersBytes {
+    let ops = ops.iter().map(|op| version.encode(op)).collect();
+    let args = abi::encode(&[Token::Array(ops), Token::Address(beneficiary)]);
+    [&version.handle_ops_selector()[..], &args].concat().into()
+}
+
+fn topic(signature: &str) -> B256 {
+    B256::from(keccak256(signature))
+}
+
+/// Decodes the `FailedOp` and `FailedOpWithRevert` errors the EntryPoint reverts with when an
+/// operation fails validation.
+fn decode_failed_op(output: &[u8]) -> Option<String> {
+    if output.len() < 4 {
+        return None
+    }
+    let (selector, args) = output.split_at(4);
+    let failed_op = [ParamType::Uint(256), ParamType::String];
+    if selector == abi::short_signature("FailedOp", &failed_op) {
+        return abi::decode(&failed_op, args).ok()?.pop()?.into_string()
+    }
+    let with_revert = [ParamType::Uint(256), ParamType::String, ParamType::Bytes];
+    if selector == abi::short_signature("FailedOpWithRevert", &with_revert) {
+        let mut tokens = abi::decode(&with_revert, args).ok()?.into_iter().skip(1);
+        let reason = tokens.next()?.into_string()?;
+        let inner = tokens.next()?.into_bytes()?;
+        return Some(format!("{reason}: {}", EthersBytes::from(inner)))
+    }
+    None
+}
+
+/// Signature of `UserOperationEvent`, which the EntryPoint logs for every operation it executes.
+const USER_OPERATION_EVENT: &str =
+    "UserOperationEvent(bytes32,address,address,uint256,bool,uint256,uint256)";
+
+/// Signature of `UserOperationRevertReason`, logged when the call of an account reverts.
+const USER_OPERATION_REVERT_REASON: &str =
+    "UserOperationRevertReason(bytes32,address,uint256,bytes)";
+
+impl UserOperationSimulation {
+    fn new(result: ExecutionResult, sender: EthersAddress) -> Self {
+        let bundle_gas_used = result.gas_used();
+        let validation_error = match result {
+            ExecutionResult::Success { logs, .. } => {
+                return Self::from_logs(logs, sender, bundle_gas_used)
+            }
+            ExecutionResult::Revert { output, .. } => decode_failed_op(&output)
+                .unwrap_or_else(|| format!("handleOps reverted: {}", EthersBytes::from(output))),
+            ExecutionResult::Halt { reason, .. } => format!("handleOps halted: {reason:?}"),
+        };
+        Self { validation_error: Some(validation_error), bundle_gas_used, ..Default::default() }
+    }
+
+    fn from_logs(logs: Vec<Log>, sender: EthersAddress, bundle_gas_used: u64) -> Self {
+        let sender_topic = B256::from(EthersH256::from(sender).0);
+        let find = |signature: &str| {
+            logs.iter().find(|log| {
+                log.topics.first() == Some(&topic(signature)) &&
+                    log.topics.get(2) == Some(&sender_topic)
+            })
+        };
+
+        let mut simulation = Self { bundle_gas_used, ..Default::default() };
+        if let Some(log) = find(USER_OPERATION_EVENT) {
+            let fields =
+                [ParamType::Uint(256), ParamType::Bool, ParamType::Uint(256), ParamType::Uint(256)];
+            if let Ok(tokens) = abi::decode(&fields, &log.data) {
+                simulation.user_op_hash = Some(EthersH256(log.topics[1].0));
+                simulation.success = tokens[1].clone().into_bool();
+                simulation.actual_gas_cost = tokens[2].clone().into_uint();
+                simulation.actual_gas_used = tokens[3].clone().into_uint();
+            }
+        }
+        if let Some(log) = find(USER_OPERATION_REVERT_REASON) {
+            simulation.revert_reason =
+                abi::decode(&[ParamType::Uint(256), ParamType::Bytes], &log.data)
+                    .ok()
+                    .and_then(|mut tokens| tokens.pop()?.into_bytes())
+                    .map(EthersBytes::from);
+        }
+        simulation.logs = logs
+            .into_iter()
+            .map(|log| EthersLog {
+                address: EthersAddress::from(log.address.0),
+                topics: log.topics.iter().map(|topic| EthersH256(topic.0)).collect(),
+                data: log.data.into(),
+                ..Default::default()
+            })
+            .collect();
+        simulation
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the user operations bundled by the `handleOps` transactions of the canonical
+    /// blocks in `range`, of either EntryPoint version and at any address.
+    ///
+    /// Only bundles sent straight to an EntryPoint are found, not those a contract forwards.
+    pub async fn get_user_operations(
+        &self,
+        range: RangeInclusive<u64>,
+    ) -> Result<Vec<BundledUserOperation>, RethMiddlewareError<M>> {
+        instrument("get_user_operations", async {
+            record_block(&range);
+            let (first, last) = range.clone().into_inner();
+            if first > last || last - first >= MAX_USER_OPERATION_SCAN_BLOCKS {
+                return Err(EthApiError::InvalidParams(format!(
+                    "at most {MAX_USER_OPERATION_SCAN_BLOCKS} blocks can be scanned"
+                ))
+                .into())
+            }
+
+            let mut operations = vec![];
+            for number in range {
+                let transactions = db_read("transactions_by_block", || {
+                    self.reth_provider.transactions_by_block(number.into())
+                })?
+                .unwrap_or_default();
+                for tx in transactions {
+                    let Some(entry_point) = tx.to() else { continue };
+                    let Some((version, ops, beneficiary)) = decode_handle_ops(tx.input()) else {
+                        continue
+                    };
+                    let transaction_hash = tx.hash().into_ethers();
+                    operations.extend(ops.into_iter().enumerate().map(|(index, operation)| {
+                        BundledUserOperation {
+                            block_number: number,
+                            transaction_hash,
+                            entry_point: entry_point.into_ethers(),
+                            version,
+                            beneficiary,
+                            index,
+                            operation,
+                        }
+                    }));
+                }
+            }
+            Ok(operations)
+        })
+        .await
+    }
+
+    /// Executes `op` in a bundle of its own sent to `entry_point`, on the state of `block`, the
+    /// latest if `None`, and reports how its validation and execution went.
+    ///
+    /// The bundle pays the block's base fee from a funded bundler, as a real one would, but the
+    /// operation is not checked against the ERC-7562 validation rules bundlers enforce.
+    pub async fn simulate_user_operation(
+        &self,
+        entry_point: EthersAddress,
+        version: EntryPointVersion,
+        op: &UserOperation,
+        block: Option<EthersBlockId>,
+    ) -> Result<UserOperationSimulation, RethMiddlewareError<M>> {
+        instrument(
+            "simulate_user_operation",
+            self.with_heavy_permit(self.run_call(|| {
+                let block = block.unwrap_or(EthersBlockNumber::Latest.into());
+                let (mut env, mut db) =
+                    self.state_at_transaction(StateContext { block, transaction_index: None })?;
+                db.insert_account_info(
+                    B160::from(SIMULATION_BUNDLER.0),
+                    AccountInfo { balance: U256::from(u128::MAX), ..Default::default() },
+                );
+
+                let tx = TransactionRequest::new()
+                    .from(SIMULATION_BUNDLER)
+                    .to(entry_point)
+                    .data(encode_handle_ops(version, std::slice::from_ref(op), SIMULATION_BUNDLER))
+                    .gas_price(EthersU256::from(env.block.basefee))
+                    .into();
+                env.tx = call_tx_env(&tx, self.gas_cap(env.block.gas_limit.to::<u64>()));
+
+                let mut evm = EVM::with_env(env);
+                evm.database(db);
+                let result =
+                    self.precompile_overrides.transact(&mut evm).map_err(EthApiError::from)?.result;
+                Ok(UserOperationSimulation::new(result, op.sender))
+            })),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reth_revm::primitives::Bytes;
+
+    fn operation() -> UserOperation {
+        UserOperation {
+            sender: EthersAddress::from_low_u64_be(0xa11ce),
+            nonce: 3.into(),
+            init_code: vec![0xfa, 0xc7].into(),
+            call_data: vec![0xb6, 0x1d, 0x27, 0xf6].into(),
+            call_gas_limit: 100_000.into(),
+            verification_gas_limit: 150_000.into(),
+            pre_verification_gas: 21_000.into(),
+            max_fee_per_gas: 30_000_000_000u64.into(),
+            max_priority_fee_per_gas: 1_000_000_000.into(),
+            paymaster_and_data: EthersBytes::default(),
+            signature: vec![0x51; 65].into(),
+        }
+    }
+
+    fn address_topic(address: EthersAddress) -> B256 {
+        B256::from(EthersH256::from(address).0)
+    }
+
+    #[test]
+    fn handle_ops_selectors_match_the_entry_points() {
+        assert_eq!(EntryPointVersion::V06.handle_ops_selector(), hex!("1fad948c"));
+        assert_eq!(EntryPointVersion::V07.handle_ops_selector(), hex!("765e827f"));
+    }
+
+    #[test]
+    fn handle_ops_round_trip() {
+        let beneficiary = EthersAddress::from_low_u64_be(0xb0b);
+        let ops = vec![operation(), UserOperation { nonce: 4.into(), ..operation() }];
+        for version in [EntryPointVersion::V06, EntryPointVersion::V07] {
+            let data = encode_handle_ops(version, &ops, beneficiary);
+            assert_eq!(decode_handle_ops(&data), Some((version, ops.clone(), beneficiary)));
+        }
+    }
+
+    #[test]
+    fn v07_operations_pack_gas_limits_and_fees_in_pairs() {
+        let data = encode_handle_ops(EntryPointVersion::V07, &[operation()], EthersAddress::zero());
+        let tokens = abi::decode(&EntryPointVersion::V07.handle_ops_params(), &data[4..]).unwrap();
+        let fields = tokens[0].clone().into_array().unwrap()[0].clone().into_tuple().unwrap();
+
+        let gas_limits = fields[4].clone().into_fixed_bytes().unwrap();
+        assert_eq!(EthersU256::from_big_endian(&gas_limits[..16]), 150_000.into());
+        assert_eq!(EthersU256::from_big_endian(&gas_limits[16..]), 100_000.into());
+        let gas_fees = fields[6].clone().into_fixed_bytes().unwrap();
+        assert_eq!(EthersU256::from_big_endian(&gas_fees[..16]), 1_000_000_000.into());
+    }
+
+    #[test]
+    fn other_calldata_is_not_decoded() {
+        assert_eq!(decode_handle_ops(&[]), None);
+        assert_eq!(decode_handle_ops(&hex!("a9059cbb")), None);
+        // the right selector with arguments that do not decode
+        assert_eq!(decode_handle_ops(&hex!("1fad948c00")), None);
+    }
+
+    #[test]
+    fn failed_ops_are_decoded() {
+        let args = [Token::Uint(0.into()), Token::String("AA21 didn't pay prefund".to_string())];
+        let output = [&hex!("220266b6")[..], &abi::encode(&args)].concat();
+        assert_eq!(decode_failed_op(&output).as_deref(), Some("AA21 didn't pay prefund"));
+
+        let args = [
+            Token::Uint(0.into()),
+            Token::String("AA23 reverted".to_string()),
+            Token::Bytes(vec![0xde, 0xad]),
+        ];
+        let output = [&hex!("65c8fd4d")[..], &abi::encode(&args)].concat();
+        assert_eq!(decode_failed_op(&output).as_deref(), Some("AA23 reverted: 0xdead"));
+        assert_eq!(decode_failed_op(&hex!("08c379a0")), None);
+    }
+
+    #[test]
+    fn simulations_read_the_events_of_the_sender() {
+        let sender = operation().sender;
+        let event = |sender: EthersAddress, gas_used: u64| Log {
+            address: B160::from(ENTRY_POINT_V06.0),
+            topics: vec![
+                topic(USER_OPERATION_EVENT),
+                B256::repeat_byte(0x0b),
+                address_topic(sender),
+                address_topic(EthersAddress::zero()),
+            ],
+            data: Bytes::from(abi::encode(&[
+                Token::Uint(3.into()),
+                Token::Bool(false),
+                Token::Uint(1_000.into()),
+                Token::Uint(gas_used.into()),
+            ])),
+        };
+        let revert_reason = Log {
+            address: B160::from(ENTRY_POINT_V06.0),
+            topics: vec![
+                topic(USER_OPERATION_REVERT_REASON),
+                B256::repeat_byte(0x0b),
+                address_topic(sender),
+            ],
+            data: Bytes::from(abi::encode(&[Token::Uint(3.into()), Token::Bytes(vec![0x01])])),
+        };
+        let logs = vec![event(EthersAddress::zero(), 1), revert_reason, event(sender, 50_000)];
+        let simulation = UserOperationSimulation::from_logs(logs, sender, 80_000);
+
+        assert_eq!(simulation.user_op_hash, Some(EthersH256::repeat_byte(0x0b)));
+        assert_eq!(simulation.success, Some(false));
+        assert_eq!(simulation.actual_gas_cost, Some(1_000.into()));
+        assert_eq!(simulation.actual_gas_used, Some(50_000.into()));
+        assert_eq!(simulation.revert_reason, Some(vec![0x01].into()));
+        assert_eq!((simulation.bundle_gas_used, simulation.logs.len()), (80_000, 3));
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{
+    fanout::Lagged, instrument::db_read, logs::split_block_range, RethMiddleware,
+    RethMiddlewareError,
+};
+use ethers::{
+    abi::{self, RawLog},
+    contract::{EthEvent, EthLogDecode},
+    providers::Middleware,
+    types::{Address, Filter, Log},
+};
+use futures::{stream, Stream, StreamExt};
+
+// Reth
+use reth_provider::BlockNumReader;
+
+/// Returns a filter on the logs `E` is emitted as by the contract at `address`.
+pub fn event_filter<E: EthEvent>(address: Address) -> Filter {
+    let filter = Filter::new().address(address);
+    if E::is_anonymous() {
+        filter
+    } else {
+        filter.topic0(E::signature())
+    }
+}
+
+/// Decodes a log as `E`, which may be a single event or the event enum of a contract ABI.
+pub fn decode_event<E: EthLogDecode>(log: &Log) -> Result<E, abi::Error> {
+    E::decode_log(&RawLog::from(log))
+}
+
+/// Decodes a log of a subscription as `E`, passing on the gaps of a subscriber that fell behind.
+fn decode_streamed<E: EthLogDecode, M: Middleware>(
+    log: Result<Log, Lagged>,
+) -> Result<(E, Log), RethMiddlewareError<M>> {
+    let log = log?;
+    Ok((decode_event(&log)?, log))
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the logs matching the filter decoded as `E`, along with the logs themselves.
+    ///
+    /// Logs are read from the database like `eth_getLogs`, the way `Event::query` reads them
+    /// through this middleware.
+    pub async fn query_events<E: EthLogDecode>(
+        &self,
+        filter: &Filter,
+    ) -> Result<Vec<(E, Log)>, RethMiddlewareError<M>> {
+        self.get_logs(filter).await?.into_iter().map(|log| Ok((decode_event(&log)?, log))).collect()
+    }
+
+    /// Subscribes to the logs of newly committed blocks matching the filter, decoded as `E`.
+    ///
+    /// Unlike `Event::stream`, which polls a filter installed on the inner provider, events are
+    /// pushed from canonical state notifications. Events of reverted blocks are sent again with
+    /// `removed` set on their log. Watchers of the same filter share their logs, see
+    /// [Self::watch_logs_shared].
+    pub fn watch_events<E: EthLogDecode>(
+        &self,
+        filter: Filter,
+    ) -> impl Stream<Item = Result<(E, Log), RethMiddlewareError<M>>> {
+        self.watch_logs_shared(filter).into_stream().map(decode_streamed)
+    }
+
+    /// Subscribes to the `E` events emitted by the contract at `address`, see
+    /// [Self::watch_events].
+    pub fn watch_contract_events<E: EthEvent>(
+        &self,
+        address: Address,
+    ) -> impl Stream<Item = Result<(E, Log), RethMiddlewareError<M>>> {
+        self.watch_events(event_filter::<E>(address))
+    }
+
+    /// Streams the events matching the filter from `from_block` on: those up to the head, read
+    /// from the database in ranges within the log limits, then those of newly committed blocks,
+    /// without gap or duplicate between the two. Events of reverted blocks are sent again with
+    /// `removed` set on their log.
+    ///
+    /// The filter's block range is ignored. With abigen bindings, pass the `filter` of
+    /// `contract.events()` to decode the contract's event enum.
+    pub fn stream_events<E: EthLogDecode + 'static>(
+        &self,
+        filter: Filter,
+        from_block: u64,
+    ) -> impl Stream<Item = Result<(E, Log), RethMiddlewareError<M>>> + '_ {
+        // subscribed first, so blocks committed during the backfill are not missed
+        let mut subscription = self.watch_logs(filter.clone());
+        let backfill = async move {
+            let head = db_read("best_block_number", || self.reth_provider.best_block_number())?;
+            let max_blocks = self.log_limits().max_block_range.unwrap_or(u64::MAX);
+            let mut logs = vec![];
+            if from_block <= head {
+                for range in split_block_range(from_block..=head, max_blocks) {
+                    let chunk = filter.clone().from_block(*range.start()).to_block(*range.end());
+                    logs.extend(self.get_logs(&chunk).await?);
+                }
+            }
+            subscription.mark_delivered(&logs);
+            Ok::<_, RethMiddlewareError<M>>((logs, subscription))
+        };
+
+        stream::once(backfill).flat_map(|backfill| match backfill {
+            Ok((logs, subscription)) => stream::iter(logs.into_iter().map(Ok))
+                .chain(subscription.into_stream())
+                .map(decode_streamed)
+                .left_stream(),
+            Err(err) => stream::iter([Err(err)]).right_stream(),
+        })
+    }
+}
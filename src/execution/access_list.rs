@@ -0,0 +1,136 @@
+use crate::{
+    instrument::instrument, limits::CallDeadline, type_conversions::ToReth, RethMiddleware,
+    RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{
+        transaction::eip2930::{AccessList, AccessListItem},
+        Address as EthersAddress, H256 as EthersH256,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_revm::{Database, EVM};
+use reth_rpc::eth::error::EthApiError;
+
+// Std
+use std::collections::BTreeMap;
+
+/// The state of an account a transaction read and wrote.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountAccesses {
+    pub address: EthersAddress,
+    /// Every storage slot loaded, written ones included, in order.
+    pub storage_reads: Vec<EthersH256>,
+    /// Storage slots whose value changed, with their value after the transaction.
+    pub storage_writes: BTreeMap<EthersH256, EthersH256>,
+    pub balance_written: bool,
+    pub nonce_written: bool,
+    /// Whether the transaction created the account or gave it code.
+    pub code_written: bool,
+    pub self_destructed: bool,
+}
+
+impl AccountAccesses {
+    /// Whether the transaction changed anything in the account.
+    pub fn is_written(&self) -> bool {
+        !self.storage_writes.is_empty() ||
+            self.balance_written ||
+            self.nonce_written ||
+            self.code_written ||
+            self.self_destructed
+    }
+}
+
+/// Every account and storage slot a mined transaction touched.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionAccessList {
+    pub transaction_hash: EthersH256,
+    /// The accounts loaded, the sender, recipient and fee recipient included, by address.
+    pub accounts: Vec<AccountAccesses>,
+}
+
+impl TransactionAccessList {
+    /// Returns the accounts read and their slots as an EIP-2930 access list.
+    pub fn to_access_list(&self) -> AccessList {
+        AccessList(
+            self.accounts
+                .iter()
+                .map(|account| AccessListItem {
+                    address: account.address,
+                    storage_keys: account.storage_reads.clone(),
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns the accounts the transaction changed.
+    pub fn writes(&self) -> impl Iterator<Item = &AccountAccesses> {
+        self.accounts.iter().filter(|account| account.is_written())
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Replays a mined transaction on top of the state it ran on and returns everything it read
+    /// and wrote. Reads of calls that reverted are included, their writes are not.
+    ///
+    /// Unlike `eth_createAccessList`, which is estimated for a transaction yet to be sent, this
+    /// is exactly what the transaction touched in its block. The replay runs under the call
+    /// timeout, holding a heavy request slot.
+    pub async fn get_transaction_access_list(
+        &self,
+        tx_hash: EthersH256,
+    ) -> Result<TransactionAccessList, RethMiddlewareError<M>> {
+        instrument(
+            "get_transaction_access_list",
+            self.with_heavy_permit(self.run_call(|| {
+                let (env, db) = self.transaction_replay(tx_hash.into_reth())?;
+                let mut evm = EVM::with_env(env);
+                evm.database(db);
+                let state = CallDeadline::transact(&mut evm).map_err(EthApiError::from)?.state;
+                // not committed, the database still holds the state before the transaction
+                let db = evm.db.as_mut().expect("database set above");
+
+                let mut accounts = Vec::with_capacity(state.len());
+                for (address, account) in state {
+                    let before = Database::basic(db, address)?.unwrap_or_default();
+                    let mut storage_reads: Vec<_> = account.storage.keys().copied().collect();
+                    storage_reads.sort_unstable();
+                    accounts.push(AccountAccesses {
+                        address: EthersAddress::from(address.0),
+                        storage_reads: storage_reads
+                            .into_iter()
+                            .map(|slot| EthersH256(slot.to_be_bytes()))
+                            .collect(),
+                        storage_writes: account
+                            .storage
+                            .iter()
+                            .filter(|(_, value)| value.is_changed())
+                            .map(|(slot, value)| {
+                                (
+                                    EthersH256(slot.to_be_bytes()),
+                                    EthersH256(value.present_value.to_be_bytes()),
+                                )
+                            })
+                            .collect(),
+                        balance_written: account.info.balance != before.balance,
+                        nonce_written: account.info.nonce != before.nonce,
+                        code_written: account.is_created() ||
+                            account.info.code_hash != before.code_hash,
+                        self_destructed: account.is_selfdestructed(),
+                    });
+                }
+                accounts.sort_unstable_by_key(|account| account.address);
+                Ok(TransactionAccessList { transaction_hash: tx_hash, accounts })
+            })),
+        )
+        .await
+    }
+}
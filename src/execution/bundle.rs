@@ -0,0 +1,170 @@
+use crate::{
+    database::RethDatabaseRef,
+    execution::{coinbase::priority_fee, SLOT_DURATION},
+    instrument::instrument,
+    type_conversions::ToEthers,
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{
+        Address as EthersAddress, Bytes as EthersBytes, H256 as EthersH256, U256 as EthersU256,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_primitives::{BlockId, BlockNumberOrTag, TransactionSigned};
+use reth_revm::{
+    db::CacheDB,
+    env::fill_tx_env,
+    primitives::{B160, U256},
+    Database, EVM,
+};
+use reth_rpc::eth::error::EthApiError;
+
+/// Most transactions a single bundle may hold.
+pub const MAX_BUNDLE_TRANSACTIONS: usize = 256;
+
+/// Outcome of a single transaction of a simulated bundle.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulatedTransaction {
+    pub hash: EthersH256,
+    pub from: EthersAddress,
+    pub gas_used: u64,
+    /// False if the transaction reverted or halted.
+    pub success: bool,
+    /// Return or revert data.
+    pub output: EthersBytes,
+    /// Balance the block's coinbase gained from the transaction, fees and direct transfers.
+    pub coinbase_diff: EthersU256,
+    /// Gas used times the tip paid above the base fee.
+    pub priority_fee: EthersU256,
+    /// Paid to the coinbase directly, on top of the priority fee.
+    pub coinbase_transfer: EthersU256,
+}
+
+/// Outcome of a bundle executed on top of the local head.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleSimulation {
+    /// The block the bundle was simulated for, one past the head.
+    pub block_number: u64,
+    /// Hash of the head the bundle was executed on.
+    pub parent_hash: EthersH256,
+    /// Timestamp the bundle was simulated with.
+    pub timestamp: u64,
+    pub transactions: Vec<SimulatedTransaction>,
+    pub gas_used: u64,
+    /// Total balance the coinbase gained from the bundle.
+    pub coinbase_diff: EthersU256,
+    /// Coinbase payment per unit of gas, what builders rank bundles by.
+    pub effective_gas_price: EthersU256,
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Executes the raw signed transactions in order on top of the latest block, in the
+    /// environment of the block that follows it.
+    ///
+    /// Reverting transactions do not fail the simulation, they are reported with `success` unset.
+    /// The simulation runs under the call limits, transactions asking for more gas than the cap
+    /// being capped, and holds a heavy request slot. Bundles of more than
+    /// [MAX_BUNDLE_TRANSACTIONS] transactions are rejected.
+    pub async fn simulate_bundle(
+        &self,
+        transactions: &[EthersBytes],
+    ) -> Result<BundleSimulation, RethMiddlewareError<M>> {
+        instrument("simulate_bundle", async {
+            if transactions.len() > MAX_BUNDLE_TRANSACTIONS {
+                return Err(EthApiError::InvalidParams(format!(
+                    "at most {MAX_BUNDLE_TRANSACTIONS} transactions can be bundled"
+                ))
+                .into())
+            }
+            let transactions = transactions
+                .iter()
+                .map(|raw| {
+                    let tx = TransactionSigned::decode_enveloped(raw.0.clone())
+                        .map_err(|_| EthApiError::FailedToDecodeSignedTransaction)?;
+                    let sender =
+                        tx.recover_signer().ok_or(EthApiError::InvalidTransactionSignature)?;
+                    Ok((tx, sender))
+                })
+                .collect::<Result<Vec<_>, EthApiError>>()?;
+
+            self.with_heavy_permit(self.run_call(|| {
+                let (head, _) =
+                    self.sealed_block_with_senders(BlockId::Number(BlockNumberOrTag::Latest))?;
+                let mut env = self.block_env(&head)?;
+                let timestamp = head.timestamp + SLOT_DURATION;
+                env.block.number = U256::from(head.number + 1);
+                env.block.timestamp = U256::from(timestamp);
+                let base_fee = head.next_block_base_fee().unwrap_or_default();
+                env.block.basefee = U256::from(base_fee);
+                let coinbase = env.block.coinbase;
+                let gas_cap = self.gas_cap(env.block.gas_limit.to::<u64>());
+
+                let state = RethDatabaseRef::at_block(&self.reth_provider, head.hash())?;
+                let mut evm = EVM::with_env(env);
+                evm.database(CacheDB::new(state));
+
+                let mut simulated = Vec::with_capacity(transactions.len());
+                let mut balance = coinbase_balance(&mut evm, coinbase)?;
+                for (tx, sender) in transactions.iter() {
+                    fill_tx_env(&mut evm.env.tx, tx, *sender);
+                    evm.env.tx.gas_limit = evm.env.tx.gas_limit.min(gas_cap);
+                    let result = self
+                        .precompile_overrides
+                        .transact_commit(&mut evm)
+                        .map_err(EthApiError::from)?;
+
+                    let balance_after = coinbase_balance(&mut evm, coinbase)?;
+                    let coinbase_diff = balance_after.saturating_sub(balance);
+                    let priority_fee = priority_fee(tx, result.gas_used(), base_fee);
+                    simulated.push(SimulatedTransaction {
+                        hash: tx.hash().into_ethers(),
+                        from: sender.into_ethers(),
+                        gas_used: result.gas_used(),
+                        success: result.is_success(),
+                        output: result.output().cloned().unwrap_or_default().into(),
+                        coinbase_diff: coinbase_diff.into_ethers(),
+                        priority_fee: priority_fee.into_ethers(),
+                        coinbase_transfer: coinbase_diff.saturating_sub(priority_fee).into_ethers(),
+                    });
+                    balance = balance_after;
+                }
+
+                let gas_used = simulated.iter().map(|tx| tx.gas_used).sum::<u64>();
+                let coinbase_diff =
+                    simulated.iter().fold(EthersU256::zero(), |sum, tx| sum + tx.coinbase_diff);
+                let effective_gas_price =
+                    if gas_used == 0 { EthersU256::zero() } else { coinbase_diff / gas_used };
+
+                Ok(BundleSimulation {
+                    block_number: head.number + 1,
+                    parent_hash: head.hash().into_ethers(),
+                    timestamp,
+                    transactions: simulated,
+                    gas_used,
+                    coinbase_diff,
+                    effective_gas_price,
+                })
+            }))
+            .await
+        })
+        .await
+    }
+}
+
+/// Returns the balance of the coinbase in the state staged so far.
+pub(crate) fn coinbase_balance(
+    evm: &mut EVM<CacheDB<RethDatabaseRef<'_>>>,
+    coinbase: B160,
+) -> Result<U256, EthApiError> {
+    let db = evm.db.as_mut().expect("database is set");
+    Ok(db.basic(coinbase)?.map(|account| account.balance).unwrap_or_default())
+}
@@ -0,0 +1,330 @@
+use crate::{
+    database::RethDatabaseRef,
+    execution::{call_output, call_tx_env, execute_transactions},
+    instrument::{instrument, record_block},
+    type_conversions::ToReth,
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{
+        spoof, transaction::eip2718::TypedTransaction, Address as EthersAddress,
+        BlockId as EthersBlockId, Bytes as EthersBytes, H256 as EthersH256, U256 as EthersU256,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_primitives::BlockId;
+use reth_revm::{
+    db::CacheDB,
+    primitives::{Bytecode, Env, B160, U256},
+    Database, DatabaseRef, EVM,
+};
+use reth_rpc::eth::error::EthApiError;
+
+// Std
+use std::collections::HashMap;
+
+/// Fields of the block environment to replace while executing calls.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub number: Option<EthersU256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<EthersU256>,
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "time")]
+    pub timestamp: Option<EthersU256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gas_limit: Option<EthersU256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coinbase: Option<EthersAddress>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_fee: Option<EthersU256>,
+}
+
+impl BlockOverrides {
+    /// Applies the overrides to a block environment.
+    pub(crate) fn apply(&self, env: &mut Env) {
+        if let Some(number) = self.number {
+            env.block.number = number.into_reth();
+        }
+        if let Some(difficulty) = self.difficulty {
+            env.block.difficulty = difficulty.into_reth();
+        }
+        if let Some(timestamp) = self.timestamp {
+            env.block.timestamp = timestamp.into_reth();
+        }
+        if let Some(gas_limit) = self.gas_limit {
+            env.block.gas_limit = gas_limit.into_reth();
+        }
+        if let Some(coinbase) = self.coinbase {
+            env.block.coinbase = B160::from(coinbase.0);
+        }
+        if let Some(base_fee) = self.base_fee {
+            env.block.basefee = base_fee.into_reth();
+        }
+    }
+}
+
+/// Calls executed one after the other, each seeing the changes of the previous ones.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallBundle {
+    pub transactions: Vec<TypedTransaction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_override: Option<BlockOverrides>,
+}
+
+/// The state calls run on: the state of `block` right before its transaction at
+/// `transaction_index`, or after the whole block if there is no index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateContext {
+    pub block: EthersBlockId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction_index: Option<usize>,
+}
+
+impl Default for StateContext {
+    fn default() -> Self {
+        Self { block: EthersBlockId::Number(Default::default()), transaction_index: None }
+    }
+}
+
+/// Outcome of a single call: its return data, or why it failed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CallResult {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<EthersBytes>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl From<Result<EthersBytes, EthApiError>> for CallResult {
+    fn from(res: Result<EthersBytes, EthApiError>) -> Self {
+        match res {
+            Ok(value) => Self { value: Some(value), error: None },
+            Err(err) => Self { value: None, error: Some(err.to_string()) },
+        }
+    }
+}
+
+/// Applies a state override set to the staged state.
+pub(crate) fn apply_state_overrides<DB>(
+    overrides: &spoof::State,
+    db: &mut CacheDB<DB>,
+) -> Result<(), EthApiError>
+where
+    DB: DatabaseRef,
+    DB::Error: Into<EthApiError>,
+{
+    // the accounts of the override set are only reachable through its serialization
+    let accounts: HashMap<EthersAddress, spoof::Account> = serde_json::to_value(overrides)
+        .and_then(serde_json::from_value)
+        .map_err(|err| EthApiError::InvalidParams(format!("invalid state overrides: {err}")))?;
+
+    for (address, account) in accounts {
+        let address = B160::from(address.0);
+        let mut info = db.basic(address).map_err(Into::into)?.unwrap_or_default();
+        if let Some(nonce) = account.nonce {
+            info.nonce = nonce.as_u64();
+        }
+        if let Some(balance) = account.balance {
+            info.balance = balance.into_reth();
+        }
+        if let Some(code) = account.code {
+            let code = Bytecode::new_raw(code.0);
+            info.code_hash = code.hash_slow();
+            info.code = Some(code);
+        }
+        db.insert_account_info(address, info);
+
+        let slots = |storage: HashMap<EthersH256, EthersH256>| {
+            storage
+                .into_iter()
+                .map(|(slot, value)| (U256::from_be_bytes(slot.0), U256::from_be_bytes(value.0)))
+        };
+        match account.storage {
+            Some(spoof::Storage::Replace(storage)) => {
+                db.replace_account_storage(address, slots(storage).collect())
+                    .map_err(Into::into)?;
+            }
+            Some(spoof::Storage::Diff(storage)) => {
+                for (slot, value) in slots(storage) {
+                    db.insert_account_storage(address, slot, value).map_err(Into::into)?;
+                }
+            }
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Executes bundles of calls one after the other on the state given by `context`, like
+    /// `eth_callMany`, returning the result of every call of every bundle.
+    ///
+    /// Calls commit their changes, so later calls and bundles observe them. A failing call, or a
+    /// call whose output exceeds the result size limit, is reported in its result and does not
+    /// stop the others.
+    pub async fn call_many(
+        &self,
+        bundles: &[CallBundle],
+        context: StateContext,
+        state_overrides: Option<&spoof::State>,
+    ) -> Result<Vec<Vec<CallResult>>, RethMiddlewareError<M>> {
+        instrument(
+            "call_many",
+            self.with_heavy_permit(self.run_call(|| {
+                let (env, mut db) = self.state_at_transaction(context)?;
+                if let Some(overrides) = state_overrides {
+                    apply_state_overrides(overrides, &mut db)?;
+                }
+
+                let mut results = Vec::with_capacity(bundles.len());
+                for bundle in bundles {
+                    let mut env = env.clone();
+                    if let Some(block_override) = &bundle.block_override {
+                        block_override.apply(&mut env);
+                    }
+                    let base_fee = env.block.basefee;
+                    let gas_cap = self.gas_cap(env.block.gas_limit.to::<u64>());
+
+                    let mut evm = EVM::with_env(env);
+                    evm.database(db);
+                    let mut bundle_results = Vec::with_capacity(bundle.transactions.len());
+                    for tx in &bundle.transactions {
+                        // unpriced calls are not charged, as with `eth_call`
+                        evm.env.block.basefee =
+                            if tx.gas_price().is_none() { U256::ZERO } else { base_fee };
+                        evm.env.tx = call_tx_env(tx, gas_cap);
+                        let result = self
+                            .precompile_overrides
+                            .transact_commit(&mut evm)
+                            .map_err(EthApiError::from)
+                            .and_then(call_output);
+                        let result = match result {
+                            // the call is still committed, only its output is withheld
+                            Ok(output) => match self.ensure_result_size(output.len()) {
+                                Ok(()) => CallResult { value: Some(output), error: None },
+                                Err(err) => {
+                                    CallResult { value: None, error: Some(err.to_string()) }
+                                }
+                            },
+                            Err(err) => CallResult::from(Err(err)),
+                        };
+                        bundle_results.push(result);
+                    }
+                    results.push(bundle_results);
+                    db = evm.db.take().expect("database is set");
+                }
+                Ok(results)
+            })),
+        )
+        .await
+    }
+
+    /// Executes a call on the state of `block` right before its transaction at `index`, i.e. with
+    /// the transactions before it applied, in the environment of that block.
+    ///
+    /// An index equal to the number of transactions of the block runs the call after all of them.
+    pub async fn call_at_transaction_index(
+        &self,
+        tx: &TypedTransaction,
+        block: EthersBlockId,
+        index: usize,
+    ) -> Result<EthersBytes, RethMiddlewareError<M>> {
+        instrument(
+            "call_at_transaction_index",
+            self.with_heavy_permit(self.run_call(|| {
+                let context = StateContext { block, transaction_index: Some(index) };
+                let (env, db) = self.state_at_transaction(context)?;
+                self.execute_call(tx, env, db)
+            })),
+        )
+        .await
+    }
+
+    /// Executes a call on the state of `block` with the balance, nonce, code and storage of
+    /// accounts replaced by `overrides`, like `eth_call` with a state override set.
+    pub async fn call_with_overrides(
+        &self,
+        tx: &TypedTransaction,
+        block: EthersBlockId,
+        overrides: &spoof::State,
+    ) -> Result<EthersBytes, RethMiddlewareError<M>> {
+        instrument(
+            "call_with_overrides",
+            self.with_heavy_permit(self.run_call(|| {
+                let context = StateContext { block, transaction_index: None };
+                let (env, mut db) = self.state_at_transaction(context)?;
+                apply_state_overrides(overrides, &mut db)?;
+                self.execute_call(tx, env, db)
+            })),
+        )
+        .await
+    }
+
+    /// Executes a call in the environment of a block on the given state, discarding its changes.
+    fn execute_call(
+        &self,
+        tx: &TypedTransaction,
+        mut env: Env,
+        db: CacheDB<RethDatabaseRef<'_>>,
+    ) -> Result<EthersBytes, RethMiddlewareError<M>> {
+        if tx.gas_price().is_none() {
+            // unpriced calls are not charged, as with `eth_call`
+            env.block.basefee = U256::ZERO;
+        }
+        env.tx = call_tx_env(tx, self.gas_cap(env.block.gas_limit.to::<u64>()));
+
+        let mut evm = EVM::with_env(env);
+        evm.database(db);
+        let result =
+            self.precompile_overrides.transact(&mut evm).map_err(EthApiError::from)?.result;
+        let output = call_output(result)?;
+        self.ensure_result_size(output.len())?;
+        Ok(output)
+    }
+
+    /// Returns the environment of the context's block and its state right before the
+    /// transaction at the context's index, replaying the transactions before it.
+    pub(crate) fn state_at_transaction(
+        &self,
+        context: StateContext,
+    ) -> Result<(Env, CacheDB<RethDatabaseRef<'_>>), RethMiddlewareError<M>> {
+        let block_id: BlockId = context.block.into_reth();
+        record_block(&block_id);
+        self.ensure_history(Some(block_id))?;
+        self.ensure_fresh(Some(block_id))?;
+        self.ensure_forkchoice(Some(block_id))?;
+
+        let (block, senders) = self.sealed_block_with_senders(block_id)?;
+        let env = self.block_env(&block)?;
+
+        let db = match context.transaction_index {
+            Some(index) if index > block.body.len() => {
+                return Err(EthApiError::InvalidParams(format!(
+                    "transaction index {index} is past the {} transactions of the block",
+                    block.body.len()
+                ))
+                .into())
+            }
+            Some(index) => {
+                let state = RethDatabaseRef::at_block(&self.reth_provider, block.parent_hash)?;
+                let replayed = block.body.iter().zip(senders.iter().copied()).take(index);
+                execute_transactions(env.clone(), CacheDB::new(state), replayed)?.1
+            }
+            None => {
+                let state = RethDatabaseRef::at_block(&self.reth_provider, block.hash())?;
+                CacheDB::new(state)
+            }
+        };
+        Ok((env, db))
+    }
+}
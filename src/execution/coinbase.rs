@@ -0,0 +1,125 @@
+use crate::{
+    database::RethDatabaseRef,
+    execution::bundle::coinbase_balance,
+    instrument::{instrument, record_block},
+    limits::CallDeadline,
+    type_conversions::{ToEthers, ToReth},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{
+        Address as EthersAddress, BlockId as EthersBlockId, H256 as EthersH256, U256 as EthersU256,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_primitives::{BlockId, TransactionSigned};
+use reth_revm::{db::CacheDB, env::fill_tx_env, primitives::U256, EVM};
+use reth_rpc::eth::error::EthApiError;
+
+/// What the coinbase of a block earned from one of its transactions.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoinbasePayment {
+    pub hash: EthersH256,
+    pub from: EthersAddress,
+    pub gas_used: u64,
+    /// Gas used times the tip paid above the base fee.
+    pub priority_fee: EthersU256,
+    /// Paid to the coinbase directly, on top of the priority fee.
+    pub coinbase_transfer: EthersU256,
+}
+
+/// Payments to the coinbase of a block, per transaction.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoinbasePayments {
+    pub block_number: u64,
+    pub coinbase: EthersAddress,
+    pub transactions: Vec<CoinbasePayment>,
+    pub priority_fees: EthersU256,
+    pub coinbase_transfers: EthersU256,
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Re-executes a block on top of its parent state and reports, per transaction, the priority
+    /// fee and the direct transfers its coinbase received.
+    ///
+    /// Direct transfers are measured from the coinbase balance, so transactions sent by the
+    /// coinbase itself report none. The block is re-executed under the call timeout, holding a
+    /// heavy request slot.
+    pub async fn get_coinbase_payments<T: Into<EthersBlockId> + Send + Sync>(
+        &self,
+        block: T,
+    ) -> Result<CoinbasePayments, RethMiddlewareError<M>> {
+        instrument("get_coinbase_payments", async {
+            let block_id: BlockId = block.into().into_reth();
+            record_block(&block_id);
+            self.ensure_history(Some(block_id))?;
+
+            self.with_heavy_permit(self.run_call(|| {
+                let (block, senders) = self.sealed_block_with_senders(block_id)?;
+                let env = self.block_env(&block)?;
+                let base_fee = block.base_fee_per_gas.unwrap_or_default();
+                let coinbase = env.block.coinbase;
+
+                let state = RethDatabaseRef::at_block(&self.reth_provider, block.parent_hash)?;
+                let mut evm = EVM::with_env(env);
+                evm.database(CacheDB::new(state));
+
+                let mut transactions = Vec::with_capacity(block.body.len());
+                let mut balance = coinbase_balance(&mut evm, coinbase)?;
+                for (tx, sender) in block.body.iter().zip(senders) {
+                    fill_tx_env(&mut evm.env.tx, tx, sender);
+                    let result =
+                        CallDeadline::transact_commit(&mut evm).map_err(EthApiError::from)?;
+
+                    let balance_after = coinbase_balance(&mut evm, coinbase)?;
+                    let priority_fee = priority_fee(tx, result.gas_used(), base_fee);
+                    transactions.push(CoinbasePayment {
+                        hash: tx.hash().into_ethers(),
+                        from: sender.into_ethers(),
+                        gas_used: result.gas_used(),
+                        priority_fee: priority_fee.into_ethers(),
+                        coinbase_transfer: balance_after
+                            .saturating_sub(balance)
+                            .saturating_sub(priority_fee)
+                            .into_ethers(),
+                    });
+                    balance = balance_after;
+                }
+
+                let priority_fees =
+                    transactions.iter().fold(EthersU256::zero(), |sum, tx| sum + tx.priority_fee);
+                let coinbase_transfers = transactions
+                    .iter()
+                    .fold(EthersU256::zero(), |sum, tx| sum + tx.coinbase_transfer);
+                Ok(CoinbasePayments {
+                    block_number: block.number,
+                    coinbase: EthersAddress::from(coinbase.0),
+                    transactions,
+                    priority_fees,
+                    coinbase_transfers,
+                })
+            }))
+            .await
+        })
+        .await
+    }
+}
+
+/// Returns the priority fee a transaction pays for `gas_used` in a block with `base_fee`.
+pub(crate) fn priority_fee(tx: &TransactionSigned, gas_used: u64, base_fee: u64) -> U256 {
+    U256::from(tip_per_gas(tx, base_fee)) * U256::from(gas_used)
+}
+
+/// Returns the tip per gas a transaction pays above `base_fee`.
+pub(crate) fn tip_per_gas(tx: &TransactionSigned, base_fee: u64) -> u128 {
+    let tip = tx.max_fee_per_gas().saturating_sub(base_fee as u128);
+    tx.max_priority_fee_per_gas().map_or(tip, |priority| priority.min(tip))
+}
@@ -0,0 +1,272 @@
+use crate::{
+    database::RethDatabaseRef,
+    execution::{call_many::StateContext, call_tx_env},
+    instrument::instrument,
+    limits::CallDeadline,
+    type_conversions::{ToEthers, ToReth},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{
+        transaction::eip2718::TypedTransaction, BlockId as EthersBlockId, Bytes as EthersBytes,
+        H256 as EthersH256, U256 as EthersU256, U64 as EthersU64,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_revm::{
+    db::CacheDB,
+    interpreter::{opcode::OPCODE_JUMPMAP, InstructionResult, Interpreter},
+    primitives::{Env, ExecutionResult, U256},
+    Database, EVMData, Inspector, EVM,
+};
+use reth_rpc::eth::error::EthApiError;
+
+/// A line of an [EIP-3155](https://eips.ethereum.org/EIPS/eip-3155) trace, the state of the
+/// machine before an instruction.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Eip3155Step {
+    pub pc: u64,
+    pub op: u8,
+    /// Gas left before the instruction.
+    pub gas: EthersU64,
+    /// Gas charged by the instruction, including the gas handed to the frame it opens.
+    pub gas_cost: EthersU64,
+    pub mem_size: u64,
+    /// The stack, its top last. Empty if the trace limits disable the stack.
+    pub stack: Vec<EthersU256>,
+    /// Call depth, 1 for the top-level call.
+    pub depth: u64,
+    pub return_data: EthersBytes,
+    pub refund: EthersU64,
+    pub op_name: String,
+    /// How the instruction failed, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The last line of an EIP-3155 trace. The post-state root is left out, computing it would take
+/// a trie update per traced transaction.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Eip3155Summary {
+    pub output: EthersBytes,
+    pub gas_used: EthersU64,
+    pub pass: bool,
+    /// How the execution failed, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// An EIP-3155 trace: a line per executed instruction, then a summary.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Eip3155Trace {
+    pub steps: Vec<Eip3155Step>,
+    pub summary: Eip3155Summary,
+}
+
+impl Eip3155Trace {
+    /// Returns the trace as JSON lines, as the other clients' standard tracers print it.
+    pub fn to_json_lines(&self) -> String {
+        let mut lines = String::new();
+        for step in &self.steps {
+            lines.push_str(&serde_json::to_string(step).expect("steps serialize"));
+            lines.push('\n');
+        }
+        lines.push_str(&serde_json::to_string(&self.summary).expect("summary serializes"));
+        lines.push('\n');
+        lines
+    }
+}
+
+/// An [Inspector] recording an EIP-3155 step per executed instruction.
+#[derive(Debug, Default)]
+pub struct Eip3155Tracer {
+    capture_stack: bool,
+    steps: Vec<Eip3155Step>,
+    /// Index in `steps` of the instructions being executed, the innermost last, `None` for the
+    /// instructions deeper than `max_depth`.
+    pending: Vec<Option<usize>>,
+    deadline: Option<CallDeadline>,
+    max_depth: Option<usize>,
+    max_size: Option<usize>,
+    /// Bytes of JSON of the recorded steps.
+    size: usize,
+}
+
+impl Eip3155Tracer {
+    /// Halts the traced execution at the deadline of the running call, if there is one.
+    pub fn new(capture_stack: bool) -> Self {
+        Self { capture_stack, deadline: CallDeadline::current(), ..Default::default() }
+    }
+
+    /// Skips the instructions deeper than `max_depth` and halts the execution once the recorded
+    /// steps are larger than `max_size` bytes of JSON, rather than tracing it to the end.
+    pub fn with_limits(mut self, max_depth: Option<usize>, max_size: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self.max_size = max_size;
+        self
+    }
+
+    /// Returns the size of the recorded steps if they outgrew the size limit.
+    pub fn oversized(&self) -> Option<usize> {
+        self.max_size.filter(|limit| self.size > *limit).map(|_| self.size)
+    }
+
+    /// Returns the recorded steps followed by the summary of the execution's result.
+    pub fn into_trace(self, result: ExecutionResult) -> Eip3155Trace {
+        let gas_used = result.gas_used().into();
+        let summary = match result {
+            ExecutionResult::Success { output, .. } => Eip3155Summary {
+                output: output.into_data().into(),
+                gas_used,
+                pass: true,
+                error: None,
+            },
+            ExecutionResult::Revert { output, .. } => Eip3155Summary {
+                output: output.into(),
+                gas_used,
+                pass: false,
+                error: Some("execution reverted".to_string()),
+            },
+            ExecutionResult::Halt { reason, .. } => Eip3155Summary {
+                output: EthersBytes::default(),
+                gas_used,
+                pass: false,
+                error: Some(format!("{reason:?}")),
+            },
+        };
+        Eip3155Trace { steps: self.steps, summary }
+    }
+}
+
+impl<DB: Database> Inspector<DB> for Eip3155Tracer {
+    fn step(&mut self, interp: &mut Interpreter, data: &mut EVMData<'_, DB>) -> InstructionResult {
+        if self.deadline.as_mut().map_or(false, CallDeadline::passed) {
+            return InstructionResult::OutOfGas
+        }
+        let depth = data.journaled_state.depth() as u64;
+        if self.max_depth.map_or(false, |max_depth| depth as usize > max_depth) {
+            self.pending.push(None);
+            return InstructionResult::Continue
+        }
+        let opcode = interp.current_opcode();
+        let stack = if self.capture_stack {
+            interp
+                .stack
+                .data()
+                .iter()
+                .map(|value| ToEthers::<EthersU256>::into_ethers(*value))
+                .collect()
+        } else {
+            vec![]
+        };
+        self.pending.push(Some(self.steps.len()));
+        self.steps.push(Eip3155Step {
+            pc: interp.program_counter() as u64,
+            op: opcode,
+            gas: interp.gas.remaining().into(),
+            gas_cost: EthersU64::zero(),
+            mem_size: interp.memory.len() as u64,
+            stack,
+            depth,
+            return_data: interp.return_data_buffer.clone().into(),
+            refund: (interp.gas.refunded().max(0) as u64).into(),
+            op_name: OPCODE_JUMPMAP[opcode as usize].unwrap_or("INVALID").to_string(),
+            error: None,
+        });
+        InstructionResult::Continue
+    }
+
+    fn step_end(
+        &mut self,
+        interp: &mut Interpreter,
+        _data: &mut EVMData<'_, DB>,
+        eval: InstructionResult,
+    ) -> InstructionResult {
+        let Some(Some(index)) = self.pending.pop() else { return InstructionResult::Continue };
+        let step = &mut self.steps[index];
+        step.gas_cost = step.gas.as_u64().saturating_sub(interp.gas.remaining()).into();
+        step.error = match eval {
+            InstructionResult::Continue |
+            InstructionResult::Stop |
+            InstructionResult::Return |
+            InstructionResult::SelfDestruct => None,
+            InstructionResult::Revert => Some("execution reverted".to_string()),
+            halt => Some(format!("{halt:?}")),
+        };
+        if let Some(limit) = self.max_size {
+            // the step and its separator, once complete
+            self.size += serde_json::to_string(step).map_or(0, |json| json.len()) + 1;
+            if self.size > limit {
+                return InstructionResult::OutOfGas
+            }
+        }
+        InstructionResult::Continue
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Replays a mined transaction on top of the state it ran on and returns its EIP-3155 trace.
+    pub async fn debug_trace_transaction_eip3155(
+        &self,
+        tx_hash: EthersH256,
+    ) -> Result<Eip3155Trace, RethMiddlewareError<M>> {
+        instrument("debug_trace_transaction_eip3155", async {
+            self.ensure_namespace("debug_trace_transaction_eip3155")?;
+            self.with_heavy_permit(async {
+                let (env, db) = self.transaction_replay(tx_hash.into_reth())?;
+                self.eip3155_trace(env, db)
+            })
+            .await
+        })
+        .await
+    }
+
+    /// Executes a call on the state of `block`, like `eth_call`, and returns its EIP-3155 trace.
+    pub async fn debug_trace_call_eip3155(
+        &self,
+        tx: &TypedTransaction,
+        block: Option<EthersBlockId>,
+    ) -> Result<Eip3155Trace, RethMiddlewareError<M>> {
+        instrument(
+            "debug_trace_call_eip3155",
+            self.with_heavy_permit(self.run_call(|| {
+                self.ensure_namespace("debug_trace_call_eip3155")?;
+                let block = block.unwrap_or(EthersBlockId::Number(Default::default()));
+                let context = StateContext { block, transaction_index: None };
+                let (mut env, db) = self.state_at_transaction(context)?;
+                if tx.gas_price().is_none() {
+                    // unpriced calls are not charged, as with `eth_call`
+                    env.block.basefee = U256::ZERO;
+                }
+                env.tx = call_tx_env(tx, self.gas_cap(env.block.gas_limit.to::<u64>()));
+                self.eip3155_trace(env, db)
+            })),
+        )
+        .await
+    }
+
+    fn eip3155_trace(
+        &self,
+        env: Env,
+        db: CacheDB<RethDatabaseRef<'_>>,
+    ) -> Result<Eip3155Trace, RethMiddlewareError<M>> {
+        let mut evm = EVM::with_env(env);
+        evm.database(db);
+        let limits = self.trace_limits();
+        let mut tracer = Eip3155Tracer::new(!limits.disable_stack)
+            .with_limits(limits.max_depth, limits.max_output_size);
+        let result = evm.inspect(&mut tracer).map_err(EthApiError::from)?.result;
+        if let (Some(size), Some(limit)) = (tracer.oversized(), limits.max_output_size) {
+            return Err(RethMiddlewareError::TraceTooLarge { size, limit })
+        }
+        self.limit_trace(tracer.into_trace(result))
+    }
+}
@@ -0,0 +1,183 @@
+use crate::{
+    database::RethDatabaseRef,
+    execution::call_many::StateContext,
+    instrument::db_read,
+    limits::CallDeadline,
+    type_conversions::{ToEthers, ToReth},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{
+        transaction::eip2718::TypedTransaction, BlockId as EthersBlockId, Bytes as EthersBytes,
+    },
+};
+
+// Reth
+use reth_primitives::{Address, BlockId, SealedBlock, TransactionSigned, H256};
+use reth_provider::{BlockReaderIdExt, EvmEnvProvider, HeaderProvider, TransactionsProvider};
+use reth_revm::{
+    db::CacheDB,
+    env::{fill_cfg_and_block_env, fill_tx_env},
+    primitives::{Env, ExecutionResult, TransactTo, TxEnv, B160},
+    Database, DatabaseCommit, EVM,
+};
+use reth_rpc::eth::error::{EthApiError, RevertError, RpcInvalidTransactionError};
+
+pub mod access_list;
+pub mod bundle;
+pub mod call_many;
+pub mod coinbase;
+#[cfg(feature = "debug")]
+pub mod eip3155;
+pub mod multicall;
+pub mod overlay;
+pub mod precompiles;
+pub mod profile;
+pub mod recorder;
+pub mod replay;
+pub mod simulate;
+pub(crate) mod snapshot;
+pub mod source_gas;
+pub mod sparse_trie;
+pub mod spec;
+pub mod stateless;
+#[cfg(feature = "debug")]
+pub mod tracers;
+pub mod witness;
+
+/// Seconds between a block and the next one simulated on top of it.
+pub(crate) const SLOT_DURATION: u64 = 12;
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Loads a sealed block together with the recovered sender of each transaction.
+    pub(crate) fn sealed_block_with_senders(
+        &self,
+        block_id: BlockId,
+    ) -> Result<(SealedBlock, Vec<Address>), RethMiddlewareError<M>> {
+        let block = db_read("block_by_id", || self.reth_provider.block_by_id(block_id))?
+            .ok_or(RethMiddlewareError::BlockNotFound)?
+            .seal_slow();
+
+        let senders = self.recover_senders(&block.body)?;
+
+        Ok((block, senders))
+    }
+
+    /// Returns the evm environment the given block was executed with, under the rules of the
+    /// spec override if one is set.
+    pub(crate) fn block_env(&self, block: &SealedBlock) -> Result<Env, RethMiddlewareError<M>> {
+        let mut env = Env::default();
+        match &self.spec_override.chain {
+            Some(chain) => {
+                let total_difficulty =
+                    db_read("header_td", || self.reth_provider.header_td(&block.hash()))?
+                        .ok_or(RethMiddlewareError::BlockNotFound)?;
+                fill_cfg_and_block_env(
+                    &mut env.cfg,
+                    &mut env.block,
+                    chain,
+                    &block.header,
+                    total_difficulty,
+                );
+            }
+            None => db_read("fill_env_at", || {
+                self.reth_provider.fill_env_at(&mut env.cfg, &mut env.block, block.hash().into())
+            })?,
+        }
+        if let Some(spec_id) = self.spec_override.spec_id {
+            env.cfg.spec_id = spec_id;
+        }
+        Ok(env)
+    }
+
+    /// Returns the environment of a mined transaction and the state it ran on, replaying the
+    /// transactions before it in its block.
+    pub(crate) fn transaction_replay(
+        &self,
+        hash: H256,
+    ) -> Result<(Env, CacheDB<RethDatabaseRef<'_>>), RethMiddlewareError<M>> {
+        let (tx, meta) = db_read("transaction_by_hash_with_meta", || {
+            self.reth_provider.transaction_by_hash_with_meta(hash)
+        })?
+        .ok_or(EthApiError::TransactionNotFound)?;
+        let sender = tx.recover_signer().ok_or(EthApiError::InvalidTransactionSignature)?;
+
+        let (mut env, db) = self.state_at_transaction(StateContext {
+            block: EthersBlockId::Hash(meta.block_hash.into_ethers()),
+            transaction_index: Some(meta.index as usize),
+        })?;
+        fill_tx_env(&mut env.tx, &tx, sender);
+        Ok((env, db))
+    }
+}
+
+/// Executes the transactions sequentially on top of `db` in the block environment `env`,
+/// committing every result so each transaction observes the changes of the previous ones.
+///
+/// Returns the execution results together with the updated database.
+pub(crate) fn execute_transactions<'a, DB, I>(
+    env: Env,
+    db: DB,
+    transactions: I,
+) -> Result<(Vec<ExecutionResult>, DB), EthApiError>
+where
+    DB: Database + DatabaseCommit,
+    DB::Error: Into<EthApiError>,
+    I: IntoIterator<Item = (&'a TransactionSigned, Address)>,
+{
+    let mut evm = EVM::with_env(env);
+    evm.database(db);
+
+    // once passed, the deadline halts the remaining transactions at their first instruction
+    let mut deadline = CallDeadline::current();
+    let mut results = vec![];
+    for (tx, sender) in transactions {
+        fill_tx_env(&mut evm.env.tx, tx, sender);
+        let result = match &mut deadline {
+            Some(deadline) => evm.inspect_commit(deadline)?,
+            None => evm.transact_commit()?,
+        };
+        results.push(result);
+    }
+
+    Ok((results, evm.db.take().unwrap()))
+}
+
+/// Returns the transaction environment of a call, capped at `gas_cap` gas.
+pub(crate) fn call_tx_env(tx: &TypedTransaction, gas_cap: u64) -> TxEnv {
+    TxEnv {
+        caller: B160::from(tx.from().copied().unwrap_or_default().0),
+        gas_limit: tx.gas().map_or(gas_cap, |gas| gas.as_u64().min(gas_cap)),
+        gas_price: tx.gas_price().unwrap_or_default().into_reth(),
+        gas_priority_fee: tx
+            .as_eip1559_ref()
+            .and_then(|tx| tx.max_priority_fee_per_gas)
+            .map(|fee| fee.into_reth()),
+        transact_to: match tx.to_addr() {
+            Some(to) => TransactTo::Call(B160::from(to.0)),
+            None => TransactTo::create(),
+        },
+        value: tx.value().copied().unwrap_or_default().into_reth(),
+        data: tx.data().cloned().unwrap_or_default().0,
+        chain_id: None,
+        nonce: None,
+        access_list: vec![],
+    }
+}
+
+/// Returns the output of a call, or the revert or halt it ended with as an error.
+pub(crate) fn call_output(result: ExecutionResult) -> Result<EthersBytes, EthApiError> {
+    match result {
+        ExecutionResult::Success { output, .. } => Ok(output.into_data().into()),
+        ExecutionResult::Revert { output, .. } => Err(EthApiError::InvalidTransaction(
+            RpcInvalidTransactionError::Revert(RevertError::new(output)),
+        )),
+        ExecutionResult::Halt { reason, .. } => {
+            Err(EthApiError::InvalidTransaction(RpcInvalidTransactionError::EvmHalt(reason)))
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{
+    execution::{call_many::StateContext, call_tx_env},
+    instrument::instrument,
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    abi::{self, ParamType, Token},
+    providers::Middleware,
+    types::{
+        transaction::eip2718::TypedTransaction, Address as EthersAddress, BlockId as EthersBlockId,
+        Bytes as EthersBytes, TransactionRequest, H160,
+    },
+};
+use hex_literal::hex;
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_revm::{
+    primitives::{Bytes, ExecutionResult, U256},
+    EVM,
+};
+use reth_rpc::eth::error::{EthApiError, RevertError, RpcInvalidTransactionError};
+
+/// Address of Multicall3, the same on every chain it is deployed on.
+pub const MULTICALL3_ADDRESS: EthersAddress =
+    H160(hex!("cA11bde05977b3631167028862bE2a173976CA11"));
+
+/// Selector of `aggregate3((address,bool,bytes)[])`.
+const AGGREGATE3_SELECTOR: [u8; 4] = hex!("82ad56cb");
+
+/// A call of a Multicall3 `aggregate3` batch.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Call3 {
+    pub target: EthersAddress,
+    /// Whether the batch goes on if the call fails, else the whole batch reverts.
+    pub allow_failure: bool,
+    pub call_data: EthersBytes,
+}
+
+/// The outcome of a [Call3]: its return data, or its revert data if it failed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Call3Result {
+    pub success: bool,
+    pub return_data: EthersBytes,
+}
+
+fn call3_type() -> ParamType {
+    ParamType::Array(Box::new(ParamType::Tuple(vec![
+        ParamType::Address,
+        ParamType::Bool,
+        ParamType::Bytes,
+    ])))
+}
+
+/// Decodes the calls of `aggregate3` calldata, none if the calldata is not an `aggregate3` call.
+pub fn decode_aggregate3(data: &[u8]) -> Option<Vec<Call3>> {
+    let args = data.strip_prefix(&AGGREGATE3_SELECTOR)?;
+    let Token::Array(calls) = abi::decode(&[call3_type()], args).ok()?.pop()? else { return None };
+    calls
+        .into_iter()
+        .map(|call| {
+            let Token::Tuple(fields) = call else { return None };
+            match fields.as_slice() {
+                [Token::Address(target), Token::Bool(allow_failure), Token::Bytes(call_data)] => {
+                    Some(Call3 {
+                        target: *target,
+                        allow_failure: *allow_failure,
+                        call_data: call_data.clone().into(),
+                    })
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Encodes the results of an `aggregate3` batch as Multicall3 returns them.
+pub fn encode_aggregate3_output(results: &[Call3Result]) -> EthersBytes {
+    let results = results
+        .iter()
+        .map(|result| {
+            Token::Tuple(vec![
+                Token::Bool(result.success),
+                Token::Bytes(result.return_data.to_vec()),
+            ])
+        })
+        .collect();
+    abi::encode(&[Token::Array(results)]).into()
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Serves `eth_call`s of Multicall3's `aggregate3` by running every call of the batch directly,
+    /// instead of executing the Multicall3 contract. Results are the same, the sub-calls being
+    /// sent from the Multicall3 address, but gas is not metered across the batch.
+    pub fn with_native_multicall(mut self, enabled: bool) -> Self {
+        self.native_multicall = enabled;
+        self
+    }
+
+    /// Returns the calls of a call to `aggregate3` the middleware serves natively, if it is one.
+    pub(crate) fn native_multicall_calls(&self, tx: &TypedTransaction) -> Option<Vec<Call3>> {
+        if !self.native_multicall ||
+            tx.to_addr() != Some(&MULTICALL3_ADDRESS) ||
+            !tx.value().map_or(true, |value| value.is_zero())
+        {
+            return None
+        }
+        decode_aggregate3(tx.data()?)
+    }
+
+    /// Runs the calls of an `aggregate3` batch one after the other on the state of `block`, like
+    /// Multicall3 would. Calls observe the changes of the previous ones.
+    ///
+    /// A failing call without `allow_failure` fails the batch with Multicall3's revert.
+    pub async fn aggregate3(
+        &self,
+        calls: &[Call3],
+        block: Option<EthersBlockId>,
+    ) -> Result<Vec<Call3Result>, RethMiddlewareError<M>> {
+        instrument("aggregate3", self.run_call(|| self.run_aggregate3(calls, block))).await
+    }
+
+    pub(crate) fn run_aggregate3(
+        &self,
+        calls: &[Call3],
+        block: Option<EthersBlockId>,
+    ) -> Result<Vec<Call3Result>, RethMiddlewareError<M>> {
+        let block = block.unwrap_or(EthersBlockId::Number(Default::default()));
+        let (mut env, db) =
+            self.state_at_transaction(StateContext { block, transaction_index: None })?;
+        // unpriced, as with `eth_call`, and sent by a contract
+        env.block.basefee = U256::ZERO;
+        env.cfg.disable_eip3607 = true;
+        let gas_cap = self.gas_cap(env.block.gas_limit.to::<u64>());
+
+        let mut evm = EVM::with_env(env);
+        evm.database(db);
+        let mut results = Vec::with_capacity(calls.len());
+        for call in calls {
+            let tx = TransactionRequest::new()
+                .from(MULTICALL3_ADDRESS)
+                .to(call.target)
+                .data(call.call_data.clone())
+                .into();
+            evm.env.tx = call_tx_env(&tx, gas_cap);
+            let result =
+                self.precompile_overrides.transact_commit(&mut evm).map_err(EthApiError::from)?;
+            let result = match result {
+                ExecutionResult::Success { output, .. } => {
+                    Call3Result { success: true, return_data: output.into_data().into() }
+                }
+                ExecutionResult::Revert { output, .. } => {
+                    Call3Result { success: false, return_data: output.into() }
+                }
+                ExecutionResult::Halt { .. } => Call3Result::default(),
+            };
+            if !result.success && !call.allow_failure {
+                let reason = abi::encode(&[Token::String("Multicall3: call failed".to_string())]);
+                let output = [&hex!("08c379a0")[..], &reason].concat();
+                return Err(EthApiError::InvalidTransaction(RpcInvalidTransactionError::Revert(
+                    RevertError::new(Bytes::from(output)),
+                ))
+                .into())
+            }
+            self.ensure_result_size(result.return_data.len())?;
+            results.push(result);
+        }
+        Ok(results)
+    }
+}
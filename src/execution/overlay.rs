@@ -0,0 +1,372 @@
+use crate::{
+    database::RethDatabaseRef,
+    execution::{
+        call_output, call_tx_env, precompiles::PrecompileOverrides, snapshot::Snapshots,
+        SLOT_DURATION,
+    },
+    instrument::record_block,
+    type_conversions::ToReth,
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{
+        transaction::eip2718::TypedTransaction, Address as EthersAddress, BlockId as EthersBlockId,
+        Bytes as EthersBytes, TransactionRequest, H256 as EthersH256, U256 as EthersU256,
+    },
+    utils::{get_create2_address_from_hash, keccak256},
+};
+use hex_literal::hex;
+
+// Reth
+use reth_primitives::{BlockId, Header};
+use reth_revm::{
+    db::CacheDB,
+    primitives::{AccountInfo, Bytecode, B160, B256, U256},
+    Database, EVM,
+};
+use reth_rpc::eth::error::EthApiError;
+
+/// Code creating the init code after the first word of the calldata with CREATE2, salted with
+/// that word, and returning the address, or reverting with the constructor's revert data.
+const CREATE2_DEPLOYER_CODE: [u8; 41] =
+    hex!("6020360380602060003760003590600034f58015601f5760005260206000f35b3d6000803e3d6000fd");
+
+/// Options of [ForkOverlay::mine_block], for the block opened after the mined one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MineOptions {
+    /// Timestamp of the next block, [SLOT_DURATION] seconds after the mined one if `None`.
+    pub timestamp: Option<u64>,
+    /// Base fee of the next block, adjusted to the gas used by the mined one as EIP-1559 does
+    /// if `None`.
+    pub base_fee: Option<EthersU256>,
+}
+
+/// A block sealed in a fork overlay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OverlayBlock {
+    pub number: u64,
+    /// Hash `BLOCKHASH` returns for the block. Overlay blocks have no header, it is the hash of
+    /// the parent's hash and the number.
+    pub hash: EthersH256,
+    pub timestamp: u64,
+    pub base_fee: EthersU256,
+    pub gas_used: u64,
+    pub transaction_count: usize,
+}
+
+/// The block transactions of an overlay execute in, until it is mined.
+#[derive(Debug, Clone, Copy)]
+struct OpenBlock {
+    parent_hash: B256,
+    gas_used: u64,
+    transaction_count: usize,
+}
+
+/// An in-memory fork of the chain on top of a block of the database. Transactions execute in the
+/// block following it and their changes stay in the overlay, which can be manipulated like a
+/// test node with foundry-style cheatcodes.
+pub struct ForkOverlay<'a> {
+    evm: EVM<CacheDB<RethDatabaseRef<'a>>>,
+    precompiles: PrecompileOverrides,
+    gas_cap: u64,
+    impersonated: Option<B160>,
+    block: OpenBlock,
+    snapshots: Snapshots<OpenBlock>,
+}
+
+impl std::fmt::Debug for ForkOverlay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ForkOverlay").field("block", &self.evm.env.block).finish_non_exhaustive()
+    }
+}
+
+impl<'a> ForkOverlay<'a> {
+    /// Number of the block transactions execute in.
+    pub fn block_number(&self) -> u64 {
+        self.evm.env.block.number.to::<u64>()
+    }
+
+    /// Timestamp of the block transactions execute in.
+    pub fn timestamp(&self) -> u64 {
+        self.evm.env.block.timestamp.to::<u64>()
+    }
+
+    /// Base fee of the block transactions execute in.
+    pub fn base_fee(&self) -> EthersU256 {
+        self.evm.env.block.basefee.into()
+    }
+
+    /// Sets the timestamp of the block, like `vm.warp`.
+    pub fn warp(&mut self, timestamp: u64) {
+        self.evm.env.block.timestamp = U256::from(timestamp);
+    }
+
+    /// Sets the number of the block, like `vm.roll`.
+    pub fn roll(&mut self, number: u64) {
+        self.evm.env.block.number = U256::from(number);
+    }
+
+    /// Sets the base fee of the block, like `vm.fee`.
+    pub fn set_base_fee(&mut self, base_fee: EthersU256) {
+        self.evm.env.block.basefee = base_fee.into_reth();
+    }
+
+    /// Sets the beneficiary of the block, like `vm.coinbase`.
+    pub fn set_coinbase(&mut self, coinbase: EthersAddress) {
+        self.evm.env.block.coinbase = B160::from(coinbase.0);
+    }
+
+    /// Sets the randomness of the block, like `vm.prevrandao`.
+    pub fn set_prevrandao(&mut self, prevrandao: EthersH256) {
+        self.evm.env.block.prevrandao = Some(B256::from(prevrandao.0));
+    }
+
+    /// Returns the balance of an account.
+    pub fn balance(&mut self, address: EthersAddress) -> Result<EthersU256, EthApiError> {
+        let info = self.db().basic(B160::from(address.0))?;
+        Ok(info.map(|info| info.balance).unwrap_or_default().into())
+    }
+
+    /// Sets the balance of an account, like `vm.deal`.
+    pub fn set_balance(
+        &mut self,
+        address: EthersAddress,
+        balance: EthersU256,
+    ) -> Result<(), EthApiError> {
+        self.update_account(address, |info| info.balance = balance.into_reth())
+    }
+
+    /// Sets the nonce of an account, like `vm.setNonce`.
+    pub fn set_nonce(&mut self, address: EthersAddress, nonce: u64) -> Result<(), EthApiError> {
+        self.update_account(address, |info| info.nonce = nonce)
+    }
+
+    /// Replaces the code of an account, like `vm.etch`.
+    pub fn set_code(
+        &mut self,
+        address: EthersAddress,
+        code: EthersBytes,
+    ) -> Result<(), EthApiError> {
+        let code = Bytecode::new_raw(code.0);
+        self.update_account(address, |info| {
+            info.code_hash = code.hash_slow();
+            info.code = Some(code);
+        })
+    }
+
+    /// Returns the value of a storage slot.
+    pub fn storage_at(
+        &mut self,
+        address: EthersAddress,
+        slot: EthersH256,
+    ) -> Result<EthersH256, EthApiError> {
+        let value = self.db().storage(B160::from(address.0), U256::from_be_bytes(slot.0))?;
+        Ok(EthersH256(value.to_be_bytes()))
+    }
+
+    /// Sets the value of a storage slot, like `vm.store`.
+    pub fn set_storage_at(
+        &mut self,
+        address: EthersAddress,
+        slot: EthersH256,
+        value: EthersH256,
+    ) -> Result<(), EthApiError> {
+        self.db().insert_account_storage(
+            B160::from(address.0),
+            U256::from_be_bytes(slot.0),
+            U256::from_be_bytes(value.0),
+        )?;
+        Ok(())
+    }
+
+    /// Sends the transactions without a `from` from `address`, like `vm.startPrank`.
+    /// Transactions of the overlay are not signed, so any account can send them.
+    pub fn impersonate(&mut self, address: EthersAddress) {
+        self.impersonated = Some(B160::from(address.0));
+    }
+
+    /// Stops sending the transactions without a `from` from the impersonated account.
+    pub fn stop_impersonating(&mut self) {
+        self.impersonated = None;
+    }
+
+    /// Snapshots the overlay and its block environment, like `evm_snapshot`. Returns the id to
+    /// revert to.
+    pub fn snapshot(&mut self) -> u64 {
+        let db = self.evm.db.as_ref().expect("database is set");
+        self.snapshots.take(db, &self.evm.env.block, self.block)
+    }
+
+    /// Reverts the overlay to a snapshot, like `evm_revert`: the snapshot and the ones taken after
+    /// it are dropped. Returns false if there is no such snapshot.
+    pub fn revert(&mut self, id: u64) -> bool {
+        let db = self.evm.db.as_mut().expect("database is set");
+        match self.snapshots.revert(id, db, &mut self.evm.env.block) {
+            Some(block) => {
+                self.block = block;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Seals the transactions executed since the last block into a block and opens the next
+    /// one, e.g. to simulate scenarios spanning several blocks like vesting cliffs.
+    pub fn mine_block(&mut self, options: MineOptions) -> OverlayBlock {
+        let env = &mut self.evm.env.block;
+        let number = env.number.to::<u64>();
+        let hash = B256::from(keccak256(
+            [self.block.parent_hash.as_bytes(), &number.to_be_bytes()].concat(),
+        ));
+        let mined = OverlayBlock {
+            number,
+            hash: EthersH256(hash.0),
+            timestamp: env.timestamp.to::<u64>(),
+            base_fee: env.basefee.into(),
+            gas_used: self.block.gas_used,
+            transaction_count: self.block.transaction_count,
+        };
+
+        let base_fee = match options.base_fee {
+            Some(base_fee) => base_fee.into_reth(),
+            None => {
+                let header = Header {
+                    gas_used: mined.gas_used,
+                    gas_limit: env.gas_limit.to::<u64>(),
+                    base_fee_per_gas: Some(env.basefee.to::<u64>()),
+                    ..Default::default()
+                };
+                U256::from(header.next_block_base_fee().unwrap_or_default())
+            }
+        };
+        env.number = U256::from(number + 1);
+        env.timestamp = U256::from(options.timestamp.unwrap_or(mined.timestamp + SLOT_DURATION));
+        env.basefee = base_fee;
+
+        self.db().block_hashes.insert(U256::from(number), hash);
+        self.block = OpenBlock { parent_hash: hash, gas_used: 0, transaction_count: 0 };
+        mined
+    }
+
+    /// Executes a call without keeping its changes.
+    pub fn call(&mut self, tx: &TypedTransaction) -> Result<EthersBytes, EthApiError> {
+        self.execute(tx, false)
+    }
+
+    /// Executes a transaction and keeps its changes, whether it succeeds or not.
+    pub fn transact(&mut self, tx: &TypedTransaction) -> Result<EthersBytes, EthApiError> {
+        self.execute(tx, true)
+    }
+
+    /// Deploys `init_code` at its CREATE2 address as `deployer` would with `salt`, and returns
+    /// the address, e.g. to call a counterfactual wallet or hook before it is deployed.
+    ///
+    /// The constructor runs with `deployer` as its sender, whose nonce is incremented as CREATE2
+    /// does. Deployers without a factory, e.g. accounts, deploy just as well.
+    pub fn deploy_create2(
+        &mut self,
+        deployer: EthersAddress,
+        salt: EthersH256,
+        init_code: EthersBytes,
+    ) -> Result<EthersAddress, EthApiError> {
+        let address = get_create2_address_from_hash(deployer, salt, keccak256(&init_code));
+        let original = self.db().basic(B160::from(deployer.0))?.unwrap_or_default();
+        self.set_code(deployer, EthersBytes::from(CREATE2_DEPLOYER_CODE))?;
+
+        let tx = TransactionRequest::new()
+            .from(EthersAddress::zero())
+            .to(deployer)
+            .data([salt.as_bytes(), &init_code].concat())
+            .into();
+        let result = self.transact(&tx);
+        self.update_account(deployer, |info| {
+            info.code_hash = original.code_hash;
+            info.code = original.code;
+        })?;
+        result?;
+        Ok(address)
+    }
+
+    fn execute(&mut self, tx: &TypedTransaction, commit: bool) -> Result<EthersBytes, EthApiError> {
+        let base_fee = self.evm.env.block.basefee;
+        if tx.gas_price().is_none() {
+            // unpriced transactions are not charged, as with `eth_call`
+            self.evm.env.block.basefee = U256::ZERO;
+        }
+        self.evm.env.tx = call_tx_env(tx, self.gas_cap);
+        if let (None, Some(sender)) = (tx.from(), self.impersonated) {
+            self.evm.env.tx.caller = sender;
+        }
+        let result = if commit {
+            self.precompiles.transact_commit(&mut self.evm)
+        } else {
+            self.precompiles.transact(&mut self.evm).map(|result| result.result)
+        };
+        self.evm.env.block.basefee = base_fee;
+        let result = result?;
+        if commit {
+            self.block.gas_used += result.gas_used();
+            self.block.transaction_count += 1;
+        }
+        call_output(result)
+    }
+
+    fn update_account(
+        &mut self,
+        address: EthersAddress,
+        update: impl FnOnce(&mut AccountInfo),
+    ) -> Result<(), EthApiError> {
+        let address = B160::from(address.0);
+        let db = self.db();
+        let mut info = db.basic(address)?.unwrap_or_default();
+        update(&mut info);
+        db.insert_account_info(address, info);
+        Ok(())
+    }
+
+    fn db(&mut self) -> &mut CacheDB<RethDatabaseRef<'a>> {
+        self.evm.db.as_mut().expect("database is set")
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Forks the chain at `block`. Transactions sent to the overlay execute in the block after
+    /// it, [SLOT_DURATION] seconds later, under the simulator's precompile overrides.
+    pub fn fork_overlay<T: Into<EthersBlockId>>(
+        &self,
+        block: T,
+    ) -> Result<ForkOverlay<'_>, RethMiddlewareError<M>> {
+        let block_id: BlockId = block.into().into_reth();
+        record_block(&block_id);
+        self.ensure_history(Some(block_id))?;
+        self.ensure_fresh(Some(block_id))?;
+        self.ensure_forkchoice(Some(block_id))?;
+
+        let (block, _) = self.sealed_block_with_senders(block_id)?;
+        let mut env = self.block_env(&block)?;
+        env.block.number = U256::from(block.number + 1);
+        env.block.timestamp = U256::from(block.timestamp + SLOT_DURATION);
+        env.block.basefee = U256::from(block.next_block_base_fee().unwrap_or_default());
+        let gas_cap = self.gas_cap(block.gas_limit);
+
+        let state = RethDatabaseRef::at_block(&self.reth_provider, block.hash())?;
+        let mut evm = EVM::with_env(env);
+        evm.database(CacheDB::new(state));
+        Ok(ForkOverlay {
+            evm,
+            precompiles: self.precompile_overrides.clone(),
+            gas_cap,
+            impersonated: None,
+            block: OpenBlock {
+                parent_hash: B256::from(block.hash().0),
+                gas_used: 0,
+                transaction_count: 0,
+            },
+            snapshots: Snapshots::default(),
+        })
+    }
+}
@@ -0,0 +1,135 @@
+use crate::{limits::CallDeadline, RethMiddleware};
+use ethers::{providers::Middleware, types::Address as EthersAddress};
+
+// Reth
+use reth_revm::{
+    interpreter::{CallInputs, Gas, InstructionResult, Interpreter},
+    precompile::{Error as PrecompileError, PrecompileResult},
+    primitives::{Bytes, EVMError, EVMResult, ExecutionResult, B160},
+    Database, DatabaseCommit, EVMData, Inspector, EVM,
+};
+
+// Std
+use std::{collections::HashMap, fmt, sync::Arc};
+
+/// Implementation of a precompile: given the call input and gas limit, returns the gas used and
+/// the output.
+pub type PrecompileFn = Arc<dyn Fn(&[u8], u64) -> PrecompileResult + Send + Sync>;
+
+/// Precompiles added to, or replacing the ones of, the local simulator, e.g. to mock the point
+/// evaluation precompile or try out a new one against real state.
+///
+/// Calls to an overridden address run the override instead of any code deployed there. As with
+/// the built-in precompiles, a failing override consumes all the gas of the call. Value sent
+/// along is not transferred.
+#[derive(Clone, Default)]
+pub struct PrecompileOverrides {
+    precompiles: HashMap<B160, PrecompileFn>,
+}
+
+impl PrecompileOverrides {
+    /// Runs `precompile` for calls to `address`.
+    pub fn insert(
+        mut self,
+        address: EthersAddress,
+        precompile: impl Fn(&[u8], u64) -> PrecompileResult + Send + Sync + 'static,
+    ) -> Self {
+        self.precompiles.insert(B160::from(address.0), Arc::new(precompile));
+        self
+    }
+
+    /// Restores the default behavior of `address`.
+    pub fn remove(mut self, address: EthersAddress) -> Self {
+        self.precompiles.remove(&B160::from(address.0));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.precompiles.is_empty()
+    }
+
+    /// Executes the transaction of `evm`, with the overrides if there are any, halting at the
+    /// deadline of the running call.
+    pub(crate) fn transact<DB: Database>(&self, evm: &mut EVM<DB>) -> EVMResult<DB::Error> {
+        match CallDeadline::current() {
+            None if self.is_empty() => evm.transact(),
+            deadline => evm.inspect(CallInspector { overrides: self, deadline }),
+        }
+    }
+
+    /// Executes the transaction of `evm` and commits its changes, with the overrides if there
+    /// are any, halting at the deadline of the running call.
+    pub(crate) fn transact_commit<DB: Database + DatabaseCommit>(
+        &self,
+        evm: &mut EVM<DB>,
+    ) -> Result<ExecutionResult, EVMError<DB::Error>> {
+        match CallDeadline::current() {
+            None if self.is_empty() => evm.transact_commit(),
+            deadline => evm.inspect_commit(CallInspector { overrides: self, deadline }),
+        }
+    }
+
+    /// Runs the override of the called address, if there is one.
+    fn call(&self, inputs: &CallInputs) -> (InstructionResult, Gas, Bytes) {
+        let Some(precompile) = self.precompiles.get(&inputs.contract) else {
+            return (InstructionResult::Continue, Gas::new(0), Bytes::new())
+        };
+
+        let mut gas = Gas::new(inputs.gas_limit);
+        match precompile(&inputs.input, inputs.gas_limit) {
+            Ok((gas_used, output)) if gas.record_cost(gas_used) => {
+                (InstructionResult::Return, gas, output.into())
+            }
+            Ok(_) | Err(PrecompileError::OutOfGas) => {
+                (InstructionResult::PrecompileOOG, gas, Bytes::new())
+            }
+            Err(_) => (InstructionResult::PrecompileError, gas, Bytes::new()),
+        }
+    }
+}
+
+impl fmt::Debug for PrecompileOverrides {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.precompiles.keys()).finish()
+    }
+}
+
+/// Inspector of the local calls: runs the precompile overrides, and halts at the deadline of the
+/// running call.
+struct CallInspector<'a> {
+    overrides: &'a PrecompileOverrides,
+    deadline: Option<CallDeadline>,
+}
+
+impl<DB: Database> Inspector<DB> for CallInspector<'_> {
+    fn step(&mut self, interp: &mut Interpreter, data: &mut EVMData<'_, DB>) -> InstructionResult {
+        match &mut self.deadline {
+            Some(deadline) => deadline.step(interp, data),
+            None => InstructionResult::Continue,
+        }
+    }
+
+    fn call(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        inputs: &mut CallInputs,
+    ) -> (InstructionResult, Gas, Bytes) {
+        self.overrides.call(inputs)
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Sets the precompiles overridden in the local simulator.
+    pub fn with_precompile_overrides(mut self, precompiles: PrecompileOverrides) -> Self {
+        self.precompile_overrides = precompiles;
+        self
+    }
+
+    /// Returns the precompiles overridden in the local simulator.
+    pub fn precompile_overrides(&self) -> &PrecompileOverrides {
+        &self.precompile_overrides
+    }
+}
@@ -0,0 +1,239 @@
+use crate::{
+    instrument::instrument, limits::CallDeadline, type_conversions::ToReth, RethMiddleware,
+    RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Address as EthersAddress, H256 as EthersH256},
+};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_revm::{
+    interpreter::{
+        opcode::OPCODE_JUMPMAP, CallInputs, CreateInputs, Gas, InstructionResult, Interpreter,
+    },
+    primitives::{Bytes, B160, B256},
+    Database, EVMData, Inspector, EVM,
+};
+use reth_rpc::eth::error::EthApiError;
+
+// Std
+use std::collections::HashMap;
+
+/// How often an opcode ran and the gas it cost.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpcodeStats {
+    pub opcode: String,
+    pub count: u64,
+    pub gas: u64,
+}
+
+/// An instruction of a contract and the gas it cost over the whole transaction.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Hotspot {
+    /// The contract whose code the instruction belongs to, the caller's for delegate calls.
+    pub address: EthersAddress,
+    pub pc: usize,
+    pub opcode: String,
+    pub count: u64,
+    pub gas: u64,
+}
+
+/// Where a transaction spent its gas, per opcode and per instruction.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GasProfile {
+    pub transaction_hash: EthersH256,
+    /// Gas used by the transaction, intrinsic gas and refunds included.
+    pub gas_used: u64,
+    /// Every opcode executed, the most expensive first.
+    pub opcodes: Vec<OpcodeStats>,
+    /// The most expensive instructions, the most expensive first.
+    pub hotspots: Vec<Hotspot>,
+}
+
+/// An instruction being executed.
+#[derive(Debug)]
+struct Step {
+    address: B160,
+    pc: usize,
+    code_hash: B256,
+    opcode: u8,
+    gas_remaining: u64,
+    /// Gas used by the frames the instruction opened.
+    frames_gas: u64,
+}
+
+/// An [Inspector] counting the executions and the gas of every opcode and instruction.
+///
+/// The gas of a `CALL` or `CREATE` excludes what the frame it opens spends, which is counted on
+/// the instructions of that frame.
+#[derive(Debug, Default)]
+pub struct OpcodeProfiler {
+    steps: Vec<Step>,
+    opcodes: HashMap<u8, (u64, u64)>,
+    locations: HashMap<(B160, usize), (u8, u64, u64)>,
+    /// Executions and gas per instruction of a code, wherever it ran.
+    code_locations: HashMap<(B256, usize), (u64, u64)>,
+    deadline: Option<CallDeadline>,
+}
+
+impl OpcodeProfiler {
+    /// Returns a profiler halting at the deadline of the call running on this thread, if any.
+    pub fn new() -> Self {
+        Self { deadline: CallDeadline::current(), ..Default::default() }
+    }
+
+    /// Returns the stats of every opcode and the `top` most expensive instructions.
+    pub fn into_stats(self, top: usize) -> (Vec<OpcodeStats>, Vec<Hotspot>) {
+        let mut opcodes: Vec<_> = self
+            .opcodes
+            .into_iter()
+            .map(|(opcode, (count, gas))| OpcodeStats { opcode: opcode_name(opcode), count, gas })
+            .collect();
+        opcodes.sort_by(|a, b| b.gas.cmp(&a.gas).then_with(|| a.opcode.cmp(&b.opcode)));
+
+        let mut hotspots: Vec<_> = self
+            .locations
+            .into_iter()
+            .map(|((address, pc), (opcode, count, gas))| Hotspot {
+                address: EthersAddress::from(address.0),
+                pc,
+                opcode: opcode_name(opcode),
+                count,
+                gas,
+            })
+            .collect();
+        hotspots.sort_by(|a, b| {
+            b.gas.cmp(&a.gas).then_with(|| (a.address, a.pc).cmp(&(b.address, b.pc)))
+        });
+        hotspots.truncate(top);
+
+        (opcodes, hotspots)
+    }
+
+    /// Returns every instruction executed, as the hash of its code, its program counter, how
+    /// often it ran and the gas it cost.
+    pub(crate) fn instructions(&self) -> impl Iterator<Item = (B256, usize, u64, u64)> + '_ {
+        self.code_locations.iter().map(|((hash, pc), (count, gas))| (*hash, *pc, *count, *gas))
+    }
+
+    /// Adds the gas a frame used to the instruction that opened it, if it was not the top-level
+    /// frame.
+    fn end_frame(&mut self, gas: &Gas) {
+        if let Some(step) = self.steps.last_mut() {
+            step.frames_gas += gas.spend();
+        }
+    }
+}
+
+impl<DB: Database> Inspector<DB> for OpcodeProfiler {
+    fn step(&mut self, interp: &mut Interpreter, _data: &mut EVMData<'_, DB>) -> InstructionResult {
+        if self.deadline.as_mut().map_or(false, CallDeadline::passed) {
+            return InstructionResult::OutOfGas
+        }
+        self.steps.push(Step {
+            address: interp.contract.address,
+            pc: interp.program_counter(),
+            code_hash: interp.contract.hash,
+            opcode: interp.current_opcode(),
+            gas_remaining: interp.gas.remaining(),
+            frames_gas: 0,
+        });
+        InstructionResult::Continue
+    }
+
+    fn step_end(
+        &mut self,
+        interp: &mut Interpreter,
+        _data: &mut EVMData<'_, DB>,
+        _eval: InstructionResult,
+    ) -> InstructionResult {
+        let Some(step) = self.steps.pop() else { return InstructionResult::Continue };
+        let gas = step
+            .gas_remaining
+            .saturating_sub(interp.gas.remaining())
+            .saturating_sub(step.frames_gas);
+
+        let (count, total) = self.opcodes.entry(step.opcode).or_default();
+        *count += 1;
+        *total += gas;
+        let (_, count, total) =
+            self.locations.entry((step.address, step.pc)).or_insert((step.opcode, 0, 0));
+        *count += 1;
+        *total += gas;
+        let (count, total) = self.code_locations.entry((step.code_hash, step.pc)).or_default();
+        *count += 1;
+        *total += gas;
+        InstructionResult::Continue
+    }
+
+    fn call_end(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        _inputs: &CallInputs,
+        remaining_gas: Gas,
+        ret: InstructionResult,
+        out: Bytes,
+    ) -> (InstructionResult, Gas, Bytes) {
+        self.end_frame(&remaining_gas);
+        (ret, remaining_gas, out)
+    }
+
+    fn create_end(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        _inputs: &CreateInputs,
+        ret: InstructionResult,
+        address: Option<B160>,
+        remaining_gas: Gas,
+        out: Bytes,
+    ) -> (InstructionResult, Option<B160>, Gas, Bytes) {
+        self.end_frame(&remaining_gas);
+        (ret, address, remaining_gas, out)
+    }
+}
+
+/// Returns the mnemonic of an opcode, its hex value if it is not defined.
+fn opcode_name(opcode: u8) -> String {
+    OPCODE_JUMPMAP[opcode as usize]
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("UNDEFINED(0x{opcode:02x})"))
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Replays a mined transaction on top of the state it ran on and reports the gas spent per
+    /// opcode, along with the `top` most expensive instructions. The replay runs under the call
+    /// timeout, holding a heavy request slot.
+    pub async fn profile_transaction(
+        &self,
+        tx_hash: EthersH256,
+        top: usize,
+    ) -> Result<GasProfile, RethMiddlewareError<M>> {
+        instrument(
+            "profile_transaction",
+            self.with_heavy_permit(self.run_call(|| {
+                let (env, db) = self.transaction_replay(tx_hash.into_reth())?;
+                let mut evm = EVM::with_env(env);
+                evm.database(db);
+
+                let mut profiler = OpcodeProfiler::new();
+                let result = evm.inspect(&mut profiler).map_err(EthApiError::from)?.result;
+                let (opcodes, hotspots) = profiler.into_stats(top);
+                Ok(GasProfile {
+                    transaction_hash: tx_hash,
+                    gas_used: result.gas_used(),
+                    opcodes,
+                    hotspots,
+                })
+            })),
+        )
+        .await
+    }
+}
@@ -0,0 +1,75 @@
+// Reth
+use reth_revm::{
+    primitives::{AccountInfo, Bytecode, B160, B256, U256},
+    DatabaseRef,
+};
+
+// Std
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+};
+
+/// State read by an execution.
+#[derive(Debug, Clone, Default)]
+pub struct StateAccesses {
+    /// Accessed accounts and the storage slots read from each of them.
+    pub accounts: BTreeMap<B160, BTreeSet<U256>>,
+    /// Bytecodes loaded during execution, keyed by code hash.
+    pub codes: BTreeMap<B256, Bytecode>,
+    /// Ancestor block hashes read through `BLOCKHASH`.
+    pub block_hashes: BTreeMap<u64, B256>,
+}
+
+/// A [DatabaseRef] wrapper recording every account, storage slot, bytecode and block hash read
+/// from the underlying database.
+#[derive(Debug)]
+pub struct AccessRecorder<DB> {
+    inner: DB,
+    accesses: RefCell<StateAccesses>,
+}
+
+impl<DB> AccessRecorder<DB> {
+    pub fn new(inner: DB) -> Self {
+        Self { inner, accesses: RefCell::new(StateAccesses::default()) }
+    }
+
+    /// Consumes the recorder, returning everything read so far.
+    pub fn into_accesses(self) -> StateAccesses {
+        self.accesses.into_inner()
+    }
+}
+
+impl<DB: DatabaseRef> DatabaseRef for AccessRecorder<DB> {
+    type Error = DB::Error;
+
+    fn basic(&self, address: B160) -> Result<Option<AccountInfo>, Self::Error> {
+        let info = self.inner.basic(address)?;
+
+        let mut accesses = self.accesses.borrow_mut();
+        accesses.accounts.entry(address).or_default();
+        if let Some(code) = info.as_ref().and_then(|info| info.code.clone()) {
+            accesses.codes.insert(code.hash(), code);
+        }
+
+        Ok(info)
+    }
+
+    fn code_by_hash(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        let code = self.inner.code_by_hash(code_hash)?;
+        self.accesses.borrow_mut().codes.insert(code_hash, code.clone());
+        Ok(code)
+    }
+
+    fn storage(&self, address: B160, index: U256) -> Result<U256, Self::Error> {
+        let value = self.inner.storage(address, index)?;
+        self.accesses.borrow_mut().accounts.entry(address).or_default().insert(index);
+        Ok(value)
+    }
+
+    fn block_hash(&self, number: U256) -> Result<B256, Self::Error> {
+        let hash = self.inner.block_hash(number)?;
+        self.accesses.borrow_mut().block_hashes.insert(number.to::<u64>(), hash);
+        Ok(hash)
+    }
+}
@@ -0,0 +1,266 @@
+use crate::{
+    instrument::instrument,
+    limits::CallDeadline,
+    type_conversions::{ToEthers, ToReth},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{
+        Address as EthersAddress, Bytes as EthersBytes, Log as EthersLog, TxHash as EthersTxHash,
+        H256 as EthersH256, U256 as EthersU256,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_revm::{
+    primitives::{ResultAndState, TxEnv, B160},
+    EVM,
+};
+use reth_rpc::eth::error::EthApiError;
+
+// Std
+use std::collections::BTreeMap;
+
+/// Changes to a historical transaction replayed with [RethMiddleware::replay_modified]. Unset
+/// fields keep the transaction's.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionModifications {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<EthersBytes>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<EthersU256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gas: Option<u64>,
+    /// Another sender, whose nonce is not checked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<EthersAddress>,
+}
+
+impl TransactionModifications {
+    fn apply(&self, tx: &mut TxEnv) {
+        if let Some(input) = &self.input {
+            tx.data = input.0.clone();
+        }
+        if let Some(value) = self.value {
+            tx.value = value.into_reth();
+        }
+        if let Some(gas) = self.gas {
+            tx.gas_limit = gas;
+        }
+        if let Some(from) = self.from {
+            tx.caller = B160::from(from.0);
+            tx.nonce = None;
+        }
+    }
+}
+
+/// What executing a transaction did, without committing it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulationOutcome {
+    pub success: bool,
+    pub gas_used: u64,
+    /// Return data, or revert data.
+    pub output: EthersBytes,
+    pub logs: Vec<EthersLog>,
+    /// Balances after the transaction of the accounts it accessed.
+    pub balances: BTreeMap<EthersAddress, EthersU256>,
+    /// Storage slots the transaction changed, with their values after it.
+    pub storage: BTreeMap<EthersAddress, BTreeMap<EthersH256, EthersH256>>,
+}
+
+impl From<ResultAndState> for SimulationOutcome {
+    fn from(ResultAndState { result, state }: ResultAndState) -> Self {
+        let logs = result
+            .logs()
+            .into_iter()
+            .map(|log| EthersLog {
+                address: EthersAddress::from(log.address.0),
+                topics: log.topics.iter().map(|topic| EthersH256(topic.0)).collect(),
+                data: log.data.into(),
+                ..Default::default()
+            })
+            .collect();
+
+        let mut balances = BTreeMap::new();
+        let mut storage = BTreeMap::new();
+        for (address, account) in state {
+            let address = EthersAddress::from(address.0);
+            balances.insert(address, account.info.balance.into_ethers());
+            let written: BTreeMap<_, _> = account
+                .storage
+                .iter()
+                .filter(|(_, value)| value.is_changed())
+                .map(|(slot, value)| {
+                    (EthersH256(slot.to_be_bytes()), EthersH256(value.present_value.to_be_bytes()))
+                })
+                .collect();
+            if !written.is_empty() {
+                storage.insert(address, written);
+            }
+        }
+
+        Self {
+            success: result.is_success(),
+            gas_used: result.gas_used(),
+            output: result.output().cloned().unwrap_or_default().into(),
+            logs,
+            balances,
+            storage,
+        }
+    }
+}
+
+/// A value in two simulation outcomes, `None` where the outcome has none, e.g. an account it did
+/// not access.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change<T> {
+    pub first: Option<T>,
+    pub second: Option<T>,
+}
+
+/// How a simulation outcome differs from another, e.g. of the same transaction simulated with a
+/// different parameter. Only what differs is listed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutcomeDelta {
+    /// Gas the second outcome used more than the first, negative if less.
+    pub gas_used: i128,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub success: Option<Change<bool>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<Change<EthersBytes>>,
+    pub balances: BTreeMap<EthersAddress, Change<EthersU256>>,
+    pub storage: BTreeMap<EthersAddress, BTreeMap<EthersH256, Change<EthersH256>>>,
+    /// Logs of the first outcome the second does not have.
+    pub removed_logs: Vec<EthersLog>,
+    /// Logs of the second outcome the first does not have.
+    pub added_logs: Vec<EthersLog>,
+}
+
+impl OutcomeDelta {
+    /// Returns true if the outcomes are the same.
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Returns the values of two maps that differ, keyed like them.
+fn map_delta<K: Ord + Copy, V: PartialEq + Copy>(
+    first: &BTreeMap<K, V>,
+    second: &BTreeMap<K, V>,
+) -> BTreeMap<K, Change<V>> {
+    first
+        .keys()
+        .chain(second.keys())
+        .map(|key| {
+            (*key, Change { first: first.get(key).copied(), second: second.get(key).copied() })
+        })
+        .filter(|(_, change)| change.first != change.second)
+        .collect()
+}
+
+impl SimulationOutcome {
+    /// Compares the outcome, as the first, against `other`.
+    pub fn diff(&self, other: &SimulationOutcome) -> OutcomeDelta {
+        let change = |first, second| {
+            (first != second).then_some(Change { first: Some(first), second: Some(second) })
+        };
+
+        let empty = BTreeMap::new();
+        let mut storage = BTreeMap::new();
+        for address in self.storage.keys().chain(other.storage.keys()) {
+            let slots = map_delta(
+                self.storage.get(address).unwrap_or(&empty),
+                other.storage.get(address).unwrap_or(&empty),
+            );
+            if !slots.is_empty() {
+                storage.insert(*address, slots);
+            }
+        }
+
+        // logs match one to one, so repeated logs count as many times as they are emitted
+        let mut added_logs = other.logs.clone();
+        let mut removed_logs = vec![];
+        for log in &self.logs {
+            match added_logs.iter().position(|added| added == log) {
+                Some(index) => {
+                    added_logs.remove(index);
+                }
+                None => removed_logs.push(log.clone()),
+            }
+        }
+
+        OutcomeDelta {
+            gas_used: other.gas_used as i128 - self.gas_used as i128,
+            success: change(self.success, other.success),
+            output: (self.output != other.output).then(|| Change {
+                first: Some(self.output.clone()),
+                second: Some(other.output.clone()),
+            }),
+            balances: map_delta(&self.balances, &other.balances),
+            storage,
+            removed_logs,
+            added_logs,
+        }
+    }
+}
+
+/// A historical transaction replayed as it was and with modifications, on the same state.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModifiedReplay {
+    pub original: SimulationOutcome,
+    pub modified: SimulationOutcome,
+}
+
+impl ModifiedReplay {
+    /// Gas the modified transaction used more than the original, negative if less.
+    pub fn gas_delta(&self) -> i128 {
+        self.modified.gas_used as i128 - self.original.gas_used as i128
+    }
+
+    /// Returns true if the modifications changed anything the transaction did.
+    pub fn is_changed(&self) -> bool {
+        self.original != self.modified
+    }
+
+    /// Returns what the modifications changed.
+    pub fn delta(&self) -> OutcomeDelta {
+        self.original.diff(&self.modified)
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Replays a mined transaction on the exact state it ran on, its block with the transactions
+    /// before it applied, then again with `modifications`, e.g. other calldata or a larger gas
+    /// limit, returning both outcomes.
+    ///
+    /// Precompile overrides are not applied, so the original replay matches the chain.
+    pub async fn replay_modified(
+        &self,
+        hash: EthersTxHash,
+        modifications: &TransactionModifications,
+    ) -> Result<ModifiedReplay, RethMiddlewareError<M>> {
+        instrument(
+            "replay_modified",
+            self.run_call(|| {
+                let (env, db) = self.transaction_replay(hash.into_reth())?;
+                let mut evm = EVM::with_env(env);
+                evm.database(db);
+
+                let original = CallDeadline::transact(&mut evm).map_err(EthApiError::from)?;
+                modifications.apply(&mut evm.env.tx);
+                let modified = CallDeadline::transact(&mut evm).map_err(EthApiError::from)?;
+                Ok(ModifiedReplay { original: original.into(), modified: modified.into() })
+            }),
+        )
+        .await
+    }
+}
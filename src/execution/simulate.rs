@@ -0,0 +1,231 @@
+use crate::{
+    database::RethDatabaseRef,
+    execution::{
+        call_many::{apply_state_overrides, BlockOverrides},
+        call_output, call_tx_env, SLOT_DURATION,
+    },
+    instrument::{instrument, record_block},
+    type_conversions::{ToEthers, ToReth},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{
+        spoof, transaction::eip2718::TypedTransaction, Address as EthersAddress,
+        Block as EthersBlock, BlockId as EthersBlockId, BlockNumber as EthersBlockNumber,
+        Bytes as EthersBytes, Log as EthersLog, H256 as EthersH256, U64 as EthersU64,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_primitives::{Address, BlockId, Header, H256};
+use reth_revm::{
+    db::CacheDB,
+    primitives::{ExecutionResult, U256},
+    EVM,
+};
+use reth_rpc::eth::error::EthApiError;
+
+/// Most blocks a single simulation may span.
+pub const MAX_SIMULATED_BLOCKS: usize = 256;
+
+/// Calls to execute in one simulated block, with the overrides that apply to it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockStateCalls {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_overrides: Option<BlockOverrides>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_overrides: Option<spoof::State>,
+    #[serde(default)]
+    pub calls: Vec<TypedTransaction>,
+}
+
+/// Request of `eth_simulateV1`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulatePayload {
+    pub block_state_calls: Vec<BlockStateCalls>,
+    /// Enforce nonces and base fees as a real block would. Without it, blocks get a zero base fee
+    /// unless overridden.
+    #[serde(default)]
+    pub validation: bool,
+}
+
+/// Outcome of a simulated call.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulatedCall {
+    pub return_data: EthersBytes,
+    pub logs: Vec<EthersLog>,
+    pub gas_used: EthersU64,
+    /// 1 on success, 0 if the call reverted or halted.
+    pub status: EthersU64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A simulated block: its header fields, the hashes of its calls and their outcomes. The state,
+/// transactions and receipts roots are not computed and left zero.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SimulatedBlock {
+    #[serde(flatten)]
+    pub block: EthersBlock<EthersH256>,
+    pub calls: Vec<SimulatedCall>,
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Simulates a sequence of blocks on top of `block`, defaulting to the latest one, like
+    /// `eth_simulateV1`. Every block sees the state left by the previous ones.
+    ///
+    /// Blocks follow each other by one number and [SLOT_DURATION] seconds unless overridden.
+    /// Calls are identified by the hash of their unsigned transaction.
+    pub async fn simulate(
+        &self,
+        payload: &SimulatePayload,
+        block: Option<EthersBlockId>,
+    ) -> Result<Vec<SimulatedBlock>, RethMiddlewareError<M>> {
+        instrument(
+            "simulate",
+            self.with_heavy_permit(self.run_call(|| {
+                if payload.block_state_calls.len() > MAX_SIMULATED_BLOCKS {
+                    return Err(EthApiError::InvalidParams(format!(
+                        "at most {MAX_SIMULATED_BLOCKS} blocks can be simulated"
+                    ))
+                    .into())
+                }
+
+                let block_id: BlockId =
+                    block.unwrap_or(EthersBlockNumber::Latest.into()).into_reth();
+                record_block(&block_id);
+                self.ensure_history(Some(block_id))?;
+                self.ensure_fresh(Some(block_id))?;
+                self.ensure_forkchoice(Some(block_id))?;
+
+                let (base, _) = self.sealed_block_with_senders(block_id)?;
+                let base_env = self.block_env(&base)?;
+                let state = RethDatabaseRef::at_block(&self.reth_provider, base.hash())?;
+                let mut db = CacheDB::new(state);
+                let mut parent = base.header.clone();
+
+                let mut blocks = Vec::with_capacity(payload.block_state_calls.len());
+                for block_calls in &payload.block_state_calls {
+                    if let Some(overrides) = &block_calls.state_overrides {
+                        apply_state_overrides(overrides, &mut db)?;
+                    }
+
+                    let mut env = base_env.clone();
+                    env.block.number = U256::from(parent.number + 1);
+                    env.block.timestamp = U256::from(parent.timestamp + SLOT_DURATION);
+                    env.block.basefee = if payload.validation {
+                        U256::from(parent.next_block_base_fee().unwrap_or_default())
+                    } else {
+                        U256::ZERO
+                    };
+                    if let Some(block_overrides) = &block_calls.block_overrides {
+                        block_overrides.apply(&mut env);
+                    }
+                    let gas_limit = env.block.gas_limit.to::<u64>();
+                    let gas_cap = self.gas_cap(gas_limit);
+
+                    let mut header = Header {
+                        parent_hash: parent.hash,
+                        number: env.block.number.to::<u64>(),
+                        timestamp: env.block.timestamp.to::<u64>(),
+                        beneficiary: Address::from(env.block.coinbase.0),
+                        difficulty: env.block.difficulty,
+                        mix_hash: env
+                            .block
+                            .prevrandao
+                            .map_or_else(H256::zero, |randao| randao.0.into()),
+                        gas_limit,
+                        base_fee_per_gas: parent
+                            .base_fee_per_gas
+                            .map(|_| env.block.basefee.to::<u64>()),
+                        ..Default::default()
+                    };
+
+                    let mut evm = EVM::with_env(env);
+                    evm.database(db);
+                    let mut calls = Vec::with_capacity(block_calls.calls.len());
+                    let mut hashes = Vec::with_capacity(block_calls.calls.len());
+                    let mut log_index = 0u64;
+                    for tx in &block_calls.calls {
+                        evm.env.tx =
+                            call_tx_env(tx, gas_cap.min(gas_limit.saturating_sub(header.gas_used)));
+                        if payload.validation {
+                            evm.env.tx.nonce = tx.nonce().map(|nonce| nonce.as_u64());
+                        }
+                        let result = self
+                            .precompile_overrides
+                            .transact_commit(&mut evm)
+                            .map_err(EthApiError::from)?;
+                        header.gas_used += result.gas_used();
+
+                        let hash = tx.sighash();
+                        let logs = result
+                            .logs()
+                            .iter()
+                            .map(|log| {
+                                log_index += 1;
+                                EthersLog {
+                                    address: EthersAddress::from(log.address.0),
+                                    topics: log
+                                        .topics
+                                        .iter()
+                                        .map(|topic| EthersH256::from(topic.0))
+                                        .collect(),
+                                    data: log.data.clone().into(),
+                                    block_number: Some(header.number.into()),
+                                    transaction_hash: Some(hash),
+                                    transaction_index: Some(hashes.len().into()),
+                                    log_index: Some((log_index - 1).into()),
+                                    removed: Some(false),
+                                    ..Default::default()
+                                }
+                            })
+                            .collect();
+                        let call = simulated_call(result, logs);
+                        self.ensure_result_size(call.return_data.len())?;
+                        calls.push(call);
+                        hashes.push(hash);
+                    }
+                    db = evm.db.take().expect("database is set");
+
+                    let sealed = header.seal_slow();
+                    let mut block: EthersBlock<EthersH256> = sealed.clone().into_ethers();
+                    block.transactions = hashes;
+                    for call in calls.iter_mut() {
+                        for log in call.logs.iter_mut() {
+                            log.block_hash = block.hash;
+                        }
+                    }
+                    parent = sealed;
+                    blocks.push(SimulatedBlock { block, calls });
+                }
+                Ok(blocks)
+            })),
+        )
+        .await
+    }
+}
+
+/// Converts the result of a committed call.
+fn simulated_call(result: ExecutionResult, logs: Vec<EthersLog>) -> SimulatedCall {
+    let gas_used = result.gas_used().into();
+    let status = u64::from(result.is_success()).into();
+    match call_output(result) {
+        Ok(return_data) => SimulatedCall { return_data, logs, gas_used, status, error: None },
+        Err(err) => SimulatedCall {
+            return_data: EthersBytes::default(),
+            logs: vec![],
+            gas_used,
+            status,
+            error: Some(err.to_string()),
+        },
+    }
+}
@@ -0,0 +1,71 @@
+use reth_revm::{
+    db::{CacheDB, DbAccount},
+    primitives::{BlockEnv, Bytecode, Log, B160, B256, U256},
+};
+
+// Std
+use std::collections::{BTreeMap, HashMap};
+
+/// The changes a simulation staged on top of the database, and the block it executes in.
+#[derive(Debug, Clone)]
+struct Snapshot<T> {
+    accounts: HashMap<B160, DbAccount>,
+    contracts: HashMap<B256, Bytecode>,
+    logs: Vec<Log>,
+    block_hashes: HashMap<U256, B256>,
+    block: BlockEnv,
+    extra: T,
+}
+
+/// Snapshots of a simulation, reverted to like with `evm_snapshot` and `evm_revert`. `T` is
+/// state the simulation keeps besides its database.
+#[derive(Debug, Clone)]
+pub(crate) struct Snapshots<T = ()> {
+    next_id: u64,
+    taken: BTreeMap<u64, Snapshot<T>>,
+}
+
+impl<T> Default for Snapshots<T> {
+    fn default() -> Self {
+        Self { next_id: 0, taken: BTreeMap::new() }
+    }
+}
+
+impl<T> Snapshots<T> {
+    /// Saves the staged changes of `db` and the block environment, returning the id of the
+    /// snapshot.
+    pub(crate) fn take<DB>(&mut self, db: &CacheDB<DB>, block: &BlockEnv, extra: T) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.taken.insert(
+            id,
+            Snapshot {
+                accounts: db.accounts.clone(),
+                contracts: db.contracts.clone(),
+                logs: db.logs.clone(),
+                block_hashes: db.block_hashes.clone(),
+                block: block.clone(),
+                extra,
+            },
+        );
+        id
+    }
+
+    /// Restores the snapshot with the given id, returning its extra state, `None` if there is
+    /// no such snapshot. The snapshot and the ones taken after it are dropped.
+    pub(crate) fn revert<DB>(
+        &mut self,
+        id: u64,
+        db: &mut CacheDB<DB>,
+        block: &mut BlockEnv,
+    ) -> Option<T> {
+        let snapshot = self.taken.remove(&id)?;
+        self.taken.split_off(&id);
+        db.accounts = snapshot.accounts;
+        db.contracts = snapshot.contracts;
+        db.logs = snapshot.logs;
+        db.block_hashes = snapshot.block_hashes;
+        *block = snapshot.block;
+        Some(snapshot.extra)
+    }
+}
@@ -0,0 +1,427 @@
+use crate::{
+    execution::profile::OpcodeProfiler, instrument::instrument, type_conversions::ToReth,
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Address as EthersAddress, H256 as EthersH256},
+};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_revm::{
+    interpreter::opcode::{PUSH1, PUSH32},
+    primitives::B160,
+    Database, EVM,
+};
+use reth_rpc::eth::error::EthApiError;
+
+// Std
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Range,
+};
+
+/// A source file of a contract.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceFile {
+    pub path: String,
+    pub content: String,
+}
+
+/// A deployed contract compiled with solc: the source map of its runtime code and its sources.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceArtifact {
+    /// Where the contract is deployed. Its code is matched wherever it runs, e.g. behind a proxy.
+    pub address: EthersAddress,
+    /// The `evm.deployedBytecode.sourceMap` output of the contract.
+    pub source_map: String,
+    /// The sources by their id in the source map, solc's `sources.<path>.id`.
+    pub sources: BTreeMap<u32, SourceFile>,
+}
+
+/// The gas spent on a line of source.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineGas {
+    pub path: String,
+    /// The line, starting at 1.
+    pub line: usize,
+    /// The function the line belongs to, `None` outside of functions.
+    pub function: Option<String>,
+    /// Instructions executed for the line.
+    pub count: u64,
+    pub gas: u64,
+}
+
+/// The gas spent in the body of a function, excluding the frames it opened.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionGas {
+    pub path: String,
+    pub function: String,
+    pub gas: u64,
+}
+
+/// Where a transaction spent its gas in the sources of the contracts it ran.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceGasReport {
+    pub transaction_hash: EthersH256,
+    /// Gas used by the transaction, intrinsic gas and refunds included.
+    pub gas_used: u64,
+    /// The functions, the most expensive first.
+    pub functions: Vec<FunctionGas>,
+    /// The lines, the most expensive first.
+    pub lines: Vec<LineGas>,
+    /// Gas of instructions without a source: of contracts without an artifact, of code the
+    /// compiler generated and of contract creations.
+    pub unmapped_gas: u64,
+}
+
+/// Decodes a compressed solc source map into the source file and the offset of every
+/// instruction. An empty field repeats the previous entry's.
+fn decode_source_map(source_map: &str) -> Vec<(Option<u32>, usize)> {
+    let (mut file, mut start) = (None, 0);
+    source_map
+        .split(';')
+        .map(|entry| {
+            let mut fields = entry.split(':');
+            if let Some(offset) = fields.next().and_then(|field| field.parse().ok()) {
+                start = offset;
+            }
+            // the length is not needed to find the line
+            fields.next();
+            if let Some(id) = fields.next().filter(|field| !field.is_empty()) {
+                // generated code has the file -1
+                file = id.parse().ok();
+            }
+            (file, start)
+        })
+        .collect()
+}
+
+/// Maps the program counter of every instruction of `code` to its index, which source maps are
+/// indexed by.
+fn instruction_indices(code: &[u8]) -> HashMap<usize, usize> {
+    let mut indices = HashMap::new();
+    let mut pc = 0;
+    while pc < code.len() {
+        indices.insert(pc, indices.len());
+        let opcode = code[pc];
+        pc += 1;
+        if (PUSH1..=PUSH32).contains(&opcode) {
+            pc += (opcode - PUSH1 + 1) as usize;
+        }
+    }
+    indices
+}
+
+/// Returns the length of the string literal `source` starts with.
+fn string_len(source: &[u8]) -> usize {
+    let quote = source[0];
+    let mut len = 1;
+    while len < source.len() {
+        match source[len] {
+            b'\\' => len += 2,
+            b'\n' => return len,
+            byte if byte == quote => return len + 1,
+            _ => len += 1,
+        }
+    }
+    source.len()
+}
+
+/// Blanks out the comments and string literals of a source, keeping offsets, so their braces and
+/// keywords are not taken for code.
+fn blank_comments_and_strings(source: &str) -> Vec<u8> {
+    let mut code = source.as_bytes().to_vec();
+    let mut offset = 0;
+    while offset < code.len() {
+        let rest = &source.as_bytes()[offset..];
+        let len = if rest.starts_with(b"//") {
+            rest.iter().position(|byte| *byte == b'\n').unwrap_or(rest.len())
+        } else if rest.starts_with(b"/*") {
+            rest.windows(2).skip(2).position(|pair| pair == b"*/").map_or(rest.len(), |end| end + 4)
+        } else if rest[0] == b'"' || rest[0] == b'\'' {
+            string_len(rest)
+        } else {
+            offset += 1;
+            continue
+        };
+        code[offset..offset + len].fill(b' ');
+        offset += len;
+    }
+    code
+}
+
+const FUNCTION_KEYWORDS: [&str; 5] = ["function", "modifier", "constructor", "fallback", "receive"];
+
+/// Finds the functions of a Solidity source and their spans, from their keyword to the end of
+/// their body. Functions without a body, e.g. of interfaces, are skipped.
+fn function_spans(source: &str) -> Vec<(String, Range<usize>)> {
+    let code = blank_comments_and_strings(source);
+    let is_identifier = |byte: &u8| byte.is_ascii_alphanumeric() || *byte == b'_' || *byte == b'$';
+    let word_at = |offset: usize| {
+        let len = code[offset..].iter().take_while(|byte| is_identifier(*byte)).count();
+        &source[offset..offset + len]
+    };
+
+    let mut spans = vec![];
+    let mut offset = 0;
+    while offset < code.len() {
+        if !is_identifier(&code[offset]) || (offset > 0 && is_identifier(&code[offset - 1])) {
+            offset += 1;
+            continue
+        }
+        let keyword = word_at(offset);
+        let start = offset;
+        offset += keyword.len().max(1);
+        if !FUNCTION_KEYWORDS.contains(&keyword) {
+            continue
+        }
+
+        let mut name = keyword;
+        if matches!(keyword, "function" | "modifier") {
+            let after =
+                offset + code[offset..].iter().take_while(|b| b.is_ascii_whitespace()).count();
+            if after < code.len() && !word_at(after).is_empty() {
+                name = word_at(after);
+            }
+        }
+        let Some(open) = code[offset..].iter().position(|byte| matches!(byte, b'{' | b';')) else {
+            break
+        };
+        let open = offset + open;
+        if code[open] == b';' {
+            continue
+        }
+
+        let mut depth = 0;
+        let mut end = code.len();
+        for (index, byte) in code.iter().enumerate().skip(open) {
+            match byte {
+                b'{' => depth += 1,
+                b'}' => depth -= 1,
+                _ => continue,
+            }
+            if depth == 0 {
+                end = index + 1;
+                break
+            }
+        }
+        spans.push((name.to_string(), start..end));
+        // nested keywords, e.g. of function types, are not functions
+        offset = end;
+    }
+    spans
+}
+
+/// A source file indexed to find the line and the function of an offset.
+#[derive(Debug)]
+struct IndexedSource<'a> {
+    path: &'a str,
+    line_starts: Vec<usize>,
+    functions: Vec<(String, Range<usize>)>,
+}
+
+impl<'a> IndexedSource<'a> {
+    fn new(file: &'a SourceFile) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(file.content.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        Self { path: &file.path, line_starts, functions: function_spans(&file.content) }
+    }
+
+    fn line(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|start| *start <= offset)
+    }
+
+    fn function(&self, offset: usize) -> Option<&str> {
+        self.functions
+            .iter()
+            .find(|(_, span)| span.contains(&offset))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// The runtime code of an artifact, indexed to map its instructions to their sources.
+#[derive(Debug)]
+struct MappedCode<'a> {
+    indices: HashMap<usize, usize>,
+    source_map: Vec<(Option<u32>, usize)>,
+    sources: BTreeMap<u32, IndexedSource<'a>>,
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Replays a mined transaction on top of the state it ran on and attributes the gas of every
+    /// instruction to a line and a function of the contracts' sources, using their solc source
+    /// maps.
+    ///
+    /// An artifact's code is read at its address before the transaction, so contracts it creates
+    /// are not mapped. Functions are found in the sources by their keywords and braces.
+    pub async fn source_gas_report(
+        &self,
+        tx_hash: EthersH256,
+        artifacts: &[SourceArtifact],
+    ) -> Result<SourceGasReport, RethMiddlewareError<M>> {
+        instrument("source_gas_report", async {
+            let (env, mut db) = self.transaction_replay(tx_hash.into_reth())?;
+
+            // the artifacts by the hash of their code, which instructions are recorded with
+            let mut codes = HashMap::new();
+            for artifact in artifacts {
+                let Some(info) = Database::basic(&mut db, B160::from(artifact.address.0))? else {
+                    continue
+                };
+                let code = match info.code {
+                    Some(code) => code,
+                    None => Database::code_by_hash(&mut db, info.code_hash)?,
+                };
+                let mapped = MappedCode {
+                    indices: instruction_indices(&code.original_bytes()),
+                    source_map: decode_source_map(&artifact.source_map),
+                    sources: artifact
+                        .sources
+                        .iter()
+                        .map(|(id, file)| (*id, IndexedSource::new(file)))
+                        .collect(),
+                };
+                codes.insert(info.code_hash, mapped);
+            }
+
+            let mut evm = EVM::with_env(env);
+            evm.database(db);
+            let mut profiler = OpcodeProfiler::new();
+            let result = evm.inspect(&mut profiler).map_err(EthApiError::from)?.result;
+
+            let mut lines: HashMap<(&str, usize), LineGas> = HashMap::new();
+            let mut functions: HashMap<(&str, &str), u64> = HashMap::new();
+            let mut unmapped_gas = 0;
+            for (code_hash, pc, count, gas) in profiler.instructions() {
+                let location = codes.get(&code_hash).and_then(|code| {
+                    let index = code.indices.get(&pc)?;
+                    let (file, offset) = code.source_map.get(*index).copied()?;
+                    Some((code.sources.get(&file?)?, offset))
+                });
+                let Some((source, offset)) = location else {
+                    unmapped_gas += gas;
+                    continue
+                };
+
+                let line = source.line(offset);
+                let function = source.function(offset);
+                let entry = lines.entry((source.path, line)).or_insert_with(|| LineGas {
+                    path: source.path.to_string(),
+                    line,
+                    function: function.map(str::to_string),
+                    ..Default::default()
+                });
+                entry.count += count;
+                entry.gas += gas;
+                if let Some(function) = function {
+                    *functions.entry((source.path, function)).or_default() += gas;
+                }
+            }
+
+            let mut lines: Vec<_> = lines.into_values().collect();
+            lines.sort_by(|a, b| {
+                b.gas.cmp(&a.gas).then_with(|| (&a.path, a.line).cmp(&(&b.path, b.line)))
+            });
+            let mut functions: Vec<_> = functions
+                .into_iter()
+                .map(|((path, function), gas)| FunctionGas {
+                    path: path.to_string(),
+                    function: function.to_string(),
+                    gas,
+                })
+                .collect();
+            functions.sort_by(|a, b| {
+                b.gas.cmp(&a.gas).then_with(|| (&a.path, &a.function).cmp(&(&b.path, &b.function)))
+            });
+
+            Ok(SourceGasReport {
+                transaction_hash: tx_hash,
+                gas_used: result.gas_used(),
+                functions,
+                lines,
+                unmapped_gas,
+            })
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"contract Counter {
+    // function commented() {}
+    function bump(uint256 by) public {
+        if (by > 0) { emit Bumped("}"); }
+    }
+    function peek() external view returns (uint256);
+    constructor() {}
+}
+"#;
+
+    #[test]
+    fn source_maps_repeat_omitted_fields() {
+        assert_eq!(
+            decode_source_map("0:10:0:-;;5:3;:2:-1;7::1"),
+            vec![(Some(0), 0), (Some(0), 0), (Some(0), 5), (None, 5), (Some(1), 7)]
+        );
+    }
+
+    #[test]
+    fn instructions_are_indexed_past_push_data() {
+        // PUSH1 0x80, PUSH2 0x0000, STOP
+        let indices = instruction_indices(&[0x60, 0x80, 0x61, 0x00, 0x00, 0x00]);
+        assert_eq!(indices, HashMap::from([(0, 0), (2, 1), (5, 2)]));
+        // truncated push data
+        assert_eq!(instruction_indices(&[0x7f, 0x01]), HashMap::from([(0, 0)]));
+    }
+
+    #[test]
+    fn comments_and_strings_are_blanked_in_place() {
+        let source = "a // {\nb /* } */ c \"{\\\"\" 'd' e";
+        let blanked = String::from_utf8(blank_comments_and_strings(source)).unwrap();
+        assert_eq!(blanked.len(), source.len());
+        assert_eq!(blanked.split_whitespace().collect::<Vec<_>>(), vec!["a", "b", "c", "e"]);
+        // unterminated comments and strings run to the end of the source, or of the line
+        assert_eq!(blank_comments_and_strings("/* {"), b"    ");
+        assert_eq!(blank_comments_and_strings("\"{\n}"), b"  \n}");
+    }
+
+    #[test]
+    fn function_spans_cover_bodies_only() {
+        let spans = function_spans(SOURCE);
+        let bump = SOURCE.find("function bump").unwrap();
+        let constructor = SOURCE.find("constructor").unwrap();
+        assert_eq!(
+            spans,
+            vec![
+                ("bump".to_string(), bump..SOURCE.find("\n    function peek").unwrap()),
+                ("constructor".to_string(), constructor..constructor + "constructor() {}".len()),
+            ]
+        );
+    }
+
+    #[test]
+    fn offsets_map_to_lines_and_functions() {
+        let file = SourceFile { path: "Counter.sol".to_string(), content: SOURCE.to_string() };
+        let source = IndexedSource::new(&file);
+        let emit = SOURCE.find("emit").unwrap();
+
+        assert_eq!(source.line(0), 1);
+        assert_eq!(source.line(emit), 4);
+        assert_eq!(source.function(emit), Some("bump"));
+        assert_eq!(source.function(SOURCE.find("peek").unwrap()), None);
+    }
+}
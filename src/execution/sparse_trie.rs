@@ -0,0 +1,442 @@
+//! A Merkle Patricia trie of which only the nodes of a witness are known, the rest of it being
+//! hashes. Reads, updates and the root hash work as long as they only walk known nodes.
+use ethers::{
+    types::H256 as EthersH256,
+    utils::{
+        keccak256,
+        rlp::{Rlp, RlpStream},
+    },
+};
+use thiserror::Error;
+
+// Std
+use std::collections::HashMap;
+
+/// Root of the empty trie, the hash of the RLP of an empty string.
+pub const EMPTY_ROOT: EthersH256 = EthersH256([
+    0x56, 0xe8, 0x1f, 0x17, 0x1b, 0xcc, 0x55, 0xa6, 0xff, 0x83, 0x45, 0xe6, 0x92, 0xc0, 0xf8, 0x6e,
+    0x5b, 0x48, 0xe0, 0x1b, 0x99, 0x6c, 0xad, 0xc0, 0x01, 0x62, 0x2f, 0xb5, 0xe3, 0x63, 0xb4, 0x21,
+]);
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum TrieError {
+    /// A walk reached a node the witness does not have.
+    #[error("witness lacks trie node {0:?}")]
+    MissingNode(EthersH256),
+    #[error("invalid trie node: {0}")]
+    InvalidNode(String),
+}
+
+/// Trie nodes by hash.
+pub type NodeSet = HashMap<EthersH256, Vec<u8>>;
+
+/// Collects RLP encoded trie nodes by hash.
+pub fn node_set<'a>(nodes: impl IntoIterator<Item = &'a [u8]>) -> NodeSet {
+    nodes.into_iter().map(|node| (keccak256(node).into(), node.to_vec())).collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Empty,
+    Leaf {
+        path: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Extension {
+        path: Vec<u8>,
+        child: Box<Node>,
+    },
+    Branch {
+        children: Box<[Node; 16]>,
+    },
+    /// A node known by hash only, resolved from the node set when walked.
+    Hash(EthersH256),
+}
+
+/// A trie keyed by 32 byte keys, e.g. the hashed keys of the state and storage tries.
+#[derive(Debug, Clone)]
+pub struct SparseTrie<'a> {
+    root: Node,
+    nodes: &'a NodeSet,
+}
+
+impl<'a> SparseTrie<'a> {
+    /// Opens the trie with the given root, its nodes to be found in `nodes`.
+    pub fn new(root: EthersH256, nodes: &'a NodeSet) -> Self {
+        let root = if root == EMPTY_ROOT { Node::Empty } else { Node::Hash(root) };
+        Self { root, nodes }
+    }
+
+    /// Returns the value of `key`, `None` if the trie proves it absent.
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, TrieError> {
+        get(&mut self.root, &nibbles(key), self.nodes)
+    }
+
+    /// Sets the value of `key`, removing it if `value` is empty.
+    pub fn insert(&mut self, key: &[u8], value: Vec<u8>) -> Result<(), TrieError> {
+        let path = nibbles(key);
+        let root = std::mem::replace(&mut self.root, Node::Empty);
+        self.root = if value.is_empty() {
+            remove(root, &path, self.nodes)?
+        } else {
+            insert(root, &path, value, self.nodes)?
+        };
+        Ok(())
+    }
+
+    /// Returns the root hash of the trie.
+    pub fn root(&self) -> EthersH256 {
+        match &self.root {
+            Node::Empty => EMPTY_ROOT,
+            Node::Hash(hash) => *hash,
+            node => keccak256(encode(node)).into(),
+        }
+    }
+}
+
+/// Splits bytes into nibbles, high nibble first.
+fn nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter().flat_map(|byte| [byte >> 4, byte & 0x0f]).collect()
+}
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+fn empty_children() -> Box<[Node; 16]> {
+    Box::new(std::array::from_fn(|_| Node::Empty))
+}
+
+/// Replaces a hash node by the node it stands for.
+fn resolve(node: &mut Node, nodes: &NodeSet) -> Result<(), TrieError> {
+    if let Node::Hash(hash) = node {
+        let encoded = nodes.get(hash).ok_or(TrieError::MissingNode(*hash))?;
+        *node = decode(&Rlp::new(encoded))?;
+    }
+    Ok(())
+}
+
+fn get(node: &mut Node, path: &[u8], nodes: &NodeSet) -> Result<Option<Vec<u8>>, TrieError> {
+    resolve(node, nodes)?;
+    match node {
+        Node::Empty => Ok(None),
+        Node::Leaf { path: leaf_path, value } => {
+            Ok((leaf_path[..] == *path).then(|| value.clone()))
+        }
+        Node::Extension { path: extension, child } => match path.strip_prefix(&extension[..]) {
+            Some(rest) => get(child, rest, nodes),
+            None => Ok(None),
+        },
+        Node::Branch { children } => match path.split_first() {
+            Some((first, rest)) => get(&mut children[*first as usize], rest, nodes),
+            None => Ok(None),
+        },
+        Node::Hash(_) => unreachable!("resolved above"),
+    }
+}
+
+fn insert(mut node: Node, path: &[u8], value: Vec<u8>, nodes: &NodeSet) -> Result<Node, TrieError> {
+    resolve(&mut node, nodes)?;
+    Ok(match node {
+        Node::Empty => Node::Leaf { path: path.to_vec(), value },
+        Node::Leaf { path: leaf_path, value: leaf_value } => {
+            if leaf_path == path {
+                return Ok(Node::Leaf { path: leaf_path, value })
+            }
+            let common = common_prefix(&leaf_path, path);
+            let mut children = empty_children();
+            children[leaf_path[common] as usize] =
+                Node::Leaf { path: leaf_path[common + 1..].to_vec(), value: leaf_value };
+            children[path[common] as usize] =
+                Node::Leaf { path: path[common + 1..].to_vec(), value };
+            extend(&path[..common], Node::Branch { children })
+        }
+        Node::Extension { path: extension, child } => {
+            let common = common_prefix(&extension, path);
+            if common == extension.len() {
+                let child = insert(*child, &path[common..], value, nodes)?;
+                return Ok(Node::Extension { path: extension, child: Box::new(child) })
+            }
+            let mut children = empty_children();
+            children[extension[common] as usize] = extend(&extension[common + 1..], *child);
+            children[path[common] as usize] =
+                Node::Leaf { path: path[common + 1..].to_vec(), value };
+            extend(&extension[..common], Node::Branch { children })
+        }
+        Node::Branch { mut children } => {
+            let index = path[0] as usize;
+            let child = std::mem::replace(&mut children[index], Node::Empty);
+            children[index] = insert(child, &path[1..], value, nodes)?;
+            Node::Branch { children }
+        }
+        Node::Hash(_) => unreachable!("resolved above"),
+    })
+}
+
+fn remove(mut node: Node, path: &[u8], nodes: &NodeSet) -> Result<Node, TrieError> {
+    resolve(&mut node, nodes)?;
+    Ok(match node {
+        Node::Empty => Node::Empty,
+        Node::Leaf { path: leaf_path, value } => {
+            if leaf_path == path {
+                Node::Empty
+            } else {
+                Node::Leaf { path: leaf_path, value }
+            }
+        }
+        Node::Extension { path: extension, child } => {
+            if !path.starts_with(&extension) {
+                return Ok(Node::Extension { path: extension, child })
+            }
+            let child = remove(*child, &path[extension.len()..], nodes)?;
+            extend(&extension, child)
+        }
+        Node::Branch { mut children } => {
+            let index = path[0] as usize;
+            let child = std::mem::replace(&mut children[index], Node::Empty);
+            children[index] = remove(child, &path[1..], nodes)?;
+
+            let remaining: Vec<usize> =
+                (0..16).filter(|index| children[*index] != Node::Empty).collect();
+            match remaining[..] {
+                [] => Node::Empty,
+                [index] => {
+                    // a branch left with a single child collapses into it, which takes knowing it
+                    let mut child = std::mem::replace(&mut children[index], Node::Empty);
+                    resolve(&mut child, nodes)?;
+                    extend(&[index as u8], child)
+                }
+                _ => Node::Branch { children },
+            }
+        }
+        Node::Hash(_) => unreachable!("resolved above"),
+    })
+}
+
+/// Prefixes a node's path with `prefix`, merging the prefix into leaves and extensions.
+fn extend(prefix: &[u8], node: Node) -> Node {
+    if prefix.is_empty() {
+        return node
+    }
+    match node {
+        Node::Empty => Node::Empty,
+        Node::Leaf { path, value } => Node::Leaf { path: [prefix, &path].concat(), value },
+        Node::Extension { path, child } => {
+            Node::Extension { path: [prefix, &path].concat(), child }
+        }
+        node => Node::Extension { path: prefix.to_vec(), child: Box::new(node) },
+    }
+}
+
+/// Hex-prefix encodes a path, flagging leaves.
+fn compact(path: &[u8], leaf: bool) -> Vec<u8> {
+    let flag = if leaf { 2 } else { 0 };
+    let mut out = Vec::with_capacity(path.len() / 2 + 1);
+    let rest = if path.len() % 2 == 1 {
+        out.push(((flag + 1) << 4) | path[0]);
+        &path[1..]
+    } else {
+        out.push(flag << 4);
+        path
+    };
+    out.extend(rest.chunks(2).map(|pair| (pair[0] << 4) | pair[1]));
+    out
+}
+
+/// Decodes a hex-prefix encoded path into its nibbles and whether it is a leaf's.
+fn decompact(encoded: &[u8]) -> Result<(Vec<u8>, bool), TrieError> {
+    let (first, rest) =
+        encoded.split_first().ok_or_else(|| TrieError::InvalidNode("empty path".to_string()))?;
+    let flag = first >> 4;
+    let mut path = if flag & 1 == 1 { vec![first & 0x0f] } else { vec![] };
+    path.extend(nibbles(rest));
+    Ok((path, flag & 2 == 2))
+}
+
+fn decode(rlp: &Rlp<'_>) -> Result<Node, TrieError> {
+    let invalid = |err: ethers::utils::rlp::DecoderError| TrieError::InvalidNode(err.to_string());
+    match rlp.item_count().map_err(invalid)? {
+        2 => {
+            let (path, leaf) = decompact(rlp.at(0).map_err(invalid)?.data().map_err(invalid)?)?;
+            let second = rlp.at(1).map_err(invalid)?;
+            if leaf {
+                Ok(Node::Leaf { path, value: second.data().map_err(invalid)?.to_vec() })
+            } else {
+                Ok(Node::Extension { path, child: Box::new(decode_child(&second)?) })
+            }
+        }
+        17 => {
+            let mut children = empty_children();
+            for (index, child) in children.iter_mut().enumerate() {
+                *child = decode_child(&rlp.at(index).map_err(invalid)?)?;
+            }
+            Ok(Node::Branch { children })
+        }
+        count => Err(TrieError::InvalidNode(format!("node of {count} items"))),
+    }
+}
+
+/// Decodes a reference to a node: a hash, an inline node or nothing.
+fn decode_child(rlp: &Rlp<'_>) -> Result<Node, TrieError> {
+    if rlp.is_list() {
+        return decode(rlp)
+    }
+    let data = rlp.data().map_err(|err| TrieError::InvalidNode(err.to_string()))?;
+    match data.len() {
+        0 => Ok(Node::Empty),
+        32 => Ok(Node::Hash(EthersH256::from_slice(data))),
+        len => Err(TrieError::InvalidNode(format!("child reference of {len} bytes"))),
+    }
+}
+
+fn encode(node: &Node) -> Vec<u8> {
+    let mut stream = RlpStream::new();
+    match node {
+        Node::Empty => {
+            stream.append_empty_data();
+        }
+        Node::Leaf { path, value } => {
+            stream.begin_list(2);
+            stream.append(&compact(path, true));
+            stream.append(value);
+        }
+        Node::Extension { path, child } => {
+            stream.begin_list(2);
+            stream.append(&compact(path, false));
+            append_child(&mut stream, child);
+        }
+        Node::Branch { children } => {
+            stream.begin_list(17);
+            children.iter().for_each(|child| append_child(&mut stream, child));
+            stream.append_empty_data();
+        }
+        Node::Hash(hash) => {
+            stream.append(hash);
+        }
+    }
+    stream.out().to_vec()
+}
+
+/// Appends a reference to a node: nodes shorter than a hash are inlined.
+fn append_child(stream: &mut RlpStream, child: &Node) {
+    match child {
+        Node::Empty => {
+            stream.append_empty_data();
+        }
+        Node::Hash(hash) => {
+            stream.append(hash);
+        }
+        node => {
+            let encoded = encode(node);
+            if encoded.len() < 32 {
+                stream.append_raw(&encoded, 1);
+            } else {
+                stream.append(&EthersH256::from(keccak256(&encoded)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(index: u8) -> [u8; 32] {
+        keccak256([index])
+    }
+
+    fn hash(hex: &str) -> EthersH256 {
+        hex.parse().unwrap()
+    }
+
+    /// A fully known trie of the keys of `indexes`, each set to its index repeated thrice.
+    fn full_trie(indexes: impl IntoIterator<Item = u8>, nodes: &NodeSet) -> SparseTrie<'_> {
+        let mut trie = SparseTrie::new(EMPTY_ROOT, nodes);
+        for index in indexes {
+            trie.insert(&key(index), vec![index; 3]).unwrap();
+        }
+        trie
+    }
+
+    /// Collects the nodes of a fully known trie, as a witness of all of it would hold them.
+    fn witness(node: &Node, nodes: &mut NodeSet) {
+        let encoded = encode(node);
+        nodes.insert(keccak256(&encoded).into(), encoded);
+        match node {
+            Node::Extension { child, .. } => witness(child, nodes),
+            Node::Branch { children } => children.iter().for_each(|child| witness(child, nodes)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn empty_root_is_the_hash_of_the_empty_string() {
+        assert_eq!(EthersH256::from(keccak256([0x80])), EMPTY_ROOT);
+        let nodes = NodeSet::new();
+        assert_eq!(SparseTrie::new(EMPTY_ROOT, &nodes).get(&key(1)), Ok(None));
+    }
+
+    #[test]
+    fn roots_match_reference_tries() {
+        let nodes = NodeSet::new();
+        assert_eq!(
+            full_trie([1], &nodes).root(),
+            hash("0x8242a824df2048ba00e0ccf8c9679c5eda70a3a70e21b15c5b17def03a7551fb")
+        );
+        assert_eq!(
+            full_trie([1, 2, 3], &nodes).root(),
+            hash("0x9e18fcc284d18ddcf52aa14bbc88701ecf9e1df8efebf61daa67afe8a40812f5")
+        );
+    }
+
+    #[test]
+    fn roots_do_not_depend_on_the_order_of_updates() {
+        let nodes = NodeSet::new();
+        let root = full_trie([1, 2, 3], &nodes).root();
+        assert_eq!(full_trie([3, 1, 2], &nodes).root(), root);
+
+        let mut trie = full_trie([1, 2, 3, 4], &nodes);
+        trie.insert(&key(4), vec![]).unwrap();
+        assert_eq!(trie.root(), root);
+        trie.insert(&key(2), vec![]).unwrap();
+        trie.insert(&key(3), vec![]).unwrap();
+        assert_eq!(trie.root(), full_trie([1], &nodes).root());
+        trie.insert(&key(1), vec![]).unwrap();
+        assert_eq!(trie.root(), EMPTY_ROOT);
+    }
+
+    #[test]
+    fn witnessed_tries_are_read_and_updated_from_their_root() {
+        let empty = NodeSet::new();
+        let mut full = full_trie([1, 2, 3], &empty);
+        let mut nodes = NodeSet::new();
+        witness(&full.root, &mut nodes);
+
+        let mut sparse = SparseTrie::new(full.root(), &nodes);
+        assert_eq!(sparse.get(&key(2)), Ok(Some(vec![2; 3])));
+        assert_eq!(sparse.get(&key(4)), Ok(None));
+
+        full.insert(&key(2), vec![9]).unwrap();
+        full.insert(&key(1), vec![]).unwrap();
+        sparse.insert(&key(2), vec![9]).unwrap();
+        sparse.insert(&key(1), vec![]).unwrap();
+        assert_eq!(sparse.root(), full.root());
+    }
+
+    #[test]
+    fn walks_past_the_witness_fail() {
+        let empty = NodeSet::new();
+        let full = full_trie([1, 2, 3], &empty);
+        let root = encode(&full.root);
+        let nodes = node_set([root.as_slice()]);
+        let mut sparse = SparseTrie::new(full.root(), &nodes);
+
+        assert!(matches!(sparse.get(&key(1)), Err(TrieError::MissingNode(_))));
+        // the root branch has no child under the first nibble of a zero key, proving it absent
+        assert_eq!(sparse.get(&[0; 32]), Ok(None));
+        assert_eq!(
+            SparseTrie::new(EthersH256::repeat_byte(1), &nodes).get(&key(1)),
+            Err(TrieError::MissingNode(EthersH256::repeat_byte(1)))
+        );
+    }
+}
@@ -0,0 +1,49 @@
+use crate::RethMiddleware;
+use ethers::providers::Middleware;
+
+// Reth
+use reth_primitives::ChainSpec;
+use reth_revm::primitives::SpecId;
+
+// Std
+use std::sync::Arc;
+
+/// Rules the local executor and simulator run under instead of the ones of the database's chain,
+/// e.g. to replay a historical block under a later hardfork. The reth handlers behind the
+/// `eth`, `trace` and `debug` methods keep the chain's rules.
+#[derive(Debug, Clone, Default)]
+pub struct SpecOverride {
+    /// Chain spec the block environment is derived from: chain id and the hardfork active at
+    /// each block.
+    pub chain: Option<Arc<ChainSpec>>,
+    /// Hardfork forced on every execution, taking precedence over the chain spec.
+    pub spec_id: Option<SpecId>,
+}
+
+impl SpecOverride {
+    /// Runs every execution under `spec_id`.
+    pub fn hardfork(spec_id: SpecId) -> Self {
+        Self { chain: None, spec_id: Some(spec_id) }
+    }
+
+    /// Derives the block environment from `chain` instead of the database's chain spec.
+    pub fn chain(chain: Arc<ChainSpec>) -> Self {
+        Self { chain: Some(chain), spec_id: None }
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Sets the chain spec or hardfork the local executor and simulator run under.
+    pub fn with_spec_override(mut self, spec: SpecOverride) -> Self {
+        self.spec_override = spec;
+        self
+    }
+
+    /// Returns the rules overriding the chain's in the local executor and simulator.
+    pub fn spec_override(&self) -> &SpecOverride {
+        &self.spec_override
+    }
+}
@@ -0,0 +1,427 @@
+use crate::{
+    execution::{
+        execute_transactions,
+        sparse_trie::{node_set, NodeSet, SparseTrie, TrieError, EMPTY_ROOT},
+        witness::ExecutionWitness,
+    },
+    type_conversions::{ToEthers, ToReth},
+    verify::Comparison,
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{BlockId as EthersBlockId, H256 as EthersH256, U256 as EthersU256},
+    utils::{
+        keccak256,
+        rlp::{self, Rlp, RlpStream},
+    },
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+// Reth
+use reth_primitives::{proofs, BlockId, Log, Receipt, H160, H256};
+use reth_revm::{
+    db::{AccountState, CacheDB},
+    primitives::{AccountInfo, Bytecode, ExecutionResult, B160, B256, KECCAK_EMPTY, U256},
+    DatabaseRef,
+};
+use reth_rpc::eth::error::EthApiError;
+
+// Std
+use std::collections::{BTreeMap, HashMap};
+
+/// Why a block could not be executed against a witness.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum WitnessError {
+    #[error(transparent)]
+    Trie(#[from] TrieError),
+    #[error("account {0:?} is not in the witness")]
+    MissingAccount(B160),
+    #[error("storage slot {1} of {0:?} is not in the witness")]
+    MissingStorage(B160, U256),
+    #[error("code {0:?} is not in the witness")]
+    MissingCode(B256),
+    #[error("hash of block {0} is not in the witness")]
+    MissingBlockHash(u64),
+    #[error("invalid account: {0}")]
+    InvalidAccount(String),
+}
+
+impl From<WitnessError> for EthApiError {
+    fn from(err: WitnessError) -> Self {
+        EthApiError::InvalidParams(err.to_string())
+    }
+}
+
+/// How the re-execution of a block against a witness compares against its header.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatelessVerification {
+    pub state_root: Comparison<EthersH256>,
+    pub receipts_root: Comparison<EthersH256>,
+    pub gas_used: Comparison<EthersU256>,
+}
+
+impl StatelessVerification {
+    /// Returns true if the re-execution matches the header.
+    pub fn is_valid(&self) -> bool {
+        self.state_root.matches() && self.receipts_root.matches() && self.gas_used.matches()
+    }
+}
+
+/// An account as the state trie stores it.
+#[derive(Debug, Clone, Copy)]
+struct TrieAccount {
+    nonce: u64,
+    balance: EthersU256,
+    storage_root: EthersH256,
+    code_hash: EthersH256,
+}
+
+impl TrieAccount {
+    fn decode(encoded: &[u8]) -> Result<Self, WitnessError> {
+        let rlp = Rlp::new(encoded);
+        let invalid = |err: rlp::DecoderError| WitnessError::InvalidAccount(err.to_string());
+        Ok(Self {
+            nonce: rlp.val_at(0).map_err(invalid)?,
+            balance: rlp.val_at(1).map_err(invalid)?,
+            storage_root: rlp.val_at(2).map_err(invalid)?,
+            code_hash: rlp.val_at(3).map_err(invalid)?,
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut stream = RlpStream::new_list(4);
+        stream.append(&self.nonce).append(&self.balance);
+        stream.append(&self.storage_root).append(&self.code_hash);
+        stream.out().to_vec()
+    }
+}
+
+/// The pre-state of a witness: the values of its keys, read from its proofs.
+#[derive(Debug)]
+pub struct WitnessDb {
+    accounts: HashMap<B160, Option<TrieAccount>>,
+    storage: HashMap<(B160, U256), U256>,
+    codes: HashMap<B256, Bytecode>,
+    block_hashes: BTreeMap<u64, B256>,
+}
+
+impl WitnessDb {
+    /// Reads the values of the witness's keys from its trie nodes.
+    pub fn new(witness: &ExecutionWitness, nodes: &NodeSet) -> Result<Self, WitnessError> {
+        let mut state = SparseTrie::new(witness.parent_state_root, nodes);
+        let mut accounts = HashMap::new();
+        let mut storage = HashMap::new();
+        for (address, slots) in &witness.keys {
+            let account = state
+                .get(&keccak256(address))?
+                .map(|encoded| TrieAccount::decode(&encoded))
+                .transpose()?;
+            let address = B160::from(address.0);
+            let mut storage_trie =
+                SparseTrie::new(account.map_or(EMPTY_ROOT, |account| account.storage_root), nodes);
+            for slot in slots {
+                let value = match storage_trie.get(&keccak256(slot))? {
+                    Some(encoded) => rlp::decode::<EthersU256>(&encoded)
+                        .map_err(|err| WitnessError::InvalidAccount(err.to_string()))?,
+                    None => EthersU256::zero(),
+                };
+                storage.insert((address, U256::from_be_bytes(slot.0)), value.into_reth());
+            }
+            accounts.insert(address, account);
+        }
+
+        let codes = witness
+            .codes
+            .iter()
+            .map(|code| (B256::from(keccak256(code)), Bytecode::new_raw(code.0.clone())))
+            .collect();
+        let block_hashes = witness
+            .block_hashes
+            .iter()
+            .map(|(number, hash)| (*number, B256::from(hash.0)))
+            .collect();
+        Ok(Self { accounts, storage, codes, block_hashes })
+    }
+}
+
+impl DatabaseRef for WitnessDb {
+    type Error = WitnessError;
+
+    fn basic(&self, address: B160) -> Result<Option<AccountInfo>, Self::Error> {
+        let account = self.accounts.get(&address).ok_or(WitnessError::MissingAccount(address))?;
+        Ok(account.map(|account| {
+            let code_hash = B256::from(account.code_hash.0);
+            AccountInfo {
+                balance: account.balance.into_reth(),
+                nonce: account.nonce,
+                code_hash,
+                code: self.codes.get(&code_hash).cloned(),
+            }
+        }))
+    }
+
+    fn code_by_hash(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        if code_hash == KECCAK_EMPTY {
+            return Ok(Bytecode::new())
+        }
+        self.codes.get(&code_hash).cloned().ok_or(WitnessError::MissingCode(code_hash))
+    }
+
+    fn storage(&self, address: B160, index: U256) -> Result<U256, Self::Error> {
+        self.storage
+            .get(&(address, index))
+            .copied()
+            .ok_or(WitnessError::MissingStorage(address, index))
+    }
+
+    fn block_hash(&self, number: U256) -> Result<B256, Self::Error> {
+        let number = number.to::<u64>();
+        self.block_hashes.get(&number).copied().ok_or(WitnessError::MissingBlockHash(number))
+    }
+}
+
+/// Applies the state changes of an execution to the tries of the pre-state, returning the new
+/// state root.
+fn post_state_root(
+    parent_state_root: EthersH256,
+    nodes: &NodeSet,
+    db: &CacheDB<WitnessDb>,
+) -> Result<EthersH256, WitnessError> {
+    let mut state = SparseTrie::new(parent_state_root, nodes);
+    for (address, account) in &db.accounts {
+        let key = keccak256(address);
+        let info = &account.info;
+        if account.account_state == AccountState::NotExisting || info.is_empty() {
+            state.insert(&key, vec![])?;
+            continue
+        }
+
+        let pre = db.db.accounts.get(address).copied().flatten();
+        let storage_root = match (pre, &account.account_state) {
+            (Some(pre), state) if *state != AccountState::StorageCleared => pre.storage_root,
+            _ => EMPTY_ROOT,
+        };
+        let mut storage = SparseTrie::new(storage_root, nodes);
+        for (slot, value) in &account.storage {
+            let encoded = if *value == U256::ZERO {
+                vec![]
+            } else {
+                rlp::encode(&value.into_ethers()).to_vec()
+            };
+            storage.insert(&keccak256(slot.to_be_bytes::<32>()), encoded)?;
+        }
+
+        let account = TrieAccount {
+            nonce: info.nonce,
+            balance: info.balance.into_ethers(),
+            storage_root: storage.root(),
+            code_hash: EthersH256(info.code_hash.0),
+        };
+        state.insert(&key, account.encode())?;
+    }
+    Ok(state.root())
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Re-executes a block against an execution witness instead of the database, then checks
+    /// the resulting state root, receipts root and gas used against the block's header.
+    ///
+    /// The witness must prove every account and slot the block touches, as
+    /// [RethMiddleware::execution_witness] does, and the nodes needed to collapse the branches
+    /// of deleted keys. Blocks before the merge are not supported, their ommer rewards going to
+    /// accounts the witness does not prove.
+    pub async fn verify_block_stateless<T: Into<EthersBlockId> + Send + Sync>(
+        &self,
+        block: T,
+        witness: &ExecutionWitness,
+    ) -> Result<StatelessVerification, RethMiddlewareError<M>> {
+        let block_id: BlockId = block.into().into_reth();
+        let (block, senders) = self.sealed_block_with_senders(block_id)?;
+        if !block.difficulty.is_zero() {
+            return Err(EthApiError::InvalidParams(
+                "stateless verification of blocks before the merge is not supported".to_string(),
+            )
+            .into())
+        }
+
+        let nodes = node_set(witness.state.iter().map(|node| node.as_ref()));
+        let pre_state = WitnessDb::new(witness, &nodes).map_err(EthApiError::from)?;
+        let env = self.block_env(&block)?;
+        let (results, mut db) =
+            execute_transactions(env, CacheDB::new(pre_state), block.body.iter().zip(senders))?;
+
+        // withdrawals are credited after the transactions, in gwei
+        for withdrawal in block.withdrawals.iter().flatten() {
+            let address = B160::from(withdrawal.address.0);
+            let mut info = reth_revm::Database::basic(&mut db, address)
+                .map_err(EthApiError::from)?
+                .unwrap_or_default();
+            info.balance += U256::from(withdrawal.amount) * U256::from(1_000_000_000u64);
+            db.insert_account_info(address, info);
+        }
+
+        let mut cumulative_gas_used = 0;
+        let receipts: Vec<_> = block
+            .body
+            .iter()
+            .zip(results)
+            .map(|(tx, result)| {
+                cumulative_gas_used += result.gas_used();
+                let success = result.is_success();
+                let logs = match result {
+                    ExecutionResult::Success { logs, .. } => logs
+                        .into_iter()
+                        .map(|log| Log {
+                            address: H160::from(log.address.0),
+                            topics: log
+                                .topics
+                                .into_iter()
+                                .map(|topic| H256::from(topic.0))
+                                .collect(),
+                            data: log.data.into(),
+                        })
+                        .collect(),
+                    _ => vec![],
+                };
+                Receipt { tx_type: tx.tx_type(), success, cumulative_gas_used, logs }.with_bloom()
+            })
+            .collect();
+
+        let state_root =
+            post_state_root(witness.parent_state_root, &nodes, &db).map_err(EthApiError::from)?;
+        Ok(StatelessVerification {
+            state_root: Comparison {
+                expected: block.state_root.into_ethers(),
+                computed: state_root,
+            },
+            receipts_root: Comparison {
+                expected: block.receipts_root.into_ethers(),
+                computed: proofs::calculate_receipt_root(&receipts).into_ethers(),
+            },
+            gas_used: Comparison {
+                expected: block.gas_used.into(),
+                computed: cumulative_gas_used.into(),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::{Address as EthersAddress, Bytes as EthersBytes};
+    use reth_revm::Database;
+
+    const CODE: [u8; 2] = [0x60, 0x00];
+
+    /// Encodes the leaf of a trie holding a single key, the root of that trie.
+    fn leaf(hashed_key: [u8; 32], value: Vec<u8>) -> Vec<u8> {
+        let mut path = vec![0x20];
+        path.extend(hashed_key);
+        let mut stream = RlpStream::new_list(2);
+        stream.append(&path).append(&value);
+        stream.out().to_vec()
+    }
+
+    fn account(storage_root: EthersH256, balance: u64) -> TrieAccount {
+        TrieAccount {
+            nonce: 1,
+            balance: balance.into(),
+            storage_root,
+            code_hash: keccak256(CODE).into(),
+        }
+    }
+
+    /// A witness of a contract holding 42 in slot 1, the only account of the state.
+    fn witness() -> (ExecutionWitness, EthersAddress) {
+        let contract = EthersAddress::from_low_u64_be(0xc0);
+        let slot = EthersH256::from_low_u64_be(1);
+        let storage = leaf(keccak256(slot), rlp::encode(&EthersU256::from(42)).to_vec());
+        let state = leaf(keccak256(contract), account(keccak256(&storage).into(), 7).encode());
+        let witness = ExecutionWitness {
+            parent_state_root: keccak256(&state).into(),
+            state: vec![state.into(), storage.into()],
+            codes: vec![EthersBytes::from(CODE.to_vec())],
+            keys: BTreeMap::from([
+                (contract, vec![slot, EthersH256::from_low_u64_be(2)]),
+                (EthersAddress::from_low_u64_be(0xee), vec![]),
+            ]),
+            ..Default::default()
+        };
+        (witness, contract)
+    }
+
+    #[test]
+    fn trie_accounts_round_trip() {
+        let account = account(EMPTY_ROOT, 7);
+        let decoded = TrieAccount::decode(&account.encode()).unwrap();
+        assert_eq!(decoded.encode(), account.encode());
+        assert_eq!(decoded.balance, EthersU256::from(7));
+        assert!(TrieAccount::decode(&[0xc0]).is_err());
+    }
+
+    #[test]
+    fn witness_db_reads_the_proven_keys() {
+        let (witness, contract) = witness();
+        let nodes = node_set(witness.state.iter().map(|node| node.as_ref()));
+        let db = WitnessDb::new(&witness, &nodes).unwrap();
+        let contract = B160::from(contract.0);
+
+        let info = db.basic(contract).unwrap().unwrap();
+        assert_eq!((info.nonce, info.balance), (1, U256::from(7)));
+        assert_eq!(info.code_hash, B256::from(keccak256(CODE)));
+        assert_eq!(db.storage(contract, U256::from(1)), Ok(U256::from(42)));
+        // proven absent from the storage trie
+        assert_eq!(db.storage(contract, U256::from(2)), Ok(U256::ZERO));
+        assert_eq!(db.basic(B160::from_low_u64_be(0xee)), Ok(None));
+
+        assert_eq!(
+            db.storage(contract, U256::from(3)),
+            Err(WitnessError::MissingStorage(contract, U256::from(3)))
+        );
+        let unknown = B160::from_low_u64_be(0xff);
+        assert_eq!(db.basic(unknown), Err(WitnessError::MissingAccount(unknown)));
+        assert_eq!(db.block_hash(U256::from(1)), Err(WitnessError::MissingBlockHash(1)));
+        assert_eq!(db.code_by_hash(KECCAK_EMPTY), Ok(Bytecode::new()));
+    }
+
+    #[test]
+    fn witnesses_missing_nodes_are_rejected() {
+        let (mut witness, _) = witness();
+        witness.state.pop();
+        let nodes = node_set(witness.state.iter().map(|node| node.as_ref()));
+        assert!(matches!(
+            WitnessDb::new(&witness, &nodes),
+            Err(WitnessError::Trie(TrieError::MissingNode(_)))
+        ));
+    }
+
+    #[test]
+    fn post_state_roots_apply_the_changes() {
+        let (witness, contract) = witness();
+        let nodes = node_set(witness.state.iter().map(|node| node.as_ref()));
+        let mut db = CacheDB::new(WitnessDb::new(&witness, &nodes).unwrap());
+        assert_eq!(
+            post_state_root(witness.parent_state_root, &nodes, &db),
+            Ok(witness.parent_state_root)
+        );
+
+        let address = B160::from(contract.0);
+        let mut info = Database::basic(&mut db, address).unwrap().unwrap();
+        info.balance = U256::from(8);
+        db.insert_account_info(address, info);
+
+        let storage_root = keccak256(&witness.state[1]).into();
+        let state = leaf(keccak256(contract), account(storage_root, 8).encode());
+        assert_eq!(
+            post_state_root(witness.parent_state_root, &nodes, &db),
+            Ok(EthersH256::from(keccak256(state)))
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use crate::{
+    database::RethDatabaseRef,
+    execution::{
+        call_many::{apply_state_overrides, StateContext},
+        call_tx_env,
+    },
+    limits::CallDeadline,
+    type_conversions::ToReth,
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{
+        transaction::eip2718::TypedTransaction, BlockId as EthersBlockId, GethDebugTracerType,
+        GethDebugTracingCallOptions as EthersDebugTracingCallOptions,
+        GethDebugTracingOptions as EthersDebugTracingOptions, GethTrace as EthersGethTrace,
+        H256 as EthersH256,
+    },
+};
+use serde_json::Value;
+
+// Reth
+use reth_primitives::BlockId;
+use reth_revm::{
+    db::CacheDB,
+    env::fill_tx_env,
+    interpreter::{CallInputs, CreateInputs, Gas, InstructionResult, Interpreter},
+    primitives::{Bytes, ExecutionResult, B160, B256, U256},
+    Database, EVMData, Inspector, EVM,
+};
+use reth_rpc::eth::error::EthApiError;
+
+// Std
+use std::{collections::HashMap, fmt, sync::Arc};
+
+/// The state native tracers inspect: the database at a block, with the changes of the
+/// transactions executed so far on top.
+pub type TracerDb<'a> = CacheDB<RethDatabaseRef<'a>>;
+
+/// A tracer written in Rust: the [Inspector] it runs every traced transaction with, and how the
+/// trace is made out of the inspector once the transaction is done.
+pub trait NativeTracer: Send + Sync + 'static {
+    type Inspector: for<'a> Inspector<TracerDb<'a>>;
+
+    /// Returns the inspector of a transaction, configured by the request's `tracerConfig`, null
+    /// if there is none.
+    fn inspector(&self, config: &Value) -> Result<Self::Inspector, String>;
+
+    /// Returns the trace of a transaction from its inspector and how its execution ended.
+    fn result(&self, inspector: Self::Inspector, result: &ExecutionResult) -> Value;
+}
+
+/// A [NativeTracer] with its inspector type erased, so tracers can share a registry.
+pub(crate) trait DynTracer: Send + Sync {
+    /// Executes the transaction of `evm` under the tracer, committing its changes.
+    fn trace(&self, evm: &mut EVM<TracerDb<'_>>, config: &Value) -> Result<Value, EthApiError>;
+}
+
+impl<T: NativeTracer> DynTracer for T {
+    fn trace(&self, evm: &mut EVM<TracerDb<'_>>, config: &Value) -> Result<Value, EthApiError> {
+        let inspector = self.inspector(config).map_err(EthApiError::InvalidParams)?;
+        let mut inspector = Deadlined { inspector, deadline: CallDeadline::current() };
+        let result = evm.inspect_commit(&mut inspector)?;
+        Ok(self.result(inspector.inspector, &result))
+    }
+}
+
+/// The inspector of a native tracer, halting at the deadline of the running call on top.
+struct Deadlined<I> {
+    inspector: I,
+    deadline: Option<CallDeadline>,
+}
+
+impl<DB: Database, I: Inspector<DB>> Inspector<DB> for Deadlined<I> {
+    fn initialize_interp(
+        &mut self,
+        interp: &mut Interpreter,
+        data: &mut EVMData<'_, DB>,
+    ) -> InstructionResult {
+        self.inspector.initialize_interp(interp, data)
+    }
+
+    fn step(&mut self, interp: &mut Interpreter, data: &mut EVMData<'_, DB>) -> InstructionResult {
+        if self.deadline.as_mut().map_or(false, CallDeadline::passed) {
+            return InstructionResult::OutOfGas
+        }
+        self.inspector.step(interp, data)
+    }
+
+    fn log(
+        &mut self,
+        evm_data: &mut EVMData<'_, DB>,
+        address: &B160,
+        topics: &[B256],
+        data: &Bytes,
+    ) {
+        self.inspector.log(evm_data, address, topics, data)
+    }
+
+    fn step_end(
+        &mut self,
+        interp: &mut Interpreter,
+        data: &mut EVMData<'_, DB>,
+        eval: InstructionResult,
+    ) -> InstructionResult {
+        self.inspector.step_end(interp, data, eval)
+    }
+
+    fn call(
+        &mut self,
+        data: &mut EVMData<'_, DB>,
+        inputs: &mut CallInputs,
+    ) -> (InstructionResult, Gas, Bytes) {
+        self.inspector.call(data, inputs)
+    }
+
+    fn call_end(
+        &mut self,
+        data: &mut EVMData<'_, DB>,
+        inputs: &CallInputs,
+        remaining_gas: Gas,
+        ret: InstructionResult,
+        out: Bytes,
+    ) -> (InstructionResult, Gas, Bytes) {
+        self.inspector.call_end(data, inputs, remaining_gas, ret, out)
+    }
+
+    fn create(
+        &mut self,
+        data: &mut EVMData<'_, DB>,
+        inputs: &mut CreateInputs,
+    ) -> (InstructionResult, Option<B160>, Gas, Bytes) {
+        self.inspector.create(data, inputs)
+    }
+
+    fn create_end(
+        &mut self,
+        data: &mut EVMData<'_, DB>,
+        inputs: &CreateInputs,
+        ret: InstructionResult,
+        address: Option<B160>,
+        remaining_gas: Gas,
+        out: Bytes,
+    ) -> (InstructionResult, Option<B160>, Gas, Bytes) {
+        self.inspector.create_end(data, inputs, ret, address, remaining_gas, out)
+    }
+
+    fn selfdestruct(&mut self, contract: B160, target: B160) {
+        self.inspector.selfdestruct(contract, target)
+    }
+}
+
+/// Native tracers by name, run instead of the built-in ones when a `debug_trace*` request sets
+/// their name as its `tracer`.
+///
+/// Names of built-in tracers, e.g. `callTracer`, always select the built-in one.
+#[derive(Clone, Default)]
+pub struct NativeTracers {
+    tracers: HashMap<String, Arc<dyn DynTracer>>,
+}
+
+impl NativeTracers {
+    /// Registers `tracer` under `name`, replacing the tracer registered under it if any.
+    pub fn insert(mut self, name: impl Into<String>, tracer: impl NativeTracer) -> Self {
+        self.tracers.insert(name.into(), Arc::new(tracer));
+        self
+    }
+
+    pub fn remove(mut self, name: &str) -> Self {
+        self.tracers.remove(name);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracers.is_empty()
+    }
+
+    /// Returns the tracer the options select and its config, if it is a native one.
+    pub(crate) fn select(
+        &self,
+        options: &EthersDebugTracingOptions,
+    ) -> Result<Option<(Arc<dyn DynTracer>, Value)>, EthApiError> {
+        let Some(GethDebugTracerType::JsTracer(name)) = &options.tracer else { return Ok(None) };
+        let Some(tracer) = self.tracers.get(name) else { return Ok(None) };
+        let config = match &options.tracer_config {
+            Some(config) => serde_json::to_value(config)
+                .map_err(|err| EthApiError::InvalidParams(err.to_string()))?,
+            None => Value::Null,
+        };
+        Ok(Some((tracer.clone(), config)))
+    }
+}
+
+impl fmt::Debug for NativeTracers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.tracers.keys()).finish()
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Sets the native tracers `debug_trace*` requests can select.
+    pub fn with_native_tracers(mut self, tracers: NativeTracers) -> Self {
+        self.native_tracers = tracers;
+        self
+    }
+
+    /// Returns the native tracers `debug_trace*` requests can select.
+    pub fn native_tracers(&self) -> &NativeTracers {
+        &self.native_tracers
+    }
+
+    /// Traces a mined transaction with a native tracer, on top of the state it ran on, under the
+    /// call timeout.
+    pub(crate) async fn native_trace_transaction(
+        &self,
+        tx_hash: EthersH256,
+        tracer: &dyn DynTracer,
+        config: &Value,
+    ) -> Result<EthersGethTrace, RethMiddlewareError<M>> {
+        self.run_call(|| {
+            let (env, db) = self.transaction_replay(tx_hash.into_reth())?;
+            let mut evm = EVM::with_env(env);
+            evm.database(db);
+            Ok(EthersGethTrace::Unknown(tracer.trace(&mut evm, config)?))
+        })
+        .await
+    }
+
+    /// Traces every transaction of a block with a native tracer, each on top of the previous one,
+    /// under the call timeout, failing as soon as the traces outgrow the output size limit.
+    pub(crate) async fn native_trace_block(
+        &self,
+        block_id: BlockId,
+        tracer: &dyn DynTracer,
+        config: &Value,
+    ) -> Result<Vec<EthersGethTrace>, RethMiddlewareError<M>> {
+        self.ensure_history(Some(block_id))?;
+        self.run_call(|| {
+            let (block, senders) = self.sealed_block_with_senders(block_id)?;
+            let env = self.block_env(&block)?;
+            let state = RethDatabaseRef::at_block(&self.reth_provider, block.parent_hash)?;
+            let mut evm = EVM::with_env(env);
+            evm.database(CacheDB::new(state));
+
+            let mut traces = Vec::with_capacity(block.body.len());
+            // the size of the response, checked after each transaction
+            let mut size = 0;
+            for (tx, sender) in block.body.iter().zip(senders) {
+                fill_tx_env(&mut evm.env.tx, tx, sender);
+                let trace = EthersGethTrace::Unknown(tracer.trace(&mut evm, config)?);
+                size += 1;
+                self.charge_trace(&mut size, &trace)?;
+                traces.push(trace);
+            }
+            Ok(traces)
+        })
+        .await
+    }
+
+    /// Traces a call on the state of `block` with a native tracer, like `debug_traceCall`, under
+    /// the call timeout.
+    pub(crate) async fn native_trace_call(
+        &self,
+        tx: &TypedTransaction,
+        block: Option<EthersBlockId>,
+        options: &EthersDebugTracingCallOptions,
+        tracer: &dyn DynTracer,
+        config: &Value,
+    ) -> Result<EthersGethTrace, RethMiddlewareError<M>> {
+        let block = block.unwrap_or(EthersBlockId::Number(Default::default()));
+        let context = StateContext { block, transaction_index: None };
+        self.run_call(|| {
+            let (mut env, mut db) = self.state_at_transaction(context)?;
+            if let Some(overrides) = &options.state_overrides {
+                apply_state_overrides(overrides, &mut db)?;
+            }
+            if tx.gas_price().is_none() {
+                // unpriced calls are not charged, as with `eth_call`
+                env.block.basefee = U256::ZERO;
+            }
+            env.tx = call_tx_env(tx, self.gas_cap(env.block.gas_limit.to::<u64>()));
+
+            let mut evm = EVM::with_env(env);
+            evm.database(db);
+            Ok(EthersGethTrace::Unknown(tracer.trace(&mut evm, config)?))
+        })
+        .await
+    }
+}
@@ -0,0 +1,134 @@
+use crate::{
+    database::RethDatabaseRef,
+    execution::{execute_transactions, recorder::AccessRecorder},
+    type_conversions::{ToEthers, ToReth},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{
+        Address as EthersAddress, BlockId as EthersBlockId, Bytes as EthersBytes,
+        H256 as EthersH256,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_primitives::{serde_helper::JsonStorageKey, BlockId, H256};
+use reth_provider::BlockReaderIdExt;
+use reth_revm::db::CacheDB;
+use reth_rpc_api::EthApiServer;
+
+// Std
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The pre-state needed to re-execute a block without access to the database, in the spirit of
+/// geth's `debug_executionWitness`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionWitness {
+    /// Hash of the witnessed block.
+    pub block_hash: EthersH256,
+    /// State root of the parent block the witness proves against.
+    pub parent_state_root: EthersH256,
+    /// Deduplicated account and storage trie nodes proving every accessed value.
+    pub state: Vec<EthersBytes>,
+    /// Bytecodes of the contracts loaded during execution.
+    pub codes: Vec<EthersBytes>,
+    /// Accessed accounts and the storage slots read from each of them.
+    pub keys: BTreeMap<EthersAddress, Vec<EthersH256>>,
+    /// Ancestor block hashes read through `BLOCKHASH`.
+    pub block_hashes: BTreeMap<u64, EthersH256>,
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Re-executes the block on top of its parent state and returns the witness of every piece of
+    /// state the execution read.
+    ///
+    /// Block reward and withdrawal recipients are included, since stateless verification needs
+    /// their proofs to apply the post-block balance changes.
+    ///
+    /// Only works at the tip: the proofs are taken on the state of the parent block through
+    /// `get_proof`, which the pinned reth revision serves for the latest block alone, so the
+    /// witness of a block whose parent is no longer the latest block fails with
+    /// [EthApiError::InvalidBlockRange].
+    ///
+    /// [EthApiError::InvalidBlockRange]: reth_rpc::eth::error::EthApiError::InvalidBlockRange
+    pub async fn execution_witness<T: Into<EthersBlockId> + Send + Sync>(
+        &self,
+        block: T,
+    ) -> Result<ExecutionWitness, RethMiddlewareError<M>> {
+        let block_id: BlockId = block.into().into_reth();
+        self.with_heavy_permit(self.witness(block_id)).await
+    }
+
+    async fn witness(&self, block_id: BlockId) -> Result<ExecutionWitness, RethMiddlewareError<M>> {
+        let (block, senders) = self.sealed_block_with_senders(block_id)?;
+        let parent_state_root = self
+            .reth_provider
+            .block_by_id(block.parent_hash.into())?
+            .ok_or(RethMiddlewareError::BlockNotFound)?
+            .state_root;
+
+        let accesses = {
+            let env = self.block_env(&block)?;
+            let state = RethDatabaseRef::at_block(&self.reth_provider, block.parent_hash)?;
+            let db = CacheDB::new(AccessRecorder::new(state));
+
+            let (_, db) = execute_transactions(env, db, block.body.iter().zip(senders))?;
+            db.db.into_accesses()
+        };
+
+        let mut keys = accesses.accounts;
+        keys.entry(block.beneficiary).or_default();
+        for withdrawal in block.withdrawals.iter().flatten() {
+            keys.entry(withdrawal.address).or_default();
+        }
+
+        let mut nodes = BTreeSet::new();
+        for (address, slots) in keys.iter() {
+            let storage_keys = slots
+                .iter()
+                .map(|slot| JsonStorageKey(H256::from(slot.to_be_bytes::<32>())))
+                .collect();
+            let proof = self
+                .reth_api
+                .get_proof(*address, storage_keys, Some(block.parent_hash.into()))
+                .await?;
+
+            nodes.extend(proof.account_proof.iter().map(|node| node.to_vec()));
+            for storage_proof in proof.storage_proof {
+                nodes.extend(storage_proof.proof.iter().map(|node| node.to_vec()));
+            }
+        }
+
+        Ok(ExecutionWitness {
+            block_hash: block.hash().into_ethers(),
+            parent_state_root: parent_state_root.into_ethers(),
+            state: nodes.into_iter().map(EthersBytes::from).collect(),
+            codes: accesses
+                .codes
+                .into_values()
+                .map(|code| EthersBytes::from(code.original_bytes().to_vec()))
+                .collect(),
+            keys: keys
+                .into_iter()
+                .map(|(address, slots)| {
+                    let slots = slots
+                        .into_iter()
+                        .map(|slot| H256::from(slot.to_be_bytes::<32>()).into_ethers())
+                        .collect();
+                    (address.into_ethers(), slots)
+                })
+                .collect(),
+            block_hashes: accesses
+                .block_hashes
+                .into_iter()
+                .map(|(number, hash)| (number, hash.into_ethers()))
+                .collect(),
+        })
+    }
+}
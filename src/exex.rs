@@ -0,0 +1,47 @@
+use crate::RethMiddleware;
+use ethers::providers::Middleware;
+use futures::{Stream, StreamExt};
+
+// Reth
+use reth_provider::CanonStateNotification;
+
+// Std
+use tokio::sync::broadcast;
+
+/// Capacity of the channel re-broadcasting externally sourced notifications.
+pub const NOTIFICATION_CHANNEL_SIZE: usize = 256;
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Feeds the middleware's subscription streams from an external source of canonical state
+    /// notifications instead of the provider's own.
+    ///
+    /// This is the hook for running inside the node, e.g. from an execution extension: build the
+    /// middleware with [RethMiddleware::from_provider] over the node's provider and pass the
+    /// extension's notification stream here. Execution extension notifications map one to one onto
+    /// [CanonStateNotification] (committed, reorged and reverted chains), so any stream whose items
+    /// convert into it is accepted.
+    ///
+    /// The stream is drained by a background task that ends once the stream does.
+    pub fn with_notification_stream<S, N>(mut self, stream: S) -> Self
+    where
+        S: Stream<Item = N> + Send + Unpin + 'static,
+        N: Into<CanonStateNotification> + Send + 'static,
+    {
+        let (sender, _) = broadcast::channel(NOTIFICATION_CHANNEL_SIZE);
+        self.notifications = Some(sender.clone());
+
+        let task = tokio::task::spawn(async move {
+            let mut stream = stream;
+            while let Some(notification) = stream.next().await {
+                // no subscribers yet is not an error, later subscribers only see later blocks
+                let _ = sender.send(notification.into());
+            }
+        });
+        self.tasks.register(&task);
+
+        self
+    }
+}
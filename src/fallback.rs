@@ -0,0 +1,262 @@
+use crate::{staleness::StaleTipAction, RethMiddleware, RethMiddlewareError};
+use ethers::providers::{Middleware, MiddlewareError};
+use jsonrpsee::types::error::INVALID_PARAMS_CODE;
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_rpc::eth::error::EthApiError;
+
+// Std
+use std::{collections::HashMap, future::Future};
+
+/// Code of the JSON-RPC errors of reverted calls.
+const EXECUTION_REVERTED_CODE: i32 = 3;
+
+/// When a request is delegated to the wrapped provider instead of being served from the database.
+///
+/// Configuration files also accept the names `local_only`, `fallback_on_missing` and
+/// `fallback_on_error` for the first three policies.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FallbackPolicy {
+    /// Always serve the request from the database, surfacing its errors.
+    #[default]
+    #[serde(alias = "local_only")]
+    Never,
+    /// Delegate when the database cannot serve the request: the block is unknown because the node
+    /// is behind, the data was pruned, or the method is not supported locally.
+    #[serde(alias = "fallback_on_missing")]
+    OnUnavailable,
+    /// Delegate whenever the request fails locally, e.g. on a database error or a call timeout,
+    /// as well as when the database cannot serve it. Invalid parameters, reverts and halts are
+    /// the request's fault and fail the same way remotely, so they are not delegated.
+    #[serde(alias = "fallback_on_error")]
+    OnError,
+    /// Always delegate the request.
+    Always,
+}
+
+impl FallbackPolicy {
+    /// Returns true if a request that failed locally with `err` is delegated under this policy.
+    pub fn delegates<M: Middleware>(self, err: &RethMiddlewareError<M>) -> bool {
+        match self {
+            FallbackPolicy::OnUnavailable => err.is_unavailable(),
+            FallbackPolicy::OnError => !err.is_invalid_request(),
+            FallbackPolicy::Never | FallbackPolicy::Always => false,
+        }
+    }
+}
+
+/// Per-method fallback policies, keyed by the name of the [Middleware] method.
+#[derive(Debug, Clone, Default)]
+pub struct FallbackConfig {
+    default: FallbackPolicy,
+    methods: HashMap<&'static str, FallbackPolicy>,
+}
+
+impl FallbackConfig {
+    /// Applies `policy` to every method without an override.
+    pub fn new(policy: FallbackPolicy) -> Self {
+        Self { default: policy, methods: HashMap::new() }
+    }
+
+    /// Overrides the policy of a single method, e.g. `"get_logs"`.
+    pub fn with_method(mut self, method: &'static str, policy: FallbackPolicy) -> Self {
+        self.methods.insert(method, policy);
+        self
+    }
+
+    /// Returns the policy applied to `method`.
+    pub fn policy(&self, method: &str) -> FallbackPolicy {
+        self.methods.get(method).copied().unwrap_or(self.default)
+    }
+}
+
+impl<M: Middleware> RethMiddlewareError<M> {
+    /// Returns true if the error means the database cannot serve the request, rather than the
+    /// request itself being invalid.
+    pub fn is_unavailable(&self) -> bool {
+        matches!(
+            self,
+            RethMiddlewareError::BlockNotFound |
+                RethMiddlewareError::MissingTrace |
+                RethMiddlewareError::PrunedData(_) |
+                RethMiddlewareError::UnsupportedMethod(_) |
+                RethMiddlewareError::UnknownForkchoice(_) |
+                RethMiddlewareError::ProofTipMoved(_) |
+                RethMiddlewareError::EthApiError(
+                    EthApiError::UnknownBlockNumber | EthApiError::UnknownBlockOrTxIndex
+                )
+        )
+    }
+
+    /// Returns true if the error is caused by the request itself, invalid parameters or a call
+    /// that reverts or halts, and would be returned by any node.
+    pub fn is_invalid_request(&self) -> bool {
+        match self {
+            RethMiddlewareError::EthApiError(
+                EthApiError::InvalidParams(_) | EthApiError::InvalidTransaction(_),
+            ) => true,
+            RethMiddlewareError::RethApiError(err) => {
+                matches!(err.code(), INVALID_PARAMS_CODE | EXECUTION_REVERTED_CODE)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Sets the policies deciding which requests are delegated to the wrapped provider, so the
+    /// middleware can replace an existing provider without failing where the database falls short.
+    pub fn with_fallback(mut self, config: FallbackConfig) -> Self {
+        self.fallback = config;
+        self
+    }
+
+    /// Serves `method` from the database, delegating to `remote` according to its policy.
+    ///
+    /// Requests picked by the differential mode run both, the local result being returned.
+    pub(crate) async fn fallback<T, L, R>(
+        &self,
+        method: &'static str,
+        local: L,
+        remote: impl FnOnce() -> R,
+    ) -> Result<T, RethMiddlewareError<M>>
+    where
+        T: Serialize,
+        L: Future<Output = Result<T, RethMiddlewareError<M>>>,
+        R: Future<Output = Result<T, M::Error>>,
+    {
+        let local = self.with_method_timeout(method, local);
+        let policy = self.fallback.policy(method);
+        if policy == FallbackPolicy::Always {
+            return remote().await.map_err(RethMiddlewareError::from_err)
+        }
+
+        if self.differential.sample(method) {
+            let (local, remote) = futures::join!(local, remote());
+            self.differential.compare(method, &local, &remote);
+            return match local {
+                Err(err) if self.should_fall_back(policy, &err) => {
+                    remote.map_err(RethMiddlewareError::from_err)
+                }
+                res => res,
+            }
+        }
+
+        match local.await {
+            Err(err) if self.should_fall_back(policy, &err) => {
+                remote().await.map_err(RethMiddlewareError::from_err)
+            }
+            res => res,
+        }
+    }
+
+    /// Like [Self::fallback], also treating a missing block or transaction as unavailable, since
+    /// the node may not have synced it yet.
+    pub(crate) async fn fallback_optional<T, L, R>(
+        &self,
+        method: &'static str,
+        local: L,
+        remote: impl FnOnce() -> R,
+    ) -> Result<Option<T>, RethMiddlewareError<M>>
+    where
+        T: Serialize,
+        L: Future<Output = Result<Option<T>, RethMiddlewareError<M>>>,
+        R: Future<Output = Result<Option<T>, M::Error>>,
+    {
+        match self.fallback.policy(method) {
+            policy @ (FallbackPolicy::OnUnavailable | FallbackPolicy::OnError)
+                if self.differential.sample(method) =>
+            {
+                let local = self.with_method_timeout(method, local);
+                let (local, remote) = futures::join!(local, remote());
+                self.differential.compare(method, &local, &remote);
+                match local {
+                    Ok(None) => remote.map_err(RethMiddlewareError::from_err),
+                    Err(err) if self.should_fall_back(policy, &err) => {
+                        remote.map_err(RethMiddlewareError::from_err)
+                    }
+                    res => res,
+                }
+            }
+            policy @ (FallbackPolicy::OnUnavailable | FallbackPolicy::OnError) => {
+                match self.with_method_timeout(method, local).await {
+                    Ok(None) => remote().await.map_err(RethMiddlewareError::from_err),
+                    Err(err) if self.should_fall_back(policy, &err) => {
+                        remote().await.map_err(RethMiddlewareError::from_err)
+                    }
+                    res => res,
+                }
+            }
+            _ => self.fallback(method, local, remote).await,
+        }
+    }
+
+    /// Returns true if a request that failed locally with `err` is delegated. A stale tip is
+    /// delegated according to the stale tip policy alone.
+    fn should_fall_back(&self, policy: FallbackPolicy, err: &RethMiddlewareError<M>) -> bool {
+        match err {
+            RethMiddlewareError::StaleTip { .. } => {
+                self.stale_tip.action == StaleTipAction::Fallback
+            }
+            err => policy.delegates(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::providers::{MockProvider, Provider};
+    use jsonrpsee::types::ErrorObjectOwned;
+    use reth_rpc::eth::error::{RevertError, RpcInvalidTransactionError};
+
+    type Error = RethMiddlewareError<Provider<MockProvider>>;
+
+    #[test]
+    fn unavailable_data_is_delegated_on_unavailable() {
+        let policy = FallbackPolicy::OnUnavailable;
+        assert!(policy.delegates(&Error::BlockNotFound));
+        assert!(policy.delegates(&Error::PrunedData("receipts".to_string())));
+        assert!(policy.delegates(&Error::EthApiError(EthApiError::UnknownBlockNumber)));
+        assert!(!policy.delegates(&Error::CallTimeout(std::time::Duration::from_secs(1))));
+    }
+
+    #[test]
+    fn invalid_requests_are_not_delegated_on_error() {
+        let policy = FallbackPolicy::OnError;
+        assert!(policy.delegates(&Error::BlockNotFound));
+        assert!(policy.delegates(&Error::CallTimeout(std::time::Duration::from_secs(1))));
+        assert!(
+            !policy.delegates(&Error::EthApiError(EthApiError::InvalidParams("bad".to_string())))
+        );
+        let revert = RpcInvalidTransactionError::Revert(RevertError::new(Default::default()));
+        assert!(!policy.delegates(&Error::EthApiError(EthApiError::InvalidTransaction(revert))));
+        let reverted = ErrorObjectOwned::owned(EXECUTION_REVERTED_CODE, "reverted", None::<()>);
+        assert!(!policy.delegates(&Error::RethApiError(reverted)));
+        let invalid = ErrorObjectOwned::owned(INVALID_PARAMS_CODE, "invalid", None::<()>);
+        assert!(!policy.delegates(&Error::RethApiError(invalid)));
+    }
+
+    #[test]
+    fn nothing_is_delegated_locally_only() {
+        for policy in [FallbackPolicy::Never, FallbackPolicy::Always] {
+            assert!(!policy.delegates(&Error::BlockNotFound));
+            assert!(!policy.delegates(&Error::CallTimeout(std::time::Duration::from_secs(1))));
+        }
+    }
+
+    #[test]
+    fn policies_accept_their_long_names() {
+        let policy: FallbackPolicy = serde_json::from_str("\"fallback_on_missing\"").unwrap();
+        assert_eq!(policy, FallbackPolicy::OnUnavailable);
+        let policy: FallbackPolicy = serde_json::from_str("\"local_only\"").unwrap();
+        assert_eq!(policy, FallbackPolicy::Never);
+        let policy: FallbackPolicy = serde_json::from_str("\"on_error\"").unwrap();
+        assert_eq!(policy, FallbackPolicy::OnError);
+    }
+}
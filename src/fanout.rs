@@ -0,0 +1,474 @@
+use crate::{head_stats::HeadStats, RethMiddleware};
+use ethers::{
+    providers::Middleware,
+    types::{Filter, Log},
+};
+use futures::{Stream, StreamExt};
+use thiserror::Error;
+
+// Std
+use std::{
+    any::Any,
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+use tokio::{sync::Notify, task::JoinHandle};
+
+/// The default number of items a subscriber buffers.
+pub const DEFAULT_SUBSCRIBER_BUFFER: usize = 1024;
+
+/// What a subscription does once its consumer falls behind and its buffer is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LagPolicy {
+    /// Waits for the consumer, holding the shared pipeline and so every subscriber of the
+    /// stream. Subscriptions reading the notifications directly cannot hold the node, and drop
+    /// the oldest instead.
+    Block,
+    /// Drops the oldest items, with a [Lagged] marker in their place.
+    #[default]
+    DropOldest,
+    /// Ends the subscription with [Lagged].
+    Error,
+}
+
+/// A subscriber fell behind and missed this many items, or notifications for the subscriptions
+/// reading them directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("subscriber fell behind and missed {0} items")]
+pub struct Lagged(pub u64);
+
+/// A logical stream that subscribers share.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum StreamKey {
+    HeadStats,
+    /// Logs matching a filter, keyed by its debug representation.
+    Logs(String),
+}
+
+#[derive(Debug)]
+struct BufferState<T> {
+    items: VecDeque<Result<T, Lagged>>,
+    /// Set once the pipeline or the subscription ended.
+    closed: bool,
+    dropped: bool,
+}
+
+/// The items queued for a subscriber, up to `capacity` besides a gap marker.
+#[derive(Debug)]
+struct Buffer<T> {
+    state: Mutex<BufferState<T>>,
+    capacity: usize,
+    policy: LagPolicy,
+    /// Notified when an item is queued.
+    notify: Notify,
+    /// Notified when an item is taken, or the subscription dropped.
+    space: Notify,
+}
+
+impl<T> Buffer<T> {
+    fn new(capacity: usize, policy: LagPolicy) -> Self {
+        Self {
+            state: Mutex::new(BufferState {
+                items: VecDeque::new(),
+                closed: false,
+                dropped: false,
+            }),
+            capacity: capacity.max(1),
+            policy,
+            notify: Notify::new(),
+            space: Notify::new(),
+        }
+    }
+
+    fn is_live(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        !state.closed && !state.dropped
+    }
+
+    /// Waits until the buffer has room, or the subscription ended.
+    async fn wait_for_space(&self) {
+        loop {
+            {
+                let state = self.state.lock().unwrap();
+                if state.items.len() < self.capacity || state.closed || state.dropped {
+                    return
+                }
+            }
+            self.space.notified().await;
+        }
+    }
+
+    /// Queues an item, or a gap of the source, under the lag policy.
+    fn push(&self, item: Result<T, Lagged>) {
+        let mut state = self.state.lock().unwrap();
+        if state.closed || state.dropped {
+            return
+        }
+        let full = state.items.len() >= self.capacity;
+        match item {
+            Err(gap) if self.policy == LagPolicy::Error => {
+                push_gap(&mut state.items, gap);
+                state.closed = true;
+            }
+            Ok(_) if full && self.policy == LagPolicy::Error => {
+                push_gap(&mut state.items, Lagged(1));
+                state.closed = true;
+            }
+            Err(gap) => push_gap(&mut state.items, gap),
+            Ok(item) => {
+                if full {
+                    drop_oldest(&mut state.items, self.capacity);
+                }
+                state.items.push_back(Ok(item));
+            }
+        }
+        self.notify.notify_one();
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.notify.notify_one();
+    }
+}
+
+/// Appends a gap, merged with a gap that ends the queue.
+fn push_gap<T>(items: &mut VecDeque<Result<T, Lagged>>, gap: Lagged) {
+    match items.back_mut() {
+        Some(Err(last)) => last.0 += gap.0,
+        _ => items.push_back(Err(gap)),
+    }
+}
+
+/// Drops the oldest items to make room for one, replaced by a gap at the front of the queue.
+fn drop_oldest<T>(items: &mut VecDeque<Result<T, Lagged>>, capacity: usize) {
+    let mut missed = 0;
+    while items.len() >= capacity {
+        match items.pop_front() {
+            Some(Ok(_)) => missed += 1,
+            Some(Err(gap)) => missed += gap.0,
+            None => break,
+        }
+    }
+    match items.front_mut() {
+        Some(Err(first)) => first.0 += missed,
+        _ => items.push_front(Err(Lagged(missed))),
+    }
+}
+
+/// A subscription to a stream shared with other subscribers, see
+/// [RethMiddleware::watch_logs_shared].
+#[derive(Debug)]
+pub struct SharedSubscription<T> {
+    buffer: Arc<Buffer<T>>,
+}
+
+impl<T> SharedSubscription<T> {
+    /// Waits for the next item, or the number of items missed in its place, `None` once the
+    /// stream ended.
+    pub async fn next(&mut self) -> Option<Result<T, Lagged>> {
+        loop {
+            {
+                let mut state = self.buffer.state.lock().unwrap();
+                if let Some(item) = state.items.pop_front() {
+                    self.buffer.space.notify_one();
+                    return Some(item)
+                }
+                if state.closed {
+                    return None
+                }
+            }
+            // a push since the check left a permit, so it is not missed
+            self.buffer.notify.notified().await;
+        }
+    }
+
+    /// Turns the subscription into a stream.
+    pub fn into_stream(self) -> impl Stream<Item = Result<T, Lagged>> {
+        futures::stream::unfold(self, |mut subscription| async move {
+            subscription.next().await.map(|item| (item, subscription))
+        })
+    }
+}
+
+impl<T> Drop for SharedSubscription<T> {
+    fn drop(&mut self) {
+        self.buffer.state.lock().unwrap().dropped = true;
+        self.buffer.space.notify_one();
+    }
+}
+
+/// The feeding end of a subscription served by a task of its own rather than a shared pipeline,
+/// see [channel].
+#[derive(Debug)]
+pub(crate) struct Publisher<T> {
+    buffer: Arc<Buffer<T>>,
+}
+
+impl<T> Publisher<T> {
+    /// Queues an item under the lag policy, waiting for room under [LagPolicy::Block]. Returns
+    /// false once the subscription ended, so the feeding task can stop.
+    pub(crate) async fn send(&self, item: Result<T, Lagged>) -> bool {
+        if self.buffer.policy == LagPolicy::Block {
+            self.buffer.wait_for_space().await;
+        }
+        self.buffer.push(item);
+        self.buffer.is_live()
+    }
+}
+
+impl<T> Drop for Publisher<T> {
+    fn drop(&mut self) {
+        self.buffer.close();
+    }
+}
+
+/// Returns a subscription buffering up to `capacity` items under `policy`, fed by the returned
+/// publisher.
+pub(crate) fn channel<T>(
+    capacity: usize,
+    policy: LagPolicy,
+) -> (Publisher<T>, SharedSubscription<T>) {
+    let buffer = Arc::new(Buffer::new(capacity, policy));
+    (Publisher { buffer: buffer.clone() }, SharedSubscription { buffer })
+}
+
+type Pipelines = Arc<Mutex<HashMap<StreamKey, Arc<dyn Any + Send + Sync>>>>;
+
+/// The subscribers of a stream, fed by a single task.
+#[derive(Debug)]
+struct Pipeline<T> {
+    subscribers: Mutex<Vec<Arc<Buffer<T>>>>,
+}
+
+impl<T: Clone> Pipeline<T> {
+    /// Converts the source once per item and copies the items to every subscriber, until the
+    /// source ends or no subscriber is left.
+    async fn run<S: Stream<Item = Result<T, Lagged>>>(
+        self: Arc<Self>,
+        source: S,
+        key: StreamKey,
+        pipelines: Pipelines,
+    ) {
+        futures::pin_mut!(source);
+        while let Some(item) = source.next().await {
+            let subscribers = {
+                let mut subscribers = self.subscribers.lock().unwrap();
+                subscribers.retain(|buffer| buffer.is_live());
+                subscribers.clone()
+            };
+            for buffer in &subscribers {
+                if buffer.policy == LagPolicy::Block {
+                    buffer.wait_for_space().await;
+                }
+                buffer.push(item.clone());
+            }
+            if !subscribers.is_empty() {
+                continue
+            }
+
+            // checked again under the registry's lock, which new subscribers join under
+            let mut pipelines = pipelines.lock().unwrap();
+            let mut subscribers = self.subscribers.lock().unwrap();
+            subscribers.retain(|buffer| buffer.is_live());
+            if subscribers.is_empty() {
+                pipelines.remove(&key);
+                return
+            }
+        }
+
+        pipelines.lock().unwrap().remove(&key);
+        for buffer in self.subscribers.lock().unwrap().iter() {
+            buffer.close();
+        }
+    }
+}
+
+/// Shares the streams of the middleware between their subscribers: one task per stream consumes
+/// the canonical state notifications and converts them once, and each subscriber reads the items
+/// from a bounded buffer of its own. A stream's task stops at the first item after its last
+/// subscriber is dropped.
+#[derive(Debug, Clone)]
+pub struct FanOut {
+    pipelines: Pipelines,
+    buffer_size: usize,
+    lag_policy: LagPolicy,
+}
+
+impl Default for FanOut {
+    fn default() -> Self {
+        Self::new(DEFAULT_SUBSCRIBER_BUFFER, LagPolicy::default())
+    }
+}
+
+impl FanOut {
+    /// Buffers up to `buffer_size` items per subscriber, applying `lag_policy` past it.
+    pub fn new(buffer_size: usize, lag_policy: LagPolicy) -> Self {
+        Self { pipelines: Arc::default(), buffer_size, lag_policy }
+    }
+
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    pub fn lag_policy(&self) -> LagPolicy {
+        self.lag_policy
+    }
+
+    /// Subscribes to the stream under `key`, starting its pipeline from `source` if it has no
+    /// subscriber yet, in which case the task of the pipeline is returned too.
+    pub fn subscribe<T, S>(
+        &self,
+        key: StreamKey,
+        source: impl FnOnce() -> S,
+    ) -> (SharedSubscription<T>, Option<JoinHandle<()>>)
+    where
+        T: Clone + Send + Sync + 'static,
+        S: Stream<Item = Result<T, Lagged>> + Send + 'static,
+    {
+        let buffer = Arc::new(Buffer::new(self.buffer_size, self.lag_policy));
+        let mut pipelines = self.pipelines.lock().unwrap();
+        let running = pipelines
+            .get(&key)
+            .and_then(|pipeline| pipeline.clone().downcast::<Pipeline<T>>().ok());
+        if let Some(pipeline) = running {
+            pipeline.subscribers.lock().unwrap().push(buffer.clone());
+            return (SharedSubscription { buffer }, None)
+        }
+
+        let pipeline = Arc::new(Pipeline { subscribers: Mutex::new(vec![buffer.clone()]) });
+        pipelines.insert(key.clone(), pipeline.clone());
+        let task = tokio::spawn(pipeline.run(source(), key, self.pipelines.clone()));
+        (SharedSubscription { buffer }, Some(task))
+    }
+
+    /// Returns the number of streams with a running pipeline.
+    pub fn pipeline_count(&self) -> usize {
+        self.pipelines.lock().unwrap().len()
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Buffers up to `buffer_size` items per subscriber of the shared streams.
+    pub fn with_subscriber_buffer(mut self, buffer_size: usize) -> Self {
+        self.fanout = FanOut::new(buffer_size, self.fanout.lag_policy);
+        self
+    }
+
+    /// Sets what the subscriptions do once their consumer falls behind.
+    pub fn with_lag_policy(mut self, lag_policy: LagPolicy) -> Self {
+        self.fanout = FanOut::new(self.fanout.buffer_size, lag_policy);
+        self
+    }
+
+    fn subscribe_shared<T, S>(
+        &self,
+        key: StreamKey,
+        source: impl FnOnce() -> S,
+    ) -> SharedSubscription<T>
+    where
+        T: Clone + Send + Sync + 'static,
+        S: Stream<Item = Result<T, Lagged>> + Send + 'static,
+    {
+        let (subscription, task) = self.fanout.subscribe(key, source);
+        if let Some(task) = task {
+            self.tasks.register(&task);
+        }
+        subscription
+    }
+
+    /// Like [Self::watch_head_stats], sharing one pipeline with the other subscribers.
+    pub fn watch_head_stats_shared(&self) -> SharedSubscription<HeadStats> {
+        self.subscribe_shared(StreamKey::HeadStats, || self.watch_head_stats().into_stream())
+    }
+
+    /// Like [Self::watch_logs], sharing one pipeline with the other subscribers to the same
+    /// filter. A subscriber joining after some logs were delivered may receive them as removed
+    /// if their block is reverted.
+    pub fn watch_logs_shared(&self, filter: Filter) -> SharedSubscription<Log> {
+        let key = StreamKey::Logs(format!("{filter:?}"));
+        self.subscribe_shared(key, || self.watch_logs(filter).into_stream())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn drop_oldest_replaces_missed_items_with_a_gap() {
+        let (publisher, mut subscription) = channel(2, LagPolicy::DropOldest);
+        for item in 1..=4 {
+            assert!(publisher.send(Ok(item)).await);
+        }
+        assert_eq!(subscription.next().await, Some(Err(Lagged(2))));
+        assert_eq!(subscription.next().await, Some(Ok(3)));
+        assert_eq!(subscription.next().await, Some(Ok(4)));
+        drop(publisher);
+        assert_eq!(subscription.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn consecutive_gaps_are_merged() {
+        let (publisher, mut subscription) = channel::<u64>(4, LagPolicy::DropOldest);
+        publisher.send(Err(Lagged(2))).await;
+        publisher.send(Err(Lagged(3))).await;
+        assert_eq!(subscription.next().await, Some(Err(Lagged(5))));
+    }
+
+    #[tokio::test]
+    async fn error_policy_ends_a_full_subscription() {
+        let (publisher, mut subscription) = channel(1, LagPolicy::Error);
+        assert!(publisher.send(Ok(1)).await);
+        assert!(!publisher.send(Ok(2)).await);
+        assert_eq!(subscription.next().await, Some(Ok(1)));
+        assert_eq!(subscription.next().await, Some(Err(Lagged(1))));
+        assert_eq!(subscription.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn block_policy_waits_for_the_consumer() {
+        let (publisher, mut subscription) = channel(1, LagPolicy::Block);
+        assert!(publisher.send(Ok(1)).await);
+        let blocked = tokio::time::timeout(Duration::from_millis(50), publisher.send(Ok(2))).await;
+        assert!(blocked.is_err());
+
+        let send = tokio::spawn(async move { publisher.send(Ok(2)).await });
+        assert_eq!(subscription.next().await, Some(Ok(1)));
+        assert!(send.await.unwrap());
+        assert_eq!(subscription.next().await, Some(Ok(2)));
+    }
+
+    #[tokio::test]
+    async fn dropped_subscription_stops_its_publisher() {
+        let (publisher, subscription) = channel(1, LagPolicy::Block);
+        assert!(publisher.send(Ok(1)).await);
+        drop(subscription);
+        assert!(!publisher.send(Ok(2)).await);
+    }
+
+    #[tokio::test]
+    async fn subscribers_share_a_pipeline() {
+        let fanout = FanOut::new(8, LagPolicy::DropOldest);
+        let (tx, rx) = futures::channel::mpsc::unbounded::<Result<u64, Lagged>>();
+        let (mut first, task) = fanout.subscribe(StreamKey::HeadStats, || rx);
+        let (mut second, joined): (SharedSubscription<u64>, _) =
+            fanout.subscribe(StreamKey::HeadStats, futures::stream::empty);
+        assert!(task.is_some());
+        assert!(joined.is_none());
+        assert_eq!(fanout.pipeline_count(), 1);
+
+        tx.unbounded_send(Ok(7)).unwrap();
+        assert_eq!(first.next().await, Some(Ok(7)));
+        assert_eq!(second.next().await, Some(Ok(7)));
+
+        drop(tx);
+        assert_eq!(first.next().await, None);
+        assert_eq!(second.next().await, None);
+        task.unwrap().await.unwrap();
+        assert_eq!(fanout.pipeline_count(), 0);
+    }
+}
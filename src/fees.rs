@@ -0,0 +1,295 @@
+use crate::{
+    execution::coinbase::tip_per_gas, instrument::instrument, RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{BlockNumber as EthersBlockNumber, FeeHistory, U256 as EthersU256},
+};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_primitives::{BlockId, BlockNumberOrTag};
+use reth_transaction_pool::{PoolTransaction, TransactionPool};
+
+/// Signature of the estimators ethers lets callers pass to `estimate_eip1559_fees`: given the
+/// base fee and the rewards paid in recent blocks, returns the max fee and max priority fee.
+pub type Eip1559Estimator = fn(EthersU256, Vec<Vec<EthersU256>>) -> (EthersU256, EthersU256);
+
+/// How EIP-1559 fees are estimated from the fee history of recent blocks, replacing the default
+/// estimator of ethers.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeeEstimator {
+    /// Number of recent blocks sampled.
+    pub blocks: u64,
+    /// Percentile of the priority fees paid in each block that is suggested.
+    pub reward_percentile: f64,
+    /// Multiplier of the next block's base fee in the max fee, so the transaction stays
+    /// includable while the base fee rises. 2 covers six full blocks.
+    pub base_fee_multiplier: f64,
+    /// Lowest priority fee suggested, in wei.
+    pub min_priority_fee: u64,
+    /// Percentile to switch to when the sampled blocks are congested.
+    pub congestion: Option<Congestion>,
+}
+
+/// Bids higher when recent blocks are nearly full.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Congestion {
+    /// Average ratio of gas used to gas limit above which blocks count as congested.
+    pub gas_used_ratio: f64,
+    /// Percentile of the priority fees suggested in congested periods.
+    pub reward_percentile: f64,
+}
+
+/// Where gas prices and priority fees are suggested from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GasPriceMode {
+    /// Gas prices come from the inner provider, priority fees from the fee history of recent
+    /// blocks.
+    #[default]
+    History,
+    /// Both come from the local transaction pool: the tip to outbid the pending transactions
+    /// competing for the next block.
+    Pool,
+}
+
+/// What it takes to be included in the next block, read from the local transaction pool.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolFeeSuggestion {
+    /// Base fee of the next block.
+    pub base_fee: EthersU256,
+    /// Lowest tip of the pending transactions that fit in the next block, or the fee estimator's
+    /// minimum priority fee if they do not fill it.
+    pub priority_fee: EthersU256,
+    /// Gas limit of the pending transactions able to pay the base fee.
+    pub pending_gas: u64,
+    pub pending: usize,
+    pub queued: usize,
+}
+
+impl Default for FeeEstimator {
+    fn default() -> Self {
+        Self {
+            blocks: 10,
+            reward_percentile: 5.0,
+            base_fee_multiplier: 2.0,
+            min_priority_fee: 1_000_000_000,
+            congestion: Some(Congestion { gas_used_ratio: 0.9, reward_percentile: 50.0 }),
+        }
+    }
+}
+
+impl FeeEstimator {
+    /// Returns the max fee of a transaction paying `max_priority_fee` on top of the base fee,
+    /// the base fee scaled by the multiplier.
+    fn max_fee(&self, base_fee: EthersU256, max_priority_fee: EthersU256) -> EthersU256 {
+        let multiplier = EthersU256::from((self.base_fee_multiplier * 1000.0) as u64);
+        base_fee * multiplier / 1000 + max_priority_fee
+    }
+}
+
+/// Returns the median of the non-zero rewards of recent blocks at the configured percentile, or
+/// at the congestion one if the blocks were congested, at least the minimum priority fee.
+fn history_priority_fee(config: &FeeEstimator, history: &FeeHistory) -> EthersU256 {
+    let congested = config.congestion.filter(|congestion| {
+        let ratios = &history.gas_used_ratio;
+        !ratios.is_empty() &&
+            ratios.iter().sum::<f64>() / ratios.len() as f64 >= congestion.gas_used_ratio
+    });
+    let column = usize::from(congested.is_some());
+    let mut rewards: Vec<EthersU256> = history
+        .reward
+        .iter()
+        .filter_map(|rewards| rewards.get(column).copied())
+        .filter(|reward| !reward.is_zero())
+        .collect();
+    rewards.sort();
+    let median = rewards.get(rewards.len() / 2).copied().unwrap_or_default();
+    median.max(config.min_priority_fee.into())
+}
+
+/// Returns the tip to beat given the tips and gas limits of the pending transactions able to
+/// pay the base fee: one above the tip of the first transaction that no longer fits in a block
+/// of `gas_limit`, at least `min_priority_fee`.
+fn pool_priority_fee(mut tips: Vec<(u128, u64)>, gas_limit: u64, min_priority_fee: u64) -> u128 {
+    tips.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+    let mut gas = 0;
+    for (tip, tx_gas_limit) in tips {
+        gas += tx_gas_limit;
+        if gas > gas_limit {
+            return (min_priority_fee as u128).max(tip + 1)
+        }
+    }
+    min_priority_fee as u128
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Sets how EIP-1559 fees are estimated.
+    pub fn with_fee_estimator(mut self, estimator: FeeEstimator) -> Self {
+        self.fee_estimator = estimator;
+        self
+    }
+
+    /// Returns how EIP-1559 fees are estimated.
+    pub fn fee_estimator(&self) -> FeeEstimator {
+        self.fee_estimator
+    }
+
+    /// Sets where gas prices and priority fees are suggested from.
+    pub fn with_gas_price_mode(mut self, mode: GasPriceMode) -> Self {
+        self.gas_price_mode = mode;
+        self
+    }
+
+    /// Returns where gas prices and priority fees are suggested from.
+    pub fn gas_price_mode(&self) -> GasPriceMode {
+        self.gas_price_mode
+    }
+
+    /// Suggests a priority fee from the transactions pending in the local pool: ordered by tip,
+    /// the ones that fit in the next block set the tip to beat.
+    pub fn suggest_pool_fees(&self) -> Result<PoolFeeSuggestion, RethMiddlewareError<M>> {
+        let (head, _) =
+            self.sealed_block_with_senders(BlockId::Number(BlockNumberOrTag::Latest))?;
+        let base_fee = head.next_block_base_fee().unwrap_or_default();
+
+        let pending = self.reth_pool.pending_transactions();
+        let tips: Vec<(u128, u64)> = pending
+            .iter()
+            .map(|tx| tx.transaction.to_recovered_transaction())
+            .filter(|tx| tx.max_fee_per_gas() >= base_fee as u128)
+            .map(|tx| (tip_per_gas(&tx, base_fee), tx.gas_limit()))
+            .collect();
+        let pending_gas: u64 = tips.iter().map(|(_, gas_limit)| gas_limit).sum();
+        let priority_fee =
+            pool_priority_fee(tips, head.gas_limit, self.fee_estimator.min_priority_fee);
+
+        Ok(PoolFeeSuggestion {
+            base_fee: base_fee.into(),
+            priority_fee: priority_fee.into(),
+            pending_gas,
+            pending: pending.len(),
+            queued: self.reth_pool.queued_transactions().len(),
+        })
+    }
+
+    /// Returns the gas price suggested by the gas price mode.
+    pub(crate) async fn suggested_gas_price(&self) -> Result<EthersU256, RethMiddlewareError<M>> {
+        match self.gas_price_mode {
+            GasPriceMode::History => {
+                self.inner.get_gas_price().await.map_err(RethMiddlewareError::MiddlewareError)
+            }
+            GasPriceMode::Pool => {
+                let suggestion = self.suggest_pool_fees()?;
+                Ok(suggestion.base_fee + suggestion.priority_fee)
+            }
+        }
+    }
+
+    /// Estimates the max fee and max priority fee of a transaction for the next block from the
+    /// fee history of recent blocks.
+    ///
+    /// An `estimator` passed by the caller is given the next block's base fee and the rewards at
+    /// the configured percentile, as ethers would.
+    pub(crate) async fn eip1559_fees(
+        &self,
+        estimator: Option<Eip1559Estimator>,
+    ) -> Result<(EthersU256, EthersU256), RethMiddlewareError<M>> {
+        instrument("estimate_eip1559_fees", async {
+            let config = self.fee_estimator;
+            let mut percentiles = vec![config.reward_percentile];
+            if let Some(congestion) = config.congestion {
+                percentiles.push(congestion.reward_percentile);
+            }
+            let history =
+                self.fee_history(config.blocks, EthersBlockNumber::Latest, &percentiles).await?;
+
+            let base_fee = history.base_fee_per_gas.last().copied().unwrap_or_default();
+            if base_fee.is_zero() {
+                return Err(RethMiddlewareError::Eip1559NotActivated)
+            }
+            if let Some(estimator) = estimator {
+                let rewards = history
+                    .reward
+                    .iter()
+                    .map(|rewards| rewards.iter().take(1).copied().collect())
+                    .collect();
+                return Ok(estimator(base_fee, rewards))
+            }
+
+            let max_priority_fee = match self.gas_price_mode {
+                GasPriceMode::History => history_priority_fee(&config, &history),
+                GasPriceMode::Pool => self.suggest_pool_fees()?.priority_fee,
+            };
+            Ok((config.max_fee(base_fee, max_priority_fee), max_priority_fee))
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GWEI: u64 = 1_000_000_000;
+
+    fn history(gas_used_ratio: f64, rewards: &[[u64; 2]]) -> FeeHistory {
+        FeeHistory {
+            base_fee_per_gas: vec![],
+            gas_used_ratio: vec![gas_used_ratio; rewards.len()],
+            oldest_block: EthersU256::zero(),
+            reward: rewards
+                .iter()
+                .map(|rewards| rewards.iter().map(|reward| (*reward).into()).collect())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn estimators_deserialize_over_the_defaults() {
+        let estimator: FeeEstimator =
+            serde_json::from_str(r#"{"blocks": 20, "congestion": null}"#).unwrap();
+        assert_eq!(
+            estimator,
+            FeeEstimator { blocks: 20, congestion: None, ..FeeEstimator::default() }
+        );
+        assert!(serde_json::from_str::<FeeEstimator>(r#"{"block": 20}"#).is_err());
+        assert_eq!(serde_json::from_str::<GasPriceMode>(r#""pool""#).unwrap(), GasPriceMode::Pool);
+    }
+
+    #[test]
+    fn priority_fees_are_the_median_of_recent_rewards() {
+        let config = FeeEstimator::default();
+        let rewards = [[2 * GWEI, 9 * GWEI], [0, 0], [4 * GWEI, 9 * GWEI], [3 * GWEI, 9 * GWEI]];
+        // empty blocks report zero rewards, which are skipped
+        assert_eq!(history_priority_fee(&config, &history(0.5, &rewards)), (3 * GWEI).into());
+        // congested blocks switch to the second percentile
+        assert_eq!(history_priority_fee(&config, &history(0.95, &rewards)), (9 * GWEI).into());
+        assert_eq!(history_priority_fee(&config, &history(0.5, &[[1, 1]])), GWEI.into());
+        assert_eq!(history_priority_fee(&config, &history(0.5, &[])), GWEI.into());
+    }
+
+    #[test]
+    fn max_fees_scale_the_base_fee() {
+        let config = FeeEstimator { base_fee_multiplier: 1.125, ..FeeEstimator::default() };
+        assert_eq!(config.max_fee((8 * GWEI).into(), GWEI.into()), (10 * GWEI).into());
+    }
+
+    #[test]
+    fn pool_tips_beat_the_first_transaction_left_out() {
+        let tips =
+            vec![(GWEI as u128, 10_000_000), (5, 20_000_000), (3 * GWEI as u128, 15_000_000)];
+        // the 1 gwei transaction no longer fits behind the 3 gwei one
+        assert_eq!(pool_priority_fee(tips.clone(), 20_000_000, 0), GWEI as u128 + 1);
+        assert_eq!(pool_priority_fee(tips.clone(), 30_000_000, 0), 6);
+        assert_eq!(pool_priority_fee(tips.clone(), 30_000_000, GWEI), GWEI as u128);
+        assert_eq!(pool_priority_fee(tips, 60_000_000, 7), 7);
+    }
+}
@@ -0,0 +1,352 @@
+//! A deterministic test chain in a temporary datadir, behind the `test-utils` feature.
+//!
+//! [TestChain::generate] executes a short chain with reth's executor and writes it the way the
+//! node's pipeline would, so every middleware method has real blocks, receipts, logs and state to
+//! read without a running node:
+//!
+//! | Block | Transactions |
+//! | --- | --- |
+//! | 1 | a transfer, the deployment of the [emitter](emitter_runtime) |
+//! | 2 | a call emitting a log, a reverted call |
+//! | 3 | a transfer |
+//! | 4 | a call emitting a log |
+//! | 5 | none |
+//!
+//! Blocks 3 and 4 replaced an older fork of two blocks, kept in [TestChain::orphaned], so the
+//! datadir went through a reorg of depth 2. Keys, timestamps and transactions are fixed, so the
+//! block hashes are the same on every run.
+
+use crate::{
+    type_conversions::{ToEthers, ToReth},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    signers::{LocalWallet, Signer},
+    types::{
+        transaction::eip2718::TypedTransaction, Address as EthersAddress, Bytes as EthersBytes,
+        Eip1559TransactionRequest, H256 as EthersH256,
+    },
+    utils::{get_contract_address, keccak256},
+};
+use tempfile::TempDir;
+
+// Reth
+use reth_db::{
+    init_db,
+    mdbx::{Env, WriteMap},
+};
+use reth_primitives::{
+    constants::EMPTY_OMMER_ROOT,
+    proofs,
+    stage::{StageCheckpoint, StageId},
+    Account, Address, Block, Bloom, Chain, ChainSpec, ChainSpecBuilder, Genesis, GenesisAccount,
+    Header, Receipt, SealedBlock, SealedBlockWithSenders, TransactionSigned, U256,
+};
+use reth_provider::{
+    BlockExecutor, BlockWriter, ExecutorFactory, PostState, ProviderFactory, StageCheckpointWriter,
+};
+use reth_revm::Factory;
+
+// Std
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Chain id of the test chain.
+pub const TEST_CHAIN_ID: u64 = 1337;
+
+/// Balance of every test account at genesis, 1000 ether.
+pub const TEST_BALANCE: u128 = 1_000_000_000_000_000_000_000;
+
+/// Number of funded test accounts, whose keys are `[1; 32]`, `[2; 32]`, ...
+pub const TEST_ACCOUNTS: usize = 3;
+
+/// Value of the test transfers, 1 ether.
+pub const TRANSFER_VALUE: u128 = 1_000_000_000_000_000_000;
+
+/// Topic of the logs of the emitter.
+pub fn emitter_topic() -> EthersH256 {
+    keccak256("Emitted(bytes)").into()
+}
+
+/// Returns the runtime code of the emitter: it emits its calldata as the data of a log with
+/// [emitter_topic] as only topic, and reverts when called without calldata.
+pub fn emitter_runtime() -> Vec<u8> {
+    let mut code = vec![
+        0x36, 0x15, 0x60, 0x31, 0x57, // JUMPI(0x31, ISZERO(CALLDATASIZE))
+        0x36, 0x60, 0x00, 0x60, 0x00, 0x37, // CALLDATACOPY(0, 0, CALLDATASIZE)
+        0x7f, // PUSH32 topic
+    ];
+    code.extend_from_slice(emitter_topic().as_bytes());
+    code.extend_from_slice(&[
+        0x36, 0x60, 0x00, 0xa1, 0x00, // LOG1(0, CALLDATASIZE, topic) STOP
+        0x5b, 0x60, 0x00, 0x80, 0xfd, // 0x31: JUMPDEST REVERT(0, 0)
+    ]);
+    code
+}
+
+/// Returns the init code deploying the emitter.
+fn emitter_init_code() -> Vec<u8> {
+    let runtime = emitter_runtime();
+    let len = runtime.len() as u8;
+    let mut code = vec![
+        0x60, len, 0x80, // PUSH1 len DUP1
+        0x60, 0x0b, 0x60, 0x00,
+        0x39, // CODECOPY(0, 11, len), 11 being the length of this prefix
+        0x60, 0x00, 0xf3, // RETURN(0, len)
+    ];
+    code.extend(runtime);
+    code
+}
+
+/// A generated test chain, removed from disk on drop.
+#[derive(Debug)]
+pub struct TestChain {
+    dir: TempDir,
+    pub chain: Arc<ChainSpec>,
+    /// The funded accounts, with [TEST_CHAIN_ID] as chain id.
+    pub wallets: Vec<LocalWallet>,
+    /// The canonical blocks, genesis first.
+    pub blocks: Vec<SealedBlock>,
+    /// The blocks replaced by the reorg, no longer in the database.
+    pub orphaned: Vec<SealedBlock>,
+    /// Address of the emitter.
+    pub emitter: EthersAddress,
+    /// The transfer of block 1.
+    pub transfer: EthersH256,
+    /// The deployment of the emitter.
+    pub deployment: EthersH256,
+    /// The call of block 2 emitting a log.
+    pub emit: EthersH256,
+    /// The reverted call of block 2.
+    pub reverted: EthersH256,
+}
+
+impl TestChain {
+    /// Generates the chain in a new temporary directory.
+    pub fn generate() -> eyre::Result<Self> {
+        let dir = tempfile::tempdir()?;
+        let db_path = dir.path().join("db");
+        let wallets = (1..=TEST_ACCOUNTS as u8)
+            .map(|key| Ok(LocalWallet::from_bytes(&[key; 32])?.with_chain_id(TEST_CHAIN_ID)))
+            .collect::<eyre::Result<Vec<_>>>()?;
+
+        let alloc: HashMap<Address, GenesisAccount> = wallets
+            .iter()
+            .map(|wallet| {
+                let account =
+                    GenesisAccount { balance: U256::from(TEST_BALANCE), ..Default::default() };
+                (wallet.address().into_reth(), account)
+            })
+            .collect();
+        let genesis = Genesis { gas_limit: 30_000_000, alloc, ..Default::default() };
+        let chain = Arc::new(
+            ChainSpecBuilder::default()
+                .chain(Chain::Id(TEST_CHAIN_ID))
+                .genesis(genesis)
+                .paris_activated()
+                .build(),
+        );
+
+        // the database is closed when the builder is dropped, before the middleware opens it
+        let mut builder = ChainBuilder::new(Arc::new(init_db(&db_path)?), chain.clone(), wallets)?;
+        let (alice, bob, carol) = (0, 1, 2);
+        let emitter = get_contract_address(builder.wallets[alice].address(), builder.nonces[alice]);
+
+        let transfer = builder.transfer(alice, bob)?;
+        let deployment =
+            builder.sign(alice, Eip1559TransactionRequest::new().data(emitter_init_code()))?;
+        builder.mine(vec![transfer.clone(), deployment.clone()])?;
+
+        let emit = builder.sign(alice, call(emitter, b"first"))?;
+        let reverted = builder.sign(bob, call(emitter, b""))?;
+        builder.mine(vec![emit.clone(), reverted.clone()])?;
+
+        // the fork replaced by the reorg
+        let nonces = builder.nonces.clone();
+        let orphaned = builder.transfer(carol, alice)?;
+        builder.mine(vec![orphaned])?;
+        builder.mine(vec![])?;
+        let orphaned = builder.unwind(2)?;
+        builder.nonces = nonces;
+
+        let transfer_back = builder.transfer(bob, carol)?;
+        builder.mine(vec![transfer_back])?;
+        let second_emit = builder.sign(alice, call(emitter, b"second"))?;
+        builder.mine(vec![second_emit])?;
+        builder.mine(vec![])?;
+
+        let ChainBuilder { wallets, blocks, .. } = builder;
+        Ok(Self {
+            dir,
+            chain,
+            wallets,
+            blocks,
+            orphaned,
+            emitter,
+            transfer: transfer.hash().into_ethers(),
+            deployment: deployment.hash().into_ethers(),
+            emit: emit.hash().into_ethers(),
+            reverted: reverted.hash().into_ethers(),
+        })
+    }
+
+    /// Returns the datadir, holding the database in `db`.
+    pub fn datadir(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn db_path(&self) -> PathBuf {
+        self.dir.path().join("db")
+    }
+
+    /// Returns the canonical tip.
+    pub fn tip(&self) -> &SealedBlock {
+        self.blocks.last().expect("the chain has a genesis block")
+    }
+
+    /// Opens the middleware over the chain, which must outlive it. Must be called within a tokio
+    /// runtime.
+    pub fn middleware<M: Middleware>(
+        &self,
+        inner: M,
+    ) -> Result<RethMiddleware<M>, RethMiddlewareError<M>> {
+        RethMiddleware::builder(inner, self.db_path()).chain(self.chain.clone()).build()
+    }
+}
+
+/// Returns a call of `to` with `data`.
+fn call(to: EthersAddress, data: &[u8]) -> Eip1559TransactionRequest {
+    Eip1559TransactionRequest::new().to(to).data(EthersBytes::from(data.to_vec()))
+}
+
+/// Executes blocks on top of the database and writes them like the pipeline would.
+struct ChainBuilder {
+    factory: ProviderFactory<Arc<Env<WriteMap>>>,
+    chain: Arc<ChainSpec>,
+    wallets: Vec<LocalWallet>,
+    nonces: Vec<u64>,
+    blocks: Vec<SealedBlock>,
+}
+
+impl ChainBuilder {
+    /// Writes the genesis block and its state.
+    fn new(
+        db: Arc<Env<WriteMap>>,
+        chain: Arc<ChainSpec>,
+        wallets: Vec<LocalWallet>,
+    ) -> eyre::Result<Self> {
+        let mut state = PostState::new();
+        for (address, account) in &chain.genesis().alloc {
+            let account = Account { nonce: 0, balance: account.balance, bytecode_hash: None };
+            state.create_account(0, *address, account);
+        }
+        let genesis = SealedBlock {
+            header: chain.genesis_header().seal_slow(),
+            body: vec![],
+            ommers: vec![],
+            withdrawals: None,
+        };
+
+        let nonces = vec![0; wallets.len()];
+        let factory = ProviderFactory::new(db, chain.clone());
+        let mut builder = Self { factory, chain, wallets, nonces, blocks: vec![] };
+        builder.write(genesis, vec![], state)?;
+        Ok(builder)
+    }
+
+    /// Signs a transaction of the account at `from`, with its next nonce.
+    fn sign(
+        &mut self,
+        from: usize,
+        tx: Eip1559TransactionRequest,
+    ) -> eyre::Result<TransactionSigned> {
+        let wallet = &self.wallets[from];
+        let tx: TypedTransaction = tx
+            .from(wallet.address())
+            .nonce(self.nonces[from])
+            .chain_id(TEST_CHAIN_ID)
+            .gas(200_000)
+            .max_fee_per_gas(100_000_000_000u64)
+            .max_priority_fee_per_gas(1_000_000_000u64)
+            .into();
+        self.nonces[from] += 1;
+
+        let signature = wallet.sign_transaction_sync(&tx)?;
+        let raw = tx.rlp_signed(&signature);
+        Ok(TransactionSigned::decode_enveloped(raw.to_vec().into())?)
+    }
+
+    /// Signs a transfer of [TRANSFER_VALUE] between two accounts.
+    fn transfer(&mut self, from: usize, to: usize) -> eyre::Result<TransactionSigned> {
+        let to = self.wallets[to].address();
+        self.sign(from, Eip1559TransactionRequest::new().to(to).value(TRANSFER_VALUE))
+    }
+
+    /// Executes a block of transactions on top of the tip and writes it.
+    fn mine(&mut self, body: Vec<TransactionSigned>) -> eyre::Result<()> {
+        let parent = &self.blocks.last().expect("genesis is written first").header;
+        let senders = body
+            .iter()
+            .map(|tx| tx.recover_signer().ok_or_else(|| eyre::eyre!("invalid signature")))
+            .collect::<eyre::Result<Vec<_>>>()?;
+        let header = Header {
+            parent_hash: parent.hash(),
+            number: parent.number + 1,
+            timestamp: parent.timestamp + 12,
+            gas_limit: parent.gas_limit,
+            base_fee_per_gas: parent.next_block_base_fee(),
+            ommers_hash: EMPTY_OMMER_ROOT,
+            transactions_root: proofs::calculate_transaction_root(&body),
+            ..Default::default()
+        };
+        let mut block = Block { header, body, ommers: vec![], withdrawals: None };
+
+        let state = Factory::new(self.chain.clone()).with_sp(self.factory.latest()?).execute(
+            &block,
+            U256::ZERO,
+            Some(senders.clone()),
+        )?;
+        let receipts: Vec<_> =
+            state.receipts(block.number).iter().cloned().map(Receipt::with_bloom).collect();
+        block.header.gas_used =
+            receipts.last().map(|receipt| receipt.receipt.cumulative_gas_used).unwrap_or_default();
+        block.header.logs_bloom =
+            receipts.iter().fold(Bloom::zero(), |bloom, receipt| bloom | receipt.bloom);
+        block.header.receipts_root = proofs::calculate_receipt_root(&receipts);
+        block.header.state_root = state.state_root_slow(self.factory.provider()?.tx_ref())?;
+
+        self.write(block.seal_slow(), senders, state)
+    }
+
+    fn write(
+        &mut self,
+        block: SealedBlock,
+        senders: Vec<Address>,
+        state: PostState,
+    ) -> eyre::Result<()> {
+        let number = block.number;
+        let provider = self.factory.provider_rw()?;
+        let with_senders = SealedBlockWithSenders::new(block.clone(), senders)
+            .ok_or_else(|| eyre::eyre!("one sender per transaction"))?;
+        provider.append_blocks_with_post_state(vec![with_senders], state)?;
+        provider.save_stage_checkpoint(StageId::Finish, StageCheckpoint::new(number))?;
+        provider.commit()?;
+        self.blocks.push(block);
+        Ok(())
+    }
+
+    /// Removes the last `depth` blocks and their state, returning them.
+    fn unwind(&mut self, depth: usize) -> eyre::Result<Vec<SealedBlock>> {
+        let tip = self.blocks.len() as u64 - 1;
+        let ancestor = tip - depth as u64;
+        let provider = self.factory.provider_rw()?;
+        provider.take_block_and_execution_range(&self.chain, ancestor + 1..=tip)?;
+        provider.save_stage_checkpoint(StageId::Finish, StageCheckpoint::new(ancestor))?;
+        provider.commit()?;
+        Ok(self.blocks.split_off(ancestor as usize + 1))
+    }
+}
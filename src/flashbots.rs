@@ -0,0 +1,33 @@
+//! Searcher workflow pairing the local bundle simulator with Flashbots submission, behind the
+//! `flashbots` feature.
+//!
+//! Simulating against the database avoids a round trip to the relay's `eth_callBundle` for every
+//! candidate; only profitable bundles need to be sent.
+
+use crate::{execution::bundle::BundleSimulation, RethMiddleware, RethMiddlewareError};
+use ethers::{providers::Middleware, types::Bytes as EthersBytes};
+use ethers_flashbots::BundleRequest;
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Simulates the bundle on top of the local head and returns it as a request targeting the
+    /// next block, ready for `FlashbotsMiddleware::send_bundle`, together with the simulation to
+    /// check its profit against.
+    pub async fn flashbots_bundle(
+        &self,
+        transactions: Vec<EthersBytes>,
+    ) -> Result<(BundleRequest, BundleSimulation), RethMiddlewareError<M>> {
+        let simulation = self.simulate_bundle(&transactions).await?;
+
+        let bundle = transactions
+            .into_iter()
+            .fold(BundleRequest::new(), |bundle, tx| bundle.push_transaction(tx))
+            .set_block(simulation.block_number.into())
+            .set_simulation_block((simulation.block_number - 1).into())
+            .set_simulation_timestamp(simulation.timestamp);
+
+        Ok((bundle, simulation))
+    }
+}
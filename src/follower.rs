@@ -0,0 +1,298 @@
+use crate::{cache::ResponseCache, type_conversions::ToEthers, RethClient, RethMiddleware};
+use ethers::providers::Middleware;
+
+// Reth
+use reth_db::DatabaseError;
+use reth_primitives::{BlockHashOrNumber, SealedBlockWithSenders, H256};
+use reth_provider::{
+    BlockHashReader, BlockNumReader, BlockReader, CanonChainTracker, CanonStateNotification, Chain,
+    HeaderProvider, PostState, ReceiptProvider,
+};
+
+// Std
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use tokio::{sync::broadcast, task::JoinHandle};
+
+/// libmdbx error returned when another process changed the database geometry while we held a
+/// reader (`MDBX_MAP_RESIZED`). Opening a fresh read transaction picks up the new mapping.
+pub const MDBX_MAP_RESIZED: i32 = -30785;
+
+/// libmdbx error returned when a reader slot was reclaimed by the writer (`MDBX_BAD_RSLOT`).
+pub const MDBX_BAD_RSLOT: i32 = -30783;
+
+/// Most blocks the follower remembers to report as reverted, and broadcasts in one notification.
+pub const FOLLOWED_BLOCKS: u64 = 64;
+
+/// Configuration of the read-only follower.
+#[derive(Debug, Clone, Copy)]
+pub struct FollowerConfig {
+    /// How often the database is checked for newly committed blocks.
+    pub poll_interval: Duration,
+    /// How many times a refresh is retried after a transient MDBX condition.
+    pub max_retries: usize,
+}
+
+impl Default for FollowerConfig {
+    fn default() -> Self {
+        Self { poll_interval: Duration::from_millis(500), max_retries: 3 }
+    }
+}
+
+/// Returns true for MDBX conditions caused by the node writing concurrently, which are resolved by
+/// retrying with a fresh read transaction.
+pub fn is_transient_db_error(err: &reth_interfaces::Error) -> bool {
+    matches!(
+        err,
+        reth_interfaces::Error::Database(
+            DatabaseError::InitTransaction(code) | DatabaseError::Read(code)
+        ) if *code == MDBX_MAP_RESIZED || *code == MDBX_BAD_RSLOT
+    )
+}
+
+/// Runs `f`, retrying up to `max_retries` times while it fails with a transient MDBX condition.
+pub fn retry_transient<T>(
+    max_retries: usize,
+    mut f: impl FnMut() -> reth_interfaces::Result<T>,
+) -> reth_interfaces::Result<T> {
+    let mut attempt = 0;
+    loop {
+        match f() {
+            Err(err) if attempt < max_retries && is_transient_db_error(&err) => attempt += 1,
+            res => return res,
+        }
+    }
+}
+
+/// Moves the provider's canonical head to the last block committed to the database.
+///
+/// Returns the new head number if it changed, including when a reorg replaced the head with
+/// another block at the same height.
+pub fn refresh_canonical_head(provider: &RethClient) -> reth_interfaces::Result<Option<u64>> {
+    let last = provider.last_block_number()?;
+    let Some(header) = provider.sealed_header(last)? else { return Ok(None) };
+    let best = provider.chain_info()?;
+    if best.best_number == last && best.best_hash == header.hash() {
+        return Ok(None)
+    }
+
+    provider.set_canonical_head(header);
+    Ok(Some(last))
+}
+
+/// The last blocks the follower broadcast, by number, to tell the blocks a reorg replaced.
+#[derive(Debug, Default)]
+pub struct FollowedBlocks {
+    blocks: BTreeMap<u64, SealedBlockWithSenders>,
+}
+
+impl FollowedBlocks {
+    /// Returns the number of the highest followed block still canonical, `canonical` returning
+    /// the canonical hash at a height. `None` if no followed block is.
+    fn fork(&self, canonical: impl Fn(u64) -> Option<H256>) -> Option<u64> {
+        self.blocks
+            .iter()
+            .rev()
+            .find(|(number, block)| canonical(**number) == Some(block.hash()))
+            .map(|(number, _)| *number)
+    }
+
+    /// Takes out the followed blocks above `fork`, all of them if it is `None`.
+    fn revert_above(&mut self, fork: Option<u64>) -> Vec<SealedBlockWithSenders> {
+        let reverted = match fork {
+            Some(fork) => self.blocks.split_off(&(fork + 1)),
+            None => std::mem::take(&mut self.blocks),
+        };
+        reverted.into_values().collect()
+    }
+
+    /// Follows the committed blocks, forgetting the ones past [FOLLOWED_BLOCKS].
+    fn extend(&mut self, blocks: impl IntoIterator<Item = SealedBlockWithSenders>) {
+        self.blocks.extend(blocks.into_iter().map(|block| (block.header.number, block)));
+        while self.blocks.len() as u64 > FOLLOWED_BLOCKS {
+            self.blocks.pop_first();
+        }
+    }
+
+    /// Builds the notification taking the followed chain to the canonical head at `head`: the
+    /// blocks committed since, with their receipts, and the followed blocks they replaced.
+    ///
+    /// Nothing followed yet, only the head is committed. At most [FOLLOWED_BLOCKS] blocks are
+    /// committed at once, the older ones being skipped.
+    pub fn notification(
+        &mut self,
+        provider: &RethClient,
+        head: u64,
+    ) -> reth_interfaces::Result<Option<CanonStateNotification>> {
+        let fork = self.fork(|number| provider.block_hash(number).ok().flatten());
+        let first = match (fork, self.blocks.is_empty()) {
+            (Some(fork), _) => fork + 1,
+            (None, true) => head,
+            (None, false) => 0,
+        }
+        .max(head.saturating_sub(FOLLOWED_BLOCKS - 1));
+        if fork.map_or(false, |fork| fork >= head) {
+            // the head moved back to a followed block, reverted along with the next commit
+            return Ok(None)
+        }
+
+        let mut committed = Vec::new();
+        for number in first..=head {
+            let Some(block) = provider.block(BlockHashOrNumber::Number(number))? else { break };
+            let block = block.seal_slow();
+            let Some(senders) = block.body.iter().map(|tx| tx.recover_signer()).collect() else {
+                break
+            };
+            let mut state = PostState::new();
+            state.add_receipts(
+                number,
+                provider.receipts_by_block(number.into())?.unwrap_or_default(),
+            );
+            committed.push((SealedBlockWithSenders { block, senders }, state));
+        }
+        let reverted = self.revert_above(fork);
+        if committed.is_empty() && reverted.is_empty() {
+            return Ok(None)
+        }
+
+        self.extend(committed.iter().map(|(block, _)| block.clone()));
+        let new = Arc::new(Chain::new(committed));
+        if reverted.is_empty() {
+            return Ok(Some(CanonStateNotification::Commit { new }))
+        }
+        let old = Arc::new(Chain::new(
+            reverted.into_iter().map(|block| (block, PostState::default())).collect(),
+        ));
+        Ok(Some(CanonStateNotification::Reorg { old, new }))
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Follows a database that is being written by a running reth node.
+    ///
+    /// The middleware opens the database read-only, and every read transaction already observes
+    /// the latest committed data. What goes stale is the in-memory canonical head used to resolve
+    /// `latest`, which is only advanced by the node's engine. This task periodically moves it to
+    /// the last block committed by the node, transparently retrying when the node resizes the map
+    /// or reclaims a reader slot.
+    ///
+    /// Every move of the head is broadcast as a canonical state notification, committing the new
+    /// blocks and reverting the ones a reorg replaced, so the subscriptions, caches and watchers
+    /// fed by the notifications follow the node too. A middleware built over a provider it did
+    /// not open, see [RethMiddleware::from_provider], only moves the head.
+    ///
+    /// The caches of [RethMiddleware::with_cache], enabled before the follower is spawned, drop
+    /// the entries of a replaced head as soon as the follower moves the head, whether or not the
+    /// notification reporting the reorg reaches them.
+    pub fn spawn_follower(&self, config: FollowerConfig) -> JoinHandle<()> {
+        let provider = self.reth_provider.clone();
+        let sender = self.canon_state_sender.clone();
+        let cache = self.response_cache.clone();
+        let task = tokio::task::spawn(async move {
+            let mut interval = tokio::time::interval(config.poll_interval);
+            let mut followed = FollowedBlocks::default();
+            loop {
+                interval.tick().await;
+                let before = provider.chain_info().ok();
+                // errors that survive the retries are picked up again on the next tick
+                let refreshed =
+                    retry_transient(config.max_retries, || refresh_canonical_head(&provider));
+                let Ok(Some(head)) = refreshed else { continue };
+                if let (Some(cache), Some(before)) = (&cache, before) {
+                    invalidate_replaced(&provider, cache, before.best_number, before.best_hash);
+                }
+                if let Some(sender) = &sender {
+                    notify(&provider, &mut followed, head, sender, config.max_retries);
+                }
+            }
+        });
+        self.tasks.register(&task);
+        task
+    }
+}
+
+/// Drops the cached entries of the previous head and the blocks below it a reorg replaced, if
+/// the previous head is no longer canonical.
+fn invalidate_replaced(provider: &RethClient, cache: &ResponseCache, number: u64, hash: H256) {
+    let canonical = |number| provider.block_hash(number).ok().flatten();
+    if canonical(number) != Some(hash) {
+        cache.on_replaced_head(number, |number| canonical(number).map(ToEthers::into_ethers));
+    }
+}
+
+/// Broadcasts the notification taking the followed blocks to the new head, if any.
+fn notify(
+    provider: &RethClient,
+    followed: &mut FollowedBlocks,
+    head: u64,
+    sender: &broadcast::Sender<CanonStateNotification>,
+    max_retries: usize,
+) {
+    let notification = retry_transient(max_retries, || followed.notification(provider, head));
+    if let Ok(Some(notification)) = notification {
+        // no subscribers is not an error, later subscribers only see later blocks
+        let _ = sender.send(notification);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reth_primitives::{Header, SealedBlock};
+    use std::collections::HashMap;
+
+    fn block(number: u64, fork: u8) -> SealedBlockWithSenders {
+        let header = Header { number, extra_data: vec![fork].into(), ..Default::default() };
+        SealedBlockWithSenders {
+            block: SealedBlock { header: header.seal_slow(), ..Default::default() },
+            senders: vec![],
+        }
+    }
+
+    fn followed(blocks: Vec<SealedBlockWithSenders>) -> FollowedBlocks {
+        let mut followed = FollowedBlocks::default();
+        followed.extend(blocks);
+        followed
+    }
+
+    fn canonical(blocks: &[SealedBlockWithSenders]) -> HashMap<u64, H256> {
+        blocks.iter().map(|block| (block.header.number, block.hash())).collect()
+    }
+
+    #[test]
+    fn fork_is_the_highest_canonical_block() {
+        let followed = followed((1..=4).map(|number| block(number, 0)).collect());
+
+        let extended = canonical(&(1..=6).map(|number| block(number, 0)).collect::<Vec<_>>());
+        assert_eq!(followed.fork(|number| extended.get(&number).copied()), Some(4));
+
+        let reorged = canonical(&[block(1, 0), block(2, 0), block(3, 1), block(4, 1)]);
+        assert_eq!(followed.fork(|number| reorged.get(&number).copied()), Some(2));
+
+        let replaced = canonical(&(1..=4).map(|number| block(number, 1)).collect::<Vec<_>>());
+        assert_eq!(followed.fork(|number| replaced.get(&number).copied()), None);
+    }
+
+    #[test]
+    fn blocks_above_the_fork_are_reverted() {
+        let mut followed = followed((1..=4).map(|number| block(number, 0)).collect());
+
+        let reverted = followed.revert_above(Some(2));
+        assert_eq!(reverted, vec![block(3, 0), block(4, 0)]);
+        assert_eq!(followed.blocks.keys().copied().collect::<Vec<_>>(), vec![1, 2]);
+
+        assert_eq!(followed.revert_above(None), vec![block(1, 0), block(2, 0)]);
+        assert!(followed.blocks.is_empty());
+    }
+
+    #[test]
+    fn followed_blocks_are_bounded() {
+        let followed =
+            followed((1..=FOLLOWED_BLOCKS + 10).map(|number| block(number, 0)).collect());
+
+        assert_eq!(followed.blocks.len() as u64, FOLLOWED_BLOCKS);
+        assert_eq!(followed.blocks.keys().next(), Some(&11));
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{instrument::db_read, RethMiddleware, RethMiddlewareError};
+use ethers::{
+    providers::{Middleware, MiddlewareError},
+    types::BlockNumber as EthersBlockNumber,
+};
+
+// Reth
+use reth_primitives::{BlockId, BlockNumHash, BlockNumberOrTag, H256};
+use reth_provider::{BlockIdReader, CanonChainTracker, HeaderProvider};
+
+// Std
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// The safe and finalized blocks the `safe` and `finalized` tags resolve to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Forkchoice {
+    pub safe: Option<BlockNumHash>,
+    pub finalized: Option<BlockNumHash>,
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the safe and finalized blocks known to the provider.
+    ///
+    /// A middleware built from a running node shares its fork choice tracking. One opened on a
+    /// datadir has none, as the node does not persist it, until [Self::sync_forkchoice] runs.
+    pub fn forkchoice(&self) -> Result<Forkchoice, RethMiddlewareError<M>> {
+        Ok(Forkchoice {
+            safe: self.reth_provider.safe_block_num_hash()?,
+            finalized: self.reth_provider.finalized_block_num_hash()?,
+        })
+    }
+
+    /// Copies the safe and finalized blocks of the inner provider's node, the one that drives the
+    /// database, to the local provider.
+    ///
+    /// A block is only taken over once the database holds it as canonical, so the tags never
+    /// resolve to a block that cannot be served locally.
+    pub async fn sync_forkchoice(&self) -> Result<Forkchoice, RethMiddlewareError<M>> {
+        for tag in [EthersBlockNumber::Safe, EthersBlockNumber::Finalized] {
+            let block = self.inner.get_block(tag).await.map_err(RethMiddlewareError::from_err)?;
+            let Some((number, hash)) = block.and_then(|block| block.number.zip(block.hash)) else {
+                continue
+            };
+
+            let header =
+                db_read("sealed_header", || self.reth_provider.sealed_header(number.as_u64()))?;
+            match header {
+                Some(header) if header.hash() == H256::from(hash.0) => match tag {
+                    EthersBlockNumber::Safe => self.reth_provider.set_safe(header),
+                    _ => self.reth_provider.set_finalized(header),
+                },
+                _ => {}
+            }
+        }
+
+        self.forkchoice()
+    }
+
+    /// Runs [Self::sync_forkchoice] every `interval`, aborted on [Self::shutdown].
+    pub fn spawn_forkchoice_sync(&self, interval: Duration) -> JoinHandle<()>
+    where
+        M: Clone + 'static,
+    {
+        let middleware = self.clone();
+        let task = tokio::task::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                // a failed sync keeps the previous blocks until the next tick
+                let _ = middleware.sync_forkchoice().await;
+            }
+        });
+        self.tasks.register(&task);
+        task
+    }
+
+    /// Fails if `block` is the `safe` or `finalized` tag and the provider does not know which
+    /// block it stands for, instead of the tag silently resolving to nothing.
+    pub(crate) fn ensure_forkchoice(
+        &self,
+        block: Option<BlockId>,
+    ) -> Result<(), RethMiddlewareError<M>> {
+        let known = match block {
+            Some(BlockId::Number(BlockNumberOrTag::Safe)) => {
+                ("safe", self.reth_provider.safe_block_num_hash()?.is_some())
+            }
+            Some(BlockId::Number(BlockNumberOrTag::Finalized)) => {
+                ("finalized", self.reth_provider.finalized_block_num_hash()?.is_some())
+            }
+            _ => return Ok(()),
+        };
+
+        match known {
+            (_, true) => Ok(()),
+            (tag, false) => Err(RethMiddlewareError::UnknownForkchoice(tag)),
+        }
+    }
+}
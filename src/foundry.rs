@@ -0,0 +1,43 @@
+//! foundry-evm integration behind the `foundry` feature.
+//!
+//! foundry forks a chain through a [SharedBackend], a [DatabaseRef](reth_revm::DatabaseRef) that
+//! lazily fetches accounts, storage and block hashes from a provider at a pinned block. Backed by
+//! the middleware, those requests are served in process from the database instead of over HTTP.
+
+use crate::{instrument::db_read, type_conversions::ToReth, RethMiddleware, RethMiddlewareError};
+use ethers::{providers::Middleware, types::BlockId as EthersBlockId};
+use foundry_evm::executor::fork::{BlockchainDb, BlockchainDbMeta, SharedBackend};
+
+// Reth
+use reth_primitives::BlockId;
+use reth_provider::BlockReaderIdExt;
+
+// Std
+use std::collections::BTreeSet;
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware + Clone + Unpin + 'static,
+{
+    /// Spawns a foundry fork backend pinned at `block`, in the environment the block was executed
+    /// with.
+    ///
+    /// The returned backend can be handed to foundry's executor, or wrapped in a `CacheDB` by
+    /// tooling running its own EVM.
+    pub async fn foundry_fork_backend<T: Into<EthersBlockId> + Send + Sync>(
+        &self,
+        block: T,
+    ) -> Result<SharedBackend, RethMiddlewareError<M>> {
+        let block_id: BlockId = block.into().into_reth();
+        let block = db_read("block_by_id", || self.reth_provider.block_by_id(block_id))?
+            .ok_or(RethMiddlewareError::BlockNotFound)?
+            .seal_slow();
+
+        let env = self.block_env(&block)?;
+        let meta =
+            BlockchainDbMeta { cfg_env: env.cfg, block_env: env.block, hosts: BTreeSet::new() };
+        let db = BlockchainDb::new(meta, None);
+
+        Ok(SharedBackend::spawn_backend(self.clone(), db, Some(block.number.into())).await)
+    }
+}
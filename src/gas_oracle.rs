@@ -0,0 +1,72 @@
+use crate::{fees::GasPriceMode, RethMiddleware, RethMiddlewareError};
+use async_trait::async_trait;
+use ethers::{
+    middleware::gas_oracle::{GasOracle, GasOracleError, Result},
+    providers::Middleware,
+    types::{BlockNumber as EthersBlockNumber, U256 as EthersU256},
+};
+
+/// A [GasOracle] answering from the node's own fee history or transaction pool, so a
+/// `GasOracleMiddleware` can do without a third-party oracle.
+///
+/// Fees follow the [FeeEstimator](crate::fees::FeeEstimator) of the middleware, their priority
+/// fee coming from the source picked by the oracle's [GasPriceMode].
+#[derive(Debug, Clone)]
+pub struct LocalGasOracle<M> {
+    middleware: RethMiddleware<M>,
+}
+
+impl<M> LocalGasOracle<M>
+where
+    M: Middleware + Clone,
+{
+    pub fn new(middleware: &RethMiddleware<M>, mode: GasPriceMode) -> Self {
+        Self { middleware: middleware.clone().with_gas_price_mode(mode) }
+    }
+}
+
+fn oracle_error<M: Middleware + 'static>(err: RethMiddlewareError<M>) -> GasOracleError {
+    GasOracleError::ProviderError(Box::new(err))
+}
+
+#[async_trait]
+impl<M> GasOracle for LocalGasOracle<M>
+where
+    M: Middleware + 'static,
+{
+    /// Returns the base fee of the next block and the suggested priority fee, unlike
+    /// [Middleware::get_gas_price] never asking the inner provider.
+    async fn fetch(&self) -> Result<EthersU256> {
+        match self.middleware.gas_price_mode() {
+            GasPriceMode::History => {
+                let history = self
+                    .middleware
+                    .fee_history(1u64, EthersBlockNumber::Latest, &[])
+                    .await
+                    .map_err(oracle_error)?;
+                let base_fee = history.base_fee_per_gas.last().copied().unwrap_or_default();
+                let (_, priority_fee) =
+                    self.middleware.eip1559_fees(None).await.map_err(oracle_error)?;
+                Ok(base_fee + priority_fee)
+            }
+            GasPriceMode::Pool => {
+                let suggestion = self.middleware.suggest_pool_fees().map_err(oracle_error)?;
+                Ok(suggestion.base_fee + suggestion.priority_fee)
+            }
+        }
+    }
+
+    async fn estimate_eip1559_fees(&self) -> Result<(EthersU256, EthersU256)> {
+        self.middleware.eip1559_fees(None).await.map_err(oracle_error)
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware + Clone,
+{
+    /// Returns a [GasOracle] backed by the node, suggesting fees from `mode`'s source.
+    pub fn gas_oracle(&self, mode: GasPriceMode) -> LocalGasOracle<M> {
+        LocalGasOracle::new(self, mode)
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{
+    fanout::{LagPolicy, Lagged},
+    type_conversions::{rpc::block::CancunFields, ToEthers},
+    RethMiddleware,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Block as EthersBlock, H256 as EthersH256},
+};
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_primitives::{BlockNumHash, SealedBlockWithSenders};
+use reth_provider::{CanonStateNotification, CanonStateNotifications};
+
+// Std
+use std::collections::VecDeque;
+use tokio::sync::broadcast::error::RecvError;
+
+/// A summary of a new canonical head, for monitoring without fetching the block.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeadStats {
+    pub number: u64,
+    pub hash: EthersH256,
+    pub timestamp: u64,
+    pub base_fee_per_gas: Option<u64>,
+    pub gas_used: u64,
+    pub gas_limit: u64,
+    /// Gas used over the gas limit.
+    pub gas_used_ratio: f64,
+    /// Blob gas used, `None` for headers without Cancun fields.
+    pub blob_gas_used: Option<u64>,
+    pub transaction_count: usize,
+    /// Whether the head replaced blocks that were canonical, the first head of a reorg.
+    pub reorg: bool,
+}
+
+impl HeadStats {
+    /// Summarizes a block, `reorg` set if it replaced canonical blocks.
+    pub fn of(block: &SealedBlockWithSenders, reorg: bool) -> Self {
+        let header: EthersBlock<EthersH256> = block.header.clone().into_ethers();
+        Self {
+            number: block.header.number,
+            hash: block.hash().into_ethers(),
+            timestamp: block.header.timestamp,
+            base_fee_per_gas: block.header.base_fee_per_gas,
+            gas_used: block.header.gas_used,
+            gas_limit: block.header.gas_limit,
+            gas_used_ratio: if block.header.gas_limit == 0 {
+                0.0
+            } else {
+                block.header.gas_used as f64 / block.header.gas_limit as f64
+            },
+            blob_gas_used: CancunFields::of(&header).blob_gas_used.map(|used| used.as_u64()),
+            transaction_count: block.body.len(),
+            reorg,
+        }
+    }
+}
+
+/// A subscription to a [HeadStats] per newly committed block, built on canonical state
+/// notifications.
+#[derive(Debug)]
+pub struct HeadStatsSubscription {
+    notifications: CanonStateNotifications,
+    head: Option<BlockNumHash>,
+    queued: VecDeque<HeadStats>,
+    lag_policy: LagPolicy,
+    ended: bool,
+}
+
+impl HeadStatsSubscription {
+    pub fn new(notifications: CanonStateNotifications) -> Self {
+        Self {
+            notifications,
+            head: None,
+            queued: VecDeque::new(),
+            lag_policy: LagPolicy::default(),
+            ended: false,
+        }
+    }
+
+    /// Sets what the subscription does when it misses notifications, by default reporting them
+    /// and going on.
+    pub fn with_lag_policy(mut self, lag_policy: LagPolicy) -> Self {
+        self.lag_policy = lag_policy;
+        self
+    }
+
+    /// Waits for the next head, or the number of notifications missed, `None` once the
+    /// notification channel closes.
+    pub async fn next(&mut self) -> Option<Result<HeadStats, Lagged>> {
+        loop {
+            if let Some(stats) = self.queued.pop_front() {
+                return Some(Ok(stats))
+            }
+            if self.ended {
+                return None
+            }
+            match self.notifications.recv().await {
+                Ok(notification) => self.process(&notification),
+                Err(RecvError::Lagged(missed)) => {
+                    self.ended = self.lag_policy == LagPolicy::Error;
+                    return Some(Err(Lagged(missed)))
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Turns the subscription into a stream of heads.
+    pub fn into_stream(self) -> impl Stream<Item = Result<HeadStats, Lagged>> {
+        futures::stream::unfold(self, |mut subscription| async move {
+            subscription.next().await.map(|stats| (stats, subscription))
+        })
+    }
+
+    fn process(&mut self, notification: &CanonStateNotification) {
+        let Some(chain) = notification.committed() else { return };
+        // a segment not extending the last head replaces it, even if no revert was reported
+        let mut reorg = notification.reverted().is_some();
+        for block in chain.blocks().values() {
+            if let Some(head) = self.head {
+                reorg |= block.header.number <= head.number ||
+                    (block.header.number == head.number + 1 &&
+                        block.header.parent_hash != head.hash);
+            }
+            self.queued.push_back(HeadStats::of(block, reorg));
+            self.head = Some(BlockNumHash { number: block.header.number, hash: block.hash() });
+            reorg = false;
+        }
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Subscribes to a summary of every newly committed block.
+    pub fn watch_head_stats(&self) -> HeadStatsSubscription {
+        HeadStatsSubscription::new(self.subscribe_to_canonical_state())
+            .with_lag_policy(self.fanout.lag_policy())
+    }
+}
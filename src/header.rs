@@ -0,0 +1,56 @@
+use crate::{
+    instrument::{convert, db_read, instrument, record_block},
+    type_conversions::{ToEthers, ToReth},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Block as EthersBlock, BlockId as EthersBlockId, H256 as EthersH256},
+};
+
+// Reth
+use reth_primitives::BlockId;
+use reth_provider::{BlockReaderIdExt, HeaderProvider};
+
+// Std
+use std::ops::RangeInclusive;
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the header of a block without reading its body, as a block with no transactions
+    /// or uncles.
+    pub async fn get_header<T: Into<EthersBlockId> + Send + Sync>(
+        &self,
+        block: T,
+    ) -> Result<Option<EthersBlock<EthersH256>>, RethMiddlewareError<M>> {
+        instrument("get_header", async {
+            let block_id: BlockId = block.into().into_reth();
+            record_block(&block_id);
+            self.ensure_fresh(Some(block_id))?;
+            self.ensure_forkchoice(Some(block_id))?;
+
+            let header = db_read("sealed_header_by_id", || {
+                self.reth_provider.sealed_header_by_id(block_id)
+            })?;
+            Ok(convert("header", || header.map(ToEthers::into_ethers)))
+        })
+        .await
+    }
+
+    /// Returns the headers of the canonical blocks in `range`, in order, stopping at the first
+    /// block the database does not have.
+    pub async fn get_headers(
+        &self,
+        range: RangeInclusive<u64>,
+    ) -> Result<Vec<EthersBlock<EthersH256>>, RethMiddlewareError<M>> {
+        instrument("get_headers", async {
+            record_block(&range);
+            let headers =
+                db_read("sealed_headers_range", || self.reth_provider.sealed_headers_range(range))?;
+            Ok(convert("headers", || headers.into_iter().map(ToEthers::into_ethers).collect()))
+        })
+        .await
+    }
+}
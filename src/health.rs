@@ -0,0 +1,82 @@
+use crate::{
+    instrument::{db_read, instrument},
+    type_conversions::ToEthers,
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{providers::Middleware, types::H256 as EthersH256};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_primitives::stage::StageId;
+use reth_provider::{BlockNumReader, HeaderProvider, StageCheckpointReader};
+
+// Std
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Freshness of the local database, for load balancers and supervisors deciding whether the
+/// middleware should serve traffic.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Health {
+    /// The block `latest` resolves to.
+    pub block_number: u64,
+    pub block_hash: EthersH256,
+    pub timestamp: u64,
+    /// Seconds elapsed since the timestamp of the latest block.
+    pub age: u64,
+    /// True while the node is still downloading headers past the last fully processed block.
+    pub syncing: bool,
+    /// Highest header the node downloaded.
+    pub highest_header: u64,
+    /// Blocks the node committed that `latest` does not include yet, see
+    /// [RethMiddleware::spawn_follower].
+    pub reader_lag: u64,
+}
+
+impl Health {
+    /// Returns true if the node is synced and the latest block is at most `max_age` old.
+    pub fn is_healthy(&self, max_age: Duration) -> bool {
+        !self.syncing && self.age <= max_age.as_secs()
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Reports the latest local block, its age, the node's sync status and how far `latest`
+    /// trails the blocks committed to the database.
+    pub async fn health(&self) -> Result<Health, RethMiddlewareError<M>> {
+        instrument("health", async {
+            let chain_info = self.reth_provider.chain_info()?;
+            let header = db_read("sealed_header", || {
+                self.reth_provider.sealed_header(chain_info.best_number)
+            })?
+            .ok_or(RethMiddlewareError::BlockNotFound)?;
+
+            let last_committed =
+                db_read("last_block_number", || self.reth_provider.last_block_number())?;
+            let finished = db_read("stage_checkpoint", || {
+                self.reth_provider.get_stage_checkpoint(StageId::Finish)
+            })?
+            .map_or(0, |checkpoint| checkpoint.block_number);
+            let highest_header = db_read("stage_checkpoint", || {
+                self.reth_provider.get_stage_checkpoint(StageId::Headers)
+            })?
+            .map_or(0, |checkpoint| checkpoint.block_number);
+
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+            Ok(Health {
+                block_number: header.number,
+                block_hash: header.hash().into_ethers(),
+                timestamp: header.timestamp,
+                age: now.saturating_sub(header.timestamp),
+                syncing: highest_header > finished,
+                highest_header,
+                reader_lag: last_committed.saturating_sub(chain_info.best_number),
+            })
+        })
+        .await
+    }
+}
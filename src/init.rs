@@ -1,10 +1,16 @@
-use eyre::Context;
 use reth_beacon_consensus::BeaconConsensus;
 use reth_blockchain_tree::{
     externals::TreeExternals, BlockchainTree, BlockchainTreeConfig, ShareableBlockchainTree,
 };
 
-use crate::{RethApi, RethDebug, RethFilter, RethMiddleware, RethTrace};
+#[cfg(feature = "debug")]
+use crate::RethDebug;
+#[cfg(feature = "trace")]
+use crate::RethTrace;
+use crate::{
+    chain::detect_chain, network::RethNetwork, pruning::PruneLimits, shutdown::BackgroundTasks,
+    RethApi, RethClient, RethFilter, RethMiddleware,
+};
 use ethers::providers::Middleware;
 // Reth
 use reth_db::{
@@ -15,21 +21,27 @@ use reth_db::{
     DatabaseError,
 };
 use reth_network_api::noop::NoopNetwork;
-use reth_primitives::MAINNET;
-use reth_provider::{providers::BlockchainProvider, ProviderFactory};
+use reth_primitives::{ChainSpec, MAINNET};
+use reth_provider::{providers::BlockchainProvider, CanonStateNotification, ProviderFactory};
 use reth_revm::Factory;
+#[cfg(feature = "debug")]
+use reth_rpc::DebugApi;
+#[cfg(feature = "trace")]
+use reth_rpc::TraceApi;
+#[cfg(any(feature = "trace", feature = "debug"))]
+use reth_rpc::TracingCallGuard;
 use reth_rpc::{
     eth::{
         cache::{EthStateCache, EthStateCacheConfig},
         gas_oracle::{GasPriceOracle, GasPriceOracleConfig},
     },
-    DebugApi, EthApi, EthFilter, TraceApi, TracingCallGuard,
+    EthApi, EthFilter,
 };
-use reth_tasks::TaskManager;
+use reth_tasks::{TaskExecutor, TaskManager};
 use reth_transaction_pool::{EthTransactionValidator, GasCostOrdering, Pool, PooledTransaction};
 // Std
 use std::{fmt::Debug, path::Path, sync::Arc};
-use tokio::runtime::Handle;
+use tokio::{runtime::Handle, sync::broadcast};
 
 pub type Provider = BlockchainProvider<
     Arc<Env<WriteMap>>,
@@ -39,21 +51,114 @@ pub type Provider = BlockchainProvider<
 pub type RethTxPool =
     Pool<EthTransactionValidator<Provider, PooledTransaction>, GasCostOrdering<PooledTransaction>>;
 
+/// Sizing of the RPC handlers.
+#[derive(Debug, Clone)]
+pub struct ApiConfig {
+    /// Sizes of the block, receipt and environment caches shared by the handlers.
+    pub state_cache: EthStateCacheConfig,
+    /// Block range and percentile used to suggest gas prices.
+    pub gas_oracle: GasPriceOracleConfig,
+    /// Number of `trace` and `debug` requests executed concurrently.
+    pub max_tracing_requests: u32,
+    /// Maximum number of logs returned by a single `eth_getLogs`.
+    pub max_logs_per_response: usize,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            state_cache: EthStateCacheConfig::default(),
+            gas_oracle: GasPriceOracleConfig::default(),
+            max_tracing_requests: 10,
+            max_logs_per_response: 1000,
+        }
+    }
+}
+
+/// Handles of a running node the middleware can be built from, so embedding it in a custom reth
+/// binary shares the node's database, pool and executor instead of opening its own.
+///
+/// The eth handlers are served with a no-op network, as the middleware never gossips
+/// transactions itself. The node's network handle only answers the networking methods.
+#[derive(Clone)]
+pub struct NodeComponents {
+    /// The node's blockchain provider, wrapping its provider factory and blockchain tree.
+    pub provider: RethClient,
+    /// The node's transaction pool.
+    pub pool: RethTxPool,
+    /// Executor used to spawn the handlers' background tasks.
+    pub task_executor: TaskExecutor,
+    /// Segments of the database the node pruned.
+    pub prune_limits: PruneLimits,
+    /// Tasks aborted on [RethMiddleware::shutdown].
+    pub tasks: BackgroundTasks,
+    /// The node's database, for the reads the provider has no method for, e.g. walking the
+    /// state. `None` if only the provider was shared.
+    pub db: Option<Arc<Env<WriteMap>>>,
+    /// The node's network handle, answering `net_peerCount`, `admin_peers` and `admin_nodeInfo`.
+    /// `None` for a database opened on its own.
+    pub network: Option<Arc<dyn RethNetwork>>,
+    /// Sender of the canonical state notifications of the provider's tree, through which the
+    /// follower broadcasts the blocks it takes over. `None` if the node's engine sends them.
+    pub canon_state_sender: Option<broadcast::Sender<CanonStateNotification>>,
+}
+
+impl NodeComponents {
+    /// Sets the network handle of the node, e.g. reth's `NetworkHandle`.
+    pub fn with_network(mut self, network: impl RethNetwork) -> Self {
+        self.network = Some(Arc::new(network));
+        self
+    }
+}
+
+/// The RPC handlers the middleware serves requests with. The `trace` and `debug` handlers are
+/// only built with their features.
+#[derive(Clone)]
+pub struct RethApis {
+    pub eth: RethApi,
+    pub filter: RethFilter,
+    #[cfg(feature = "trace")]
+    pub trace: RethTrace,
+    #[cfg(feature = "debug")]
+    pub debug: RethDebug,
+}
+
 impl<M> RethMiddleware<M>
 where
     M: Middleware,
 {
-    pub fn try_new(
+    /// Opens the database, with the chain detected from its genesis block. Databases of unknown
+    /// chains are opened as mainnet.
+    pub fn try_new(db_path: &Path, handle: Handle) -> reth_interfaces::Result<NodeComponents> {
+        let db = Arc::new(init_db(db_path)?);
+        let chain = detect_chain(&db)?.unwrap_or_else(|| MAINNET.clone());
+        Self::init_components(db, chain, handle)
+    }
+
+    /// Opens the database of a node running `chain`.
+    pub fn try_new_with_chain(
         db_path: &Path,
+        chain: Arc<ChainSpec>,
         handle: Handle,
-    ) -> Result<(RethApi, RethFilter, RethTrace, RethDebug), DatabaseError> {
+    ) -> reth_interfaces::Result<NodeComponents> {
+        let db = Arc::new(init_db(db_path)?);
+        Self::init_components(db, chain, handle)
+    }
+
+    /// Builds the provider and pool on top of an open database.
+    pub fn init_components(
+        db: Arc<Env<WriteMap>>,
+        chain: Arc<ChainSpec>,
+        handle: Handle,
+    ) -> reth_interfaces::Result<NodeComponents> {
         let task_manager = TaskManager::new(handle);
         let task_executor = task_manager.executor();
 
-        tokio::task::spawn(task_manager);
+        let tasks = BackgroundTasks::default();
+        // aborting the manager drops it, which fires the shutdown signal of its executor
+        tasks.register(&tokio::task::spawn(task_manager));
 
-        let chain = MAINNET.clone();
-        let db = Arc::new(init_db(db_path).unwrap());
+        let prune_limits = PruneLimits::detect(&db)?;
 
         let tree_externals = TreeExternals::new(
             db.clone(),
@@ -67,38 +172,66 @@ where
         let (canon_state_notification_sender, _receiver) =
             tokio::sync::broadcast::channel(tree_config.max_reorg_depth() as usize * 2);
 
-        let blockchain_tree = ShareableBlockchainTree::new(
-            BlockchainTree::new(tree_externals, canon_state_notification_sender, tree_config)
-                .unwrap(),
-        );
+        let blockchain_tree = ShareableBlockchainTree::new(BlockchainTree::new(
+            tree_externals,
+            canon_state_notification_sender.clone(),
+            tree_config,
+        )?);
 
         let provider = BlockchainProvider::new(
             ProviderFactory::new(Arc::clone(&db), Arc::clone(&chain)),
             blockchain_tree,
-        )
-        .unwrap();
+        )?;
 
-        let state_cache = EthStateCache::spawn(provider.clone(), EthStateCacheConfig::default());
+        let pool = Self::init_pool(provider.clone(), chain, task_executor.clone());
 
-        let tx_pool = reth_transaction_pool::Pool::eth_pool(
-            EthTransactionValidator::new(provider.clone(), chain, task_executor.clone()),
+        Ok(NodeComponents {
+            provider,
+            pool,
+            task_executor,
+            prune_limits,
+            tasks,
+            db: Some(db),
+            network: None,
+            canon_state_sender: Some(canon_state_notification_sender),
+        })
+    }
+
+    /// Creates an empty transaction pool validating against the given provider.
+    pub fn init_pool(
+        provider: RethClient,
+        chain: Arc<ChainSpec>,
+        task_executor: TaskExecutor,
+    ) -> RethTxPool {
+        reth_transaction_pool::Pool::eth_pool(
+            EthTransactionValidator::new(provider, chain, task_executor),
             Default::default(),
-        );
+        )
+    }
+
+    /// Builds the RPC handlers on top of an already open provider and pool, e.g. the ones of a
+    /// running node.
+    pub fn init_apis(components: &NodeComponents) -> RethApis {
+        Self::init_apis_with_config(components, &ApiConfig::default())
+    }
+
+    /// Like [Self::init_apis], with the caches and limits of the handlers sized by `config`.
+    pub fn init_apis_with_config(components: &NodeComponents, config: &ApiConfig) -> RethApis {
+        let NodeComponents { provider, pool: tx_pool, task_executor, .. } = components.clone();
+        let state_cache = EthStateCache::spawn(provider.clone(), config.state_cache.clone());
 
         let reth_api = EthApi::new(
             provider.clone(),
             tx_pool.clone(),
             NoopNetwork::default(),
             state_cache.clone(),
-            GasPriceOracle::new(
-                provider.clone(),
-                GasPriceOracleConfig::default(),
-                state_cache.clone(),
-            ),
+            GasPriceOracle::new(provider.clone(), config.gas_oracle.clone(), state_cache.clone()),
         );
 
-        let tracing_call_guard = TracingCallGuard::new(10);
+        #[cfg(any(feature = "trace", feature = "debug"))]
+        let tracing_call_guard = TracingCallGuard::new(config.max_tracing_requests);
 
+        #[cfg(feature = "trace")]
         let reth_trace = TraceApi::new(
             provider.clone(),
             reth_api.clone(),
@@ -107,6 +240,7 @@ where
             tracing_call_guard.clone(),
         );
 
+        #[cfg(feature = "debug")]
         let reth_debug = DebugApi::new(
             provider.clone(),
             reth_api.clone(),
@@ -114,10 +248,22 @@ where
             tracing_call_guard,
         );
 
-        let reth_filter =
-            EthFilter::new(provider, tx_pool, state_cache, 1000, Box::new(task_executor));
+        let reth_filter = EthFilter::new(
+            provider,
+            tx_pool,
+            state_cache,
+            config.max_logs_per_response,
+            Box::new(task_executor),
+        );
 
-        Ok((reth_api, reth_filter, reth_trace, reth_debug))
+        RethApis {
+            eth: reth_api,
+            filter: reth_filter,
+            #[cfg(feature = "trace")]
+            trace: reth_trace,
+            #[cfg(feature = "debug")]
+            debug: reth_debug,
+        }
     }
 }
 
@@ -136,19 +282,27 @@ where
 }
 
 /// Opens up an existing database at the specified path.
-pub fn init_db<P: AsRef<Path> + Debug>(path: P) -> eyre::Result<Env<WriteMap>> {
-    let _ = std::fs::create_dir_all(path.as_ref());
-    let db = reth_db::mdbx::Env::<reth_db::mdbx::WriteMap>::open(
-        path.as_ref(),
-        reth_db::mdbx::EnvKind::RO,
-        None,
-    )?;
+pub fn init_db<P: AsRef<Path> + Debug>(path: P) -> Result<Env<WriteMap>, DatabaseError> {
+    let db = open_db(path)?;
 
     view(&db, |tx| {
-        for table in tables::Tables::ALL.iter().map(|table| table.name()) {
-            tx.inner.open_db(Some(table)).wrap_err("Could not open db.").unwrap();
-        }
-    })?;
+        tables::Tables::ALL.iter().try_for_each(|table| {
+            tx.inner
+                .open_db(Some(table.name()))
+                .map(|_| ())
+                .map_err(|e| DatabaseError::FailedToOpen(e.to_err_code()))
+        })
+    })??;
 
     Ok(db)
 }
+
+/// Opens the database at the specified path read-only, without checking its tables.
+pub(crate) fn open_db<P: AsRef<Path>>(path: P) -> Result<Env<WriteMap>, DatabaseError> {
+    let _ = std::fs::create_dir_all(path.as_ref());
+    reth_db::mdbx::Env::<reth_db::mdbx::WriteMap>::open(
+        path.as_ref(),
+        reth_db::mdbx::EnvKind::RO,
+        None,
+    )
+}
@@ -0,0 +1,82 @@
+//! Instrumentation shared by every middleware method.
+//!
+//! Requests are recorded as metrics when the `metrics` feature is enabled, and wrapped in
+//! [tracing] spans unless the default `tracing` feature is disabled.
+
+use std::{fmt::Debug, future::Future};
+
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+#[cfg(feature = "tracing")]
+use tracing::{field, Instrument};
+
+/// Runs a middleware request inside its own span, recording it as a metric.
+pub(crate) async fn instrument<T, E, F>(method: &'static str, request: F) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    #[cfg(feature = "tracing")]
+    let request = request.instrument(tracing::debug_span!(
+        target: "ethers_reth",
+        "request",
+        method,
+        block = field::Empty,
+        bytes = field::Empty,
+    ));
+
+    #[cfg(feature = "metrics")]
+    let start = Instant::now();
+
+    let res = request.await;
+
+    #[cfg(feature = "metrics")]
+    crate::metrics::record_request(method, start.elapsed(), res.is_ok());
+    #[cfg(not(feature = "metrics"))]
+    let _ = method;
+
+    res
+}
+
+/// Records the block the current request resolved to.
+pub(crate) fn record_block(block: &impl Debug) {
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record("block", field::debug(block));
+    #[cfg(not(feature = "tracing"))]
+    let _ = block;
+}
+
+/// Records the size of the payload returned by the current request.
+pub(crate) fn record_bytes(len: usize) {
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record("bytes", len);
+    #[cfg(not(feature = "tracing"))]
+    let _ = len;
+}
+
+/// Runs a reth -> ethers conversion inside its own span.
+pub(crate) fn convert<T>(ty: &'static str, conversion: impl FnOnce() -> T) -> T {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::trace_span!(target: "ethers_reth::conversion", "convert", ty).entered();
+    #[cfg(not(feature = "tracing"))]
+    let _ = ty;
+
+    conversion()
+}
+
+/// Runs a direct database read inside its own span, recording it as a metric.
+pub(crate) fn db_read<T>(operation: &'static str, read: impl FnOnce() -> T) -> T {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::trace_span!(target: "ethers_reth::db", "db_read", operation).entered();
+
+    #[cfg(feature = "metrics")]
+    let start = Instant::now();
+
+    let res = read();
+
+    #[cfg(feature = "metrics")]
+    crate::metrics::record_db_read(operation, start.elapsed());
+    #[cfg(not(any(feature = "metrics", feature = "tracing")))]
+    let _ = operation;
+
+    res
+}
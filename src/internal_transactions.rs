@@ -0,0 +1,109 @@
+use crate::{
+    otterscan::{internal_operation, OperationType},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{
+        Address as EthersAddress, BlockNumber as EthersBlockNumber, Trace as EthersTrace,
+        TxHash as EthersTxHash, U256 as EthersU256,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+// Std
+use std::collections::HashSet;
+
+/// What to extract internal transactions from: every transaction of a block, or a single one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InternalTransactionsOf {
+    Block(EthersBlockNumber),
+    Transaction(EthersTxHash),
+}
+
+impl From<EthersBlockNumber> for InternalTransactionsOf {
+    fn from(block: EthersBlockNumber) -> Self {
+        InternalTransactionsOf::Block(block)
+    }
+}
+
+impl From<u64> for InternalTransactionsOf {
+    fn from(block: u64) -> Self {
+        InternalTransactionsOf::Block(block.into())
+    }
+}
+
+impl From<EthersTxHash> for InternalTransactionsOf {
+    fn from(tx_hash: EthersTxHash) -> Self {
+        InternalTransactionsOf::Transaction(tx_hash)
+    }
+}
+
+/// A value transfer made below the top-level call of a transaction, what block explorers list as
+/// an internal transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InternalTransaction {
+    pub block_number: u64,
+    pub transaction_hash: Option<EthersTxHash>,
+    pub transaction_position: Option<usize>,
+    /// Position of the frame in the call tree of the transaction.
+    pub trace_address: Vec<usize>,
+    #[serde(rename = "type")]
+    pub ty: OperationType,
+    pub from: EthersAddress,
+    pub to: EthersAddress,
+    pub value: EthersU256,
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the calls, contract creations and self-destructs moving ether below the top-level
+    /// call of the transactions of a block, or of a single transaction, in execution order.
+    ///
+    /// Frames that reverted, or whose parent reverted, moved nothing and are left out.
+    pub async fn get_internal_transactions<T: Into<InternalTransactionsOf>>(
+        &self,
+        target: T,
+    ) -> Result<Vec<InternalTransaction>, RethMiddlewareError<M>> {
+        let traces = match target.into() {
+            InternalTransactionsOf::Block(block) => self.trace_block(block).await?,
+            InternalTransactionsOf::Transaction(tx_hash) => self.trace_transaction(tx_hash).await?,
+        };
+        Ok(internal_transactions(&traces))
+    }
+}
+
+/// Extracts the internal transactions from the parity traces of one or more transactions.
+fn internal_transactions(traces: &[EthersTrace]) -> Vec<InternalTransaction> {
+    let reverted: HashSet<(Option<usize>, &[usize])> = traces
+        .iter()
+        .filter(|trace| trace.error.is_some())
+        .map(|trace| (trace.transaction_position, trace.trace_address.as_slice()))
+        .collect();
+    let is_reverted = |trace: &EthersTrace| {
+        (0..=trace.trace_address.len()).any(|depth| {
+            reverted.contains(&(trace.transaction_position, &trace.trace_address[..depth]))
+        })
+    };
+
+    traces
+        .iter()
+        .filter(|trace| !trace.trace_address.is_empty() && !is_reverted(trace))
+        .filter_map(|trace| {
+            let operation = internal_operation(trace)?;
+            (!operation.value.is_zero()).then(|| InternalTransaction {
+                block_number: trace.block_number,
+                transaction_hash: trace.transaction_hash,
+                transaction_position: trace.transaction_position,
+                trace_address: trace.trace_address.clone(),
+                ty: operation.ty,
+                from: operation.from,
+                to: operation.to,
+                value: operation.value,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,295 @@
+use crate::{RethMiddleware, RethMiddlewareError};
+use ethers::{
+    providers::Middleware,
+    types::{Filter, Log, Trace, TransactionReceipt},
+};
+use futures::{stream, StreamExt, TryStreamExt};
+
+// Std
+use std::{
+    fmt, fs, io,
+    ops::RangeInclusive,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// Callback receiving the progress of an export after every chunk.
+pub type ProgressFn = Arc<dyn Fn(&JobProgress) + Send + Sync>;
+
+/// What an export job reads from every block of its range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExportKind {
+    /// Logs matching the filter, whose block range is ignored.
+    Logs(Filter),
+    /// Parity traces of every transaction.
+    Traces,
+    /// Receipts of every transaction.
+    Receipts,
+}
+
+/// The data exported from a chunk of blocks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExportData {
+    Logs(Vec<Log>),
+    Traces(Vec<Trace>),
+    Receipts(Vec<TransactionReceipt>),
+}
+
+/// A chunk of exported blocks, handed to the sink in block order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportBatch {
+    pub blocks: RangeInclusive<u64>,
+    pub data: ExportData,
+}
+
+/// How far an export job got.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobProgress {
+    pub job: String,
+    /// Last block handed to the sink.
+    pub done_through: u64,
+    pub blocks_done: u64,
+    pub blocks_total: u64,
+}
+
+/// How an export job ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobOutcome {
+    /// Every block of the range was exported.
+    Completed,
+    /// The job was cancelled, and resumes from `next_block` when run again.
+    Cancelled { next_block: u64 },
+}
+
+/// Flag shared with a running export job to stop it at the next chunk boundary.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Where export jobs record the next block to export, so a restarted job resumes there.
+pub trait CheckpointStore: Send + Sync {
+    /// Returns the next block to export for the job, `None` if it never ran.
+    fn load(&self, job: &str) -> io::Result<Option<u64>>;
+
+    /// Records the next block to export for the job.
+    fn save(&self, job: &str, next_block: u64) -> io::Result<()>;
+}
+
+/// Checkpoints kept in a directory, one file per job.
+#[derive(Debug, Clone)]
+pub struct FileCheckpoints {
+    dir: PathBuf,
+}
+
+impl FileCheckpoints {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, job: &str) -> PathBuf {
+        self.dir.join(format!("{job}.checkpoint"))
+    }
+}
+
+impl CheckpointStore for FileCheckpoints {
+    fn load(&self, job: &str) -> io::Result<Option<u64>> {
+        match fs::read_to_string(self.path(job)) {
+            Ok(contents) => contents
+                .trim()
+                .parse()
+                .map(Some)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn save(&self, job: &str, next_block: u64) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        // written aside and renamed, so a crash never leaves a torn checkpoint
+        let tmp = self.dir.join(format!("{job}.checkpoint.tmp"));
+        fs::write(&tmp, next_block.to_string())?;
+        fs::rename(tmp, self.path(job))
+    }
+}
+
+/// A long export or backfill over a block range, split in chunks read concurrently and handed to
+/// a sink in order.
+#[derive(Clone)]
+pub struct ExportJob {
+    id: String,
+    kind: ExportKind,
+    range: RangeInclusive<u64>,
+    chunk_size: u64,
+    concurrency: usize,
+    checkpoints: Option<Arc<dyn CheckpointStore>>,
+    progress: Option<ProgressFn>,
+    cancel: CancelToken,
+}
+
+impl fmt::Debug for ExportJob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExportJob")
+            .field("id", &self.id)
+            .field("kind", &self.kind)
+            .field("range", &self.range)
+            .field("chunk_size", &self.chunk_size)
+            .field("concurrency", &self.concurrency)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ExportJob {
+    /// Creates a job exporting the blocks of `range`, 100 blocks at a time, 4 chunks at once.
+    /// The id names its checkpoint.
+    pub fn new(id: impl Into<String>, kind: ExportKind, range: RangeInclusive<u64>) -> Self {
+        Self {
+            id: id.into(),
+            kind,
+            range,
+            chunk_size: 100,
+            concurrency: 4,
+            checkpoints: None,
+            progress: None,
+            cancel: CancelToken::default(),
+        }
+    }
+
+    /// Sets the number of blocks read per chunk.
+    pub fn chunk_size(mut self, blocks: u64) -> Self {
+        self.chunk_size = blocks.max(1);
+        self
+    }
+
+    /// Sets the number of chunks read concurrently.
+    pub fn concurrency(mut self, chunks: usize) -> Self {
+        self.concurrency = chunks.max(1);
+        self
+    }
+
+    /// Resumes from, and records progress to, the given checkpoint store.
+    pub fn checkpoints(mut self, store: impl CheckpointStore + 'static) -> Self {
+        self.checkpoints = Some(Arc::new(store));
+        self
+    }
+
+    /// Calls `progress` after every chunk handed to the sink.
+    pub fn on_progress(mut self, progress: impl Fn(&JobProgress) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Returns a token cancelling the job.
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Runs an export job, handing every chunk to `sink` in block order.
+    ///
+    /// The checkpoint is saved once the sink accepted a chunk, so a job run again after a crash
+    /// resumes at the first chunk the sink may not have seen: delivery is at least once. A
+    /// cancelled job stops at the next chunk boundary.
+    pub async fn run_export<E: fmt::Display>(
+        &self,
+        job: &ExportJob,
+        mut sink: impl FnMut(ExportBatch) -> Result<(), E>,
+    ) -> Result<JobOutcome, RethMiddlewareError<M>> {
+        let (first, last) = (*job.range.start(), *job.range.end());
+        let checkpoint_error = |err: io::Error| {
+            RethMiddlewareError::ExportError(format!("checkpoint of {}: {err}", job.id))
+        };
+
+        let start = match &job.checkpoints {
+            Some(store) => store.load(&job.id).map_err(checkpoint_error)?.unwrap_or(first),
+            None => first,
+        }
+        .max(first);
+        let blocks_total = (last + 1).saturating_sub(first);
+
+        let chunks = (start..=last)
+            .step_by(job.chunk_size as usize)
+            .map(|from| from..=(from + job.chunk_size - 1).min(last));
+        let mut batches = stream::iter(chunks)
+            .map(|blocks| self.with_heavy_permit(self.export_chunk(&job.kind, blocks)))
+            .buffered(job.concurrency);
+
+        let mut next_block = start;
+        while let Some(batch) = batches.try_next().await? {
+            if job.cancel.is_cancelled() {
+                return Ok(JobOutcome::Cancelled { next_block })
+            }
+            let done_through = *batch.blocks.end();
+            sink(batch).map_err(|err| {
+                RethMiddlewareError::ExportError(format!("sink of {}: {err}", job.id))
+            })?;
+
+            next_block = done_through + 1;
+            if let Some(store) = &job.checkpoints {
+                store.save(&job.id, next_block).map_err(checkpoint_error)?;
+            }
+            if let Some(progress) = &job.progress {
+                progress(&JobProgress {
+                    job: job.id.clone(),
+                    done_through,
+                    blocks_done: next_block - first,
+                    blocks_total,
+                });
+            }
+        }
+        Ok(JobOutcome::Completed)
+    }
+
+    /// Reads the data of a chunk of blocks, in the heavy request slot of the chunk.
+    async fn export_chunk(
+        &self,
+        kind: &ExportKind,
+        blocks: RangeInclusive<u64>,
+    ) -> Result<ExportBatch, RethMiddlewareError<M>> {
+        let data = match kind {
+            ExportKind::Logs(filter) => {
+                let filter = filter.clone().from_block(*blocks.start()).to_block(*blocks.end());
+                ExportData::Logs(self.get_logs(&filter).await?)
+            }
+            ExportKind::Traces => {
+                let mut traces = vec![];
+                for number in blocks.clone() {
+                    traces.extend(self.trace_block(number.into()).await?);
+                }
+                ExportData::Traces(traces)
+            }
+            ExportKind::Receipts => {
+                let mut receipts = vec![];
+                for number in blocks.clone() {
+                    let block =
+                        self.get_block(number).await?.ok_or(RethMiddlewareError::BlockNotFound)?;
+                    for tx_hash in block.transactions {
+                        receipts.extend(self.get_transaction_receipt(tx_hash).await?);
+                    }
+                }
+                ExportData::Receipts(receipts)
+            }
+        };
+        Ok(ExportBatch { blocks, data })
+    }
+}
@@ -0,0 +1,228 @@
+//! EIP-4844 blob sidecars: the versioned hashes transactions commit to blobs with, and, with the
+//! `kzg` feature, the verification of the KZG proofs of blobs.
+use ethers::types::{Bytes, H256};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+#[cfg(feature = "kzg")]
+use std::path::Path;
+
+/// Version byte of the versioned hashes of KZG commitments.
+pub const VERSIONED_HASH_VERSION_KZG: u8 = 0x01;
+
+/// Size of a blob, 4096 field elements of 32 bytes.
+pub const BYTES_PER_BLOB: usize = 131_072;
+
+/// Size of a KZG commitment or proof, a compressed G1 point.
+pub const BYTES_PER_COMMITMENT: usize = 48;
+
+/// A blob with its KZG commitment and proof, as the beacon API's `blob_sidecars` returns them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlobSidecar {
+    pub blob: Bytes,
+    pub kzg_commitment: Bytes,
+    pub kzg_proof: Bytes,
+}
+
+impl BlobSidecar {
+    /// Returns the versioned hash a transaction carrying the blob commits to it with.
+    pub fn versioned_hash(&self) -> H256 {
+        kzg_to_versioned_hash(&self.kzg_commitment)
+    }
+}
+
+/// A blob sidecar that does not match its commitment or the transaction it belongs to.
+#[derive(Error, Debug)]
+pub enum KzgError {
+    #[error("Sidecar {index} has a {field} of {len} bytes, expected {expected}")]
+    InvalidLength { index: usize, field: &'static str, len: usize, expected: usize },
+
+    #[error("Sidecar {index} has the versioned hash {found:?}, expected {expected:?}")]
+    VersionedHashMismatch { index: usize, expected: H256, found: H256 },
+
+    #[error("Expected {expected} sidecars, got {found}")]
+    SidecarCount { expected: usize, found: usize },
+
+    /// The proof does not open the commitment to the blob.
+    #[error("Sidecar {0} has an invalid KZG proof")]
+    InvalidProof(usize),
+
+    /// The KZG library rejected the trusted setup or a blob, commitment or proof as malformed.
+    #[error("KZG error: {0}")]
+    Backend(String),
+}
+
+/// Returns the versioned hash of a KZG commitment: its SHA-256 hash, with the first byte set to
+/// [VERSIONED_HASH_VERSION_KZG].
+pub fn kzg_to_versioned_hash(commitment: &[u8]) -> H256 {
+    let mut hash: [u8; 32] = Sha256::digest(commitment).into();
+    hash[0] = VERSIONED_HASH_VERSION_KZG;
+    H256(hash)
+}
+
+/// Checks that `sidecars` are, in order, the blobs of the versioned hashes of a transaction.
+/// Their proofs are checked by [KzgVerifier].
+pub fn verify_versioned_hashes(
+    sidecars: &[BlobSidecar],
+    versioned_hashes: &[H256],
+) -> Result<(), KzgError> {
+    if sidecars.len() != versioned_hashes.len() {
+        return Err(KzgError::SidecarCount {
+            expected: versioned_hashes.len(),
+            found: sidecars.len(),
+        })
+    }
+    for (index, (sidecar, expected)) in sidecars.iter().zip(versioned_hashes).enumerate() {
+        check_lengths(index, sidecar)?;
+        let found = sidecar.versioned_hash();
+        if found != *expected {
+            return Err(KzgError::VersionedHashMismatch { index, expected: *expected, found })
+        }
+    }
+    Ok(())
+}
+
+fn check_lengths(index: usize, sidecar: &BlobSidecar) -> Result<(), KzgError> {
+    let fields = [
+        ("blob", sidecar.blob.len(), BYTES_PER_BLOB),
+        ("commitment", sidecar.kzg_commitment.len(), BYTES_PER_COMMITMENT),
+        ("proof", sidecar.kzg_proof.len(), BYTES_PER_COMMITMENT),
+    ];
+    match fields.into_iter().find(|(_, len, expected)| len != expected) {
+        Some((field, len, expected)) => {
+            Err(KzgError::InvalidLength { index, field, len, expected })
+        }
+        None => Ok(()),
+    }
+}
+
+/// Verifies the KZG proofs of blob sidecars against the trusted setup of the KZG ceremony.
+#[cfg(feature = "kzg")]
+pub struct KzgVerifier {
+    settings: c_kzg::KzgSettings,
+}
+
+#[cfg(feature = "kzg")]
+impl std::fmt::Debug for KzgVerifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KzgVerifier").finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "kzg")]
+fn backend_error(err: c_kzg::Error) -> KzgError {
+    KzgError::Backend(format!("{err:?}"))
+}
+
+#[cfg(feature = "kzg")]
+impl KzgVerifier {
+    /// Loads the trusted setup from a file in the format of the consensus specs'
+    /// `trusted_setup.txt`.
+    pub fn load_trusted_setup_file(path: &Path) -> Result<Self, KzgError> {
+        let settings = c_kzg::KzgSettings::load_trusted_setup_file(path).map_err(backend_error)?;
+        Ok(Self { settings })
+    }
+
+    /// Checks that the proof of a sidecar opens its commitment to its blob.
+    pub fn verify(&self, sidecar: &BlobSidecar) -> Result<(), KzgError> {
+        self.verify_batch(std::slice::from_ref(sidecar))
+    }
+
+    /// Checks the proofs of several sidecars at once, faster than one by one. If the batch fails,
+    /// the proofs are checked one by one to name the first invalid sidecar.
+    pub fn verify_batch(&self, sidecars: &[BlobSidecar]) -> Result<(), KzgError> {
+        let mut blobs = Vec::with_capacity(sidecars.len());
+        let mut commitments = Vec::with_capacity(sidecars.len());
+        let mut proofs = Vec::with_capacity(sidecars.len());
+        for (index, sidecar) in sidecars.iter().enumerate() {
+            check_lengths(index, sidecar)?;
+            blobs.push(c_kzg::Blob::from_bytes(&sidecar.blob).map_err(backend_error)?);
+            commitments
+                .push(c_kzg::Bytes48::from_bytes(&sidecar.kzg_commitment).map_err(backend_error)?);
+            proofs.push(c_kzg::Bytes48::from_bytes(&sidecar.kzg_proof).map_err(backend_error)?);
+        }
+
+        let valid = c_kzg::KzgProof::verify_blob_kzg_proof_batch(
+            &blobs,
+            &commitments,
+            &proofs,
+            &self.settings,
+        )
+        .map_err(backend_error)?;
+        if valid {
+            return Ok(())
+        }
+        let index = (0..sidecars.len())
+            .find(|index| {
+                !c_kzg::KzgProof::verify_blob_kzg_proof(
+                    &blobs[*index],
+                    &commitments[*index],
+                    &proofs[*index],
+                    &self.settings,
+                )
+                .unwrap_or(false)
+            })
+            .unwrap_or_default();
+        Err(KzgError::InvalidProof(index))
+    }
+
+    /// Checks that `sidecars` are the blobs of a transaction's versioned hashes, in order, and
+    /// that their proofs hold, so blob data fetched from a beacon node can be trusted end to end.
+    pub fn verify_sidecars(
+        &self,
+        sidecars: &[BlobSidecar],
+        versioned_hashes: &[H256],
+    ) -> Result<(), KzgError> {
+        verify_versioned_hashes(sidecars, versioned_hashes)?;
+        self.verify_batch(sidecars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The sidecar of the zero blob, whose commitment and proof are the point at infinity.
+    fn zero_sidecar() -> BlobSidecar {
+        let mut infinity = vec![0; BYTES_PER_COMMITMENT];
+        infinity[0] = 0xc0;
+        BlobSidecar {
+            blob: vec![0; BYTES_PER_BLOB].into(),
+            kzg_commitment: infinity.clone().into(),
+            kzg_proof: infinity.into(),
+        }
+    }
+
+    fn zero_versioned_hash() -> H256 {
+        "0x010657f37554c781402a22917dee2f75def7ab966d7b770905398eba3c444014".parse().unwrap()
+    }
+
+    #[test]
+    fn versioned_hashes_are_versioned_sha256_hashes() {
+        assert_eq!(zero_sidecar().versioned_hash(), zero_versioned_hash());
+    }
+
+    #[test]
+    fn sidecars_match_the_versioned_hashes_in_order() {
+        let sidecar = zero_sidecar();
+        let hash = zero_versioned_hash();
+        assert!(verify_versioned_hashes(&[sidecar.clone()], &[hash]).is_ok());
+
+        assert!(matches!(
+            verify_versioned_hashes(&[sidecar.clone()], &[hash, hash]),
+            Err(KzgError::SidecarCount { expected: 2, found: 1 })
+        ));
+        assert!(matches!(
+            verify_versioned_hashes(&[sidecar.clone(), sidecar.clone()], &[hash, H256::zero()]),
+            Err(KzgError::VersionedHashMismatch { index: 1, .. })
+        ));
+
+        let mut short = sidecar;
+        short.kzg_proof = vec![0; 32].into();
+        assert!(matches!(
+            verify_versioned_hashes(&[short], &[hash]),
+            Err(KzgError::InvalidLength { index: 0, field: "proof", len: 32, expected: 48 })
+        ));
+    }
+}
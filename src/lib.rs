@@ -1,27 +1,152 @@
 // std
-use eyre::Result;
 use std::{fmt::Debug, path::Path, sync::Arc};
 
 // ethers
-use ethers::providers::{Middleware, MiddlewareError};
+use ethers::providers::{Middleware, MiddlewareError, ProviderError};
 
 //Reth
 use reth_beacon_consensus::BeaconConsensus;
 use reth_blockchain_tree::ShareableBlockchainTree;
 use reth_db::mdbx::{Env, WriteMap};
 use reth_network_api::noop::NoopNetwork;
-use reth_provider::providers::BlockchainProvider;
+use reth_primitives::{ChainSpec, H256};
+use reth_provider::{providers::BlockchainProvider, CanonStateNotification};
 use reth_revm::Factory;
-use reth_rpc::{eth::error::EthApiError, DebugApi, EthApi, EthFilter, TraceApi};
+#[cfg(feature = "debug")]
+use reth_rpc::DebugApi;
+#[cfg(feature = "trace")]
+use reth_rpc::TraceApi;
+use reth_rpc::{eth::error::EthApiError, EthApi, EthFilter};
+use reth_tasks::TaskExecutor;
 use reth_transaction_pool::{EthTransactionValidator, GasCostOrdering, Pool, PooledTransaction};
 //Error
+use accounts::Accounts;
+use builder::Namespaces;
+use cache::ResponseCache;
+use differential::Differential;
+#[cfg(feature = "debug")]
+use execution::tracers::NativeTracers;
+use execution::{precompiles::PrecompileOverrides, spec::SpecOverride};
+use fallback::FallbackConfig;
+use fanout::FanOut;
+use fees::{FeeEstimator, GasPriceMode};
+use init::{ApiConfig, NodeComponents};
 use jsonrpsee::types::ErrorObjectOwned;
+use limits::{CallLimits, ConcurrencyLimit, LogLimits, MethodTimeouts, TraceLimits};
+use network::RethNetwork;
+use nonce::NonceManager;
+use pending::PendingMode;
+use pruning::PruneLimits;
+use receipt::RederivedReceipts;
+use senders::SenderCache;
+use server::{auth::ServerAuth, rate_limit::RateLimiter};
+use shutdown::BackgroundTasks;
+use staleness::StaleTipPolicy;
 use thiserror::Error;
+use timestamp::TimestampPivots;
+use transport::{RethTransport, RethTransportError};
 
+pub mod account;
+pub mod accounts;
+#[cfg(feature = "alloy")]
+pub mod alloy;
+#[cfg(feature = "anvil")]
+pub mod anvil;
+pub mod balance;
+pub mod beacon_root;
+pub mod blob;
+mod block_body;
+pub mod builder;
+pub mod cache;
+pub mod call_tree;
+pub mod chain;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod confirmations;
+pub mod database;
+pub mod datadir;
+pub mod deployments;
+#[cfg(feature = "dex")]
+pub mod dex;
+pub mod differential;
+pub mod erc20;
+pub mod erc4337;
+pub mod events;
+pub mod execution;
+pub mod exex;
+pub mod fallback;
+pub mod fanout;
+pub mod fees;
+#[cfg(feature = "test-utils")]
+pub mod fixture;
+#[cfg(feature = "flashbots")]
+pub mod flashbots;
+pub mod follower;
+pub mod forkchoice;
+#[cfg(feature = "foundry")]
+pub mod foundry;
+pub mod gas_oracle;
+pub mod head_stats;
+mod header;
+pub mod health;
 pub mod init;
+mod instrument;
+#[cfg(feature = "otterscan")]
+pub mod internal_transactions;
+pub mod jobs;
+pub mod kzg;
+pub mod limits;
+pub mod logs;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod middleware;
-pub mod type_conversions;
-use tokio::runtime::Handle;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod network;
+pub mod nonce;
+#[cfg(feature = "optimism")]
+pub mod optimism;
+#[cfg(feature = "otterscan")]
+pub mod otterscan;
+pub mod pending;
+pub mod priority_fees;
+pub mod proof;
+pub mod proxy;
+pub mod pruning;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "debug")]
+pub mod raw;
+mod receipt;
+pub mod registry;
+pub mod reorg;
+mod senders;
+pub mod server;
+#[cfg(feature = "shadow-fork")]
+pub mod shadow_fork;
+pub mod shutdown;
+pub mod staleness;
+pub mod state_stats;
+pub mod state_walker;
+pub mod storage_layout;
+pub mod storage_slots;
+pub mod supply;
+pub mod timestamp;
+pub mod tokens;
+pub mod trace_diff;
+#[cfg(feature = "trace")]
+pub mod trace_filter;
+pub mod transport;
+pub mod tx_lookup;
+pub mod typed_call;
+pub use ethers_reth_types as type_conversions;
+pub mod verify;
+pub mod web3;
+pub mod withdrawals;
+use tokio::{
+    runtime::Handle,
+    sync::{broadcast, OnceCell},
+};
 
 pub type RethClient = BlockchainProvider<
     Arc<Env<WriteMap>>,
@@ -35,16 +160,58 @@ pub type RethTxPool = Pool<
 
 pub type RethApi = EthApi<RethClient, RethTxPool, NoopNetwork>;
 pub type RethFilter = EthFilter<RethClient, RethTxPool>;
+#[cfg(feature = "trace")]
 pub type RethTrace = TraceApi<RethClient, RethApi>;
+#[cfg(feature = "debug")]
 pub type RethDebug = DebugApi<RethClient, RethApi>;
 
 #[derive(Clone)]
 pub struct RethMiddleware<M> {
     inner: M,
+    reth_provider: RethClient,
+    reth_pool: RethTxPool,
+    task_executor: TaskExecutor,
     reth_api: RethApi,
     reth_filter: RethFilter,
+    #[cfg(feature = "trace")]
     reth_trace: RethTrace,
+    #[cfg(feature = "debug")]
     reth_debug: RethDebug,
+    notifications: Option<broadcast::Sender<CanonStateNotification>>,
+    canon_state_sender: Option<broadcast::Sender<CanonStateNotification>>,
+    network: Option<Arc<dyn RethNetwork>>,
+    fallback: FallbackConfig,
+    differential: Differential,
+    prune_limits: PruneLimits,
+    stale_tip: StaleTipPolicy,
+    pending: PendingMode,
+    call_limits: CallLimits,
+    log_limits: LogLimits,
+    proof_chunk_size: Option<usize>,
+    max_proof_keys: Option<usize>,
+    trace_limits: TraceLimits,
+    method_timeouts: MethodTimeouts,
+    heavy_requests: ConcurrencyLimit,
+    spec_override: SpecOverride,
+    precompile_overrides: PrecompileOverrides,
+    native_multicall: bool,
+    #[cfg(feature = "debug")]
+    native_tracers: NativeTracers,
+    fee_estimator: FeeEstimator,
+    gas_price_mode: GasPriceMode,
+    nonces: NonceManager,
+    accounts: Accounts,
+    namespaces: Namespaces,
+    rate_limiter: RateLimiter,
+    server_auth: ServerAuth,
+    transport: Arc<OnceCell<ethers::providers::Provider<RethTransport>>>,
+    timestamp_pivots: TimestampPivots,
+    senders: SenderCache,
+    rederived_receipts: Option<RederivedReceipts>,
+    response_cache: Option<ResponseCache>,
+    fanout: FanOut,
+    tasks: BackgroundTasks,
+    db: Option<Arc<Env<WriteMap>>>,
 }
 
 impl<M: std::fmt::Debug> std::fmt::Debug for RethMiddleware<M> {
@@ -67,12 +234,131 @@ pub enum RethMiddlewareError<M: Middleware> {
     #[error(transparent)]
     EthApiError(#[from] EthApiError),
 
+    /// An error occurred opening or reading the Reth database.
+    #[error(transparent)]
+    ProviderError(#[from] reth_interfaces::Error),
+
+    /// A Reth type could not be converted to its ethers counterpart.
+    #[error("Conversion error: {0}")]
+    ConversionError(String),
+
+    /// An error occurred dispatching a raw request to the local handlers.
+    #[error(transparent)]
+    TransportError(#[from] RethTransportError),
+
     /// A trace was expected but none was found.
     #[error("Missing trace")]
     MissingTrace,
 
+    /// The requested block does not exist in the database.
+    #[error("Block not found")]
+    BlockNotFound,
+
+    /// The requested data has been pruned from the database.
+    #[error("Pruned data: {0}")]
+    PrunedData(String),
+
+    /// The method cannot be served from the database.
+    #[error("Unsupported method: {0}")]
+    UnsupportedMethod(&'static str),
+
     #[error("Chain Id unavailable")]
     ChainIdUnavailable,
+
+    /// The middleware was created from a provider alone, without its database.
+    #[error("No database handle for {0}")]
+    NoDatabase(&'static str),
+
+    /// The local tip is older than the stale tip policy allows.
+    #[error("Stale tip: latest block is {age}s old and {blocks_behind} blocks behind")]
+    StaleTip { age: u64, blocks_behind: u64 },
+
+    /// A call ran past the timeout of the call limits.
+    #[error("Call timed out after {0:?}")]
+    CallTimeout(std::time::Duration),
+
+    /// A method served from the database ran past its time budget.
+    #[error("{method} timed out after {timeout:?}")]
+    Timeout { method: &'static str, timeout: std::time::Duration },
+
+    /// A call returned more than the result size limit.
+    #[error("Call result of {size} bytes exceeds the limit of {limit} bytes")]
+    ResultTooLarge { size: usize, limit: usize },
+
+    /// A trace was larger than the output size limit of the trace limits.
+    #[error("Trace of {size} bytes exceeds the limit of {limit} bytes")]
+    TraceTooLarge { size: usize, limit: usize },
+
+    /// A `get_logs` query spans more blocks than the log limits allow.
+    #[error(
+        "Log query of blocks {from} to {to} exceeds the limit of {limit} blocks, \
+         query blocks {from} to {suggested_to} first"
+    )]
+    LogRangeTooLarge { from: u64, to: u64, limit: u64, suggested_to: u64 },
+
+    /// A `get_logs` query matched more logs than the log limits allow.
+    #[error("Log query matched more than {0} logs")]
+    TooManyLogs(usize),
+
+    /// EIP-1559 fees were requested before London.
+    #[error("EIP-1559 not activated")]
+    Eip1559NotActivated,
+
+    /// A function signature could not be parsed, or a call encoded or decoded with it.
+    #[error("ABI error: {0}")]
+    AbiError(String),
+
+    /// A log could not be decoded as the requested event.
+    #[error("Event decoding error: {0}")]
+    EventDecodingError(#[from] ethers::abi::Error),
+
+    /// An export job could not hand a chunk to its sink or record its checkpoint.
+    #[error("Export error: {0}")]
+    ExportError(String),
+
+    /// A subscriber fell behind its stream.
+    #[error(transparent)]
+    Lagged(#[from] fanout::Lagged),
+
+    /// The canonical state notifications stopped before the awaited event.
+    #[error("Canonical state notifications closed")]
+    NotificationsClosed,
+
+    /// The `safe` or `finalized` tag was requested before the block it stands for is known.
+    #[error("Unknown {0} block")]
+    UnknownForkchoice(&'static str),
+
+    /// The network handle of the node failed to answer.
+    #[error(transparent)]
+    NetworkError(#[from] reth_network_api::NetworkError),
+
+    /// The datadir failed validation.
+    #[error(transparent)]
+    DatadirError(#[from] datadir::DatadirError),
+
+    /// The tip moved past the block a chunked proof was pinned to before all of its chunks were
+    /// proven.
+    #[error("Tip moved past block {0:?} while proving its storage")]
+    ProofTipMoved(H256),
+
+    /// The chain spec passed does not match the genesis block of the database.
+    #[error("Chain spec with genesis {expected:?} does not match the database genesis {found:?}")]
+    ChainMismatch { expected: H256, found: H256 },
+}
+
+impl<M: Middleware> From<RethMiddlewareError<M>> for ProviderError {
+    fn from(err: RethMiddlewareError<M>) -> Self {
+        match err {
+            RethMiddlewareError::UnsupportedMethod(_) => ProviderError::UnsupportedRPC,
+            err => ProviderError::CustomError(err.to_string()),
+        }
+    }
+}
+
+impl<M: Middleware> From<type_conversions::ConversionError> for RethMiddlewareError<M> {
+    fn from(err: type_conversions::ConversionError) -> Self {
+        RethMiddlewareError::ConversionError(err.to_string())
+    }
 }
 
 impl<M: Middleware> MiddlewareError for RethMiddlewareError<M> {
@@ -94,13 +380,127 @@ impl<M> RethMiddleware<M>
 where
     M: Middleware,
 {
-    pub fn new<P: AsRef<Path>>(inner: M, db_path: P, handle: Handle) -> Result<Self> {
-        let (reth_api, reth_filter, reth_trace, reth_debug) =
-            Self::try_new(db_path.as_ref(), handle)?;
-        Ok(Self { inner, reth_api, reth_filter, reth_trace, reth_debug })
+    /// Opens the mainnet database at `db_path`, see [Self::builder] for the other options.
+    pub fn new<P: AsRef<Path>>(
+        inner: M,
+        db_path: P,
+        handle: Handle,
+    ) -> Result<Self, RethMiddlewareError<M>> {
+        Self::builder(inner, db_path).handle(handle).build()
+    }
+
+    /// Creates the middleware over the database of a node running `chain`, e.g. an OP-stack chain.
+    pub fn new_with_chain<P: AsRef<Path>>(
+        inner: M,
+        db_path: P,
+        chain: Arc<ChainSpec>,
+        handle: Handle,
+    ) -> Result<Self, RethMiddlewareError<M>> {
+        Self::builder(inner, db_path).chain(chain).handle(handle).build()
+    }
+
+    /// Creates the middleware on top of a provider that is already open, sharing its database
+    /// handle and canonical state notifications instead of opening the database again.
+    ///
+    /// Pruned segments are not detected, see [Self::with_prune_limits].
+    pub fn from_provider(
+        inner: M,
+        provider: RethClient,
+        chain: Arc<ChainSpec>,
+        task_executor: TaskExecutor,
+    ) -> Self {
+        let pool = Self::init_pool(provider.clone(), chain, task_executor.clone());
+        Self::from_node(
+            inner,
+            NodeComponents {
+                provider,
+                pool,
+                task_executor,
+                prune_limits: PruneLimits::default(),
+                tasks: BackgroundTasks::default(),
+                db: None,
+                network: None,
+                canon_state_sender: None,
+            },
+        )
+    }
+
+    /// Creates the middleware from the components of a running node.
+    pub fn from_node(inner: M, components: NodeComponents) -> Self {
+        Self::from_node_with_config(inner, components, &ApiConfig::default())
+    }
+
+    /// Like [Self::from_node], with the handlers sized by `config`.
+    pub fn from_node_with_config(inner: M, components: NodeComponents, config: &ApiConfig) -> Self {
+        let apis = Self::init_apis_with_config(&components, config);
+        let NodeComponents {
+            provider,
+            pool,
+            task_executor,
+            prune_limits,
+            tasks,
+            db,
+            network,
+            canon_state_sender,
+        } = components;
+        Self {
+            inner,
+            reth_provider: provider,
+            reth_pool: pool,
+            task_executor,
+            reth_api: apis.eth,
+            reth_filter: apis.filter,
+            #[cfg(feature = "trace")]
+            reth_trace: apis.trace,
+            #[cfg(feature = "debug")]
+            reth_debug: apis.debug,
+            notifications: None,
+            canon_state_sender,
+            network,
+            fallback: FallbackConfig::default(),
+            differential: Differential::default(),
+            prune_limits,
+            stale_tip: StaleTipPolicy::default(),
+            pending: PendingMode::default(),
+            call_limits: CallLimits::default(),
+            log_limits: LogLimits::default(),
+            proof_chunk_size: None,
+            max_proof_keys: None,
+            trace_limits: TraceLimits::default(),
+            method_timeouts: MethodTimeouts::default(),
+            heavy_requests: ConcurrencyLimit::default(),
+            spec_override: SpecOverride::default(),
+            precompile_overrides: PrecompileOverrides::default(),
+            native_multicall: false,
+            #[cfg(feature = "debug")]
+            native_tracers: NativeTracers::default(),
+            fee_estimator: FeeEstimator::default(),
+            gas_price_mode: GasPriceMode::default(),
+            nonces: NonceManager::default(),
+            accounts: Accounts::default(),
+            namespaces: Namespaces::default(),
+            rate_limiter: RateLimiter::default(),
+            server_auth: ServerAuth::default(),
+            transport: Arc::new(OnceCell::new()),
+            timestamp_pivots: TimestampPivots::default(),
+            senders: SenderCache::default(),
+            rederived_receipts: None,
+            response_cache: None,
+            fanout: FanOut::default(),
+            tasks,
+            db,
+        }
     }
 
     pub fn reth_api(&self) -> &RethApi {
         &self.reth_api
     }
+
+    pub fn reth_provider(&self) -> &RethClient {
+        &self.reth_provider
+    }
+
+    pub fn reth_pool(&self) -> &RethTxPool {
+        &self.reth_pool
+    }
 }
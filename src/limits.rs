@@ -0,0 +1,653 @@
+#[cfg(feature = "debug")]
+use crate::execution::eip3155::Eip3155Trace;
+use crate::{RethMiddleware, RethMiddlewareError};
+use ethers::providers::Middleware;
+#[cfg(feature = "trace")]
+use ethers::types::{BlockTrace as EthersBlockTrace, Trace as EthersTrace};
+#[cfg(feature = "debug")]
+use ethers::types::{
+    CallFrame, GethDebugTracingOptions as EthersDebugTracingOptions, GethTrace as EthersGethTrace,
+    GethTraceFrame,
+};
+use serde::Deserialize;
+#[cfg(any(feature = "trace", feature = "debug"))]
+use serde::Serialize;
+
+// Reth
+use reth_primitives::U256;
+use reth_revm::{
+    interpreter::{InstructionResult, Interpreter},
+    primitives::{EVMError, EVMResult, ExecutionResult},
+    Database, DatabaseCommit, EVMData, Inspector, EVM,
+};
+use reth_rpc_types::CallRequest;
+
+// Std
+#[cfg(any(feature = "trace", feature = "debug"))]
+use std::io;
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    runtime::{Handle, RuntimeFlavor},
+    sync::{Semaphore, SemaphorePermit},
+    task::block_in_place,
+};
+
+/// Protections for the requests executing calls, in the spirit of geth's `--rpc.gascap` and
+/// `--rpc.evmtimeout`, so a misbehaving simulation cannot wedge the embedding process. Disabled
+/// unless a limit is set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CallLimits {
+    /// Most gas a single call or gas estimation may use, calls asking for more are capped.
+    pub gas_cap: Option<u64>,
+    /// Longest a call, gas estimation or simulation may run. Calls executed locally halt once
+    /// the deadline passes.
+    #[serde(with = "timeout_secs")]
+    pub timeout: Option<Duration>,
+    /// Largest output, in bytes, a call may return.
+    pub max_result_size: Option<usize>,
+}
+
+/// Bounds on the traces served from the database, so tracing a pathological transaction does not
+/// exhaust the memory of the embedding process. Disabled unless set.
+///
+/// The depth and size limits are enforced as the native tracers, `trace_filter` and the EIP-3155
+/// traces accumulate. The traces built by reth's `trace` and `debug` handlers are only cut and
+/// measured once complete, so they bound the response, not the memory used to build it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TraceLimits {
+    /// Deepest call depth kept in traces, the top-level call being at depth 1. Struct logs,
+    /// call frames and parity traces below it are dropped.
+    pub max_depth: Option<usize>,
+    /// Never capture the memory in struct logs, whatever the tracing options ask for.
+    pub disable_memory: bool,
+    /// Never capture the stack in struct logs, whatever the tracing options ask for.
+    pub disable_stack: bool,
+    /// Largest trace, in bytes of JSON, a request may return.
+    pub max_output_size: Option<usize>,
+    /// Most blocks a single `trace_filter` may span, [MAX_TRACE_FILTER_BLOCKS] if unset.
+    ///
+    /// [MAX_TRACE_FILTER_BLOCKS]: crate::trace_filter::MAX_TRACE_FILTER_BLOCKS
+    pub max_filter_blocks: Option<u64>,
+}
+
+/// Bounds on `get_logs` queries, like the block range and result limits of hosted providers.
+/// Disabled unless a limit is set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogLimits {
+    /// Most blocks a query may span.
+    pub max_block_range: Option<u64>,
+    /// Most logs a query may return, across all of its chunks.
+    pub max_results: Option<usize>,
+    /// Serve queries spanning more than `max_block_range` blocks in chunks of that many blocks,
+    /// merging their logs, instead of failing them.
+    pub chunk: bool,
+}
+
+/// Time budgets of the [Middleware] methods served from the database, keyed by method name, e.g.
+/// a fraction of a second for state reads and more for `trace_block`. A method past its budget
+/// fails with [RethMiddlewareError::Timeout] instead of hanging on a pathological query.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MethodTimeouts {
+    /// Budget of the methods without their own, unbounded if unset.
+    #[serde(with = "timeout_secs")]
+    pub default: Option<Duration>,
+    #[serde(deserialize_with = "timeout_secs::map")]
+    pub methods: HashMap<String, Duration>,
+}
+
+impl MethodTimeouts {
+    /// Applies `timeout` to every method without its own.
+    pub fn new(timeout: Duration) -> Self {
+        Self { default: Some(timeout), methods: HashMap::new() }
+    }
+
+    /// Sets the budget of a single method, e.g. `"trace_block"`.
+    pub fn with_method(mut self, method: impl Into<String>, timeout: Duration) -> Self {
+        self.methods.insert(method.into(), timeout);
+        self
+    }
+
+    /// Returns the budget of `method`.
+    pub fn timeout(&self, method: &str) -> Option<Duration> {
+        self.methods.get(method).copied().or(self.default)
+    }
+}
+
+/// Bound on the heavy requests served from the database at once: traces, log scans and proofs.
+/// Requests past the bound wait for a slot, so load spikes queue instead of piling up on MDBX.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ConcurrencyLimit(Option<Arc<Semaphore>>);
+
+impl ConcurrencyLimit {
+    pub(crate) fn new(max: Option<usize>) -> Self {
+        Self(max.map(|max| Arc::new(Semaphore::new(max.max(1)))))
+    }
+
+    /// Waits for a free slot, held until the permit drops. `None` if unbounded.
+    pub(crate) async fn acquire(&self) -> Option<SemaphorePermit<'_>> {
+        match &self.0 {
+            Some(semaphore) => Some(semaphore.acquire().await.expect("semaphore is never closed")),
+            None => None,
+        }
+    }
+}
+
+tokio::task_local! {
+    /// The method served and its budget, see [RethMiddleware::with_method_timeout].
+    static METHOD_BUDGET: (&'static str, Duration, Instant);
+    /// Set while the task holds a heavy request slot, see [RethMiddleware::with_heavy_permit].
+    static HEAVY_PERMIT: ();
+}
+
+thread_local! {
+    /// Deadline of the EVM work running on this thread, see [RethMiddleware::run_call].
+    static CALL_DEADLINE: Cell<Option<Instant>> = Cell::new(None);
+}
+
+/// Instructions executed between two reads of the clock.
+const DEADLINE_CHECK_INTERVAL: u32 = 1024;
+
+/// Halts the EVM once the deadline of the work running on this thread passed, so a pathological
+/// call stops at its timeout instead of running to completion.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CallDeadline {
+    deadline: Instant,
+    steps: u32,
+    passed: bool,
+}
+
+impl CallDeadline {
+    /// Returns the deadline of the work running on this thread, if it has one.
+    pub(crate) fn current() -> Option<Self> {
+        CALL_DEADLINE.with(Cell::get).map(|deadline| Self { deadline, steps: 0, passed: false })
+    }
+
+    /// Returns true once the deadline passed, reading the clock every
+    /// [DEADLINE_CHECK_INTERVAL] instructions.
+    pub(crate) fn passed(&mut self) -> bool {
+        if !self.passed {
+            self.steps = self.steps.wrapping_add(1);
+            if self.steps % DEADLINE_CHECK_INTERVAL == 0 {
+                self.passed = Instant::now() >= self.deadline;
+            }
+        }
+        self.passed
+    }
+
+    /// Executes the transaction of `evm`, halting at the deadline of the running call.
+    pub(crate) fn transact<DB: Database>(evm: &mut EVM<DB>) -> EVMResult<DB::Error> {
+        match Self::current() {
+            Some(deadline) => evm.inspect(deadline),
+            None => evm.transact(),
+        }
+    }
+
+    /// Executes the transaction of `evm` and commits its changes, halting at the deadline of the
+    /// running call.
+    pub(crate) fn transact_commit<DB: Database + DatabaseCommit>(
+        evm: &mut EVM<DB>,
+    ) -> Result<ExecutionResult, EVMError<DB::Error>> {
+        match Self::current() {
+            Some(deadline) => evm.inspect_commit(deadline),
+            None => evm.transact_commit(),
+        }
+    }
+}
+
+impl<DB: Database> Inspector<DB> for CallDeadline {
+    fn step(
+        &mut self,
+        _interp: &mut Interpreter,
+        _data: &mut EVMData<'_, DB>,
+    ) -> InstructionResult {
+        // every frame halts, up to the top-level call
+        if self.passed() {
+            InstructionResult::OutOfGas
+        } else {
+            InstructionResult::Continue
+        }
+    }
+}
+
+/// Returns true if the deadline of the work running on this thread passed, for the loops
+/// executing one transaction after the other.
+pub(crate) fn call_deadline_passed() -> bool {
+    CALL_DEADLINE.with(Cell::get).map_or(false, |deadline| Instant::now() >= deadline)
+}
+
+/// Sets the deadline of the work running on this thread, restoring the previous one on drop.
+struct DeadlineGuard(Option<Instant>);
+
+impl DeadlineGuard {
+    fn set(deadline: Option<Instant>) -> Self {
+        Self(CALL_DEADLINE.with(|cell| cell.replace(deadline)))
+    }
+}
+
+impl Drop for DeadlineGuard {
+    fn drop(&mut self) {
+        CALL_DEADLINE.with(|cell| cell.set(self.0));
+    }
+}
+
+/// Runs blocking work in place, handing the worker over to the other tasks of a multi-threaded
+/// runtime meanwhile.
+fn run_blocking<T>(work: impl FnOnce() -> T) -> T {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => block_in_place(work),
+        _ => work(),
+    }
+}
+
+/// Returns true if the [Middleware] method is bounded by the concurrency limit.
+pub(crate) fn is_heavy(method: &str) -> bool {
+    method.starts_with("trace_") ||
+        method.starts_with("debug_trace") ||
+        matches!(method, "get_logs" | "get_proof")
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Sets the gas cap, timeout and result size limit applied to calls.
+    pub fn with_call_limits(mut self, limits: CallLimits) -> Self {
+        self.call_limits = limits;
+        self
+    }
+
+    /// Returns the limits applied to calls.
+    pub fn call_limits(&self) -> CallLimits {
+        self.call_limits
+    }
+
+    /// Returns the gas available to a call executed in a block with the given gas limit.
+    pub(crate) fn gas_cap(&self, block_gas_limit: u64) -> u64 {
+        self.call_limits.gas_cap.map_or(block_gas_limit, |cap| cap.min(block_gas_limit))
+    }
+
+    /// Caps the gas of a call request handed to the reth handlers.
+    pub(crate) fn cap_call_request(&self, request: &mut CallRequest) {
+        if let Some(cap) = self.call_limits.gas_cap {
+            let cap = U256::from(cap);
+            request.gas = Some(request.gas.map_or(cap, |gas| gas.min(cap)));
+        }
+    }
+
+    /// Runs the EVM work of a request executing calls under the call timeout, and the budget of
+    /// the method it serves.
+    ///
+    /// The work runs on a thread handed over to blocking work, see [block_in_place], so it does
+    /// not stall the other tasks of the runtime, and the EVM halts once the deadline passes, so
+    /// the request fails at its timeout rather than once the call completes.
+    pub(crate) async fn run_call<T>(
+        &self,
+        work: impl FnOnce() -> Result<T, RethMiddlewareError<M>>,
+    ) -> Result<T, RethMiddlewareError<M>> {
+        let call = self.call_limits.timeout.map(|timeout| (timeout, Instant::now() + timeout));
+        let method = METHOD_BUDGET.try_with(|budget| *budget).ok();
+        let deadline = call
+            .map(|(_, deadline)| deadline)
+            .into_iter()
+            .chain(method.map(|(_, _, deadline)| deadline))
+            .min();
+
+        let result = run_blocking(|| {
+            let _deadline = DeadlineGuard::set(deadline);
+            work()
+        });
+
+        let now = Instant::now();
+        match (call, method) {
+            (Some((timeout, deadline)), _) if now >= deadline => {
+                Err(RethMiddlewareError::CallTimeout(timeout))
+            }
+            (_, Some((method, timeout, deadline))) if now >= deadline => {
+                Err(RethMiddlewareError::Timeout { method, timeout })
+            }
+            _ => result,
+        }
+    }
+
+    /// Runs a request executing calls under the timeout, for work awaiting the reth handlers,
+    /// which execute calls on their own blocking tasks.
+    pub(crate) async fn with_call_timeout<T>(
+        &self,
+        call: impl Future<Output = Result<T, RethMiddlewareError<M>>>,
+    ) -> Result<T, RethMiddlewareError<M>> {
+        match self.call_limits.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, call)
+                .await
+                .map_err(|_| RethMiddlewareError::CallTimeout(timeout))?,
+            None => call.await,
+        }
+    }
+
+    /// Fails if a call returned more than the result size limit.
+    pub(crate) fn ensure_result_size(&self, size: usize) -> Result<(), RethMiddlewareError<M>> {
+        match self.call_limits.max_result_size {
+            Some(limit) if size > limit => Err(RethMiddlewareError::ResultTooLarge { size, limit }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sets the block range and result limits applied to `get_logs`.
+    pub fn with_log_limits(mut self, limits: LogLimits) -> Self {
+        self.log_limits = limits;
+        self
+    }
+
+    /// Returns the limits applied to `get_logs`.
+    pub fn log_limits(&self) -> LogLimits {
+        self.log_limits
+    }
+
+    /// Sets the time budgets of the methods served from the database.
+    pub fn with_method_timeouts(mut self, timeouts: MethodTimeouts) -> Self {
+        self.method_timeouts = timeouts;
+        self
+    }
+
+    /// Returns the time budgets of the methods served from the database.
+    pub fn method_timeouts(&self) -> &MethodTimeouts {
+        &self.method_timeouts
+    }
+
+    /// Bounds the number of traces, log scans, proofs, multi-call simulations, witnesses and
+    /// export chunks served from the database at once, unbounded if `None`.
+    pub fn with_max_heavy_requests(mut self, max: Option<usize>) -> Self {
+        self.heavy_requests = ConcurrencyLimit::new(max);
+        self
+    }
+
+    /// Returns the number of heavy requests that can start without waiting, `None` if unbounded.
+    pub fn available_heavy_requests(&self) -> Option<usize> {
+        self.heavy_requests.0.as_ref().map(|semaphore| semaphore.available_permits())
+    }
+
+    /// Serves a method from the database within its time budget, waiting for a slot first if it
+    /// is heavy. The wait counts towards the budget.
+    pub(crate) async fn with_method_timeout<T>(
+        &self,
+        method: &'static str,
+        local: impl Future<Output = Result<T, RethMiddlewareError<M>>>,
+    ) -> Result<T, RethMiddlewareError<M>> {
+        let local = async {
+            if is_heavy(method) {
+                self.with_heavy_permit(local).await
+            } else {
+                local.await
+            }
+        };
+        match self.method_timeouts.timeout(method) {
+            Some(timeout) => {
+                // read by the EVM work of the method, which cannot be interrupted at an await
+                let budget = (method, timeout, Instant::now() + timeout);
+                tokio::time::timeout(timeout, METHOD_BUDGET.scope(budget, local))
+                    .await
+                    .map_err(|_| RethMiddlewareError::Timeout { method, timeout })?
+            }
+            None => local.await,
+        }
+    }
+
+    /// Runs heavy work once a slot of the concurrency limit is free, holding it until the work
+    /// completes. Work nested in a heavy request runs in the slot of the request rather than
+    /// waiting for another one, which could never free up.
+    pub(crate) async fn with_heavy_permit<T>(&self, work: impl Future<Output = T>) -> T {
+        match &self.heavy_requests.0 {
+            Some(semaphore) if HEAVY_PERMIT.try_with(|_| ()).is_err() => {
+                let _permit = semaphore.acquire().await.expect("semaphore is never closed");
+                HEAVY_PERMIT.scope((), work).await
+            }
+            _ => work.await,
+        }
+    }
+
+    /// Sets the depth, capture and size limits applied to traces.
+    pub fn with_trace_limits(mut self, limits: TraceLimits) -> Self {
+        self.trace_limits = limits;
+        self
+    }
+
+    /// Returns the limits applied to traces.
+    pub fn trace_limits(&self) -> TraceLimits {
+        self.trace_limits
+    }
+
+    /// Turns off the struct log captures the trace limits disable.
+    #[cfg(feature = "debug")]
+    pub(crate) fn limit_tracing_options(
+        &self,
+        mut options: EthersDebugTracingOptions,
+    ) -> EthersDebugTracingOptions {
+        if self.trace_limits.disable_memory {
+            options.enable_memory = Some(false);
+        }
+        if self.trace_limits.disable_stack {
+            options.disable_stack = Some(true);
+        }
+        options
+    }
+
+    /// Drops the parts of a trace deeper than the depth limit, then fails if what is left is
+    /// larger than the output size limit.
+    #[cfg(any(feature = "trace", feature = "debug"))]
+    pub(crate) fn limit_trace<T: LimitedTrace>(
+        &self,
+        mut trace: T,
+    ) -> Result<T, RethMiddlewareError<M>> {
+        self.truncate_trace(&mut trace);
+        self.charge_trace(&mut 0, &trace)?;
+        Ok(trace)
+    }
+
+    /// Drops the parts of a trace deeper than the depth limit.
+    #[cfg(any(feature = "trace", feature = "debug"))]
+    pub(crate) fn truncate_trace<T: LimitedTrace>(&self, trace: &mut T) {
+        if let Some(max_depth) = self.trace_limits.max_depth {
+            trace.truncate(max_depth);
+        }
+    }
+
+    /// Adds the JSON size of a part of a response to `size`, the bytes of the parts already kept,
+    /// and fails as soon as the total is larger than the output size limit, so responses built
+    /// block by block or transaction by transaction stop growing at the limit.
+    #[cfg(any(feature = "trace", feature = "debug"))]
+    pub(crate) fn charge_trace<T: Serialize>(
+        &self,
+        size: &mut usize,
+        trace: &T,
+    ) -> Result<(), RethMiddlewareError<M>> {
+        if let Some(limit) = self.trace_limits.max_output_size {
+            let mut counter = ByteCounter(0);
+            // counting cannot fail, and every trace type serializes
+            let _ = serde_json::to_writer(&mut counter, trace);
+            *size = size.saturating_add(counter.0);
+            if *size > limit {
+                return Err(RethMiddlewareError::TraceTooLarge { size: *size, limit })
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Traces that can be cut at a call depth.
+#[cfg(any(feature = "trace", feature = "debug"))]
+pub(crate) trait LimitedTrace: Serialize {
+    /// Drops everything deeper than `max_depth`.
+    fn truncate(&mut self, max_depth: usize);
+}
+
+#[cfg(feature = "debug")]
+impl LimitedTrace for EthersGethTrace {
+    fn truncate(&mut self, max_depth: usize) {
+        match self {
+            EthersGethTrace::Known(GethTraceFrame::Default(frame)) => {
+                frame.struct_logs.retain(|log| log.depth as usize <= max_depth)
+            }
+            EthersGethTrace::Known(GethTraceFrame::CallTracer(frame)) => {
+                truncate_call_frame(frame, 1, max_depth)
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(feature = "debug")]
+impl LimitedTrace for Vec<EthersGethTrace> {
+    fn truncate(&mut self, max_depth: usize) {
+        self.iter_mut().for_each(|trace| trace.truncate(max_depth))
+    }
+}
+
+#[cfg(feature = "debug")]
+impl LimitedTrace for Eip3155Trace {
+    fn truncate(&mut self, max_depth: usize) {
+        self.steps.retain(|step| step.depth as usize <= max_depth)
+    }
+}
+
+#[cfg(feature = "trace")]
+impl LimitedTrace for Vec<EthersTrace> {
+    fn truncate(&mut self, max_depth: usize) {
+        self.retain(|trace| trace.trace_address.len() < max_depth);
+        for trace in self.iter_mut() {
+            if trace.trace_address.len() + 1 == max_depth {
+                trace.subtraces = 0;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "trace")]
+impl LimitedTrace for EthersBlockTrace {
+    fn truncate(&mut self, max_depth: usize) {
+        if let Some(traces) = &mut self.trace {
+            traces.retain(|trace| trace.trace_address.len() < max_depth);
+            for trace in traces.iter_mut() {
+                if trace.trace_address.len() + 1 == max_depth {
+                    trace.subtraces = 0;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "trace")]
+impl LimitedTrace for Vec<EthersBlockTrace> {
+    fn truncate(&mut self, max_depth: usize) {
+        self.iter_mut().for_each(|trace| trace.truncate(max_depth))
+    }
+}
+
+/// Drops the calls of a frame at `depth` made below `max_depth`.
+#[cfg(feature = "debug")]
+fn truncate_call_frame(frame: &mut CallFrame, depth: usize, max_depth: usize) {
+    if depth >= max_depth {
+        frame.calls = None;
+    } else if let Some(calls) = &mut frame.calls {
+        calls.iter_mut().for_each(|call| truncate_call_frame(call, depth + 1, max_depth))
+    }
+}
+
+/// Counts the bytes written to it, to size a trace without buffering its JSON.
+#[cfg(any(feature = "trace", feature = "debug"))]
+struct ByteCounter(usize);
+
+#[cfg(any(feature = "trace", feature = "debug"))]
+impl io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads timeouts given in seconds.
+mod timeout_secs {
+    use serde::{de::Error, Deserialize, Deserializer};
+    use std::{collections::HashMap, time::Duration};
+
+    /// Fails on negative, infinite and NaN timeouts rather than panicking.
+    fn duration<E: Error>(secs: f64) -> Result<Duration, E> {
+        Duration::try_from_secs_f64(secs)
+            .map_err(|err| E::custom(format!("invalid timeout of {secs} seconds: {err}")))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        Option::<f64>::deserialize(deserializer)?.map(duration).transpose()
+    }
+
+    pub(super) fn map<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<String, Duration>, D::Error> {
+        let secs = HashMap::<String, f64>::deserialize(deserializer)?;
+        secs.into_iter().map(|(method, secs)| Ok((method, duration(secs)?))).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeouts_are_read_in_seconds() {
+        let limits: CallLimits = serde_json::from_str(r#"{ "timeout": 1.5 }"#).unwrap();
+        assert_eq!(limits.timeout, Some(Duration::from_millis(1500)));
+
+        let timeouts: MethodTimeouts =
+            serde_json::from_str(r#"{ "default": 0.25, "methods": { "trace_block": 10 } }"#)
+                .unwrap();
+        assert_eq!(timeouts.timeout("get_balance"), Some(Duration::from_millis(250)));
+        assert_eq!(timeouts.timeout("trace_block"), Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn invalid_timeouts_are_rejected() {
+        assert!(serde_json::from_str::<CallLimits>(r#"{ "timeout": -1 }"#).is_err());
+        assert!(serde_json::from_str::<CallLimits>(r#"{ "timeout": 1e300 }"#).is_err());
+        let methods = r#"{ "methods": { "trace_block": -0.5 } }"#;
+        assert!(serde_json::from_str::<MethodTimeouts>(methods).is_err());
+    }
+
+    #[test]
+    fn deadline_halts_once_passed() {
+        let _deadline = DeadlineGuard::set(Some(Instant::now()));
+        assert!(call_deadline_passed());
+
+        let mut deadline = CallDeadline::current().unwrap();
+        // the clock is only read every interval
+        for _ in 1..DEADLINE_CHECK_INTERVAL {
+            assert!(!deadline.passed());
+        }
+        assert!(deadline.passed());
+        assert!(deadline.passed());
+    }
+
+    #[test]
+    fn deadline_is_restored() {
+        assert!(CallDeadline::current().is_none());
+        {
+            let _deadline = DeadlineGuard::set(Some(Instant::now() + Duration::from_secs(60)));
+            assert!(CallDeadline::current().is_some());
+            assert!(!call_deadline_passed());
+        }
+        assert!(CallDeadline::current().is_none());
+    }
+}
@@ -0,0 +1,344 @@
+use crate::{
+    fanout::{LagPolicy, Lagged},
+    instrument::{convert, db_read},
+    reorg::DEFAULT_REORG_WINDOW,
+    type_conversions::{ToEthers, ToReth},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{BlockId, BlockNumber, Filter, FilterBlockOption, Log, Topic, ValueOrArray, H256},
+};
+use futures::Stream;
+
+// Reth
+use reth_primitives::SealedBlockWithSenders;
+use reth_provider::{
+    BlockIdReader, BlockNumReader, CanonStateNotification, CanonStateNotifications, Chain,
+};
+use reth_rpc_api::EthFilterApiServer;
+
+// Std
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    ops::RangeInclusive,
+};
+use tokio::sync::broadcast::error::RecvError;
+
+/// Returns true if a log with `log_topics` matches the topic filter the way geth matches it.
+///
+/// A missing topic, `null`, an empty array and an array containing `null` are wildcards, an array
+/// matches any of its values, and every position up to the last one set must be present in the
+/// log, wildcards included.
+pub fn matches_topics(topics: &[Option<Topic>], log_topics: &[H256]) -> bool {
+    let len = topics.iter().rposition(Option::is_some).map_or(0, |last| last + 1);
+    if len > log_topics.len() {
+        return false
+    }
+
+    topics[..len].iter().zip(log_topics).all(|(topic, log_topic)| match topic {
+        None | Some(ValueOrArray::Value(None)) => true,
+        Some(ValueOrArray::Value(Some(topic))) => topic == log_topic,
+        Some(ValueOrArray::Array(topics)) => {
+            topics.is_empty() || topics.iter().any(|topic| topic.map_or(true, |t| t == *log_topic))
+        }
+    })
+}
+
+/// Returns true if the log matches the address and topics of the filter. The block range is not
+/// checked, as with geth's log subscriptions.
+pub fn matches_log(filter: &Filter, log: &Log) -> bool {
+    let address = match &filter.address {
+        None => true,
+        Some(ValueOrArray::Value(address)) => *address == log.address,
+        Some(ValueOrArray::Array(addresses)) => {
+            addresses.is_empty() || addresses.contains(&log.address)
+        }
+    };
+    address && matches_topics(&filter.topics, &log.topics)
+}
+
+/// Splits a block range into consecutive ranges of at most `max_blocks` blocks, in order.
+pub fn split_block_range(range: RangeInclusive<u64>, max_blocks: u64) -> Vec<RangeInclusive<u64>> {
+    let (mut from, to) = range.into_inner();
+    let max_blocks = max_blocks.max(1);
+    let mut ranges = vec![];
+    while from <= to {
+        let end = from.saturating_add(max_blocks - 1).min(to);
+        ranges.push(from..=end);
+        if end == u64::MAX {
+            break
+        }
+        from = end + 1;
+    }
+    ranges
+}
+
+/// The logs delivered to a subscriber, by block, so a reorg only retracts the logs the subscriber
+/// actually received.
+#[derive(Debug, Clone)]
+pub struct DeliveredLogs {
+    blocks: BTreeMap<u64, (H256, Vec<Log>)>,
+    max_blocks: usize,
+}
+
+impl Default for DeliveredLogs {
+    fn default() -> Self {
+        Self::new(DEFAULT_REORG_WINDOW)
+    }
+}
+
+impl DeliveredLogs {
+    /// Remembers the logs of up to `max_blocks` blocks, older ones can no longer be retracted.
+    pub fn new(max_blocks: usize) -> Self {
+        Self { blocks: BTreeMap::new(), max_blocks: max_blocks.max(1) }
+    }
+
+    /// Returns true if the block was already delivered.
+    pub fn contains(&self, number: u64, hash: H256) -> bool {
+        self.blocks.get(&number).map_or(false, |(delivered, _)| *delivered == hash)
+    }
+
+    /// Records the logs delivered for a canonical block.
+    pub fn deliver(&mut self, number: u64, hash: H256, logs: &[Log]) {
+        self.blocks.insert(number, (hash, logs.to_vec()));
+        while self.blocks.len() > self.max_blocks {
+            self.blocks.pop_first();
+        }
+    }
+
+    /// Forgets the blocks `is_reverted` reports as no longer canonical, returning the logs that
+    /// were delivered for them flagged as removed, oldest first.
+    pub fn retract(&mut self, mut is_reverted: impl FnMut(u64, H256) -> bool) -> Vec<Log> {
+        let reverted: Vec<u64> = self
+            .blocks
+            .iter()
+            .filter(|(number, (hash, _))| is_reverted(**number, *hash))
+            .map(|(number, _)| *number)
+            .collect();
+
+        reverted
+            .into_iter()
+            .filter_map(|number| self.blocks.remove(&number))
+            .flat_map(|(_, logs)| logs)
+            .map(|log| Log { removed: Some(true), ..log })
+            .collect()
+    }
+}
+
+/// A subscription to the logs of newly committed blocks, built on canonical state notifications.
+///
+/// When blocks are reverted, the logs that were delivered for them are sent again with `removed`
+/// set, and only those.
+#[derive(Debug)]
+pub struct LogSubscription {
+    filter: Filter,
+    notifications: CanonStateNotifications,
+    delivered: DeliveredLogs,
+    queued: VecDeque<Log>,
+    lag_policy: LagPolicy,
+    ended: bool,
+}
+
+impl LogSubscription {
+    pub fn new(filter: Filter, notifications: CanonStateNotifications) -> Self {
+        Self {
+            filter,
+            notifications,
+            delivered: DeliveredLogs::default(),
+            queued: VecDeque::new(),
+            lag_policy: LagPolicy::default(),
+            ended: false,
+        }
+    }
+
+    /// Sets what the subscription does when it misses notifications, by default reporting them
+    /// and going on.
+    pub fn with_lag_policy(mut self, lag_policy: LagPolicy) -> Self {
+        self.lag_policy = lag_policy;
+        self
+    }
+
+    /// Waits for the next log, or the number of notifications missed, `None` once the
+    /// notification channel closes.
+    pub async fn next(&mut self) -> Option<Result<Log, Lagged>> {
+        loop {
+            if let Some(log) = self.queued.pop_front() {
+                return Some(Ok(log))
+            }
+            if self.ended {
+                return None
+            }
+            match self.notifications.recv().await {
+                Ok(notification) => self.process(&notification),
+                Err(RecvError::Lagged(missed)) => {
+                    self.ended = self.lag_policy == LagPolicy::Error;
+                    return Some(Err(Lagged(missed)))
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Records logs delivered by other means, e.g. read from the database up to the head, so the
+    /// subscription skips their blocks and retracts them if they are reverted.
+    pub fn mark_delivered(&mut self, logs: &[Log]) {
+        let mut blocks: BTreeMap<(u64, H256), Vec<Log>> = BTreeMap::new();
+        for log in logs {
+            if let (Some(number), Some(hash)) = (log.block_number, log.block_hash) {
+                blocks.entry((number.as_u64(), hash)).or_default().push(log.clone());
+            }
+        }
+        for ((number, hash), logs) in blocks {
+            self.delivered.deliver(number, hash, &logs);
+        }
+    }
+
+    /// Turns the subscription into a stream of logs.
+    pub fn into_stream(self) -> impl Stream<Item = Result<Log, Lagged>> {
+        futures::stream::unfold(self, |mut subscription| async move {
+            subscription.next().await.map(|log| (log, subscription))
+        })
+    }
+
+    fn process(&mut self, notification: &CanonStateNotification) {
+        let committed = notification.committed();
+        let reverted: HashSet<H256> = notification
+            .reverted()
+            .map(|chain| chain.blocks().values().map(|block| block.hash().into_ethers()).collect())
+            .unwrap_or_default();
+        let new_hashes: BTreeMap<u64, H256> = committed
+            .iter()
+            .flat_map(|chain| chain.blocks().values())
+            .map(|block| (block.header.number, block.hash().into_ethers()))
+            .collect();
+        let fork = new_hashes.keys().next().copied().unwrap_or(u64::MAX);
+
+        // blocks overwritten by the committed segment are reverted even if not reported
+        let removed = self.delivered.retract(|number, hash| {
+            reverted.contains(&hash) || (number >= fork && new_hashes.get(&number) != Some(&hash))
+        });
+        self.queued.extend(removed);
+
+        let Some(chain) = committed else { return };
+        for block in chain.blocks().values() {
+            let hash = block.hash().into_ethers();
+            if self.delivered.contains(block.header.number, hash) {
+                continue
+            }
+            let logs = block_logs(&chain, block, &self.filter);
+            self.delivered.deliver(block.header.number, hash, &logs);
+            self.queued.extend(logs);
+        }
+    }
+}
+
+/// Collects the logs of a block of the chain matching the filter.
+fn block_logs(chain: &Chain, block: &SealedBlockWithSenders, filter: &Filter) -> Vec<Log> {
+    let Some(receipts) = chain.receipts_by_block_hash(block.hash()) else { return vec![] };
+
+    let mut logs = vec![];
+    let mut log_index = 0u64;
+    for (tx_index, (tx, receipt)) in block.body.iter().zip(receipts).enumerate() {
+        for (tx_log_index, log) in receipt.logs.iter().enumerate() {
+            let log = Log {
+                address: log.address.into_ethers(),
+                topics: log.topics.clone().into_ethers(),
+                data: log.data.clone().into_ethers(),
+                block_hash: Some(block.hash().into_ethers()),
+                block_number: Some(block.header.number.into()),
+                transaction_hash: Some(tx.hash().into_ethers()),
+                transaction_index: Some(tx_index.into()),
+                log_index: Some(log_index.into()),
+                transaction_log_index: Some(tx_log_index.into()),
+                log_type: None,
+                removed: Some(false),
+            };
+            log_index += 1;
+            if matches_log(filter, &log) {
+                logs.push(log);
+            }
+        }
+    }
+    logs
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Serves `get_logs` from the database under the log limits, querying ranges longer than
+    /// the block range limit in chunks if the limits allow it.
+    pub(crate) async fn local_logs(
+        &self,
+        filter: &Filter,
+    ) -> Result<Vec<Log>, RethMiddlewareError<M>> {
+        let limits = self.log_limits();
+        let range = match (&filter.block_option, limits.max_block_range) {
+            (FilterBlockOption::Range { from_block, to_block }, Some(_)) => {
+                let from = self.log_range_bound(from_block.unwrap_or(BlockNumber::Latest))?;
+                let to = self.log_range_bound(to_block.unwrap_or(BlockNumber::Latest))?;
+                Some(from..=to)
+            }
+            _ => None,
+        };
+        if let Some(from_block) = filter.get_from_block() {
+            self.ensure_receipts(from_block.as_u64())?;
+        }
+
+        let chunks = match (range, limits.max_block_range) {
+            (Some(range), Some(limit)) if range.end().saturating_sub(*range.start()) >= limit => {
+                let (from, to) = range.clone().into_inner();
+                if !limits.chunk {
+                    return Err(RethMiddlewareError::LogRangeTooLarge {
+                        from,
+                        to,
+                        limit,
+                        suggested_to: from + limit - 1,
+                    })
+                }
+                split_block_range(range, limit)
+                    .into_iter()
+                    .map(|chunk| filter.clone().from_block(*chunk.start()).to_block(*chunk.end()))
+                    .collect()
+            }
+            _ => vec![filter.clone()],
+        };
+
+        let mut logs = vec![];
+        for chunk in chunks {
+            let reth_logs = self.reth_filter.logs(chunk.into_reth()).await?;
+            let mut chunk_logs: Vec<Log> = convert("logs", || reth_logs.into_ethers());
+            // reth does not count trailing wildcards towards the topics a log must have
+            chunk_logs.retain(|log| matches_topics(&filter.topics, &log.topics));
+            logs.extend(chunk_logs);
+            if let Some(limit) = limits.max_results {
+                if logs.len() > limit {
+                    return Err(RethMiddlewareError::TooManyLogs(limit))
+                }
+            }
+        }
+        Ok(logs)
+    }
+
+    /// Resolves a bound of a log query's range to a block number.
+    pub(crate) fn log_range_bound(
+        &self,
+        block: BlockNumber,
+    ) -> Result<u64, RethMiddlewareError<M>> {
+        let block_id = BlockId::Number(block).into_reth();
+        let number =
+            db_read("block_number_for_id", || self.reth_provider.block_number_for_id(block_id))?;
+        match number {
+            Some(number) => Ok(number),
+            // `pending` logs come from the latest block
+            None => Ok(db_read("best_block_number", || self.reth_provider.best_block_number())?),
+        }
+    }
+
+    /// Subscribes to the logs of newly committed blocks matching the filter's address and topics.
+    pub fn watch_logs(&self, filter: Filter) -> LogSubscription {
+        LogSubscription::new(filter, self.subscribe_to_canonical_state())
+            .with_lag_policy(self.fanout.lag_policy())
+    }
+}
@@ -0,0 +1,71 @@
+//! Prometheus metrics of the embedded RPC layer, enabled by the `metrics` feature.
+//!
+//! Metrics are recorded through the [metrics](::metrics) facade, so any recorder works. Use
+//! [install_prometheus_exporter] to expose them the same way reth does.
+
+use metrics_exporter_prometheus::BuildError;
+use std::{net::SocketAddr, time::Duration};
+
+/// Requests served, labelled by `method`.
+pub const REQUESTS_TOTAL: &str = "ethers_reth_requests_total";
+/// Failed requests, labelled by `method`.
+pub const REQUEST_ERRORS_TOTAL: &str = "ethers_reth_request_errors_total";
+/// Request latency in seconds, labelled by `method`.
+pub const REQUEST_DURATION_SECONDS: &str = "ethers_reth_request_duration_seconds";
+/// Cache hits, labelled by `cache`.
+pub const CACHE_HITS_TOTAL: &str = "ethers_reth_cache_hits_total";
+/// Cache misses, labelled by `cache`.
+pub const CACHE_MISSES_TOTAL: &str = "ethers_reth_cache_misses_total";
+/// Number of entries held by a cache, labelled by `cache`.
+pub const CACHE_ENTRIES: &str = "ethers_reth_cache_entries";
+/// Requests mirrored to the inner provider, labelled by `method` and `outcome`: `match`,
+/// `mismatch` or `remote_error`.
+pub const DIFFERENTIAL_REQUESTS_TOTAL: &str = "ethers_reth_differential_requests_total";
+/// Direct database reads, labelled by `operation`.
+pub const DB_READS_TOTAL: &str = "ethers_reth_db_reads_total";
+/// Direct database read latency in seconds, labelled by `operation`.
+pub const DB_READ_DURATION_SECONDS: &str = "ethers_reth_db_read_duration_seconds";
+
+/// Records a served middleware request.
+pub fn record_request(method: &'static str, elapsed: Duration, success: bool) {
+    ::metrics::increment_counter!(REQUESTS_TOTAL, "method" => method);
+    ::metrics::histogram!(REQUEST_DURATION_SECONDS, elapsed.as_secs_f64(), "method" => method);
+    if !success {
+        ::metrics::increment_counter!(REQUEST_ERRORS_TOTAL, "method" => method);
+    }
+}
+
+/// Records a cache lookup.
+pub fn record_cache_lookup(cache: &'static str, hit: bool) {
+    if hit {
+        ::metrics::increment_counter!(CACHE_HITS_TOTAL, "cache" => cache);
+    } else {
+        ::metrics::increment_counter!(CACHE_MISSES_TOTAL, "cache" => cache);
+    }
+}
+
+/// Records the current number of entries of a cache.
+pub fn record_cache_entries(cache: &'static str, entries: usize) {
+    ::metrics::gauge!(CACHE_ENTRIES, entries as f64, "cache" => cache);
+}
+
+/// Records a direct read of the database that bypassed the RPC handlers.
+pub fn record_db_read(operation: &'static str, elapsed: Duration) {
+    ::metrics::increment_counter!(DB_READS_TOTAL, "operation" => operation);
+    ::metrics::histogram!(DB_READ_DURATION_SECONDS, elapsed.as_secs_f64(), "operation" => operation);
+}
+
+/// Records how a request mirrored to the inner provider compared.
+pub fn record_differential(method: &'static str, outcome: &'static str) {
+    ::metrics::increment_counter!(
+        DIFFERENTIAL_REQUESTS_TOTAL,
+        "method" => method,
+        "outcome" => outcome
+    );
+}
+
+/// Installs a global Prometheus recorder serving the metrics over HTTP at `addr`.
+pub fn install_prometheus_exporter(addr: SocketAddr) -> Result<(), BuildError> {
+    metrics_exporter_prometheus::PrometheusBuilder::new().with_http_listener(addr).install()?;
+    Ok(())
+}
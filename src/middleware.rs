@@ -1,24 +1,37 @@
+#[cfg(feature = "trace")]
+use crate::type_conversions::TryToEthers;
 use crate::{
+    execution::multicall::encode_aggregate3_output,
+    instrument::{convert, instrument, record_block, record_bytes},
+    pending::{is_pending, PendingMode},
     type_conversions::{ToEthers, ToReth},
+    web3::client_version,
     RethMiddleware, RethMiddlewareError,
 };
 use async_trait::async_trait;
 
 // Ether rs Types
+#[cfg(feature = "trace")]
+use ethers::types::{
+    BlockTrace as EthersBlockTrace, Trace as EthersTrace, TraceFilter as EthersTraceFilter,
+    TraceType as EthersTraceType,
+};
+#[cfg(feature = "debug")]
+use ethers::types::{
+    GethDebugTracingCallOptions as EthersDebugTracingCallOptions,
+    GethDebugTracingOptions as EthersDebugTracingOptions, GethTrace as EthersGethTrace,
+};
 use ethers::{
-    providers::{Middleware, MiddlewareError},
+    providers::{admin::PeerInfo, Middleware, MiddlewareError, PendingTransaction, ProviderError},
     types::{
         transaction::{
             eip2718::TypedTransaction,
             eip2930::AccessListWithGasUsed as EthersAccessListWithGasUsed,
         },
         Address as EthersAddress, Block as EthersBlock, BlockId as EthersBlockId,
-        BlockNumber as EthersBlocKNumber, BlockNumber as EthersBlockNumber,
-        BlockTrace as EthersBlockTrace, Bytes as EthersBytes,
+        BlockNumber as EthersBlocKNumber, BlockNumber as EthersBlockNumber, Bytes as EthersBytes,
         EIP1186ProofResponse as EthersEIP1186ProofResponse, FeeHistory as EthersFeeHistory,
-        Filter as EthersFilter, GethDebugTracingCallOptions as EthersDebugTracingCallOptions,
-        GethDebugTracingOptions as EthersDebugTracingOptions, GethTrace as EthersGethTrace,
-        Log as EthersLog, NameOrAddress, Trace as EthersTrace, TraceType as EthersTraceType,
+        Filter as EthersFilter, FilterBlockOption, Log as EthersLog, NameOrAddress,
         Transaction as EthersTransaction, TransactionReceipt as EthersTransactionReceipt,
         TxHash as EthersTxHash, H256 as EthersH256, U256 as EthersU256, U64 as EthersU64,
     },
@@ -27,14 +40,11 @@ use ethers::{
 // Reth Types
 use reth_primitives::BlockId;
 use reth_rpc::eth::revm_utils::EvmOverrides;
-use reth_rpc_api::{EthApiServer, EthFilterApiServer};
-// use reth_rpc_types::trace::geth::TraceResult;
-use reth_rpc_types::{
-    trace::{
-        common::TraceResult,
-        geth::{DefaultFrame, GethTrace},
-    },
-    Filter,
+use reth_rpc_api::EthApiServer;
+#[cfg(feature = "debug")]
+use reth_rpc_types::trace::{
+    common::TraceResult,
+    geth::{DefaultFrame, GethTrace},
 };
 
 impl<M> RethMiddleware<M>
@@ -67,16 +77,151 @@ where
         &self.inner
     }
 
+    async fn client_version(&self) -> Result<String, Self::Error> {
+        instrument("client_version", async { Ok(client_version()) }).await
+    }
+
+    async fn peers(&self) -> Result<Vec<PeerInfo>, Self::Error> {
+        instrument("peers", self.fallback("peers", self.local_peers(), || self.inner.peers())).await
+    }
+
+    fn default_sender(&self) -> Option<EthersAddress> {
+        self.accounts.sender().or_else(|| self.inner.default_sender())
+    }
+
+    async fn get_accounts(&self) -> Result<Vec<EthersAddress>, Self::Error> {
+        instrument("get_accounts", async {
+            if self.accounts.addresses.is_empty() {
+                return self.inner.get_accounts().await.map_err(RethMiddlewareError::from_err)
+            }
+            Ok(self.accounts.addresses.clone())
+        })
+        .await
+    }
+
+    // Transaction related methods
+    async fn fill_transaction(
+        &self,
+        tx: &mut TypedTransaction,
+        block: Option<EthersBlockId>,
+    ) -> Result<(), Self::Error> {
+        instrument("fill_transaction", async {
+            if let Some(default_sender) = self.default_sender() {
+                if tx.from().is_none() {
+                    tx.set_from(default_sender);
+                }
+            }
+
+            if let Some(name @ NameOrAddress::Name(_)) = tx.to().cloned() {
+                let addr = self.get_address(name).await?;
+                tx.set_to(addr);
+            }
+
+            if self.manages_nonces() && tx.nonce().is_none() {
+                if let Some(from) = tx.from().copied() {
+                    tx.set_nonce(self.next_nonce(from).await?);
+                }
+            }
+
+            match tx {
+                TypedTransaction::Eip1559(ref mut inner) => {
+                    if inner.max_fee_per_gas.is_none() || inner.max_priority_fee_per_gas.is_none() {
+                        let (max_fee_per_gas, max_priority_fee_per_gas) =
+                            self.estimate_eip1559_fees(None).await?;
+                        // the tip cannot exceed the max fee, so a tip set by the caller is capped
+                        // by the max fee, set or estimated
+                        let max_fee_per_gas = inner.max_fee_per_gas.get_or_insert(max_fee_per_gas);
+                        inner.max_priority_fee_per_gas = inner
+                            .max_priority_fee_per_gas
+                            .map(|tip| std::cmp::min(tip, *max_fee_per_gas))
+                            .or(Some(max_priority_fee_per_gas));
+                    }
+                }
+                _ => {
+                    if tx.gas_price().is_none() {
+                        let gas_price = self.get_gas_price().await?;
+                        tx.set_gas_price(gas_price);
+                    }
+                }
+            }
+
+            // gas is estimated against the database, only if it was not set by the caller
+            if tx.gas().is_none() {
+                let gas = self.estimate_gas(tx, block).await?;
+                tx.set_gas(gas);
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn send_transaction<T: Into<TypedTransaction> + Send + Sync>(
+        &self,
+        tx: T,
+        block: Option<EthersBlockId>,
+    ) -> Result<PendingTransaction<'_, Self::Provider>, Self::Error> {
+        let mut tx = tx.into();
+        let managed_nonce = self.manages_nonces() && tx.nonce().is_none();
+        let pending = match self.fill_transaction(&mut tx, block).await {
+            Ok(()) => self
+                .inner
+                .send_transaction(tx.clone(), block)
+                .await
+                .map_err(RethMiddlewareError::from_err),
+            Err(err) => Err(err),
+        };
+        // a nonce reserved for a transaction that was not sent is handed out again
+        if let (true, Err(_), Some(from), Some(nonce)) =
+            (managed_nonce, &pending, tx.from(), tx.nonce())
+        {
+            self.release_nonce(*from, *nonce);
+        }
+        pending
+    }
+
     // Call related methods
     async fn call(
         &self,
         tx: &TypedTransaction,
         block: Option<EthersBlockId>,
     ) -> Result<EthersBytes, Self::Error> {
-        let call_request = tx.into_reth();
-        let block_id = block.into_reth();
-
-        Ok(self.reth_api.call(call_request, block_id, EvmOverrides::default()).await?.into_ethers())
+        instrument(
+            "call",
+            self.fallback(
+                "call",
+                self.with_call_timeout(async {
+                    let mut call_request = tx.into_reth();
+                    self.cap_call_request(&mut call_request);
+                    let block_id = block.into_reth();
+                    record_block(&block_id);
+                    self.ensure_history(block_id)?;
+                    self.ensure_fresh(block_id)?;
+                    self.ensure_forkchoice(block_id)?;
+                    if is_pending(block_id) && self.pending == PendingMode::Block {
+                        let output = self.run_call(|| self.pending_call(tx)).await?;
+                        record_bytes(output.len());
+                        self.ensure_result_size(output.len())?;
+                        return Ok(output)
+                    }
+                    if let Some(calls) = self.native_multicall_calls(tx) {
+                        let results = self.run_call(|| self.run_aggregate3(&calls, block)).await?;
+                        let output = encode_aggregate3_output(&results);
+                        record_bytes(output.len());
+                        self.ensure_result_size(output.len())?;
+                        return Ok(output)
+                    }
+
+                    let output =
+                        self.reth_api.call(call_request, block_id, EvmOverrides::default()).await?;
+                    record_bytes(output.len());
+                    self.ensure_result_size(output.len())?;
+                    Ok(output.into_ethers())
+                }),
+                || self.inner.call(tx, block),
+            ),
+        )
+        .await
     }
 
     async fn estimate_gas(
@@ -84,10 +229,25 @@ where
         tx: &TypedTransaction,
         block: Option<EthersBlockId>,
     ) -> Result<EthersU256, Self::Error> {
-        let call_request = tx.into_reth();
-        let block_id = block.into_reth();
-
-        Ok(self.reth_api.estimate_gas(call_request, block_id).await?.into())
+        instrument(
+            "estimate_gas",
+            self.fallback(
+                "estimate_gas",
+                self.with_call_timeout(async {
+                    let mut call_request = tx.into_reth();
+                    self.cap_call_request(&mut call_request);
+                    let block_id = block.into_reth();
+                    record_block(&block_id);
+                    self.ensure_history(block_id)?;
+                    self.ensure_fresh(block_id)?;
+                    self.ensure_forkchoice(block_id)?;
+
+                    Ok(self.reth_api.estimate_gas(call_request, block_id).await?.into())
+                }),
+                || self.inner.estimate_gas(tx, block),
+            ),
+        )
+        .await
     }
 
     async fn create_access_list(
@@ -95,12 +255,27 @@ where
         tx: &TypedTransaction,
         block: Option<EthersBlockId>,
     ) -> Result<EthersAccessListWithGasUsed, Self::Error> {
-        let call_request = tx.into_reth();
-        let block_id = block.into_reth();
-
-        let result = self.reth_api.create_access_list(call_request, block_id).await?;
-
-        Ok(result.into_ethers())
+        instrument(
+            "create_access_list",
+            self.fallback(
+                "create_access_list",
+                self.with_call_timeout(async {
+                    let mut call_request = tx.into_reth();
+                    self.cap_call_request(&mut call_request);
+                    let block_id = block.into_reth();
+                    record_block(&block_id);
+                    self.ensure_history(block_id)?;
+                    self.ensure_fresh(block_id)?;
+                    self.ensure_forkchoice(block_id)?;
+
+                    let result = self.reth_api.create_access_list(call_request, block_id).await?;
+
+                    Ok(result.into_ethers())
+                }),
+                || self.inner.create_access_list(tx, block),
+            ),
+        )
+        .await
     }
 
     // State related methods
@@ -111,15 +286,30 @@ where
         location: EthersH256,
         block: Option<EthersBlockId>,
     ) -> Result<EthersH256, Self::Error> {
-        // convert `from` to `Address`
-        let from = self.get_address(from).await?;
-        // convert `location` to `JsonStorageKey`
-        let index = location.into_reth();
-        // convert `block` to `Option<BlockId>`
-        let block_id = block.into_reth();
-
-        // call `storage_at` and convert the result
-        Ok(self.reth_api.storage_at(from.into(), index, block_id).await?.into())
+        let from = from.into();
+        instrument(
+            "get_storage_at",
+            self.fallback(
+                "get_storage_at",
+                async {
+                    // convert `from` to `Address`
+                    let from = self.get_address(from.clone()).await?;
+                    // convert `location` to `JsonStorageKey`
+                    let index = location.into_reth();
+                    // convert `block` to `Option<BlockId>`
+                    let block_id = block.into_reth();
+                    record_block(&block_id);
+                    self.ensure_history(block_id)?;
+                    self.ensure_fresh(block_id)?;
+                    self.ensure_forkchoice(block_id)?;
+
+                    // call `storage_at` and convert the result
+                    Ok(self.reth_api.storage_at(from.into(), index, block_id).await?.into())
+                },
+                || self.inner.get_storage_at(from.clone(), location, block),
+            ),
+        )
+        .await
     }
 
     async fn get_code<T: Into<NameOrAddress> + Send + Sync>(
@@ -127,12 +317,28 @@ where
         at: T,
         block: Option<EthersBlockId>,
     ) -> Result<EthersBytes, Self::Error> {
-        let at = self.get_address(at).await?;
-
-        let block_id = block.into_reth();
-        let code = self.reth_api.get_code(at.into(), block_id).await?;
-        // Convert to EthersBytes
-        Ok(code.into_ethers())
+        let at = at.into();
+        instrument(
+            "get_code",
+            self.fallback(
+                "get_code",
+                async {
+                    let at = self.get_address(at.clone()).await?;
+
+                    let block_id = block.into_reth();
+                    record_block(&block_id);
+                    self.ensure_history(block_id)?;
+                    self.ensure_fresh(block_id)?;
+                    self.ensure_forkchoice(block_id)?;
+                    let code = self.reth_api.get_code(at.into(), block_id).await?;
+                    record_bytes(code.len());
+                    // Convert to EthersBytes
+                    Ok(code.into_ethers())
+                },
+                || self.inner.get_code(at.clone(), block),
+            ),
+        )
+        .await
     }
 
     async fn get_balance<T: Into<NameOrAddress> + Send + Sync>(
@@ -140,8 +346,27 @@ where
         from: T,
         block: Option<EthersBlockId>,
     ) -> Result<EthersU256, Self::Error> {
-        let from = self.get_address(from).await?;
-        Ok(self.reth_api.balance(from.into(), block.into_reth()).await?.into())
+        let from = from.into();
+        instrument(
+            "get_balance",
+            self.fallback(
+                "get_balance",
+                async {
+                    let from = self.get_address(from.clone()).await?;
+                    let block_id = block.into_reth();
+                    record_block(&block_id);
+                    self.ensure_history(block_id)?;
+                    self.ensure_fresh(block_id)?;
+                    self.ensure_forkchoice(block_id)?;
+                    if is_pending(block_id) && self.pending == PendingMode::Block {
+                        return self.pending_balance(from.into())
+                    }
+                    Ok(self.reth_api.balance(from.into(), block_id).await?.into())
+                },
+                || self.inner.get_balance(from.clone(), block),
+            ),
+        )
+        .await
     }
 
     async fn get_proof<T: Into<NameOrAddress> + Send + Sync>(
@@ -150,13 +375,25 @@ where
         locations: Vec<EthersH256>,
         block: Option<EthersBlockId>,
     ) -> Result<EthersEIP1186ProofResponse, RethMiddlewareError<M>> {
-        let from = self.get_address(from).await?;
-
-        Ok(self
-            .reth_api
-            .get_proof(from.into(), locations.into_reth(), block.into_reth())
-            .await?
-            .into_ethers())
+        let from = from.into();
+        instrument(
+            "get_proof",
+            self.fallback(
+                "get_proof",
+                async {
+                    let from = self.get_address(from.clone()).await?;
+                    let block_id = block.into_reth();
+                    record_block(&block_id);
+                    self.ensure_history(block_id)?;
+                    self.ensure_fresh(block_id)?;
+                    self.ensure_forkchoice(block_id)?;
+
+                    self.local_proof(from, locations.clone(), block_id).await
+                },
+                || self.inner.get_proof(from.clone(), locations.clone(), block),
+            ),
+        )
+        .await
     }
 
     async fn fee_history<T: Into<EthersU256> + Send + Sync>(
@@ -165,29 +402,73 @@ where
         last_block: EthersBlocKNumber,
         reward_percentiles: &[f64],
     ) -> Result<EthersFeeHistory, Self::Error> {
-        Ok(self
-            .reth_api
-            .fee_history(
-                block_count.into().into_reth(),
-                last_block.into_reth(),
-                Some(reward_percentiles.to_vec()),
-            )
-            .await?
-            .into_ethers())
+        let block_count: EthersU256 = block_count.into();
+        instrument(
+            "fee_history",
+            self.fallback(
+                "fee_history",
+                async {
+                    self.ensure_forkchoice(Some(BlockId::Number(last_block.into_reth())))?;
+                    Ok(self
+                        .reth_api
+                        .fee_history(
+                            block_count.into_reth(),
+                            last_block.into_reth(),
+                            Some(reward_percentiles.to_vec()),
+                        )
+                        .await?
+                        .into_ethers())
+                },
+                || self.inner.fee_history(block_count, last_block, reward_percentiles),
+            ),
+        )
+        .await
+    }
+
+    async fn get_gas_price(&self) -> Result<EthersU256, Self::Error> {
+        instrument("get_gas_price", self.suggested_gas_price()).await
+    }
+
+    async fn estimate_eip1559_fees(
+        &self,
+        estimator: Option<fn(EthersU256, Vec<Vec<EthersU256>>) -> (EthersU256, EthersU256)>,
+    ) -> Result<(EthersU256, EthersU256), Self::Error> {
+        self.eip1559_fees(estimator).await
     }
 
     // Chain Info
 
     async fn get_chainid(&self) -> Result<EthersU256, RethMiddlewareError<M>> {
-        let chain_id = EthApiServer::chain_id(&self.reth_api)
-            .await?
-            .ok_or_else(|| RethMiddlewareError::ChainIdUnavailable)?;
-
-        Ok(chain_id.into_ethers())
+        instrument(
+            "get_chainid",
+            self.fallback(
+                "get_chainid",
+                async {
+                    let chain_id = EthApiServer::chain_id(&self.reth_api)
+                        .await?
+                        .ok_or_else(|| RethMiddlewareError::ChainIdUnavailable)?;
+
+                    Ok(chain_id.into_ethers())
+                },
+                || self.inner.get_chainid(),
+            ),
+        )
+        .await
     }
 
     async fn get_block_number(&self) -> Result<EthersU64, RethMiddlewareError<M>> {
-        Ok(self.reth_api.block_number()?.into_ethers())
+        instrument(
+            "get_block_number",
+            self.fallback(
+                "get_block_number",
+                async {
+                    self.ensure_fresh(None)?;
+                    Ok(self.reth_api.block_number()?.into_ethers())
+                },
+                || self.inner.get_block_number(),
+            ),
+        )
+        .await
     }
 
     /*async fn get_block_receipts<T: Into<EthersBlockNumber> + Send + Sync>(
@@ -210,24 +491,74 @@ where
         &self,
         transaction_hash: T,
     ) -> Result<Option<EthersTransaction>, Self::Error> {
-        let maybe_transaction =
-            self.reth_api.transaction_by_hash(transaction_hash.into().into()).await?;
+        let transaction_hash: EthersTxHash = transaction_hash.into();
+        instrument(
+            "get_transaction",
+            self.fallback_optional(
+                "get_transaction",
+                async {
+                    let maybe_transaction =
+                        self.reth_api.transaction_by_hash(transaction_hash.into()).await?;
+
+                    match maybe_transaction {
+                        Some(reth_tx) => Ok(Some(reth_tx.into_ethers())),
+                        None => {
+                            self.ensure_transaction_lookup()?;
+                            Ok(None)
+                        }
+                    }
+                },
+                || self.inner.get_transaction(transaction_hash),
+            ),
+        )
+        .await
+    }
 
-        match maybe_transaction {
-            Some(reth_tx) => Ok(Some(reth_tx.into_ethers())),
-            None => Ok(None),
-        }
+    async fn get_transaction_by_block_and_index<T: Into<EthersBlockId> + Send + Sync>(
+        &self,
+        block_hash_or_number: T,
+        idx: EthersU64,
+    ) -> Result<Option<EthersTransaction>, ProviderError> {
+        let block_id: EthersBlockId = block_hash_or_number.into();
+        instrument(
+            "get_transaction_by_block_and_index",
+            self.fallback_optional(
+                "get_transaction_by_block_and_index",
+                self.transaction_by_block_and_index(block_id, idx.as_u64()),
+                || async {
+                    self.inner
+                        .get_transaction_by_block_and_index(block_id, idx)
+                        .await
+                        .map_err(M::Error::from_provider_err)
+                },
+            ),
+        )
+        .await
+        .map_err(Into::into)
     }
 
     async fn get_transaction_receipt<T: Send + Sync + Into<EthersTxHash>>(
         &self,
         transaction_hash: T,
     ) -> Result<Option<EthersTransactionReceipt>, RethMiddlewareError<M>> {
-        let hash = ethers::types::H256::from_slice(transaction_hash.into().as_bytes());
-        match self.reth_api.transaction_receipt(hash.into()).await? {
-            Some(receipt) => Ok(Some(receipt.into_ethers())),
-            None => Ok(None),
-        }
+        let transaction_hash: EthersTxHash = transaction_hash.into();
+        instrument(
+            "get_transaction_receipt",
+            self.fallback_optional(
+                "get_transaction_receipt",
+                async {
+                    let generation = self.cache_generation();
+                    if let Some(receipt) = self.cached_receipt(transaction_hash) {
+                        return Ok(Some(receipt))
+                    }
+                    let receipt = self.local_transaction_receipt(transaction_hash).await?;
+                    self.cache_receipt(&receipt, generation);
+                    Ok(receipt)
+                },
+                || self.inner.get_transaction_receipt(transaction_hash),
+            ),
+        )
+        .await
     }
 
     async fn get_transaction_count<T: Into<NameOrAddress> + Send + Sync>(
@@ -235,10 +566,29 @@ where
         from: T,
         block: Option<EthersBlockId>,
     ) -> Result<EthersU256, Self::Error> {
-        let from = self.get_address(from).await?;
-
-        let block_id = block.into_reth();
-        Ok(self.reth_api.transaction_count(from.into(), block_id).await?.into())
+        let from = from.into();
+        instrument(
+            "get_transaction_count",
+            self.fallback(
+                "get_transaction_count",
+                async {
+                    let from = self.get_address(from.clone()).await?;
+
+                    let block_id = block.into_reth();
+                    record_block(&block_id);
+                    self.ensure_history(block_id)?;
+                    self.ensure_fresh(block_id)?;
+                    self.ensure_forkchoice(block_id)?;
+                    let count = self.reth_api.transaction_count(from.into(), block_id).await?;
+                    if is_pending(block_id) {
+                        return Ok(self.pending_nonce(from.into(), count.to::<u64>()).into())
+                    }
+                    Ok(count.into())
+                },
+                || self.inner.get_transaction_count(from.clone(), block),
+            ),
+        )
+        .await
     }
 
     // Blocks
@@ -248,15 +598,39 @@ where
         block_hash_or_number: T,
     ) -> Result<Option<EthersBlock<EthersH256>>, Self::Error> {
         let block_id: EthersBlockId = block_hash_or_number.into();
-
-        let block = match block_id {
-            EthersBlockId::Hash(hash) => self.reth_api.block_by_hash(hash.into(), false).await?,
-            EthersBlockId::Number(num) => {
-                self.reth_api.block_by_number(num.into_reth(), false).await?
-            }
-        };
-
-        Ok(block.into_ethers())
+        instrument(
+            "get_block",
+            self.fallback_optional(
+                "get_block",
+                async {
+                    record_block(&block_id);
+                    self.ensure_fresh(Some(block_id.into_reth()))?;
+                    self.ensure_forkchoice(Some(block_id.into_reth()))?;
+                    let generation = self.cache_generation();
+                    if let Some(block) = self.cached_block(block_id) {
+                        return Ok(Some(block))
+                    }
+
+                    let block = match block_id {
+                        EthersBlockId::Hash(hash) => {
+                            self.reth_api.block_by_hash(hash.into(), false).await?
+                        }
+                        EthersBlockId::Number(num) => {
+                            self.reth_api.block_by_number(num.into_reth(), false).await?
+                        }
+                    };
+
+                    let block = self.with_block_info(block)?;
+                    let block = convert("block", || block.into_ethers());
+                    if !is_pending(Some(block_id.into_reth())) {
+                        self.cache_block(&block, generation);
+                    }
+                    Ok(block)
+                },
+                || self.inner.get_block(block_id),
+            ),
+        )
+        .await
     }
 
     async fn get_uncle<T: Into<EthersBlockId> + Send + Sync>(
@@ -264,67 +638,131 @@ where
         block_hash_or_number: T,
         idx: EthersU64,
     ) -> Result<Option<EthersBlock<EthersTxHash>>, Self::Error> {
-        let block_id = block_hash_or_number.into();
-
-        let block = match block_id {
-            EthersBlockId::Hash(hash) => {
-                self.reth_api
-                    .uncle_by_block_hash_and_index(hash.into(), idx.as_usize().into())
-                    .await?
-            }
-            EthersBlockId::Number(num) => {
-                self.reth_api
-                    .uncle_by_block_number_and_index(num.into_reth(), idx.as_usize().into())
-                    .await?
-            }
-        };
-
-        Ok(block.into_ethers())
+        let block_id: EthersBlockId = block_hash_or_number.into();
+        instrument(
+            "get_uncle",
+            self.fallback_optional(
+                "get_uncle",
+                async {
+                    let block = match block_id {
+                        EthersBlockId::Hash(hash) => {
+                            self.reth_api
+                                .uncle_by_block_hash_and_index(hash.into(), idx.as_usize().into())
+                                .await?
+                        }
+                        EthersBlockId::Number(num) => {
+                            self.reth_api
+                                .uncle_by_block_number_and_index(
+                                    num.into_reth(),
+                                    idx.as_usize().into(),
+                                )
+                                .await?
+                        }
+                    };
+
+                    Ok(block.into_ethers())
+                },
+                || self.inner.get_uncle(block_id, idx),
+            ),
+        )
+        .await
     }
 
     async fn get_block_with_txs<T: Into<EthersBlockId> + Send + Sync>(
         &self,
         block_hash_or_number: T,
     ) -> Result<Option<EthersBlock<EthersTransaction>>, Self::Error> {
-        let block_id = block_hash_or_number.into();
-
-        let block = match block_id {
-            EthersBlockId::Hash(hash) => self.reth_api.block_by_hash(hash.into(), true).await?,
-            EthersBlockId::Number(num) => {
-                self.reth_api.block_by_number(num.into_reth(), true).await?
-            }
-        };
-
-        Ok(block.into_ethers())
+        let block_id: EthersBlockId = block_hash_or_number.into();
+        instrument(
+            "get_block_with_txs",
+            self.fallback_optional(
+                "get_block_with_txs",
+                async {
+                    record_block(&block_id);
+                    self.ensure_fresh(Some(block_id.into_reth()))?;
+                    self.ensure_forkchoice(Some(block_id.into_reth()))?;
+
+                    // read without transactions, which reth recovers the senders of one by one
+                    let block = match block_id {
+                        EthersBlockId::Hash(hash) => {
+                            self.reth_api.block_by_hash(hash.into(), false).await?
+                        }
+                        EthersBlockId::Number(num) => {
+                            self.reth_api.block_by_number(num.into_reth(), false).await?
+                        }
+                    };
+
+                    let block = self.with_full_transactions(self.with_block_info(block)?)?;
+                    Ok(convert("block_with_txs", || block.into_ethers()))
+                },
+                || self.inner.get_block_with_txs(block_id),
+            ),
+        )
+        .await
     }
 
     // Logs
 
     async fn get_logs(&self, filter: &EthersFilter) -> Result<Vec<EthersLog>, Self::Error> {
-        let to_reth_filter: Filter = filter.into_reth();
-        let reth_logs = self.reth_filter.logs(to_reth_filter).await?;
-        Ok(reth_logs.into_ethers())
+        instrument(
+            "get_logs",
+            self.fallback(
+                "get_logs",
+                async {
+                    // logs up to the tip are only as fresh as the tip
+                    if let FilterBlockOption::Range { to_block, .. } = filter.block_option {
+                        self.ensure_fresh(
+                            to_block.map(|block| BlockId::Number(block.into_reth())),
+                        )?;
+                    }
+                    self.cached_logs(filter).await
+                },
+                || self.inner.get_logs(filter),
+            ),
+        )
+        .await
     }
 
     //TODO: Implement get_logs_paginated
-    //TODO: Implement stream event logs (watch)
+    // Filters (`new_filter`, `watch`, ...) stay on the inner provider: `FilterWatcher` polls the
+    // filter changes through it, so the filter ids must be its own. See
+    // `RethMiddleware::watch_events` for a local, notification-driven alternative.
     //TODO: Watch pending tx
 
-    // Tracing
+    // Tracing, forwarded to the inner provider without the `trace` and `debug` features
+    #[cfg(feature = "trace")]
     async fn trace_call<T: Into<TypedTransaction> + Send + Sync>(
         &self,
         req: T,
         trace_type: Vec<EthersTraceType>,
         block: Option<EthersBlockNumber>,
     ) -> Result<EthersBlockTrace, Self::Error> {
-        let tx = req.into();
-        let trace = self
-            .reth_trace
-            .trace_call(tx.into_reth(), trace_type.into_reth(), block.into_reth(), None, None)
-            .await?;
-        Ok(trace.into_ethers())
+        let tx: TypedTransaction = req.into();
+        instrument(
+            "trace_call",
+            self.fallback(
+                "trace_call",
+                async {
+                    self.ensure_namespace("trace_call")?;
+                    let trace = self
+                        .reth_trace
+                        .trace_call(
+                            tx.clone().into_reth(),
+                            trace_type.clone().into_reth(),
+                            block.into_reth(),
+                            None,
+                            None,
+                        )
+                        .await?;
+                    self.limit_trace(trace.into_ethers())
+                },
+                || self.inner.trace_call(tx.clone(), trace_type.clone(), block),
+            ),
+        )
+        .await
     }
 
+    #[cfg(feature = "trace")]
     async fn trace_call_many<T: Into<TypedTransaction> + Send + Sync>(
         &self,
         req: Vec<(T, Vec<EthersTraceType>)>,
@@ -332,143 +770,363 @@ where
     ) -> Result<Vec<EthersBlockTrace>, Self::Error> {
         let tx: Vec<(TypedTransaction, Vec<EthersTraceType>)> =
             req.into_iter().map(|r| (r.0.into(), r.1)).collect();
-        Ok(self.reth_trace.trace_call_many(tx.into_reth(), block.into_reth()).await?.into_ethers())
+        instrument(
+            "trace_call_many",
+            self.fallback(
+                "trace_call_many",
+                async {
+                    self.ensure_namespace("trace_call_many")?;
+                    let traces = self
+                        .reth_trace
+                        .trace_call_many(tx.clone().into_reth(), block.into_reth())
+                        .await?;
+                    self.limit_trace(traces.into_ethers())
+                },
+                || self.inner.trace_call_many(tx.clone(), block),
+            ),
+        )
+        .await
     }
 
+    #[cfg(feature = "trace")]
     async fn trace_raw_transaction(
         &self,
         data: EthersBytes,
         trace_type: Vec<EthersTraceType>,
     ) -> Result<EthersBlockTrace, Self::Error> {
-        Ok(self
-            .reth_trace
-            .trace_raw_transaction(data.into_reth(), trace_type.into_reth(), None)
-            .await?
-            .into_ethers())
+        instrument(
+            "trace_raw_transaction",
+            self.fallback(
+                "trace_raw_transaction",
+                async {
+                    self.ensure_namespace("trace_raw_transaction")?;
+                    let trace = self
+                        .reth_trace
+                        .trace_raw_transaction(
+                            data.clone().into_reth(),
+                            trace_type.clone().into_reth(),
+                            None,
+                        )
+                        .await?;
+                    self.limit_trace(trace.into_ethers())
+                },
+                || self.inner.trace_raw_transaction(data.clone(), trace_type.clone()),
+            ),
+        )
+        .await
     }
 
+    #[cfg(feature = "trace")]
     async fn trace_replay_transaction(
         &self,
         hash: EthersH256,
         trace_type: Vec<EthersTraceType>,
     ) -> Result<EthersBlockTrace, Self::Error> {
-        Ok(self
-            .reth_trace
-            .replay_transaction(hash.into(), trace_type.into_reth())
-            .await?
-            .into_ethers())
+        instrument(
+            "trace_replay_transaction",
+            self.fallback(
+                "trace_replay_transaction",
+                async {
+                    self.ensure_namespace("trace_replay_transaction")?;
+                    let trace = self
+                        .reth_trace
+                        .replay_transaction(hash.into(), trace_type.clone().into_reth())
+                        .await?;
+                    self.limit_trace(trace.into_ethers())
+                },
+                || self.inner.trace_replay_transaction(hash, trace_type.clone()),
+            ),
+        )
+        .await
     }
 
+    #[cfg(feature = "trace")]
     async fn trace_replay_block_transactions(
         &self,
         block: EthersBlockNumber,
         trace_type: Vec<EthersTraceType>,
     ) -> Result<Vec<EthersBlockTrace>, Self::Error> {
-        let res = self
-            .reth_trace
-            .replay_block_transactions(BlockId::Number(block.into_reth()), trace_type.into_reth())
-            .await?;
-        Ok(res.unwrap().into_ethers())
+        instrument(
+            "trace_replay_block_transactions",
+            self.fallback(
+                "trace_replay_block_transactions",
+                async {
+                    self.ensure_namespace("trace_replay_block_transactions")?;
+                    self.ensure_history(Some(BlockId::Number(block.into_reth())))?;
+                    self.ensure_fresh(Some(BlockId::Number(block.into_reth())))?;
+                    self.ensure_forkchoice(Some(BlockId::Number(block.into_reth())))?;
+                    let res = self
+                        .reth_trace
+                        .replay_block_transactions(
+                            BlockId::Number(block.into_reth()),
+                            trace_type.clone().into_reth(),
+                        )
+                        .await?;
+                    self.limit_trace(res.ok_or(RethMiddlewareError::BlockNotFound)?.into_ethers())
+                },
+                || self.inner.trace_replay_block_transactions(block, trace_type.clone()),
+            ),
+        )
+        .await
     }
 
+    #[cfg(feature = "trace")]
     async fn trace_block(&self, block: EthersBlockNumber) -> Result<Vec<EthersTrace>, Self::Error> {
-        let block_id = block.into_reth();
-        let trace_opt = self.reth_trace.trace_block(BlockId::Number(block_id)).await?;
-        Ok(trace_opt.ok_or(RethMiddlewareError::MissingTrace)?.into_ethers())
+        instrument(
+            "trace_block",
+            self.fallback(
+                "trace_block",
+                async {
+                    self.ensure_namespace("trace_block")?;
+                    let block_id = block.into_reth();
+                    record_block(&block_id);
+                    self.ensure_history(Some(BlockId::Number(block_id)))?;
+                    self.ensure_fresh(Some(BlockId::Number(block_id)))?;
+                    self.ensure_forkchoice(Some(BlockId::Number(block_id)))?;
+                    let trace_opt = self.reth_trace.trace_block(BlockId::Number(block_id)).await?;
+                    let traces = trace_opt.ok_or(RethMiddlewareError::MissingTrace)?;
+                    self.limit_trace(convert("traces", || traces.try_into_ethers())?)
+                },
+                || self.inner.trace_block(block),
+            ),
+        )
+        .await
     }
 
+    #[cfg(feature = "trace")]
+    async fn trace_filter(
+        &self,
+        filter: EthersTraceFilter,
+    ) -> Result<Vec<EthersTrace>, Self::Error> {
+        instrument(
+            "trace_filter",
+            self.fallback(
+                "trace_filter",
+                async {
+                    self.ensure_namespace("trace_filter")?;
+                    self.local_trace_filter(&filter).await
+                },
+                || self.inner.trace_filter(filter.clone()),
+            ),
+        )
+        .await
+    }
+
+    #[cfg(feature = "debug")]
     async fn debug_trace_transaction(
         &self,
         tx_hash: EthersTxHash,
         trace_options: EthersDebugTracingOptions,
     ) -> Result<EthersGethTrace, Self::Error> {
-        let debug_trace = self
-            .reth_debug
-            .debug_trace_transaction(tx_hash.into(), trace_options.into_reth())
-            .await?;
-
-        Ok(debug_trace.into_ethers())
+        instrument(
+            "debug_trace_transaction",
+            self.fallback(
+                "debug_trace_transaction",
+                async {
+                    self.ensure_namespace("debug_trace_transaction")?;
+                    if let Some((tracer, config)) = self.native_tracers.select(&trace_options)? {
+                        let trace = self
+                            .native_trace_transaction(tx_hash, tracer.as_ref(), &config)
+                            .await?;
+                        return self.limit_trace(trace)
+                    }
+                    let debug_trace = self
+                        .reth_debug
+                        .debug_trace_transaction(
+                            tx_hash.into(),
+                            self.limit_tracing_options(trace_options.clone()).into_reth(),
+                        )
+                        .await?;
+
+                    self.limit_trace(debug_trace.into_ethers())
+                },
+                || self.inner.debug_trace_transaction(tx_hash, trace_options.clone()),
+            ),
+        )
+        .await
     }
 
+    #[cfg(feature = "debug")]
     async fn debug_trace_block_by_hash(
         &self,
         block: EthersH256,
         trace_options: EthersDebugTracingOptions,
     ) -> Result<Vec<EthersGethTrace>, Self::Error> {
-        let mut debug_trace = self
-            .reth_debug
-            .debug_trace_block(
-                BlockId::from(reth_primitives::H256(block.0)),
-                trace_options.into_reth(),
-            )
-            .await?;
-
-        let mut trace = vec![];
-
-        debug_trace.iter_mut().for_each(|x| {
-            trace.push(match x {
-                TraceResult::Success { result: val } => val.clone(),
-                TraceResult::Error { error: _ } => GethTrace::Default(DefaultFrame::default()),
-            });
-        });
-
-        Ok(trace.into_ethers())
+        instrument(
+            "debug_trace_block_by_hash",
+            self.fallback(
+                "debug_trace_block_by_hash",
+                async {
+                    self.ensure_namespace("debug_trace_block_by_hash")?;
+                    if let Some((tracer, config)) = self.native_tracers.select(&trace_options)? {
+                        let block_id = BlockId::from(reth_primitives::H256(block.0));
+                        let traces =
+                            self.native_trace_block(block_id, tracer.as_ref(), &config).await?;
+                        return self.limit_trace(traces)
+                    }
+                    let mut debug_trace = self
+                        .reth_debug
+                        .debug_trace_block(
+                            BlockId::from(reth_primitives::H256(block.0)),
+                            self.limit_tracing_options(trace_options.clone()).into_reth(),
+                        )
+                        .await?;
+
+                    let mut trace = vec![];
+
+                    debug_trace.iter_mut().for_each(|x| {
+                        trace.push(match x {
+                            TraceResult::Success { result: val } => val.clone(),
+                            TraceResult::Error { error: _ } => {
+                                GethTrace::Default(DefaultFrame::default())
+                            }
+                        });
+                    });
+
+                    self.limit_trace(trace.into_ethers())
+                },
+                || self.inner.debug_trace_block_by_hash(block, trace_options.clone()),
+            ),
+        )
+        .await
     }
 
+    #[cfg(feature = "debug")]
     async fn debug_trace_block_by_number(
         &self,
         block: Option<ethers::types::BlockNumber>,
         trace_options: EthersDebugTracingOptions,
     ) -> Result<Vec<EthersGethTrace>, Self::Error> {
-        let mut debug_trace = self
-            .reth_debug
-            .debug_trace_block(block.unwrap().into_reth(), trace_options.into_reth())
-            .await?;
-
-        let mut trace = vec![];
-
-        debug_trace.iter_mut().for_each(|x| {
-            trace.push(match x {
-                TraceResult::Success { result: val } => val.clone(),
-                TraceResult::Error { error: _ } => GethTrace::Default(DefaultFrame::default()),
-            });
-        });
-
-        Ok(trace.into_ethers())
+        instrument(
+            "debug_trace_block_by_number",
+            self.fallback(
+                "debug_trace_block_by_number",
+                async {
+                    self.ensure_namespace("debug_trace_block_by_number")?;
+                    if let Some((tracer, config)) = self.native_tracers.select(&trace_options)? {
+                        let block_id = block.unwrap_or_default().into_reth();
+                        let traces =
+                            self.native_trace_block(block_id, tracer.as_ref(), &config).await?;
+                        return self.limit_trace(traces)
+                    }
+                    let mut debug_trace = self
+                        .reth_debug
+                        .debug_trace_block(
+                            block.unwrap_or_default().into_reth(),
+                            self.limit_tracing_options(trace_options.clone()).into_reth(),
+                        )
+                        .await?;
+
+                    let mut trace = vec![];
+
+                    debug_trace.iter_mut().for_each(|x| {
+                        trace.push(match x {
+                            TraceResult::Success { result: val } => val.clone(),
+                            TraceResult::Error { error: _ } => {
+                                GethTrace::Default(DefaultFrame::default())
+                            }
+                        });
+                    });
+
+                    self.limit_trace(trace.into_ethers())
+                },
+                || self.inner.debug_trace_block_by_number(block, trace_options.clone()),
+            ),
+        )
+        .await
     }
 
+    #[cfg(feature = "debug")]
     async fn debug_trace_call<T: Into<TypedTransaction> + Send + Sync>(
         &self,
         call: T,
         block_id: Option<EthersBlockId>,
         trace_options: EthersDebugTracingCallOptions,
     ) -> Result<EthersGethTrace, Self::Error> {
-        let debug_trace = self
-            .reth_debug
-            .debug_trace_call(
-                call.into().into_reth(),
-                block_id.into_reth(),
-                trace_options.into_reth(),
-            )
-            .await?;
-
-        Ok(debug_trace.into_ethers())
+        let call: TypedTransaction = call.into();
+        instrument(
+            "debug_trace_call",
+            self.fallback(
+                "debug_trace_call",
+                async {
+                    self.ensure_namespace("debug_trace_call")?;
+                    let native = self.native_tracers.select(&trace_options.tracing_options)?;
+                    if let Some((tracer, config)) = native {
+                        let trace = self
+                            .native_trace_call(
+                                &call,
+                                block_id,
+                                &trace_options,
+                                tracer.as_ref(),
+                                &config,
+                            )
+                            .await?;
+                        return self.limit_trace(trace)
+                    }
+                    let mut options = trace_options.clone();
+                    options.tracing_options = self.limit_tracing_options(options.tracing_options);
+                    let debug_trace = self
+                        .reth_debug
+                        .debug_trace_call(
+                            call.clone().into_reth(),
+                            block_id.into_reth(),
+                            options.into_reth(),
+                        )
+                        .await?;
+
+                    self.limit_trace(debug_trace.into_ethers())
+                },
+                || self.inner.debug_trace_call(call.clone(), block_id, trace_options.clone()),
+            ),
+        )
+        .await
     }
 
+    #[cfg(feature = "trace")]
     async fn trace_get<T: Into<EthersU64> + Send + Sync>(
         &self,
         hash: EthersH256,
         index: Vec<T>,
     ) -> Result<EthersTrace, Self::Error> {
-        let index: Vec<usize> = index.into_iter().map(|i| i.into().as_usize()).collect();
-        Ok(self.reth_trace.trace_get(hash.into(), index).await?.into_ethers().unwrap())
+        let index: Vec<EthersU64> = index.into_iter().map(Into::into).collect();
+        instrument(
+            "trace_get",
+            self.fallback(
+                "trace_get",
+                async {
+                    self.ensure_namespace("trace_get")?;
+                    let index: Vec<usize> = index.iter().map(|i| i.as_usize()).collect();
+                    let trace = self.reth_trace.trace_get(hash.into(), index).await?;
+                    trace.try_into_ethers()?.ok_or(RethMiddlewareError::MissingTrace)
+                },
+                || self.inner.trace_get(hash, index.clone()),
+            ),
+        )
+        .await
     }
 
+    #[cfg(feature = "trace")]
     async fn trace_transaction(
         &self,
         tx_hash: EthersTxHash,
     ) -> Result<Vec<EthersTrace>, Self::Error> {
-        let trace = self.reth_trace.trace_transaction(tx_hash.into()).await?;
-        Ok(trace.into_ethers().unwrap())
+        instrument(
+            "trace_transaction",
+            self.fallback(
+                "trace_transaction",
+                async {
+                    self.ensure_namespace("trace_transaction")?;
+                    let trace = self.reth_trace.trace_transaction(tx_hash.into()).await?;
+                    if trace.is_none() {
+                        self.ensure_transaction_lookup()?;
+                    }
+                    let traces =
+                        trace.try_into_ethers()?.ok_or(RethMiddlewareError::MissingTrace)?;
+                    self.limit_trace(traces)
+                },
+                || self.inner.trace_transaction(tx_hash),
+            ),
+        )
+        .await
     }
 }
@@ -0,0 +1,370 @@
+//! A stand-in for [RethMiddleware](crate::RethMiddleware) in downstream unit tests, behind the
+//! `mock` feature.
+//!
+//! [MockRethMiddleware] serves the methods the middleware answers from the database with
+//! responses queued per method, and records every call. No datadir is needed, so code generic over
+//! [Middleware] can be tested against it in CI.
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+// Ethers
+use ethers::{
+    providers::{Middleware, MiddlewareError, MockProvider, Provider, ProviderError},
+    types::{
+        transaction::{eip2718::TypedTransaction, eip2930::AccessListWithGasUsed},
+        Block, BlockId, BlockNumber, BlockTrace, Bytes, EIP1186ProofResponse, FeeHistory, Filter,
+        GethDebugTracingCallOptions, GethDebugTracingOptions, GethTrace, Log, NameOrAddress, Trace,
+        TraceFilter, TraceType, Transaction, TransactionReceipt, TxHash, H256, U256, U64,
+    },
+};
+
+// Std
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+/// A call received by the mock.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockCall {
+    /// Name of the [Middleware] method.
+    pub method: &'static str,
+    /// The arguments, serialized in order.
+    pub params: Value,
+}
+
+/// A middleware answering with programmed responses.
+///
+/// Responses are queued per method with [MockRethMiddleware::push] and served first in, first
+/// out. Methods the real middleware does not serve locally, e.g. `send_transaction`, go to the
+/// inner `Provider<MockProvider>`, whose responses are programmed through
+/// [MockRethMiddleware::provider_mock].
+#[derive(Debug, Clone)]
+pub struct MockRethMiddleware {
+    inner: Provider<MockProvider>,
+    provider_mock: MockProvider,
+    responses: Arc<Mutex<HashMap<&'static str, VecDeque<Value>>>>,
+    calls: Arc<Mutex<Vec<MockCall>>>,
+}
+
+#[derive(Error, Debug)]
+pub enum MockRethError {
+    /// An error occured in the inner provider.
+    #[error(transparent)]
+    MiddlewareError(#[from] ProviderError),
+
+    /// No response was queued for the method.
+    #[error("No response queued for {0}")]
+    NoResponse(&'static str),
+
+    /// The queued response does not deserialize into the method's return type.
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+}
+
+impl MiddlewareError for MockRethError {
+    type Inner = ProviderError;
+
+    fn from_err(e: Self::Inner) -> Self {
+        MockRethError::MiddlewareError(e)
+    }
+
+    fn as_inner(&self) -> Option<&Self::Inner> {
+        match self {
+            MockRethError::MiddlewareError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Default for MockRethMiddleware {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockRethMiddleware {
+    pub fn new() -> Self {
+        let (inner, provider_mock) = Provider::mocked();
+        Self { inner, provider_mock, responses: Default::default(), calls: Default::default() }
+    }
+
+    /// Queues a response for the method, e.g. `mock.push("get_balance", U256::from(1))`.
+    pub fn push<T: Serialize>(
+        &self,
+        method: &'static str,
+        response: T,
+    ) -> Result<(), serde_json::Error> {
+        let response = serde_json::to_value(response)?;
+        self.responses.lock().unwrap().entry(method).or_default().push_back(response);
+        Ok(())
+    }
+
+    /// Returns the calls received so far, oldest first.
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Returns the calls received so far for the method.
+    pub fn calls_to(&self, method: &str) -> Vec<MockCall> {
+        self.calls().into_iter().filter(|call| call.method == method).collect()
+    }
+
+    /// Returns the mock behind the inner provider.
+    pub fn provider_mock(&self) -> &MockProvider {
+        &self.provider_mock
+    }
+
+    /// Records the call and pops the next response queued for the method.
+    fn respond<P: Serialize, R: DeserializeOwned>(
+        &self,
+        method: &'static str,
+        params: P,
+    ) -> Result<R, MockRethError> {
+        let params = serde_json::to_value(params)?;
+        self.calls.lock().unwrap().push(MockCall { method, params });
+
+        let response = self
+            .responses
+            .lock()
+            .unwrap()
+            .get_mut(method)
+            .and_then(VecDeque::pop_front)
+            .ok_or(MockRethError::NoResponse(method))?;
+        Ok(serde_json::from_value(response)?)
+    }
+}
+
+#[async_trait]
+impl Middleware for MockRethMiddleware {
+    type Error = MockRethError;
+    type Provider = MockProvider;
+    type Inner = Provider<MockProvider>;
+
+    fn inner(&self) -> &Provider<MockProvider> {
+        &self.inner
+    }
+
+    async fn call(
+        &self,
+        tx: &TypedTransaction,
+        block: Option<BlockId>,
+    ) -> Result<Bytes, Self::Error> {
+        self.respond("call", (tx, block))
+    }
+
+    async fn estimate_gas(
+        &self,
+        tx: &TypedTransaction,
+        block: Option<BlockId>,
+    ) -> Result<U256, Self::Error> {
+        self.respond("estimate_gas", (tx, block))
+    }
+
+    async fn create_access_list(
+        &self,
+        tx: &TypedTransaction,
+        block: Option<BlockId>,
+    ) -> Result<AccessListWithGasUsed, Self::Error> {
+        self.respond("create_access_list", (tx, block))
+    }
+
+    async fn get_storage_at<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        from: T,
+        location: H256,
+        block: Option<BlockId>,
+    ) -> Result<H256, Self::Error> {
+        self.respond("get_storage_at", (from.into(), location, block))
+    }
+
+    async fn get_code<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        at: T,
+        block: Option<BlockId>,
+    ) -> Result<Bytes, Self::Error> {
+        self.respond("get_code", (at.into(), block))
+    }
+
+    async fn get_balance<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        from: T,
+        block: Option<BlockId>,
+    ) -> Result<U256, Self::Error> {
+        self.respond("get_balance", (from.into(), block))
+    }
+
+    async fn get_proof<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        from: T,
+        locations: Vec<H256>,
+        block: Option<BlockId>,
+    ) -> Result<EIP1186ProofResponse, Self::Error> {
+        self.respond("get_proof", (from.into(), locations, block))
+    }
+
+    async fn fee_history<T: Into<U256> + Send + Sync>(
+        &self,
+        block_count: T,
+        last_block: BlockNumber,
+        reward_percentiles: &[f64],
+    ) -> Result<FeeHistory, Self::Error> {
+        self.respond("fee_history", (block_count.into(), last_block, reward_percentiles))
+    }
+
+    async fn get_chainid(&self) -> Result<U256, Self::Error> {
+        self.respond("get_chainid", ())
+    }
+
+    async fn get_block_number(&self) -> Result<U64, Self::Error> {
+        self.respond("get_block_number", ())
+    }
+
+    async fn get_transaction<T: Send + Sync + Into<TxHash>>(
+        &self,
+        transaction_hash: T,
+    ) -> Result<Option<Transaction>, Self::Error> {
+        self.respond("get_transaction", (transaction_hash.into(),))
+    }
+
+    async fn get_transaction_receipt<T: Send + Sync + Into<TxHash>>(
+        &self,
+        transaction_hash: T,
+    ) -> Result<Option<TransactionReceipt>, Self::Error> {
+        self.respond("get_transaction_receipt", (transaction_hash.into(),))
+    }
+
+    async fn get_transaction_count<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        from: T,
+        block: Option<BlockId>,
+    ) -> Result<U256, Self::Error> {
+        self.respond("get_transaction_count", (from.into(), block))
+    }
+
+    async fn get_block<T: Into<BlockId> + Send + Sync>(
+        &self,
+        block_hash_or_number: T,
+    ) -> Result<Option<Block<TxHash>>, Self::Error> {
+        self.respond("get_block", (block_hash_or_number.into(),))
+    }
+
+    async fn get_uncle<T: Into<BlockId> + Send + Sync>(
+        &self,
+        block_hash_or_number: T,
+        idx: U64,
+    ) -> Result<Option<Block<TxHash>>, Self::Error> {
+        self.respond("get_uncle", (block_hash_or_number.into(), idx))
+    }
+
+    async fn get_block_with_txs<T: Into<BlockId> + Send + Sync>(
+        &self,
+        block_hash_or_number: T,
+    ) -> Result<Option<Block<Transaction>>, Self::Error> {
+        self.respond("get_block_with_txs", (block_hash_or_number.into(),))
+    }
+
+    async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, Self::Error> {
+        self.respond("get_logs", (filter,))
+    }
+
+    async fn trace_call<T: Into<TypedTransaction> + Send + Sync>(
+        &self,
+        req: T,
+        trace_type: Vec<TraceType>,
+        block: Option<BlockNumber>,
+    ) -> Result<BlockTrace, Self::Error> {
+        self.respond("trace_call", (req.into(), trace_type, block))
+    }
+
+    async fn trace_call_many<T: Into<TypedTransaction> + Send + Sync>(
+        &self,
+        req: Vec<(T, Vec<TraceType>)>,
+        block: Option<BlockNumber>,
+    ) -> Result<Vec<BlockTrace>, Self::Error> {
+        let req: Vec<(TypedTransaction, Vec<TraceType>)> =
+            req.into_iter().map(|(tx, trace_type)| (tx.into(), trace_type)).collect();
+        self.respond("trace_call_many", (req, block))
+    }
+
+    async fn trace_raw_transaction(
+        &self,
+        data: Bytes,
+        trace_type: Vec<TraceType>,
+    ) -> Result<BlockTrace, Self::Error> {
+        self.respond("trace_raw_transaction", (data, trace_type))
+    }
+
+    async fn trace_replay_transaction(
+        &self,
+        hash: H256,
+        trace_type: Vec<TraceType>,
+    ) -> Result<BlockTrace, Self::Error> {
+        self.respond("trace_replay_transaction", (hash, trace_type))
+    }
+
+    async fn trace_replay_block_transactions(
+        &self,
+        block: BlockNumber,
+        trace_type: Vec<TraceType>,
+    ) -> Result<Vec<BlockTrace>, Self::Error> {
+        self.respond("trace_replay_block_transactions", (block, trace_type))
+    }
+
+    async fn trace_block(&self, block: BlockNumber) -> Result<Vec<Trace>, Self::Error> {
+        self.respond("trace_block", (block,))
+    }
+
+    async fn trace_filter(&self, filter: TraceFilter) -> Result<Vec<Trace>, Self::Error> {
+        self.respond("trace_filter", (filter,))
+    }
+
+    async fn debug_trace_transaction(
+        &self,
+        tx_hash: TxHash,
+        trace_options: GethDebugTracingOptions,
+    ) -> Result<GethTrace, Self::Error> {
+        self.respond("debug_trace_transaction", (tx_hash, trace_options))
+    }
+
+    async fn debug_trace_block_by_hash(
+        &self,
+        block: H256,
+        trace_options: GethDebugTracingOptions,
+    ) -> Result<Vec<GethTrace>, Self::Error> {
+        self.respond("debug_trace_block_by_hash", (block, trace_options))
+    }
+
+    async fn debug_trace_block_by_number(
+        &self,
+        block: Option<BlockNumber>,
+        trace_options: GethDebugTracingOptions,
+    ) -> Result<Vec<GethTrace>, Self::Error> {
+        self.respond("debug_trace_block_by_number", (block, trace_options))
+    }
+
+    async fn debug_trace_call<T: Into<TypedTransaction> + Send + Sync>(
+        &self,
+        call: T,
+        block_id: Option<BlockId>,
+        trace_options: GethDebugTracingCallOptions,
+    ) -> Result<GethTrace, Self::Error> {
+        self.respond("debug_trace_call", (call.into(), block_id, trace_options))
+    }
+
+    async fn trace_get<T: Into<U64> + Send + Sync>(
+        &self,
+        hash: H256,
+        index: Vec<T>,
+    ) -> Result<Trace, Self::Error> {
+        let index: Vec<U64> = index.into_iter().map(Into::into).collect();
+        self.respond("trace_get", (hash, index))
+    }
+
+    async fn trace_transaction(&self, tx_hash: TxHash) -> Result<Vec<Trace>, Self::Error> {
+        self.respond("trace_transaction", (tx_hash,))
+    }
+}
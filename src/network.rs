@@ -0,0 +1,178 @@
+use crate::{type_conversions::ToEthers, RethMiddleware, RethMiddlewareError};
+use ethers::{
+    providers::{
+        admin::{EthInfo, EthPeerInfo, PeerInfo, PeerNetworkInfo, PeerProtocolInfo},
+        Middleware,
+    },
+    types::{H256 as EthersH256, U256 as EthersU256, U64 as EthersU64},
+};
+use jsonrpsee::{core::Error as RpcError, RpcModule};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_network_api::{
+    NetworkError, NetworkInfo, NetworkStatus, PeerInfo as RethPeerInfo, Peers, PeersInfo,
+};
+use reth_primitives::NodeRecord;
+
+// Std
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+/// A handle to the network of a running node, e.g. reth's `NetworkHandle`.
+pub trait RethNetwork: NetworkInfo + Peers + 'static {}
+
+impl<T: NetworkInfo + Peers + 'static> RethNetwork for T {}
+
+/// The node's own networking info, as `admin_nodeInfo` reports it.
+///
+/// ethers' `NodeInfo` requires the signed ENR of the node, which only its secret key can produce,
+/// so the record is reported as an enode URL only.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeInfo {
+    /// Hex encoded public key of the node.
+    pub id: String,
+    /// Client version, e.g. `reth/v0.1.0-alpha.4`.
+    pub name: String,
+    pub enode: String,
+    pub ip: IpAddr,
+    pub ports: NodePorts,
+    pub listen_addr: SocketAddr,
+    pub protocols: NodeProtocols,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodePorts {
+    pub discovery: u16,
+    pub listener: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeProtocols {
+    pub eth: EthNodeInfo,
+}
+
+/// The chain the node follows, as announced to its peers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EthNodeInfo {
+    pub network: u64,
+    pub difficulty: EthersU256,
+    pub genesis: EthersH256,
+    pub head: EthersH256,
+}
+
+impl NodeInfo {
+    fn new(record: NodeRecord, listen_addr: SocketAddr, status: NetworkStatus) -> Self {
+        let eth = status.eth_protocol_info;
+        Self {
+            id: format!("{:x}", record.id),
+            name: status.client_version,
+            enode: record.to_string(),
+            ip: record.address,
+            ports: NodePorts { discovery: record.udp_port, listener: record.tcp_port },
+            listen_addr,
+            protocols: NodeProtocols {
+                eth: EthNodeInfo {
+                    network: eth.network,
+                    difficulty: eth.difficulty.into_ethers(),
+                    genesis: eth.genesis.into_ethers(),
+                    head: eth.head.into_ethers(),
+                },
+            },
+        }
+    }
+}
+
+/// Converts a connected peer to the ethers type of `admin_peers`.
+fn peer_info(peer: RethPeerInfo) -> PeerInfo {
+    PeerInfo {
+        enr: None,
+        enode: NodeRecord::new(peer.remote_addr, peer.remote_id).to_string(),
+        id: format!("{:x}", peer.remote_id),
+        name: peer.client_version.to_string(),
+        caps: peer.capabilities.capabilities().iter().map(ToString::to_string).collect(),
+        network: PeerNetworkInfo {
+            local_address: peer.local_addr.unwrap_or(peer.remote_addr),
+            remote_address: peer.remote_addr,
+            inbound: peer.direction.is_incoming(),
+            trusted: false,
+            static_node: false,
+        },
+        protocols: PeerProtocolInfo {
+            eth: Some(EthPeerInfo::Info(Box::new(EthInfo {
+                version: peer.eth_version as u64,
+                difficulty: peer.status.total_difficulty.into_ethers(),
+                head: peer.status.blockhash.into_ethers(),
+            }))),
+            snap: None,
+        },
+    }
+}
+
+/// Returns the `net_peerCount`, `admin_peers` and `admin_nodeInfo` methods of a network.
+pub(crate) fn network_module(network: Arc<dyn RethNetwork>) -> Result<RpcModule<()>, RpcError> {
+    let custom = |err: NetworkError| RpcError::Custom(err.to_string());
+
+    let mut module = RpcModule::new(());
+    let handle = network.clone();
+    module.register_method("net_peerCount", move |_, _| {
+        Ok(EthersU64::from(handle.num_connected_peers()))
+    })?;
+    let handle = network.clone();
+    module.register_async_method("admin_peers", move |_, _| {
+        let handle = handle.clone();
+        async move {
+            let peers = handle.get_all_peers().await.map_err(custom)?;
+            Ok(peers.into_iter().map(peer_info).collect::<Vec<_>>())
+        }
+    })?;
+    module.register_async_method("admin_nodeInfo", move |_, _| {
+        let handle = network.clone();
+        async move {
+            let status = handle.network_status().await.map_err(custom)?;
+            Ok(NodeInfo::new(handle.local_node_record(), handle.local_addr(), status))
+        }
+    })?;
+    Ok(module)
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Reads the networking info of a running node from its network handle, answering
+    /// `net_peerCount`, `admin_peers` and `admin_nodeInfo`. Without one, they are unsupported.
+    pub fn with_network(mut self, network: impl RethNetwork) -> Self {
+        self.network = Some(Arc::new(network));
+        self
+    }
+
+    /// Returns the number of connected peers, like `net_peerCount`.
+    pub fn peer_count(&self) -> Result<u64, RethMiddlewareError<M>> {
+        Ok(self.network("net_peer_count")?.num_connected_peers() as u64)
+    }
+
+    /// Returns the networking info of the node, like `admin_nodeInfo`.
+    pub async fn admin_node_info(&self) -> Result<NodeInfo, RethMiddlewareError<M>> {
+        let network = self.network("admin_node_info")?;
+        let status = network.network_status().await?;
+        Ok(NodeInfo::new(network.local_node_record(), network.local_addr(), status))
+    }
+
+    /// Returns the connected peers, like `admin_peers`.
+    pub(crate) async fn local_peers(&self) -> Result<Vec<PeerInfo>, RethMiddlewareError<M>> {
+        let peers = self.network("peers")?.get_all_peers().await?;
+        Ok(peers.into_iter().map(peer_info).collect())
+    }
+
+    /// Returns the network handle, failing if the middleware was built without one.
+    fn network(
+        &self,
+        method: &'static str,
+    ) -> Result<&Arc<dyn RethNetwork>, RethMiddlewareError<M>> {
+        self.network.as_ref().ok_or(RethMiddlewareError::UnsupportedMethod(method))
+    }
+}
@@ -0,0 +1,80 @@
+use crate::{instrument::instrument, RethMiddleware, RethMiddlewareError};
+use ethers::{
+    providers::Middleware,
+    types::{Address as EthersAddress, U256 as EthersU256},
+};
+
+// Reth
+use reth_primitives::{BlockId, BlockNumberOrTag};
+use reth_rpc_api::EthApiServer;
+
+// Std
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Nonces handed out per sender and not yet seen in the pool or on chain, shared by the clones
+/// of a middleware.
+#[derive(Debug, Clone, Default)]
+pub struct NonceManager {
+    pub(crate) enabled: bool,
+    next: Arc<Mutex<HashMap<EthersAddress, u64>>>,
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Fills the nonce of transactions that have none with [Self::next_nonce].
+    pub fn with_nonce_manager(mut self, enabled: bool) -> Self {
+        self.nonces.enabled = enabled;
+        self
+    }
+
+    /// Returns true if transactions without a nonce get one from [Self::next_nonce].
+    pub fn manages_nonces(&self) -> bool {
+        self.nonces.enabled
+    }
+
+    /// Returns the next nonce `address` can use and reserves it: the nonce past its transactions
+    /// on chain, in the local pool and handed out by earlier calls, whichever is highest.
+    ///
+    /// Unlike ethers' `NonceManagerMiddleware`, transactions submitted out of band are accounted
+    /// for as soon as they reach the pool or a block.
+    pub async fn next_nonce(
+        &self,
+        address: EthersAddress,
+    ) -> Result<EthersU256, RethMiddlewareError<M>> {
+        instrument("next_nonce", async {
+            let latest = self
+                .reth_api
+                .transaction_count(address.into(), Some(BlockId::Number(BlockNumberOrTag::Latest)))
+                .await?;
+            let pending = self.pending_nonce(address.into(), latest.to::<u64>());
+
+            let mut next = self.nonces.next.lock().unwrap();
+            let reserved = next.entry(address).or_default();
+            let nonce = pending.max(*reserved);
+            *reserved = nonce + 1;
+            Ok(nonce.into())
+        })
+        .await
+    }
+
+    /// Gives back a nonce from [Self::next_nonce] whose transaction was never sent, so the next
+    /// call hands it out again. Ignored if a later nonce was handed out since.
+    pub fn release_nonce(&self, address: EthersAddress, nonce: EthersU256) {
+        let mut next = self.nonces.next.lock().unwrap();
+        if let Some(reserved) = next.get_mut(&address) {
+            if *reserved == nonce.as_u64() + 1 {
+                *reserved = nonce.as_u64();
+            }
+        }
+    }
+
+    /// Forgets the nonces handed out to `address`, e.g. after its transactions were dropped.
+    pub fn reset_nonce(&self, address: EthersAddress) {
+        self.nonces.next.lock().unwrap().remove(&address);
+    }
+}
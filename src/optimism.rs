@@ -0,0 +1,178 @@
+//! Support for OP-stack chains (Optimism, Base) behind the `optimism` feature.
+//!
+//! The pinned reth revision predates native OP-stack support, so the database is read as a regular
+//! post-merge chain. What this module adds on top is what OP-stack users expect from the RPC: the
+//! deposit transaction type in conversions, and the L1 data fee fields of receipts, computed from
+//! the `L1Block` predeploy the same way op-geth does.
+//!
+//! Execution and tracing use the L1 EVM: deposits, including the system transaction opening each
+//! block, are replayed as regular transactions, so their traces can differ from op-geth's.
+
+use crate::{type_conversions::ToEthers, RethMiddleware, RethMiddlewareError};
+use ethers::{
+    providers::Middleware,
+    types::{
+        Transaction as EthersTransaction, TransactionReceipt as EthersTransactionReceipt,
+        U256 as EthersU256,
+    },
+};
+use hex_literal::hex;
+
+// Reth
+use reth_primitives::{Address, Chain, ChainSpec, ChainSpecBuilder, H160, H256, U256};
+use reth_provider::{StateProvider, StateProviderFactory};
+use reth_rpc_api::EthApiServer;
+
+// Std
+use std::sync::Arc;
+
+pub const OP_MAINNET_CHAIN_ID: u64 = 10;
+pub const OP_GOERLI_CHAIN_ID: u64 = 420;
+pub const BASE_MAINNET_CHAIN_ID: u64 = 8453;
+pub const BASE_GOERLI_CHAIN_ID: u64 = 84531;
+
+/// Genesis hashes of the known chains, the bedrock genesis of chains migrated from legacy.
+const GENESIS_HASHES: [(u64, H256); 4] = [
+    (
+        OP_MAINNET_CHAIN_ID,
+        H256(hex!("7ca38a1916c42007829c55e69d3e9a73265554b586a499015373241b8a3fa48b")),
+    ),
+    (
+        OP_GOERLI_CHAIN_ID,
+        H256(hex!("c1fc15cd51159b1f1e5cbc4b82e85c1447ddfa33c52cf1d98d14fba0d6354be1")),
+    ),
+    (
+        BASE_MAINNET_CHAIN_ID,
+        H256(hex!("f712aa9241cc24369b143cf6dce85f0902a9731e70d66818a3a5845b296c73dd")),
+    ),
+    (
+        BASE_GOERLI_CHAIN_ID,
+        H256(hex!("a3ab140f15ea7f7443a4702da64c10314eb04d488e72974e02e2d728096b4f76")),
+    ),
+];
+
+/// EIP-2718 type of deposit transactions.
+pub const DEPOSIT_TX_TYPE: u64 = 0x7E;
+
+/// The `L1Block` predeploy holding the attributes of the latest L1 block.
+pub const L1_BLOCK_CONTRACT: Address = H160([
+    0x42, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x15,
+]);
+
+const L1_BASE_FEE_SLOT: u64 = 1;
+const L1_OVERHEAD_SLOT: u64 = 5;
+const L1_SCALAR_SLOT: u64 = 6;
+
+/// Precision of the L1 fee scalar.
+const L1_SCALAR_DECIMALS: u64 = 1_000_000;
+
+/// Builds the chain spec of an OP-stack chain.
+///
+/// Bedrock chains start with every L1 hardfork up to the merge active, which is all the execution
+/// needs: the genesis itself is already in the database. The genesis hash is only set for the
+/// known chains, use [chain_spec_with_genesis_hash] for the others so the database can be checked
+/// against it.
+pub fn chain_spec(chain_id: u64) -> Arc<ChainSpec> {
+    let genesis_hash = GENESIS_HASHES
+        .iter()
+        .find(|(id, _)| *id == chain_id)
+        .map(|(_, genesis_hash)| *genesis_hash);
+    let mut chain =
+        ChainSpecBuilder::mainnet().chain(Chain::Id(chain_id)).paris_activated().build();
+    chain.genesis_hash = genesis_hash;
+    Arc::new(chain)
+}
+
+/// Builds the chain spec of an OP-stack chain starting with the given genesis block.
+pub fn chain_spec_with_genesis_hash(chain_id: u64, genesis_hash: H256) -> Arc<ChainSpec> {
+    let mut chain =
+        ChainSpecBuilder::mainnet().chain(Chain::Id(chain_id)).paris_activated().build();
+    chain.genesis_hash = Some(genesis_hash);
+    Arc::new(chain)
+}
+
+/// Returns the id of the known OP-stack chain starting with the given genesis block.
+pub fn chain_id_by_genesis(genesis_hash: H256) -> Option<u64> {
+    GENESIS_HASHES.iter().find(|(_, hash)| *hash == genesis_hash).map(|(chain_id, _)| *chain_id)
+}
+
+/// The L1 attributes the data fee of a block's transactions is computed from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct L1BlockInfo {
+    pub l1_base_fee: U256,
+    pub l1_fee_overhead: U256,
+    pub l1_fee_scalar: U256,
+}
+
+impl L1BlockInfo {
+    /// Returns the L1 gas charged for posting the encoded transaction, as of Regolith.
+    pub fn l1_gas_used(&self, tx: &[u8]) -> U256 {
+        let zeroes = tx.iter().filter(|b| **b == 0).count() as u64;
+        let ones = tx.len() as u64 - zeroes;
+        U256::from(zeroes * 4 + ones * 16) + self.l1_fee_overhead
+    }
+
+    /// Returns the L1 data fee charged for the encoded transaction.
+    pub fn l1_fee(&self, tx: &[u8]) -> U256 {
+        self.l1_gas_used(tx) * self.l1_base_fee * self.l1_fee_scalar /
+            U256::from(L1_SCALAR_DECIMALS)
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Reads the L1 attributes from the `L1Block` predeploy at the given block.
+    pub fn l1_block_info(&self, block_hash: H256) -> Result<L1BlockInfo, RethMiddlewareError<M>> {
+        let state = self.reth_provider.history_by_block_hash(block_hash)?;
+        let slot = |slot: u64| -> Result<U256, RethMiddlewareError<M>> {
+            let key = H256::from(U256::from(slot).to_be_bytes::<32>());
+            Ok(state.storage(L1_BLOCK_CONTRACT, key)?.unwrap_or_default())
+        };
+
+        Ok(L1BlockInfo {
+            l1_base_fee: slot(L1_BASE_FEE_SLOT)?,
+            l1_fee_overhead: slot(L1_OVERHEAD_SLOT)?,
+            l1_fee_scalar: slot(L1_SCALAR_SLOT)?,
+        })
+    }
+
+    /// Adds the `l1GasPrice`, `l1GasUsed`, `l1Fee` and `l1FeeScalar` fields op-geth returns to
+    /// the receipt. Deposit transactions pay no L1 fee and are left untouched.
+    pub(crate) async fn with_l1_fee_fields(
+        &self,
+        mut receipt: EthersTransactionReceipt,
+    ) -> Result<EthersTransactionReceipt, RethMiddlewareError<M>> {
+        let Some(block_hash) = receipt.block_hash else { return Ok(receipt) };
+        if receipt.transaction_type == Some(DEPOSIT_TX_TYPE.into()) {
+            return Ok(receipt)
+        }
+
+        let Some(tx) = self.reth_api.transaction_by_hash(receipt.transaction_hash.into()).await?
+        else {
+            return Ok(receipt)
+        };
+        let encoded = ToEthers::<EthersTransaction>::into_ethers(tx).rlp();
+
+        let info = self.l1_block_info(block_hash.into())?;
+        let scalar = info.l1_fee_scalar.to::<u64>() as f64 / L1_SCALAR_DECIMALS as f64;
+        let quantity =
+            |value: U256| serde_json::to_value(ToEthers::<EthersU256>::into_ethers(value));
+        let fields = [
+            ("l1GasPrice", quantity(info.l1_base_fee)),
+            ("l1GasUsed", quantity(info.l1_gas_used(&encoded))),
+            ("l1Fee", quantity(info.l1_fee(&encoded))),
+            ("l1FeeScalar", serde_json::to_value(scalar.to_string())),
+        ];
+        for (key, value) in fields {
+            receipt.other.insert(
+                key.to_string(),
+                value.map_err(|e| RethMiddlewareError::ConversionError(e.to_string()))?,
+            );
+        }
+
+        Ok(receipt)
+    }
+}
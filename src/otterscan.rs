@@ -0,0 +1,399 @@
+use crate::{
+    instrument::{db_read, instrument},
+    supply::block_rewards,
+    type_conversions::{rpc::transaction::effective_gas_price, ToEthers},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{
+        Action, Address as EthersAddress, Block as EthersBlock, BlockId as EthersBlockId, CallType,
+        Res, Trace as EthersTrace, Transaction as EthersTransaction,
+        TransactionReceipt as EthersTransactionReceipt, TxHash as EthersTxHash, H256 as EthersH256,
+        U256 as EthersU256,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_primitives::{Address, BlockId, H256};
+use reth_provider::{
+    AccountReader, BlockNumReader, BlockReader, ChainSpecProvider, ReceiptProvider,
+    StateProviderFactory,
+};
+use reth_rpc_api::EthApiServer;
+use reth_rpc_types::Transaction;
+use reth_transaction_pool::{PoolTransaction, TransactionPool};
+
+/// Number of blocks scanned for a sender's transaction when the state history needed to locate it
+/// was pruned.
+pub const SENDER_NONCE_SCAN_LIMIT: u64 = 1024;
+
+/// Number of blocks a single transaction search scans, as there is no index of the blocks an
+/// address appears in.
+pub const SEARCH_SCAN_LIMIT: u64 = 10_000;
+
+/// Kind of an [InternalOperation], numbered as in Otterscan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "u8", try_from = "u8")]
+pub enum OperationType {
+    Transfer = 0,
+    SelfDestruct = 1,
+    Create = 2,
+    Create2 = 3,
+}
+
+impl From<OperationType> for u8 {
+    fn from(ty: OperationType) -> u8 {
+        ty as u8
+    }
+}
+
+impl TryFrom<u8> for OperationType {
+    type Error = String;
+
+    fn try_from(ty: u8) -> Result<Self, Self::Error> {
+        match ty {
+            0 => Ok(OperationType::Transfer),
+            1 => Ok(OperationType::SelfDestruct),
+            2 => Ok(OperationType::Create),
+            3 => Ok(OperationType::Create2),
+            ty => Err(format!("unknown operation type {ty}")),
+        }
+    }
+}
+
+/// A value transfer, contract creation or self-destruct below the top-level call of a
+/// transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InternalOperation {
+    #[serde(rename = "type")]
+    pub ty: OperationType,
+    pub from: EthersAddress,
+    pub to: EthersAddress,
+    pub value: EthersU256,
+}
+
+/// Ether minted by a block, split between its miner and the miners of its uncles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Issuance {
+    pub block_reward: EthersU256,
+    pub uncle_reward: EthersU256,
+    pub issuance: EthersU256,
+}
+
+/// A block without its transactions, with the totals a block explorer shows.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockDetails {
+    pub block: EthersBlock<EthersTxHash>,
+    pub transaction_count: usize,
+    pub issuance: Issuance,
+    /// Fees paid by the block's transactions, burnt base fee included.
+    pub total_fees: EthersU256,
+}
+
+/// A page of the transactions an address sent or received, newest first.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionsPage {
+    pub txs: Vec<EthersTransaction>,
+    pub receipts: Vec<EthersTransactionReceipt>,
+    /// The page reaches the latest block.
+    pub first_page: bool,
+    /// The page reaches the genesis block.
+    pub last_page: bool,
+}
+
+/// Returns the operation a parity trace stands for, if it transfers value, creates a contract or
+/// self-destructs.
+pub(crate) fn internal_operation(trace: &EthersTrace) -> Option<InternalOperation> {
+    match &trace.action {
+        Action::Call(call)
+            if !call.value.is_zero() &&
+                matches!(call.call_type, CallType::Call | CallType::CallCode) =>
+        {
+            Some(InternalOperation {
+                ty: OperationType::Transfer,
+                from: call.from,
+                to: call.to,
+                value: call.value,
+            })
+        }
+        Action::Create(create) => Some(InternalOperation {
+            ty: OperationType::Create,
+            from: create.from,
+            to: match &trace.result {
+                Some(Res::Create(result)) => result.address,
+                _ => EthersAddress::zero(),
+            },
+            value: create.value,
+        }),
+        Action::Suicide(suicide) => Some(InternalOperation {
+            ty: OperationType::SelfDestruct,
+            from: suicide.address,
+            to: suicide.refund_address,
+            value: suicide.balance,
+        }),
+        _ => None,
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the transaction `sender` sent with `nonce`, like Otterscan's
+    /// `ots_getTransactionBySenderAndNonce`.
+    ///
+    /// The including block is located with a binary search over the sender's nonce in the state
+    /// history, then scanned for the transaction. If that history was pruned, the
+    /// [SENDER_NONCE_SCAN_LIMIT] blocks before the earliest state kept are scanned instead.
+    /// Transactions that are not mined yet are looked up in the pool.
+    pub async fn get_transaction_by_sender_and_nonce(
+        &self,
+        sender: EthersAddress,
+        nonce: u64,
+    ) -> Result<Option<EthersTransaction>, RethMiddlewareError<M>> {
+        instrument("get_transaction_by_sender_and_nonce", async {
+            let sender: Address = sender.into();
+            let best = self.reth_provider.chain_info()?.best_number;
+
+            if self.nonce_after(sender, best)? <= nonce {
+                let pooled = self
+                    .reth_pool
+                    .pooled_transactions()
+                    .into_iter()
+                    .find(|tx| tx.sender() == sender && tx.nonce() == nonce);
+                return Ok(pooled.map(|tx| {
+                    Transaction::from_recovered(tx.transaction.to_recovered_transaction())
+                        .into_ethers()
+                }))
+            }
+
+            let earliest = self.prune_limits.history.map_or(0, |limit| limit.saturating_sub(1));
+            let hash = if self.nonce_after(sender, earliest)? > nonce {
+                let from = earliest.saturating_sub(SENDER_NONCE_SCAN_LIMIT);
+                let mut found = None;
+                for number in (from..=earliest).rev() {
+                    found = self.find_sender_transaction(number, sender, nonce)?;
+                    if found.is_some() {
+                        break
+                    }
+                }
+                found
+            } else {
+                // the nonce is at most `nonce` after `low` and above it after `high`
+                let (mut low, mut high) = (earliest, best);
+                while high - low > 1 {
+                    let mid = low + (high - low) / 2;
+                    if self.nonce_after(sender, mid)? > nonce {
+                        high = mid;
+                    } else {
+                        low = mid;
+                    }
+                }
+                self.find_sender_transaction(high, sender, nonce)?
+            };
+
+            match hash {
+                Some(hash) => Ok(self.reth_api.transaction_by_hash(hash).await?.into_ethers()),
+                None => Ok(None),
+            }
+        })
+        .await
+    }
+
+    /// Returns the value transfers, contract creations and self-destructs made by a transaction
+    /// below its top-level call, like `ots_getInternalOperations`.
+    ///
+    /// Operations of reverted frames are left out. Parity traces do not tell `CREATE2` apart, so
+    /// every creation is reported as [OperationType::Create].
+    pub async fn get_internal_operations(
+        &self,
+        tx_hash: EthersTxHash,
+    ) -> Result<Vec<InternalOperation>, RethMiddlewareError<M>> {
+        let traces = self.trace_transaction(tx_hash).await?;
+        Ok(traces
+            .iter()
+            .filter(|trace| !trace.trace_address.is_empty() && trace.error.is_none())
+            .filter_map(internal_operation)
+            .collect())
+    }
+
+    /// Returns true if `address` has code at `block`, like `ots_hasCode`.
+    pub async fn has_code(
+        &self,
+        address: EthersAddress,
+        block: Option<EthersBlockId>,
+    ) -> Result<bool, RethMiddlewareError<M>> {
+        Ok(!self.get_code(address, block).await?.is_empty())
+    }
+
+    /// Returns a block without its transactions together with its issuance and fees, like
+    /// `ots_getBlockDetails`.
+    pub async fn get_block_details(
+        &self,
+        number: u64,
+    ) -> Result<Option<BlockDetails>, RethMiddlewareError<M>> {
+        let Some(block) = self.get_block_with_txs(number).await? else { return Ok(None) };
+        let uncles = self.uncle_headers(number)?;
+
+        let receipts =
+            db_read("receipts_by_block", || self.reth_provider.receipts_by_block(number.into()))?
+                .unwrap_or_default();
+        let mut cumulative_gas_used = 0;
+        let mut total_fees = EthersU256::zero();
+        for (tx, receipt) in block.transactions.iter().zip(receipts) {
+            let gas_used = receipt.cumulative_gas_used - cumulative_gas_used;
+            cumulative_gas_used = receipt.cumulative_gas_used;
+            total_fees += effective_gas_price(tx, block.base_fee_per_gas) * gas_used;
+        }
+
+        let issuance = self.issuance(&block, &uncles);
+        let transaction_count = block.transactions.len();
+        Ok(Some(BlockDetails { block: block.into(), transaction_count, issuance, total_fees }))
+    }
+
+    /// Returns the transactions `address` sent or received in the blocks before `block`, newest
+    /// first, like `ots_searchTransactionsBefore`. A `block` of 0 starts from the latest block.
+    ///
+    /// Blocks are scanned until `page_size` transactions are found, completing the last block,
+    /// or [SEARCH_SCAN_LIMIT] blocks were scanned.
+    pub async fn search_transactions_before(
+        &self,
+        address: EthersAddress,
+        block: u64,
+        page_size: usize,
+    ) -> Result<TransactionsPage, RethMiddlewareError<M>> {
+        let best = self.reth_provider.chain_info()?.best_number;
+        let start = if block == 0 { best } else { block.saturating_sub(1).min(best) };
+        let end = start.saturating_sub(SEARCH_SCAN_LIMIT - 1);
+
+        let mut page = TransactionsPage { first_page: block == 0, ..Default::default() };
+        for number in (end..=start).rev() {
+            self.push_address_transactions(&mut page, number, address.into()).await?;
+            if number == 0 {
+                page.last_page = true;
+            }
+            if page.txs.len() >= page_size {
+                break
+            }
+        }
+        Ok(page)
+    }
+
+    /// Returns the transactions `address` sent or received in the blocks after `block`, newest
+    /// first, like `ots_searchTransactionsAfter`. A `block` of 0 starts from the genesis block.
+    ///
+    /// Scanned the same way as [Self::search_transactions_before].
+    pub async fn search_transactions_after(
+        &self,
+        address: EthersAddress,
+        block: u64,
+        page_size: usize,
+    ) -> Result<TransactionsPage, RethMiddlewareError<M>> {
+        let best = self.reth_provider.chain_info()?.best_number;
+        let start = if block == 0 { 0 } else { block + 1 };
+        let end = start.saturating_add(SEARCH_SCAN_LIMIT - 1).min(best);
+
+        let mut page = TransactionsPage { last_page: block == 0, ..Default::default() };
+        for number in start..=end {
+            let mut block_page = TransactionsPage::default();
+            self.push_address_transactions(&mut block_page, number, address.into()).await?;
+            page.txs.extend(block_page.txs);
+            page.receipts.extend(block_page.receipts);
+            if number == best {
+                page.first_page = true;
+            }
+            if page.txs.len() >= page_size {
+                break
+            }
+        }
+        page.txs.reverse();
+        page.receipts.reverse();
+        Ok(page)
+    }
+
+    /// Appends the transactions of block `number` sent by or to `address`, newest first.
+    async fn push_address_transactions(
+        &self,
+        page: &mut TransactionsPage,
+        number: u64,
+        address: Address,
+    ) -> Result<(), RethMiddlewareError<M>> {
+        let (block, senders) = self.sealed_block_with_senders(BlockId::Number(number.into()))?;
+        let hashes: Vec<H256> = block
+            .body
+            .iter()
+            .zip(senders)
+            .filter(|(tx, sender)| *sender == address || tx.to() == Some(address))
+            .map(|(tx, _)| tx.hash())
+            .collect();
+
+        for hash in hashes.into_iter().rev() {
+            let hash = EthersH256::from(hash.0);
+            let tx = self.get_transaction(hash).await?;
+            let receipt = self.get_transaction_receipt(hash).await?;
+            if let (Some(tx), Some(receipt)) = (tx, receipt) {
+                page.txs.push(tx);
+                page.receipts.push(receipt);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the headers of the uncles of block `number`.
+    fn uncle_headers(
+        &self,
+        number: u64,
+    ) -> Result<Vec<reth_primitives::Header>, RethMiddlewareError<M>> {
+        Ok(db_read("ommers", || self.reth_provider.ommers(number.into()))?.unwrap_or_default())
+    }
+
+    /// Returns the ether minted by a block: nothing after the merge, which is when blocks no
+    /// longer carry a difficulty.
+    fn issuance(
+        &self,
+        block: &EthersBlock<EthersTransaction>,
+        uncles: &[reth_primitives::Header],
+    ) -> Issuance {
+        let number = block.number.unwrap_or_default().as_u64();
+        let (block_reward, uncle_reward) = block_rewards(
+            &self.reth_provider.chain_spec(),
+            number,
+            block.difficulty.is_zero(),
+            uncles,
+        );
+        Issuance {
+            block_reward: block_reward.into(),
+            uncle_reward: uncle_reward.into(),
+            issuance: (block_reward + uncle_reward).into(),
+        }
+    }
+
+    /// Returns the nonce of `address` once block `number` is applied.
+    fn nonce_after(&self, address: Address, number: u64) -> Result<u64, RethMiddlewareError<M>> {
+        let state = db_read("history_by_block_number", || {
+            self.reth_provider.history_by_block_number(number)
+        })?;
+        Ok(state.basic_account(address)?.map(|account| account.nonce).unwrap_or_default())
+    }
+
+    /// Returns the hash of the transaction of block `number` sent by `sender` with `nonce`.
+    fn find_sender_transaction(
+        &self,
+        number: u64,
+        sender: Address,
+        nonce: u64,
+    ) -> Result<Option<H256>, RethMiddlewareError<M>> {
+        let (block, senders) = self.sealed_block_with_senders(BlockId::Number(number.into()))?;
+        Ok(block
+            .body
+            .iter()
+            .zip(senders)
+            .find(|(tx, tx_sender)| *tx_sender == sender && tx.nonce() == nonce)
+            .map(|(tx, _)| tx.hash()))
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{
+    database::RethDatabaseRef,
+    execution::{call_output, call_tx_env},
+    limits::{call_deadline_passed, CallDeadline},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{transaction::eip2718::TypedTransaction, Bytes as EthersBytes, U256 as EthersU256},
+};
+use serde::Deserialize;
+
+// Reth
+use reth_primitives::{Address, BlockId, BlockNumberOrTag};
+use reth_revm::{
+    db::CacheDB,
+    env::fill_tx_env,
+    primitives::{Env, B160, U256},
+    Database, EVM,
+};
+use reth_rpc::eth::error::EthApiError;
+use reth_transaction_pool::{PoolTransaction, TransactionPool};
+
+/// How requests for the `pending` block are served.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PendingMode {
+    /// Nonces count the sender's executable transactions in the pool, balances and calls see the
+    /// latest block.
+    #[default]
+    Pool,
+    /// Nonces count the pool as well, balances and calls see a block built on top of the latest
+    /// one from the best transactions of the pool.
+    Block,
+}
+
+/// Returns true if the request targets the `pending` block.
+pub(crate) fn is_pending(block: Option<BlockId>) -> bool {
+    matches!(block, Some(BlockId::Number(BlockNumberOrTag::Pending)))
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Sets how requests for the `pending` block are served.
+    pub fn with_pending_mode(mut self, mode: PendingMode) -> Self {
+        self.pending = mode;
+        self
+    }
+
+    /// Returns how requests for the `pending` block are served.
+    pub fn pending_mode(&self) -> PendingMode {
+        self.pending
+    }
+
+    /// Returns the nonce of the next transaction of `address`, past its executable transactions
+    /// in the pool. `latest` is its nonce at the latest block.
+    pub(crate) fn pending_nonce(&self, address: Address, latest: u64) -> u64 {
+        self.reth_pool
+            .pooled_transactions()
+            .iter()
+            .filter(|tx| tx.sender() == address)
+            .map(|tx| tx.nonce() + 1)
+            .fold(latest, u64::max)
+    }
+
+    /// Returns the balance of `address` once the pending block is applied.
+    pub(crate) fn pending_balance(
+        &self,
+        address: Address,
+    ) -> Result<EthersU256, RethMiddlewareError<M>> {
+        let (_, mut db) = self.pending_state()?;
+        let account = db.basic(B160::from(address.0))?;
+        Ok(account.map(|account| account.balance).unwrap_or_default().into())
+    }
+
+    /// Executes a call on top of the pending block, without committing it.
+    pub(crate) fn pending_call(
+        &self,
+        tx: &TypedTransaction,
+    ) -> Result<EthersBytes, RethMiddlewareError<M>> {
+        let (mut env, db) = self.pending_state()?;
+        if tx.gas_price().is_none() {
+            // unpriced calls are not charged, as with `eth_call`
+            env.block.basefee = U256::ZERO;
+        }
+        env.tx = call_tx_env(tx, self.gas_cap(env.block.gas_limit.to::<u64>()));
+
+        let mut evm = EVM::with_env(env);
+        evm.database(db);
+        let result = CallDeadline::transact(&mut evm).map_err(EthApiError::from)?.result;
+        Ok(call_output(result)?)
+    }
+
+    /// Builds the pending block: the best transactions of the pool executed on top of the latest
+    /// block, in the environment of the block that follows it, up to its gas limit.
+    ///
+    /// Transactions that no longer apply, e.g. replaced by the latest block, are left out.
+    fn pending_state(&self) -> Result<(Env, CacheDB<RethDatabaseRef<'_>>), RethMiddlewareError<M>> {
+        let (head, _) =
+            self.sealed_block_with_senders(BlockId::Number(BlockNumberOrTag::Latest))?;
+        let mut env = self.block_env(&head)?;
+        env.block.number = U256::from(head.number + 1);
+        env.block.basefee = U256::from(head.next_block_base_fee().unwrap_or_default());
+        let gas_limit = env.block.gas_limit.to::<u64>();
+
+        let state = RethDatabaseRef::at_block(&self.reth_provider, head.hash())?;
+        let mut evm = EVM::with_env(env.clone());
+        evm.database(CacheDB::new(state));
+
+        let mut gas_used = 0;
+        for tx in self.reth_pool.best_transactions() {
+            // the call fails at its deadline anyway
+            if call_deadline_passed() {
+                break
+            }
+            if gas_used + tx.gas_limit() > gas_limit {
+                continue
+            }
+            let tx = tx.transaction.to_recovered_transaction();
+            let sender = tx.signer();
+            fill_tx_env(&mut evm.env.tx, &tx.into_signed(), sender);
+            if let Ok(result) = evm.transact_commit() {
+                gas_used += result.gas_used();
+            }
+        }
+
+        Ok((env, evm.db.take().expect("database is set")))
+    }
+}
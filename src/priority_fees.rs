@@ -0,0 +1,165 @@
+use crate::{
+    execution::coinbase::tip_per_gas,
+    instrument::{db_read, instrument, record_block},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{providers::Middleware, types::U256 as EthersU256};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_provider::{HeaderProvider, ReceiptProvider, TransactionsProvider};
+use reth_rpc::eth::error::EthApiError;
+
+// Std
+use std::ops::RangeInclusive;
+
+/// Most blocks a single priority fee analysis may span.
+pub const MAX_PRIORITY_FEE_SCAN_BLOCKS: u64 = 10_000;
+
+/// The priority fees paid in a block.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockPriorityFees {
+    pub block_number: u64,
+    /// `None` before London, where the whole gas price is a tip.
+    pub base_fee_per_gas: Option<u64>,
+    pub transaction_count: usize,
+    pub gas_used: u64,
+    /// Priority fees paid to the fee recipient, in wei.
+    pub total_priority_fees: EthersU256,
+    /// The tip per gas at each requested percentile, weighted by gas used.
+    pub percentiles: Vec<EthersU256>,
+}
+
+/// The priority fees paid over a range of blocks.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PriorityFeeStats {
+    pub blocks: Vec<BlockPriorityFees>,
+    pub total_priority_fees: EthersU256,
+    /// The tip per gas at each requested percentile over all the blocks, weighted by gas used.
+    pub percentiles: Vec<EthersU256>,
+}
+
+/// Returns the tip per gas at each percentile of the gas of `tips`, pairs of a tip and the gas
+/// paying it, weighted like the rewards of `eth_feeHistory`.
+fn weighted_percentiles(tips: &mut [(u128, u64)], percentiles: &[f64]) -> Vec<EthersU256> {
+    tips.sort_unstable();
+    let total_gas: u64 = tips.iter().map(|(_, gas)| gas).sum();
+    percentiles
+        .iter()
+        .map(|percentile| {
+            let threshold = (total_gas as f64 * percentile / 100.0) as u64;
+            let mut cumulative_gas = 0;
+            tips.iter()
+                .find(|(_, gas)| {
+                    cumulative_gas += gas;
+                    cumulative_gas >= threshold
+                })
+                .or(tips.last())
+                .map_or(0, |(tip, _)| *tip)
+                .into()
+        })
+        .collect()
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the distribution of the priority fees actually paid in the canonical blocks of
+    /// `range`, per block and over the range, at `percentiles` from 0 to 100.
+    ///
+    /// Tips are what each transaction paid above the base fee, weighted by the gas its receipt
+    /// used, so the percentiles match `eth_feeHistory`'s rewards for the same blocks.
+    pub async fn priority_fee_stats(
+        &self,
+        range: RangeInclusive<u64>,
+        percentiles: &[f64],
+    ) -> Result<PriorityFeeStats, RethMiddlewareError<M>> {
+        instrument("priority_fee_stats", async {
+            record_block(&range);
+            let (first, last) = range.clone().into_inner();
+            if first > last || last - first >= MAX_PRIORITY_FEE_SCAN_BLOCKS {
+                return Err(EthApiError::InvalidParams(format!(
+                    "at most {MAX_PRIORITY_FEE_SCAN_BLOCKS} blocks can be scanned"
+                ))
+                .into())
+            }
+            if percentiles.iter().any(|percentile| !(0.0..=100.0).contains(percentile)) {
+                return Err(EthApiError::InvalidParams(
+                    "percentiles must be from 0 to 100".to_string(),
+                )
+                .into())
+            }
+            self.ensure_receipts(first)?;
+
+            let headers =
+                db_read("sealed_headers_range", || self.reth_provider.sealed_headers_range(range))?;
+            let mut stats = PriorityFeeStats::default();
+            let mut range_tips = vec![];
+            for header in headers {
+                let transactions = db_read("transactions_by_block", || {
+                    self.reth_provider.transactions_by_block(header.number.into())
+                })?
+                .unwrap_or_default();
+                let receipts = db_read("receipts_by_block", || {
+                    self.reth_provider.receipts_by_block(header.number.into())
+                })?
+                .unwrap_or_default();
+
+                let base_fee = header.base_fee_per_gas.unwrap_or_default();
+                let mut tips = Vec::with_capacity(transactions.len());
+                let mut cumulative_gas_used = 0;
+                let mut total_priority_fees = EthersU256::zero();
+                for (tx, receipt) in transactions.iter().zip(receipts) {
+                    let gas_used = receipt.cumulative_gas_used - cumulative_gas_used;
+                    cumulative_gas_used = receipt.cumulative_gas_used;
+                    let tip = tip_per_gas(tx, base_fee);
+                    total_priority_fees += EthersU256::from(tip) * gas_used;
+                    tips.push((tip, gas_used));
+                }
+
+                range_tips.extend_from_slice(&tips);
+                stats.total_priority_fees += total_priority_fees;
+                stats.blocks.push(BlockPriorityFees {
+                    block_number: header.number,
+                    base_fee_per_gas: header.base_fee_per_gas,
+                    transaction_count: transactions.len(),
+                    gas_used: header.gas_used,
+                    total_priority_fees,
+                    percentiles: weighted_percentiles(&mut tips, percentiles),
+                });
+            }
+            stats.percentiles = weighted_percentiles(&mut range_tips, percentiles);
+            Ok(stats)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn percentiles(tips: &[(u128, u64)], percentiles: &[f64]) -> Vec<u64> {
+        let mut tips = tips.to_vec();
+        weighted_percentiles(&mut tips, percentiles).iter().map(|tip| tip.as_u64()).collect()
+    }
+
+    #[test]
+    fn percentiles_are_weighted_by_gas() {
+        let tips = [(3, 100), (1, 100), (2, 200)];
+        assert_eq!(
+            percentiles(&tips, &[0.0, 25.0, 50.0, 75.0, 76.0, 100.0]),
+            vec![1, 1, 2, 2, 3, 3]
+        );
+    }
+
+    #[test]
+    fn blocks_without_tips_have_zero_percentiles() {
+        assert_eq!(percentiles(&[], &[10.0, 90.0]), vec![0, 0]);
+        assert_eq!(percentiles(&[(5, 0)], &[50.0]), vec![5]);
+        assert!(percentiles(&[(5, 21_000)], &[]).is_empty());
+    }
+}
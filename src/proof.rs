@@ -0,0 +1,135 @@
+use crate::{
+    instrument::db_read,
+    type_conversions::{ToEthers, ToReth},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{
+        Address as EthersAddress, EIP1186ProofResponse as EthersEIP1186ProofResponse,
+        H256 as EthersH256,
+    },
+};
+
+// Reth
+use reth_primitives::{BlockId, BlockNumberOrTag, H256};
+use reth_provider::{BlockIdReader, BlockNumReader};
+use reth_rpc::eth::error::EthApiError;
+use reth_rpc_api::EthApiServer;
+
+/// Most times a chunked proof of `latest` starts over against a new tip.
+pub const MAX_PROOF_ATTEMPTS: usize = 3;
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Proves at most `keys` storage keys per trie walk, so proofs of hundreds of keys are built
+    /// in chunks and merged instead of in one walk. Unchunked if `None`.
+    pub fn with_proof_chunk_size(mut self, keys: Option<usize>) -> Self {
+        self.proof_chunk_size = keys.map(|keys| keys.max(1));
+        self
+    }
+
+    /// Returns the most storage keys proven per trie walk, `None` if proofs are not chunked.
+    pub fn proof_chunk_size(&self) -> Option<usize> {
+        self.proof_chunk_size
+    }
+
+    /// Sets the most storage keys a single `get_proof` request may ask for, `None` for no limit.
+    pub fn with_max_proof_keys(mut self, keys: Option<usize>) -> Self {
+        self.max_proof_keys = keys;
+        self
+    }
+
+    /// Returns the most storage keys a single `get_proof` request may ask for.
+    pub fn max_proof_keys(&self) -> Option<usize> {
+        self.max_proof_keys
+    }
+
+    /// Serves `get_proof` from the database, a chunk of storage keys at a time if the keys are
+    /// more than the chunk size.
+    ///
+    /// Reth only proves the state of the tip, so chunks are proven against the tip pinned by
+    /// hash. If the tip moves before all chunks are proven, a proof of `latest` starts over
+    /// against the new tip, up to [MAX_PROOF_ATTEMPTS] times, while a proof of a given block
+    /// fails with [RethMiddlewareError::ProofTipMoved]. Partial proofs are never returned.
+    pub(crate) async fn local_proof(
+        &self,
+        address: EthersAddress,
+        keys: Vec<EthersH256>,
+        block_id: Option<BlockId>,
+    ) -> Result<EthersEIP1186ProofResponse, RethMiddlewareError<M>> {
+        if let Some(limit) = self.max_proof_keys {
+            if keys.len() > limit {
+                return Err(EthApiError::InvalidParams(format!(
+                    "at most {limit} storage keys can be proven at once"
+                ))
+                .into())
+            }
+        }
+        let chunk_size = match self.proof_chunk_size {
+            Some(chunk_size) if keys.len() > chunk_size => chunk_size,
+            _ => {
+                return Ok(self
+                    .reth_api
+                    .get_proof(address.into_reth(), keys.into_reth(), block_id)
+                    .await?
+                    .into_ethers())
+            }
+        };
+
+        let latest = matches!(block_id, None | Some(BlockId::Number(BlockNumberOrTag::Latest)));
+        let block_id = block_id.unwrap_or(BlockId::Number(BlockNumberOrTag::Latest));
+        let mut attempts = 0;
+        loop {
+            let block_hash =
+                db_read("block_hash_for_id", || self.reth_provider.block_hash_for_id(block_id))?
+                    .ok_or(RethMiddlewareError::BlockNotFound)?;
+            match self.prove_chunks(address, &keys, chunk_size, block_hash).await? {
+                Some(proof) => return Ok(proof),
+                None if latest && attempts + 1 < MAX_PROOF_ATTEMPTS => attempts += 1,
+                None => return Err(RethMiddlewareError::ProofTipMoved(block_hash)),
+            }
+        }
+    }
+
+    /// Proves the keys a chunk at a time against the block `block_hash`, `None` if the tip moved
+    /// past it before all chunks were proven.
+    async fn prove_chunks(
+        &self,
+        address: EthersAddress,
+        keys: &[EthersH256],
+        chunk_size: usize,
+        block_hash: H256,
+    ) -> Result<Option<EthersEIP1186ProofResponse>, RethMiddlewareError<M>> {
+        let pinned = Some(BlockId::Hash(block_hash.into()));
+        let mut proof: Option<EthersEIP1186ProofResponse> = None;
+        for chunk in keys.chunks(chunk_size) {
+            if self.tip_hash()? != block_hash {
+                return Ok(None)
+            }
+            let chunk_proof: EthersEIP1186ProofResponse = match self
+                .reth_api
+                .get_proof(address.into_reth(), chunk.to_vec().into_reth(), pinned)
+                .await
+            {
+                Ok(chunk_proof) => chunk_proof.into_ethers(),
+                // the tip moved while the chunk was proven
+                Err(_) if self.tip_hash()? != block_hash => return Ok(None),
+                Err(err) => return Err(err.into()),
+            };
+            match &mut proof {
+                // the account part of the proof is the same for every chunk
+                Some(proof) => proof.storage_proof.extend(chunk_proof.storage_proof),
+                None => proof = Some(chunk_proof),
+            }
+        }
+        Ok(proof)
+    }
+
+    /// Returns the hash of the local tip.
+    fn tip_hash(&self) -> Result<H256, RethMiddlewareError<M>> {
+        Ok(db_read("chain_info", || self.reth_provider.chain_info())?.best_hash)
+    }
+}
@@ -0,0 +1,190 @@
+//! Resolution of upgradeable and minimal proxies to the contracts they delegate to.
+use crate::{RethMiddleware, RethMiddlewareError};
+use ethers::{
+    providers::Middleware,
+    types::{Address, BlockId, H256},
+};
+use hex_literal::hex;
+use serde::{Deserialize, Serialize};
+
+/// EIP-1967 slot of the implementation, `keccak256("eip1967.proxy.implementation") - 1`.
+pub const EIP1967_IMPLEMENTATION_SLOT: H256 =
+    H256(hex!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc"));
+
+/// EIP-1967 slot of the beacon, `keccak256("eip1967.proxy.beacon") - 1`.
+pub const EIP1967_BEACON_SLOT: H256 =
+    H256(hex!("a3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50"));
+
+/// EIP-1967 slot of the admin, `keccak256("eip1967.proxy.admin") - 1`.
+pub const EIP1967_ADMIN_SLOT: H256 =
+    H256(hex!("b53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d6103"));
+
+/// EIP-1822 (UUPS) slot of the implementation, `keccak256("PROXIABLE")`.
+pub const EIP1822_PROXIABLE_SLOT: H256 =
+    H256(hex!("c5f16f0fcc639fa48a6947836d9850f504798523bf8c9a3a87d5876cf622bcf7"));
+
+/// Slot of the implementation of OpenZeppelin's proxies before EIP-1967,
+/// `keccak256("org.zeppelinos.proxy.implementation")`.
+pub const ZEPPELINOS_IMPLEMENTATION_SLOT: H256 =
+    H256(hex!("7050c9e0f4ca769c69bd3a8ef740bc37934f8e2c036e5a723fd8ee048ed3f8c3"));
+
+/// Code of an EIP-1167 minimal proxy before the implementation address.
+const MINIMAL_PROXY_PREFIX: [u8; 10] = hex!("363d3d373d3d3d363d73");
+
+/// Code of an EIP-1167 minimal proxy after the implementation address.
+const MINIMAL_PROXY_SUFFIX: [u8; 15] = hex!("5af43d82803e903d91602b57fd5bf3");
+
+/// How a proxy stores the address of its implementation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProxyKind {
+    /// In the EIP-1967 implementation slot.
+    Eip1967,
+    /// Behind the beacon in the EIP-1967 beacon slot.
+    Eip1967Beacon,
+    /// In the EIP-1822 `PROXIABLE` slot.
+    Eip1822,
+    /// In the slot of OpenZeppelin's proxies before EIP-1967.
+    ZeppelinOs,
+    /// In the code of an EIP-1167 minimal proxy.
+    Eip1167,
+}
+
+/// A proxy and the contract it delegates to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyInfo {
+    pub proxy: Address,
+    pub kind: ProxyKind,
+    pub implementation: Address,
+    /// The beacon of a [ProxyKind::Eip1967Beacon] proxy.
+    pub beacon: Option<Address>,
+    /// The account allowed to upgrade an EIP-1967 proxy, if set.
+    pub admin: Option<Address>,
+}
+
+/// Returns the implementation an EIP-1167 minimal proxy delegates to, `None` for other code.
+pub fn minimal_proxy_implementation(code: &[u8]) -> Option<Address> {
+    if code.len() != MINIMAL_PROXY_PREFIX.len() + 20 + MINIMAL_PROXY_SUFFIX.len() {
+        return None
+    }
+    let (prefix, rest) = code.split_at(MINIMAL_PROXY_PREFIX.len());
+    let (implementation, suffix) = rest.split_at(20);
+    (prefix == MINIMAL_PROXY_PREFIX && suffix == MINIMAL_PROXY_SUFFIX)
+        .then(|| Address::from_slice(implementation))
+}
+
+/// Returns the address in the low 20 bytes of a slot, `None` if it is zero.
+fn slot_address(value: H256) -> Option<Address> {
+    Some(Address::from(value)).filter(|address| !address.is_zero())
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the contract a proxy delegates to at `block`, the latest if `None`, or `None` if
+    /// `address` is not a proxy it recognizes.
+    ///
+    /// The EIP-1967 implementation and beacon slots are tried first, then the EIP-1822 and
+    /// legacy OpenZeppelin slots, then the code of EIP-1167 minimal proxies. A beacon's
+    /// implementation is read by calling its `implementation()`. Only one level is resolved, an
+    /// implementation may be a proxy itself.
+    pub async fn resolve_proxy(
+        &self,
+        address: Address,
+        block: Option<BlockId>,
+    ) -> Result<Option<ProxyInfo>, RethMiddlewareError<M>> {
+        // pinned, so every read sees the same state
+        let block = Some(match block {
+            Some(block) => block,
+            None => self.get_block_number().await?.into(),
+        });
+        let read = |slot| async move {
+            Ok::<_, RethMiddlewareError<M>>(slot_address(
+                self.get_storage_at(address, slot, block).await?,
+            ))
+        };
+        let info = |kind, implementation| ProxyInfo {
+            proxy: address,
+            kind,
+            implementation,
+            beacon: None,
+            admin: None,
+        };
+
+        if let Some(implementation) = read(EIP1967_IMPLEMENTATION_SLOT).await? {
+            let admin = read(EIP1967_ADMIN_SLOT).await?;
+            return Ok(Some(ProxyInfo { admin, ..info(ProxyKind::Eip1967, implementation) }))
+        }
+        if let Some(beacon) = read(EIP1967_BEACON_SLOT).await? {
+            let implementation: Address = self
+                .call_typed(beacon, "function implementation() view returns (address)", (), block)
+                .await?;
+            let admin = read(EIP1967_ADMIN_SLOT).await?;
+            return Ok(Some(ProxyInfo {
+                beacon: Some(beacon),
+                admin,
+                ..info(ProxyKind::Eip1967Beacon, implementation)
+            }))
+        }
+        if let Some(implementation) = read(EIP1822_PROXIABLE_SLOT).await? {
+            return Ok(Some(info(ProxyKind::Eip1822, implementation)))
+        }
+        if let Some(implementation) = read(ZEPPELINOS_IMPLEMENTATION_SLOT).await? {
+            return Ok(Some(info(ProxyKind::ZeppelinOs, implementation)))
+        }
+
+        let code = self.get_code(address, block).await?;
+        Ok(minimal_proxy_implementation(&code)
+            .map(|implementation| info(ProxyKind::Eip1167, implementation)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{types::U256, utils::keccak256};
+
+    /// Returns `keccak256(id) - 1`, how EIP-1967 derives its slots.
+    fn eip1967_slot(id: &str) -> H256 {
+        let slot = U256::from_big_endian(&keccak256(id)) - 1;
+        let mut bytes = [0; 32];
+        slot.to_big_endian(&mut bytes);
+        H256(bytes)
+    }
+
+    #[test]
+    fn slots_match_their_derivation() {
+        assert_eq!(EIP1967_IMPLEMENTATION_SLOT, eip1967_slot("eip1967.proxy.implementation"));
+        assert_eq!(EIP1967_BEACON_SLOT, eip1967_slot("eip1967.proxy.beacon"));
+        assert_eq!(EIP1967_ADMIN_SLOT, eip1967_slot("eip1967.proxy.admin"));
+        assert_eq!(EIP1822_PROXIABLE_SLOT, H256(keccak256("PROXIABLE")));
+        assert_eq!(
+            ZEPPELINOS_IMPLEMENTATION_SLOT,
+            H256(keccak256("org.zeppelinos.proxy.implementation"))
+        );
+    }
+
+    #[test]
+    fn minimal_proxies_are_recognized_by_their_code() {
+        let implementation = Address::from_low_u64_be(0xbeef);
+        let code =
+            [&MINIMAL_PROXY_PREFIX[..], implementation.as_bytes(), &MINIMAL_PROXY_SUFFIX].concat();
+        assert_eq!(code.len(), 45);
+        assert_eq!(minimal_proxy_implementation(&code), Some(implementation));
+
+        assert_eq!(minimal_proxy_implementation(&code[..44]), None);
+        let mut other = code.clone();
+        other[44] = 0x00;
+        assert_eq!(minimal_proxy_implementation(&other), None);
+        assert_eq!(minimal_proxy_implementation(&[]), None);
+    }
+
+    #[test]
+    fn empty_slots_hold_no_address() {
+        assert_eq!(slot_address(H256::zero()), None);
+        let implementation = Address::from_low_u64_be(0xbeef);
+        assert_eq!(slot_address(implementation.into()), Some(implementation));
+    }
+}
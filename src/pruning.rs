@@ -0,0 +1,117 @@
+use crate::{init::view, instrument::db_read, RethMiddleware, RethMiddlewareError};
+use ethers::providers::Middleware;
+
+// Reth
+use reth_db::{
+    cursor::DbCursorRO,
+    mdbx::{Env, WriteMap},
+    tables,
+    transaction::DbTx,
+    DatabaseError,
+};
+use reth_primitives::BlockId;
+use reth_provider::BlockIdReader;
+
+/// The lowest blocks whose data is still stored, for each segment the node can prune.
+///
+/// `None` means the segment is complete.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PruneLimits {
+    /// First block whose receipts are stored.
+    pub receipts: Option<u64>,
+    /// First block whose transactions can be looked up by hash.
+    pub transaction_lookup: Option<u64>,
+    /// First block whose account and storage changesets are stored. State can be read at this
+    /// block's parent and later.
+    pub history: Option<u64>,
+}
+
+impl PruneLimits {
+    /// Detects the segments the node pruned by looking at the lowest entries of their tables.
+    ///
+    /// Pruning always removes the oldest entries, so a segment is pruned when its first entry
+    /// starts after the first transaction, or for history, after the first block.
+    pub fn detect(db: &Env<WriteMap>) -> Result<Self, DatabaseError> {
+        view(db, |tx| {
+            let block_of = |tx_num: u64| -> Result<Option<u64>, DatabaseError> {
+                Ok(tx.cursor_read::<tables::TransactionBlock>()?.seek(tx_num)?.map(|(_, b)| b))
+            };
+
+            let receipts = match tx.cursor_read::<tables::Receipts>()?.first()? {
+                Some((first_tx, _)) if first_tx > 0 => block_of(first_tx)?,
+                _ => None,
+            };
+
+            let missing_lookups = tx
+                .entries::<tables::Transactions>()?
+                .saturating_sub(tx.entries::<tables::TxHashNumber>()?);
+            let transaction_lookup =
+                if missing_lookups > 0 { block_of(missing_lookups as u64)? } else { None };
+
+            // every block has at least the beneficiary's balance change until the merge, so the
+            // first changeset only starts after block 1 if older ones were pruned
+            let history = match tx.cursor_read::<tables::AccountChangeSet>()?.first()? {
+                Some((first_block, _)) if first_block > 1 => Some(first_block),
+                _ => None,
+            };
+
+            Ok(Self { receipts, transaction_lookup, history })
+        })?
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the segments of the database the node pruned.
+    pub fn prune_limits(&self) -> PruneLimits {
+        self.prune_limits
+    }
+
+    /// Sets the pruned segments of the database, e.g. the ones detected with
+    /// [PruneLimits::detect] when the middleware is built from a running node.
+    pub fn with_prune_limits(mut self, limits: PruneLimits) -> Self {
+        self.prune_limits = limits;
+        self
+    }
+
+    /// Fails if the state at `block` needs changesets that were pruned.
+    pub(crate) fn ensure_history(
+        &self,
+        block: Option<BlockId>,
+    ) -> Result<(), RethMiddlewareError<M>> {
+        let (Some(limit), Some(block)) = (self.prune_limits.history, block) else { return Ok(()) };
+
+        let number =
+            db_read("block_number_for_id", || self.reth_provider.block_number_for_id(block))?;
+        match number {
+            Some(number) if number + 1 < limit => Err(RethMiddlewareError::PrunedData(format!(
+                "state history before block {}",
+                limit - 1
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails if the receipts of `block` were pruned.
+    pub(crate) fn ensure_receipts(&self, block: u64) -> Result<(), RethMiddlewareError<M>> {
+        match self.prune_limits.receipts {
+            Some(limit) if block < limit => {
+                Err(RethMiddlewareError::PrunedData(format!("receipts before block {limit}")))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails if transactions of older blocks can no longer be looked up by hash, explaining why a
+    /// transaction may not have been found.
+    pub(crate) fn ensure_transaction_lookup(&self) -> Result<(), RethMiddlewareError<M>> {
+        match self.prune_limits.transaction_lookup {
+            Some(limit) => Err(RethMiddlewareError::PrunedData(format!(
+                "transaction lookup before block {limit}"
+            ))),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! Python bindings of the read path, built as the `ethers_reth` extension module with maturin.
+//!
+//! Results are converted to the dicts and lists `json.loads` returns for the JSON-RPC response of
+//! the same method, so code written against a node's RPC keeps working on the datadir.
+use crate::{RethMiddleware, RethMiddlewareError};
+use ethers::{
+    providers::{Middleware, MockProvider, Provider},
+    types::{
+        transaction::eip2718::TypedTransaction, Address, BlockId, BlockNumber, Filter,
+        TransactionRequest, H256,
+    },
+};
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyModule};
+use serde::Serialize;
+use tokio::runtime::Runtime;
+
+// Std
+use std::{fmt::Display, future::Future, str::FromStr};
+
+type Inner = Provider<MockProvider>;
+
+/// A block argument: a number, a `0x` prefixed hash, or a tag like `"latest"`.
+#[derive(FromPyObject)]
+enum BlockArg {
+    Number(u64),
+    Tag(String),
+}
+
+impl BlockArg {
+    fn into_block_id(self) -> PyResult<BlockId> {
+        let tag = match self {
+            BlockArg::Number(number) => return Ok(BlockNumber::Number(number.into()).into()),
+            BlockArg::Tag(tag) => tag,
+        };
+        let number = match tag.as_str() {
+            "latest" => BlockNumber::Latest,
+            "finalized" => BlockNumber::Finalized,
+            "safe" => BlockNumber::Safe,
+            "earliest" => BlockNumber::Earliest,
+            "pending" => BlockNumber::Pending,
+            hash => return Ok(parse::<H256>(hash)?.into()),
+        };
+        Ok(number.into())
+    }
+
+    fn into_block_number(self) -> PyResult<BlockNumber> {
+        match self.into_block_id()? {
+            BlockId::Number(number) => Ok(number),
+            BlockId::Hash(_) => Err(PyValueError::new_err("filters take block numbers or tags")),
+        }
+    }
+}
+
+fn parse<T: FromStr>(value: &str) -> PyResult<T> {
+    value.parse().map_err(|_| PyValueError::new_err(format!("invalid value `{value}`")))
+}
+
+fn py_err(err: impl Display) -> PyErr {
+    PyValueError::new_err(err.to_string())
+}
+
+/// Converts a result to the Python objects of its JSON encoding.
+fn to_py(py: Python<'_>, value: &impl Serialize) -> PyResult<PyObject> {
+    let json = serde_json::to_string(value).map_err(py_err)?;
+    Ok(py.import("json")?.call_method1("loads", (json,))?.into())
+}
+
+/// A middleware reading a reth datadir, with the runtime its calls block on.
+#[pyclass(name = "RethMiddleware")]
+struct PyRethMiddleware {
+    runtime: Runtime,
+    middleware: RethMiddleware<Inner>,
+}
+
+impl PyRethMiddleware {
+    /// Runs a call on the runtime, releasing the GIL meanwhile.
+    fn run<T: Serialize + Send>(
+        &self,
+        py: Python<'_>,
+        call: impl Future<Output = Result<T, RethMiddlewareError<Inner>>> + Send,
+    ) -> PyResult<PyObject> {
+        let value = py.allow_threads(|| self.runtime.block_on(call)).map_err(py_err)?;
+        to_py(py, &value)
+    }
+}
+
+#[pymethods]
+impl PyRethMiddleware {
+    /// Opens the datadir of a reth node, e.g. `~/.local/share/reth/mainnet`.
+    #[new]
+    fn new(datadir: &str) -> PyResult<Self> {
+        let runtime = Runtime::new()?;
+        let middleware = {
+            let _guard = runtime.enter();
+            // every call is answered from the database, the mock only fills the inner provider
+            RethMiddleware::from_db_path(Provider::new(MockProvider::new()), datadir)
+                .map_err(py_err)?
+        };
+        Ok(Self { runtime, middleware })
+    }
+
+    fn block_number(&self, py: Python<'_>) -> PyResult<u64> {
+        let number = py
+            .allow_threads(|| self.runtime.block_on(self.middleware.get_block_number()))
+            .map_err(py_err)?;
+        Ok(number.as_u64())
+    }
+
+    #[pyo3(signature = (block = BlockArg::Tag("latest".into()), full_transactions = false))]
+    fn get_block(
+        &self,
+        py: Python<'_>,
+        block: BlockArg,
+        full_transactions: bool,
+    ) -> PyResult<PyObject> {
+        let block = block.into_block_id()?;
+        if full_transactions {
+            return self.run(py, self.middleware.get_block_with_txs(block))
+        }
+        self.run(py, self.middleware.get_block(block))
+    }
+
+    fn get_transaction(&self, py: Python<'_>, hash: &str) -> PyResult<PyObject> {
+        self.run(py, self.middleware.get_transaction(parse::<H256>(hash)?))
+    }
+
+    fn get_transaction_receipt(&self, py: Python<'_>, hash: &str) -> PyResult<PyObject> {
+        self.run(py, self.middleware.get_transaction_receipt(parse::<H256>(hash)?))
+    }
+
+    /// Returns the logs between two blocks, emitted by any of `addresses` with any of `topics` as
+    /// first topic.
+    #[pyo3(signature = (from_block, to_block, addresses = vec![], topics = vec![]))]
+    fn get_logs(
+        &self,
+        py: Python<'_>,
+        from_block: BlockArg,
+        to_block: BlockArg,
+        addresses: Vec<String>,
+        topics: Vec<String>,
+    ) -> PyResult<PyObject> {
+        let mut filter = Filter::new()
+            .from_block(from_block.into_block_number()?)
+            .to_block(to_block.into_block_number()?);
+        if !addresses.is_empty() {
+            let addresses = addresses.iter().map(|address| parse::<Address>(address));
+            filter = filter.address(addresses.collect::<PyResult<Vec<_>>>()?);
+        }
+        if !topics.is_empty() {
+            let topics = topics.iter().map(|topic| parse::<H256>(topic));
+            filter = filter.topic0(topics.collect::<PyResult<Vec<_>>>()?);
+        }
+        self.run(py, self.middleware.get_logs(&filter))
+    }
+
+    /// Executes a call, given as the dict of an `eth_call` request, and returns its output as hex.
+    #[pyo3(signature = (tx, block = None))]
+    fn call(&self, py: Python<'_>, tx: &PyAny, block: Option<BlockArg>) -> PyResult<PyObject> {
+        let tx: String = py.import("json")?.call_method1("dumps", (tx,))?.extract()?;
+        let tx: TransactionRequest = serde_json::from_str(&tx).map_err(py_err)?;
+        let tx = TypedTransaction::Legacy(tx);
+        let block = block.map(BlockArg::into_block_id).transpose()?;
+        self.run(py, self.middleware.call(&tx, block))
+    }
+
+    fn trace_transaction(&self, py: Python<'_>, hash: &str) -> PyResult<PyObject> {
+        self.run(py, self.middleware.trace_transaction(parse::<H256>(hash)?))
+    }
+
+    fn trace_block(&self, py: Python<'_>, block: BlockArg) -> PyResult<PyObject> {
+        self.run(py, self.middleware.trace_block(block.into_block_number()?))
+    }
+}
+
+#[pymodule]
+fn ethers_reth(_py: Python<'_>, module: &PyModule) -> PyResult<()> {
+    module.add_class::<PyRethMiddleware>()?;
+    module.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    Ok(())
+}
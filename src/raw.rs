@@ -0,0 +1,161 @@
+use crate::{
+    instrument::{db_read, instrument, record_block, record_bytes},
+    type_conversions::ToReth,
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{BlockId as EthersBlockId, Bytes as EthersBytes, TxHash as EthersTxHash},
+};
+
+// Reth
+use reth_primitives::{BlockId, H256};
+use reth_provider::{BlockIdReader, BlockReaderIdExt, ReceiptProvider, TransactionsProvider};
+use reth_rlp::Encodable;
+use reth_transaction_pool::{PoolTransaction, TransactionPool};
+
+/// Strips the RLP string header reth wraps typed receipts in, leaving their EIP-2718 encoding.
+/// Legacy receipts are RLP lists and are returned as they are.
+fn receipt_envelope(encoded: &[u8]) -> &[u8] {
+    match encoded.first() {
+        Some(0x80..=0xb7) => &encoded[1..],
+        Some(&b @ 0xb8..=0xbf) => &encoded[1 + (b - 0xb7) as usize..],
+        _ => encoded,
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the RLP encoded header of a block, like `debug_getRawHeader`.
+    pub async fn debug_get_raw_header<T: Into<EthersBlockId> + Send + Sync>(
+        &self,
+        block: T,
+    ) -> Result<EthersBytes, RethMiddlewareError<M>> {
+        instrument("debug_get_raw_header", async {
+            self.ensure_namespace("debug_get_raw_header")?;
+            let block_id: BlockId = block.into().into_reth();
+            record_block(&block_id);
+
+            let header = db_read("header_by_id", || self.reth_provider.header_by_id(block_id))?
+                .ok_or(RethMiddlewareError::BlockNotFound)?;
+            let mut out = Vec::new();
+            header.encode(&mut out);
+            record_bytes(out.len());
+            Ok(out.into())
+        })
+        .await
+    }
+
+    /// Returns the RLP encoded block, like `debug_getRawBlock`.
+    pub async fn debug_get_raw_block<T: Into<EthersBlockId> + Send + Sync>(
+        &self,
+        block: T,
+    ) -> Result<EthersBytes, RethMiddlewareError<M>> {
+        instrument("debug_get_raw_block", async {
+            self.ensure_namespace("debug_get_raw_block")?;
+            let block_id: BlockId = block.into().into_reth();
+            record_block(&block_id);
+
+            let block = db_read("block_by_id", || self.reth_provider.block_by_id(block_id))?
+                .ok_or(RethMiddlewareError::BlockNotFound)?;
+            let mut out = Vec::new();
+            block.encode(&mut out);
+            record_bytes(out.len());
+            Ok(out.into())
+        })
+        .await
+    }
+
+    /// Returns the EIP-2718 encoded signed transaction, like `debug_getRawTransaction`.
+    pub async fn debug_get_raw_transaction(
+        &self,
+        hash: EthersTxHash,
+    ) -> Result<Option<EthersBytes>, RethMiddlewareError<M>> {
+        instrument("debug_get_raw_transaction", async {
+            self.ensure_namespace("debug_get_raw_transaction")?;
+            let raw = self.stored_raw_transaction(hash.into())?;
+            if let Some(raw) = &raw {
+                record_bytes(raw.len());
+            }
+            Ok(raw)
+        })
+        .await
+    }
+
+    /// Returns the EIP-2718 encoded signed transaction, mined or still in the pool, like
+    /// `eth_getRawTransactionByHash`.
+    pub async fn get_raw_transaction(
+        &self,
+        hash: EthersTxHash,
+    ) -> Result<Option<EthersBytes>, RethMiddlewareError<M>> {
+        instrument("get_raw_transaction", async {
+            let raw = match self.stored_raw_transaction(hash.into())? {
+                Some(raw) => Some(raw),
+                None => self.reth_pool.get(&hash.into()).map(|tx| {
+                    let mut out = Vec::new();
+                    tx.transaction
+                        .to_recovered_transaction()
+                        .into_signed()
+                        .encode_enveloped(&mut out);
+                    out.into()
+                }),
+            };
+
+            match &raw {
+                Some(raw) => record_bytes(raw.len()),
+                None => self.ensure_transaction_lookup()?,
+            }
+            Ok(raw)
+        })
+        .await
+    }
+
+    /// Reads a mined transaction and encodes it as EIP-2718 bytes.
+    fn stored_raw_transaction(
+        &self,
+        hash: H256,
+    ) -> Result<Option<EthersBytes>, RethMiddlewareError<M>> {
+        let tx = db_read("transaction_by_hash", || self.reth_provider.transaction_by_hash(hash))?;
+        Ok(tx.map(|tx| {
+            let mut out = Vec::new();
+            tx.encode_enveloped(&mut out);
+            out.into()
+        }))
+    }
+
+    /// Returns the EIP-2718 encoded receipts of a block, like `debug_getRawReceipts`.
+    pub async fn debug_get_raw_receipts<T: Into<EthersBlockId> + Send + Sync>(
+        &self,
+        block: T,
+    ) -> Result<Vec<EthersBytes>, RethMiddlewareError<M>> {
+        instrument("debug_get_raw_receipts", async {
+            self.ensure_namespace("debug_get_raw_receipts")?;
+            let block_id: BlockId = block.into().into_reth();
+            record_block(&block_id);
+
+            let number = db_read("block_number_for_id", || {
+                self.reth_provider.block_number_for_id(block_id)
+            })?
+            .ok_or(RethMiddlewareError::BlockNotFound)?;
+            self.ensure_receipts(number)?;
+
+            let receipts = db_read("receipts_by_block", || {
+                self.reth_provider.receipts_by_block(number.into())
+            })?
+            .ok_or(RethMiddlewareError::BlockNotFound)?;
+            let raw: Vec<EthersBytes> = receipts
+                .into_iter()
+                .map(|receipt| {
+                    let mut out = Vec::new();
+                    receipt.with_bloom().encode(&mut out);
+                    receipt_envelope(&out).to_vec().into()
+                })
+                .collect();
+            record_bytes(raw.iter().map(|receipt| receipt.len()).sum());
+            Ok(raw)
+        })
+        .await
+    }
+}
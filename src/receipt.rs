@@ -0,0 +1,230 @@
+use crate::{
+    execution::coinbase::tip_per_gas,
+    instrument::db_read,
+    type_conversions::{rpc::transaction::effective_gas_price, ToEthers, TryToEthers},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Log as EthersLog, TransactionReceipt as EthersTransactionReceipt, H256 as EthersH256},
+    utils::get_contract_address,
+};
+
+// Reth
+use reth_primitives::{BlockId, Receipt, H256};
+use reth_provider::{
+    BlockExecutor, ChainSpecProvider, ExecutorFactory, HeaderProvider, StateProviderFactory,
+};
+use reth_revm::Factory;
+use reth_rpc_api::EthApiServer;
+use reth_rpc_types::Transaction;
+
+// Std
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+/// The receipts of the last blocks re-executed because their receipts were pruned, shared by the
+/// clones of a middleware.
+#[derive(Debug, Clone)]
+pub(crate) struct RederivedReceipts {
+    blocks: Arc<Mutex<VecDeque<(H256, Arc<Vec<EthersTransactionReceipt>>)>>>,
+    capacity: usize,
+}
+
+impl RederivedReceipts {
+    fn get(&self, block_hash: H256) -> Option<Arc<Vec<EthersTransactionReceipt>>> {
+        let receipts = self
+            .blocks
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(hash, _)| *hash == block_hash)
+            .map(|(_, receipts)| receipts.clone());
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_cache_lookup("rederived_receipts", receipts.is_some());
+        receipts
+    }
+
+    fn insert(&self, block_hash: H256, receipts: Arc<Vec<EthersTransactionReceipt>>) {
+        let mut blocks = self.blocks.lock().unwrap();
+        blocks.push_back((block_hash, receipts));
+        while blocks.len() > self.capacity {
+            blocks.pop_front();
+        }
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Fills in the effective gas price of a receipt reth left without one, from its transaction
+    /// and the base fee of its block.
+    pub(crate) async fn with_effective_gas_price(
+        &self,
+        mut receipt: EthersTransactionReceipt,
+    ) -> Result<EthersTransactionReceipt, RethMiddlewareError<M>> {
+        if receipt.effective_gas_price.map_or(false, |price| !price.is_zero()) {
+            return Ok(receipt)
+        }
+        let Some(tx) = self.reth_api.transaction_by_hash(receipt.transaction_hash.into()).await?
+        else {
+            return Ok(receipt)
+        };
+
+        let base_fee = match receipt.block_hash {
+            Some(hash) => db_read("header", || self.reth_provider.header(&hash.into()))?
+                .and_then(|header| header.base_fee_per_gas),
+            None => None,
+        };
+        receipt.effective_gas_price =
+            Some(effective_gas_price(&tx.into_ethers(), base_fee.map(Into::into)));
+        Ok(receipt)
+    }
+
+    /// Returns the receipt of a transaction from the database, re-derived if its receipts were
+    /// pruned and re-derivation is on.
+    pub(crate) async fn local_transaction_receipt(
+        &self,
+        transaction_hash: EthersH256,
+    ) -> Result<Option<EthersTransactionReceipt>, RethMiddlewareError<M>> {
+        let hash = H256::from_slice(transaction_hash.as_bytes());
+        match self.reth_api.transaction_receipt(hash).await? {
+            #[cfg(feature = "optimism")]
+            Some(receipt) => {
+                let receipt = self.with_effective_gas_price(receipt.try_into_ethers()?).await?;
+                Ok(Some(self.with_l1_fee_fields(receipt).await?))
+            }
+            #[cfg(not(feature = "optimism"))]
+            Some(receipt) => {
+                Ok(Some(self.with_effective_gas_price(receipt.try_into_ethers()?).await?))
+            }
+            None => match self.reth_api.transaction_by_hash(hash).await? {
+                Some(tx) => {
+                    if let Some(number) = tx.block_number {
+                        if let Err(pruned) = self.ensure_receipts(number.to::<u64>()) {
+                            return self.rederived_receipt(&tx).await?.ok_or(pruned).map(Some)
+                        }
+                    }
+                    Ok(None)
+                }
+                None => {
+                    self.ensure_transaction_lookup()?;
+                    Ok(None)
+                }
+            },
+        }
+    }
+
+    /// Re-executes the blocks of transactions whose receipts the node pruned to derive their
+    /// receipts again, keeping those of the last `cached_blocks` blocks. Off by default, as each
+    /// block costs a replay.
+    pub fn with_receipt_rederivation(mut self, cached_blocks: usize) -> Self {
+        self.rederived_receipts =
+            Some(RederivedReceipts { blocks: Arc::default(), capacity: cached_blocks });
+        self
+    }
+
+    /// Returns the receipt of a mined transaction whose receipts were pruned, re-derived by
+    /// replaying its block, `None` if re-derivation is off.
+    pub(crate) async fn rederived_receipt(
+        &self,
+        tx: &Transaction,
+    ) -> Result<Option<EthersTransactionReceipt>, RethMiddlewareError<M>> {
+        let (Some(cache), Some(block_hash), Some(index)) =
+            (&self.rederived_receipts, tx.block_hash, tx.transaction_index)
+        else {
+            return Ok(None)
+        };
+
+        let receipts = match cache.get(block_hash) {
+            Some(receipts) => receipts,
+            None => {
+                let receipts = Arc::new(self.rederive_block_receipts(block_hash)?);
+                cache.insert(block_hash, receipts.clone());
+                receipts
+            }
+        };
+        let Some(receipt) = receipts.get(index.to::<usize>()).cloned() else { return Ok(None) };
+        #[cfg(feature = "optimism")]
+        let receipt = self.with_l1_fee_fields(receipt).await?;
+        Ok(Some(receipt))
+    }
+
+    /// Replays a block from its parent's state with the node's executor and builds the receipts
+    /// of its transactions, as the node would have stored them.
+    fn rederive_block_receipts(
+        &self,
+        block_hash: H256,
+    ) -> Result<Vec<EthersTransactionReceipt>, RethMiddlewareError<M>> {
+        let (block, senders) = self.sealed_block_with_senders(BlockId::Hash(block_hash.into()))?;
+        self.ensure_history(Some(BlockId::Hash(block.parent_hash.into())))?;
+        let total_difficulty = db_read("header_td", || self.reth_provider.header_td(&block_hash))?
+            .ok_or(RethMiddlewareError::BlockNotFound)?;
+        let state = db_read("history_by_block_hash", || {
+            self.reth_provider.history_by_block_hash(block.parent_hash)
+        })?;
+        let post_state = Factory::new(self.reth_provider.chain_spec())
+            .with_sp(state)
+            .execute(&block.clone().unseal(), total_difficulty, Some(senders.clone()))
+            .map_err(reth_interfaces::Error::from)?;
+
+        let base_fee = block.base_fee_per_gas.unwrap_or_default();
+        let mut cumulative_gas_used = 0;
+        let mut log_index = 0;
+        let mut receipts = Vec::with_capacity(block.body.len());
+        for (index, (receipt, (tx, sender))) in post_state
+            .receipts(block.number)
+            .iter()
+            .zip(block.body.iter().zip(&senders))
+            .enumerate()
+        {
+            let transaction_hash = tx.hash().into_ethers();
+            let logs = receipt
+                .logs
+                .iter()
+                .enumerate()
+                .map(|(transaction_log_index, log)| EthersLog {
+                    address: log.address.into_ethers(),
+                    topics: log.topics.iter().map(|topic| topic.into_ethers()).collect(),
+                    data: log.data.clone().into_ethers(),
+                    block_hash: Some(block_hash.into_ethers()),
+                    block_number: Some(block.number.into()),
+                    transaction_hash: Some(transaction_hash),
+                    transaction_index: Some(index.into()),
+                    log_index: Some((log_index + transaction_log_index).into()),
+                    transaction_log_index: Some(transaction_log_index.into()),
+                    log_type: None,
+                    removed: Some(false),
+                })
+                .collect();
+            log_index += receipt.logs.len();
+
+            let bloom = Receipt::with_bloom(receipt.clone()).bloom;
+            receipts.push(EthersTransactionReceipt {
+                transaction_hash,
+                transaction_index: index.into(),
+                block_hash: Some(block_hash.into_ethers()),
+                block_number: Some(block.number.into()),
+                from: sender.into_ethers(),
+                to: tx.to().map(ToEthers::into_ethers),
+                cumulative_gas_used: receipt.cumulative_gas_used.into(),
+                gas_used: Some((receipt.cumulative_gas_used - cumulative_gas_used).into()),
+                contract_address: tx
+                    .to()
+                    .is_none()
+                    .then(|| get_contract_address(sender.into_ethers(), tx.nonce())),
+                logs,
+                status: Some((receipt.success as u64).into()),
+                logs_bloom: bloom.into_ethers(),
+                transaction_type: Some((receipt.tx_type as u64).into()),
+                effective_gas_price: Some((base_fee as u128 + tip_per_gas(tx, base_fee)).into()),
+                ..Default::default()
+            });
+            cumulative_gas_used = receipt.cumulative_gas_used;
+        }
+        Ok(receipts)
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{RethMiddleware, RethMiddlewareError};
+use async_trait::async_trait;
+use thiserror::Error;
+
+// Ethers
+use ethers::{
+    providers::{Middleware, MiddlewareError, PendingTransaction},
+    types::{
+        transaction::{eip2718::TypedTransaction, eip2930::AccessListWithGasUsed},
+        BlockId, BlockNumber, BlockTrace, Bytes, GethDebugTracingCallOptions, GethTrace, TraceType,
+        U256,
+    },
+};
+
+// Std
+use std::collections::BTreeMap;
+
+/// Middlewares over the databases of several chains, e.g. mainnet and an L2, behind a single
+/// object.
+///
+/// Select a chain explicitly with [RethRegistry::chain]. Used as a [Middleware] itself, requests
+/// carrying a transaction are routed by the transaction's chain id, and everything else is served
+/// by the default chain.
+#[derive(Debug, Clone)]
+pub struct RethRegistry<M> {
+    default_chain: u64,
+    chains: BTreeMap<u64, RethMiddleware<M>>,
+}
+
+#[derive(Error, Debug)]
+pub enum RegistryError<M: Middleware> {
+    /// An error occured in the middleware of one of the chains.
+    #[error(transparent)]
+    MiddlewareError(RethMiddlewareError<M>),
+
+    /// No database was registered for the chain.
+    #[error("Unknown chain id {0}")]
+    UnknownChain(u64),
+}
+
+impl<M: Middleware> MiddlewareError for RegistryError<M> {
+    type Inner = RethMiddlewareError<M>;
+
+    fn from_err(e: Self::Inner) -> Self {
+        RegistryError::MiddlewareError(e)
+    }
+
+    fn as_inner(&self) -> Option<&Self::Inner> {
+        match self {
+            RegistryError::MiddlewareError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl<M> RethRegistry<M>
+where
+    M: Middleware,
+{
+    /// Creates the registry with the middleware serving requests without a chain id.
+    pub fn new(chain_id: u64, middleware: RethMiddleware<M>) -> Self {
+        Self { default_chain: chain_id, chains: BTreeMap::from([(chain_id, middleware)]) }
+    }
+
+    /// Registers the middleware of another chain.
+    pub fn with_chain(mut self, chain_id: u64, middleware: RethMiddleware<M>) -> Self {
+        self.chains.insert(chain_id, middleware);
+        self
+    }
+
+    /// Returns the middleware of the chain.
+    pub fn chain(&self, chain_id: u64) -> Option<&RethMiddleware<M>> {
+        self.chains.get(&chain_id)
+    }
+
+    /// Returns the ids of the registered chains.
+    pub fn chain_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.chains.keys().copied()
+    }
+
+    /// Returns the middleware of the chain the transaction targets.
+    fn route(&self, tx: &TypedTransaction) -> Result<&RethMiddleware<M>, RegistryError<M>> {
+        let chain_id = tx.chain_id().map_or(self.default_chain, |id| id.as_u64());
+        self.chain(chain_id).ok_or(RegistryError::UnknownChain(chain_id))
+    }
+}
+
+#[async_trait]
+impl<M> Middleware for RethRegistry<M>
+where
+    M: Middleware,
+{
+    type Error = RegistryError<M>;
+    type Provider = M::Provider;
+    type Inner = RethMiddleware<M>;
+
+    fn inner(&self) -> &RethMiddleware<M> {
+        &self.chains[&self.default_chain]
+    }
+
+    async fn fill_transaction(
+        &self,
+        tx: &mut TypedTransaction,
+        block: Option<BlockId>,
+    ) -> Result<(), Self::Error> {
+        self.route(tx)?.fill_transaction(tx, block).await.map_err(RegistryError::from_err)
+    }
+
+    async fn send_transaction<T: Into<TypedTransaction> + Send + Sync>(
+        &self,
+        tx: T,
+        block: Option<BlockId>,
+    ) -> Result<PendingTransaction<'_, Self::Provider>, Self::Error> {
+        let tx = tx.into();
+        self.route(&tx)?.send_transaction(tx, block).await.map_err(RegistryError::from_err)
+    }
+
+    async fn call(
+        &self,
+        tx: &TypedTransaction,
+        block: Option<BlockId>,
+    ) -> Result<Bytes, Self::Error> {
+        self.route(tx)?.call(tx, block).await.map_err(RegistryError::from_err)
+    }
+
+    async fn estimate_gas(
+        &self,
+        tx: &TypedTransaction,
+        block: Option<BlockId>,
+    ) -> Result<U256, Self::Error> {
+        self.route(tx)?.estimate_gas(tx, block).await.map_err(RegistryError::from_err)
+    }
+
+    async fn create_access_list(
+        &self,
+        tx: &TypedTransaction,
+        block: Option<BlockId>,
+    ) -> Result<AccessListWithGasUsed, Self::Error> {
+        self.route(tx)?.create_access_list(tx, block).await.map_err(RegistryError::from_err)
+    }
+
+    async fn trace_call<T: Into<TypedTransaction> + Send + Sync>(
+        &self,
+        req: T,
+        trace_type: Vec<TraceType>,
+        block: Option<BlockNumber>,
+    ) -> Result<BlockTrace, Self::Error> {
+        let req = req.into();
+        self.route(&req)?.trace_call(req, trace_type, block).await.map_err(RegistryError::from_err)
+    }
+
+    async fn debug_trace_call<T: Into<TypedTransaction> + Send + Sync>(
+        &self,
+        req: T,
+        block: Option<BlockId>,
+        trace_options: GethDebugTracingCallOptions,
+    ) -> Result<GethTrace, Self::Error> {
+        let req = req.into();
+        self.route(&req)?
+            .debug_trace_call(req, block, trace_options)
+            .await
+            .map_err(RegistryError::from_err)
+    }
+}
@@ -0,0 +1,346 @@
+use crate::{RethClient, RethMiddleware};
+use ethers::providers::Middleware;
+use futures::future::BoxFuture;
+
+// Reth
+use reth_primitives::{BlockNumHash, BlockNumber, SealedHeader};
+use reth_provider::{
+    BlockNumReader, CanonStateNotification, CanonStateNotifications, CanonStateSubscriptions,
+    Chain, HeaderProvider,
+};
+
+// Std
+use std::{collections::BTreeMap, future::Future, sync::Arc};
+use tokio::{sync::broadcast::error::RecvError, task::JoinHandle};
+
+/// Number of canonical headers remembered by default to detect reorgs that are not reported by the
+/// notification itself.
+pub const DEFAULT_REORG_WINDOW: usize = 128;
+
+/// Async callback invoked with every detected reorg.
+pub type ReorgCallback = Box<dyn Fn(Arc<ReorgEvent>) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// A reorg of the canonical chain.
+#[derive(Debug, Clone)]
+pub struct ReorgEvent {
+    /// Number of canonical blocks that were replaced.
+    pub depth: u64,
+    /// Last block shared by the old and the new chain.
+    pub common_ancestor: BlockNumHash,
+    /// Headers that are no longer canonical, oldest first.
+    pub old: Vec<SealedHeader>,
+    /// Headers that became canonical, oldest first.
+    pub new: Vec<SealedHeader>,
+}
+
+/// Tracks the canonical chain from reth's canonical state notifications and invokes the registered
+/// callbacks whenever blocks are reverted.
+pub struct ReorgDetector {
+    window: BTreeMap<BlockNumber, SealedHeader>,
+    max_window: usize,
+    callbacks: Vec<ReorgCallback>,
+}
+
+impl std::fmt::Debug for ReorgDetector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReorgDetector")
+            .field("tip", &self.window.values().next_back().map(|header| header.num_hash()))
+            .field("max_window", &self.max_window)
+            .field("callbacks", &self.callbacks.len())
+            .finish()
+    }
+}
+
+impl Default for ReorgDetector {
+    fn default() -> Self {
+        Self::new(DEFAULT_REORG_WINDOW)
+    }
+}
+
+impl ReorgDetector {
+    /// Creates a detector remembering up to `max_window` canonical headers.
+    pub fn new(max_window: usize) -> Self {
+        Self { window: BTreeMap::new(), max_window: max_window.max(1), callbacks: Vec::new() }
+    }
+
+    /// Registers a callback invoked with every detected reorg.
+    pub fn on_reorg<F, Fut>(&mut self, callback: F) -> &mut Self
+    where
+        F: Fn(Arc<ReorgEvent>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.callbacks.push(Box::new(move |event| Box::pin(callback(event))));
+        self
+    }
+
+    /// Returns the latest canonical header seen by the detector.
+    pub fn tip(&self) -> Option<&SealedHeader> {
+        self.window.values().next_back()
+    }
+
+    /// Updates the tracked chain with a canonical state notification, returning the reorg it
+    /// caused, if any.
+    pub fn process(&mut self, notification: &CanonStateNotification) -> Option<ReorgEvent> {
+        let reverted = notification.reverted().map(|chain| chain_headers(&chain));
+        let committed = notification.committed().map(|chain| chain_headers(&chain));
+        self.apply(reverted.unwrap_or_default(), committed.unwrap_or_default())
+    }
+
+    /// Updates the tracked chain with the reverted and committed segments, both oldest first.
+    ///
+    /// Headers of the window that are overwritten by the committed segment are treated as
+    /// reverted even if the notification did not report them.
+    pub fn apply(
+        &mut self,
+        reverted: Vec<SealedHeader>,
+        committed: Vec<SealedHeader>,
+    ) -> Option<ReorgEvent> {
+        let fork_number =
+            committed.first().or_else(|| reverted.first()).map(|header| header.number)?;
+
+        let replaced: Vec<SealedHeader> =
+            self.window.split_off(&fork_number).into_values().collect();
+        let old = if reverted.is_empty() { replaced } else { reverted };
+
+        let first = committed.first().or_else(|| old.first())?;
+        let common_ancestor =
+            BlockNumHash { number: first.number.saturating_sub(1), hash: first.parent_hash };
+
+        for header in committed.iter() {
+            self.window.insert(header.number, header.clone());
+        }
+        while self.window.len() > self.max_window {
+            self.window.pop_first();
+        }
+
+        let old: Vec<SealedHeader> =
+            old.into_iter().filter(|header| !committed.contains(header)).collect();
+        if old.is_empty() {
+            return None
+        }
+
+        Some(ReorgEvent { depth: old.len() as u64, common_ancestor, old, new: committed })
+    }
+
+    /// Updates the tracked chain with the latest canonical headers, oldest first, e.g. read from
+    /// the database after missing notifications, returning the reorg that happened meanwhile, if
+    /// any.
+    ///
+    /// Headers of the window that differ from the canonical ones, or are above the canonical tip,
+    /// are reported as reverted.
+    pub fn resync(&mut self, canonical: Vec<SealedHeader>) -> Option<ReorgEvent> {
+        let tip = canonical.last()?.number;
+        let committed: Vec<SealedHeader> = canonical
+            .into_iter()
+            .skip_while(|header| self.window.get(&header.number) == Some(header))
+            .collect();
+        if committed.is_empty() {
+            let reverted = self.window.range(tip + 1..).map(|(_, header)| header.clone()).collect();
+            return self.apply(reverted, committed)
+        }
+        self.apply(Vec::new(), committed)
+    }
+
+    /// Drives the detector with the given notifications until the channel closes.
+    ///
+    /// The notifications missed when lagging behind are not replayed, the window is cleared
+    /// instead, so a reorg spanning them goes unreported. See [Self::spawn_with_resync] to
+    /// rebuild the window from the database instead.
+    pub fn spawn(self, notifications: CanonStateNotifications) -> JoinHandle<()> {
+        self.spawn_with_resync(notifications, |_| None)
+    }
+
+    /// Drives the detector with the given notifications until the channel closes, rebuilding the
+    /// window from `canonical` after lagging behind them, see [Self::resync]. `canonical`
+    /// returns up to the given number of latest canonical headers, oldest first, `None` if they
+    /// cannot be read, in which case the window is cleared.
+    pub fn spawn_with_resync<F>(
+        mut self,
+        mut notifications: CanonStateNotifications,
+        canonical: F,
+    ) -> JoinHandle<()>
+    where
+        F: Fn(usize) -> Option<Vec<SealedHeader>> + Send + 'static,
+    {
+        tokio::task::spawn(async move {
+            loop {
+                let event = match notifications.recv().await {
+                    Ok(notification) => self.process(&notification),
+                    Err(RecvError::Lagged(_)) => match canonical(self.max_window) {
+                        Some(headers) => self.resync(headers),
+                        None => {
+                            self.window.clear();
+                            None
+                        }
+                    },
+                    Err(RecvError::Closed) => break,
+                };
+
+                if let Some(event) = event {
+                    let event = Arc::new(event);
+                    for callback in self.callbacks.iter() {
+                        callback(event.clone()).await;
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Reads up to `count` latest canonical headers, oldest first.
+fn latest_headers(provider: &RethClient, count: usize) -> Option<Vec<SealedHeader>> {
+    let tip = provider.best_block_number().ok()?;
+    let first = tip.saturating_sub(count.saturating_sub(1) as u64);
+    (first..=tip).map(|number| provider.sealed_header(number).ok().flatten()).collect()
+}
+
+/// Collects the sealed headers of a chain segment, oldest first.
+fn chain_headers(chain: &Chain) -> Vec<SealedHeader> {
+    chain.blocks().values().map(|block| block.header.clone()).collect()
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Subscribes to reth's canonical state notifications.
+    ///
+    /// When an external notification source was attached (see
+    /// [RethMiddleware::with_notification_stream]) it takes precedence over the provider's own.
+    pub fn subscribe_to_canonical_state(&self) -> CanonStateNotifications {
+        match &self.notifications {
+            Some(sender) => sender.subscribe(),
+            None => self.reth_provider.subscribe_to_canonical_state(),
+        }
+    }
+
+    /// Runs the given detector against this middleware's canonical state notifications,
+    /// rebuilding its window from the database when it lags behind them.
+    pub fn spawn_reorg_detector(&self, detector: ReorgDetector) -> JoinHandle<()> {
+        let provider = self.reth_provider.clone();
+        let task = detector.spawn_with_resync(self.subscribe_to_canonical_state(), move |count| {
+            latest_headers(&provider, count)
+        });
+        self.tasks.register(&task);
+        task
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reth_primitives::{Header, H256};
+
+    /// Headers `from..=to` of a fork, each the child of the previous one, the first a child of
+    /// `parent`.
+    fn headers(parent: H256, from: u64, to: u64, fork: u8) -> Vec<SealedHeader> {
+        let mut parent_hash = parent;
+        (from..=to)
+            .map(|number| {
+                let header = Header {
+                    number,
+                    parent_hash,
+                    extra_data: vec![fork].into(),
+                    ..Default::default()
+                }
+                .seal_slow();
+                parent_hash = header.hash();
+                header
+            })
+            .collect()
+    }
+
+    #[test]
+    fn extending_the_chain_is_no_reorg() {
+        let mut detector = ReorgDetector::default();
+        let chain = headers(H256::zero(), 1, 4, 0);
+        assert!(detector.apply(vec![], chain[..2].to_vec()).is_none());
+        assert!(detector.apply(vec![], chain[2..].to_vec()).is_none());
+        assert_eq!(detector.tip(), chain.last());
+    }
+
+    #[test]
+    fn reports_reverted_blocks() {
+        let mut detector = ReorgDetector::default();
+        let chain = headers(H256::zero(), 1, 4, 0);
+        detector.apply(vec![], chain.clone());
+
+        let fork = headers(chain[1].hash(), 3, 5, 1);
+        let event = detector.apply(chain[2..].to_vec(), fork.clone()).unwrap();
+        assert_eq!(event.depth, 2);
+        assert_eq!(event.common_ancestor, chain[1].num_hash());
+        assert_eq!(event.old, chain[2..]);
+        assert_eq!(event.new, fork);
+        assert_eq!(detector.tip(), fork.last());
+    }
+
+    #[test]
+    fn detects_overwritten_blocks_without_reverted_segment() {
+        let mut detector = ReorgDetector::default();
+        let chain = headers(H256::zero(), 1, 3, 0);
+        detector.apply(vec![], chain.clone());
+
+        let fork = headers(chain[0].hash(), 2, 2, 1);
+        let event = detector.apply(vec![], fork.clone()).unwrap();
+        assert_eq!(event.depth, 2);
+        assert_eq!(event.common_ancestor, chain[0].num_hash());
+        assert_eq!(event.old, chain[1..]);
+        assert_eq!(detector.tip(), fork.last());
+    }
+
+    #[test]
+    fn recommitting_the_same_blocks_is_no_reorg() {
+        let mut detector = ReorgDetector::default();
+        let chain = headers(H256::zero(), 1, 3, 0);
+        detector.apply(vec![], chain.clone());
+        assert!(detector.apply(vec![], chain[1..].to_vec()).is_none());
+    }
+
+    #[test]
+    fn window_is_bounded() {
+        let mut detector = ReorgDetector::new(2);
+        let chain = headers(H256::zero(), 1, 5, 0);
+        detector.apply(vec![], chain.clone());
+        assert_eq!(detector.window.len(), 2);
+        assert_eq!(detector.window.keys().copied().collect::<Vec<_>>(), vec![4, 5]);
+    }
+
+    #[test]
+    fn resync_reports_missed_reorg() {
+        let mut detector = ReorgDetector::default();
+        let chain = headers(H256::zero(), 1, 4, 0);
+        detector.apply(vec![], chain.clone());
+
+        // blocks 3 and 4 were replaced while the notifications lagged
+        let mut canonical = chain[..2].to_vec();
+        canonical.extend(headers(chain[1].hash(), 3, 5, 1));
+        let event = detector.resync(canonical.clone()).unwrap();
+        assert_eq!(event.common_ancestor, chain[1].num_hash());
+        assert_eq!(event.old, chain[2..]);
+        assert_eq!(event.new, canonical[2..]);
+        assert_eq!(detector.tip(), canonical.last());
+    }
+
+    #[test]
+    fn resync_without_reorg_extends_the_window() {
+        let mut detector = ReorgDetector::default();
+        let chain = headers(H256::zero(), 1, 6, 0);
+        detector.apply(vec![], chain[..3].to_vec());
+
+        assert!(detector.resync(chain.clone()).is_none());
+        assert_eq!(detector.tip(), chain.last());
+    }
+
+    #[test]
+    fn resync_reports_blocks_above_the_canonical_tip() {
+        let mut detector = ReorgDetector::default();
+        let chain = headers(H256::zero(), 1, 4, 0);
+        detector.apply(vec![], chain.clone());
+
+        let event = detector.resync(chain[..2].to_vec()).unwrap();
+        assert_eq!(event.depth, 2);
+        assert_eq!(event.old, chain[2..]);
+        assert!(event.new.is_empty());
+        assert_eq!(detector.tip(), chain.get(1));
+    }
+}
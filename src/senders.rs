@@ -0,0 +1,137 @@
+use crate::{instrument::db_read, RethMiddleware, RethMiddlewareError};
+use ethers::providers::Middleware;
+use rayon::prelude::*;
+
+// Reth
+use reth_primitives::{Address, TransactionSigned, TxHash, U256};
+use reth_provider::BlockReader;
+use reth_rpc::eth::error::EthApiError;
+use reth_rpc_types::{Block, BlockTransactions, Rich, Transaction};
+
+// Std
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+/// The default number of recovered senders cached.
+pub const DEFAULT_SENDER_CACHE_SIZE: usize = 100_000;
+
+#[derive(Debug, Default)]
+struct Senders {
+    by_hash: HashMap<TxHash, Address>,
+    /// Hashes in insertion order, the oldest evicted first.
+    order: VecDeque<TxHash>,
+}
+
+/// The senders recovered from transaction signatures, by transaction hash, shared by the clones
+/// of a middleware. A transaction's sender never changes, so entries stay valid across reorgs.
+#[derive(Debug, Clone)]
+pub(crate) struct SenderCache {
+    senders: Arc<Mutex<Senders>>,
+    capacity: usize,
+}
+
+impl Default for SenderCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_SENDER_CACHE_SIZE)
+    }
+}
+
+impl SenderCache {
+    fn new(capacity: usize) -> Self {
+        Self { senders: Arc::default(), capacity }
+    }
+
+    fn get(&self, hash: &TxHash) -> Option<Address> {
+        let sender = self.senders.lock().unwrap().by_hash.get(hash).copied();
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_cache_lookup("senders", sender.is_some());
+        sender
+    }
+
+    fn insert(&self, recovered: impl IntoIterator<Item = (TxHash, Address)>) {
+        let mut senders = self.senders.lock().unwrap();
+        for (hash, sender) in recovered {
+            if senders.by_hash.insert(hash, sender).is_none() {
+                senders.order.push_back(hash);
+            }
+        }
+        while senders.order.len() > self.capacity {
+            let Some(oldest) = senders.order.pop_front() else { break };
+            senders.by_hash.remove(&oldest);
+        }
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Caches the senders of up to `size` transactions, 0 to recover them on every request.
+    pub fn with_sender_cache_size(mut self, size: usize) -> Self {
+        self.senders = SenderCache::new(size);
+        self
+    }
+
+    /// Returns the senders of transactions, in order. Senders missing from the cache are
+    /// recovered from the signatures in parallel, as recovery dominates the conversion of full
+    /// blocks.
+    pub(crate) fn recover_senders(
+        &self,
+        transactions: &[TransactionSigned],
+    ) -> Result<Vec<Address>, RethMiddlewareError<M>> {
+        let cached: Vec<_> = transactions.iter().map(|tx| self.senders.get(&tx.hash())).collect();
+        let recovered = transactions
+            .par_iter()
+            .zip(&cached)
+            .map(|(tx, cached)| match cached {
+                Some(sender) => Ok(*sender),
+                None => tx.recover_signer().ok_or(EthApiError::InvalidTransactionSignature),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.senders.insert(
+            transactions
+                .iter()
+                .zip(&cached)
+                .zip(&recovered)
+                .filter(|((_, cached), _)| cached.is_none())
+                .map(|((tx, _), sender)| (tx.hash(), *sender)),
+        );
+        Ok(recovered)
+    }
+
+    /// Replaces the transaction hashes of a block with its transactions, their senders recovered
+    /// with [Self::recover_senders].
+    pub(crate) fn with_full_transactions(
+        &self,
+        block: Option<Rich<Block>>,
+    ) -> Result<Option<Rich<Block>>, RethMiddlewareError<M>> {
+        let Some(mut block) = block else { return Ok(None) };
+        let Some(hash) = block.header.hash else { return Ok(Some(block)) };
+        let Some(stored) = db_read("block_by_hash", || self.reth_provider.block_by_hash(hash))?
+        else {
+            return Ok(Some(block))
+        };
+
+        let senders = self.recover_senders(&stored.body)?;
+        let transactions = stored
+            .body
+            .into_iter()
+            .zip(senders)
+            .enumerate()
+            .map(|(index, (tx, sender))| {
+                Transaction::from_recovered_with_block_context(
+                    tx.with_signer(sender),
+                    hash,
+                    stored.header.number,
+                    stored.header.base_fee_per_gas,
+                    U256::from(index),
+                )
+            })
+            .collect();
+        block.inner.transactions = BlockTransactions::Full(transactions);
+        Ok(Some(block))
+    }
+}
@@ -0,0 +1,185 @@
+use crate::RethMiddleware;
+use ethers::providers::Middleware;
+
+// Reth
+use reth_rpc::JwtSecret;
+
+/// Authentication required from the clients of the HTTP and WebSocket server, through the
+/// `Authorization: Bearer` header. Needed before exposing the database beyond localhost.
+#[derive(Debug, Clone, Default)]
+pub enum ServerAuth {
+    /// No authentication, for endpoints bound to localhost.
+    #[default]
+    None,
+    /// JWTs signed with the shared secret, with an `iat` claim within 60 seconds of the server's
+    /// clock, as the engine API authenticates consensus clients.
+    Jwt(JwtSecret),
+    /// A static token.
+    Token(String),
+}
+
+impl ServerAuth {
+    /// Returns true if the value of an `Authorization` header is accepted.
+    pub fn authorize(&self, header: Option<&str>) -> bool {
+        let token = header.and_then(|header| header.strip_prefix("Bearer "));
+        match (self, token) {
+            (ServerAuth::None, _) => true,
+            (ServerAuth::Jwt(secret), Some(token)) => secret.validate(token.to_string()).is_ok(),
+            (ServerAuth::Token(expected), Some(token)) => {
+                constant_time_eq(expected.as_bytes(), token.as_bytes())
+            }
+            (_, None) => false,
+        }
+    }
+}
+
+/// Compares tokens in a time that does not depend on where they differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Sets the authentication required by the HTTP and WebSocket server.
+    pub fn with_server_auth(mut self, auth: ServerAuth) -> Self {
+        self.server_auth = auth;
+        self
+    }
+
+    /// Returns the authentication required by the HTTP and WebSocket server.
+    pub fn server_auth(&self) -> &ServerAuth {
+        &self.server_auth
+    }
+}
+
+#[cfg(feature = "http-server")]
+pub(crate) use layer::AuthLayer;
+
+/// Authentication as a tower layer of the HTTP server, checking every HTTP request and the upgrade
+/// request of every WebSocket connection.
+#[cfg(feature = "http-server")]
+mod layer {
+    use super::ServerAuth;
+    use futures::future::BoxFuture;
+    use hyper::{header::AUTHORIZATION, Body, Request, Response, StatusCode};
+    use std::task::{Context, Poll};
+    use tower::{Layer, Service};
+
+    #[derive(Debug, Clone)]
+    pub(crate) struct AuthLayer(pub(crate) ServerAuth);
+
+    impl<S> Layer<S> for AuthLayer {
+        type Service = Auth<S>;
+
+        fn layer(&self, inner: S) -> Self::Service {
+            Auth { inner, auth: self.0.clone() }
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub(crate) struct Auth<S> {
+        inner: S,
+        auth: ServerAuth,
+    }
+
+    impl<S> Service<Request<Body>> for Auth<S>
+    where
+        S: Service<Request<Body>, Response = Response<Body>>,
+        S::Future: Send + 'static,
+    {
+        type Response = Response<Body>;
+        type Error = S::Error;
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.inner.poll_ready(cx)
+        }
+
+        fn call(&mut self, request: Request<Body>) -> Self::Future {
+            let header = request.headers().get(AUTHORIZATION).and_then(|value| value.to_str().ok());
+            if self.auth.authorize(header) {
+                return Box::pin(self.inner.call(request))
+            }
+            let response = Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .body(Body::from("invalid or missing authorization"))
+                .expect("response is valid");
+            Box::pin(async move { Ok(response) })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const SECRET: [u8; 32] = [0x42; 32];
+
+    fn base64url(data: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+        let bits = data.iter().fold(String::new(), |bits, byte| bits + &format!("{byte:08b}"));
+        bits.as_bytes()
+            .chunks(6)
+            .map(|chunk| {
+                let index = chunk.iter().fold(0, |index, bit| index << 1 | (bit - b'0') as usize);
+                ALPHABET[index << (6 - chunk.len())] as char
+            })
+            .collect()
+    }
+
+    fn hmac_sha256(key: &[u8; 32], message: &[u8]) -> Vec<u8> {
+        let pad = |byte: u8| key.iter().chain(&[0; 32]).map(|key| key ^ byte).collect::<Vec<_>>();
+        let inner = Sha256::new().chain_update(pad(0x36)).chain_update(message).finalize();
+        Sha256::new().chain_update(pad(0x5c)).chain_update(inner).finalize().to_vec()
+    }
+
+    /// Returns an HS256 JWT issued `age` seconds ago.
+    fn jwt(secret: &[u8; 32], age: u64) -> String {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let header = base64url(br#"{"alg":"HS256","typ":"JWT"}"#);
+        let claims = base64url(format!(r#"{{"iat":{}}}"#, now - age).as_bytes());
+        let signature = base64url(&hmac_sha256(secret, format!("{header}.{claims}").as_bytes()));
+        format!("{header}.{claims}.{signature}")
+    }
+
+    fn jwt_auth() -> ServerAuth {
+        ServerAuth::Jwt(JwtSecret::from_hex(ethers::utils::hex::encode(SECRET)).unwrap())
+    }
+
+    #[test]
+    fn base64url_encodes_without_padding() {
+        assert_eq!(base64url(b"f"), "Zg");
+        assert_eq!(base64url(b"foob"), "Zm9vYg");
+        assert_eq!(base64url(&[0xfb, 0xff]), "-_8");
+    }
+
+    #[test]
+    fn fresh_jwts_signed_with_the_secret_are_accepted() {
+        let auth = jwt_auth();
+        assert!(auth.authorize(Some(&format!("Bearer {}", jwt(&SECRET, 0)))));
+
+        assert!(!auth.authorize(Some(&format!("Bearer {}", jwt(&[0x43; 32], 0)))));
+        // issued outside of the 60 seconds allowed
+        assert!(!auth.authorize(Some(&format!("Bearer {}", jwt(&SECRET, 600)))));
+        assert!(!auth.authorize(Some("Bearer not.a.jwt")));
+        assert!(!auth.authorize(Some(&format!("Basic {}", jwt(&SECRET, 0)))));
+        assert!(!auth.authorize(None));
+    }
+
+    #[test]
+    fn static_tokens_must_match_exactly() {
+        let auth = ServerAuth::Token("s3cret".to_string());
+        assert!(auth.authorize(Some("Bearer s3cret")));
+        assert!(!auth.authorize(Some("Bearer s3cre")));
+        assert!(!auth.authorize(Some("Bearer s3cret ")));
+        assert!(!auth.authorize(Some("bearer s3cret")));
+        assert!(!auth.authorize(None));
+
+        assert!(ServerAuth::None.authorize(None));
+        assert!(ServerAuth::None.authorize(Some("Bearer anything")));
+    }
+}
@@ -0,0 +1,266 @@
+//! The limits of the middleware, applied to the reth handlers it serves as they are.
+use super::rate_limit::{RawParams, RATE_LIMITED_CODE};
+use crate::{
+    limits::{is_heavy, CallLimits, ConcurrencyLimit, MethodTimeouts, TraceLimits},
+    RethMiddleware,
+};
+use ethers::providers::Middleware;
+use jsonrpsee::{
+    core::{server::rpc_module::MethodCallback, Error as RpcError},
+    types::{
+        error::{CallError, INVALID_PARAMS_CODE},
+        ErrorObject,
+    },
+    RpcModule,
+};
+use serde_json::{value::RawValue, Map, Value};
+
+// Std
+use std::{sync::Arc, time::Duration};
+
+/// Methods executing calls, whose first param is the call request.
+const CALL_METHODS: &[&str] = &["eth_call", "eth_estimateGas", "eth_createAccessList"];
+
+/// The limits of a middleware, applied to the methods of its module.
+#[derive(Debug, Clone)]
+pub(crate) struct Guard {
+    call_limits: CallLimits,
+    trace_limits: TraceLimits,
+    method_timeouts: MethodTimeouts,
+    heavy_requests: ConcurrencyLimit,
+    max_proof_keys: Option<usize>,
+}
+
+impl Guard {
+    pub(crate) fn new<M: Middleware>(middleware: &RethMiddleware<M>) -> Self {
+        Self {
+            call_limits: middleware.call_limits,
+            trace_limits: middleware.trace_limits,
+            method_timeouts: middleware.method_timeouts.clone(),
+            heavy_requests: middleware.heavy_requests.clone(),
+            max_proof_keys: middleware.max_proof_keys,
+        }
+    }
+
+    /// Returns the time budget of the method, the shortest of its call timeout and the budget of
+    /// the matching [Middleware] method.
+    fn timeout(&self, method: &str) -> Option<Duration> {
+        let call_timeout = self.call_limits.timeout.filter(|_| CALL_METHODS.contains(&method));
+        let budget = self.method_timeouts.timeout(&middleware_method(method));
+        call_timeout.into_iter().chain(budget).min()
+    }
+
+    /// Puts the methods of a module under the limits, leaving its subscriptions as they are.
+    pub(crate) fn apply(self, mut module: RpcModule<()>) -> Result<RpcModule<()>, RpcError> {
+        let inner = Arc::new(module.clone());
+        let guard = Arc::new(self);
+        let mut guarded = RpcModule::new(());
+        let names: Vec<&'static str> = module.method_names().collect();
+        for name in names {
+            let timeout = guard.timeout(name);
+            let heavy = is_heavy(&middleware_method(name));
+            if !matches!(
+                module.method(name),
+                Some(MethodCallback::Sync(_) | MethodCallback::Async(_))
+            ) || !(timeout.is_some() ||
+                heavy ||
+                CALL_METHODS.contains(&name) ||
+                is_trace(name) ||
+                name == "eth_getProof")
+            {
+                continue
+            }
+            let (inner, guard) = (inner.clone(), guard.clone());
+            guarded.register_async_method(name, move |params, _| {
+                let (inner, guard) = (inner.clone(), guard.clone());
+                async move {
+                    let params = guard.limit_params(name, params.as_str())?;
+                    let call = async {
+                        // the wait for a slot counts towards the budget
+                        let _permit =
+                            if heavy { guard.heavy_requests.acquire().await } else { None };
+                        inner.call::<_, Box<RawValue>>(name, RawParams(params)).await
+                    };
+                    let output = match timeout {
+                        Some(timeout) => {
+                            tokio::time::timeout(timeout, call).await.map_err(|_| {
+                                limit_exceeded(format!("{name} timed out after {timeout:?}"))
+                            })??
+                        }
+                        None => call.await?,
+                    };
+                    guard.limit_output(name, output)
+                }
+            })?;
+            module.remove_method(name);
+        }
+        module.merge(guarded)?;
+        Ok(module)
+    }
+
+    /// Caps the gas of call requests, turns off the struct log captures the trace limits
+    /// disable and rejects proofs of more storage keys than the proof key limit.
+    fn limit_params(&self, method: &str, params: Option<&str>) -> Result<Option<String>, RpcError> {
+        if let (Some(limit), "eth_getProof") = (self.max_proof_keys, method) {
+            let params: Value = params.map_or(Ok(Value::Null), serde_json::from_str)?;
+            let keys = params.get(1).and_then(Value::as_array).map_or(0, Vec::len);
+            if keys > limit {
+                let message = format!("at most {limit} storage keys can be proven at once");
+                return Err(RpcError::Call(CallError::Custom(ErrorObject::owned(
+                    INVALID_PARAMS_CODE,
+                    message,
+                    None::<()>,
+                ))))
+            }
+        }
+        let gas_cap = self.call_limits.gas_cap.filter(|_| CALL_METHODS.contains(&method));
+        let tracing = method.starts_with("debug_trace") &&
+            (self.trace_limits.disable_memory || self.trace_limits.disable_stack);
+        if gas_cap.is_none() && !tracing {
+            return Ok(params.map(String::from))
+        }
+
+        let mut value: Value = params.map_or(Ok(Value::Array(vec![])), serde_json::from_str)?;
+        let Some(params) = value.as_array_mut() else { return Ok(params.map(String::from)) };
+        if let Some(cap) = gas_cap {
+            if let Some(request) = params.get_mut(0).and_then(Value::as_object_mut) {
+                // gas above 64 bits is above any cap
+                let gas = request
+                    .get("gas")
+                    .and_then(Value::as_str)
+                    .and_then(|gas| u64::from_str_radix(gas.trim_start_matches("0x"), 16).ok());
+                let gas = gas.map_or(cap, |gas| gas.min(cap));
+                request.insert("gas".to_string(), Value::String(format!("{gas:#x}")));
+            }
+        }
+        if tracing {
+            // the options follow the call request and block of `debug_traceCall`, and the
+            // transaction or block of the others
+            let index = if method == "debug_traceCall" { 2 } else { 1 };
+            if params.len() <= index {
+                params.resize(index + 1, Value::Null);
+            }
+            if params[index].is_null() {
+                params[index] = Value::Object(Map::new());
+            }
+            if let Some(options) = params[index].as_object_mut() {
+                if self.trace_limits.disable_memory {
+                    options.insert("enableMemory".to_string(), Value::Bool(false));
+                }
+                if self.trace_limits.disable_stack {
+                    options.insert("disableStack".to_string(), Value::Bool(true));
+                }
+            }
+        }
+        Ok(Some(value.to_string()))
+    }
+
+    /// Fails calls returning more than the result size limit, drops the frames of traces
+    /// deeper than the depth limit and fails traces larger than the output size limit.
+    ///
+    /// The trace limits apply to the complete output of the handler, so unlike the traces of
+    /// the middleware, a served trace is built in full before it is cut or rejected.
+    fn limit_output(&self, method: &str, output: Box<RawValue>) -> Result<Box<RawValue>, RpcError> {
+        if let (Some(limit), "eth_call") = (self.call_limits.max_result_size, method) {
+            // a quoted 0x-prefixed hex string
+            let size = output.get().len().saturating_sub(4) / 2;
+            if size > limit {
+                return Err(limit_exceeded(format!(
+                    "call result of {size} bytes exceeds the limit of {limit} bytes"
+                )))
+            }
+        }
+        if !is_trace(method) {
+            return Ok(output)
+        }
+
+        let output = match self.trace_limits.max_depth {
+            Some(max_depth) => {
+                let mut trace: Value = serde_json::from_str(output.get())?;
+                truncate_trace(&mut trace, max_depth);
+                serde_json::value::to_raw_value(&trace)?
+            }
+            None => output,
+        };
+        if let Some(limit) = self.trace_limits.max_output_size {
+            let size = output.get().len();
+            if size > limit {
+                return Err(limit_exceeded(format!(
+                    "trace of {size} bytes exceeds the limit of {limit} bytes"
+                )))
+            }
+        }
+        Ok(output)
+    }
+}
+
+/// Returns the name of the [Middleware] method serving an RPC method, the budgets of
+/// [MethodTimeouts] being keyed by it, e.g. `get_logs` for `eth_getLogs` and
+/// `debug_trace_transaction` for `debug_traceTransaction`.
+fn middleware_method(method: &str) -> String {
+    let method = method.strip_prefix("eth_").unwrap_or(method);
+    let mut name = String::with_capacity(method.len() + 4);
+    for char in method.chars() {
+        if char.is_ascii_uppercase() {
+            name.push('_');
+        }
+        name.push(char.to_ascii_lowercase());
+    }
+    name
+}
+
+/// Returns true for the methods of the `trace` namespace and the `debug` tracing methods.
+fn is_trace(method: &str) -> bool {
+    method.starts_with("trace_") || method.starts_with("debug_trace")
+}
+
+/// Drops the struct logs, call frames and parity traces of a served trace deeper than
+/// `max_depth`, wherever they sit in the response.
+fn truncate_trace(trace: &mut Value, max_depth: usize) {
+    match trace {
+        Value::Array(traces) if traces.iter().any(|trace| trace.get("traceAddress").is_some()) => {
+            let depth = |trace: &Value| {
+                trace.get("traceAddress").and_then(Value::as_array).map_or(0, Vec::len)
+            };
+            traces.retain(|trace| depth(trace) < max_depth);
+            for trace in traces.iter_mut().filter(|trace| depth(trace) + 1 == max_depth) {
+                trace["subtraces"] = Value::from(0);
+            }
+        }
+        Value::Array(traces) => {
+            traces.iter_mut().for_each(|trace| truncate_trace(trace, max_depth))
+        }
+        Value::Object(trace) => {
+            if let Some(Value::Array(logs)) = trace.get_mut("structLogs") {
+                logs.retain(|log| {
+                    log.get("depth")
+                        .and_then(Value::as_u64)
+                        .map_or(true, |depth| depth as usize <= max_depth)
+                });
+            } else if trace.contains_key("calls") || trace.contains_key("gasUsed") {
+                truncate_call_frame(trace, 1, max_depth);
+            } else {
+                // the results of block traces and trace replays wrap the traces
+                trace.values_mut().for_each(|trace| truncate_trace(trace, max_depth));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Drops the calls of a call frame at `depth` made below `max_depth`.
+fn truncate_call_frame(frame: &mut Map<String, Value>, depth: usize, max_depth: usize) {
+    if depth >= max_depth {
+        frame.remove("calls");
+    } else if let Some(Value::Array(calls)) = frame.get_mut("calls") {
+        calls
+            .iter_mut()
+            .filter_map(Value::as_object_mut)
+            .for_each(|call| truncate_call_frame(call, depth + 1, max_depth));
+    }
+}
+
+/// The error of a request past a limit, with the "limit exceeded" code of the rate limits.
+fn limit_exceeded(message: String) -> RpcError {
+    RpcError::Call(CallError::Custom(ErrorObject::owned(RATE_LIMITED_CODE, message, None::<()>)))
+}
@@ -0,0 +1,63 @@
+use crate::{
+    server::{
+        auth::{AuthLayer, ServerAuth},
+        rate_limit::ConnectionRateLimitLayer,
+        ServerError,
+    },
+    RethMiddleware,
+};
+use ethers::providers::Middleware;
+use jsonrpsee::{
+    server::{ServerBuilder, ServerHandle},
+    RpcModule,
+};
+
+// Std
+use std::net::{Ipv4Addr, SocketAddr};
+use tower::ServiceBuilder;
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Serves the middleware's RPC surface over HTTP and WebSocket on `addr`, turning the database
+    /// into a read optimized RPC endpoint for external clients.
+    ///
+    /// Both transports share the same port. WebSocket clients can additionally use `eth_subscribe`,
+    /// served from the canonical state notifications and the transaction pool. Requests are
+    /// subject to the authentication and rate limits, see [Self::with_server_auth] and
+    /// [Self::with_rate_limits].
+    pub async fn serve_http_ws(&self, addr: SocketAddr) -> Result<ServerHandle, ServerError> {
+        let auth = match self.server_auth {
+            ServerAuth::None => None,
+            ref auth => Some(AuthLayer(auth.clone())),
+        };
+        let limits = &self.rate_limiter.limits;
+        let per_connection =
+            limits.per_connection.map(|quota| ConnectionRateLimitLayer::new(quota, limits.clone()));
+        let server = ServerBuilder::default()
+            .set_middleware(ServiceBuilder::new().option_layer(auth).option_layer(per_connection))
+            .build(addr)
+            .await?;
+        Ok(server.start(self.pubsub_rpc_module()?)?)
+    }
+
+    /// Serves the RPC surface on a free loopback port, returning its url.
+    ///
+    /// Tools that only fork from a url, like Anvil, can be pointed at it to fork from the database
+    /// without a round trip to a remote node. As it only listens on loopback, it requires no
+    /// authentication.
+    pub async fn serve_fork_source(&self) -> Result<(String, ServerHandle), ServerError> {
+        let server =
+            ServerBuilder::default().build(SocketAddr::from((Ipv4Addr::LOCALHOST, 0))).await?;
+        let url = format!("http://{}", server.local_addr()?);
+        Ok((url, server.start(self.pubsub_rpc_module()?)?))
+    }
+
+    /// Returns [Self::served_rpc_module] extended with `eth_subscribe`.
+    fn pubsub_rpc_module(&self) -> Result<RpcModule<()>, ServerError> {
+        let mut module = self.served_rpc_module()?;
+        module.merge(self.pubsub_module()?)?;
+        Ok(module)
+    }
+}
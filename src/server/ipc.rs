@@ -0,0 +1,34 @@
+use crate::{
+    server::{rate_limit::ConnectionRateLimitLayer, ServerError},
+    RethMiddleware,
+};
+use ethers::providers::Middleware;
+use jsonrpsee::server::ServerHandle;
+use tower::ServiceBuilder;
+
+// Reth
+use reth_ipc::server::Builder as IpcServerBuilder;
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Serves the middleware's RPC surface over a Unix socket (or named pipe on Windows) at
+    /// `endpoint`, so other processes on the machine can use the database backed handlers through
+    /// standard JSON-RPC tooling.
+    ///
+    /// Requests are subject to the global, method and per connection rate limits, see
+    /// [Self::with_rate_limits].
+    ///
+    /// The server runs until the returned handle is stopped or dropped.
+    pub async fn serve_ipc(&self, endpoint: impl AsRef<str>) -> Result<ServerHandle, ServerError> {
+        let module = self.served_rpc_module()?;
+        let limits = &self.rate_limiter.limits;
+        let per_connection =
+            limits.per_connection.map(|quota| ConnectionRateLimitLayer::new(quota, limits.clone()));
+        let server = IpcServerBuilder::default()
+            .set_middleware(ServiceBuilder::new().option_layer(per_connection))
+            .build(endpoint.as_ref().to_string())?;
+        Ok(server.start(module).await?)
+    }
+}
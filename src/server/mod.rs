@@ -0,0 +1,98 @@
+use crate::{
+    account::account_module, network::network_module, server::guard::Guard, web3::web3_module,
+    RethMiddleware,
+};
+use ethers::providers::Middleware;
+use jsonrpsee::{core::Error as RpcError, RpcModule};
+use thiserror::Error;
+
+// Reth
+use reth_network_api::noop::NoopNetwork;
+use reth_rpc::EthPubSub;
+#[cfg(feature = "debug")]
+use reth_rpc_api::DebugApiServer;
+#[cfg(feature = "trace")]
+use reth_rpc_api::TraceApiServer;
+use reth_rpc_api::{EthApiServer, EthFilterApiServer, EthPubSubApiServer};
+
+pub mod auth;
+mod guard;
+#[cfg(feature = "http-server")]
+pub mod http;
+#[cfg(feature = "ipc-server")]
+pub mod ipc;
+pub mod rate_limit;
+
+#[derive(Error, Debug)]
+pub enum ServerError {
+    /// The methods could not be registered, e.g. two modules serve the same method.
+    #[error(transparent)]
+    Rpc(#[from] RpcError),
+
+    /// The server could not bind its address or socket.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the `eth`, `trace`, `debug` and `web3` namespaces backing the middleware as a single
+    /// jsonrpsee module, ready to be served over any transport, with `eth_getAccount` on top of
+    /// reth's `eth` methods, and `net_peerCount` and the `admin` peers and node info when a network
+    /// handle is set. Disabled namespaces, and the ones
+    /// whose feature is off, are left out.
+    ///
+    /// Transaction submission is left out too, as the local pool does not broadcast. The served
+    /// calls are held to the [CallLimits](crate::limits::CallLimits) of the middleware, the
+    /// traces to its [TraceLimits](crate::limits::TraceLimits), and every method to the budget of
+    /// the matching method in its [MethodTimeouts](crate::limits::MethodTimeouts). Traces, log
+    /// scans and proofs wait for a slot of its heavy request bound.
+    pub fn rpc_module(&self) -> Result<RpcModule<()>, ServerError> {
+        let mut module = RpcModule::new(());
+        module.merge(self.reth_api.clone().into_rpc())?;
+        module.merge(self.reth_filter.clone().into_rpc())?;
+        // answered locally, the eth handlers would ask the no-op network
+        module.remove_method("eth_protocolVersion");
+        // the local pool does not broadcast, submitted transactions would be dropped silently
+        module.remove_method("eth_sendRawTransaction");
+        module.remove_method("eth_sendTransaction");
+        module.merge(web3_module()?)?;
+        module.merge(account_module(self.reth_provider.clone())?)?;
+        if let Some(network) = &self.network {
+            module.merge(network_module(network.clone())?)?;
+        }
+        #[cfg(feature = "trace")]
+        if self.namespaces.trace {
+            module.merge(self.reth_trace.clone().into_rpc())?;
+        }
+        #[cfg(feature = "debug")]
+        if self.namespaces.debug {
+            module.merge(self.reth_debug.clone().into_rpc())?;
+        }
+        Ok(Guard::new(self).apply(module)?)
+    }
+
+    /// Returns [Self::rpc_module] under the global rate limit, to be served to external clients.
+    pub(crate) fn served_rpc_module(&self) -> Result<RpcModule<()>, ServerError> {
+        Ok(self.rate_limiter.apply(self.rpc_module()?)?)
+    }
+
+    /// Returns reth's `eth_subscribe` and `eth_unsubscribe`, served from the canonical state
+    /// notifications and the transaction pool.
+    pub(crate) fn pubsub_module(&self) -> Result<RpcModule<()>, ServerError> {
+        let mut module = RpcModule::new(());
+        module.merge(
+            EthPubSub::with_spawner(
+                self.reth_provider.clone(),
+                self.reth_pool.clone(),
+                self.reth_provider.clone(),
+                NoopNetwork::default(),
+                Box::new(self.task_executor.clone()),
+            )
+            .into_rpc(),
+        )?;
+        Ok(module)
+    }
+}
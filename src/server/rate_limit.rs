@@ -0,0 +1,423 @@
+use crate::RethMiddleware;
+use ethers::providers::Middleware;
+use jsonrpsee::{
+    core::{server::rpc_module::MethodCallback, traits::ToRpcParams, Error as RpcError},
+    types::{error::CallError, ErrorObject},
+    RpcModule,
+};
+use serde::Deserialize;
+use serde_json::value::RawValue;
+
+// Std
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+/// JSON-RPC error code of rejected requests, the "limit exceeded" code of EIP-1474.
+pub const RATE_LIMITED_CODE: i32 = -32005;
+
+/// A sustained rate of request weight per second, with bursts up to `burst`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Quota {
+    pub per_second: f64,
+    pub burst: u32,
+}
+
+/// Rate limits of the served endpoints, so one heavy consumer cannot starve the other readers of
+/// the database. Disabled unless a quota is set.
+///
+/// Every call costs the weight of its method, 1 unless configured, a batch the sum of the
+/// weights of its calls, and is rejected with [RATE_LIMITED_CODE] when a quota it counts against
+/// does not have enough left. Subscriptions are not limited.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimits {
+    /// Quota shared by every client, over every transport.
+    pub global: Option<Quota>,
+    /// Quota of each HTTP and IPC connection. WebSocket connections are charged their handshake,
+    /// their calls only count against the global and method quotas.
+    pub per_connection: Option<Quota>,
+    /// Quotas of single methods, shared by every client, e.g. to cap `debug_traceTransaction`
+    /// below the global rate.
+    pub method_quotas: HashMap<String, Quota>,
+    /// Weight of the methods heavier than a single read, e.g. `trace_block`.
+    pub method_weights: HashMap<String, u32>,
+    pub default_weight: u32,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            global: None,
+            per_connection: None,
+            method_quotas: HashMap::new(),
+            method_weights: HashMap::new(),
+            default_weight: 1,
+        }
+    }
+}
+
+impl RateLimits {
+    /// Sets the weight of a method.
+    pub fn method_weight(mut self, method: impl Into<String>, weight: u32) -> Self {
+        self.method_weights.insert(method.into(), weight);
+        self
+    }
+
+    /// Sets the quota of a method.
+    pub fn method_quota(mut self, method: impl Into<String>, quota: Quota) -> Self {
+        self.method_quotas.insert(method.into(), quota);
+        self
+    }
+
+    /// Returns the weight of a method.
+    pub fn weight(&self, method: &str) -> u32 {
+        self.method_weights.get(method).copied().unwrap_or(self.default_weight)
+    }
+
+    /// Returns the weight of a raw JSON-RPC request: the weight of its method, or the sum of the
+    /// weights of the calls of a batch. Requests that do not parse cost the default weight, the
+    /// server rejects them anyway.
+    pub fn request_weight(&self, request: &[u8]) -> u32 {
+        #[derive(Deserialize)]
+        struct Call<'a> {
+            #[serde(borrow)]
+            method: Option<&'a str>,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Request<'a> {
+            #[serde(borrow)]
+            Batch(Vec<Call<'a>>),
+            #[serde(borrow)]
+            Single(Call<'a>),
+        }
+
+        let call_weight =
+            |call: &Call<'_>| call.method.map_or(self.default_weight, |method| self.weight(method));
+        match serde_json::from_slice::<Request<'_>>(request) {
+            Ok(Request::Single(call)) => call_weight(&call),
+            Ok(Request::Batch(calls)) => {
+                calls.iter().fold(0u32, |total, call| total.saturating_add(call_weight(call)))
+            }
+            Err(_) => self.default_weight,
+        }
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Sets the rate limits of the served endpoints.
+    pub fn with_rate_limits(mut self, limits: RateLimits) -> Self {
+        self.rate_limiter = RateLimiter::new(limits);
+        self
+    }
+
+    /// Returns the rate limits of the served endpoints.
+    pub fn rate_limits(&self) -> &RateLimits {
+        &self.rate_limiter.limits
+    }
+}
+
+/// Params forwarded as received.
+pub(super) struct RawParams(pub(super) Option<String>);
+
+impl ToRpcParams for RawParams {
+    fn to_rpc_params(self) -> Result<Option<Box<RawValue>>, serde_json::Error> {
+        self.0.map(RawValue::from_string).transpose()
+    }
+}
+
+fn rate_limited() -> RpcError {
+    RpcError::Call(CallError::Custom(ErrorObject::owned(
+        RATE_LIMITED_CODE,
+        "rate limit exceeded",
+        None::<()>,
+    )))
+}
+
+/// The rate limits of a middleware, with the global and method buckets shared by every server it
+/// starts.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RateLimiter {
+    pub(crate) limits: RateLimits,
+    global: Option<Arc<TokenBucket>>,
+    methods: HashMap<String, Arc<TokenBucket>>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limits: RateLimits) -> Self {
+        let global = limits.global.map(|quota| Arc::new(TokenBucket::new(quota)));
+        let methods = limits
+            .method_quotas
+            .iter()
+            .map(|(method, quota)| (method.clone(), Arc::new(TokenBucket::new(*quota))))
+            .collect();
+        Self { limits, global, methods }
+    }
+
+    /// Puts the methods of a module under the global quota and their own, leaving its
+    /// subscriptions as they are.
+    pub(crate) fn apply(&self, mut module: RpcModule<()>) -> Result<RpcModule<()>, RpcError> {
+        if self.global.is_none() && self.methods.is_empty() {
+            return Ok(module)
+        }
+
+        let inner = Arc::new(module.clone());
+        let mut limited = RpcModule::new(());
+        let names: Vec<&'static str> = module.method_names().collect();
+        for name in names {
+            if !matches!(
+                module.method(name),
+                Some(MethodCallback::Sync(_) | MethodCallback::Async(_))
+            ) {
+                continue
+            }
+            let buckets: Vec<Arc<TokenBucket>> =
+                self.global.iter().chain(self.methods.get(name)).cloned().collect();
+            if buckets.is_empty() {
+                continue
+            }
+            let (inner, weight) = (inner.clone(), self.limits.weight(name));
+            limited.register_async_method(name, move |params, _| {
+                let (inner, buckets) = (inner.clone(), buckets.clone());
+                async move {
+                    if !buckets.iter().all(|bucket| bucket.try_acquire(weight)) {
+                        return Err(rate_limited())
+                    }
+                    let params = RawParams(params.as_str().map(String::from));
+                    inner.call::<_, Box<RawValue>>(name, params).await
+                }
+            })?;
+            module.remove_method(name);
+        }
+        module.merge(limited)?;
+        Ok(module)
+    }
+}
+
+/// Token bucket refilled continuously at the quota's rate.
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+    quota: Quota,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(quota: Quota) -> Self {
+        Self { quota, state: Mutex::new((quota.burst as f64, Instant::now())) }
+    }
+
+    /// Takes `weight` tokens if that many are left.
+    pub(crate) fn try_acquire(&self, weight: u32) -> bool {
+        self.try_acquire_at(weight, Instant::now())
+    }
+
+    fn try_acquire_at(&self, weight: u32, now: Instant) -> bool {
+        let mut state = self.state.lock().expect("bucket lock is never poisoned");
+        let (tokens, refilled) = &mut *state;
+        let now = now.max(*refilled);
+        let refill = now.duration_since(*refilled).as_secs_f64() * self.quota.per_second;
+        *tokens = (*tokens + refill).min(self.quota.burst as f64);
+        *refilled = now;
+
+        if *tokens < weight as f64 {
+            return false
+        }
+        *tokens -= weight as f64;
+        true
+    }
+}
+
+#[cfg(any(feature = "http-server", feature = "ipc-server"))]
+pub(crate) use connection::ConnectionRateLimitLayer;
+
+/// The error response of a request over its quota.
+#[cfg(any(feature = "http-server", feature = "ipc-server"))]
+fn rate_limited_response() -> serde_json::Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": null,
+        "error": { "code": RATE_LIMITED_CODE, "message": "rate limit exceeded" },
+    })
+}
+
+/// Per connection limits, as a tower layer of the HTTP and IPC servers. The servers layer the
+/// service of every connection they accept, so each connection gets its own bucket.
+///
+/// Every request is charged the weight of its calls. Over HTTP, that is the body of the request,
+/// the handshake of a WebSocket connection included. Over IPC, every message of the connection.
+#[cfg(any(feature = "http-server", feature = "ipc-server"))]
+mod connection {
+    use super::{rate_limited_response, Quota, RateLimits, TokenBucket};
+    use futures::future::BoxFuture;
+    use std::{
+        sync::Arc,
+        task::{Context, Poll},
+    };
+    use tower::{Layer, Service};
+
+    #[derive(Debug, Clone)]
+    pub(crate) struct ConnectionRateLimitLayer {
+        quota: Quota,
+        limits: Arc<RateLimits>,
+    }
+
+    impl ConnectionRateLimitLayer {
+        pub(crate) fn new(quota: Quota, limits: RateLimits) -> Self {
+            Self { quota, limits: Arc::new(limits) }
+        }
+    }
+
+    impl<S> Layer<S> for ConnectionRateLimitLayer {
+        type Service = ConnectionRateLimit<S>;
+
+        fn layer(&self, inner: S) -> Self::Service {
+            ConnectionRateLimit {
+                inner,
+                bucket: Arc::new(TokenBucket::new(self.quota)),
+                limits: self.limits.clone(),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub(crate) struct ConnectionRateLimit<S> {
+        inner: S,
+        bucket: Arc<TokenBucket>,
+        limits: Arc<RateLimits>,
+    }
+
+    /// Most bytes of an HTTP body read to weigh it, the default limit of the server. Larger
+    /// bodies are rejected.
+    #[cfg(feature = "http-server")]
+    const MAX_BODY_SIZE: usize = 10 * 1024 * 1024;
+
+    #[cfg(feature = "http-server")]
+    impl<S> Service<hyper::Request<hyper::Body>> for ConnectionRateLimit<S>
+    where
+        S: Service<hyper::Request<hyper::Body>, Response = hyper::Response<hyper::Body>>
+            + Clone
+            + Send
+            + 'static,
+        S::Future: Send + 'static,
+    {
+        type Response = hyper::Response<hyper::Body>;
+        type Error = S::Error;
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.inner.poll_ready(cx)
+        }
+
+        fn call(&mut self, request: hyper::Request<hyper::Body>) -> Self::Future {
+            use hyper::{body::HttpBody, Body, Response, StatusCode};
+
+            // the ready service handles the request, a clone takes its place
+            let clone = self.inner.clone();
+            let mut inner = std::mem::replace(&mut self.inner, clone);
+            let (bucket, limits) = (self.bucket.clone(), self.limits.clone());
+            Box::pin(async move {
+                let (parts, mut body) = request.into_parts();
+                let mut bytes = Vec::new();
+                while let Some(chunk) = body.data().await {
+                    let Ok(chunk) = chunk else { break };
+                    if bytes.len() + chunk.len() > MAX_BODY_SIZE {
+                        let response = Response::builder()
+                            .status(StatusCode::PAYLOAD_TOO_LARGE)
+                            .body(Body::empty())
+                            .expect("response is valid");
+                        return Ok(response)
+                    }
+                    bytes.extend_from_slice(&chunk);
+                }
+
+                if !bucket.try_acquire(limits.request_weight(&bytes)) {
+                    let response = Response::builder()
+                        .status(StatusCode::TOO_MANY_REQUESTS)
+                        .header(hyper::header::CONTENT_TYPE, "application/json")
+                        .body(Body::from(rate_limited_response().to_string()))
+                        .expect("response is valid");
+                    return Ok(response)
+                }
+                inner.call(hyper::Request::from_parts(parts, Body::from(bytes))).await
+            })
+        }
+    }
+
+    #[cfg(feature = "ipc-server")]
+    impl<S> Service<String> for ConnectionRateLimit<S>
+    where
+        S: Service<String, Response = Option<String>>,
+        S::Future: Send + 'static,
+    {
+        type Response = Option<String>;
+        type Error = S::Error;
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.inner.poll_ready(cx)
+        }
+
+        fn call(&mut self, request: String) -> Self::Future {
+            if self.bucket.try_acquire(self.limits.request_weight(request.as_bytes())) {
+                return Box::pin(self.inner.call(request))
+            }
+            let response = rate_limited_response().to_string();
+            Box::pin(async move { Ok(Some(response)) })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn bucket_is_exhausted_by_bursts() {
+        let bucket = TokenBucket::new(Quota { per_second: 1.0, burst: 3 });
+        let now = Instant::now();
+        assert!(bucket.try_acquire_at(2, now));
+        assert!(bucket.try_acquire_at(1, now));
+        assert!(!bucket.try_acquire_at(1, now));
+        // a failed acquisition takes nothing
+        assert!(!bucket.try_acquire_at(4, now));
+    }
+
+    #[test]
+    fn bucket_refills_at_the_quota_rate_up_to_the_burst() {
+        let bucket = TokenBucket::new(Quota { per_second: 10.0, burst: 5 });
+        let start = Instant::now();
+        assert!(bucket.try_acquire_at(5, start));
+        assert!(!bucket.try_acquire_at(1, start + Duration::from_millis(50)));
+        assert!(bucket.try_acquire_at(1, start + Duration::from_millis(100)));
+
+        // idle time never refills past the burst
+        let later = start + Duration::from_secs(60);
+        assert!(bucket.try_acquire_at(5, later));
+        assert!(!bucket.try_acquire_at(1, later));
+    }
+
+    #[test]
+    fn requests_weigh_the_sum_of_their_calls() {
+        let limits = RateLimits::default().method_weight("trace_block", 50);
+        let single = br#"{"jsonrpc":"2.0","id":1,"method":"trace_block","params":["latest"]}"#;
+        assert_eq!(limits.request_weight(single), 50);
+
+        let batch = br#"[
+            {"jsonrpc":"2.0","id":1,"method":"eth_call","params":[]},
+            {"jsonrpc":"2.0","id":2,"method":"trace_block","params":[]},
+            {"jsonrpc":"2.0","id":3,"method":"eth_call","params":[]}
+        ]"#;
+        assert_eq!(limits.request_weight(batch), 52);
+        assert_eq!(limits.request_weight(b"not json"), 1);
+        assert_eq!(limits.request_weight(b""), 1);
+    }
+}
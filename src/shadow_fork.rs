@@ -0,0 +1,890 @@
+//! A long-lived shadow fork of the chain, behind the `shadow-fork` feature.
+//!
+//! [ShadowFork] starts from a pinned block of the database, accepts signed transactions over
+//! time, e.g. the ones arriving in the node's pool, and mines them into simulated blocks on
+//! demand. As a [Middleware], it serves reads of the simulated blocks and of their latest state
+//! from the session, and everything up to the pinned block from the database, so a staging
+//! environment can point at it like at a node.
+//!
+//! Only the latest simulated state is kept: state reads at older simulated blocks fail. Simulated
+//! blocks have no state root.
+use crate::{
+    database::RethDatabaseRef,
+    execution::{
+        call_output, call_tx_env, precompiles::PrecompileOverrides, snapshot::Snapshots,
+        SLOT_DURATION,
+    },
+    instrument::record_block,
+    type_conversions::{rpc::transaction::effective_gas_price, ToEthers, ToReth},
+    RethClient, RethMiddleware, RethMiddlewareError,
+};
+use async_trait::async_trait;
+use ethers::{
+    providers::{Middleware, MiddlewareError},
+    types::{
+        transaction::eip2718::TypedTransaction, Address as EthersAddress, Block as EthersBlock,
+        BlockId as EthersBlockId, BlockNumber as EthersBlockNumber, Bytes as EthersBytes,
+        Log as EthersLog, NameOrAddress, Transaction as EthersTransaction,
+        TransactionReceipt as EthersTransactionReceipt, TxHash as EthersTxHash, H256 as EthersH256,
+        U256 as EthersU256, U64 as EthersU64,
+    },
+    utils::get_contract_address,
+};
+use thiserror::Error;
+use tokio::task::JoinHandle;
+
+// Reth
+use reth_primitives::{
+    constants::EMPTY_OMMER_ROOT, proofs, Address, BlockId, Bloom, Header, Log, Receipt,
+    ReceiptWithBloom, SealedHeader, TransactionSigned, TransactionSignedEcRecovered, H256,
+};
+use reth_revm::{
+    db::CacheDB,
+    env::fill_tx_env,
+    primitives::{
+        AccountInfo, Bytecode, EVMError, ExecutionResult, InvalidTransaction, B160, B256, U256,
+    },
+    Database, DatabaseRef, EVM,
+};
+use reth_rpc::eth::error::EthApiError;
+use reth_rpc_types::Transaction;
+use reth_transaction_pool::{PoolTransaction, TransactionPool};
+
+// Std
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+/// The state at the end of the pinned block.
+///
+/// Every read opens its own database transaction, so a session kept for hours does not hold a
+/// reader open against the node.
+struct PinnedState {
+    provider: RethClient,
+    block_hash: H256,
+}
+
+impl PinnedState {
+    fn state(&self) -> reth_interfaces::Result<RethDatabaseRef<'_>> {
+        RethDatabaseRef::at_block(&self.provider, self.block_hash)
+    }
+}
+
+impl DatabaseRef for PinnedState {
+    type Error = reth_interfaces::Error;
+
+    fn basic(&self, address: B160) -> Result<Option<AccountInfo>, Self::Error> {
+        DatabaseRef::basic(&self.state()?, address)
+    }
+
+    fn code_by_hash(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        DatabaseRef::code_by_hash(&self.state()?, code_hash)
+    }
+
+    fn storage(&self, address: B160, index: U256) -> Result<U256, Self::Error> {
+        DatabaseRef::storage(&self.state()?, address, index)
+    }
+
+    fn block_hash(&self, number: U256) -> Result<B256, Self::Error> {
+        DatabaseRef::block_hash(&self.state()?, number)
+    }
+}
+
+/// A block mined by a shadow fork.
+#[derive(Debug, Clone)]
+pub struct ShadowBlock {
+    pub header: SealedHeader,
+    pub transactions: Vec<TransactionSignedEcRecovered>,
+    pub receipts: Vec<EthersTransactionReceipt>,
+}
+
+impl ShadowBlock {
+    /// Returns the transaction at `index`, with its block context.
+    pub fn transaction(&self, index: usize) -> Option<EthersTransaction> {
+        let tx = self.transactions.get(index)?;
+        Some(
+            Transaction::from_recovered_with_block_context(
+                tx.clone(),
+                self.header.hash(),
+                self.header.number,
+                self.header.base_fee_per_gas,
+                U256::from(index),
+            )
+            .into_ethers(),
+        )
+    }
+
+    /// Returns the block with its transactions as `transactions`.
+    pub fn to_ethers<TX>(&self, transactions: Vec<TX>) -> EthersBlock<TX> {
+        let header = &self.header;
+        EthersBlock {
+            hash: Some(header.hash().into_ethers()),
+            parent_hash: header.parent_hash.into_ethers(),
+            uncles_hash: header.ommers_hash.into_ethers(),
+            author: Some(header.beneficiary.into_ethers()),
+            transactions_root: header.transactions_root.into_ethers(),
+            receipts_root: header.receipts_root.into_ethers(),
+            number: Some(header.number.into()),
+            gas_used: header.gas_used.into(),
+            gas_limit: header.gas_limit.into(),
+            logs_bloom: Some(header.logs_bloom.into_ethers()),
+            timestamp: header.timestamp.into(),
+            mix_hash: Some(header.mix_hash.into_ethers()),
+            base_fee_per_gas: header.base_fee_per_gas.map(Into::into),
+            transactions,
+            ..Default::default()
+        }
+    }
+}
+
+/// Why a shadow fork could not serve a request.
+#[derive(Error, Debug)]
+pub enum ShadowForkError<M: Middleware> {
+    /// An error occured in the middleware, or executing on the fork.
+    #[error(transparent)]
+    MiddlewareError(RethMiddlewareError<M>),
+
+    /// The state at a simulated block other than the latest was requested.
+    #[error("State of simulated block {0} is not kept, only the latest one")]
+    StateNotKept(u64),
+}
+
+impl<M: Middleware> MiddlewareError for ShadowForkError<M> {
+    type Inner = RethMiddlewareError<M>;
+
+    fn from_err(e: Self::Inner) -> Self {
+        ShadowForkError::MiddlewareError(e)
+    }
+
+    fn as_inner(&self) -> Option<&Self::Inner> {
+        match self {
+            ShadowForkError::MiddlewareError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl<M: Middleware> From<EthApiError> for ShadowForkError<M> {
+    fn from(err: EthApiError) -> Self {
+        ShadowForkError::MiddlewareError(err.into())
+    }
+}
+
+/// Where a request for a block is served from.
+enum Target {
+    /// The database, at or before the pinned block.
+    Database(EthersBlockId),
+    /// The latest state of the session.
+    Latest,
+    /// A simulated block before the latest one.
+    Simulated(u64),
+}
+
+struct ShadowState {
+    evm: EVM<CacheDB<PinnedState>>,
+    head: SealedHeader,
+    pending: Vec<TransactionSignedEcRecovered>,
+    blocks: Vec<ShadowBlock>,
+    /// Block and index of every mined transaction.
+    transactions: HashMap<H256, (usize, usize)>,
+    snapshots: Snapshots<ChainSnapshot>,
+    impersonated: HashSet<EthersAddress>,
+}
+
+/// The simulated chain of a snapshot of the session.
+struct ChainSnapshot {
+    head: SealedHeader,
+    pending: Vec<TransactionSignedEcRecovered>,
+    blocks: Vec<ShadowBlock>,
+    transactions: HashMap<H256, (usize, usize)>,
+}
+
+/// A shadow fork of the chain, see the [module docs](self). Clones share the session.
+#[derive(Clone)]
+pub struct ShadowFork<M> {
+    middleware: RethMiddleware<M>,
+    fork_block: u64,
+    precompiles: PrecompileOverrides,
+    gas_cap: u64,
+    state: Arc<Mutex<ShadowState>>,
+}
+
+impl<M: Middleware> std::fmt::Debug for ShadowFork<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShadowFork")
+            .field("fork_block", &self.fork_block)
+            .field("block_number", &self.lock().head.number)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<M> ShadowFork<M>
+where
+    M: Middleware,
+{
+    /// Number of the block the session forked from.
+    pub fn fork_block(&self) -> u64 {
+        self.fork_block
+    }
+
+    /// Number of the latest simulated block, the pinned block before any is mined.
+    pub fn block_number(&self) -> u64 {
+        self.lock().head.number
+    }
+
+    /// Number of transactions waiting for the next block.
+    pub fn pending_count(&self) -> usize {
+        self.lock().pending.len()
+    }
+
+    /// Sets the timestamp of the next simulated block, like `vm.warp`. The blocks after it follow
+    /// it by [SLOT_DURATION] seconds.
+    pub fn warp(&self, timestamp: u64) {
+        self.lock().evm.env.block.timestamp = U256::from(timestamp);
+    }
+
+    /// Sets the number of the next simulated block, like `vm.roll`. It must be after the latest
+    /// one, so blocks keep resolving by number.
+    pub fn roll(&self, number: u64) -> Result<(), EthApiError> {
+        let mut state = self.lock();
+        if number <= state.head.number {
+            return Err(EthApiError::InvalidParams(format!(
+                "block {number} is not after the latest simulated block {}",
+                state.head.number
+            )))
+        }
+        state.evm.env.block.number = U256::from(number);
+        Ok(())
+    }
+
+    /// Sets the base fee of the next simulated block, like `vm.fee`.
+    pub fn set_base_fee(&self, base_fee: EthersU256) {
+        self.lock().evm.env.block.basefee = base_fee.into_reth();
+    }
+
+    /// Sets the beneficiary of the simulated blocks, like `vm.coinbase`, until changed again.
+    pub fn set_coinbase(&self, coinbase: EthersAddress) {
+        self.lock().evm.env.block.coinbase = B160::from(coinbase.0);
+    }
+
+    /// Sets the randomness of the simulated blocks, like `vm.prevrandao`, until changed again.
+    pub fn set_prevrandao(&self, prevrandao: EthersH256) {
+        self.lock().evm.env.block.prevrandao = Some(B256::from(prevrandao.0));
+    }
+
+    /// Queues a raw signed transaction for the next block.
+    pub fn submit_raw_transaction(&self, raw: EthersBytes) -> Result<EthersTxHash, EthApiError> {
+        let tx = TransactionSigned::decode_enveloped(raw.0)
+            .map_err(|_| EthApiError::FailedToDecodeSignedTransaction)?
+            .into_ecrecovered()
+            .ok_or(EthApiError::InvalidTransactionSignature)?;
+        let hash = tx.hash().into_ethers();
+        self.submit(tx);
+        Ok(hash)
+    }
+
+    /// Queues a transaction for the next block.
+    pub fn submit(&self, tx: TransactionSignedEcRecovered) {
+        self.lock().pending.push(tx);
+    }
+
+    /// Lets `address` queue unsigned transactions with [ShadowFork::submit_impersonated], like
+    /// `anvil_impersonateAccount`.
+    pub fn impersonate(&self, address: EthersAddress) {
+        self.lock().impersonated.insert(address);
+    }
+
+    /// Stops letting `address` queue unsigned transactions.
+    pub fn stop_impersonating(&self, address: EthersAddress) {
+        self.lock().impersonated.remove(&address);
+    }
+
+    /// Queues an unsigned transaction of an impersonated account for the next block. A missing
+    /// nonce, gas limit, gas price or chain id is filled from the session.
+    ///
+    /// The transaction carries a placeholder signature, which only the session accepts, and its
+    /// hash is the hash of its encoding with it.
+    pub fn submit_impersonated(
+        &self,
+        mut tx: TypedTransaction,
+    ) -> Result<EthersTxHash, EthApiError> {
+        let from = tx
+            .from()
+            .copied()
+            .ok_or_else(|| EthApiError::InvalidParams("transaction has no sender".to_string()))?;
+        let sender = Address::from(from.0);
+        {
+            let mut state = self.lock();
+            if !state.impersonated.contains(&from) {
+                return Err(EthApiError::InvalidParams(format!("{from:?} is not impersonated")))
+            }
+            if tx.nonce().is_none() {
+                // after the sender's transactions already queued
+                let queued = state.pending.iter().filter(|tx| tx.signer() == sender).count();
+                let db = state.evm.db.as_mut().expect("database is set");
+                let nonce = Database::basic(db, B160::from(from.0))?
+                    .map(|account| account.nonce)
+                    .unwrap_or_default();
+                tx.set_nonce(nonce + queued as u64);
+            }
+            if tx.gas().is_none() {
+                tx.set_gas(self.gas_cap);
+            }
+            if tx.gas_price().is_none() {
+                tx.set_gas_price(EthersU256::from(state.evm.env.block.basefee));
+            }
+            if tx.chain_id().is_none() {
+                tx.set_chain_id(state.evm.env.cfg.chain_id.to::<u64>());
+            }
+        }
+
+        let signature = EthersSignature { r: 1.into(), s: 1.into(), v: 27 };
+        let signed = TransactionSigned::decode_enveloped(tx.rlp_signed(&signature).0)
+            .map_err(|_| EthApiError::FailedToDecodeSignedTransaction)?;
+        let tx = TransactionSignedEcRecovered::from_signed_transaction(signed, sender);
+        let hash = tx.hash().into_ethers();
+        self.submit(tx);
+        Ok(hash)
+    }
+
+    /// Mines the queued transactions, in the order they were submitted, into the next block,
+    /// [SLOT_DURATION] seconds after the latest one.
+    ///
+    /// Transactions that do not fit in the block, or whose nonce is ahead of their sender's,
+    /// wait for the next one. Transactions that cannot apply, e.g. already replaced, are dropped.
+    pub fn advance(&self) -> Result<ShadowBlock, EthApiError> {
+        let mut guard = self.lock();
+        let state = &mut *guard;
+        let gas_limit = state.evm.env.block.gas_limit.to::<u64>();
+        let base_fee = state.evm.env.block.basefee.to::<u64>();
+
+        let mut included = vec![];
+        let mut receipts = vec![];
+        let mut gas_used = vec![];
+        let mut waiting = vec![];
+        let mut cumulative_gas_used = 0;
+        for tx in std::mem::take(&mut state.pending) {
+            if cumulative_gas_used + tx.gas_limit() > gas_limit {
+                waiting.push(tx);
+                continue
+            }
+            fill_tx_env(&mut state.evm.env.tx, &tx.clone().into_signed(), tx.signer());
+            let result = match self.precompiles.transact_commit(&mut state.evm) {
+                Ok(result) => result,
+                Err(EVMError::Transaction(InvalidTransaction::NonceTooHigh { .. })) => {
+                    waiting.push(tx);
+                    continue
+                }
+                Err(EVMError::Database(err)) => return Err(err.into()),
+                Err(_) => continue,
+            };
+
+            cumulative_gas_used += result.gas_used();
+            gas_used.push(result.gas_used());
+            receipts.push(consensus_receipt(&tx, result, cumulative_gas_used));
+            included.push(tx);
+        }
+        state.pending = waiting;
+
+        let block_env = &state.evm.env.block;
+        let signed: Vec<TransactionSigned> =
+            included.iter().map(|tx| tx.clone().into_signed()).collect();
+        let header = Header {
+            parent_hash: state.head.hash(),
+            ommers_hash: EMPTY_OMMER_ROOT,
+            beneficiary: Address::from(block_env.coinbase.0),
+            transactions_root: proofs::calculate_transaction_root(&signed),
+            receipts_root: proofs::calculate_receipt_root(&receipts),
+            logs_bloom: receipts.iter().fold(Bloom::zero(), |bloom, receipt| bloom | receipt.bloom),
+            number: block_env.number.to::<u64>(),
+            gas_limit,
+            gas_used: cumulative_gas_used,
+            timestamp: block_env.timestamp.to::<u64>(),
+            mix_hash: block_env.prevrandao.map(|randao| H256::from(randao.0)).unwrap_or_default(),
+            base_fee_per_gas: Some(base_fee),
+            ..Default::default()
+        }
+        .seal_slow();
+
+        let mut block = ShadowBlock { header, transactions: included, receipts: vec![] };
+        let mut log_index = 0;
+        for (index, (receipt, gas_used)) in receipts.iter().zip(gas_used).enumerate() {
+            let receipt = ethers_receipt(&block, index, receipt, gas_used, log_index);
+            log_index += receipt.logs.len();
+            block.receipts.push(receipt);
+        }
+
+        // the next block follows the mined one
+        let env = &mut state.evm.env.block;
+        env.number = U256::from(block.header.number + 1);
+        env.timestamp = U256::from(block.header.timestamp + SLOT_DURATION);
+        env.basefee = U256::from(block.header.next_block_base_fee().unwrap_or_default());
+        let db = state.evm.db.as_mut().expect("database is set");
+        db.block_hashes.insert(U256::from(block.header.number), B256::from(block.header.hash().0));
+
+        let number = state.blocks.len();
+        for (index, tx) in block.transactions.iter().enumerate() {
+            state.transactions.insert(tx.hash(), (number, index));
+        }
+        state.head = block.header.clone();
+        state.blocks.push(block.clone());
+        Ok(block)
+    }
+
+    /// Snapshots the state, the simulated blocks and the queued transactions of the session, like
+    /// `evm_snapshot`. Returns the id to revert to.
+    pub fn snapshot(&self) -> u64 {
+        let mut guard = self.lock();
+        let state = &mut *guard;
+        let chain = ChainSnapshot {
+            head: state.head.clone(),
+            pending: state.pending.clone(),
+            blocks: state.blocks.clone(),
+            transactions: state.transactions.clone(),
+        };
+        let db = state.evm.db.as_ref().expect("database is set");
+        state.snapshots.take(db, &state.evm.env.block, chain)
+    }
+
+    /// Reverts the session to a snapshot, like `evm_revert`: the snapshot and the ones taken
+    /// after it are dropped. Returns false if there is no such snapshot.
+    pub fn revert(&self, id: u64) -> bool {
+        let mut guard = self.lock();
+        let state = &mut *guard;
+        let db = state.evm.db.as_mut().expect("database is set");
+        let Some(chain) = state.snapshots.revert(id, db, &mut state.evm.env.block) else {
+            return false
+        };
+        state.head = chain.head;
+        state.pending = chain.pending;
+        state.blocks = chain.blocks;
+        state.transactions = chain.transactions;
+        true
+    }
+
+    /// Returns the simulated block with the given number.
+    pub fn block(&self, number: u64) -> Option<ShadowBlock> {
+        // numbers may skip ahead after a roll
+        self.lock().blocks.iter().find(|block| block.header.number == number).cloned()
+    }
+
+    /// Executes a call on the latest state of the session, in the next block, without keeping
+    /// its changes.
+    pub fn call_latest(&self, tx: &TypedTransaction) -> Result<EthersBytes, EthApiError> {
+        Ok(call_output(self.execute(tx)?)?)
+    }
+
+    fn execute(&self, tx: &TypedTransaction) -> Result<ExecutionResult, EthApiError> {
+        let mut state = self.lock();
+        let base_fee = state.evm.env.block.basefee;
+        if tx.gas_price().is_none() {
+            // unpriced calls are not charged, as with `eth_call`
+            state.evm.env.block.basefee = U256::ZERO;
+        }
+        state.evm.env.tx = call_tx_env(tx, self.gas_cap);
+        let result = self.precompiles.transact(&mut state.evm);
+        state.evm.env.block.basefee = base_fee;
+        Ok(result?.result)
+    }
+
+    fn account(&self, address: EthersAddress) -> Result<Option<AccountInfo>, EthApiError> {
+        let mut state = self.lock();
+        let db = state.evm.db.as_mut().expect("database is set");
+        Ok(Database::basic(db, B160::from(address.0))?)
+    }
+
+    fn storage(&self, address: EthersAddress, slot: EthersH256) -> Result<EthersH256, EthApiError> {
+        let mut state = self.lock();
+        let db = state.evm.db.as_mut().expect("database is set");
+        let value = Database::storage(db, B160::from(address.0), U256::from_be_bytes(slot.0))?;
+        Ok(EthersH256(value.to_be_bytes()))
+    }
+
+    fn mined(&self, hash: EthersTxHash) -> Option<(ShadowBlock, usize)> {
+        let state = self.lock();
+        let (block, index) = *state.transactions.get(&hash.into_reth())?;
+        Some((state.blocks[block].clone(), index))
+    }
+
+    fn target(&self, block: Option<EthersBlockId>) -> Target {
+        let state = self.lock();
+        let number = match block {
+            None |
+            Some(EthersBlockId::Number(EthersBlockNumber::Latest | EthersBlockNumber::Pending)) => {
+                return Target::Latest
+            }
+            Some(EthersBlockId::Number(EthersBlockNumber::Number(number))) => number.as_u64(),
+            Some(EthersBlockId::Hash(hash)) => {
+                match state.blocks.iter().find(|block| block.header.hash() == hash.into_reth()) {
+                    Some(block) => block.header.number,
+                    None => return Target::Database(EthersBlockId::Hash(hash)),
+                }
+            }
+            Some(block) => return Target::Database(block),
+        };
+
+        if number == state.head.number {
+            Target::Latest
+        } else if number <= self.fork_block {
+            Target::Database(number.into())
+        } else {
+            Target::Simulated(number)
+        }
+    }
+
+    /// Resolves a block to a simulated block, or to a block of the database.
+    fn block_target(&self, block: EthersBlockId) -> Result<Option<ShadowBlock>, EthersBlockId> {
+        let number = match self.target(Some(block)) {
+            Target::Database(block) => return Err(block),
+            Target::Latest => self.block_number(),
+            Target::Simulated(number) => number,
+        };
+        if number <= self.fork_block {
+            return Err(number.into())
+        }
+        Ok(self.block(number))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ShadowState> {
+        // a panic while mining leaves the state as consistent as an aborted block
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<M> ShadowFork<M>
+where
+    M: Middleware + Clone + 'static,
+{
+    /// Queues the transactions becoming pending in the node's pool as they arrive, until the
+    /// returned task is aborted.
+    pub fn follow_mempool(&self) -> JoinHandle<()> {
+        let session = self.clone();
+        let mut listener = self.middleware.reth_pool.pending_transactions_listener();
+        let task = tokio::task::spawn(async move {
+            while let Some(hash) = listener.recv().await {
+                // transactions already gone from the pool are missed
+                if let Some(tx) = session.middleware.reth_pool.get(&hash) {
+                    session.submit(tx.transaction.to_recovered_transaction());
+                }
+            }
+        });
+        self.middleware.tasks.register(&task);
+        task
+    }
+}
+
+/// Returns the receipt of a mined transaction, for the receipts root.
+fn consensus_receipt(
+    tx: &TransactionSignedEcRecovered,
+    result: ExecutionResult,
+    cumulative_gas_used: u64,
+) -> ReceiptWithBloom {
+    let success = result.is_success();
+    let logs = match result {
+        ExecutionResult::Success { logs, .. } => logs
+            .into_iter()
+            .map(|log| Log {
+                address: Address::from(log.address.0),
+                topics: log.topics.into_iter().map(|topic| H256::from(topic.0)).collect(),
+                data: log.data.into(),
+            })
+            .collect(),
+        _ => vec![],
+    };
+    Receipt { tx_type: tx.tx_type(), success, cumulative_gas_used, logs }.with_bloom()
+}
+
+/// Returns the receipt of the transaction at `index` of a mined block, in the format of
+/// `eth_getTransactionReceipt`.
+fn ethers_receipt(
+    block: &ShadowBlock,
+    index: usize,
+    receipt: &ReceiptWithBloom,
+    gas_used: u64,
+    first_log_index: usize,
+) -> EthersTransactionReceipt {
+    let tx = &block.transactions[index];
+    let block_hash = block.header.hash().into_ethers();
+    let transaction_hash = tx.hash().into_ethers();
+    let from = tx.signer().into_ethers();
+    let logs = receipt
+        .receipt
+        .logs
+        .iter()
+        .enumerate()
+        .map(|(log_index, log)| EthersLog {
+            address: log.address.into_ethers(),
+            topics: log.topics.iter().map(|topic| topic.into_ethers()).collect(),
+            data: log.data.clone().into_ethers(),
+            block_hash: Some(block_hash),
+            block_number: Some(block.header.number.into()),
+            transaction_hash: Some(transaction_hash),
+            transaction_index: Some((index as u64).into()),
+            log_index: Some((first_log_index + log_index).into()),
+            transaction_log_index: Some(log_index.into()),
+            removed: Some(false),
+            ..Default::default()
+        })
+        .collect();
+    let price = block
+        .transaction(index)
+        .map(|tx| effective_gas_price(&tx, block.header.base_fee_per_gas.map(Into::into)));
+
+    EthersTransactionReceipt {
+        transaction_hash,
+        transaction_index: (index as u64).into(),
+        block_hash: Some(block_hash),
+        block_number: Some(block.header.number.into()),
+        from,
+        to: tx.to().map(|to| to.into_ethers()),
+        cumulative_gas_used: receipt.receipt.cumulative_gas_used.into(),
+        gas_used: Some(gas_used.into()),
+        contract_address: tx.to().is_none().then(|| get_contract_address(from, tx.nonce())),
+        logs,
+        status: Some((receipt.receipt.success as u64).into()),
+        logs_bloom: receipt.bloom.into_ethers(),
+        effective_gas_price: price,
+        transaction_type: Some((tx.tx_type() as u8 as u64).into()),
+        ..Default::default()
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware + Clone,
+{
+    /// Starts a shadow fork of the chain at `block`, see [ShadowFork]. Transactions execute
+    /// under the middleware's precompile overrides and call gas cap.
+    pub fn shadow_fork<T: Into<EthersBlockId>>(
+        &self,
+        block: T,
+    ) -> Result<ShadowFork<M>, RethMiddlewareError<M>> {
+        let block_id: BlockId = block.into().into_reth();
+        record_block(&block_id);
+        self.ensure_history(Some(block_id))?;
+        self.ensure_forkchoice(Some(block_id))?;
+
+        let (block, _) = self.sealed_block_with_senders(block_id)?;
+        let mut env = self.block_env(&block)?;
+        env.block.number = U256::from(block.number + 1);
+        env.block.timestamp = U256::from(block.timestamp + SLOT_DURATION);
+        env.block.basefee = U256::from(block.next_block_base_fee().unwrap_or_default());
+        let gas_cap = self.gas_cap(block.gas_limit);
+
+        let state = PinnedState { provider: self.reth_provider.clone(), block_hash: block.hash() };
+        let mut evm = EVM::with_env(env);
+        evm.database(CacheDB::new(state));
+        let fork_block = block.number;
+        let head = block.header;
+
+        Ok(ShadowFork {
+            middleware: self.clone(),
+            fork_block,
+            precompiles: self.precompile_overrides.clone(),
+            gas_cap,
+            state: Arc::new(Mutex::new(ShadowState {
+                evm,
+                head,
+                pending: vec![],
+                blocks: vec![],
+                transactions: HashMap::new(),
+                snapshots: Snapshots::default(),
+                impersonated: HashSet::new(),
+            })),
+        })
+    }
+}
+
+#[async_trait]
+impl<M> Middleware for ShadowFork<M>
+where
+    M: Middleware + Clone,
+{
+    type Error = ShadowForkError<M>;
+    type Provider = M::Provider;
+    type Inner = RethMiddleware<M>;
+
+    fn inner(&self) -> &RethMiddleware<M> {
+        &self.middleware
+    }
+
+    async fn get_block_number(&self) -> Result<EthersU64, Self::Error> {
+        Ok(self.block_number().into())
+    }
+
+    async fn get_block<T: Into<EthersBlockId> + Send + Sync>(
+        &self,
+        block: T,
+    ) -> Result<Option<EthersBlock<EthersTxHash>>, Self::Error> {
+        match self.block_target(block.into()) {
+            Ok(block) => Ok(block.map(|block| {
+                let hashes = block.transactions.iter().map(|tx| tx.hash().into_ethers()).collect();
+                block.to_ethers(hashes)
+            })),
+            Err(block) => self.middleware.get_block(block).await.map_err(MiddlewareError::from_err),
+        }
+    }
+
+    async fn get_block_with_txs<T: Into<EthersBlockId> + Send + Sync>(
+        &self,
+        block: T,
+    ) -> Result<Option<EthersBlock<EthersTransaction>>, Self::Error> {
+        match self.block_target(block.into()) {
+            Ok(block) => Ok(block.map(|block| {
+                let transactions = (0..block.transactions.len())
+                    .filter_map(|index| block.transaction(index))
+                    .collect();
+                block.to_ethers(transactions)
+            })),
+            Err(block) => {
+                self.middleware.get_block_with_txs(block).await.map_err(MiddlewareError::from_err)
+            }
+        }
+    }
+
+    async fn get_transaction<T: Send + Sync + Into<EthersTxHash>>(
+        &self,
+        hash: T,
+    ) -> Result<Option<EthersTransaction>, Self::Error> {
+        let hash = hash.into();
+        match self.mined(hash) {
+            Some((block, index)) => Ok(block.transaction(index)),
+            None => self.middleware.get_transaction(hash).await.map_err(MiddlewareError::from_err),
+        }
+    }
+
+    async fn get_transaction_receipt<T: Send + Sync + Into<EthersTxHash>>(
+        &self,
+        hash: T,
+    ) -> Result<Option<EthersTransactionReceipt>, Self::Error> {
+        let hash = hash.into();
+        match self.mined(hash) {
+            Some((block, index)) => Ok(block.receipts.get(index).cloned()),
+            None => self
+                .middleware
+                .get_transaction_receipt(hash)
+                .await
+                .map_err(MiddlewareError::from_err),
+        }
+    }
+
+    async fn get_balance<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        from: T,
+        block: Option<EthersBlockId>,
+    ) -> Result<EthersU256, Self::Error> {
+        match self.target(block) {
+            Target::Database(block) => self
+                .middleware
+                .get_balance(from, Some(block))
+                .await
+                .map_err(MiddlewareError::from_err),
+            Target::Simulated(number) => Err(ShadowForkError::StateNotKept(number)),
+            Target::Latest => {
+                let address =
+                    self.middleware.get_address(from).await.map_err(MiddlewareError::from_err)?;
+                let account = self.account(address)?;
+                Ok(account.map(|account| account.balance).unwrap_or_default().into())
+            }
+        }
+    }
+
+    async fn get_transaction_count<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        from: T,
+        block: Option<EthersBlockId>,
+    ) -> Result<EthersU256, Self::Error> {
+        match self.target(block) {
+            Target::Database(block) => self
+                .middleware
+                .get_transaction_count(from, Some(block))
+                .await
+                .map_err(MiddlewareError::from_err),
+            Target::Simulated(number) => Err(ShadowForkError::StateNotKept(number)),
+            Target::Latest => {
+                let address =
+                    self.middleware.get_address(from).await.map_err(MiddlewareError::from_err)?;
+                let account = self.account(address)?;
+                Ok(account.map(|account| account.nonce).unwrap_or_default().into())
+            }
+        }
+    }
+
+    async fn get_code<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        at: T,
+        block: Option<EthersBlockId>,
+    ) -> Result<EthersBytes, Self::Error> {
+        match self.target(block) {
+            Target::Database(block) => {
+                self.middleware.get_code(at, Some(block)).await.map_err(MiddlewareError::from_err)
+            }
+            Target::Simulated(number) => Err(ShadowForkError::StateNotKept(number)),
+            Target::Latest => {
+                let address =
+                    self.middleware.get_address(at).await.map_err(MiddlewareError::from_err)?;
+                let code = self.account(address)?.and_then(|account| account.code);
+                Ok(code.map(|code| code.original_bytes().into()).unwrap_or_default())
+            }
+        }
+    }
+
+    async fn get_storage_at<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        from: T,
+        location: EthersH256,
+        block: Option<EthersBlockId>,
+    ) -> Result<EthersH256, Self::Error> {
+        match self.target(block) {
+            Target::Database(block) => self
+                .middleware
+                .get_storage_at(from, location, Some(block))
+                .await
+                .map_err(MiddlewareError::from_err),
+            Target::Simulated(number) => Err(ShadowForkError::StateNotKept(number)),
+            Target::Latest => {
+                let address =
+                    self.middleware.get_address(from).await.map_err(MiddlewareError::from_err)?;
+                Ok(self.storage(address, location)?)
+            }
+        }
+    }
+
+    async fn call(
+        &self,
+        tx: &TypedTransaction,
+        block: Option<EthersBlockId>,
+    ) -> Result<EthersBytes, Self::Error> {
+        match self.target(block) {
+            Target::Database(block) => {
+                self.middleware.call(tx, Some(block)).await.map_err(MiddlewareError::from_err)
+            }
+            Target::Simulated(number) => Err(ShadowForkError::StateNotKept(number)),
+            Target::Latest => Ok(self.call_latest(tx)?),
+        }
+    }
+
+    /// Returns the gas the call uses on the latest state of the session, without searching for
+    /// the lowest gas limit it succeeds with as `eth_estimateGas` does.
+    async fn estimate_gas(
+        &self,
+        tx: &TypedTransaction,
+        block: Option<EthersBlockId>,
+    ) -> Result<EthersU256, Self::Error> {
+        match self.target(block) {
+            Target::Database(block) => self
+                .middleware
+                .estimate_gas(tx, Some(block))
+                .await
+                .map_err(MiddlewareError::from_err),
+            Target::Simulated(number) => Err(ShadowForkError::StateNotKept(number)),
+            Target::Latest => {
+                let result = self.execute(tx)?;
+                let gas_used = result.gas_used();
+                call_output(result)?;
+                Ok(gas_used.into())
+            }
+        }
+    }
+}
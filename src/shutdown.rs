@@ -0,0 +1,52 @@
+use crate::RethMiddleware;
+use ethers::providers::Middleware;
+
+// Std
+use std::sync::{Arc, Mutex};
+use tokio::task::{AbortHandle, JoinHandle};
+
+/// Background tasks started for a middleware, shared by its clones.
+#[derive(Debug, Clone, Default)]
+pub struct BackgroundTasks(Arc<Mutex<Vec<AbortHandle>>>);
+
+impl BackgroundTasks {
+    /// Tracks the task so it is aborted on shutdown, forgetting the tasks that already ended.
+    pub fn register<T>(&self, task: &JoinHandle<T>) {
+        let mut tasks = self.0.lock().unwrap();
+        tasks.retain(|task| !task.is_finished());
+        tasks.push(task.abort_handle());
+    }
+
+    /// Aborts every tracked task.
+    pub fn abort_all(&self) {
+        for task in self.0.lock().unwrap().drain(..) {
+            task.abort();
+        }
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Stops the background work of the middleware so the embedding application can restart it
+    /// cleanly.
+    ///
+    /// Aborts the follower, notification and reorg tasks, and the task manager of a database this
+    /// crate opened, whose shutdown signal stops the state cache, the filter manager and
+    /// subscriptions. Read transactions only live for the duration of a request, and the database
+    /// is closed once the last clone of the middleware, or a transport or server built from it, is
+    /// dropped. Components of a running node, see [RethMiddleware::from_provider], are left to the
+    /// node.
+    ///
+    /// This is explicit rather than done on drop, as servers and transports keep serving from the
+    /// handlers after the middleware they were built from is gone.
+    pub fn shutdown(&self) {
+        self.tasks.abort_all();
+    }
+
+    /// Returns the background tasks aborted by [Self::shutdown].
+    pub fn background_tasks(&self) -> &BackgroundTasks {
+        &self.tasks
+    }
+}
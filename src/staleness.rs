@@ -0,0 +1,96 @@
+use crate::{instrument::db_read, RethMiddleware, RethMiddlewareError};
+use ethers::providers::Middleware;
+
+// Reth
+use reth_primitives::{stage::StageId, BlockId, BlockNumberOrTag};
+use reth_provider::{BlockReaderIdExt, StageCheckpointReader};
+
+// Std
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// What happens to a request for `latest` state when the local tip is stale.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StaleTipAction {
+    /// Fail with [RethMiddlewareError::StaleTip].
+    #[default]
+    Error,
+    /// Log a warning and serve the request from the database anyway.
+    Warn,
+    /// Delegate the request to the inner provider, whatever its fallback policy.
+    Fallback,
+}
+
+/// Limits on how far behind the local tip may be before requests for `latest` state are
+/// considered stale, so a stalled node does not silently serve hours-old state. Disabled unless a
+/// limit is set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StaleTipPolicy {
+    /// Maximum age of the latest block's timestamp.
+    pub max_age: Option<Duration>,
+    /// Maximum number of blocks between the latest block and the highest header the node
+    /// downloaded.
+    pub max_blocks_behind: Option<u64>,
+    pub action: StaleTipAction,
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Sets the limits past which requests for `latest` state are considered stale.
+    pub fn with_stale_tip_policy(mut self, policy: StaleTipPolicy) -> Self {
+        self.stale_tip = policy;
+        self
+    }
+
+    /// Returns the limits past which requests for `latest` state are considered stale.
+    pub fn stale_tip_policy(&self) -> StaleTipPolicy {
+        self.stale_tip
+    }
+
+    /// Checks a request for `block`, defaulting to `latest`, against the stale tip policy.
+    pub(crate) fn ensure_fresh(
+        &self,
+        block: Option<BlockId>,
+    ) -> Result<(), RethMiddlewareError<M>> {
+        let policy = self.stale_tip;
+        if policy.max_age.is_none() && policy.max_blocks_behind.is_none() {
+            return Ok(())
+        }
+        if !matches!(
+            block,
+            None | Some(BlockId::Number(BlockNumberOrTag::Latest | BlockNumberOrTag::Pending))
+        ) {
+            return Ok(())
+        }
+
+        let Some(tip) = self.reth_provider.latest_header()? else { return Ok(()) };
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let age = now.saturating_sub(tip.timestamp);
+        let blocks_behind = match policy.max_blocks_behind {
+            Some(_) => db_read("stage_checkpoint", || {
+                self.reth_provider.get_stage_checkpoint(StageId::Headers)
+            })?
+            .map_or(0, |checkpoint| checkpoint.block_number.saturating_sub(tip.number)),
+            None => 0,
+        };
+
+        let too_old = policy.max_age.map_or(false, |max_age| age > max_age.as_secs());
+        let too_far = policy.max_blocks_behind.map_or(false, |max| blocks_behind > max);
+        if !too_old && !too_far {
+            return Ok(())
+        }
+
+        match policy.action {
+            StaleTipAction::Warn => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(target: "ethers_reth", age, blocks_behind, "serving a stale tip");
+                Ok(())
+            }
+            StaleTipAction::Error | StaleTipAction::Fallback => {
+                Err(RethMiddlewareError::StaleTip { age, blocks_behind })
+            }
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{
+    instrument::{db_read, instrument},
+    state_walker::MAX_WALK_PAGE_SIZE,
+    type_conversions::ToReth,
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Address as EthersAddress, BlockId as EthersBlockId},
+};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_primitives::H256;
+use reth_provider::{StateProvider, StateProviderFactory};
+use reth_rpc::eth::error::EthApiError;
+
+// Std
+use std::collections::HashSet;
+
+/// How much of the state [RethMiddleware::state_stats] walks.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateStatsConfig {
+    /// Fraction of the address space walked, from 0 to 1, starting at the zero address. Addresses
+    /// are hashes, so the counts of a fraction scale to the whole state.
+    pub sample_fraction: f64,
+    /// Walks the storage of every contract sampled, by far the most expensive part.
+    pub count_storage: bool,
+}
+
+impl Default for StateStatsConfig {
+    fn default() -> Self {
+        Self { sample_fraction: 1.0, count_storage: true }
+    }
+}
+
+/// The size of the state at a block, estimated from a sample unless the whole state was walked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateStats {
+    pub block_number: u64,
+    pub accounts: u64,
+    /// Accounts with code.
+    pub contracts: u64,
+    /// Non-zero storage slots, `None` if storage was not counted.
+    pub storage_slots: Option<u64>,
+    /// Bytes of the distinct bytecodes of the contracts sampled. Contracts share code, so this
+    /// is not scaled, and is a lower bound for a sample.
+    pub bytecode_bytes: u64,
+    /// Fraction of the address space walked, which the counts were scaled from.
+    pub sampled_fraction: f64,
+}
+
+/// Returns the first address past the sampled fraction of the address space, `None` for all of
+/// it, along with the exact fraction it bounds.
+fn sample_bound(fraction: f64) -> (Option<EthersAddress>, f64) {
+    if fraction >= 1.0 {
+        return (None, 1.0)
+    }
+    let prefix = (fraction * u64::MAX as f64) as u64;
+    let mut bound = EthersAddress::zero();
+    bound.0[..8].copy_from_slice(&prefix.to_be_bytes());
+    (Some(bound), prefix as f64 / u64::MAX as f64)
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the number of accounts, contracts and storage slots at `block`, the latest if
+    /// `None`, and the bytes of their code, using the state walks.
+    ///
+    /// Walking the whole state takes hours on mainnet, see [StateStatsConfig::sample_fraction]
+    /// to estimate it from part of the address space instead.
+    pub async fn state_stats(
+        &self,
+        block: Option<EthersBlockId>,
+        config: StateStatsConfig,
+    ) -> Result<StateStats, RethMiddlewareError<M>> {
+        instrument("state_stats", async {
+            if !(config.sample_fraction > 0.0 && config.sample_fraction <= 1.0) {
+                return Err(EthApiError::InvalidParams(
+                    "sample fraction must be in (0, 1]".to_string(),
+                )
+                .into())
+            }
+            // pinned, so the pages all read the same state
+            let number = self.walk_block_number(block)?;
+            let block = Some(EthersBlockId::from(number));
+            let (bound, sampled_fraction) = sample_bound(config.sample_fraction);
+
+            let mut stats = StateStats { block_number: number, ..Default::default() };
+            let mut storage_slots = 0;
+            let mut codes = HashSet::new();
+            let mut start = None;
+            'walk: loop {
+                let page = self.walk_accounts(block, start, MAX_WALK_PAGE_SIZE).await?;
+                let mut new_codes = vec![];
+                for account in &page.accounts {
+                    if bound.is_some_and(|bound| account.address >= bound) {
+                        break 'walk
+                    }
+                    stats.accounts += 1;
+                    let Some(code_hash) = account.code_hash else { continue };
+                    stats.contracts += 1;
+                    if codes.insert(code_hash) {
+                        new_codes.push(code_hash.into_reth());
+                    }
+                    if config.count_storage {
+                        storage_slots += self.count_storage_slots(account.address, block).await?;
+                    }
+                }
+                stats.bytecode_bytes += self.bytecode_bytes(&new_codes)?;
+
+                let Some(next) = page.next else { break };
+                start = Some(next);
+            }
+
+            let scale = |count: u64| (count as f64 / sampled_fraction).round() as u64;
+            stats.accounts = scale(stats.accounts);
+            stats.contracts = scale(stats.contracts);
+            stats.storage_slots = config.count_storage.then(|| scale(storage_slots));
+            stats.sampled_fraction = sampled_fraction;
+            Ok(stats)
+        })
+        .await
+    }
+
+    /// Returns the number of non-zero storage slots of a contract at a block.
+    async fn count_storage_slots(
+        &self,
+        address: EthersAddress,
+        block: Option<EthersBlockId>,
+    ) -> Result<u64, RethMiddlewareError<M>> {
+        let mut count = 0;
+        let mut start = None;
+        loop {
+            let page = self.walk_storage(address, block, start, MAX_WALK_PAGE_SIZE).await?;
+            count += page.slots.len() as u64;
+            let Some(next) = page.next else { return Ok(count) };
+            start = Some(next);
+        }
+    }
+
+    /// Returns the total length of bytecodes, by their hash.
+    fn bytecode_bytes(&self, code_hashes: &[H256]) -> Result<u64, RethMiddlewareError<M>> {
+        if code_hashes.is_empty() {
+            return Ok(0)
+        }
+        let state = db_read("latest", || self.reth_provider.latest())?;
+        let mut bytes = 0;
+        for code_hash in code_hashes {
+            let code = db_read("bytecode_by_hash", || state.bytecode_by_hash(*code_hash))?;
+            bytes += code.map_or(0, |code| code.0.original_bytes().len() as u64);
+        }
+        Ok(bytes)
+    }
+}
@@ -0,0 +1,207 @@
+use crate::{
+    instrument::{db_read, instrument, record_block},
+    type_conversions::{ToEthers, ToReth},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{
+        Address as EthersAddress, BlockId as EthersBlockId, H256 as EthersH256, U256 as EthersU256,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_db::{
+    cursor::{DbCursorRO, DbDupCursorRO},
+    database::Database,
+    models::{storage_sharded_key::StorageShardedKey, ShardedKey},
+    tables,
+    transaction::DbTx,
+};
+use reth_primitives::{Address, BlockId, BlockNumberOrTag, H256, KECCAK_EMPTY, U256};
+use reth_provider::{AccountReader, BlockIdReader, StateProvider, StateProviderFactory};
+
+/// Most entries a single page of a state walk may hold.
+pub const MAX_WALK_PAGE_SIZE: usize = 10_000;
+
+/// An account in the state at a block.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountEntry {
+    pub address: EthersAddress,
+    pub nonce: u64,
+    pub balance: EthersU256,
+    /// `None` for accounts without code.
+    pub code_hash: Option<EthersH256>,
+}
+
+/// A page of the accounts at a block, in address order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountPage {
+    pub block_number: u64,
+    pub accounts: Vec<AccountEntry>,
+    /// The address the next page starts at, `None` once the walk is done.
+    pub next: Option<EthersAddress>,
+}
+
+/// A non-zero storage slot of a contract at a block.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageSlot {
+    pub key: EthersH256,
+    pub value: EthersU256,
+}
+
+/// A page of the storage slots of a contract at a block, in key order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoragePage {
+    pub block_number: u64,
+    pub address: EthersAddress,
+    pub slots: Vec<StorageSlot>,
+    /// The key the next page starts at, `None` once the walk is done.
+    pub next: Option<EthersH256>,
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns a page of up to `limit` accounts at `block`, the latest if `None`, starting at
+    /// the address `start`. Pass the page's `next` as `start` to resume the walk.
+    ///
+    /// The addresses walked are those of the current state and of the history index, so
+    /// accounts deleted since the block are found too, and each is read at the block. Walks
+    /// resumed across blocks should pin a block number, as `latest` moves between pages.
+    pub async fn walk_accounts(
+        &self,
+        block: Option<EthersBlockId>,
+        start: Option<EthersAddress>,
+        limit: usize,
+    ) -> Result<AccountPage, RethMiddlewareError<M>> {
+        instrument("walk_accounts", async {
+            let db = self.db.as_ref().ok_or(RethMiddlewareError::NoDatabase("walk_accounts"))?;
+            let number = self.walk_block_number(block)?;
+            let state = db_read("history_by_block_number", || {
+                self.reth_provider.history_by_block_number(number)
+            })?;
+            let limit = limit.clamp(1, MAX_WALK_PAGE_SIZE);
+            let start = start.map_or(Address::zero(), ToReth::into_reth);
+
+            Ok(db_read("walk_accounts", || -> Result<_, reth_interfaces::Error> {
+                let tx = db.tx()?;
+                let mut current = tx.cursor_read::<tables::PlainAccountState>()?;
+                let mut history = tx.cursor_read::<tables::AccountHistory>()?;
+                let mut next_current = current.seek(start)?.map(|(address, _)| address);
+                let mut next_changed =
+                    history.seek(ShardedKey::new(start, 0))?.map(|(key, _)| key.key);
+
+                let mut page = AccountPage { block_number: number, ..Default::default() };
+                while let Some(address) = next_current.into_iter().chain(next_changed).min() {
+                    if page.accounts.len() == limit {
+                        page.next = Some(address.into_ethers());
+                        break
+                    }
+                    if next_current == Some(address) {
+                        next_current = current.next()?.map(|(address, _)| address);
+                    }
+                    if next_changed == Some(address) {
+                        // the last shard of an address is keyed by the highest block number
+                        history.seek(ShardedKey::new(address, u64::MAX))?;
+                        next_changed = history.next()?.map(|(key, _)| key.key);
+                    }
+
+                    let Some(account) = state.basic_account(address)? else { continue };
+                    page.accounts.push(AccountEntry {
+                        address: address.into_ethers(),
+                        nonce: account.nonce,
+                        balance: account.balance.into(),
+                        code_hash: account
+                            .bytecode_hash
+                            .filter(|hash| *hash != KECCAK_EMPTY)
+                            .map(ToEthers::into_ethers),
+                    });
+                }
+                Ok(page)
+            })?)
+        })
+        .await
+    }
+
+    /// Returns a page of up to `limit` non-zero storage slots of `address` at `block`, the
+    /// latest if `None`, starting at the key `start`. Pass the page's `next` as `start` to
+    /// resume the walk.
+    ///
+    /// Like [Self::walk_accounts], the keys walked are those of the current storage and of the
+    /// history index, each read at the block.
+    pub async fn walk_storage(
+        &self,
+        address: EthersAddress,
+        block: Option<EthersBlockId>,
+        start: Option<EthersH256>,
+        limit: usize,
+    ) -> Result<StoragePage, RethMiddlewareError<M>> {
+        instrument("walk_storage", async {
+            let db = self.db.as_ref().ok_or(RethMiddlewareError::NoDatabase("walk_storage"))?;
+            let number = self.walk_block_number(block)?;
+            let state = db_read("history_by_block_number", || {
+                self.reth_provider.history_by_block_number(number)
+            })?;
+            let limit = limit.clamp(1, MAX_WALK_PAGE_SIZE);
+            let account: Address = address.into_reth();
+            let start = start.map_or(H256::zero(), ToReth::into_reth);
+
+            Ok(db_read("walk_storage", || -> Result<_, reth_interfaces::Error> {
+                let tx = db.tx()?;
+                let mut current = tx.cursor_dup_read::<tables::PlainStorageState>()?;
+                let mut history = tx.cursor_read::<tables::StorageHistory>()?;
+                let of_account = |entry: Option<(StorageShardedKey, _)>| {
+                    entry
+                        .filter(|(key, _)| key.address == account)
+                        .map(|(key, _)| key.sharded_key.key)
+                };
+                let mut next_current =
+                    current.seek_by_key_subkey(account, start)?.map(|entry| entry.key);
+                let mut next_changed =
+                    of_account(history.seek(StorageShardedKey::new(account, start, 0))?);
+
+                let mut page =
+                    StoragePage { block_number: number, address, slots: vec![], next: None };
+                while let Some(key) = next_current.into_iter().chain(next_changed).min() {
+                    if page.slots.len() == limit {
+                        page.next = Some(key.into_ethers());
+                        break
+                    }
+                    if next_current == Some(key) {
+                        next_current = current.next_dup_val()?.map(|entry| entry.key);
+                    }
+                    if next_changed == Some(key) {
+                        history.seek(StorageShardedKey::new(account, key, u64::MAX))?;
+                        next_changed = of_account(history.next()?);
+                    }
+
+                    let value = state.storage(account, key)?.unwrap_or_default();
+                    if value != U256::ZERO {
+                        page.slots
+                            .push(StorageSlot { key: key.into_ethers(), value: value.into() });
+                    }
+                }
+                Ok(page)
+            })?)
+        })
+        .await
+    }
+
+    /// Returns the number of the block a walk reads the state at.
+    pub(crate) fn walk_block_number(
+        &self,
+        block: Option<EthersBlockId>,
+    ) -> Result<u64, RethMiddlewareError<M>> {
+        let block_id = block.into_reth().unwrap_or(BlockId::Number(BlockNumberOrTag::Latest));
+        record_block(&block_id);
+        self.ensure_history(Some(block_id))?;
+        db_read("block_number_for_id", || self.reth_provider.block_number_for_id(block_id))?
+            .ok_or(RethMiddlewareError::BlockNotFound)
+    }
+}
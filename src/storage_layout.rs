@@ -0,0 +1,617 @@
+use crate::{
+    instrument::{db_read, instrument, record_block},
+    type_conversions::ToReth,
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    abi::Token,
+    providers::Middleware,
+    types::{
+        Address as EthersAddress, BlockId as EthersBlockId, NameOrAddress, I256, U256 as EthersU256,
+    },
+    utils::keccak256,
+};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_primitives::{Address, BlockId, BlockNumberOrTag, H256};
+use reth_provider::{StateProvider, StateProviderFactory};
+use reth_rpc::eth::error::EthApiError;
+
+// Std
+use std::collections::HashMap;
+
+/// The storage layout of a contract, as solc outputs it with `--storage-layout`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageLayout {
+    pub storage: Vec<StorageVariable>,
+    #[serde(default)]
+    pub types: HashMap<String, StorageType>,
+}
+
+/// A state variable, or a member of a struct.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageVariable {
+    pub label: String,
+    /// Slot of the variable, in decimal, relative to its struct for members.
+    pub slot: String,
+    /// Offset of the variable in its slot, in bytes from the right.
+    pub offset: usize,
+    /// Key of the variable's type in [StorageLayout::types].
+    #[serde(rename = "type")]
+    pub ty: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageEncoding {
+    Inplace,
+    Mapping,
+    DynamicArray,
+    Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageType {
+    pub encoding: StorageEncoding,
+    /// The type as written in Solidity, e.g. `mapping(address => uint256)`.
+    pub label: String,
+    /// Size of the type in bytes, in decimal.
+    pub number_of_bytes: String,
+    /// Key type of mappings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Value type of mappings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// Element type of arrays.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    /// Members of structs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub members: Option<Vec<StorageVariable>>,
+}
+
+impl StorageType {
+    fn size(&self) -> usize {
+        self.number_of_bytes.parse().unwrap_or(32)
+    }
+}
+
+/// A step of a path into a variable: `.member` or `[key]`.
+#[derive(Debug, PartialEq, Eq)]
+enum Segment<'a> {
+    Member(&'a str),
+    Index(&'a str),
+}
+
+/// Splits a path like `positions[0xab..][3].owner` into its variable and segments. Keys may be
+/// quoted, e.g. `names["a.b"]`.
+fn parse_path(path: &str) -> Result<(&str, Vec<Segment<'_>>), EthApiError> {
+    let invalid = || EthApiError::InvalidParams(format!("invalid storage path {path}"));
+    let end = path.find(['.', '[']).unwrap_or(path.len());
+    let (variable, mut rest) = path.split_at(end);
+    if variable.is_empty() {
+        return Err(invalid())
+    }
+
+    let mut segments = vec![];
+    while !rest.is_empty() {
+        if let Some(tail) = rest.strip_prefix('.') {
+            let end = tail.find(['.', '[']).unwrap_or(tail.len());
+            let (member, tail) = tail.split_at(end);
+            if member.is_empty() {
+                return Err(invalid())
+            }
+            segments.push(Segment::Member(member));
+            rest = tail;
+        } else if let Some(tail) = rest.strip_prefix("[\"") {
+            let end = tail.find("\"]").ok_or_else(invalid)?;
+            segments.push(Segment::Index(&tail[..end]));
+            rest = &tail[end + 2..];
+        } else if let Some(tail) = rest.strip_prefix('[') {
+            let end = tail.find(']').ok_or_else(invalid)?;
+            segments.push(Segment::Index(&tail[..end]));
+            rest = &tail[end + 1..];
+        } else {
+            return Err(invalid())
+        }
+    }
+    Ok((variable, segments))
+}
+
+/// Where a value is stored.
+#[derive(Debug, Clone)]
+struct Location<'a> {
+    slot: EthersU256,
+    offset: usize,
+    ty: &'a StorageType,
+}
+
+/// Reads storage as laid out by a [StorageLayout].
+struct LayoutReader<'a> {
+    state: &'a dyn StateProvider,
+    address: Address,
+    layout: &'a StorageLayout,
+}
+
+impl<'a> LayoutReader<'a> {
+    fn ty(&self, id: &str) -> Result<&'a StorageType, EthApiError> {
+        self.layout
+            .types
+            .get(id)
+            .ok_or_else(|| EthApiError::InvalidParams(format!("type {id} missing from the layout")))
+    }
+
+    fn word(&self, slot: EthersU256) -> Result<[u8; 32], LayoutReadError> {
+        let mut key = [0; 32];
+        slot.to_big_endian(&mut key);
+        let value = self.state.storage(self.address, H256(key))?.unwrap_or_default();
+        Ok(value.to_be_bytes::<32>())
+    }
+
+    /// Returns the location of a variable of the layout, following the path's segments.
+    fn locate(&self, path: &str) -> Result<Location<'a>, LayoutReadError> {
+        let (name, segments) = parse_path(path)?;
+        let variable =
+            self.layout.storage.iter().find(|variable| variable.label == name).ok_or_else(
+                || EthApiError::InvalidParams(format!("no variable {name} in the layout")),
+            )?;
+        let mut location = Location {
+            slot: parse_slot(&variable.slot)?,
+            offset: variable.offset,
+            ty: self.ty(&variable.ty)?,
+        };
+
+        for segment in segments {
+            location = match (segment, location.ty.encoding) {
+                (Segment::Member(name), StorageEncoding::Inplace) => {
+                    let member = location
+                        .ty
+                        .members
+                        .iter()
+                        .flatten()
+                        .find(|member| member.label == name)
+                        .ok_or_else(|| {
+                            EthApiError::InvalidParams(format!(
+                                "no member {name} in {}",
+                                location.ty.label
+                            ))
+                        })?;
+                    Location {
+                        slot: location.slot + parse_slot(&member.slot)?,
+                        offset: member.offset,
+                        ty: self.ty(&member.ty)?,
+                    }
+                }
+                (Segment::Index(key), StorageEncoding::Mapping) => {
+                    let key_ty = self.ty(location.ty.key.as_deref().unwrap_or_default())?;
+                    let mut preimage = encode_key(key_ty, key)?;
+                    preimage.extend_from_slice(&word_bytes(location.slot));
+                    Location {
+                        slot: EthersU256::from_big_endian(&keccak256(preimage)),
+                        offset: 0,
+                        ty: self.ty(location.ty.value.as_deref().unwrap_or_default())?,
+                    }
+                }
+                (Segment::Index(index), StorageEncoding::DynamicArray) => {
+                    let index = parse_index(index)?;
+                    let length = EthersU256::from_big_endian(&self.word(location.slot)?);
+                    if EthersU256::from(index) >= length {
+                        return Err(EthApiError::InvalidParams(format!(
+                            "index {index} out of the {length} elements of {}",
+                            location.ty.label
+                        ))
+                        .into())
+                    }
+                    let start = EthersU256::from_big_endian(&keccak256(word_bytes(location.slot)));
+                    self.element(location.ty, start, index)?
+                }
+                (Segment::Index(index), StorageEncoding::Inplace) if location.ty.base.is_some() => {
+                    let index = parse_index(index)?;
+                    if index >= static_length(location.ty)? {
+                        return Err(EthApiError::InvalidParams(format!(
+                            "index {index} out of {}",
+                            location.ty.label
+                        ))
+                        .into())
+                    }
+                    self.element(location.ty, location.slot, index)?
+                }
+                (segment, _) => {
+                    return Err(EthApiError::InvalidParams(format!(
+                        "cannot apply {segment:?} to {}",
+                        location.ty.label
+                    ))
+                    .into())
+                }
+            };
+        }
+        Ok(location)
+    }
+
+    /// Returns the location of an element of an array whose elements start at `start`. Elements
+    /// smaller than a slot are packed, as many to a slot as fit.
+    fn element(
+        &self,
+        array: &StorageType,
+        start: EthersU256,
+        index: usize,
+    ) -> Result<Location<'a>, EthApiError> {
+        let base = self.ty(array.base.as_deref().unwrap_or_default())?;
+        let size = base.size().max(1);
+        let location = if size <= 32 {
+            let per_slot = 32 / size;
+            Location { slot: start + index / per_slot, offset: index % per_slot * size, ty: base }
+        } else {
+            let slots = (size + 31) / 32;
+            Location { slot: start + EthersU256::from(index) * slots, offset: 0, ty: base }
+        };
+        Ok(location)
+    }
+
+    /// Reads and decodes the value at a location, every element or member of arrays and structs.
+    fn read(&self, location: &Location<'a>) -> Result<Token, LayoutReadError> {
+        let ty = location.ty;
+        match ty.encoding {
+            StorageEncoding::Mapping => Err(EthApiError::InvalidParams(format!(
+                "cannot read the whole of {}, index it with a key",
+                ty.label
+            ))
+            .into()),
+            StorageEncoding::Bytes => {
+                let data = self.read_bytes(location.slot)?;
+                Ok(if ty.label == "string" {
+                    Token::String(String::from_utf8_lossy(&data).into_owned())
+                } else {
+                    Token::Bytes(data)
+                })
+            }
+            StorageEncoding::DynamicArray => {
+                let length = EthersU256::from_big_endian(&self.word(location.slot)?);
+                let start = EthersU256::from_big_endian(&keccak256(word_bytes(location.slot)));
+                let elements = (0..checked_length(length)?)
+                    .map(|index| self.read(&self.element(ty, start, index)?))
+                    .collect::<Result<_, _>>()?;
+                Ok(Token::Array(elements))
+            }
+            StorageEncoding::Inplace if ty.base.is_some() => {
+                let elements = (0..static_length(ty)?)
+                    .map(|index| self.read(&self.element(ty, location.slot, index)?))
+                    .collect::<Result<_, _>>()?;
+                Ok(Token::FixedArray(elements))
+            }
+            StorageEncoding::Inplace if ty.members.is_some() => {
+                let members = ty
+                    .members
+                    .iter()
+                    .flatten()
+                    .map(|member| {
+                        self.read(&Location {
+                            slot: location.slot + parse_slot(&member.slot)?,
+                            offset: member.offset,
+                            ty: self.ty(&member.ty)?,
+                        })
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(Token::Tuple(members))
+            }
+            StorageEncoding::Inplace => {
+                let word = self.word(location.slot)?;
+                let size = ty.size().min(32);
+                let end = 32usize.saturating_sub(location.offset);
+                Ok(decode_value(&ty.label, &word[end.saturating_sub(size)..end]))
+            }
+        }
+    }
+
+    /// Reads a `bytes` or `string`: in its slot with its length if shorter than 32 bytes, else
+    /// from the keccak of its slot on.
+    fn read_bytes(&self, slot: EthersU256) -> Result<Vec<u8>, LayoutReadError> {
+        let word = self.word(slot)?;
+        if word[31] & 1 == 0 {
+            let length = (word[31] / 2) as usize;
+            return Ok(word[..length].to_vec())
+        }
+
+        let length = checked_length(EthersU256::from_big_endian(&word) / 2)?;
+        let start = EthersU256::from_big_endian(&keccak256(word_bytes(slot)));
+        let mut data = Vec::with_capacity(length);
+        for index in 0..(length + 31) / 32 {
+            data.extend_from_slice(&self.word(start + index)?);
+        }
+        data.truncate(length);
+        Ok(data)
+    }
+}
+
+/// Failure of a read through a layout: a bad layout or path, or a failed database read.
+#[derive(Debug)]
+enum LayoutReadError {
+    Layout(EthApiError),
+    Provider(reth_interfaces::Error),
+}
+
+impl From<EthApiError> for LayoutReadError {
+    fn from(err: EthApiError) -> Self {
+        LayoutReadError::Layout(err)
+    }
+}
+
+impl From<reth_interfaces::Error> for LayoutReadError {
+    fn from(err: reth_interfaces::Error) -> Self {
+        LayoutReadError::Provider(err)
+    }
+}
+
+impl<M: Middleware> From<LayoutReadError> for RethMiddlewareError<M> {
+    fn from(err: LayoutReadError) -> Self {
+        match err {
+            LayoutReadError::Layout(err) => err.into(),
+            LayoutReadError::Provider(err) => err.into(),
+        }
+    }
+}
+
+fn parse_slot(slot: &str) -> Result<EthersU256, EthApiError> {
+    EthersU256::from_dec_str(slot)
+        .map_err(|_| EthApiError::InvalidParams(format!("invalid slot {slot} in the layout")))
+}
+
+fn parse_index(index: &str) -> Result<usize, EthApiError> {
+    index.parse().map_err(|_| EthApiError::InvalidParams(format!("invalid array index {index}")))
+}
+
+/// Returns the length of a static array from its label, e.g. 3 for `uint256[3]`.
+fn static_length(array: &StorageType) -> Result<usize, EthApiError> {
+    array
+        .label
+        .rsplit_once('[')
+        .and_then(|(_, length)| length.strip_suffix(']'))
+        .and_then(|length| length.parse().ok())
+        .ok_or_else(|| EthApiError::InvalidParams(format!("no length in {}", array.label)))
+}
+
+/// Returns a length read from storage, failing on lengths no contract could have stored, which
+/// mean the layout does not match the contract.
+fn checked_length(length: EthersU256) -> Result<usize, EthApiError> {
+    if length.bits() > 32 {
+        return Err(EthApiError::InvalidParams(format!("implausible length {length}")))
+    }
+    Ok(length.as_usize())
+}
+
+fn word_bytes(value: EthersU256) -> [u8; 32] {
+    let mut word = [0; 32];
+    value.to_big_endian(&mut word);
+    word
+}
+
+/// Encodes a mapping key the way Solidity hashes it: value types padded to a word, `string` and
+/// `bytes` as they are.
+fn encode_key(ty: &StorageType, key: &str) -> Result<Vec<u8>, EthApiError> {
+    let invalid = || EthApiError::InvalidParams(format!("invalid {} key {key}", ty.label));
+    let hex = |key: &str| {
+        ethers::utils::hex::decode(key.strip_prefix("0x").unwrap_or(key)).map_err(|_| invalid())
+    };
+    let label = ty.label.as_str();
+
+    let word = if label == "string" {
+        return Ok(key.as_bytes().to_vec())
+    } else if label == "bytes" {
+        return hex(key)
+    } else if label.starts_with("address") || label.starts_with("contract ") {
+        let address: EthersAddress = key.parse().map_err(|_| invalid())?;
+        let mut word = [0; 32];
+        word[12..].copy_from_slice(address.as_bytes());
+        word
+    } else if label == "bool" {
+        let value: bool = key.parse().map_err(|_| invalid())?;
+        word_bytes(EthersU256::from(value as u8))
+    } else if label.starts_with("bytes") {
+        let bytes = hex(key)?;
+        if bytes.len() > 32 {
+            return Err(invalid())
+        }
+        let mut word = [0; 32];
+        word[..bytes.len()].copy_from_slice(&bytes);
+        word
+    } else if label.starts_with("int") {
+        let value = I256::from_dec_str(key).map_err(|_| invalid())?;
+        word_bytes(value.into_raw())
+    } else {
+        let value = match key.strip_prefix("0x") {
+            Some(hex) => EthersU256::from_str_radix(hex, 16).ok(),
+            None => EthersU256::from_dec_str(key).ok(),
+        }
+        .ok_or_else(invalid)?;
+        word_bytes(value)
+    };
+    Ok(word.to_vec())
+}
+
+/// Decodes a value type from its bytes in its slot.
+fn decode_value(label: &str, bytes: &[u8]) -> Token {
+    if label.starts_with("address") || label.starts_with("contract ") {
+        Token::Address(EthersAddress::from_slice(&bytes[bytes.len().saturating_sub(20)..]))
+    } else if label == "bool" {
+        Token::Bool(bytes.iter().any(|byte| *byte != 0))
+    } else if label.starts_with("int") {
+        // sign extended to 256 bits
+        let fill = if bytes.first().map_or(false, |byte| byte & 0x80 != 0) { 0xff } else { 0 };
+        let mut word = [fill; 32];
+        word[32 - bytes.len()..].copy_from_slice(bytes);
+        Token::Int(EthersU256::from_big_endian(&word))
+    } else if label.starts_with("uint") || label.starts_with("enum ") {
+        Token::Uint(EthersU256::from_big_endian(bytes))
+    } else {
+        Token::FixedBytes(bytes.to_vec())
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Reads a variable of a contract at a block, given the contract's storage layout.
+    ///
+    /// The path names a state variable, then struct members, mapping keys and array indexes,
+    /// e.g. `balances[0xd8dA..6045]` or `positions[42].liquidity`. Mapping keys are written in
+    /// decimal or hex for integers, in hex for addresses and byte arrays, and as is for strings.
+    /// Arrays, structs and strings are read whole.
+    pub async fn read_storage_variable<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        contract: T,
+        layout: &StorageLayout,
+        path: &str,
+        block: Option<EthersBlockId>,
+    ) -> Result<Token, RethMiddlewareError<M>> {
+        let mut values = self.read_storage_variables(contract, layout, &[path], block).await?;
+        Ok(values.remove(0))
+    }
+
+    /// Reads several variables of a contract from the same state, see
+    /// [Self::read_storage_variable] for the paths.
+    pub async fn read_storage_variables<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        contract: T,
+        layout: &StorageLayout,
+        paths: &[&str],
+        block: Option<EthersBlockId>,
+    ) -> Result<Vec<Token>, RethMiddlewareError<M>> {
+        instrument("read_storage_variables", async {
+            let address = self.get_address(contract).await?;
+            let block_id = block.into_reth();
+            record_block(&block_id);
+            self.ensure_history(block_id)?;
+            self.ensure_fresh(block_id)?;
+            self.ensure_forkchoice(block_id)?;
+
+            let block_id = block_id.unwrap_or(BlockId::Number(BlockNumberOrTag::Latest));
+            let state = self.reth_provider.state_by_block_id(block_id)?;
+            let reader = LayoutReader { state: &*state, address: address.into_reth(), layout };
+            let values: Result<Vec<Token>, LayoutReadError> =
+                db_read("read_storage_variables", || {
+                    paths.iter().map(|path| reader.read(&reader.locate(path)?)).collect()
+                });
+            Ok(values?)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ty(label: &str, number_of_bytes: &str) -> StorageType {
+        StorageType {
+            encoding: StorageEncoding::Inplace,
+            label: label.to_string(),
+            number_of_bytes: number_of_bytes.to_string(),
+            key: None,
+            value: None,
+            base: None,
+            members: None,
+        }
+    }
+
+    #[test]
+    fn solc_layouts_deserialize() {
+        let layout: StorageLayout = serde_json::from_str(
+            r#"{
+                "storage": [{
+                    "astId": 3,
+                    "contract": "Token.sol:Token",
+                    "label": "balances",
+                    "offset": 0,
+                    "slot": "0",
+                    "type": "t_mapping(t_address,t_uint256)"
+                }],
+                "types": {
+                    "t_mapping(t_address,t_uint256)": {
+                        "encoding": "mapping",
+                        "key": "t_address",
+                        "label": "mapping(address => uint256)",
+                        "numberOfBytes": "32",
+                        "value": "t_uint256"
+                    },
+                    "t_string_storage": {
+                        "encoding": "bytes",
+                        "label": "string",
+                        "numberOfBytes": "32"
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(layout.storage[0].ty, "t_mapping(t_address,t_uint256)");
+        let mapping = &layout.types["t_mapping(t_address,t_uint256)"];
+        assert_eq!(mapping.encoding, StorageEncoding::Mapping);
+        assert_eq!(mapping.value.as_deref(), Some("t_uint256"));
+        assert_eq!(layout.types["t_string_storage"].encoding, StorageEncoding::Bytes);
+    }
+
+    #[test]
+    fn paths_split_into_members_and_indexes() {
+        let (variable, segments) = parse_path("positions[0xab][3].owner").unwrap();
+        assert_eq!(variable, "positions");
+        assert_eq!(
+            segments,
+            vec![Segment::Index("0xab"), Segment::Index("3"), Segment::Member("owner")]
+        );
+        let (_, segments) = parse_path(r#"names["a.b]"].id"#).unwrap();
+        assert_eq!(segments, vec![Segment::Index("a.b]"), Segment::Member("id")]);
+        assert_eq!(parse_path("total").unwrap(), ("total", vec![]));
+
+        for invalid in ["", "[0]", "a..b", "a[0", "a[0]b", r#"a["b]"#] {
+            assert!(parse_path(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn keys_are_encoded_as_solidity_hashes_them() {
+        let address = "0xd8da6bf26964af9d7eed9e03e53415d37aa96045";
+        let encoded = encode_key(&ty("address", "20"), address).unwrap();
+        assert_eq!(&encoded[12..], address.parse::<EthersAddress>().unwrap().as_bytes());
+        assert_eq!(encoded[..12], [0; 12]);
+
+        assert_eq!(encode_key(&ty("string", "32"), "abc").unwrap(), b"abc");
+        assert_eq!(encode_key(&ty("bytes", "32"), "0xabcd").unwrap(), [0xab, 0xcd]);
+        assert_eq!(encode_key(&ty("bytes4", "4"), "0xabcd").unwrap()[..3], [0xab, 0xcd, 0]);
+        assert_eq!(encode_key(&ty("bool", "1"), "true").unwrap()[31], 1);
+        assert_eq!(encode_key(&ty("int256", "32"), "-1").unwrap(), [0xff; 32]);
+        assert_eq!(
+            encode_key(&ty("uint256", "32"), "0x10").unwrap(),
+            encode_key(&ty("uint256", "32"), "16").unwrap()
+        );
+        assert!(encode_key(&ty("uint256", "32"), "-1").is_err());
+        assert!(encode_key(&ty("address", "20"), "0x12").is_err());
+    }
+
+    #[test]
+    fn values_are_decoded_from_their_bytes() {
+        assert_eq!(decode_value("bool", &[1]), Token::Bool(true));
+        assert_eq!(decode_value("uint8", &[0xff]), Token::Uint(0xff.into()));
+        assert_eq!(decode_value("enum Status", &[2]), Token::Uint(2.into()));
+        // negative values are sign extended
+        assert_eq!(decode_value("int8", &[0xff]), Token::Int(EthersU256::MAX));
+        assert_eq!(decode_value("int16", &[0x00, 0xff]), Token::Int(0xff.into()));
+        assert_eq!(
+            decode_value("address", &[0x11; 20]),
+            Token::Address(EthersAddress::repeat_byte(0x11))
+        );
+        assert_eq!(decode_value("bytes2", &[0xab, 0xcd]), Token::FixedBytes(vec![0xab, 0xcd]));
+    }
+
+    #[test]
+    fn lengths_are_read_from_labels_and_bounded() {
+        assert_eq!(static_length(&ty("uint256[3]", "96")).unwrap(), 3);
+        assert_eq!(static_length(&ty("uint8[2][4]", "128")).unwrap(), 4);
+        assert!(static_length(&ty("uint256[]", "32")).is_err());
+
+        assert_eq!(checked_length(EthersU256::from(7)).unwrap(), 7);
+        assert!(checked_length(EthersU256::one() << 40).is_err());
+    }
+}
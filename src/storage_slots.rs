@@ -0,0 +1,200 @@
+//! Storage slot arithmetic for Solidity's storage layout: mapping entries, dynamic array elements
+//! and values packed in a slot. See [crate::storage_layout] to read variables from a solc layout
+//! instead.
+use crate::{RethMiddleware, RethMiddlewareError};
+use ethers::{
+    abi::{self, Token},
+    providers::Middleware,
+    types::{BlockId, NameOrAddress, H256, U256},
+    utils::keccak256,
+};
+
+fn slot_key(slot: U256) -> H256 {
+    let mut key = H256::zero();
+    slot.to_big_endian(&mut key.0);
+    key
+}
+
+/// Returns the slot of `mapping[key]` for a mapping at `base_slot`.
+///
+/// Value type keys are hashed as their ABI encoding, strings and bytes as their raw bytes.
+pub fn mapping_slot(base_slot: U256, key: &Token) -> U256 {
+    let mut preimage = match key {
+        Token::String(key) => key.as_bytes().to_vec(),
+        Token::Bytes(key) => key.clone(),
+        key => abi::encode(&[key.clone()]),
+    };
+    preimage.extend_from_slice(slot_key(base_slot).as_bytes());
+    U256::from_big_endian(&keccak256(preimage))
+}
+
+/// Returns the slot of `mapping[keys[0]][keys[1]]..` for nested mappings at `base_slot`.
+pub fn nested_mapping_slot(base_slot: U256, keys: &[Token]) -> U256 {
+    keys.iter().fold(base_slot, |slot, key| mapping_slot(slot, key))
+}
+
+/// Returns the first slot of `array[index]` for a dynamic array at `base_slot`, whose length is
+/// stored there, of elements taking `element_slots` slots each.
+///
+/// Elements smaller than a slot are packed, see [packed_array_slot] for them.
+pub fn array_element_slot(base_slot: U256, index: U256, element_slots: U256) -> U256 {
+    let start = U256::from_big_endian(&keccak256(slot_key(base_slot)));
+    start.overflowing_add(index.overflowing_mul(element_slots).0).0
+}
+
+/// Returns the slot of `array[index]` for a dynamic array at `base_slot` of elements of
+/// `element_bytes` bytes packed in slots, and the element's offset in it in bytes from the right.
+pub fn packed_array_slot(base_slot: U256, index: U256, element_bytes: usize) -> (U256, usize) {
+    let per_slot = U256::from(32 / element_bytes.clamp(1, 32));
+    let slot = array_element_slot(base_slot, index / per_slot, U256::one());
+    (slot, (index % per_slot).as_usize() * element_bytes.clamp(1, 32))
+}
+
+/// Returns the value of `size` bytes packed in a slot at `offset` bytes from the right, as solc
+/// packs struct members and small state variables.
+pub fn packed_value(word: H256, offset: usize, size: usize) -> U256 {
+    let end = 32 - offset.min(32);
+    let start = end.saturating_sub(size);
+    U256::from_big_endian(&word.as_bytes()[start..end])
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Reads `mapping[key]` of a contract's mapping at `base_slot`.
+    pub async fn read_mapping<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        contract: T,
+        base_slot: U256,
+        key: &Token,
+        block: Option<BlockId>,
+    ) -> Result<H256, RethMiddlewareError<M>> {
+        self.read_nested_mapping(contract, base_slot, std::slice::from_ref(key), block).await
+    }
+
+    /// Reads `mapping[keys[0]][keys[1]]..` of a contract's nested mappings at `base_slot`.
+    pub async fn read_nested_mapping<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        contract: T,
+        base_slot: U256,
+        keys: &[Token],
+        block: Option<BlockId>,
+    ) -> Result<H256, RethMiddlewareError<M>> {
+        let slot = nested_mapping_slot(base_slot, keys);
+        self.get_storage_at(contract, slot_key(slot), block).await
+    }
+
+    /// Reads the first slot of `array[index]` of a contract's dynamic array at `base_slot`, of
+    /// elements taking `element_slots` slots each, `None` if the index is out of bounds.
+    pub async fn read_array_element<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        contract: T,
+        base_slot: U256,
+        index: U256,
+        element_slots: U256,
+        block: Option<BlockId>,
+    ) -> Result<Option<H256>, RethMiddlewareError<M>> {
+        let contract = self.get_address(contract).await?;
+        // pinned, so the length and the element are read from the same state
+        let block = Some(match block {
+            Some(block) => block,
+            None => self.get_block_number().await?.into(),
+        });
+        let length = self.get_storage_at(contract, slot_key(base_slot), block).await?;
+        if index >= U256::from_big_endian(length.as_bytes()) {
+            return Ok(None)
+        }
+        let slot = array_element_slot(base_slot, index, element_slots);
+        Ok(Some(self.get_storage_at(contract, slot_key(slot), block).await?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::Address;
+
+    fn slot(hex: &str) -> U256 {
+        U256::from_str_radix(hex, 16).unwrap()
+    }
+
+    /// keccak256 of slot 0, where the elements of a dynamic array at slot 0 start.
+    const ARRAY_START: &str = "290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e563";
+
+    fn holder() -> Token {
+        Token::Address("0xd8da6bf26964af9d7eed9e03e53415d37aa96045".parse().unwrap())
+    }
+
+    #[test]
+    fn mapping_slots_hash_the_abi_encoded_key() {
+        assert_eq!(
+            mapping_slot(U256::zero(), &Token::Uint(U256::zero())),
+            slot("ad3228b676f7d3cd4284a5443f17f1962b36e491b30a40b2405849e597ba5fb5")
+        );
+        // `mapping(address => uint256) balanceOf` at slot 1
+        assert_eq!(
+            mapping_slot(U256::one(), &holder()),
+            slot("b931be0b3d1fb06daf0d92e2b8dfe49ead9172e102b3af1e07a10cc29003beb2")
+        );
+    }
+
+    #[test]
+    fn mapping_slots_hash_string_keys_unpadded() {
+        assert_eq!(
+            mapping_slot(U256::from(2), &Token::String("abc".to_string())),
+            slot("31f76c90c4bd232b01bb0bd40689518175171c0bb64d053d77a6e90319d96718")
+        );
+        assert_eq!(
+            mapping_slot(U256::from(2), &Token::Bytes(b"abc".to_vec())),
+            mapping_slot(U256::from(2), &Token::String("abc".to_string()))
+        );
+    }
+
+    #[test]
+    fn nested_mapping_slots_hash_the_keys_in_order() {
+        // `mapping(address => mapping(address => uint256)) allowance` at slot 3
+        let spender = Token::Address(Address::from_low_u64_be(0xff));
+        let slot_of = nested_mapping_slot(U256::from(3), &[holder(), spender.clone()]);
+        assert_eq!(
+            slot_of,
+            slot("91e99e6d06816c9f513d98545e588f564a3c434a968f55d31782f5cb768f37b1")
+        );
+        assert_eq!(slot_of, mapping_slot(mapping_slot(U256::from(3), &holder()), &spender));
+        assert_eq!(nested_mapping_slot(U256::from(3), &[]), U256::from(3));
+    }
+
+    #[test]
+    fn array_elements_follow_the_hash_of_the_length_slot() {
+        assert_eq!(array_element_slot(U256::zero(), U256::zero(), U256::one()), slot(ARRAY_START));
+        // elements of two slots each, e.g. a struct of two words
+        assert_eq!(
+            array_element_slot(U256::zero(), U256::from(2), U256::from(2)),
+            slot(ARRAY_START) + 4
+        );
+    }
+
+    #[test]
+    fn packed_array_elements_share_slots() {
+        // `uint64[]`, four elements per slot: element 33 is the second of the ninth slot
+        assert_eq!(packed_array_slot(U256::zero(), U256::from(33), 8), (slot(ARRAY_START) + 8, 8));
+        assert_eq!(packed_array_slot(U256::zero(), U256::from(3), 8), (slot(ARRAY_START), 24));
+        // elements of a full slot are not packed
+        assert_eq!(packed_array_slot(U256::zero(), U256::from(5), 32), (slot(ARRAY_START) + 5, 0));
+    }
+
+    #[test]
+    fn packed_values_are_read_from_the_right() {
+        // a `uint128` of 5, a `uint64` of 7 packed after it, and a `uint8` of 0xff in the first
+        // byte
+        let mut word = [0u8; 32];
+        word[31] = 5;
+        word[15] = 7;
+        word[0] = 0xff;
+        let word = H256(word);
+        assert_eq!(packed_value(word, 0, 16), U256::from(5));
+        assert_eq!(packed_value(word, 16, 8), U256::from(7));
+        assert_eq!(packed_value(word, 31, 1), U256::from(0xff));
+        assert_eq!(packed_value(word, 32, 8), U256::zero());
+    }
+}
@@ -0,0 +1,358 @@
+use crate::{
+    fanout::{LagPolicy, Lagged},
+    instrument::{db_read, instrument, record_block},
+    type_conversions::ToEthers,
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{H256 as EthersH256, I256, U256 as EthersU256},
+};
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_primitives::{
+    ChainSpec, Hardfork, Header, SealedBlockWithSenders, SealedHeader, Withdrawal,
+    EMPTY_OMMER_ROOT, U256,
+};
+use reth_provider::{
+    BlockReader, CanonStateNotification, CanonStateNotifications, ChainSpecProvider,
+    HeaderProvider, WithdrawalsProvider,
+};
+use reth_rpc::eth::error::EthApiError;
+
+// Std
+use std::{collections::VecDeque, ops::RangeInclusive, sync::Arc};
+use tokio::sync::broadcast::error::RecvError;
+
+/// Most blocks a single supply scan may span.
+pub const MAX_SUPPLY_SCAN_BLOCKS: u64 = 100_000;
+
+const ETH: u128 = 1_000_000_000_000_000_000;
+
+const GWEI: u128 = 1_000_000_000;
+
+/// Returns the ether minted by a block for its miner and for the miners of its uncles, nothing
+/// after the merge.
+pub(crate) fn block_rewards(
+    chain: &ChainSpec,
+    number: u64,
+    merged: bool,
+    uncles: &[Header],
+) -> (u128, u128) {
+    if merged {
+        return (0, 0)
+    }
+    let base = if chain.fork(Hardfork::Constantinople).active_at_block(number) {
+        2 * ETH
+    } else if chain.fork(Hardfork::Byzantium).active_at_block(number) {
+        3 * ETH
+    } else {
+        5 * ETH
+    };
+
+    let block_reward = base + base / 32 * uncles.len() as u128;
+    let uncle_reward = uncles
+        .iter()
+        .map(|uncle| (8 + uncle.number as u128 - number as u128) * base / 8)
+        .sum::<u128>();
+    (block_reward, uncle_reward)
+}
+
+/// How a block changed the supply of ether, in wei.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SupplyDelta {
+    pub block_number: u64,
+    pub block_hash: EthersH256,
+    pub timestamp: u64,
+    /// Rewards of the miner and the uncles, nothing after the merge.
+    pub issuance: EthersU256,
+    /// Ether withdrawn from the beacon chain.
+    pub withdrawals: EthersU256,
+    /// Base fees burned, per EIP-1559.
+    pub burned: EthersU256,
+    /// Issuance and withdrawals less the burn.
+    pub delta: I256,
+    /// Sum of the deltas from the first block of the range, or of the subscription.
+    pub cumulative_delta: I256,
+}
+
+impl SupplyDelta {
+    /// Returns how a block changed the supply, its cumulative delta left to the caller.
+    fn of(
+        chain: &ChainSpec,
+        header: &SealedHeader,
+        uncles: &[Header],
+        withdrawals: Option<&[Withdrawal]>,
+    ) -> Self {
+        let merged = header.difficulty == U256::ZERO;
+        let (block_reward, uncle_reward) = block_rewards(chain, header.number, merged, uncles);
+        let issuance = block_reward + uncle_reward;
+        let withdrawn = withdrawals
+            .unwrap_or_default()
+            .iter()
+            .map(|withdrawal| withdrawal.amount as u128 * GWEI)
+            .sum::<u128>();
+        let burned = header.base_fee_per_gas.unwrap_or_default() as u128 * header.gas_used as u128;
+        Self {
+            block_number: header.number,
+            block_hash: header.hash().into_ethers(),
+            timestamp: header.timestamp,
+            issuance: issuance.into(),
+            withdrawals: withdrawn.into(),
+            burned: burned.into(),
+            delta: I256::from(issuance as i128 + withdrawn as i128 - burned as i128),
+            cumulative_delta: I256::zero(),
+        }
+    }
+}
+
+/// A subscription to a [SupplyDelta] per newly committed block, built on canonical state
+/// notifications. Reverted blocks are taken out of the cumulative delta.
+#[derive(Debug)]
+pub struct SupplySubscription {
+    notifications: CanonStateNotifications,
+    chain: Arc<ChainSpec>,
+    cumulative_delta: I256,
+    queued: VecDeque<SupplyDelta>,
+    lag_policy: LagPolicy,
+    ended: bool,
+}
+
+impl SupplySubscription {
+    pub fn new(notifications: CanonStateNotifications, chain: Arc<ChainSpec>) -> Self {
+        Self {
+            notifications,
+            chain,
+            cumulative_delta: I256::zero(),
+            queued: VecDeque::new(),
+            lag_policy: LagPolicy::default(),
+            ended: false,
+        }
+    }
+
+    /// Sets what the subscription does when it misses notifications, by default reporting them
+    /// and going on. The cumulative delta does not cover the blocks missed.
+    pub fn with_lag_policy(mut self, lag_policy: LagPolicy) -> Self {
+        self.lag_policy = lag_policy;
+        self
+    }
+
+    /// Waits for the delta of the next block, or the number of notifications missed, `None` once
+    /// the notification channel closes.
+    pub async fn next(&mut self) -> Option<Result<SupplyDelta, Lagged>> {
+        loop {
+            if let Some(delta) = self.queued.pop_front() {
+                return Some(Ok(delta))
+            }
+            if self.ended {
+                return None
+            }
+            match self.notifications.recv().await {
+                Ok(notification) => self.process(&notification),
+                Err(RecvError::Lagged(missed)) => {
+                    self.ended = self.lag_policy == LagPolicy::Error;
+                    return Some(Err(Lagged(missed)))
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Turns the subscription into a stream of deltas.
+    pub fn into_stream(self) -> impl Stream<Item = Result<SupplyDelta, Lagged>> {
+        futures::stream::unfold(self, |mut subscription| async move {
+            subscription.next().await.map(|delta| (delta, subscription))
+        })
+    }
+
+    fn process(&mut self, notification: &CanonStateNotification) {
+        let chain = self.chain.clone();
+        let delta_of = |block: &SealedBlockWithSenders| {
+            SupplyDelta::of(&chain, &block.header, &block.ommers, block.withdrawals.as_deref())
+        };
+        for block in notification.reverted().iter().flat_map(|chain| chain.blocks().values()) {
+            self.cumulative_delta -= delta_of(block).delta;
+        }
+        for block in notification.committed().iter().flat_map(|chain| chain.blocks().values()) {
+            let mut delta = delta_of(block);
+            self.cumulative_delta += delta.delta;
+            delta.cumulative_delta = self.cumulative_delta;
+            self.queued.push_back(delta);
+        }
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns how every canonical block in `range` changed the supply of ether: the rewards it
+    /// minted, the withdrawals it credited and the base fees it burned, read from the headers,
+    /// uncles and withdrawals of the blocks.
+    pub async fn supply_deltas(
+        &self,
+        range: RangeInclusive<u64>,
+    ) -> Result<Vec<SupplyDelta>, RethMiddlewareError<M>> {
+        instrument("supply_deltas", async {
+            record_block(&range);
+            let (first, last) = range.clone().into_inner();
+            if first > last || last - first >= MAX_SUPPLY_SCAN_BLOCKS {
+                return Err(EthApiError::InvalidParams(format!(
+                    "at most {MAX_SUPPLY_SCAN_BLOCKS} blocks can be scanned"
+                ))
+                .into())
+            }
+
+            let chain = self.reth_provider.chain_spec();
+            let headers =
+                db_read("sealed_headers_range", || self.reth_provider.sealed_headers_range(range))?;
+            let mut cumulative_delta = I256::zero();
+            let mut deltas = Vec::with_capacity(headers.len());
+            for header in headers {
+                let uncles = if header.ommers_hash == EMPTY_OMMER_ROOT {
+                    vec![]
+                } else {
+                    db_read("ommers", || self.reth_provider.ommers(header.number.into()))?
+                        .unwrap_or_default()
+                };
+                let withdrawals = match header.withdrawals_root {
+                    Some(_) => db_read("withdrawals_by_block", || {
+                        self.reth_provider
+                            .withdrawals_by_block(header.number.into(), header.timestamp)
+                    })?,
+                    None => None,
+                };
+
+                let mut delta = SupplyDelta::of(&chain, &header, &uncles, withdrawals.as_deref());
+                cumulative_delta += delta.delta;
+                delta.cumulative_delta = cumulative_delta;
+                deltas.push(delta);
+            }
+            Ok(deltas)
+        })
+        .await
+    }
+
+    /// Subscribes to how every newly committed block changes the supply of ether.
+    pub fn watch_supply(&self) -> SupplySubscription {
+        SupplySubscription::new(
+            self.subscribe_to_canonical_state(),
+            self.reth_provider.chain_spec(),
+        )
+        .with_lag_policy(self.fanout.lag_policy())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reth_primitives::{SealedBlock, MAINNET};
+    use reth_provider::{Chain, PostState};
+    use tokio::sync::broadcast;
+
+    /// A block after the merge burning `gas_used` gwei, of the fork `fork`.
+    fn block(number: u64, gas_used: u64, fork: u8) -> SealedBlockWithSenders {
+        let header = Header {
+            number,
+            gas_used,
+            base_fee_per_gas: Some(GWEI as u64),
+            extra_data: vec![fork].into(),
+            ..Default::default()
+        };
+        SealedBlockWithSenders {
+            block: SealedBlock { header: header.seal_slow(), ..Default::default() },
+            senders: vec![],
+        }
+    }
+
+    fn chain(blocks: Vec<SealedBlockWithSenders>) -> Arc<Chain> {
+        Arc::new(Chain::new(
+            blocks.into_iter().map(|block| (block, PostState::default())).collect(),
+        ))
+    }
+
+    fn gwei(amount: i128) -> I256 {
+        I256::from(amount * GWEI as i128)
+    }
+
+    #[test]
+    fn block_rewards_follow_the_forks() {
+        assert_eq!(block_rewards(&MAINNET, 1_000_000, false, &[]), (5 * ETH, 0));
+        assert_eq!(block_rewards(&MAINNET, 4_370_000, false, &[]), (3 * ETH, 0));
+        assert_eq!(block_rewards(&MAINNET, 7_280_000, false, &[]), (2 * ETH, 0));
+        assert_eq!(block_rewards(&MAINNET, 15_537_394, true, &[]), (0, 0));
+    }
+
+    #[test]
+    fn uncles_reward_both_miners() {
+        let uncle = Header { number: 7_280_008, ..Default::default() };
+        // the miner gets 1/32 of the base reward per uncle, the uncle's miner 6/8 of it two
+        // blocks later
+        assert_eq!(
+            block_rewards(&MAINNET, 7_280_010, false, &[uncle]),
+            (2 * ETH + ETH / 16, 3 * ETH / 2)
+        );
+    }
+
+    #[test]
+    fn deltas_add_withdrawals_and_subtract_the_burn() {
+        let withdrawals = [
+            Withdrawal { amount: 32_000_000_000, ..Default::default() },
+            Withdrawal { amount: 1_000, ..Default::default() },
+        ];
+        let block = block(17_034_870, 1_000_000, 0);
+        let delta = SupplyDelta::of(&MAINNET, &block.header, &[], Some(&withdrawals));
+
+        assert_eq!(delta.issuance, EthersU256::zero());
+        assert_eq!(delta.withdrawals, EthersU256::from(32 * ETH + 1_000 * GWEI));
+        assert_eq!(delta.burned, EthersU256::from(1_000_000 * GWEI));
+        assert_eq!(delta.delta, gwei(32_000_000_000 + 1_000 - 1_000_000));
+    }
+
+    #[tokio::test]
+    async fn reorgs_take_reverted_blocks_out_of_the_cumulative_delta() {
+        let (sender, receiver) = broadcast::channel(8);
+        let mut subscription = SupplySubscription::new(receiver, MAINNET.clone());
+
+        sender
+            .send(CanonStateNotification::Commit {
+                new: chain(vec![block(1, 10, 0), block(2, 20, 0)]),
+            })
+            .unwrap();
+        sender
+            .send(CanonStateNotification::Reorg {
+                old: chain(vec![block(2, 20, 0)]),
+                new: chain(vec![block(2, 5, 1), block(3, 1, 1)]),
+            })
+            .unwrap();
+        drop(sender);
+
+        let mut cumulative = vec![];
+        while let Some(delta) = subscription.next().await {
+            let delta = delta.unwrap();
+            cumulative.push((delta.block_number, delta.cumulative_delta));
+        }
+        assert_eq!(
+            cumulative,
+            vec![(1, gwei(-10)), (2, gwei(-30)), (2, gwei(-15)), (3, gwei(-16))]
+        );
+    }
+
+    #[tokio::test]
+    async fn missed_notifications_are_reported() {
+        let (sender, receiver) = broadcast::channel(1);
+        let mut subscription =
+            SupplySubscription::new(receiver, MAINNET.clone()).with_lag_policy(LagPolicy::Error);
+        for number in 1..=2 {
+            sender
+                .send(CanonStateNotification::Commit { new: chain(vec![block(number, 0, 0)]) })
+                .unwrap();
+        }
+
+        assert_eq!(subscription.next().await, Some(Err(Lagged(1))));
+        assert_eq!(subscription.next().await, None);
+    }
+}
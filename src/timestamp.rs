@@ -0,0 +1,233 @@
+use crate::{
+    instrument::{convert, db_read, instrument, record_block},
+    reorg::DEFAULT_REORG_WINDOW,
+    type_conversions::ToEthers,
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Block as EthersBlock, H256 as EthersH256},
+};
+
+// Reth
+use reth_provider::{BlockNumReader, HeaderProvider};
+
+// Std
+use std::{
+    collections::BTreeMap,
+    ops::RangeInclusive,
+    sync::{Arc, Mutex},
+};
+
+/// Most block timestamps remembered by the lookups by timestamp.
+pub const MAX_TIMESTAMP_PIVOTS: usize = 16_384;
+
+/// The timestamps of the blocks probed by lookups by timestamp, shared by the clones of a
+/// middleware. Only blocks deeper than the reorg window are kept, as they no longer change.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TimestampPivots(Arc<Mutex<BTreeMap<u64, u64>>>);
+
+impl TimestampPivots {
+    fn get(&self, number: u64) -> Option<u64> {
+        let timestamp = self.0.lock().unwrap().get(&number).copied();
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_cache_lookup("timestamp_pivots", timestamp.is_some());
+        timestamp
+    }
+
+    fn insert(&self, number: u64, timestamp: u64) {
+        let mut pivots = self.0.lock().unwrap();
+        pivots.insert(number, timestamp);
+        if pivots.len() > MAX_TIMESTAMP_PIVOTS {
+            pivots.pop_first();
+        }
+    }
+}
+
+/// Returns the number of the last block up to `tip` with a timestamp at or before `timestamp`,
+/// binary searching the timestamps `at` reads from `hint`.
+fn search_timestamp<E>(
+    timestamp: u64,
+    hint: Option<u64>,
+    tip: u64,
+    mut at: impl FnMut(u64) -> Result<u64, E>,
+) -> Result<Option<u64>, E> {
+    if at(0)? > timestamp {
+        return Ok(None)
+    }
+    if at(tip)? <= timestamp {
+        return Ok(Some(tip))
+    }
+
+    // the block at `lo` is at or before `timestamp`, the one at `hi` after it
+    let (mut lo, mut hi) = (0, tip);
+    if let Some(hint) = hint.filter(|hint| *hint > 0 && *hint < tip) {
+        // widen the window from the hint until it brackets the timestamp
+        let mut step = 1;
+        if at(hint)? <= timestamp {
+            lo = hint;
+            while lo + step < hi {
+                if at(lo + step)? > timestamp {
+                    hi = lo + step;
+                    break
+                }
+                lo += step;
+                step *= 2;
+            }
+        } else {
+            hi = hint;
+            while step < hi - lo {
+                if at(hi - step)? <= timestamp {
+                    lo = hi - step;
+                    break
+                }
+                hi -= step;
+                step *= 2;
+            }
+        }
+    }
+
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        if at(mid)? <= timestamp {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    Ok(Some(lo))
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the header of the last canonical block with a timestamp at or before `timestamp`,
+    /// i.e. the head of the chain at that time, `None` before genesis.
+    ///
+    /// Headers are binary searched, starting around `hint` if given, e.g. a block number
+    /// estimated from the slot time. The timestamps probed are cached, so nearby lookups read
+    /// few headers.
+    pub async fn get_block_by_timestamp(
+        &self,
+        timestamp: u64,
+        hint: Option<u64>,
+    ) -> Result<Option<EthersBlock<EthersH256>>, RethMiddlewareError<M>> {
+        instrument("get_block_by_timestamp", async {
+            record_block(&timestamp);
+            let Some(number) = self.block_number_at(timestamp, hint)? else { return Ok(None) };
+            let header = db_read("sealed_header", || self.reth_provider.sealed_header(number))?;
+            Ok(convert("header", || header.map(ToEthers::into_ethers)))
+        })
+        .await
+    }
+
+    /// Returns the numbers of the canonical blocks with a timestamp from `start` to `end`, both
+    /// included, `None` if there are none.
+    pub async fn blocks_in_time_range(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> Result<Option<RangeInclusive<u64>>, RethMiddlewareError<M>> {
+        instrument("blocks_in_time_range", async {
+            record_block(&(start..=end));
+            let first = match start.checked_sub(1) {
+                Some(before) => self.block_number_at(before, None)?.map_or(0, |number| number + 1),
+                None => 0,
+            };
+            let last = self.block_number_at(end, Some(first))?;
+            Ok(last.filter(|last| *last >= first).map(|last| first..=last))
+        })
+        .await
+    }
+
+    /// Returns the number of the last canonical block with a timestamp at or before
+    /// `timestamp`, searching from `hint`.
+    fn block_number_at(
+        &self,
+        timestamp: u64,
+        hint: Option<u64>,
+    ) -> Result<Option<u64>, RethMiddlewareError<M>> {
+        let tip = db_read("best_block_number", || self.reth_provider.best_block_number())?;
+        search_timestamp(timestamp, hint, tip, |number| self.block_timestamp(number, tip))
+    }
+
+    /// Returns the timestamp of a canonical block, from the pivots if it is deeper than the
+    /// reorg window below `tip`.
+    fn block_timestamp(&self, number: u64, tip: u64) -> Result<u64, RethMiddlewareError<M>> {
+        let settled = number.saturating_add(DEFAULT_REORG_WINDOW as u64) <= tip;
+        if settled {
+            if let Some(timestamp) = self.timestamp_pivots.get(number) {
+                return Ok(timestamp)
+            }
+        }
+
+        let header = db_read("header_by_number", || self.reth_provider.header_by_number(number))?
+            .ok_or(RethMiddlewareError::BlockNotFound)?;
+        if settled {
+            self.timestamp_pivots.insert(number, header.timestamp);
+        }
+        Ok(header.timestamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Slots of 12 seconds from 1000, with the slots of blocks 4 and 7 missed.
+    const TIMESTAMPS: [u64; 10] = [1000, 1012, 1024, 1036, 1060, 1072, 1084, 1108, 1120, 1132];
+
+    fn search(timestamp: u64, hint: Option<u64>) -> Option<u64> {
+        let tip = TIMESTAMPS.len() as u64 - 1;
+        search_timestamp::<()>(timestamp, hint, tip, |number| Ok(TIMESTAMPS[number as usize]))
+            .unwrap()
+    }
+
+    #[test]
+    fn finds_the_last_block_at_or_before_the_timestamp() {
+        for timestamp in 990..1150 {
+            let expected = TIMESTAMPS.iter().rposition(|at| *at <= timestamp).map(|n| n as u64);
+            for hint in [None, Some(0), Some(1), Some(4), Some(8), Some(9), Some(100)] {
+                assert_eq!(search(timestamp, hint), expected, "{timestamp} from {hint:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn close_hints_read_few_timestamps() {
+        let probes = |hint| {
+            let mut probes = 0;
+            let number = search_timestamp::<()>(1000 + 12 * 500_000, hint, 1_000_000, |number| {
+                probes += 1;
+                Ok(1000 + 12 * number)
+            });
+            assert_eq!(number, Ok(Some(500_000)));
+            probes
+        };
+        // genesis, the tip, the hint and the block after it
+        assert_eq!(probes(Some(500_000)), 4);
+        assert!(probes(Some(499_990)) < 12);
+        assert!(probes(None) > 20);
+    }
+
+    #[test]
+    fn read_errors_end_the_search() {
+        let result = search_timestamp(1050, None, 9, |number| match number {
+            0 | 9 => Ok(TIMESTAMPS[number as usize]),
+            _ => Err(number),
+        });
+        assert_eq!(result, Err(4));
+    }
+
+    #[test]
+    fn pivots_are_bounded() {
+        let pivots = TimestampPivots::default();
+        for number in 0..=MAX_TIMESTAMP_PIVOTS as u64 {
+            pivots.insert(number, number * 12);
+        }
+        assert_eq!(pivots.get(0), None);
+        assert_eq!(pivots.get(1), Some(12));
+        assert_eq!(pivots.0.lock().unwrap().len(), MAX_TIMESTAMP_PIVOTS);
+    }
+}
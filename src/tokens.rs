@@ -0,0 +1,237 @@
+use crate::{RethMiddleware, RethMiddlewareError};
+use ethers::{
+    abi::{self, ParamType, Token},
+    providers::Middleware,
+    types::{Address, Filter, Log, ValueOrArray, H256, U256},
+};
+use hex_literal::hex;
+use serde::{Deserialize, Serialize};
+
+// Std
+use std::{collections::BTreeMap, ops::RangeInclusive};
+
+/// Topic of the ERC-20 and ERC-721 `Transfer(address,address,uint256)` event.
+pub const TRANSFER_TOPIC: H256 =
+    H256(hex!("ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"));
+
+/// Topic of the ERC-1155 `TransferSingle(address,address,address,uint256,uint256)` event.
+pub const TRANSFER_SINGLE_TOPIC: H256 =
+    H256(hex!("c3d58168c5ae7397731d063d5bbf3d657854427343f4c083240f7aacaa2d0f62"));
+
+/// Topic of the ERC-1155 `TransferBatch(address,address,address,uint256[],uint256[])` event.
+pub const TRANSFER_BATCH_TOPIC: H256 =
+    H256(hex!("4a39dc06d4c0dbc64b70af90fd698a233a518aa5d07e595d983b8c0526c8f7fb"));
+
+/// An ERC-20 transfer, decoded from its `Transfer` log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenTransfer {
+    pub token: Address,
+    pub from: Address,
+    pub to: Address,
+    pub value: U256,
+    pub block_number: Option<u64>,
+    pub transaction_hash: Option<H256>,
+    pub log_index: Option<U256>,
+}
+
+/// Decodes an ERC-20 `Transfer` log, `None` if the log is not one.
+///
+/// ERC-721 transfers share the event signature but index the token id, so they have four topics
+/// and are not matched. Tokens appending data after the amount are accepted, the amount being read
+/// from the first word.
+pub fn decode_erc20_transfer(log: &Log) -> Option<TokenTransfer> {
+    if log.topics.len() != 3 || log.topics[0] != TRANSFER_TOPIC || log.data.len() < 32 {
+        return None
+    }
+    Some(TokenTransfer {
+        token: log.address,
+        from: Address::from(log.topics[1]),
+        to: Address::from(log.topics[2]),
+        value: U256::from_big_endian(&log.data[..32]),
+        block_number: log.block_number.map(|number| number.as_u64()),
+        transaction_hash: log.transaction_hash,
+        log_index: log.log_index,
+    })
+}
+
+/// Token standard of an [NftTransfer].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NftStandard {
+    Erc721,
+    Erc1155,
+}
+
+/// A transfer of an ERC-721 token, or of an amount of an ERC-1155 token id. Mints are transfers
+/// from the zero address and burns transfers to it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NftTransfer {
+    pub token: Address,
+    pub standard: NftStandard,
+    /// Account that made an ERC-1155 transfer, ERC-721 transfers do not log it.
+    pub operator: Option<Address>,
+    pub from: Address,
+    pub to: Address,
+    pub token_id: U256,
+    /// Always one for ERC-721 transfers.
+    pub amount: U256,
+    pub block_number: Option<u64>,
+    pub transaction_hash: Option<H256>,
+    pub log_index: Option<U256>,
+}
+
+/// Decodes an ERC-721 `Transfer` or ERC-1155 `TransferSingle` or `TransferBatch` log into its
+/// transfers, one per token id. Logs that are none of these, or are malformed, give none.
+pub fn decode_nft_transfers(log: &Log) -> Vec<NftTransfer> {
+    let transfer = |standard, operator, from: H256, to: H256, token_id, amount| NftTransfer {
+        token: log.address,
+        standard,
+        operator,
+        from: Address::from(from),
+        to: Address::from(to),
+        token_id,
+        amount,
+        block_number: log.block_number.map(|number| number.as_u64()),
+        transaction_hash: log.transaction_hash,
+        log_index: log.log_index,
+    };
+
+    match log.topics.as_slice() {
+        [topic, from, to, token_id] if *topic == TRANSFER_TOPIC => vec![transfer(
+            NftStandard::Erc721,
+            None,
+            *from,
+            *to,
+            U256::from_big_endian(token_id.as_bytes()),
+            U256::one(),
+        )],
+        [topic, operator, from, to] if *topic == TRANSFER_SINGLE_TOPIC && log.data.len() >= 64 => {
+            vec![transfer(
+                NftStandard::Erc1155,
+                Some(Address::from(*operator)),
+                *from,
+                *to,
+                U256::from_big_endian(&log.data[..32]),
+                U256::from_big_endian(&log.data[32..64]),
+            )]
+        }
+        [topic, operator, from, to] if *topic == TRANSFER_BATCH_TOPIC => {
+            let Some((ids, amounts)) = decode_batch(&log.data) else { return vec![] };
+            ids.into_iter()
+                .zip(amounts)
+                .map(|(id, amount)| {
+                    transfer(
+                        NftStandard::Erc1155,
+                        Some(Address::from(*operator)),
+                        *from,
+                        *to,
+                        id,
+                        amount,
+                    )
+                })
+                .collect()
+        }
+        _ => vec![],
+    }
+}
+
+/// Decodes the ids and amounts of a `TransferBatch` log, `None` if they do not pair up.
+fn decode_batch(data: &[u8]) -> Option<(Vec<U256>, Vec<U256>)> {
+    let array = ParamType::Array(Box::new(ParamType::Uint(256)));
+    let uints = |token: Token| -> Option<Vec<U256>> {
+        token.into_array()?.into_iter().map(Token::into_uint).collect()
+    };
+    let mut tokens = abi::decode(&[array.clone(), array], data).ok()?.into_iter();
+    let ids = uints(tokens.next()?)?;
+    let amounts = uints(tokens.next()?)?;
+    (ids.len() == amounts.len()).then_some((ids, amounts))
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the transfers of an ERC-20 token in the blocks of `range`, in order.
+    ///
+    /// Logs that do not decode as an ERC-20 transfer are skipped.
+    pub async fn get_token_transfers(
+        &self,
+        token: Address,
+        range: RangeInclusive<u64>,
+    ) -> Result<Vec<TokenTransfer>, RethMiddlewareError<M>> {
+        let filter = transfer_filter(&range).address(token);
+        Ok(self.get_logs(&filter).await?.iter().filter_map(decode_erc20_transfer).collect())
+    }
+
+    /// Returns the ERC-20 transfers of any token from or to `address` in the blocks of `range`, in
+    /// order.
+    pub async fn get_address_token_transfers(
+        &self,
+        address: Address,
+        range: RangeInclusive<u64>,
+    ) -> Result<Vec<TokenTransfer>, RethMiddlewareError<M>> {
+        let topic = H256::from(address);
+        let sent = self.get_logs(&transfer_filter(&range).topic1(topic)).await?;
+        let received = self.get_logs(&transfer_filter(&range).topic2(topic)).await?;
+
+        let mut transfers: Vec<TokenTransfer> =
+            sent.iter().chain(&received).filter_map(decode_erc20_transfer).collect();
+        transfers.sort_by_key(|transfer| (transfer.block_number, transfer.log_index));
+        // transfers to self are in both queries
+        transfers.dedup_by_key(|transfer| (transfer.block_number, transfer.log_index));
+        Ok(transfers)
+    }
+
+    /// Returns the ERC-721 and ERC-1155 transfers of an NFT contract in the blocks of `range`, in
+    /// order.
+    pub async fn get_nft_transfers(
+        &self,
+        token: Address,
+        range: RangeInclusive<u64>,
+    ) -> Result<Vec<NftTransfer>, RethMiddlewareError<M>> {
+        let filter = nft_transfer_filter(&range).address(token);
+        Ok(self.get_logs(&filter).await?.iter().flat_map(decode_nft_transfers).collect())
+    }
+
+    /// Returns the holders of a token id of an NFT contract after `block`, with the amount each
+    /// holds, reconstructed by replaying the transfers of the id from genesis.
+    ///
+    /// An ERC-721 token has at most one holder, holding one. A token that was never minted, or
+    /// was burnt, has none.
+    pub async fn get_nft_owners(
+        &self,
+        token: Address,
+        token_id: U256,
+        block: u64,
+    ) -> Result<BTreeMap<Address, U256>, RethMiddlewareError<M>> {
+        let transfers = self.get_nft_transfers(token, 0..=block).await?;
+
+        let mut owners = BTreeMap::new();
+        for transfer in transfers.into_iter().filter(|transfer| transfer.token_id == token_id) {
+            if !transfer.from.is_zero() {
+                let balance: &mut U256 = owners.entry(transfer.from).or_default();
+                *balance = balance.saturating_sub(transfer.amount);
+            }
+            if !transfer.to.is_zero() {
+                let balance: &mut U256 = owners.entry(transfer.to).or_default();
+                *balance = balance.saturating_add(transfer.amount);
+            }
+        }
+        owners.retain(|_, balance| !balance.is_zero());
+        Ok(owners)
+    }
+}
+
+/// Returns a filter on the `Transfer` logs of the blocks of `range`.
+fn transfer_filter(range: &RangeInclusive<u64>) -> Filter {
+    Filter::new().from_block(*range.start()).to_block(*range.end()).topic0(TRANSFER_TOPIC)
+}
+
+/// Returns a filter on the ERC-721 and ERC-1155 transfer logs of the blocks of `range`.
+fn nft_transfer_filter(range: &RangeInclusive<u64>) -> Filter {
+    Filter::new().from_block(*range.start()).to_block(*range.end()).topic0(ValueOrArray::Array(
+        vec![Some(TRANSFER_TOPIC), Some(TRANSFER_SINGLE_TOPIC), Some(TRANSFER_BATCH_TOPIC)],
+    ))
+}
@@ -0,0 +1,290 @@
+//! Alignment of two call trees, e.g. of a transaction and of its modified replay, to find where
+//! their executions diverge.
+use crate::{
+    call_tree::{serialize_selector, CallFrame, FrameKind, FrameStatus},
+    execution::replay::Change,
+};
+use ethers::{
+    abi::Selector,
+    types::{Address, Bytes},
+};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FrameChange {
+    /// Only the first execution has the frame.
+    Removed,
+    /// Only the second execution has the frame.
+    Added,
+    /// Both executions have the frame, which differs.
+    Changed,
+}
+
+/// A frame of two call trees that differs, or that only one of them has. Frames only one tree has
+/// are reported without their children.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrameDiff {
+    pub change: FrameChange,
+    /// Position of the frame in the first tree.
+    pub first_trace_address: Option<Vec<usize>>,
+    /// Position of the frame in the second tree.
+    pub second_trace_address: Option<Vec<usize>>,
+    pub depth: usize,
+    pub kind: FrameKind,
+    pub to: Option<Address>,
+    #[serde(serialize_with = "serialize_selector")]
+    pub selector: Option<Selector>,
+    /// Gas the second frame used more than the first, negative if less.
+    pub gas_used_delta: i128,
+    pub input: Option<Change<Bytes>>,
+    pub output: Option<Change<Bytes>>,
+    pub status: Option<Change<FrameStatus>>,
+}
+
+impl FrameDiff {
+    fn only(change: FrameChange, frame: &CallFrame) -> Self {
+        let gas_used = frame.gas_used.as_u128() as i128;
+        let (first_trace_address, second_trace_address, gas_used_delta) = match change {
+            FrameChange::Removed => (Some(frame.trace_address.clone()), None, -gas_used),
+            _ => (None, Some(frame.trace_address.clone()), gas_used),
+        };
+        Self {
+            change,
+            first_trace_address,
+            second_trace_address,
+            depth: frame.depth,
+            kind: frame.kind,
+            to: frame.to,
+            selector: frame.selector,
+            gas_used_delta,
+            input: None,
+            output: None,
+            status: None,
+        }
+    }
+}
+
+/// How a call tree differs from another, frame by frame.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceDiff {
+    /// Gas the second execution used more than the first, negative if less.
+    pub gas_used_delta: i128,
+    /// The frames that differ, in execution order.
+    pub frames: Vec<FrameDiff>,
+}
+
+impl TraceDiff {
+    /// Returns where the executions part ways: the first frame only one of them has or called
+    /// with another input, else the deepest frame whose status or output differs.
+    pub fn divergence(&self) -> Option<&FrameDiff> {
+        self.frames
+            .iter()
+            .find(|frame| frame.change != FrameChange::Changed || frame.input.is_some())
+            .or_else(|| {
+                self.frames
+                    .iter()
+                    .filter(|frame| frame.status.is_some() || frame.output.is_some())
+                    .rev()
+                    .max_by_key(|frame| frame.depth)
+            })
+    }
+}
+
+/// Frames of two trees with the same key are taken for the same frame.
+fn frame_key(frame: &CallFrame) -> (FrameKind, Option<Address>, Option<Selector>) {
+    (frame.kind, frame.to, frame.selector)
+}
+
+/// Pairs up the frames of `first` and `second` along the longest common subsequence of their
+/// keys, in order. Unpaired frames come with `None`.
+fn align<'a>(
+    first: &'a [CallFrame],
+    second: &'a [CallFrame],
+) -> Vec<(Option<&'a CallFrame>, Option<&'a CallFrame>)> {
+    let (n, m) = (first.len(), second.len());
+    let mut lengths = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[i][j] = if frame_key(&first[i]) == frame_key(&second[j]) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut pairs = Vec::with_capacity(n.max(m));
+    while i < n || j < m {
+        if i < n && j < m && frame_key(&first[i]) == frame_key(&second[j]) {
+            pairs.push((Some(&first[i]), Some(&second[j])));
+            i += 1;
+            j += 1;
+        } else if j == m || (i < n && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            pairs.push((Some(&first[i]), None));
+            i += 1;
+        } else {
+            pairs.push((None, Some(&second[j])));
+            j += 1;
+        }
+    }
+    pairs
+}
+
+fn change<T: PartialEq + Clone>(first: &T, second: &T) -> Option<Change<T>> {
+    (first != second).then(|| Change { first: Some(first.clone()), second: Some(second.clone()) })
+}
+
+fn diff_frames(first: &CallFrame, second: &CallFrame, diffs: &mut Vec<FrameDiff>) {
+    let gas_used_delta = second.gas_used.as_u128() as i128 - first.gas_used.as_u128() as i128;
+    let input = change(&first.input, &second.input);
+    let output = change(&first.output, &second.output);
+    let status = change(&first.status, &second.status);
+    if gas_used_delta != 0 || input.is_some() || output.is_some() || status.is_some() {
+        diffs.push(FrameDiff {
+            change: FrameChange::Changed,
+            first_trace_address: Some(first.trace_address.clone()),
+            second_trace_address: Some(second.trace_address.clone()),
+            depth: second.depth,
+            kind: second.kind,
+            to: second.to,
+            selector: second.selector,
+            gas_used_delta,
+            input,
+            output,
+            status,
+        });
+    }
+
+    for pair in align(&first.children, &second.children) {
+        match pair {
+            (Some(first), Some(second)) => diff_frames(first, second, diffs),
+            (Some(first), None) => diffs.push(FrameDiff::only(FrameChange::Removed, first)),
+            (None, Some(second)) => diffs.push(FrameDiff::only(FrameChange::Added, second)),
+            (None, None) => unreachable!("aligned frames come from either tree"),
+        }
+    }
+}
+
+/// Aligns two call trees, e.g. of a transaction and of its modified replay, and reports the
+/// frames only one of them has and the frames whose input, output, status or gas differ.
+///
+/// Children are matched by their kind, callee and selector, in order, so a call inserted or
+/// dropped does not shift the frames after it. The top-level frames are always matched.
+pub fn diff_traces(first: &CallFrame, second: &CallFrame) -> TraceDiff {
+    let mut frames = vec![];
+    diff_frames(first, second, &mut frames);
+    TraceDiff {
+        gas_used_delta: second.gas_used.as_u128() as i128 - first.gas_used.as_u128() as i128,
+        frames,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::U256;
+
+    fn frame(trace_address: Vec<usize>, to: u64, gas_used: u64) -> CallFrame {
+        CallFrame {
+            depth: trace_address.len(),
+            trace_address,
+            kind: FrameKind::Call,
+            from: Address::zero(),
+            to: Some(Address::from_low_u64_be(to)),
+            value: U256::zero(),
+            selector: None,
+            input: Bytes::default(),
+            output: Bytes::default(),
+            gas: U256::from(100_000),
+            gas_used: U256::from(gas_used),
+            status: FrameStatus::Success,
+            error: None,
+            revert_reason: None,
+            decoded: None,
+            children: vec![],
+        }
+    }
+
+    fn tree(children: Vec<CallFrame>) -> CallFrame {
+        let gas_used = children.iter().map(|child| child.gas_used.as_u64()).sum::<u64>();
+        CallFrame { children, ..frame(vec![], 1, 21_000 + gas_used) }
+    }
+
+    #[test]
+    fn identical_trees_do_not_differ() {
+        let first = tree(vec![frame(vec![0], 2, 5_000), frame(vec![1], 3, 7_000)]);
+        let diff = diff_traces(&first, &first.clone());
+
+        assert_eq!(diff, TraceDiff::default());
+        assert!(diff.divergence().is_none());
+    }
+
+    #[test]
+    fn inserted_calls_do_not_shift_the_frames_after_them() {
+        let first = tree(vec![frame(vec![0], 2, 5_000), frame(vec![1], 3, 7_000)]);
+        let second = tree(vec![
+            frame(vec![0], 2, 5_000),
+            frame(vec![1], 4, 2_000),
+            frame(vec![2], 3, 7_000),
+        ]);
+        let diff = diff_traces(&first, &second);
+
+        assert_eq!(diff.gas_used_delta, 2_000);
+        // the top-level frame used more gas, the inserted call is the only other difference
+        assert_eq!(diff.frames.len(), 2);
+        let added = &diff.frames[1];
+        assert_eq!(added.change, FrameChange::Added);
+        assert_eq!(added.second_trace_address, Some(vec![1]));
+        assert_eq!(added.first_trace_address, None);
+        assert_eq!(added.gas_used_delta, 2_000);
+        assert_eq!(diff.divergence(), Some(added));
+    }
+
+    #[test]
+    fn dropped_calls_are_removed() {
+        let first = tree(vec![frame(vec![0], 2, 5_000), frame(vec![1], 3, 7_000)]);
+        let second = tree(vec![frame(vec![0], 3, 7_000)]);
+        let diff = diff_traces(&first, &second);
+
+        assert_eq!(diff.gas_used_delta, -5_000);
+        let removed = diff.divergence().unwrap();
+        assert_eq!(removed.change, FrameChange::Removed);
+        assert_eq!(removed.first_trace_address, Some(vec![0]));
+        assert_eq!(removed.gas_used_delta, -5_000);
+    }
+
+    #[test]
+    fn divergence_is_the_deepest_changed_outcome_without_other_changes() {
+        let first = tree(vec![frame(vec![0], 2, 5_000)]);
+        let mut second = first.clone();
+        second.output = vec![1].into();
+        second.status = FrameStatus::Reverted;
+        second.children[0].status = FrameStatus::Failed;
+        let diff = diff_traces(&first, &second);
+
+        assert_eq!(diff.frames.len(), 2);
+        let divergence = diff.divergence().unwrap();
+        assert_eq!(divergence.depth, 1);
+        assert_eq!(
+            divergence.status,
+            Some(Change { first: Some(FrameStatus::Success), second: Some(FrameStatus::Failed) })
+        );
+    }
+
+    #[test]
+    fn divergence_is_the_first_call_with_another_input() {
+        let first = tree(vec![frame(vec![0], 2, 5_000), frame(vec![1], 3, 7_000)]);
+        let mut second = first.clone();
+        second.children[0].input = vec![1].into();
+        second.children[1].status = FrameStatus::Reverted;
+        let diff = diff_traces(&first, &second);
+
+        let divergence = diff.divergence().unwrap();
+        assert_eq!(divergence.first_trace_address, Some(vec![0]));
+        assert!(divergence.input.is_some());
+    }
+}
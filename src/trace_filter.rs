@@ -0,0 +1,130 @@
+//! `trace_filter`, served by tracing the blocks of the range one by one, as the database keeps no
+//! index of traces.
+use crate::{
+    instrument::{convert, record_block},
+    type_conversions::TryToEthers,
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Action, Address, BlockNumber, Res, Trace, TraceFilter},
+};
+use serde::Deserialize;
+
+// Reth
+use reth_primitives::BlockId;
+use reth_rpc::eth::error::EthApiError;
+use reth_rpc_api::TraceApiServer;
+
+/// Most blocks a single `trace_filter` may span, unless [TraceLimits::max_filter_blocks] sets
+/// another bound.
+///
+/// [TraceLimits::max_filter_blocks]: crate::limits::TraceLimits::max_filter_blocks
+pub const MAX_TRACE_FILTER_BLOCKS: u64 = 1_000;
+
+/// The fields of a [TraceFilter], which ethers keeps private.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TraceFilterParams {
+    from_block: Option<BlockNumber>,
+    to_block: Option<BlockNumber>,
+    from_address: Option<Vec<Address>>,
+    to_address: Option<Vec<Address>>,
+    after: Option<usize>,
+    count: Option<usize>,
+}
+
+/// Returns the sender and the recipient of a trace as parity filters them: the callee or the
+/// created contract, the beneficiary of a self-destruct, and the author of a reward.
+pub fn trace_addresses(trace: &Trace) -> (Option<Address>, Option<Address>) {
+    match &trace.action {
+        Action::Call(call) => (Some(call.from), Some(call.to)),
+        Action::Create(create) => {
+            let created = match &trace.result {
+                Some(Res::Create(result)) => Some(result.address),
+                _ => None,
+            };
+            (Some(create.from), created)
+        }
+        Action::Suicide(suicide) => (Some(suicide.address), Some(suicide.refund_address)),
+        Action::Reward(reward) => (None, Some(reward.author)),
+    }
+}
+
+/// An address filter matches any address when missing or empty.
+fn matches_address(filter: &Option<Vec<Address>>, address: Option<Address>) -> bool {
+    match filter {
+        None => true,
+        Some(addresses) if addresses.is_empty() => true,
+        Some(addresses) => address.map_or(false, |address| addresses.contains(&address)),
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Serves `trace_filter` by tracing every block of the filter's range, `latest` for a missing
+    /// bound, keeping the traces whose sender and recipient match the filter's addresses. The
+    /// trace limits apply to each block as it is traced, failing the scan once the kept traces
+    /// outgrow the output size limit.
+    pub(crate) async fn local_trace_filter(
+        &self,
+        filter: &TraceFilter,
+    ) -> Result<Vec<Trace>, RethMiddlewareError<M>> {
+        self.with_heavy_permit(self.scan_traces(filter)).await
+    }
+
+    async fn scan_traces(
+        &self,
+        filter: &TraceFilter,
+    ) -> Result<Vec<Trace>, RethMiddlewareError<M>> {
+        let params: TraceFilterParams = serde_json::to_value(filter)
+            .and_then(serde_json::from_value)
+            .map_err(|err| RethMiddlewareError::ConversionError(err.to_string()))?;
+        let first = self.log_range_bound(params.from_block.unwrap_or(BlockNumber::Latest))?;
+        let last = self.log_range_bound(params.to_block.unwrap_or(BlockNumber::Latest))?;
+        record_block(&(first..=last));
+        let max_blocks = self.trace_limits().max_filter_blocks.unwrap_or(MAX_TRACE_FILTER_BLOCKS);
+        if first > last || last - first >= max_blocks {
+            return Err(EthApiError::InvalidParams(format!(
+                "at most {max_blocks} blocks can be scanned"
+            ))
+            .into())
+        }
+        self.ensure_history(Some(BlockId::Number(first.into())))?;
+
+        let mut skip = params.after.unwrap_or_default();
+        let count = params.count.unwrap_or(usize::MAX);
+        let mut traces = vec![];
+        // the size of the response, checked as traces are kept
+        let mut size = 0;
+        for number in first..=last {
+            let block_traces = self.reth_trace.trace_block(BlockId::Number(number.into())).await?;
+            let block_traces = block_traces.ok_or(RethMiddlewareError::MissingTrace)?;
+            let mut block_traces: Vec<Trace> =
+                convert("traces", || block_traces.try_into_ethers())?;
+            self.truncate_trace(&mut block_traces);
+            for trace in block_traces {
+                let (from, to) = trace_addresses(&trace);
+                if !matches_address(&params.from_address, from) ||
+                    !matches_address(&params.to_address, to)
+                {
+                    continue
+                }
+                if skip > 0 {
+                    skip -= 1;
+                    continue
+                }
+                // the separator of the trace in the JSON array
+                size += 1;
+                self.charge_trace(&mut size, &trace)?;
+                traces.push(trace);
+                if traces.len() >= count {
+                    return Ok(traces)
+                }
+            }
+        }
+        Ok(traces)
+    }
+}
@@ -0,0 +1,431 @@
+use crate::{
+    fanout::{self, LagPolicy},
+    init::NodeComponents,
+    server::ServerError,
+    shutdown::BackgroundTasks,
+    RethMiddleware, RethMiddlewareError,
+};
+use async_trait::async_trait;
+use futures::{
+    future::{join_all, ready},
+    stream::BoxStream,
+    StreamExt,
+};
+use jsonrpsee::{
+    core::{server::rpc_module::Subscription, traits::ToRpcParams, Error as RpcModuleError},
+    types::{error::CallError, SubscriptionId},
+    RpcModule,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{value::RawValue, Value};
+use thiserror::Error;
+
+// Ethers
+use ethers::{
+    providers::{
+        JsonRpcClient, JsonRpcError, Middleware, MiddlewareError, Provider, ProviderError,
+        PubsubClient, RpcError, SubscriptionStream, Ws, WsClientError,
+    },
+    types::{Block, Filter, Log, TxHash, U256},
+};
+
+// Std
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard},
+};
+use tokio::{runtime::Handle, task::JoinHandle};
+
+/// Methods never served locally, as the local pool does not broadcast transactions: they go to the
+/// fallback, or the inner provider of a middleware, and fail without one.
+const REMOTE_METHODS: &[&str] = &["eth_sendRawTransaction", "eth_sendTransaction"];
+
+/// An ethers transport answering requests from the database, in process.
+///
+/// An alternative to wrapping a provider in [RethMiddleware]: `Provider::new(transport)` exposes
+/// the standard [Provider] API, with every request dispatched to the same handlers a reth node
+/// serves its RPC with.
+///
+/// Subscriptions, `subscribe_blocks`, `subscribe_logs` and `subscribe_pending_txs` included, are
+/// served in process too, from the canonical state notifications and the transaction pool. Each
+/// buffers up to the subscriber buffer of the middleware it was built from, under its lag
+/// policy, see [RethMiddleware::with_lag_policy].
+///
+/// With a WebSocket fallback, methods the database cannot serve and transaction submission are
+/// forwarded to the remote node, so a single provider covers both local reads and remote writes.
+/// Without one, transaction submission fails rather than leaving the transaction in a pool that
+/// never broadcasts it.
+#[derive(Clone)]
+pub struct RethTransport {
+    module: Arc<RpcModule<()>>,
+    fallback: Option<Ws>,
+    subscriptions: Arc<Mutex<HashMap<U256, LocalSubscription>>>,
+    buffer_size: usize,
+    lag_policy: LagPolicy,
+    tasks: BackgroundTasks,
+}
+
+/// A subscription served in process, until [PubsubClient::subscribe] hands out its stream.
+enum LocalSubscription {
+    Installed(Subscription),
+    /// Forwarding the notifications to the stream handed out, removed once they end.
+    Streaming(JoinHandle<()>),
+}
+
+impl Debug for RethTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RethTransport").finish_non_exhaustive()
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum RethTransportError {
+    /// The handler returned an error response.
+    #[error(transparent)]
+    JsonRpcError(JsonRpcError),
+
+    /// The params or the response could not be (de)serialized.
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+
+    /// The request could not be dispatched, e.g. the method is unknown.
+    #[error("{0}")]
+    Dispatch(String),
+
+    /// An error occurred in the WebSocket fallback.
+    #[error(transparent)]
+    WsClientError(#[from] WsClientError),
+
+    /// The database could not be opened.
+    #[error(transparent)]
+    Database(#[from] reth_interfaces::Error),
+
+    /// The handlers could not be registered.
+    #[error(transparent)]
+    Module(#[from] ServerError),
+}
+
+impl RpcError for RethTransportError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            RethTransportError::JsonRpcError(err) => Some(err),
+            RethTransportError::WsClientError(err) => err.as_error_response(),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            RethTransportError::SerdeJson(err) => Some(err),
+            RethTransportError::WsClientError(err) => err.as_serde_error(),
+            _ => None,
+        }
+    }
+}
+
+impl From<RethTransportError> for ProviderError {
+    fn from(err: RethTransportError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(err))
+    }
+}
+
+impl From<RpcModuleError> for RethTransportError {
+    fn from(err: RpcModuleError) -> Self {
+        match err {
+            RpcModuleError::Call(CallError::Custom(err)) => {
+                RethTransportError::JsonRpcError(JsonRpcError {
+                    code: err.code() as i64,
+                    message: err.message().to_string(),
+                    data: err.data().and_then(|data| serde_json::from_str(data.get()).ok()),
+                })
+            }
+            RpcModuleError::ParseError(err) => RethTransportError::SerdeJson(err),
+            err => RethTransportError::Dispatch(err.to_string()),
+        }
+    }
+}
+
+/// A request of a batch sent with [RethMiddleware::batch].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchRequest {
+    pub method: String,
+    /// The params, `null` for none.
+    #[serde(default)]
+    pub params: Value,
+}
+
+impl BatchRequest {
+    pub fn new<T: Serialize>(method: &str, params: T) -> Result<Self, serde_json::Error> {
+        Ok(Self { method: method.to_string(), params: serde_json::to_value(params)? })
+    }
+}
+
+/// Params already serialized by ethers.
+struct RawParams(Option<Box<RawValue>>);
+
+impl ToRpcParams for RawParams {
+    fn to_rpc_params(self) -> Result<Option<Box<RawValue>>, serde_json::Error> {
+        Ok(self.0)
+    }
+}
+
+/// The inner provider of a transport built without a middleware: there is no remote node, so
+/// every request fails.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Disconnected;
+
+#[async_trait]
+impl JsonRpcClient for Disconnected {
+    type Error = RethTransportError;
+
+    async fn request<T, R>(&self, method: &str, _params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        Err(RethTransportError::Dispatch(format!("{method} needs a remote node, there is none")))
+    }
+}
+
+impl RethTransport {
+    /// Opens the database at `db_path`.
+    pub fn new<P: AsRef<Path>>(db_path: P, handle: Handle) -> Result<Self, RethTransportError> {
+        let components =
+            RethMiddleware::<Provider<Disconnected>>::try_new(db_path.as_ref(), handle)?;
+        Self::from_node(components)
+    }
+
+    /// Creates the transport from the components of a running node.
+    pub fn from_node(components: NodeComponents) -> Result<Self, RethTransportError> {
+        // the handlers never reach the inner provider, it only fills the type parameter
+        let middleware = RethMiddleware::from_node(Provider::new(Disconnected), components);
+        Self::from_middleware(&middleware)
+    }
+
+    /// Creates the transport sharing the handlers of an existing middleware.
+    pub fn from_middleware<M: Middleware>(
+        middleware: &RethMiddleware<M>,
+    ) -> Result<Self, RethTransportError> {
+        let mut module = middleware.rpc_module()?;
+        module.merge(middleware.pubsub_module()?).map_err(ServerError::from)?;
+        Ok(Self {
+            module: Arc::new(module),
+            fallback: None,
+            subscriptions: Arc::default(),
+            buffer_size: middleware.fanout.buffer_size(),
+            lag_policy: middleware.fanout.lag_policy(),
+            tasks: middleware.tasks.clone(),
+        })
+    }
+
+    /// Forwards the methods the database cannot serve to a remote node.
+    pub fn with_ws_fallback(mut self, ws: Ws) -> Self {
+        self.fallback = Some(ws);
+        self
+    }
+
+    /// Returns true if the method is served from the database.
+    pub fn handles(&self, method: &str) -> bool {
+        !REMOTE_METHODS.contains(&method) && self.module.method(method).is_some()
+    }
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Sends a raw JSON-RPC request, for methods the [Middleware] trait does not cover.
+    ///
+    /// Methods of the `eth`, `trace`, `debug` and `web3` namespaces are answered locally, and
+    /// anything else, e.g. a chain specific namespace, is forwarded to the inner provider, as is
+    /// transaction submission, since the local pool does not broadcast.
+    pub async fn request<T, R>(&self, method: &str, params: T) -> Result<R, RethMiddlewareError<M>>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let transport = self.local_provider().await?.as_ref();
+        if transport.handles(method) {
+            Ok(transport.request(method, params).await?)
+        } else {
+            self.inner
+                .provider()
+                .request(method, params)
+                .await
+                .map_err(RethMiddlewareError::from_provider_err)
+        }
+    }
+
+    /// Sends a batch of requests, like a JSON-RPC batch, returning their results in the order of
+    /// the requests. Requests are dispatched as with [Self::request] and run concurrently, and
+    /// one failing does not fail the others.
+    pub async fn batch(
+        &self,
+        requests: Vec<BatchRequest>,
+    ) -> Vec<Result<Value, RethMiddlewareError<M>>> {
+        join_all(
+            requests
+                .iter()
+                .map(|request| self.request::<_, Value>(&request.method, &request.params)),
+        )
+        .await
+    }
+
+    /// Streams the new canonical blocks from the canonical state notifications, like
+    /// [Middleware::subscribe_blocks] on a provider over [RethTransport], whatever the inner
+    /// provider's transport.
+    ///
+    /// This and the other `subscribe_*` methods are inherent, they do not override the
+    /// [Middleware] ones, whose streams are typed by the inner provider. They only apply when
+    /// called on a `RethMiddleware`: through `M: Middleware`, e.g. from a middleware wrapping this
+    /// one, the trait methods subscribe through the inner provider, and need it to be a pubsub
+    /// transport.
+    pub async fn subscribe_blocks(
+        &self,
+    ) -> Result<SubscriptionStream<'_, RethTransport, Block<TxHash>>, RethMiddlewareError<M>> {
+        self.local_provider()
+            .await?
+            .subscribe_blocks()
+            .await
+            .map_err(RethMiddlewareError::from_provider_err)
+    }
+
+    /// Streams the logs of new canonical blocks matching `filter`, see [Self::subscribe_blocks].
+    pub async fn subscribe_logs<'a>(
+        &'a self,
+        filter: &Filter,
+    ) -> Result<SubscriptionStream<'a, RethTransport, Log>, RethMiddlewareError<M>> {
+        self.local_provider()
+            .await?
+            .subscribe_logs(filter)
+            .await
+            .map_err(RethMiddlewareError::from_provider_err)
+    }
+
+    /// Streams the hashes of the transactions entering the pool, see [Self::subscribe_blocks].
+    pub async fn subscribe_pending_txs(
+        &self,
+    ) -> Result<SubscriptionStream<'_, RethTransport, TxHash>, RethMiddlewareError<M>> {
+        self.local_provider()
+            .await?
+            .subscribe_pending_txs()
+            .await
+            .map_err(RethMiddlewareError::from_provider_err)
+    }
+
+    /// Returns a provider over the transport sharing the middleware's handlers, created on first
+    /// use.
+    async fn local_provider(&self) -> Result<&Provider<RethTransport>, RethMiddlewareError<M>> {
+        let transport = self
+            .transport
+            .get_or_try_init(|| async { RethTransport::from_middleware(self).map(Provider::new) })
+            .await?;
+        Ok(transport)
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for RethTransport {
+    type Error = RethTransportError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        if !self.handles(method) {
+            match &self.fallback {
+                Some(ws) => return Ok(ws.request(method, params).await?),
+                None if REMOTE_METHODS.contains(&method) => {
+                    return Err(RethTransportError::Dispatch(format!(
+                        "{method} needs a remote node, the local pool does not broadcast"
+                    )))
+                }
+                None => {}
+            }
+        }
+
+        let params = serde_json::to_value(params)?;
+        let params =
+            if params.is_null() { None } else { Some(serde_json::value::to_raw_value(&params)?) };
+
+        match method {
+            "eth_subscribe" => {
+                let subscription =
+                    self.module.subscribe(method, RawParams(params), self.buffer_size).await?;
+                let id = subscription_id(subscription.subscription_id())?;
+                self.subscriptions().insert(id, LocalSubscription::Installed(subscription));
+                Ok(serde_json::from_value(serde_json::to_value(id)?)?)
+            }
+            "eth_unsubscribe" => {
+                let [id]: [U256; 1] =
+                    serde_json::from_str(params.as_ref().map_or("[]", |p| p.get()))?;
+                let removed = self.remove_subscription(id);
+                Ok(serde_json::from_value(Value::Bool(removed))?)
+            }
+            _ => Ok(self.module.call(method, RawParams(params)).await?),
+        }
+    }
+}
+
+impl PubsubClient for RethTransport {
+    type NotificationStream = BoxStream<'static, Box<RawValue>>;
+
+    fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, Self::Error> {
+        let id = id.into();
+        let mut subscriptions = self.subscriptions();
+        let Some(LocalSubscription::Installed(mut subscription)) = subscriptions.remove(&id) else {
+            return Err(RethTransportError::Dispatch(format!("unknown subscription {id:#x}")))
+        };
+
+        let (publisher, notifications) = fanout::channel(self.buffer_size, self.lag_policy);
+        let entries = self.subscriptions.clone();
+        let task = tokio::spawn(async move {
+            while let Some(Ok((notification, _))) = subscription.next::<Box<RawValue>>().await {
+                if !publisher.send(Ok(notification)).await {
+                    break
+                }
+            }
+            // waits for the entry to be inserted, as the map stays locked until then
+            entries.lock().unwrap().remove(&id);
+        });
+        self.tasks.register(&task);
+        subscriptions.insert(id, LocalSubscription::Streaming(task));
+        // ethers streams have no gap markers, missed notifications are skipped
+        Ok(notifications.into_stream().filter_map(|item| ready(item.ok())).boxed())
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, id: T) -> Result<(), Self::Error> {
+        self.remove_subscription(id.into());
+        Ok(())
+    }
+}
+
+impl RethTransport {
+    fn subscriptions(&self) -> MutexGuard<'_, HashMap<U256, LocalSubscription>> {
+        self.subscriptions.lock().unwrap()
+    }
+
+    /// Drops a subscription, which ends it, returning false if it was not installed.
+    fn remove_subscription(&self, id: U256) -> bool {
+        match self.subscriptions().remove(&id) {
+            Some(LocalSubscription::Streaming(task)) => {
+                task.abort();
+                true
+            }
+            Some(LocalSubscription::Installed(_)) => true,
+            None => false,
+        }
+    }
+}
+
+/// Reads reth's subscription ids, hex strings, as the numbers ethers keys subscriptions by.
+fn subscription_id(id: &SubscriptionId<'_>) -> Result<U256, RethTransportError> {
+    match id {
+        SubscriptionId::Num(id) => Ok(U256::from(*id)),
+        SubscriptionId::Str(id) => U256::from_str_radix(id.trim_start_matches("0x"), 16)
+            .map_err(|_| RethTransportError::Dispatch(format!("invalid subscription id {id}"))),
+    }
+}
@@ -0,0 +1,84 @@
+//! Detection and backfill of the transaction hash lookup index, for databases whose node pruned
+//! it, so transactions of the whole retained range can be looked up by hash again.
+//!
+//! The middleware opens databases read-only, the backfill opens its own writable handle. MDBX
+//! serializes writers, so it can run next to a node, but a node still pruning lookups will prune
+//! the backfilled entries again.
+use crate::{init::view, pruning::PruneLimits};
+
+// Reth
+use reth_db::{
+    cursor::DbCursorRO,
+    database::Database,
+    mdbx::{Env, EnvKind, WriteMap},
+    tables,
+    transaction::{DbTx, DbTxMut},
+    DatabaseError,
+};
+
+// Std
+use std::{ops::RangeInclusive, path::Path};
+
+/// Blocks indexed per write transaction by default.
+pub const DEFAULT_BACKFILL_BATCH_BLOCKS: u64 = 10_000;
+
+/// What a backfill of the transaction lookup index did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TxLookupBackfill {
+    /// Blocks whose transactions were checked.
+    pub blocks: u64,
+    /// Transactions checked.
+    pub transactions: u64,
+    /// Lookup entries written, for the transactions that had none.
+    pub inserted: u64,
+}
+
+/// Opens the database at the specified path writable, for the backfill.
+pub fn open_db_rw<P: AsRef<Path>>(path: P) -> Result<Env<WriteMap>, DatabaseError> {
+    Env::<WriteMap>::open(path.as_ref(), EnvKind::RW, None)
+}
+
+/// Returns the blocks whose transactions may lack lookup entries, `None` if every transaction
+/// has one. Pruning removes the oldest entries first, so these are the oldest blocks.
+pub fn missing_transaction_lookups(
+    db: &Env<WriteMap>,
+) -> Result<Option<RangeInclusive<u64>>, DatabaseError> {
+    let Some(limit) = PruneLimits::detect(db)?.transaction_lookup else { return Ok(None) };
+    Ok(Some(0..=limit))
+}
+
+/// Writes the missing lookup entries of the transactions of `blocks`, committing every
+/// `batch_blocks` blocks so an interrupted backfill keeps its progress. Entries that exist are
+/// left untouched, so ranges can be backfilled again.
+pub fn backfill_transaction_lookups(
+    db: &Env<WriteMap>,
+    blocks: RangeInclusive<u64>,
+    batch_blocks: u64,
+) -> Result<TxLookupBackfill, DatabaseError> {
+    let mut report = TxLookupBackfill::default();
+    let (mut start, end) = blocks.into_inner();
+    let last_block = view(db, |tx| tx.cursor_read::<tables::BlockBodyIndices>()?.last())??
+        .map_or(0, |(number, _)| number);
+    let end = end.min(last_block);
+
+    while start <= end {
+        let batch_end = start.saturating_add(batch_blocks.max(1) - 1).min(end);
+        let tx = db.tx_mut()?;
+        for number in start..=batch_end {
+            let Some(indices) = tx.get::<tables::BlockBodyIndices>(number)? else { continue };
+            report.blocks += 1;
+            for tx_num in indices.tx_num_range() {
+                let Some(transaction) = tx.get::<tables::Transactions>(tx_num)? else { continue };
+                report.transactions += 1;
+                let hash = transaction.hash();
+                if tx.get::<tables::TxHashNumber>(hash)?.is_none() {
+                    tx.put::<tables::TxHashNumber>(hash, tx_num)?;
+                    report.inserted += 1;
+                }
+            }
+        }
+        tx.commit()?;
+        start = batch_end + 1;
+    }
+    Ok(report)
+}
@@ -0,0 +1,55 @@
+use crate::{RethMiddleware, RethMiddlewareError};
+use ethers::{
+    abi::{Detokenize, Function, HumanReadableParser, Tokenize},
+    providers::Middleware,
+    types::{BlockId as EthersBlockId, Bytes as EthersBytes, NameOrAddress, TransactionRequest},
+};
+
+/// Returns the calldata of a call to `function` with `args`.
+pub fn encode_call<A: Tokenize>(
+    function: &Function,
+    args: A,
+) -> Result<EthersBytes, ethers::abi::Error> {
+    function.encode_input(&args.into_tokens()).map(Into::into)
+}
+
+/// Parses a human-readable function signature, e.g.
+/// `function balanceOf(address) view returns (uint256)`.
+pub fn parse_function(signature: &str) -> Result<Function, ethers::abi::ParseError> {
+    Ok(HumanReadableParser::parse_function(signature)?)
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Calls a function of a contract and decodes its return values, without generating bindings
+    /// for the contract. `function` is a human-readable signature, e.g.
+    /// `function getReserves() view returns (uint112, uint112, uint32)`.
+    ///
+    /// The call runs on the local EVM like [Middleware::call].
+    pub async fn call_typed<T, A, C>(
+        &self,
+        contract: C,
+        function: &str,
+        args: A,
+        block: Option<EthersBlockId>,
+    ) -> Result<T, RethMiddlewareError<M>>
+    where
+        T: Detokenize,
+        A: Tokenize,
+        C: Into<NameOrAddress>,
+    {
+        let function = parse_function(function)
+            .map_err(|err| RethMiddlewareError::AbiError(err.to_string()))?;
+        let data = encode_call(&function, args)
+            .map_err(|err| RethMiddlewareError::AbiError(err.to_string()))?;
+        let tx = TransactionRequest::new().to(contract).data(data).into();
+
+        let output = self.call(&tx, block).await?;
+        let tokens = function
+            .decode_output(&output)
+            .map_err(|err| RethMiddlewareError::AbiError(err.to_string()))?;
+        T::from_tokens(tokens).map_err(|err| RethMiddlewareError::AbiError(err.to_string()))
+    }
+}
@@ -0,0 +1,300 @@
+use crate::{
+    instrument::{db_read, instrument, record_block},
+    type_conversions::{ToEthers, ToReth},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{
+        Block as EthersBlock, BlockId as EthersBlockId, Bloom as EthersBloom,
+        TransactionReceipt as EthersTransactionReceipt, H256 as EthersH256, U256 as EthersU256,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_primitives::{proofs, BlockId, Bloom, Log, Receipt, ReceiptWithBloom, TxType};
+use reth_provider::{
+    BlockExecutor, BlockIdReader, BlockNumReader, ChainSpecProvider, ExecutorFactory,
+    HeaderProvider, ReceiptProvider, StateProviderFactory, StateRootProvider,
+};
+use reth_revm::Factory;
+
+/// A value as a header commits to it and as recomputed from the block's data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Comparison<T> {
+    pub expected: T,
+    pub computed: T,
+}
+
+impl<T: PartialEq> Comparison<T> {
+    pub fn matches(&self) -> bool {
+        self.expected == self.computed
+    }
+}
+
+/// How the receipts of a block compare against its header.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptsReport {
+    pub receipts_root: Comparison<EthersH256>,
+    pub logs_bloom: Comparison<EthersBloom>,
+    /// Gas used by the block against the cumulative gas used of its last receipt.
+    pub gas_used: Comparison<EthersU256>,
+    /// Indexes of the receipts whose bloom is not the bloom of their logs.
+    pub mismatched_receipt_blooms: Vec<usize>,
+}
+
+impl ReceiptsReport {
+    /// Returns true if the receipts match the header.
+    pub fn is_valid(&self) -> bool {
+        self.receipts_root.matches() &&
+            self.logs_bloom.matches() &&
+            self.gas_used.matches() &&
+            self.mismatched_receipt_blooms.is_empty()
+    }
+
+    /// Compares receipts, their blooms recomputed from their logs, against a header's roots.
+    fn new(
+        receipts_root: EthersH256,
+        logs_bloom: EthersBloom,
+        gas_used: EthersU256,
+        receipts: &[ReceiptWithBloom],
+    ) -> Self {
+        let computed_bloom =
+            receipts.iter().fold(Bloom::zero(), |bloom, receipt| bloom | receipt.bloom);
+        let computed_gas =
+            receipts.last().map(|receipt| receipt.receipt.cumulative_gas_used).unwrap_or_default();
+        Self {
+            receipts_root: Comparison {
+                expected: receipts_root,
+                computed: proofs::calculate_receipt_root(receipts).into_ethers(),
+            },
+            logs_bloom: Comparison { expected: logs_bloom, computed: computed_bloom.into_ethers() },
+            gas_used: Comparison { expected: gas_used, computed: computed_gas.into() },
+            mismatched_receipt_blooms: vec![],
+        }
+    }
+}
+
+/// A receipt of a re-executed block that differs from the one in the database.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptMismatch {
+    pub index: usize,
+    pub transaction_hash: EthersH256,
+    pub success: Comparison<bool>,
+    pub cumulative_gas_used: Comparison<EthersU256>,
+    pub logs_bloom: Comparison<EthersBloom>,
+}
+
+/// How the re-execution of a block from its parent's state compares against its header and the
+/// receipts in the database.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReexecutionReport {
+    pub gas_used: Comparison<EthersU256>,
+    pub receipts_root: Comparison<EthersH256>,
+    pub logs_bloom: Comparison<EthersBloom>,
+    /// `None` unless the block is the tip, the database only having the state root inputs of
+    /// the latest state.
+    pub state_root: Option<Comparison<EthersH256>>,
+    /// Receipts that differ from the ones in the database, empty if those were pruned.
+    pub mismatched_receipts: Vec<ReceiptMismatch>,
+}
+
+impl ReexecutionReport {
+    /// Returns true if the re-execution matches the header and the stored receipts.
+    pub fn is_valid(&self) -> bool {
+        self.gas_used.matches() &&
+            self.receipts_root.matches() &&
+            self.logs_bloom.matches() &&
+            self.state_root.as_ref().map_or(true, Comparison::matches) &&
+            self.mismatched_receipts.is_empty()
+    }
+}
+
+/// Converts a receipt back into the consensus receipt it was built from, its bloom recomputed
+/// from its logs.
+pub fn consensus_receipt(receipt: &EthersTransactionReceipt) -> ReceiptWithBloom {
+    let tx_type = match receipt.transaction_type.map(|ty| ty.as_u64()) {
+        Some(1) => TxType::EIP2930,
+        Some(2) => TxType::EIP1559,
+        _ => TxType::Legacy,
+    };
+    let logs = receipt
+        .logs
+        .iter()
+        .map(|log| Log {
+            address: log.address.into_reth(),
+            topics: log.topics.iter().map(|topic| topic.into_reth()).collect(),
+            data: log.data.clone().into_reth(),
+        })
+        .collect();
+    Receipt {
+        tx_type,
+        success: receipt.status.map_or(false, |status| status.as_u64() == 1),
+        cumulative_gas_used: receipt.cumulative_gas_used.as_u64(),
+        logs,
+    }
+    .with_bloom()
+}
+
+/// Recomputes the receipts root, logs bloom and gas used of a block from its receipts, e.g. as
+/// another provider returned them, and compares them against its header.
+pub fn verify_receipts<TX>(
+    block: &EthersBlock<TX>,
+    receipts: &[EthersTransactionReceipt],
+) -> ReceiptsReport {
+    let consensus: Vec<ReceiptWithBloom> = receipts.iter().map(consensus_receipt).collect();
+    let mut report = ReceiptsReport::new(
+        block.receipts_root,
+        block.logs_bloom.unwrap_or_default(),
+        block.gas_used,
+        &consensus,
+    );
+    report.mismatched_receipt_blooms = receipts
+        .iter()
+        .zip(&consensus)
+        .enumerate()
+        .filter(|(_, (receipt, consensus))| receipt.logs_bloom != consensus.bloom.into_ethers())
+        .map(|(index, _)| index)
+        .collect();
+    report
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Recomputes the receipts root, logs bloom and gas used of a block from the receipts in the
+    /// database and compares them against its header, to check the database's integrity.
+    pub async fn verify_block_receipts<T: Into<EthersBlockId> + Send + Sync>(
+        &self,
+        block: T,
+    ) -> Result<ReceiptsReport, RethMiddlewareError<M>> {
+        instrument("verify_block_receipts", async {
+            let block_id: BlockId = block.into().into_reth();
+            record_block(&block_id);
+
+            let number = db_read("block_number_for_id", || {
+                self.reth_provider.block_number_for_id(block_id)
+            })?
+            .ok_or(RethMiddlewareError::BlockNotFound)?;
+            self.ensure_receipts(number)?;
+            let header =
+                db_read("header_by_number", || self.reth_provider.header_by_number(number))?
+                    .ok_or(RethMiddlewareError::BlockNotFound)?;
+            let receipts = db_read("receipts_by_block", || {
+                self.reth_provider.receipts_by_block(number.into())
+            })?
+            .ok_or(RethMiddlewareError::BlockNotFound)?;
+
+            let receipts: Vec<ReceiptWithBloom> =
+                receipts.into_iter().map(Receipt::with_bloom).collect();
+            Ok(ReceiptsReport::new(
+                header.receipts_root.into_ethers(),
+                header.logs_bloom.into_ethers(),
+                header.gas_used.into(),
+                &receipts,
+            ))
+        })
+        .await
+    }
+
+    /// Replays a block from its parent's state with the node's executor and compares the gas
+    /// used, receipts root, logs bloom and state root it computes against the block's header,
+    /// and its receipts against the stored ones, to check the database on suspicion of
+    /// corruption.
+    ///
+    /// The state root is only recomputed for the tip: applying the block's changes to the
+    /// latest state again leaves it as is, but older blocks would need the hashed state of their
+    /// time, which the database does not keep.
+    pub async fn reexecute_and_verify<T: Into<EthersBlockId> + Send + Sync>(
+        &self,
+        block: T,
+    ) -> Result<ReexecutionReport, RethMiddlewareError<M>> {
+        instrument("reexecute_and_verify", async {
+            let block_id: BlockId = block.into().into_reth();
+            record_block(&block_id);
+
+            let (block, senders) = self.sealed_block_with_senders(block_id)?;
+            self.ensure_history(Some(BlockId::Hash(block.parent_hash.into())))?;
+            let total_difficulty =
+                db_read("header_td", || self.reth_provider.header_td(&block.hash()))?
+                    .ok_or(RethMiddlewareError::BlockNotFound)?;
+            let state = db_read("history_by_block_hash", || {
+                self.reth_provider.history_by_block_hash(block.parent_hash)
+            })?;
+
+            let post_state = Factory::new(self.reth_provider.chain_spec())
+                .with_sp(state)
+                .execute(&block.clone().unseal(), total_difficulty, Some(senders))
+                .map_err(reth_interfaces::Error::from)?;
+            let receipts: Vec<ReceiptWithBloom> = post_state
+                .receipts(block.number)
+                .iter()
+                .cloned()
+                .map(Receipt::with_bloom)
+                .collect();
+            let computed = ReceiptsReport::new(
+                block.receipts_root.into_ethers(),
+                block.logs_bloom.into_ethers(),
+                block.gas_used.into(),
+                &receipts,
+            );
+
+            let tip = db_read("best_block_number", || self.reth_provider.best_block_number())?;
+            let state_root = if block.number == tip {
+                let latest = db_read("latest", || self.reth_provider.latest())?;
+                Some(Comparison {
+                    expected: block.state_root.into_ethers(),
+                    computed: latest.state_root(post_state)?.into_ethers(),
+                })
+            } else {
+                None
+            };
+
+            let stored = match self.ensure_receipts(block.number) {
+                Ok(()) => db_read("receipts_by_block", || {
+                    self.reth_provider.receipts_by_block(block.number.into())
+                })?
+                .unwrap_or_default(),
+                Err(_) => vec![],
+            };
+            let mismatched_receipts = stored
+                .into_iter()
+                .map(Receipt::with_bloom)
+                .zip(&receipts)
+                .zip(&block.body)
+                .enumerate()
+                .filter(|(_, ((stored, computed), _))| stored != *computed)
+                .map(|(index, ((stored, computed), tx))| ReceiptMismatch {
+                    index,
+                    transaction_hash: tx.hash().into_ethers(),
+                    success: Comparison {
+                        expected: stored.receipt.success,
+                        computed: computed.receipt.success,
+                    },
+                    cumulative_gas_used: Comparison {
+                        expected: stored.receipt.cumulative_gas_used.into(),
+                        computed: computed.receipt.cumulative_gas_used.into(),
+                    },
+                    logs_bloom: Comparison {
+                        expected: stored.bloom.into_ethers(),
+                        computed: computed.bloom.into_ethers(),
+                    },
+                })
+                .collect();
+
+            Ok(ReexecutionReport {
+                gas_used: computed.gas_used,
+                receipts_root: computed.receipts_root,
+                logs_bloom: computed.logs_bloom,
+                state_root,
+                mismatched_receipts,
+            })
+        })
+        .await
+    }
+}
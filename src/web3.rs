@@ -0,0 +1,31 @@
+use ethers::{
+    types::{Bytes as EthersBytes, H256 as EthersH256, U64 as EthersU64},
+    utils::keccak256,
+};
+use jsonrpsee::{core::Error as RpcError, RpcModule};
+
+/// Revision of reth the middleware reads the database with.
+pub const RETH_REVISION: &str = "31af4d5";
+
+/// Version of the `eth` wire protocol reported by `eth_protocolVersion`, the newest the pinned
+/// reth revision speaks.
+pub const ETH_PROTOCOL_VERSION: u64 = 67;
+
+/// Returns the version reported by `web3_clientVersion`, e.g. `ethers-reth/v0.1.0/reth-31af4d5`.
+pub fn client_version() -> String {
+    format!("ethers-reth/v{}/reth-{RETH_REVISION}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Returns the `web3_clientVersion`, `web3_sha3` and `eth_protocolVersion` methods, answered
+/// without the database so tools probing them work against the middleware.
+pub(crate) fn web3_module() -> Result<RpcModule<()>, RpcError> {
+    let mut module = RpcModule::new(());
+    module.register_method("web3_clientVersion", |_, _| Ok(client_version()))?;
+    module.register_method("web3_sha3", |params, _| {
+        let data: EthersBytes = params.one()?;
+        Ok(EthersH256::from(keccak256(data)))
+    })?;
+    module
+        .register_method("eth_protocolVersion", |_, _| Ok(EthersU64::from(ETH_PROTOCOL_VERSION)))?;
+    Ok(module)
+}
@@ -0,0 +1,84 @@
+use crate::{
+    instrument::{convert, db_read, instrument, record_block},
+    type_conversions::{ToEthers, ToReth},
+    RethMiddleware, RethMiddlewareError,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Address as EthersAddress, Withdrawal as EthersWithdrawal, H256 as EthersH256},
+};
+use serde::{Deserialize, Serialize};
+
+// Reth
+use reth_provider::{HeaderProvider, WithdrawalsProvider};
+use reth_rpc::eth::error::EthApiError;
+
+// Std
+use std::ops::RangeInclusive;
+
+/// Most blocks a single withdrawals scan may span.
+pub const MAX_WITHDRAWAL_SCAN_BLOCKS: u64 = 100_000;
+
+/// A withdrawal with the block that processed it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WithdrawalRecord {
+    pub block_number: u64,
+    pub block_hash: EthersH256,
+    pub timestamp: u64,
+    /// The withdrawal, its amount in gwei.
+    #[serde(flatten)]
+    pub withdrawal: EthersWithdrawal,
+}
+
+impl<M> RethMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Returns the withdrawals to `address` processed by the canonical blocks in `range`, in
+    /// order, read from the withdrawal lists of the blocks instead of their bodies.
+    ///
+    /// Blocks before Shanghai have no withdrawals and are skipped.
+    pub async fn get_withdrawals(
+        &self,
+        address: EthersAddress,
+        range: RangeInclusive<u64>,
+    ) -> Result<Vec<WithdrawalRecord>, RethMiddlewareError<M>> {
+        instrument("get_withdrawals", async {
+            record_block(&range);
+            let (first, last) = range.clone().into_inner();
+            if last.saturating_sub(first) >= MAX_WITHDRAWAL_SCAN_BLOCKS {
+                return Err(EthApiError::InvalidParams(format!(
+                    "at most {MAX_WITHDRAWAL_SCAN_BLOCKS} blocks can be scanned"
+                ))
+                .into())
+            }
+
+            let address = address.into_reth();
+            let headers =
+                db_read("sealed_headers_range", || self.reth_provider.sealed_headers_range(range))?;
+            let mut records = Vec::new();
+            for header in headers {
+                if header.withdrawals_root.is_none() {
+                    continue
+                }
+                let withdrawals = db_read("withdrawals_by_block", || {
+                    self.reth_provider.withdrawals_by_block(header.number.into(), header.timestamp)
+                })?;
+                for withdrawal in withdrawals.into_iter().flatten() {
+                    if withdrawal.address != address {
+                        continue
+                    }
+                    records.push(WithdrawalRecord {
+                        block_number: header.number,
+                        block_hash: header.hash().into_ethers(),
+                        timestamp: header.timestamp,
+                        withdrawal: convert("withdrawal", || withdrawal.into_ethers()),
+                    });
+                }
+            }
+            Ok(records)
+        })
+        .await
+    }
+}
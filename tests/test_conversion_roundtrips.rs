@@ -0,0 +1,216 @@
+//! Round trips of random values through the ethers <-> reth conversions. Besides the round trip,
+//! the converted value is compared to the original through a representation both sides share, so
+//! a byte order bug repeated in both directions is caught too.
+use ethers::types::{
+    transaction::eip2930::{
+        AccessList as EthersAccessList, AccessListItem as EthersAccessListItem,
+    },
+    BlockId as EthersBlockId, BlockNumber as EthersBlockNumber, Bloom as EthersBloom,
+    Bytes as EthersBytes, EIP1186ProofResponse as EthersEIP1186ProofResponse, Log as EthersLog,
+    StorageProof as EthersStorageProof, H160 as EthersH160, H256 as EthersH256, H64 as EthersH64,
+    U256 as EthersU256, U64 as EthersU64,
+};
+use ethers_reth::type_conversions::{ToEthers, ToReth};
+use proptest::{collection::vec, option, prelude::*};
+use reth_primitives::{
+    AccessList, BlockId, BlockNumberOrTag, Bloom, Bytes, H160, H256, H64, U128, U256, U64, U8,
+};
+use reth_rpc_types::{EIP1186AccountProofResponse, Log};
+
+fn h256() -> impl Strategy<Value = EthersH256> {
+    any::<[u8; 32]>().prop_map(EthersH256::from)
+}
+
+fn address() -> impl Strategy<Value = EthersH160> {
+    any::<[u8; 20]>().prop_map(EthersH160::from)
+}
+
+fn u256() -> impl Strategy<Value = EthersU256> {
+    any::<[u8; 32]>().prop_map(|bytes| EthersU256::from_big_endian(&bytes))
+}
+
+fn bytes() -> impl Strategy<Value = EthersBytes> {
+    vec(any::<u8>(), 0..128).prop_map(EthersBytes::from)
+}
+
+fn block_number() -> impl Strategy<Value = EthersBlockNumber> {
+    prop_oneof![
+        Just(EthersBlockNumber::Latest),
+        Just(EthersBlockNumber::Finalized),
+        Just(EthersBlockNumber::Safe),
+        Just(EthersBlockNumber::Earliest),
+        Just(EthersBlockNumber::Pending),
+        any::<u64>().prop_map(|number| EthersBlockNumber::Number(number.into())),
+    ]
+}
+
+fn access_list() -> impl Strategy<Value = EthersAccessList> {
+    let item = (address(), vec(h256(), 0..4))
+        .prop_map(|(address, storage_keys)| EthersAccessListItem { address, storage_keys });
+    vec(item, 0..4).prop_map(EthersAccessList)
+}
+
+/// A log as a node returns it, without the fields reth does not know.
+fn log() -> impl Strategy<Value = EthersLog> {
+    (
+        (address(), vec(h256(), 0..4), bytes(), option::of(h256())),
+        (option::of(any::<u64>()), option::of(h256()), option::of(any::<u64>())),
+        (option::of(u256()), option::of(any::<bool>())),
+    )
+        .prop_map(
+            |(
+                (address, topics, data, block_hash),
+                (block_number, transaction_hash, transaction_index),
+                (log_index, removed),
+            )| EthersLog {
+                address,
+                topics,
+                data,
+                block_hash,
+                block_number: block_number.map(Into::into),
+                transaction_hash,
+                transaction_index: transaction_index.map(Into::into),
+                log_index,
+                transaction_log_index: None,
+                log_type: None,
+                removed,
+            },
+        )
+}
+
+fn proof() -> impl Strategy<Value = EthersEIP1186ProofResponse> {
+    let storage_proof = (h256(), vec(bytes(), 0..4), u256())
+        .prop_map(|(key, proof, value)| EthersStorageProof { key, proof, value });
+    (
+        (address(), u256(), h256(), any::<u64>(), h256()),
+        (vec(bytes(), 0..4), vec(storage_proof, 0..4)),
+    )
+        .prop_map(
+            |(
+                (address, balance, code_hash, nonce, storage_hash),
+                (account_proof, storage_proof),
+            )| {
+                EthersEIP1186ProofResponse {
+                    address,
+                    balance,
+                    code_hash,
+                    nonce: nonce.into(),
+                    storage_hash,
+                    account_proof,
+                    storage_proof,
+                }
+            },
+        )
+}
+
+proptest! {
+    #[test]
+    fn test_hash_roundtrips(hash in h256(), address in address(), nonce in any::<[u8; 8]>()) {
+        let converted: H256 = hash.into_reth();
+        prop_assert_eq!(converted.as_bytes(), hash.as_bytes());
+        let back: EthersH256 = converted.into_ethers();
+        prop_assert_eq!(back, hash);
+
+        let converted: H160 = address.into_reth();
+        prop_assert_eq!(converted.as_bytes(), address.as_bytes());
+        let back: EthersH160 = converted.into_ethers();
+        prop_assert_eq!(back, address);
+
+        let nonce = EthersH64::from(nonce);
+        let converted: H64 = nonce.into_reth();
+        prop_assert_eq!(converted.as_bytes(), nonce.as_bytes());
+        let back: EthersH64 = converted.into_ethers();
+        prop_assert_eq!(back, nonce);
+    }
+
+    #[test]
+    fn test_bloom_and_bytes_roundtrips(bloom in vec(any::<u8>(), 256), data in bytes()) {
+        let bloom = EthersBloom::from_slice(&bloom);
+        let converted: Bloom = bloom.into_reth();
+        prop_assert_eq!(converted.as_bytes(), bloom.as_bytes());
+        let back: EthersBloom = converted.into_ethers();
+        prop_assert_eq!(back, bloom);
+
+        let converted: Bytes = data.clone().into_reth();
+        prop_assert_eq!(&converted[..], &data[..]);
+        let back: EthersBytes = converted.into_ethers();
+        prop_assert_eq!(back, data);
+    }
+
+    #[test]
+    fn test_uint_roundtrips(
+        value in u256(),
+        small in any::<u128>(),
+        word in any::<u64>(),
+        byte in any::<u8>(),
+    ) {
+        let converted: U256 = value.into_reth();
+        prop_assert_eq!(converted.to_string(), value.to_string());
+        let back: EthersU256 = converted.into_ethers();
+        prop_assert_eq!(back, value);
+
+        let converted: EthersU256 = U128::from(small).into_ethers();
+        prop_assert_eq!(converted.as_u128(), small);
+        prop_assert_eq!(ToReth::<U128>::into_reth(converted), U128::from(small));
+
+        let converted: EthersU256 = U64::from(word).into_ethers();
+        prop_assert_eq!(converted.as_u64(), word);
+        prop_assert_eq!(ToReth::<U64>::into_reth(converted), U64::from(word));
+
+        let converted: EthersU64 = U8::from(byte).into_ethers();
+        prop_assert_eq!(converted.as_u64(), byte as u64);
+        prop_assert_eq!(ToReth::<U8>::into_reth(converted), U8::from(byte));
+    }
+
+    /// The conversions to 64 bit integers keep the low 64 bits of larger values.
+    #[test]
+    fn test_uint_truncation(value in u256()) {
+        let converted: EthersU64 = ToReth::<U256>::into_reth(value).into_ethers();
+        prop_assert_eq!(converted.as_u64(), value.low_u64());
+
+        let converted: U64 = value.into_reth();
+        prop_assert_eq!(converted.as_u64(), value.low_u64());
+    }
+
+    #[test]
+    fn test_block_id_roundtrips(number in block_number(), hash in h256()) {
+        let converted: BlockNumberOrTag = number.into_reth();
+        let back: EthersBlockNumber = converted.into_ethers();
+        prop_assert_eq!(back, number);
+
+        let id = EthersBlockId::Number(number);
+        let converted: BlockId = id.into_reth();
+        let back: EthersBlockId = converted.into_ethers();
+        prop_assert_eq!(back, id);
+
+        let id = EthersBlockId::Hash(hash);
+        let converted: BlockId = id.into_reth();
+        let back: EthersBlockId = converted.into_ethers();
+        prop_assert_eq!(back, id);
+    }
+
+    #[test]
+    fn test_access_list_roundtrip(list in access_list()) {
+        let converted: AccessList = list.clone().into_reth();
+        prop_assert_eq!(converted.0.len(), list.0.len());
+        let back: EthersAccessList = converted.into_ethers();
+        prop_assert_eq!(back, list);
+    }
+
+    /// A log without `removed` comes back with `removed: false`.
+    #[test]
+    fn test_log_roundtrip(log in log()) {
+        let converted: Log = log.clone().into_reth();
+        let expected = EthersLog { removed: Some(log.removed.unwrap_or_default()), ..log };
+        let back: EthersLog = converted.into_ethers();
+        prop_assert_eq!(back, expected);
+    }
+
+    #[test]
+    fn test_proof_roundtrip(proof in proof()) {
+        let converted: EIP1186AccountProofResponse = proof.clone().into_reth();
+        prop_assert_eq!(converted.balance.to_string(), proof.balance.to_string());
+        let back: EthersEIP1186ProofResponse = converted.into_ethers();
+        prop_assert_eq!(back, proof);
+    }
+}
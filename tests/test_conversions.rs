@@ -0,0 +1,98 @@
+use ethers::types::{
+    transaction::eip2718::TypedTransaction, Block as EthersBlock, BlockId as EthersBlockId,
+    BlockNumber as EthersBlockNumber, Filter as EthersFilter, Transaction as EthersTransaction,
+    TransactionRequest, H256 as EthersH256, U256 as EthersU256,
+};
+use ethers_reth::type_conversions::{
+    rpc::{block::CancunFields, transaction::effective_gas_price},
+    ConversionError, ToEthers, ToReth, TryToEthers,
+};
+use reth_primitives::{BlockId, BlockNumberOrTag};
+use reth_rpc_types::{Block, CallRequest, Rich, TransactionReceipt};
+
+#[test]
+fn test_pending_receipt_conversion_fails() {
+    let receipt: TransactionReceipt = serde_json::from_value(serde_json::json!({
+        "from": "0x0000000000000000000000000000000000000001",
+        "to": "0x0000000000000000000000000000000000000002",
+        "cumulativeGasUsed": "0x5208",
+        "gasUsed": "0x5208",
+        "logs": [],
+        "logsBloom": format!("0x{}", "0".repeat(512)),
+        "status": "0x1",
+        "type": "0x2",
+        "effectiveGasPrice": "0x1",
+    }))
+    .unwrap();
+
+    let converted: Result<ethers::types::TransactionReceipt, _> = receipt.try_into_ethers();
+    assert_eq!(
+        converted.unwrap_err(),
+        ConversionError::MissingField { ty: "TransactionReceipt", field: "transaction_hash" }
+    );
+}
+
+#[test]
+fn test_block_id_conversion() {
+    let tag = EthersBlockId::Number(EthersBlockNumber::Latest);
+    assert_eq!(tag.into_reth(), BlockId::Number(BlockNumberOrTag::Latest));
+
+    let pending = EthersBlockId::Number(EthersBlockNumber::Pending);
+    assert_eq!(pending.into_reth(), BlockId::Number(BlockNumberOrTag::Pending));
+
+    let hash = EthersBlockId::Hash(EthersH256::repeat_byte(1));
+    let converted: BlockId = hash.into_reth();
+    assert_eq!(converted.into_ethers(), hash);
+}
+
+#[test]
+fn test_filter_without_topics_conversion() {
+    let filter = EthersFilter::new().from_block(1).to_block(EthersBlockNumber::Latest);
+    let converted: reth_rpc_types::Filter = filter.clone().into_reth();
+    assert_eq!(converted.into_ethers(), filter);
+}
+
+#[test]
+fn test_legacy_call_request_conversion() {
+    let tx: TypedTransaction = TransactionRequest::new().gas_price(1).into();
+    let request: CallRequest = tx.into_reth();
+    assert!(request.max_fee_per_gas.is_none());
+    assert!(request.max_priority_fee_per_gas.is_none());
+}
+
+#[test]
+fn test_effective_gas_price() {
+    let legacy = EthersTransaction { gas_price: Some(30.into()), ..Default::default() };
+    assert_eq!(effective_gas_price(&legacy, Some(10.into())), EthersU256::from(30));
+
+    let eip1559 = EthersTransaction {
+        gas_price: Some(50.into()),
+        max_fee_per_gas: Some(50.into()),
+        max_priority_fee_per_gas: Some(2.into()),
+        ..Default::default()
+    };
+    assert_eq!(effective_gas_price(&eip1559, Some(10.into())), EthersU256::from(12));
+    assert_eq!(effective_gas_price(&eip1559, Some(49.into())), EthersU256::from(50));
+    assert_eq!(effective_gas_price(&eip1559, None), EthersU256::from(50));
+}
+
+#[test]
+fn test_cancun_block_fields_round_trip() {
+    let mut block = EthersBlock::<EthersH256>::default();
+    block.other.insert("blobGasUsed".into(), serde_json::json!("0x20000"));
+    block.other.insert("excessBlobGas".into(), serde_json::json!("0x0"));
+    block
+        .other
+        .insert("parentBeaconBlockRoot".into(), serde_json::json!(EthersH256::repeat_byte(2)));
+
+    let converted: Rich<Block> = block.clone().into_reth();
+    let round_trip: EthersBlock<EthersH256> = converted.into_ethers();
+    assert_eq!(
+        CancunFields::of(&round_trip),
+        CancunFields {
+            blob_gas_used: Some(0x20000.into()),
+            excess_blob_gas: Some(0.into()),
+            parent_beacon_block_root: Some(EthersH256::repeat_byte(2)),
+        }
+    );
+}
@@ -0,0 +1,80 @@
+use ethers::{
+    prelude::rand::{rngs::StdRng, Rng, SeedableRng},
+    types::{Filter, Topic, ValueOrArray, H256},
+};
+use ethers_reth::{
+    logs::matches_topics,
+    type_conversions::{ToEthers, ToReth},
+};
+use serde_json::Value;
+
+/// Topics of the logs of a fixture chain, drawn from a small pool so filters match often.
+fn fixture_logs(rng: &mut StdRng, pool: &[H256]) -> Vec<Vec<H256>> {
+    let mut logs = vec![];
+    for _block in 0..64 {
+        for _log in 0..rng.gen_range(0..6) {
+            let n_topics = rng.gen_range(0..=4);
+            logs.push((0..n_topics).map(|_| pool[rng.gen_range(0..pool.len())]).collect());
+        }
+    }
+    logs
+}
+
+fn random_topic(rng: &mut StdRng, pool: &[H256]) -> Option<Topic> {
+    let kind = rng.gen_range(0..6);
+    let mut hash = || Some(pool[rng.gen_range(0..pool.len())]);
+    match kind {
+        0 => None,
+        1 => Some(ValueOrArray::Value(None)),
+        2 => Some(ValueOrArray::Value(hash())),
+        3 => Some(ValueOrArray::Array(vec![])),
+        4 => Some(ValueOrArray::Array(vec![hash(), hash()])),
+        _ => Some(ValueOrArray::Array(vec![hash(), None])),
+    }
+}
+
+/// Topics as geth parses them from the request: one list per position, empty for a wildcard.
+fn geth_topics(filter: &Filter) -> Vec<Vec<H256>> {
+    let json = serde_json::to_value(filter).unwrap();
+    let parse = |value: &Value| -> Option<H256> { serde_json::from_value(value.clone()).ok() };
+    json["topics"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|topic| match topic {
+            Value::Null => vec![],
+            Value::Array(values) if values.iter().any(Value::is_null) => vec![],
+            Value::Array(values) => values.iter().filter_map(parse).collect(),
+            value => parse(value).into_iter().collect(),
+        })
+        .collect()
+}
+
+/// Port of geth's `filterLogs` topic matching.
+fn geth_matches(topics: &[Vec<H256>], log: &[H256]) -> bool {
+    if topics.len() > log.len() {
+        return false
+    }
+    topics.iter().zip(log).all(|(sub, topic)| sub.is_empty() || sub.contains(topic))
+}
+
+#[test]
+fn test_topic_filters_match_geth() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let pool: Vec<H256> = (1..=3).map(H256::from_low_u64_be).collect();
+    let logs = fixture_logs(&mut rng, &pool);
+
+    for _ in 0..500 {
+        let mut filter = Filter::new();
+        filter.topics = std::array::from_fn(|_| random_topic(&mut rng, &pool));
+        let reference = geth_topics(&filter);
+        let converted: Filter =
+            ToReth::<reth_rpc_types::Filter>::into_reth(filter.clone()).into_ethers();
+
+        for log in &logs {
+            let expected = geth_matches(&reference, log);
+            assert_eq!(matches_topics(&filter.topics, log), expected, "{filter:?} {log:?}");
+            assert_eq!(matches_topics(&converted.topics, log), expected, "{converted:?} {log:?}");
+        }
+    }
+}
@@ -0,0 +1,59 @@
+#![cfg(feature = "test-utils")]
+
+use ethers::{
+    providers::{Middleware, MockProvider, Provider},
+    types::{spoof, Address, BlockNumber, Bytes, Filter, TransactionRequest, H256, U64},
+};
+use ethers_reth::{
+    fixture::{emitter_topic, TestChain},
+    limits::CallLimits,
+    type_conversions::ToEthers,
+    RethMiddlewareError,
+};
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_fixture_chain() {
+    let chain = TestChain::generate().unwrap();
+    // every read is answered from the database, the mock only fills the inner provider
+    let middleware = chain.middleware(Provider::new(MockProvider::new())).unwrap();
+
+    assert_eq!(middleware.get_block_number().await.unwrap(), U64::from(chain.tip().number));
+    for orphaned in &chain.orphaned {
+        let hash: H256 = orphaned.hash().into_ethers();
+        assert!(middleware.get_block(hash).await.unwrap().is_none());
+    }
+
+    let receipt = middleware.get_transaction_receipt(chain.deployment).await.unwrap().unwrap();
+    assert_eq!(receipt.contract_address, Some(chain.emitter));
+    let receipt = middleware.get_transaction_receipt(chain.reverted).await.unwrap().unwrap();
+    assert_eq!(receipt.status, Some(U64::zero()));
+
+    let filter = Filter::new().address(chain.emitter).topic0(emitter_topic()).from_block(0);
+    let logs = middleware.get_logs(&filter).await.unwrap();
+    assert_eq!(logs.len(), 2);
+    assert_eq!(logs[0].transaction_hash, Some(chain.emit));
+    assert_eq!(logs[0].data.as_ref(), b"first");
+}
+
+#[tokio::test]
+async fn test_call_timeout() {
+    let chain = TestChain::generate().unwrap();
+    let timeout = Duration::from_millis(1);
+    let middleware = chain
+        .middleware(Provider::new(MockProvider::new()))
+        .unwrap()
+        .with_call_limits(CallLimits { timeout: Some(timeout), ..Default::default() });
+
+    // JUMPDEST PUSH1 0 JUMP, looping until the call runs out of its 30M gas
+    let looping = Address::repeat_byte(0x42);
+    let mut overrides = spoof::state();
+    overrides.account(looping).code(Bytes::from(vec![0x5b, 0x60, 0x00, 0x56]));
+    let tx = TransactionRequest::new().to(looping).into();
+
+    let err = middleware
+        .call_with_overrides(&tx, BlockNumber::Latest.into(), &overrides)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, RethMiddlewareError::CallTimeout(elapsed) if elapsed == timeout));
+}
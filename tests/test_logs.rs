@@ -0,0 +1,30 @@
+use ethers::types::{Log, H256};
+use ethers_reth::logs::DeliveredLogs;
+
+fn log(block: u64, index: u64) -> Log {
+    Log {
+        block_hash: Some(H256::from_low_u64_be(block)),
+        block_number: Some(block.into()),
+        log_index: Some(index.into()),
+        removed: Some(false),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_reorg_retracts_only_delivered_logs() {
+    let mut delivered = DeliveredLogs::new(16);
+    delivered.deliver(1, H256::from_low_u64_be(1), &[log(1, 0)]);
+    delivered.deliver(2, H256::from_low_u64_be(2), &[log(2, 0), log(2, 3)]);
+    delivered.deliver(3, H256::from_low_u64_be(3), &[]);
+
+    let removed = delivered.retract(|number, _| number >= 2);
+    assert_eq!(
+        removed,
+        vec![Log { removed: Some(true), ..log(2, 0) }, Log { removed: Some(true), ..log(2, 3) },]
+    );
+
+    assert!(delivered.contains(1, H256::from_low_u64_be(1)));
+    assert!(!delivered.contains(2, H256::from_low_u64_be(2)));
+    assert!(delivered.retract(|number, _| number >= 2).is_empty());
+}
@@ -5,11 +5,16 @@ mod tests {
     use crate::test_utils::{init_testdata, spawn_http_provider, TestDb};
 
     use ethers::{
-        prelude::Lazy,
-        providers::Middleware,
-        types::{Bytes as EthersBytes, NameOrAddress, H256 as EthersH256},
+        middleware::{
+            gas_escalator::{Frequency, GeometricGasPrice},
+            GasEscalatorMiddleware, NonceManagerMiddleware, SignerMiddleware,
+        },
+        prelude::{rand::thread_rng, Lazy},
+        providers::{Middleware, Provider},
+        signers::{LocalWallet, Signer},
+        types::{Bytes as EthersBytes, NameOrAddress, TransactionRequest, H256 as EthersH256},
     };
-    use ethers_reth::{type_conversions::ToEthers, RethMiddleware};
+    use ethers_reth::{transport::RethTransport, type_conversions::ToEthers, RethMiddleware};
     use serial_test::serial;
 
     // const TEST_HTTP_URL: &str = "https://reth.sorella-beechit.com:8485";
@@ -94,4 +99,57 @@ mod tests {
 
         rt.shutdown_background();
     }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_transport_get_code() {
+        // Create a runtime and handle here for the TaskManager
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let handle = rt.handle();
+
+        let provider = Provider::new(RethTransport::new(&TEST_DB.path, handle.clone()).unwrap());
+
+        for (addr, bytecode) in &TEST_DB.bytecodes {
+            let code: EthersBytes =
+                provider.get_code(NameOrAddress::Address((*addr).into()), None).await.unwrap();
+            let expected_code: EthersBytes = bytecode.bytecode.to_vec().into();
+            assert_eq!(expected_code, code);
+        }
+
+        rt.shutdown_background();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_middleware_stack_send_transaction() {
+        // Create a runtime and handle here for the TaskManager
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let handle = rt.handle();
+
+        let (provider, mock) = Provider::mocked();
+        let middleware = RethMiddleware::new(provider, &TEST_DB.path, handle.clone()).unwrap();
+
+        let wallet: LocalWallet = LocalWallet::new(&mut thread_rng()).with_chain_id(1u64);
+        let address = wallet.address();
+
+        // Provider <- RethMiddleware <- NonceManager <- Signer <- GasEscalator
+        let middleware = NonceManagerMiddleware::new(middleware, address);
+        let middleware = SignerMiddleware::new(middleware, wallet);
+        let middleware = GasEscalatorMiddleware::new(
+            middleware,
+            GeometricGasPrice::new(1.125, 60u64, None::<u64>),
+            Frequency::Duration(60_000),
+        );
+
+        // only the raw transaction reaches the inner provider
+        let tx_hash = EthersH256::random();
+        mock.push(tx_hash).unwrap();
+
+        let tx =
+            TransactionRequest::new().to(address).value(1).gas(21_000).gas_price(1_000_000_000);
+        let pending = middleware.send_transaction(tx, None).await.unwrap();
+        assert_eq!(*pending, tx_hash);
+
+        rt.shutdown_background();
+    }
 }
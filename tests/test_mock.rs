@@ -0,0 +1,24 @@
+#![cfg(feature = "mock")]
+
+use ethers::{
+    providers::Middleware,
+    types::{Address, BlockId, BlockNumber, U256},
+};
+use ethers_reth::mock::MockRethMiddleware;
+
+#[tokio::test]
+async fn test_mock_responses_and_calls() {
+    let mock = MockRethMiddleware::new();
+    mock.push("get_balance", U256::from(1)).unwrap();
+    mock.push("get_balance", U256::from(2)).unwrap();
+
+    let address = Address::random();
+    let block = Some(BlockId::Number(BlockNumber::Latest));
+    assert_eq!(mock.get_balance(address, block).await.unwrap(), U256::from(1));
+    assert_eq!(mock.get_balance(address, None).await.unwrap(), U256::from(2));
+    assert!(mock.get_balance(address, None).await.is_err());
+
+    let calls = mock.calls_to("get_balance");
+    assert_eq!(calls.len(), 3);
+    assert_eq!(calls[0].params, serde_json::json!([address, "latest"]));
+}
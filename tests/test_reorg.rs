@@ -0,0 +1,49 @@
+mod tests {
+    use ethers_reth::reorg::ReorgDetector;
+    use reth_primitives::{Header, SealedHeader, H256};
+
+    /// Builds `len` linked headers on top of `parent`, `salt` makes sibling chains distinct.
+    fn segment(parent: (u64, H256), len: u64, salt: u64) -> Vec<SealedHeader> {
+        let (mut number, mut parent_hash) = parent;
+        (0..len)
+            .map(|_| {
+                number += 1;
+                let header = Header { number, parent_hash, gas_limit: salt, ..Default::default() }
+                    .seal_slow();
+                parent_hash = header.hash();
+                header
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_extension_is_not_a_reorg() {
+        let mut detector = ReorgDetector::default();
+        let base = segment((0, H256::zero()), 5, 0);
+        assert!(detector.apply(vec![], base.clone()).is_none());
+
+        let tip = base.last().unwrap();
+        let next = segment((tip.number, tip.hash()), 2, 0);
+        assert!(detector.apply(vec![], next.clone()).is_none());
+        assert_eq!(detector.tip(), next.last());
+    }
+
+    #[test]
+    fn test_detects_depth_and_common_ancestor() {
+        let mut detector = ReorgDetector::default();
+        let base = segment((0, H256::zero()), 5, 0);
+        detector.apply(vec![], base.clone());
+
+        // replace the last two blocks without the notification reporting them
+        let ancestor = &base[2];
+        let fork = segment((ancestor.number, ancestor.hash()), 3, 1);
+        let event = detector.apply(vec![], fork.clone()).unwrap();
+
+        assert_eq!(event.depth, 2);
+        assert_eq!(event.common_ancestor.number, ancestor.number);
+        assert_eq!(event.common_ancestor.hash, ancestor.hash());
+        assert_eq!(event.old, base[3..].to_vec());
+        assert_eq!(event.new, fork);
+        assert_eq!(detector.tip(), fork.last());
+    }
+}